@@ -1,42 +1,24 @@
+pub mod oauth;
+
 use crate::cmds::{config_path, ConfigFile};
 use crate::error::{self, Error};
+pub use crate::proto::auth::{AuthError, AuthMsg, AuthResp, Role, Session};
 use crate::{
     cache::{self, Cache, RespType},
-    msg::{Location, Payload, Permissions, Ping, Platform, Response, User},
+    config::AuthConfig,
+    msg::{corr_id, Location, Payload, Permissions, Ping, Platform, Response, User},
 };
 use bb8_redis::redis;
-use once_cell::sync::Lazy;
+use oauth::{OAuth, OAuthProvider};
 use rand::Rng;
-use serde_derive::{Deserialize, Serialize};
-use std::path::Path;
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
 use tokio::fs;
 use tokio::sync::mpsc;
 
-#[derive(Debug, Deserialize, Serialize)]
-pub enum AuthMsg {
-    ListUsers,
-    RequestCode(Arc<String>),
-    Login(Arc<String>, Arc<String>),
-}
-
-#[derive(Debug, Serialize, PartialEq)]
-pub(crate) enum AuthError {
-    Ratelimited,
-    ServerError,
-}
-
-#[derive(Debug, Serialize, PartialEq)]
-pub(crate) enum AuthResp {
-    Users(Arc<Vec<String>>),
-    InvalidUser,
-    CodeReady,
-    CodeExpired,
-    AuthSuccess(Arc<String>),
-    AuthFail,
-    AuthError(AuthError),
-}
-
 type AuthMap = HashMap<String, (Arc<String>, usize)>; // name => (discord id, code validity duration)
 
 #[derive(Clone)]
@@ -45,25 +27,18 @@ pub struct Handle {
     msg_out_tx: mpsc::Sender<(Location, Response)>,
     users: Arc<AuthMap>,
     usernames: Arc<Vec<String>>,
+    config: &'static AuthConfig,
+    /// None if OAUTH_REDIRECT_URI isn't set
+    oauth: Option<Arc<OAuth>>,
 }
 
-pub static MAX_AUTH_RATELIMIT_COUNT: Lazy<usize> = Lazy::new(|| {
-    dotenv::var("MAX_AUTH_RATELIMIT_COUNT")
-        .unwrap_or_default()
-        .parse()
-        .unwrap_or(10)
-});
-pub static MAX_AUTH_RATELIMIT_BURST: Lazy<usize> = Lazy::new(|| {
-    dotenv::var("MAX_AUTH_RATELIMIT_BURST")
-        .unwrap_or_default()
-        .parse()
-        .unwrap_or(20)
-});
+/// How long an OAuth login has to come back from the provider (in seconds)
+const OAUTH_STATE_TTL: usize = 10 * 60;
 
 fn ratelimit_key(ip: impl AsRef<str>) -> String {
     format!(
         "aussiebot!{}!loginrl!{}",
-        &*super::CHANNEL_NAME,
+        super::channel_name(),
         ip.as_ref()
     )
 }
@@ -71,11 +46,33 @@ fn ratelimit_key(ip: impl AsRef<str>) -> String {
 fn code_key(user: impl AsRef<str>) -> String {
     format!(
         "aussiebot!{}!login!{}",
-        &*super::CHANNEL_NAME,
+        super::channel_name(),
         user.as_ref()
     )
 }
 
+fn session_key(id: impl AsRef<str>) -> String {
+    format!(
+        "aussiebot!{}!session!{}",
+        super::channel_name(),
+        id.as_ref()
+    )
+}
+
+/// Provider an OAuth login was started with, by its state
+fn oauth_state_key(state: impl AsRef<str>) -> String {
+    format!(
+        "aussiebot!{}!oauthstate!{}",
+        super::channel_name(),
+        state.as_ref()
+    )
+}
+
+/// Session ids by user, so they can be listed without scanning
+fn sessions_key() -> String {
+    format!("aussiebot!{}!sessions", super::channel_name())
+}
+
 fn gen_code() -> String {
     let code1 = rand::thread_rng().gen::<u64>();
     let code2 = rand::thread_rng().gen::<u64>();
@@ -87,6 +84,8 @@ impl Handle {
         cache: cache::Handle,
         msg_out_tx: mpsc::Sender<(Location, Response)>,
         users: AuthMap,
+        config: &'static AuthConfig,
+        twitch_login: &str,
     ) -> Self {
         // TOOD: query a database table
         let users = Arc::new(users);
@@ -97,19 +96,76 @@ impl Handle {
         }
         let usernames = Arc::new(usernames);
 
+        let oauth = config.oauth.clone().and_then(|oauth| {
+            OAuth::new(oauth, twitch_login.to_owned())
+                .map_err(|e| tracing::error!("oauth logins unavailable: {}", e))
+                .ok()
+        });
+
         Self {
             cache,
             msg_out_tx,
             users,
             usernames,
+            config,
+            oauth: oauth.map(Arc::new),
+        }
+    }
+
+    /// Only users in the users file are admins, OAuth logins are prefixed so they can't pass for one
+    fn role(&self, user: &str) -> Role {
+        match self.users.contains_key(user) {
+            true => Role::Admin,
+            false => Role::Mod,
+        }
+    }
+
+    /// Record a successful login, returning the new session's id and role
+    #[tracing::instrument(skip(self))]
+    pub(crate) async fn new_session(
+        &self,
+        user: &str,
+        peer_ip: &str,
+    ) -> error::Result<(Arc<String>, Role)> {
+        let id = Arc::new(gen_code());
+        let role = self.role(user);
+        let session = Session {
+            id: id.to_string(),
+            user: user.to_owned(),
+            ip: peer_ip.to_owned(),
+            created: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs() as i64)
+                .unwrap_or_default(),
+            role,
+        };
+        let session = Arc::new(serde_json::to_string(&session)?);
+
+        let cache_resp = Cache::Set(
+            session_key(&*id).into(),
+            session,
+            self.config.session_ttl,
+            true,
+        )
+        .exec(&self.cache)
+        .await?;
+        if !matches!(cache_resp, RespType::Bool(true)) {
+            return Err(Error::Generic(format!("could not store session {}", id)));
         }
+
+        Cache::HashSet(sessions_key().into(), id.clone(), user.to_owned(), false)
+            .exec(&self.cache)
+            .await?;
+
+        tracing::info!(id = id.as_str(), role = ?role, "session started");
+        Ok((id, role))
     }
 
     #[tracing::instrument(skip_all)]
     pub(crate) async fn handle(&self, peer_ip: &str, msg: AuthMsg) -> error::Result<AuthResp> {
         let rl_key = Arc::new(ratelimit_key(peer_ip));
 
-        let rl_count = match Cache::Increment(rl_key.clone(), 1, *MAX_AUTH_RATELIMIT_BURST)
+        let rl_count = match Cache::Increment(rl_key.clone(), 1, self.config.ratelimit_burst)
             .exec(&self.cache)
             .await?
         {
@@ -119,7 +175,7 @@ impl Handle {
 
         tracing::debug!("{} = {}", rl_key, rl_count);
 
-        if rl_count > *MAX_AUTH_RATELIMIT_COUNT {
+        if rl_count > self.config.ratelimit_count {
             return Ok(AuthResp::AuthError(AuthError::Ratelimited));
         }
 
@@ -162,12 +218,15 @@ impl Handle {
                     id: id.clone(),
                     name: "".to_owned().into(),
                     perms: Permissions::NONE,
+                    roles: vec![],
                 });
 
                 Response {
                     platform: Platform::DISCORD,
-                    channel: &*crate::CHANNEL_NAME,
+                    channel: crate::channel_name(),
+                    corr_id: corr_id(),
                     payload: Payload::Ping(Ping {
+                        id: None,
                         pinger: None,
                         pingee,
                         msg: Some(msg.into()),
@@ -184,7 +243,7 @@ impl Handle {
                     return Ok(AuthResp::AuthFail);
                 }
 
-                let key = code_key(&*user); //format!(CODE_KEY, &*super::CHANNEL_NAME, user);
+                let key = code_key(&*user); //format!(CODE_KEY, super::channel_name(), user);
                 let resp = Cache::Get(key.into()).exec(&self.cache).await;
                 match resp {
                     Err(Error::Redis(e)) if e.kind() == redis::ErrorKind::TypeError => {
@@ -198,7 +257,7 @@ impl Handle {
                         Ok(AuthResp::AuthSuccess(user))
                     }
                     Ok(RespType::String(_)) => {
-                        if rl_count == *MAX_AUTH_RATELIMIT_COUNT {
+                        if rl_count == self.config.ratelimit_count {
                             // the next request will be ratelimited, so stop here
                             Ok(AuthResp::AuthError(AuthError::Ratelimited))
                         } else {
@@ -208,14 +267,138 @@ impl Handle {
                     Ok(_) => unreachable!(),
                 }
             }
+            AuthMsg::ListProviders => Ok(AuthResp::Providers(
+                self.oauth
+                    .as_ref()
+                    .map(|o| o.providers())
+                    .unwrap_or_default(),
+            )),
+            AuthMsg::OAuthStart(provider) => {
+                let state = Arc::new(gen_code());
+                let url = match self
+                    .oauth
+                    .as_ref()
+                    .and_then(|o| o.authorize_url(provider, &state))
+                {
+                    Some(url) => url,
+                    None => return Ok(AuthResp::AuthError(AuthError::Unavailable)),
+                };
+                let cache_resp = Cache::Set(
+                    oauth_state_key(&*state).into(),
+                    Arc::new(provider.label().to_owned()),
+                    OAUTH_STATE_TTL,
+                    true,
+                )
+                .exec(&self.cache)
+                .await?;
+                if !matches!(cache_resp, RespType::Bool(true)) {
+                    tracing::error!("could not store oauth state {}", state);
+                    return Ok(AuthResp::AuthError(AuthError::ServerError));
+                }
+                Ok(AuthResp::OAuthRedirect(url))
+            }
+            AuthMsg::OAuthLogin {
+                provider,
+                code,
+                state,
+            } => {
+                // each state is good for one try, and only with the provider it was made for
+                let key = oauth_state_key(&*state);
+                match Cache::GetDel(key.into()).exec(&self.cache).await {
+                    Ok(RespType::String(p)) if p.as_str() == provider.label() => {}
+                    Ok(RespType::String(_)) => return Ok(AuthResp::AuthFail),
+                    Err(Error::Redis(e)) if e.kind() == redis::ErrorKind::TypeError => {
+                        return Ok(AuthResp::CodeExpired)
+                    }
+                    Err(e) => return Err(e),
+                    Ok(_) => unreachable!(),
+                }
+
+                let oauth = match self.oauth {
+                    Some(ref oauth) => oauth,
+                    None => return Ok(AuthResp::AuthError(AuthError::Unavailable)),
+                };
+                let identity = match oauth.login(provider, &code).await {
+                    Ok(Some(identity)) => identity,
+                    Ok(None) => return Ok(AuthResp::AuthFail),
+                    Err(e) => {
+                        tracing::error!(provider = provider.label(), "{}", e);
+                        return Ok(AuthResp::AuthError(AuthError::ServerError));
+                    }
+                };
+
+                Cache::Delete(rl_key.clone()).exec(&self.cache).await?;
+                tracing::info!(
+                    provider = provider.label(),
+                    id = identity.id.as_str(),
+                    "oauth login"
+                );
+                Ok(AuthResp::AuthSuccess(self.oauth_user(provider, identity)))
+            }
         }
     }
+
+    /// Who the session's for. Discord users in the users file go by their name there, so they're
+    /// treated the same however they log in. Everyone else is prefixed with the provider, so
+    /// they can't pass for a user from the file
+    fn oauth_user(&self, provider: OAuthProvider, identity: oauth::Identity) -> Arc<String> {
+        if provider == OAuthProvider::Discord {
+            let known = self
+                .users
+                .iter()
+                .find(|(_, (id, _))| id.as_str() == identity.id);
+            if let Some((name, _)) = known {
+                return Arc::new(name.clone());
+            }
+        }
+        Arc::new(format!("{}:{}", provider.label(), identity.name))
+    }
+}
+
+/// Active sessions, dropping any that have since expired from the index
+pub(crate) async fn list_sessions(cache: &cache::Handle) -> error::Result<Vec<Session>> {
+    let index = Arc::new(sessions_key());
+    let ids = match Cache::HashGetAll(index.clone()).exec(cache).await? {
+        RespType::VecStringString(ids) => ids,
+        _ => unreachable!(),
+    };
+
+    let mut sessions = vec![];
+    for (id, _user) in ids {
+        match Cache::Get(session_key(&id).into()).exec(cache).await {
+            Ok(RespType::String(session)) => match serde_json::from_str::<Session>(&session) {
+                Ok(session) => sessions.push(session),
+                Err(e) => tracing::error!(id = id.as_str(), "{}", e),
+            },
+            Err(Error::Redis(e)) if e.kind() == redis::ErrorKind::TypeError => {
+                Cache::HashDelete(index.clone(), id.into())
+                    .exec(cache)
+                    .await?;
+            }
+            Err(e) => return Err(e),
+            Ok(_) => unreachable!(),
+        }
+    }
+
+    sessions.sort_by_key(|s| s.created);
+    Ok(sessions)
+}
+
+/// Returns false if there was no such session
+pub(crate) async fn revoke_session(cache: &cache::Handle, id: Arc<String>) -> error::Result<bool> {
+    Cache::HashDelete(sessions_key().into(), id.clone())
+        .exec(cache)
+        .await?;
+    match Cache::GetDel(session_key(&*id).into()).exec(cache).await {
+        Ok(_) => Ok(true),
+        Err(Error::Redis(e)) if e.kind() == redis::ErrorKind::TypeError => Ok(false),
+        Err(e) => Err(e),
+    }
 }
 
 pub async fn load() -> error::Result<AuthMap> {
     let contents =
-        fs::read_to_string(Path::new(&*crate::CONFIG_DIR).join(config_path(ConfigFile::Users)))
-            .await?;
+        fs::read_to_string(crate::config_dir().join(config_path(ConfigFile::Users))).await?;
 
     // deserialise
     let authmap: AuthMap = serde_json::from_str(&contents)?;
@@ -226,7 +409,7 @@ pub async fn load() -> error::Result<AuthMap> {
 // pub(super) async fn save(users: &AuthMap) -> Result<(), std::io::Error> {
 //     let dump = serde_json::to_string_pretty(&users).unwrap();
 //     fs::write(
-//         Path::new(&*crate::CONFIG_DIR).join(config_path(ConfigFile::Users)),
+//         crate::config_dir().join(config_path(ConfigFile::Users)),
 //         dump,
 //     )
 //     .await
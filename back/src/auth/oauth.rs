@@ -0,0 +1,325 @@
+//! Web UI logins through Discord or Twitch, for mods who aren't in the users file. The web UI sends
+//! them to the provider with the url from [`OAuth::authorize_url`], which sends them back to
+//! OAUTH_REDIRECT_URI with a code. That's exchanged here for who they are, and they're let in if
+//! they have one of DISCORD_OAUTH_ROLES in GUILD_ID, or moderate (or own) the TWITCH_LOGIN channel.
+//! Tokens are only used to check that, they aren't kept
+pub use crate::proto::auth::OAuthProvider;
+use crate::{
+    config::{DiscordOAuthConfig, OAuthApp, OAuthConfig},
+    error,
+};
+use serde_derive::Deserialize;
+use std::time::Duration;
+
+const DISCORD_AUTHORIZE_URL: &str = "https://discord.com/oauth2/authorize";
+const DISCORD_TOKEN_URL: &str = "https://discord.com/api/oauth2/token";
+const DISCORD_API_URL: &str = "https://discord.com/api";
+const DISCORD_SCOPES: &str = "identify guilds.members.read";
+
+const TWITCH_AUTHORIZE_URL: &str = "https://id.twitch.tv/oauth2/authorize";
+const TWITCH_TOKEN_URL: &str = "https://id.twitch.tv/oauth2/token";
+const TWITCH_USERS_URL: &str = "https://api.twitch.tv/helix/users";
+const TWITCH_MODERATED_URL: &str = "https://api.twitch.tv/helix/moderation/channels";
+const TWITCH_SCOPES: &str = "user:read:moderated_channels";
+
+/// Pages of moderated channels looked through before giving up, 100 to a page
+const MAX_TWITCH_PAGES: usize = 10;
+
+impl OAuthProvider {
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            OAuthProvider::Discord => "discord",
+            OAuthProvider::Twitch => "twitch",
+        }
+    }
+}
+
+/// Whether a guild member has any of the roles that are let in
+fn has_any_role(member: &[String], allowed: &[String]) -> bool {
+    member.iter().any(|r| allowed.contains(r))
+}
+
+/// Twitch logins are case insensitive
+fn is_channel(login: &str, channel: &str) -> bool {
+    login.eq_ignore_ascii_case(channel)
+}
+
+/// Who logged in
+#[derive(Debug)]
+pub(crate) struct Identity {
+    pub(crate) id: String,
+    pub(crate) name: String,
+}
+
+/// Logins through the providers that are set up
+pub(crate) struct OAuth {
+    client: reqwest::Client,
+    config: OAuthConfig,
+    /// Twitch channel whose mods are let in
+    twitch_login: String,
+}
+
+impl OAuth {
+    pub(crate) fn new(config: OAuthConfig, twitch_login: String) -> reqwest::Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()?;
+        Ok(Self {
+            client,
+            config,
+            twitch_login,
+        })
+    }
+
+    fn app(&self, provider: OAuthProvider) -> Option<&OAuthApp> {
+        match provider {
+            OAuthProvider::Discord => self.config.discord.as_ref().map(|d| &d.app),
+            OAuthProvider::Twitch => self.config.twitch.as_ref(),
+        }
+    }
+
+    async fn token(
+        &self,
+        provider: OAuthProvider,
+        app: &OAuthApp,
+        code: &str,
+    ) -> error::Result<String> {
+        #[derive(Deserialize)]
+        struct Token {
+            access_token: String,
+        }
+
+        let url = match provider {
+            OAuthProvider::Discord => DISCORD_TOKEN_URL,
+            OAuthProvider::Twitch => TWITCH_TOKEN_URL,
+        };
+        let token: Token = self
+            .client
+            .post(url)
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", self.config.redirect_uri.as_str()),
+                ("client_id", app.client_id.as_str()),
+                ("client_secret", app.client_secret.0.as_str()),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(token.access_token)
+    }
+
+    /// None if they aren't in the guild or don't have any of the roles
+    async fn discord(
+        &self,
+        login: &DiscordOAuthConfig,
+        token: &str,
+    ) -> error::Result<Option<Identity>> {
+        #[derive(Deserialize)]
+        struct DiscordUser {
+            id: String,
+            username: String,
+        }
+        #[derive(Deserialize)]
+        struct Member {
+            user: DiscordUser,
+            roles: Vec<String>,
+        }
+
+        let resp = self
+            .client
+            .get(format!(
+                "{}/users/@me/guilds/{}/member",
+                DISCORD_API_URL, login.guild_id
+            ))
+            .bearer_auth(token)
+            .send()
+            .await?;
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let member: Member = resp.error_for_status()?.json().await?;
+
+        if !has_any_role(&member.roles, &login.roles) {
+            tracing::info!(
+                id = member.user.id.as_str(),
+                "discord user has none of the roles"
+            );
+            return Ok(None);
+        }
+        Ok(Some(Identity {
+            id: member.user.id,
+            name: member.user.username,
+        }))
+    }
+
+    /// None if they neither own nor moderate the channel
+    async fn twitch(&self, app: &OAuthApp, token: &str) -> error::Result<Option<Identity>> {
+        #[derive(Deserialize)]
+        struct Data<T> {
+            data: Vec<T>,
+            #[serde(default)]
+            pagination: Pagination,
+        }
+        #[derive(Deserialize, Default)]
+        struct Pagination {
+            cursor: Option<String>,
+        }
+        #[derive(Deserialize)]
+        struct TwitchUser {
+            id: String,
+            login: String,
+        }
+        #[derive(Deserialize)]
+        struct Channel {
+            broadcaster_login: String,
+        }
+
+        let users: Data<TwitchUser> = self
+            .client
+            .get(TWITCH_USERS_URL)
+            .header("Client-Id", &app.client_id)
+            .bearer_auth(token)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let user = match users.data.into_iter().next() {
+            Some(user) => user,
+            None => return Ok(None),
+        };
+        let channel = self.twitch_login.as_str();
+        let identity = Identity {
+            id: user.id,
+            name: user.login,
+        };
+        if is_channel(&identity.name, channel) {
+            return Ok(Some(identity));
+        }
+
+        let mut cursor: Option<String> = None;
+        for _ in 0..MAX_TWITCH_PAGES {
+            let mut query = vec![("user_id", identity.id.as_str()), ("first", "100")];
+            if let Some(ref cursor) = cursor {
+                query.push(("after", cursor.as_str()));
+            }
+            let page: Data<Channel> = self
+                .client
+                .get(TWITCH_MODERATED_URL)
+                .query(&query)
+                .header("Client-Id", &app.client_id)
+                .bearer_auth(token)
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            if page
+                .data
+                .iter()
+                .any(|c| is_channel(&c.broadcaster_login, channel))
+            {
+                return Ok(Some(identity));
+            }
+            cursor = match page.pagination.cursor {
+                Some(next) if !page.data.is_empty() => Some(next),
+                _ => break,
+            };
+        }
+
+        tracing::info!(
+            login = identity.name.as_str(),
+            "twitch user doesn't moderate the channel"
+        );
+        Ok(None)
+    }
+
+    /// Providers that can be logged in with
+    pub(crate) fn providers(&self) -> Vec<OAuthProvider> {
+        [OAuthProvider::Discord, OAuthProvider::Twitch]
+            .into_iter()
+            .filter(|p| self.app(*p).is_some())
+            .collect()
+    }
+
+    /// Where to send the user to log in, None if the provider isn't set up
+    pub(crate) fn authorize_url(&self, provider: OAuthProvider, state: &str) -> Option<String> {
+        let app = self.app(provider)?;
+        let (url, scopes) = match provider {
+            OAuthProvider::Discord => (DISCORD_AUTHORIZE_URL, DISCORD_SCOPES),
+            OAuthProvider::Twitch => (TWITCH_AUTHORIZE_URL, TWITCH_SCOPES),
+        };
+        let url = url::Url::parse_with_params(
+            url,
+            &[
+                ("response_type", "code"),
+                ("client_id", app.client_id.as_str()),
+                ("redirect_uri", self.config.redirect_uri.as_str()),
+                ("scope", scopes),
+                ("state", state),
+            ],
+        )
+        .ok()?;
+        Some(url.into())
+    }
+
+    /// Exchange the code the provider sent the user back with, None if they aren't let in
+    pub(crate) async fn login(
+        &self,
+        provider: OAuthProvider,
+        code: &str,
+    ) -> error::Result<Option<Identity>> {
+        match provider {
+            OAuthProvider::Discord => {
+                let discord = self
+                    .config
+                    .discord
+                    .as_ref()
+                    .ok_or("discord logins aren't configured")?;
+                let token = self.token(provider, &discord.app, code).await?;
+                self.discord(discord, &token).await
+            }
+            OAuthProvider::Twitch => {
+                let app = self
+                    .config
+                    .twitch
+                    .as_ref()
+                    .ok_or("twitch logins aren't configured")?;
+                let token = self.token(provider, app, code).await?;
+                self.twitch(app, &token).await
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roles(roles: &[&str]) -> Vec<String> {
+        roles.iter().map(|r| r.to_string()).collect()
+    }
+
+    #[test]
+    fn discord_members_need_one_of_the_roles() {
+        let allowed = roles(&["10", "20"]);
+        assert!(has_any_role(&roles(&["1", "20"]), &allowed));
+        assert!(has_any_role(&roles(&["10"]), &allowed));
+        assert!(!has_any_role(&roles(&["1", "2"]), &allowed));
+        assert!(!has_any_role(&[], &allowed));
+        // ids are matched whole
+        assert!(!has_any_role(&roles(&["1", "0"]), &allowed));
+        assert!(!has_any_role(&roles(&["100"]), &allowed));
+    }
+
+    #[test]
+    fn twitch_channel_matches_ignore_case() {
+        assert!(is_channel("SomeStreamer", "somestreamer"));
+        assert!(is_channel("somestreamer", "somestreamer"));
+        assert!(!is_channel("somestreamer2", "somestreamer"));
+        assert!(!is_channel("", "somestreamer"));
+    }
+}
@@ -1,52 +1,78 @@
 use back::{
     auth, cache,
     cmds::{self, ConfigFile},
-    db, init_db, init_redis, lock, msg, pubsub, ws,
+    config::{Config, ServerConfig, StorageConfig, Transport},
+    db, health, i18n, init_db, init_read_db, init_redis, lock, log_level, msg, pubsub, shard,
+    telemetry, twitch, ws, RedisPool,
 };
 use parking_lot::RwLock;
 use std::sync::Arc;
 use tokio::main;
 use tokio::sync::mpsc;
-use tracing::Level;
-use tracing_subscriber::{fmt::format::FmtSpan, FmtSubscriber};
+use tracing_subscriber::{filter::LevelFilter, fmt::format::FmtSpan, layer::SubscriberExt};
 
 #[main]
 async fn main() {
     dotenv::dotenv().unwrap();
 
-    let file_appender = tracing_appender::rolling::never(
-        dotenv::var("LOG_DIR").expect("Log dir in env"),
-        "back.log",
-    );
+    // check everything up front, rather than at first use
+    let (config, server_config) = match Config::load_server() {
+        Ok(c) => (c.0.init(), c.1.init()),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let file_appender = tracing_appender::rolling::never(&config.log_dir, "back.log");
     let (non_blocking, _guard) = tracing_appender::non_blocking(file_appender);
 
-    // a builder for `FmtSubscriber`.
-    let subscriber = FmtSubscriber::builder()
-        // completes the builder.
-        .with_max_level(Level::DEBUG)
+    // levels can be changed per target while running, see Payload::SetLogLevel
+    let fmt = tracing_subscriber::fmt::layer()
         .with_span_events(/*FmtSpan::NEW |*/ FmtSpan::CLOSE)
         .with_writer(non_blocking)
-        .with_line_number(true)
-        //.with_thread_names(true)
-        //.with_target(false)
-        //.with_ansi(false)
-        //.with_timer(time::LocalTime::rfc_3339()) // time must be built with the unsound_local_offset cfg flag for local timestamps
-        .finish();
+        .with_line_number(true);
+    //.with_thread_names(true)
+    //.with_target(false)
+    //.with_ansi(false)
+    //.with_timer(time::LocalTime::rfc_3339()) // time must be built with the unsound_local_offset cfg flag for local timestamps
+    let (otel, _telemetry) = telemetry::layer(config.telemetry.as_ref(), "backrs");
+    let subscriber = tracing_subscriber::registry()
+        .with(log_level::layer(LevelFilter::DEBUG))
+        .with(fmt)
+        .with(otel);
 
     // use that subscriber to process traces emitted after this point
     tracing::subscriber::set_global_default(subscriber).unwrap();
 
-    let (db_pool, redis_pool, cmds, filters, timers, users) = tokio::join!(
-        init_db(),
-        init_redis(),
+    let (
+        (db, cache, lock, redis_pool),
+        cmds,
+        filters,
+        timers,
+        users,
+        locale,
+        service_accounts,
+        perm_map,
+        currency,
+        profiles,
+    ) = tokio::join!(
+        init_storage(config, server_config),
         cmds::load(ConfigFile::Commands),
         cmds::load(ConfigFile::Filters),
         cmds::load(ConfigFile::Timers),
-        auth::load()
+        auth::load(),
+        i18n::load(),
+        msg::service::ServiceAccounts::load(),
+        msg::perm_map::PermMap::load(),
+        msg::currency::CurrencyConfig::load(),
+        msg::profile::Profiles::load()
     );
 
-    let redis_pool = redis_pool.unwrap();
-    let db = db::Handle::new(db_pool.unwrap());
+    // before the msg task, so chat isn't handled for users who are someone else's
+    if let Some(shards) = &server_config.shards {
+        shard::start(shards, cache.clone(), lock.clone());
+    }
 
     let cmds = cmds.unwrap();
     let filters = filters.unwrap();
@@ -56,31 +82,44 @@ async fn main() {
     let filters = Arc::new(RwLock::new(Arc::new(filters)));
     let timers = Arc::new(RwLock::new(Arc::new(timers)));
 
-    let lock = lock::Handle::new(redis_pool.clone());
-    let cache = cache::Handle::new(redis_pool.clone());
-
     tracing::info!("commands: {:?}", commands);
     tracing::info!("filters: {:?}", filters);
     tracing::info!("timers: {:?}", timers);
 
     // plumbing
     // sub/ws -> msg task
-    let (msg_in_tx, msg_in_rx) = mpsc::channel::<(msg::Location, String)>(32);
+    let (msg_in_tx, msg_in_rx) = mpsc::channel::<(msg::Location, msg::Incoming)>(32);
     // msg task -> pub
     let (pub_in_tx, pub_in_rx) = mpsc::channel::<pubsub::Msg>(32);
     // msg task -> ws
     let (ws_in_tx, ws_in_rx) = mpsc::channel::<ws::Msg>(32);
+    // msg task -> ws, sessions to kick
+    let (ws_revoke_tx, ws_revoke_rx) = mpsc::channel::<Arc<String>>(32);
+    // msg task -> ws, what peers want broadcast
+    let (ws_subscribe_tx, ws_subscribe_rx) = mpsc::channel(32);
+    // msg task -> ws, connection listings for admins
+    let (ws_list_tx, ws_list_rx) = mpsc::channel(8);
     // start msg loop
     let (msg_out_tx, msg_out_rx) = mpsc::channel::<(msg::Location, msg::Response)>(32);
 
     let users = users.unwrap();
+    locale.unwrap();
     tracing::info!("users: {:?}", users);
 
-    let auth = auth::Handle::new(cache.clone(), msg_out_tx.clone(), users);
+    let auth = auth::Handle::new(
+        cache.clone(),
+        msg_out_tx.clone(),
+        users,
+        &server_config.auth,
+        &server_config.twitch.login,
+    );
 
     let msg = msg::Server {
         pub_in_tx,
         ws_in_tx,
+        ws_revoke_tx,
+        ws_subscribe_tx,
+        ws_list_tx,
         msg_out_tx,
         commands,
         filters,
@@ -89,23 +128,112 @@ async fn main() {
         cache: cache.clone(),
         lock: lock.clone(),
         cancel_tasks: RwLock::new(None).into(),
+        chat_load: Arc::new(msg::load::ChatLoad::new(server_config.chat_load)),
+        service_accounts: Arc::new(service_accounts.unwrap()),
+        perm_map: Arc::new(perm_map.unwrap()),
+        currency: Arc::new(currency.unwrap()),
+        profiles: Arc::new(profiles.unwrap()),
+        usage: db::usage::UsageWriter::new(db.clone()),
     };
     let hmsg = msg.start(msg_in_rx, msg_out_rx);
 
     // start redis
-    pubsub::Server::new(
-        redis_pool.clone(),
-        msg_in_tx.clone(),
-        pub_in_rx,
-        &*back::DOWNSTREAM_CHAN, // as &'static str,
-        &*back::UPSTREAM_CHAN,   //as &'static str,
-    )
-    .start();
+    match redis_pool {
+        Some(redis_pool) => {
+            let server = pubsub::Server::new(
+                redis_pool,
+                msg_in_tx.clone(),
+                pub_in_rx,
+                &config.downstream_chan,
+                &config.upstream_chan,
+            );
+            match config.upstream_transport {
+                Transport::Pubsub => server,
+                // shards each need all the chat, so they can't share a group
+                Transport::Stream { .. } => server.read_stream(pubsub::stream::Group {
+                    name: match server_config.shards {
+                        Some(_) => format!("back:{}", server_config.instance),
+                        None => "back".into(),
+                    },
+                    consumer: server_config.instance.clone(),
+                }),
+            }
+            .start()
+        }
+        None => pubsub::discard(pub_in_rx),
+    }
+
+    if let Some(health) = config.health.clone() {
+        tokio::spawn(health::serve(health, "backrs"));
+    }
+
+    // poll twitch for live status if configured
+    if let Some(poller) = twitch::Poller::new(&server_config.twitch, msg_in_tx.clone()) {
+        poller.start();
+    }
 
     // start ws
-    ws::Server::new(msg_in_tx.clone(), ws_in_rx, auth)
-        .start()
-        .await;
+    ws::Server::new(
+        msg_in_tx.clone(),
+        ws_in_rx,
+        ws_revoke_rx,
+        ws_subscribe_rx,
+        ws_list_rx,
+        auth,
+        server_config,
+    )
+    .start()
+    .await;
+
+    tokio::select! {
+        _ = hmsg => {}
+        _ = tokio::signal::ctrl_c() => tracing::info!("shutting down"),
+    }
+    // let another instance take over the timers without waiting for the lease to lapse
+    back::leader::resign(&lock).await;
+    // points from the last moments of chat
+    if let Err(e) = db.flush_points().await {
+        tracing::error!("couldn't write points: {}", e);
+    }
+}
 
-    let _ = tokio::join!(hmsg);
+/// Postgres and redis, or SQLite with the cache and locks in memory
+async fn init_storage(
+    config: &Config,
+    server_config: &ServerConfig,
+) -> (db::Handle, cache::Handle, lock::Handle, Option<RedisPool>) {
+    match server_config.storage {
+        StorageConfig::Postgres(ref database) => {
+            let redis = config
+                .redis
+                .as_ref()
+                .expect("Config::load_server requires redis with postgres");
+            let (db_pool, read_pool, redis_pool) =
+                tokio::join!(init_db(database), init_read_db(database), init_redis(redis));
+            let redis_pool = redis_pool.unwrap();
+            (
+                db::Handle::new(db_pool.unwrap(), read_pool),
+                cache::Handle::new(redis_pool.clone()),
+                lock::Handle::new(redis_pool.clone()),
+                Some(redis_pool),
+            )
+        }
+        #[cfg(feature = "sqlite")]
+        StorageConfig::Sqlite(ref path) => {
+            // the cache and locks stay in memory, but connectors still talk over pubsub if
+            // redis is there
+            let redis_pool = match config.redis {
+                Some(ref redis) => Some(init_redis(redis).await.unwrap()),
+                None => None,
+            };
+            (
+                db::Handle::sqlite(path).unwrap(),
+                cache::Handle::memory(),
+                lock::Handle::memory(),
+                redis_pool,
+            )
+        }
+        #[cfg(not(feature = "sqlite"))]
+        StorageConfig::Sqlite(_) => unreachable!("rejected by Config::load_server"),
+    }
 }
@@ -0,0 +1,541 @@
+//! An in-process stand-in for redis, for running without it. Mirrors the replies (and errors)
+//! redis gives for each op, since callers match on them
+
+use super::{Cache, RespType, TaskChanPair};
+use crate::error::{self, ChanSendError, Error};
+use bb8_redis::redis::{ErrorKind, RedisError};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    time::{Duration, Instant},
+};
+use tokio::sync::mpsc;
+
+/// How often expired keys are swept, they're also dropped when next touched
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug)]
+enum Value {
+    String(String),
+    Hash(HashMap<String, String>),
+    Set(HashSet<String>),
+    /// Kept sorted by (score, member), like redis
+    ZSet(Vec<(f64, String)>),
+    List(VecDeque<String>),
+}
+
+#[derive(Debug)]
+struct Entry {
+    value: Value,
+    expires: Option<Instant>,
+}
+
+/// What redis replies with for a missing key when a string is expected
+fn nil() -> Error {
+    Error::Redis(RedisError::from((
+        ErrorKind::TypeError,
+        "Response was of incompatible type",
+    )))
+}
+
+fn wrong_type() -> Error {
+    Error::Redis(RedisError::from((
+        ErrorKind::ResponseError,
+        "WRONGTYPE Operation against a key holding the wrong kind of value",
+    )))
+}
+
+/// A zset score bound, e.g `-inf`, `5` or `(5` (exclusive)
+fn parse_bound(bound: &str) -> error::Result<(f64, bool)> {
+    let (bound, exclusive) = match bound.strip_prefix('(') {
+        Some(bound) => (bound, true),
+        None => (bound, false),
+    };
+    let score = match bound {
+        "-inf" => f64::NEG_INFINITY,
+        "inf" | "+inf" => f64::INFINITY,
+        score => score.parse::<f64>().map_err(|_| {
+            Error::Redis(RedisError::from((
+                ErrorKind::ResponseError,
+                "min or max is not a float",
+            )))
+        })?,
+    };
+    Ok((score, exclusive))
+}
+
+/// Redis style inclusive start/stop (negative counts from the end) as a range into `len`
+fn index_range(len: usize, start: isize, stop: isize) -> std::ops::Range<usize> {
+    let len = len as isize;
+    let start = if start < 0 { len + start } else { start }.max(0);
+    let stop = if stop < 0 { len + stop } else { stop }.min(len - 1);
+    if start > stop {
+        return 0..0;
+    }
+    start as usize..stop as usize + 1
+}
+
+#[derive(Default)]
+struct Store(HashMap<String, Entry>);
+
+impl Store {
+    fn live(&mut self, key: &str) -> Option<&mut Entry> {
+        let expired = self
+            .0
+            .get(key)
+            .and_then(|entry| entry.expires)
+            .is_some_and(|at| at <= Instant::now());
+        if expired {
+            self.0.remove(key);
+        }
+        self.0.get_mut(key)
+    }
+
+    fn expire(&mut self, key: &str, secs: usize) {
+        if let Some(entry) = self.live(key) {
+            entry.expires = Some(Instant::now() + Duration::from_secs(secs as u64));
+        }
+    }
+
+    fn sweep(&mut self) {
+        let now = Instant::now();
+        self.0
+            .retain(|_, entry| entry.expires.is_none_or(|at| at > now));
+    }
+
+    /// The value at `key`, created with `default` if missing
+    fn value_or(&mut self, key: &str, default: fn() -> Value) -> &mut Value {
+        if self.live(key).is_none() {
+            self.0.insert(
+                key.to_owned(),
+                Entry {
+                    value: default(),
+                    expires: None,
+                },
+            );
+        }
+        &mut self.0.get_mut(key).unwrap().value
+    }
+
+    fn string(&mut self, key: &str) -> error::Result<Option<&String>> {
+        match self.live(key) {
+            None => Ok(None),
+            Some(Entry {
+                value: Value::String(s),
+                ..
+            }) => Ok(Some(s)),
+            Some(_) => Err(wrong_type()),
+        }
+    }
+
+    fn zset(&mut self, key: &str) -> error::Result<Option<&mut Vec<(f64, String)>>> {
+        match self.live(key) {
+            None => Ok(None),
+            Some(Entry {
+                value: Value::ZSet(z),
+                ..
+            }) => Ok(Some(z)),
+            Some(_) => Err(wrong_type()),
+        }
+    }
+
+    fn zset_or_new(&mut self, key: &str) -> error::Result<&mut Vec<(f64, String)>> {
+        match self.value_or(key, || Value::ZSet(vec![])) {
+            Value::ZSet(z) => Ok(z),
+            _ => Err(wrong_type()),
+        }
+    }
+
+    /// Insert or move a member, keeping the set sorted. True if it's new
+    fn zset_put(z: &mut Vec<(f64, String)>, score: f64, member: String) -> bool {
+        let existed = match z.iter().position(|(_, m)| *m == member) {
+            Some(i) => {
+                z.remove(i);
+                true
+            }
+            None => false,
+        };
+        let at = z.partition_point(|(s, m)| (*s, m) < (score, &member));
+        z.insert(at, (score, member));
+        !existed
+    }
+
+    /// Drop empty containers, redis doesn't keep them around
+    fn prune(&mut self, key: &str) {
+        let empty = match self.0.get(key).map(|entry| &entry.value) {
+            Some(Value::Hash(h)) => h.is_empty(),
+            Some(Value::Set(s)) => s.is_empty(),
+            Some(Value::ZSet(z)) => z.is_empty(),
+            Some(Value::List(l)) => l.is_empty(),
+            _ => false,
+        };
+        if empty {
+            self.0.remove(key);
+        }
+    }
+
+    fn handle(&mut self, task: Cache) -> error::Result<RespType> {
+        let res = match task {
+            Cache::Increment(key, delta, expire) => {
+                let count = match self.string(&key)? {
+                    None => 0,
+                    Some(s) => s.parse::<u64>().map_err(|_| {
+                        Error::Redis(RedisError::from((
+                            ErrorKind::ResponseError,
+                            "value is not an integer or out of range",
+                        )))
+                    })?,
+                } + delta as u64;
+                let expires = self.live(&key).and_then(|entry| entry.expires);
+                self.0.insert(
+                    key.to_string(),
+                    Entry {
+                        value: Value::String(count.to_string()),
+                        expires,
+                    },
+                );
+                if expire > 0 {
+                    self.expire(&key, expire);
+                }
+                RespType::U64(count)
+            }
+            Cache::Ping => RespType::String("PONG".into()),
+            Cache::Delete(key) => {
+                RespType::Bool(self.live(&key).is_some() && self.0.remove(key.as_str()).is_some())
+            }
+            Cache::Get(key) => RespType::String(self.string(&key)?.cloned().ok_or_else(nil)?),
+            Cache::GetDel(key) => {
+                let value = self.string(&key)?.cloned().ok_or_else(nil)?;
+                self.0.remove(key.as_str());
+                RespType::String(value)
+            }
+            Cache::Set(key, value, ex, nx) => {
+                if nx && self.live(&key).is_some() {
+                    return Ok(RespType::Bool(false));
+                }
+                self.0.insert(
+                    key.to_string(),
+                    Entry {
+                        value: Value::String(value.to_string()),
+                        expires: None,
+                    },
+                );
+                if ex > 0 {
+                    self.expire(&key, ex);
+                }
+                RespType::Bool(true)
+            }
+            Cache::SetGet(key, value, expire) => {
+                let old = self.string(&key)?.cloned();
+                self.0.insert(
+                    key.to_string(),
+                    Entry {
+                        value: Value::String(value.to_string()),
+                        expires: None,
+                    },
+                );
+                if expire > 0 {
+                    self.expire(&key, expire);
+                }
+                // set either way, like the pipeline does
+                RespType::String(old.ok_or_else(nil)?)
+            }
+            Cache::HashSet(key, field, value, exclusive) => {
+                match self.value_or(&key, || Value::Hash(HashMap::new())) {
+                    Value::Hash(h) => {
+                        if exclusive && h.contains_key(field.as_str()) {
+                            RespType::Bool(false)
+                        } else {
+                            RespType::Bool(h.insert(field.to_string(), value).is_none())
+                        }
+                    }
+                    _ => return Err(wrong_type()),
+                }
+            }
+            Cache::HashGetAll(key) => match self.live(&key) {
+                None => RespType::VecStringString(vec![]),
+                Some(Entry {
+                    value: Value::Hash(h),
+                    ..
+                }) => RespType::VecStringString(
+                    h.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+                ),
+                Some(_) => return Err(wrong_type()),
+            },
+            Cache::Rename(key, new_key) => {
+                // drops it if it's expired
+                self.live(&key);
+                match self.0.remove(key.as_str()) {
+                    Some(entry) => {
+                        self.0.insert(new_key.to_string(), entry);
+                        RespType::Bool(true)
+                    }
+                    None => RespType::Bool(false),
+                }
+            }
+            Cache::HashDrain(key) => {
+                let is_hash = self
+                    .live(&key)
+                    .map(|entry| matches!(entry.value, Value::Hash(_)));
+                match is_hash {
+                    None => RespType::VecStringString(vec![]),
+                    Some(false) => return Err(wrong_type()),
+                    Some(true) => match self.0.remove(key.as_str()).map(|entry| entry.value) {
+                        Some(Value::Hash(h)) => RespType::VecStringString(h.into_iter().collect()),
+                        _ => unreachable!(),
+                    },
+                }
+            }
+            Cache::HashDelete(key, field) => {
+                let removed = match self.live(&key) {
+                    None => false,
+                    Some(Entry {
+                        value: Value::Hash(h),
+                        ..
+                    }) => h.remove(field.as_str()).is_some(),
+                    Some(_) => return Err(wrong_type()),
+                };
+                self.prune(&key);
+                RespType::Bool(removed)
+            }
+            Cache::HashTake(key, field) => {
+                let value = match self.live(&key) {
+                    None => None,
+                    Some(Entry {
+                        value: Value::Hash(h),
+                        ..
+                    }) => h.remove(field.as_str()),
+                    Some(_) => return Err(wrong_type()),
+                };
+                self.prune(&key);
+                RespType::String(value.ok_or_else(nil)?)
+            }
+            Cache::HashReplace(key, field, value) => match self.live(&key) {
+                None => return Err(nil()),
+                Some(Entry {
+                    value: Value::Hash(h),
+                    ..
+                }) => match h.get_mut(field.as_str()) {
+                    Some(old) => RespType::String(std::mem::replace(old, value.to_string())),
+                    None => return Err(nil()),
+                },
+                Some(_) => return Err(wrong_type()),
+            },
+            Cache::SetAdd(key, member, expire) => {
+                let added = match self.value_or(&key, || Value::Set(HashSet::new())) {
+                    Value::Set(s) => s.insert(member.to_string()),
+                    _ => return Err(wrong_type()),
+                };
+                if expire > 0 {
+                    self.expire(&key, expire);
+                }
+                RespType::Bool(added)
+            }
+            Cache::SetIsMember(key, member) => match self.live(&key) {
+                None => RespType::Bool(false),
+                Some(Entry {
+                    value: Value::Set(s),
+                    ..
+                }) => RespType::Bool(s.contains(member.as_str())),
+                Some(_) => return Err(wrong_type()),
+            },
+            Cache::Zadd(key, score, member) => {
+                let (score, _) = parse_bound(&score)?;
+                let z = self.zset_or_new(&key)?;
+                RespType::Bool(Self::zset_put(z, score, member.to_string()))
+            }
+            Cache::Zincrby(key, member, delta) => {
+                let z = self.zset_or_new(&key)?;
+                let score = z
+                    .iter()
+                    .find(|(_, m)| **m == *member)
+                    .map_or(0.0, |(s, _)| *s)
+                    + delta as f64;
+                Self::zset_put(z, score, member.to_string());
+                RespType::U64(score.max(0.0) as u64)
+            }
+            Cache::Zremrangebyscore(key, min, max) => {
+                let ((min, min_ex), (max, max_ex)) = (parse_bound(&min)?, parse_bound(&max)?);
+                let removed = match self.zset(&key)? {
+                    None => 0,
+                    Some(z) => {
+                        let before = z.len();
+                        z.retain(|(s, _)| {
+                            let above = if min_ex { *s > min } else { *s >= min };
+                            let below = if max_ex { *s < max } else { *s <= max };
+                            !(above && below)
+                        });
+                        before - z.len()
+                    }
+                };
+                self.prune(&key);
+                RespType::Bool(removed > 0)
+            }
+            Cache::Zrangebyscore(key, min, max) => {
+                let ((min, min_ex), (max, max_ex)) = (parse_bound(&min)?, parse_bound(&max)?);
+                RespType::VecString(match self.zset(&key)? {
+                    None => vec![],
+                    Some(z) => z
+                        .iter()
+                        .filter(|(s, _)| {
+                            let above = if min_ex { *s > min } else { *s >= min };
+                            let below = if max_ex { *s < max } else { *s <= max };
+                            above && below
+                        })
+                        .map(|(_, m)| m.clone())
+                        .collect(),
+                })
+            }
+            Cache::Zrange(key, start, stop) => RespType::VecString(match self.zset(&key)? {
+                None => vec![],
+                Some(z) => z[index_range(z.len(), start, stop)]
+                    .iter()
+                    .map(|(_, m)| m.clone())
+                    .collect(),
+            }),
+            Cache::Zrangewithscores(key, start, stop) => {
+                RespType::VecStringScore(match self.zset(&key)? {
+                    None => vec![],
+                    Some(z) => z[index_range(z.len(), start, stop)]
+                        .iter()
+                        .map(|(s, m)| (m.clone(), *s as isize))
+                        .collect(),
+                })
+            }
+            Cache::Zrevrangewithscores(key, start, stop) => {
+                RespType::VecStringScore(match self.zset(&key)? {
+                    None => vec![],
+                    Some(z) => {
+                        let rev = z.iter().rev().collect::<Vec<_>>();
+                        rev[index_range(rev.len(), start, stop)]
+                            .iter()
+                            .map(|(s, m)| (m.clone(), *s as isize))
+                            .collect()
+                    }
+                })
+            }
+            Cache::Zremrangebyrank(key, start, stop) => {
+                let removed = match self.zset(&key)? {
+                    None => 0,
+                    Some(z) => z.drain(index_range(z.len(), start, stop)).count(),
+                };
+                self.prune(&key);
+                RespType::U64(removed as u64)
+            }
+            Cache::Zpopmax(key, count) => {
+                let popped = match self.zset(&key)? {
+                    None => vec![],
+                    Some(z) => {
+                        let at = z.len().saturating_sub(count.max(0) as usize);
+                        z.drain(at..).rev().map(|(s, m)| (m, s as isize)).collect()
+                    }
+                };
+                self.prune(&key);
+                RespType::VecStringScore(popped)
+            }
+            Cache::Zpopmin(key, count) => {
+                let popped = match self.zset(&key)? {
+                    None => vec![],
+                    Some(z) => {
+                        let to = (count.max(0) as usize).min(z.len());
+                        z.drain(..to).map(|(s, m)| (m, s as isize)).collect()
+                    }
+                };
+                self.prune(&key);
+                RespType::VecStringScore(popped)
+            }
+            Cache::Zrank(key, member) => {
+                let rank = self
+                    .zset(&key)?
+                    .and_then(|z| z.iter().position(|(_, m)| *m == *member));
+                match rank {
+                    Some(rank) => RespType::U64(rank as u64),
+                    None => return Err(nil()),
+                }
+            }
+            Cache::Zrem(key, member) => {
+                let removed = match self.zset(&key)? {
+                    None => false,
+                    Some(z) => {
+                        let before = z.len();
+                        z.retain(|(_, m)| *m != *member);
+                        before != z.len()
+                    }
+                };
+                self.prune(&key);
+                RespType::Bool(removed)
+            }
+            Cache::Zcard(key) => RespType::U64(self.zset(&key)?.map_or(0, |z| z.len() as u64)),
+            Cache::ListPush(key, value, max) => {
+                match self.value_or(&key, || Value::List(VecDeque::new())) {
+                    Value::List(l) => {
+                        l.push_back(value.to_string());
+                        while l.len() > max.max(0) as usize {
+                            l.pop_front();
+                        }
+                        RespType::U64(l.len() as u64)
+                    }
+                    _ => return Err(wrong_type()),
+                }
+            }
+            Cache::ListRange(key) => match self.live(&key) {
+                None => RespType::VecString(vec![]),
+                Some(Entry {
+                    value: Value::List(l),
+                    ..
+                }) => RespType::VecString(l.iter().cloned().collect()),
+                Some(_) => return Err(wrong_type()),
+            },
+            Cache::ListDrain(key) => {
+                let is_list = self
+                    .live(&key)
+                    .map(|entry| matches!(entry.value, Value::List(_)));
+                match is_list {
+                    None => RespType::VecString(vec![]),
+                    Some(false) => return Err(wrong_type()),
+                    Some(true) => match self.0.remove(key.as_str()).map(|entry| entry.value) {
+                        Some(Value::List(l)) => RespType::VecString(l.into()),
+                        _ => unreachable!(),
+                    },
+                }
+            }
+        };
+        Ok(res)
+    }
+}
+
+/// Handles store access
+/// backed by a map in memory, so it's gone on restart
+pub(super) struct Actor {
+    rx: mpsc::Receiver<TaskChanPair>,
+    store: Store,
+}
+
+impl Actor {
+    pub(super) fn new(rx: mpsc::Receiver<TaskChanPair>) -> Self {
+        Self {
+            rx,
+            store: Store::default(),
+        }
+    }
+
+    #[tracing::instrument(skip_all, name = "memory cache")]
+    pub(super) async fn run(mut self) {
+        let mut sweep = tokio::time::interval(SWEEP_INTERVAL);
+        loop {
+            tokio::select! {
+                msg = self.rx.recv() => {
+                    let (task, tx) = match msg {
+                        Some(msg) => msg,
+                        None => break,
+                    };
+                    // every op is quick, no need to spawn
+                    let resp = self.store.handle(task);
+                    if let Err(e) = tx.send(resp) {
+                        tracing::error!("{}", ChanSendError { msg: format!("{:?}", e) });
+                    }
+                }
+                _ = sweep.tick() => self.store.sweep(),
+            }
+        }
+    }
+}
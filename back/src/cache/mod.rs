@@ -1,18 +1,59 @@
+mod memory;
+
 use crate::{
     error::{self, ChanSendError, Error},
+    msg::HealthStatus,
     RedisPool,
 };
-use bb8_redis::redis::{self, AsyncCommands, RedisError};
-use std::{fmt::Debug, sync::Arc};
-use tokio::sync::{mpsc, oneshot};
+use bb8_redis::redis::{self, AsyncCommands};
+use std::{
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::sync::{mpsc, oneshot, watch};
+
+/// Consecutive connection failures before the breaker opens
+const BREAKER_THRESHOLD: usize = 5;
+const BREAKER_BACKOFF_MIN: Duration = Duration::from_secs(1);
+const BREAKER_BACKOFF_MAX: Duration = Duration::from_secs(60);
+
+/// Set the field only if it's there, returning what it was (nil if it wasn't)
+const HASH_REPLACE_SCRIPT: &str = r#"
+local old = redis.call('HGET', KEYS[1], ARGV[1])
+if old then redis.call('HSET', KEYS[1], ARGV[1], ARGV[2]) end
+return old
+"#;
+
+const RENAME_SCRIPT: &str = r#"
+if redis.call('EXISTS', KEYS[1]) == 0 then return 0 end
+redis.call('RENAME', KEYS[1], KEYS[2])
+return 1
+"#;
+
+#[derive(Debug)]
+pub struct CacheUnavailable;
+
+impl std::fmt::Display for CacheUnavailable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("cache unavailable, circuit breaker open")
+    }
+}
 
 #[derive(Debug)]
 pub(crate) enum Cache {
+    /// Answered with PONG if the cache can be reached
+    Ping,
     /// key, delta, expiry
     Increment(Arc<String>, usize, usize),
     Delete(Arc<String>),
     Get(Arc<String>),
     GetDel(Arc<String>),
+    /// key, new key, replacing whatever was there. False if there was no key
+    Rename(Arc<String>, Arc<String>),
     /// key, value, expiry, exclusive
     Set(Arc<String>, Arc<String>, usize, bool),
     SetGet(Arc<String>, Arc<String>, usize),
@@ -20,15 +61,50 @@ pub(crate) enum Cache {
     HashSet(Arc<String>, Arc<String>, String, bool),
     //HashGet(Arc<String>, String),
     HashGetAll(Arc<String>),
+    /// key, emptying the hash
+    HashDrain(Arc<String>),
+    /// key, field
+    HashDelete(Arc<String>, Arc<String>),
+    /// key, field. Removed, answered with what it was.
+    /// Missing fields are an error, like a missing key on Get
+    HashTake(Arc<String>, Arc<String>),
+    /// key, field, value. Only set if the field's already there, answered with what it was.
+    /// Missing fields are an error, like a missing key on Get
+    HashReplace(Arc<String>, Arc<String>, Arc<String>),
     //HashRand(&'static str, u64),
+    /// key, member, expiry
+    SetAdd(Arc<String>, Arc<String>, usize),
+    /// key, member
+    SetIsMember(Arc<String>, Arc<String>),
     Zadd(Arc<String>, Arc<String>, Arc<String>),
+    /// key, member, delta
+    Zincrby(Arc<String>, Arc<String>, isize),
     /// key, min, max
     Zremrangebyscore(Arc<String>, Arc<String>, Arc<String>),
+    /// key, min, max, lowest score first
+    Zrangebyscore(Arc<String>, Arc<String>, Arc<String>),
     /// key, start, stop
     Zrange(Arc<String>, isize, isize),
     /// key, start, stop
     Zrangewithscores(Arc<String>, isize, isize),
+    /// key, start, stop, highest score first
+    Zrevrangewithscores(Arc<String>, isize, isize),
+    /// key, start, stop, lowest score first
+    Zremrangebyrank(Arc<String>, isize, isize),
     Zpopmax(Arc<String>, isize),
+    /// key, count, lowest score first
+    Zpopmin(Arc<String>, isize),
+    /// key, member. Missing members are an error, like a missing key on Get
+    Zrank(Arc<String>, Arc<String>),
+    /// key, member
+    Zrem(Arc<String>, Arc<String>),
+    Zcard(Arc<String>),
+    /// key, value, max length (oldest dropped first)
+    ListPush(Arc<String>, Arc<String>, isize),
+    /// key, oldest first
+    ListRange(Arc<String>),
+    /// key, oldest first, emptying the list
+    ListDrain(Arc<String>),
 }
 
 type Resp = error::Result<RespType>;
@@ -69,20 +145,99 @@ impl Debug for RespType {
     }
 }
 
+/// Fails fast while redis is unreachable, instead of piling up tasks waiting on the pool
+struct Breaker {
+    failures: AtomicUsize,
+    open: AtomicBool,
+    status_tx: watch::Sender<HealthStatus>,
+}
+
+impl Breaker {
+    /// Only connection-level errors count, e.g a missing key doesn't
+    fn is_conn_failure(res: &error::Result<RespType>) -> bool {
+        match res {
+            Err(Error::Bb8(_)) => true,
+            Err(Error::Redis(e)) => {
+                e.is_io_error()
+                    || e.is_connection_dropped()
+                    || e.is_connection_refusal()
+                    || e.is_timeout()
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns true if the breaker has just tripped
+    fn record(&self, res: &error::Result<RespType>) -> bool {
+        if !Self::is_conn_failure(res) {
+            self.failures.store(0, Ordering::Release);
+            return false;
+        }
+
+        let failures = self.failures.fetch_add(1, Ordering::AcqRel) + 1;
+        failures >= BREAKER_THRESHOLD && !self.open.swap(true, Ordering::AcqRel)
+    }
+
+    /// Ping redis with exponential backoff until it responds, then close the breaker
+    async fn probe(self: Arc<Self>, pool: RedisPool) {
+        let mut backoff = BREAKER_BACKOFF_MIN;
+        loop {
+            tracing::warn!(backoff = ?backoff, "\x1b[91mcache unavailable, retrying\x1b[0m");
+            tokio::time::sleep(backoff).await;
+
+            let res = match pool.get().await {
+                Ok(mut conn) => redis::cmd("PING")
+                    .query_async::<redis::aio::Connection, String>(&mut *conn)
+                    .await
+                    .map_err(Error::Redis),
+                Err(e) => Err(e.into()),
+            };
+
+            match res {
+                Ok(_) => break,
+                Err(e) => {
+                    tracing::error!("{}", e);
+                    backoff = (backoff * 2).min(BREAKER_BACKOFF_MAX);
+                }
+            }
+        }
+
+        self.failures.store(0, Ordering::Release);
+        self.open.store(false, Ordering::Release);
+        let _ = self.status_tx.send(HealthStatus::Up);
+        tracing::info!("\x1b[92mcache available again\x1b[0m");
+    }
+}
+
 struct Actor {
     rx: mpsc::Receiver<TaskChanPair>,
     pool: RedisPool,
+    breaker: Arc<Breaker>,
 }
 
 /// Handles store access
 /// currently backed by redis
 impl Actor {
-    fn new(rx: mpsc::Receiver<TaskChanPair>, pool: RedisPool) -> Self {
-        Self { rx, pool }
+    fn new(rx: mpsc::Receiver<TaskChanPair>, pool: RedisPool, breaker: Arc<Breaker>) -> Self {
+        Self { rx, pool, breaker }
     }
 
-    async fn handle_task(pool: RedisPool, (task, tx): TaskChanPair) -> error::Result<()> {
-        let resp = Self::_handle_task(pool, task).await.map_err(Error::Redis);
+    async fn handle_task(
+        pool: RedisPool,
+        breaker: Arc<Breaker>,
+        (task, tx): TaskChanPair,
+    ) -> error::Result<()> {
+        let resp = if breaker.open.load(Ordering::Acquire) {
+            Err(CacheUnavailable.into())
+        } else {
+            let resp = Self::_handle_task(pool.clone(), task).await;
+            if breaker.record(&resp) {
+                tracing::error!("\x1b[91mcache circuit breaker open\x1b[0m");
+                let _ = breaker.status_tx.send(HealthStatus::Down);
+                tokio::spawn(breaker.clone().probe(pool));
+            }
+            resp
+        };
         tx.send(resp).map_err(|e| {
             ChanSendError {
                 msg: format!("{:?}", e),
@@ -91,9 +246,9 @@ impl Actor {
         })
     }
 
-    async fn _handle_task(pool: RedisPool, task: Cache) -> Result<RespType, RedisError> {
-        let mut conn = pool.get().await.unwrap();
-        match task {
+    async fn _handle_task(pool: RedisPool, task: Cache) -> error::Result<RespType> {
+        let mut conn = pool.get().await?;
+        let res = match task {
             Cache::Increment(key, delta, expire) => {
                 // atomically increment count
                 let mut cmd = redis::pipe();
@@ -105,6 +260,10 @@ impl Actor {
                     .await
                     .map(|(r,)| RespType::U64(r))
             }
+            Cache::Ping => redis::cmd("PING")
+                .query_async::<redis::aio::Connection, String>(&mut conn)
+                .await
+                .map(RespType::String),
             Cache::Delete(key) => redis::cmd("DEL")
                 .arg(key.as_str())
                 .query_async::<redis::aio::Connection, bool>(&mut conn)
@@ -166,7 +325,34 @@ impl Actor {
             //         .ok();
             //     let _ = tx.send(resp.map(RespType::String));
             // }
+            Cache::Rename(key, new_key) => redis::cmd("EVAL")
+                .arg(&[RENAME_SCRIPT, "2", &key, &new_key])
+                .query_async::<redis::aio::Connection, bool>(&mut conn)
+                .await
+                .map(RespType::Bool),
             Cache::HashGetAll(key) => conn.hgetall(&*key).await.map(RespType::VecStringString),
+            Cache::HashDrain(key) => redis::pipe()
+                .atomic()
+                .hgetall(&*key)
+                .del(&*key)
+                .ignore()
+                .query_async::<redis::aio::Connection, (Vec<(String, String)>,)>(&mut conn)
+                .await
+                .map(|(hash,)| RespType::VecStringString(hash)),
+            Cache::HashDelete(key, field) => conn.hdel(&*key, &*field).await.map(RespType::Bool),
+            Cache::HashTake(key, field) => redis::pipe()
+                .atomic()
+                .hget(&*key, &*field)
+                .hdel(&*key, &*field)
+                .ignore()
+                .query_async::<redis::aio::Connection, (String,)>(&mut conn)
+                .await
+                .map(|(value,)| RespType::String(value)),
+            Cache::HashReplace(key, field, value) => redis::cmd("EVAL")
+                .arg(&[HASH_REPLACE_SCRIPT, "1", &key, &field, &value])
+                .query_async::<redis::aio::Connection, String>(&mut conn)
+                .await
+                .map(RespType::String),
             // Cache::HashRand(key, num) => {
             //     let resp = redis::cmd("HRANDFIELD")
             //         .arg(&[key, &num.to_string()])
@@ -175,16 +361,39 @@ impl Actor {
             //         .ok();
             //     let _ = tx.send(resp.map(RespType::VecString));
             // }
+            Cache::SetAdd(key, member, expire) => {
+                // true if member wasn't already in the set
+                let mut cmd = redis::pipe();
+                cmd.sadd(&*key, &*member);
+                if expire > 0 {
+                    cmd.expire(&*key, expire).ignore();
+                }
+                cmd.query_async::<redis::aio::Connection, (bool,)>(&mut conn)
+                    .await
+                    .map(|(r,)| RespType::Bool(r))
+            }
+            Cache::SetIsMember(key, member) => {
+                conn.sismember(&*key, &*member).await.map(RespType::Bool)
+            }
             Cache::Zadd(key, score, value) => redis::cmd("ZADD")
                 .arg(&[key.as_str(), score.as_str(), value.as_str()])
                 .query_async::<redis::aio::Connection, bool>(&mut conn)
                 .await
                 .map(RespType::Bool),
+            Cache::Zincrby(key, member, delta) => conn
+                .zincr(&*key, &*member, delta)
+                .await
+                .map(|score: isize| RespType::U64(score.max(0) as u64)),
             Cache::Zremrangebyscore(key, min, max) => redis::cmd("ZREMRANGEBYSCORE")
                 .arg(&[key.as_str(), min.as_str(), max.as_str()])
                 .query_async::<redis::aio::Connection, bool>(&mut conn)
                 .await
                 .map(RespType::Bool),
+            Cache::Zrangebyscore(key, min, max) => redis::cmd("ZRANGEBYSCORE")
+                .arg(&[key.as_str(), min.as_str(), max.as_str()])
+                .query_async::<redis::aio::Connection, Vec<String>>(&mut conn)
+                .await
+                .map(RespType::VecString),
             Cache::Zrange(key, start, stop) => conn
                 .zrange(&*key, start, stop)
                 .await
@@ -193,19 +402,53 @@ impl Actor {
                 .zrange_withscores(&*key, start, stop)
                 .await
                 .map(RespType::VecStringScore),
+            Cache::Zrevrangewithscores(key, start, stop) => conn
+                .zrevrange_withscores(&*key, start, stop)
+                .await
+                .map(RespType::VecStringScore),
+            Cache::Zremrangebyrank(key, start, stop) => conn
+                .zremrangebyrank(&*key, start, stop)
+                .await
+                .map(|removed: usize| RespType::U64(removed as u64)),
             Cache::Zpopmax(key, count) => conn
                 .zpopmax(&*key, count)
                 .await
                 .map(RespType::VecStringScore),
-        }
+            Cache::Zpopmin(key, count) => conn
+                .zpopmin(&*key, count)
+                .await
+                .map(RespType::VecStringScore),
+            Cache::Zrank(key, member) => conn.zrank(&*key, &*member).await.map(RespType::U64),
+            Cache::Zrem(key, member) => conn.zrem(&*key, &*member).await.map(RespType::Bool),
+            Cache::Zcard(key) => conn.zcard(&*key).await.map(RespType::U64),
+            Cache::ListPush(key, value, max) => redis::pipe()
+                .atomic()
+                .rpush(&*key, &*value)
+                .ltrim(&*key, -max, -1)
+                .ignore()
+                .query_async::<redis::aio::Connection, (u64,)>(&mut conn)
+                .await
+                .map(|(len,)| RespType::U64(len.min(max as u64))),
+            Cache::ListRange(key) => conn.lrange(&*key, 0, -1).await.map(RespType::VecString),
+            Cache::ListDrain(key) => redis::pipe()
+                .atomic()
+                .lrange(&*key, 0, -1)
+                .del(&*key)
+                .ignore()
+                .query_async::<redis::aio::Connection, (Vec<String>,)>(&mut conn)
+                .await
+                .map(|(list,)| RespType::VecString(list)),
+        };
+        res.map_err(Error::Redis)
     }
 
     #[tracing::instrument(skip_all)]
     async fn run(mut self) {
         while let Some(msg) = self.rx.recv().await {
             let pool = self.pool.clone();
+            let breaker = self.breaker.clone();
             tokio::spawn(async move {
-                if let Err(e) = Self::handle_task(pool, msg).await {
+                if let Err(e) = Self::handle_task(pool, breaker, msg).await {
                     tracing::error!("{}", e);
                 }
             });
@@ -216,6 +459,7 @@ impl Actor {
 #[derive(Clone)]
 pub struct Handle {
     tx: mpsc::Sender<TaskChanPair>,
+    health_rx: watch::Receiver<HealthStatus>,
 }
 
 impl std::fmt::Debug for Handle {
@@ -227,8 +471,28 @@ impl std::fmt::Debug for Handle {
 impl Handle {
     pub fn new(pool: RedisPool) -> Self {
         let (tx, rx) = mpsc::channel(32);
-        tokio::spawn(Actor::new(rx, pool).run());
-        Self { tx }
+        let (status_tx, health_rx) = watch::channel(HealthStatus::Up);
+        let breaker = Arc::new(Breaker {
+            failures: AtomicUsize::new(0),
+            open: AtomicBool::new(false),
+            status_tx,
+        });
+        tokio::spawn(Actor::new(rx, pool, breaker).run());
+        Self { tx, health_rx }
+    }
+
+    /// Kept in this process instead of redis, for running without it
+    pub fn memory() -> Self {
+        let (tx, rx) = mpsc::channel(32);
+        // never goes down
+        let (_, health_rx) = watch::channel(HealthStatus::Up);
+        tokio::spawn(memory::Actor::new(rx).run());
+        Self { tx, health_rx }
+    }
+
+    /// Watch for redis availability changes
+    pub(crate) fn health(&self) -> watch::Receiver<HealthStatus> {
+        self.health_rx.clone()
     }
 
     async fn task(&self, task: Cache) -> error::Result<RespType> {
@@ -0,0 +1,274 @@
+use super::{CmdDesc, Context, Invokable, RespHandle, RunRes};
+use crate::{
+    cache::{self, Cache, RespType},
+    error,
+    msg::{
+        corr_id, Alert, Chat, Invocation, InvocationKind, Location, Payload, Platform, Response,
+    },
+};
+use back_derive::command;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{sync::watch, task::JoinHandle};
+use tracing::{debug_span, Instrument};
+
+/// How often an empty queue is checked
+const IDLE_POLL: Duration = Duration::from_secs(1);
+
+/// Waiting in the queue, scored by when it came in
+#[derive(Debug, Serialize, Deserialize)]
+struct Queued {
+    /// Keeps identical alerts apart in the sorted set
+    id: String,
+    user: String,
+    alert: Alert,
+    text: String,
+}
+
+#[command(cmd)]
+/// Show follows, subs, memberships, raids and donations on an overlay, one at a time
+pub struct Alerts {
+    /// Platforms
+    #[cmd(defl("Platform::STREAM"))]
+    platforms: Platform,
+    /// Follow alert, empty to skip. Accepts {name}
+    #[cmd(def("{name} just followed!"), constr(range = "0..=200"))]
+    follow_msg: String,
+    /// Sub alert, empty to skip. Accepts {name}, {tier}, {months}
+    #[cmd(
+        def("{name} subscribed at tier {tier} for {months} months!"),
+        constr(range = "0..=200")
+    )]
+    sub_msg: String,
+    /// Gifted sub alert, empty to skip. Accepts {name}, {tier}
+    #[cmd(def("{name} was gifted a tier {tier} sub!"), constr(range = "0..=200"))]
+    gift_msg: String,
+    /// Membership alert, empty to skip. Accepts {name}, {level}, {months}
+    #[cmd(def("{name} joined {level}!"), constr(range = "0..=200"))]
+    member_msg: String,
+    /// Raid alert, empty to skip. Accepts {name}, {viewers}
+    #[cmd(
+        def("{name} is raiding with {viewers} viewers!"),
+        constr(range = "0..=200")
+    )]
+    raid_msg: String,
+    /// Donation alert, empty to skip. Accepts {name}, {amount}
+    #[cmd(def("{name} donated {amount}!"), constr(range = "0..=200"))]
+    donation_msg: String,
+    /// Smallest raid shown
+    min_raid_viewers: u64,
+    /// Smallest donation shown, in whole units of its currency
+    min_donation: u64,
+    /// How long each alert stays up (in seconds)
+    #[cmd(def(8u64), constr(range = "1..=60"))]
+    display_secs: u64,
+    /// Max alerts waiting, more are dropped
+    #[cmd(def(50u64), constr(range = "1..=500"))]
+    max_queued: u64,
+}
+
+impl Alerts {
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn chat(&self, _ctx: &Context<'_>, _chat: &Chat) -> error::Result<RunRes> {
+        Ok(RunRes::Noop)
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn invoke(
+        &self,
+        ctx: &Context<'_>,
+        invocation: &Invocation,
+    ) -> Option<RunRes> {
+        let alert = match invocation.kind {
+            Some(InvocationKind::Alert(ref alert)) => alert,
+            _ => return None,
+        };
+
+        match self.run(ctx, alert).await {
+            Ok(r) => Some(r),
+            Err(e) => {
+                tracing::error!("{}", e);
+                None
+            }
+        }
+    }
+
+    fn queue_key(name: &str) -> Arc<String> {
+        Arc::new(format!(
+            "aussiebot!{}!alerts!{}",
+            crate::channel_name(),
+            name
+        ))
+    }
+
+    /// The filled-in template, None if it's skipped or under its threshold
+    fn text(&self, name: &str, alert: &Alert) -> Option<String> {
+        let text = match alert {
+            Alert::Follow => self.follow_msg.clone(),
+            Alert::Subscription {
+                tier,
+                months,
+                gifted,
+            } => {
+                let template = if *gifted {
+                    &self.gift_msg
+                } else {
+                    &self.sub_msg
+                };
+                template
+                    .replace("{tier}", &tier.to_string())
+                    .replace("{months}", &months.to_string())
+            }
+            Alert::Membership { level, months } => self
+                .member_msg
+                .replace("{level}", level)
+                .replace("{months}", &months.to_string()),
+            Alert::Raid { viewers } if *viewers >= self.min_raid_viewers => {
+                self.raid_msg.replace("{viewers}", &viewers.to_string())
+            }
+            Alert::Donation {
+                amount_micros,
+                display,
+                ..
+            } if amount_micros / 1_000_000 >= self.min_donation => {
+                self.donation_msg.replace("{amount}", display)
+            }
+            Alert::Raid { .. } | Alert::Donation { .. } => return None,
+        };
+        match text.is_empty() {
+            true => None,
+            false => Some(text.replace("{name}", name).replace("\\n", "\n")),
+        }
+    }
+
+    #[tracing::instrument(skip(self, ctx), name = "Alerts")]
+    async fn run(&self, ctx: &Context<'_>, alert: &Alert) -> error::Result<RunRes> {
+        if !self.enabled || !self.platforms.contains(ctx.platform) {
+            return Ok(RunRes::Disabled);
+        }
+
+        let text = match self.text(&ctx.user.name, alert) {
+            Some(text) => text,
+            None => return Ok(RunRes::Noop),
+        };
+
+        let key = Self::queue_key(&self.name);
+        let queued = match Cache::Zcard(key.clone()).exec(ctx.cache).await? {
+            RespType::U64(queued) => queued,
+            _ => unreachable!(),
+        };
+        if queued >= self.max_queued {
+            tracing::warn!(queued, "alert queue full, dropping");
+            return Ok(RunRes::Noop);
+        }
+
+        let entry = Queued {
+            id: format!("{:016x}", rand::random::<u64>()),
+            user: ctx.user.name.to_string(),
+            alert: alert.clone(),
+            text,
+        };
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+        Cache::Zadd(
+            key,
+            now.to_string().into(),
+            serde_json::to_string(&entry)?.into(),
+        )
+        .exec(ctx.cache)
+        .await?;
+
+        tracing::info!(queued = queued + 1, "alert queued for {}", ctx.user.name);
+        Ok(RunRes::Ok)
+    }
+
+    /// Show queued alerts one after another, each for display_secs
+    pub(crate) fn init(
+        &self,
+        cancel_chan: watch::Receiver<()>,
+        cache: &cache::Handle,
+        resp: &RespHandle,
+    ) -> Option<JoinHandle<()>> {
+        if !self.enabled {
+            return None;
+        }
+
+        let (name, key, duration) = (
+            self.name.clone(),
+            Self::queue_key(&self.name),
+            self.display_secs,
+        );
+        let (cache, resp) = (cache.clone(), resp.clone());
+
+        tracing::info!("\x1b[93mSpawning Alerts task for {}\x1b[0m", name);
+
+        let handle = tokio::spawn(
+            async move {
+                loop {
+                    match cancel_chan.has_changed() {
+                        Ok(false) => {}
+                        _ => {
+                            tracing::info!("\x1b[93maborting\x1b[0m");
+                            return;
+                        }
+                    }
+
+                    let next = match Cache::Zpopmin(key.clone(), 1).exec(&cache).await {
+                        Ok(RespType::VecStringScore(popped)) => popped.into_iter().next(),
+                        Ok(_) => unreachable!(),
+                        Err(e) => {
+                            tracing::error!("{}", e);
+                            None
+                        }
+                    };
+                    let next = match next.map(|(entry, _)| serde_json::from_str::<Queued>(&entry)) {
+                        Some(Ok(next)) => next,
+                        Some(Err(e)) => {
+                            tracing::error!("dropping malformed alert: {}", e);
+                            continue;
+                        }
+                        None => {
+                            tokio::time::sleep(IDLE_POLL).await;
+                            continue;
+                        }
+                    };
+
+                    tracing::info!(user = next.user.as_str(), "showing alert");
+                    Response {
+                        platform: Platform::WEB,
+                        channel: crate::channel_name(),
+                        corr_id: corr_id(),
+                        payload: Payload::AlertDisplay {
+                            name: name.clone(),
+                            user: next.user,
+                            alert: next.alert,
+                            text: next.text,
+                            duration,
+                        },
+                    }
+                    .send(Location::Websockets(None), &resp)
+                    .await;
+
+                    tokio::time::sleep(Duration::from_secs(duration)).await;
+                }
+            }
+            .instrument(debug_span!("Alerts task")),
+        );
+
+        Some(handle)
+    }
+}
+
+impl CmdDesc for Alerts {
+    #[inline]
+    fn platform(&self) -> Platform {
+        Platform::empty()
+    }
+}
+
+impl Invokable for Alerts {}
@@ -0,0 +1,90 @@
+use super::{unbang_prefix, ArgValue, Command, Context};
+use crate::{
+    db::{Db, Resp},
+    error,
+    msg::ArgMap,
+};
+use std::sync::Arc;
+
+/// Discord won't show any more than this
+pub(crate) const MAX_CHOICES: usize = 25;
+
+/// (shown, value)
+pub(crate) type Choices = Vec<(String, String)>;
+
+/// The argument being typed in, and what's been typed so far
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct PartialArg<'a> {
+    pub(crate) name: &'a str,
+    pub(crate) value: &'a str,
+}
+
+impl<'a> PartialArg<'a> {
+    /// Looks through subcommands too, the focused argument may be nested in one
+    pub(crate) fn find(args: &'a ArgMap, name: &'a str) -> Option<Self> {
+        args.iter().find_map(|(arg, value)| match value {
+            ArgValue::String(value) if arg == name => Some(Self { name, value }),
+            ArgValue::SubCommand(args) => Self::find(args, name),
+            _ => None,
+        })
+    }
+}
+
+/// Choices starting with what's been typed come first, then ones containing it,
+/// each sorted alphabetically, capped to what can be shown
+pub(crate) fn finish(mut choices: Choices, partial: &str) -> Choices {
+    let partial = partial.trim().to_lowercase();
+
+    choices.retain(|(shown, _)| shown.to_lowercase().contains(&partial));
+    choices.sort_by_cached_key(|(shown, _)| {
+        let lower = shown.to_lowercase();
+        (!lower.starts_with(&partial), lower)
+    });
+    choices.dedup();
+    choices.truncate(MAX_CHOICES);
+
+    choices
+}
+
+/// Choices for arguments no command provides its own for, picked by argument name
+pub(crate) async fn builtin(ctx: &Context<'_>, arg: PartialArg<'_>) -> error::Result<Choices> {
+    match arg.name {
+        "user" | "username" => usernames(ctx, arg.value).await,
+        "quote" => Ok(quotes(ctx)),
+        _ => Ok(vec![]),
+    }
+}
+
+/// Recently seen users on the invoking platform
+pub(crate) async fn usernames(ctx: &Context<'_>, partial: &str) -> error::Result<Choices> {
+    let users = match Db::SearchUsers(
+        ctx.platform,
+        Arc::new(partial.trim().to_owned()),
+        MAX_CHOICES as i64,
+    )
+    .exec(ctx.db)
+    .await?
+    {
+        Resp::Users(users) => users,
+        _ => unreachable!(),
+    };
+
+    Ok(users
+        .into_iter()
+        .map(|(_id, name)| (name.clone(), name))
+        .collect())
+}
+
+/// Quote commands usable on the invoking platform, by prefix
+pub(crate) fn quotes(ctx: &Context<'_>) -> Choices {
+    ctx.commands
+        .iter()
+        .filter_map(|cmd| match cmd {
+            Command::Quote(quote) if quote.can_run(ctx).is_some() => {
+                let prefix = unbang_prefix(&quote.prefix).to_owned();
+                Some((prefix.clone(), prefix))
+            }
+            _ => None,
+        })
+        .collect()
+}
@@ -0,0 +1,267 @@
+use super::{
+    bulk_mod::{self, Args},
+    util, Arg, ArgKind, CmdDesc, Context, Invokable, Log, ModAction, RunRes,
+};
+use crate::{
+    error,
+    i18n::{plural, tr},
+    msg::{Chat, Invocation, Location, Payload, Permissions, Platform, Response, User},
+};
+use back_derive::command;
+use regex::{Regex, RegexBuilder};
+use std::{collections::HashSet, sync::Arc};
+
+/// Names listed in chat, all of them go to the web UI
+const LISTED_NAMES: usize = 10;
+
+#[command(locks(pending))]
+/// Ban every recent chatter whose name matches a pattern, once the matches are confirmed.
+/// Recent chatters come from the Log, so it has to be on for the platform
+pub struct Banwave {
+    /// Command prefix
+    #[cmd(def("!banwave"), constr(non_empty))]
+    prefix: String,
+    /// Autocorrect prefix
+    autocorrect: bool,
+    /// Platforms
+    #[cmd(defl("Platform::CHAT"))]
+    platforms: Platform,
+    /// Permissions
+    #[cmd(defl("Permissions::ADMIN"))]
+    perms: Permissions,
+    /// How long there is to confirm (in seconds)
+    #[cmd(def(60u64), constr(range = "10..=600"))]
+    confirm_secs: u64,
+    /// Most chatters one banwave can ban, patterns matching more are refused
+    #[cmd(def(50u64), constr(range = "1..=500"))]
+    max_matches: u64,
+}
+
+impl Banwave {
+    fn can_run(&self, ctx: &Context<'_>) -> Option<()> {
+        if !self.enabled {
+            return None;
+        }
+
+        // check if platform is applicable
+        if !self.platforms.contains(ctx.platform) {
+            return None;
+        }
+
+        // check perms
+        if ctx.user.perms < self.perms {
+            return None;
+        }
+
+        Some(())
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn chat(&self, ctx: &Context<'_>, chat: &Chat) -> error::Result<RunRes> {
+        if self.can_run(ctx).is_none() {
+            return Ok(RunRes::Disabled);
+        }
+
+        let (autocorrect, args) = match bulk_mod::parse_arguments(
+            &self.prefix,
+            self.autocorrect,
+            &self.levenshtein,
+            chat,
+        ) {
+            Some(t) => t,
+            None if util::starts_with_prefix(&self.prefix, &chat.msg) => {
+                return Ok(RunRes::InvalidArgs)
+            }
+            None => return Ok(RunRes::Noop),
+        };
+
+        if autocorrect {
+            return Ok(RunRes::Autocorrect(self.prefix.clone()));
+        }
+
+        self.run(ctx, args).await
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn invoke(
+        &self,
+        ctx: &Context<'_>,
+        invocation: &Invocation,
+    ) -> Option<RunRes> {
+        self.can_run(ctx)?;
+
+        super::check_invoke_prefix(&self.prefix, &invocation.cmd)?;
+
+        let args = match Args::try_from(&invocation.args) {
+            Ok(args) => args,
+            Err(_) => return Some(RunRes::InvalidArgs),
+        };
+
+        match self.run(ctx, args).await {
+            Ok(r) => Some(r),
+            Err(e) => {
+                tracing::error!("{}", e);
+                None
+            }
+        }
+    }
+
+    /// Everyone in the Log for the platform whose name matches, latest first. Mods and the one
+    /// asking are left out
+    async fn matches(&self, ctx: &Context<'_>, pattern: &Regex) -> error::Result<Vec<Arc<User>>> {
+        let logs = Log::all(ctx.cache, &ctx.platform).await?;
+
+        let mut seen = HashSet::new();
+        let mut matches = vec![];
+        for entry in logs.into_iter().flat_map(|(_, entries)| entries).rev() {
+            let user = match serde_json::from_str::<(String, Chat)>(&entry) {
+                Ok((_, chat)) => chat.user,
+                Err(_) => continue,
+            };
+            if !seen.insert(user.id.clone())
+                || user.perms >= Permissions::MOD
+                || user.id == ctx.user.id
+                || !pattern.is_match(&user.name)
+            {
+                continue;
+            }
+            matches.push(user);
+        }
+        Ok(matches)
+    }
+
+    #[tracing::instrument(level = "trace", skip_all, name = "Banwave")]
+    async fn run(&self, ctx: &Context<'_>, args: Args) -> error::Result<RunRes> {
+        tracing::debug!(name = self.name.as_str(), user = ctx.user.name.as_str(), args = ?args);
+
+        let key = bulk_mod::pending_key(&BANWAVE_LOCK_PENDING, &self.name, ctx);
+        let pattern = match args {
+            Args::Confirm => {
+                let msg = match bulk_mod::take::<Vec<Arc<User>>>(ctx, key).await? {
+                    Some(users) => {
+                        let count = users.len();
+                        for user in users {
+                            bulk_mod::enact(ctx, "Banwave", user, ModAction::Ban).await;
+                        }
+                        tr(
+                            "bulkmod.banned",
+                            &[("count", &count), ("s", &plural(count))],
+                        )
+                    }
+                    None => tr("bulkmod.nothing_pending", &[("secs", &self.confirm_secs)]),
+                };
+                bulk_mod::reply(ctx, msg).await;
+                return Ok(RunRes::Ok);
+            }
+            Args::Name(pattern) => pattern,
+            Args::User(user) => regex::escape(&user.name),
+        };
+
+        let regex = match RegexBuilder::new(&pattern)
+            .case_insensitive(true)
+            .size_limit(1 << 16)
+            .build()
+        {
+            Ok(regex) => regex,
+            Err(e) => {
+                let msg = tr("bulkmod.bad_pattern", &[("error", &e)]);
+                bulk_mod::reply(ctx, msg).await;
+                return Ok(RunRes::Ok);
+            }
+        };
+
+        let users = match self.matches(ctx, &regex).await {
+            Ok(users) => users,
+            Err(e) => {
+                // finding nobody would read as nobody matching
+                bulk_mod::reply(ctx, tr("bulkmod.log_unavailable", &[])).await;
+                return Err(e);
+            }
+        };
+        let count = users.len();
+        let msg = if users.is_empty() {
+            tr("bulkmod.no_matches", &[("pattern", &pattern)])
+        } else if count as u64 > self.max_matches {
+            tr(
+                "bulkmod.too_many",
+                &[("count", &count), ("max", &self.max_matches)],
+            )
+        } else {
+            let separator = tr("list.separator", &[]);
+            let mut names = users
+                .iter()
+                .take(LISTED_NAMES)
+                .map(|u| u.name.as_str())
+                .collect::<Vec<_>>()
+                .join(&separator);
+            if count > LISTED_NAMES {
+                names.push_str(&tr(
+                    "bulkmod.and_more",
+                    &[("count", &(count - LISTED_NAMES))],
+                ));
+            }
+            tr(
+                "bulkmod.confirm_banwave",
+                &[
+                    ("count", &count),
+                    ("s", &plural(count)),
+                    ("users", &names),
+                    ("prefix", &self.prefix),
+                    ("secs", &self.confirm_secs),
+                ],
+            )
+        };
+
+        // the dry run, for the web UI to show in full
+        Response {
+            platform: ctx.platform,
+            channel: crate::channel_name(),
+            corr_id: ctx.corr_id.clone(),
+            payload: Payload::BanwavePreview {
+                platform: ctx.platform,
+                pattern: pattern.clone(),
+                users: users.clone(),
+                by: ctx.user.name.clone(),
+            },
+        }
+        .send(Location::Websockets(None), ctx.resp)
+        .await;
+
+        if !users.is_empty() && count as u64 <= self.max_matches {
+            bulk_mod::hold(ctx, key, self.confirm_secs, &users).await?;
+        }
+        bulk_mod::reply(ctx, msg).await;
+        Ok(RunRes::Ok)
+    }
+}
+
+impl CmdDesc for Banwave {
+    #[inline]
+    fn platform(&self) -> Platform {
+        self.platforms
+    }
+
+    #[inline]
+    fn description(&self, platform: Platform) -> Option<String> {
+        if platform.contains(Platform::DISCORD) {
+            return Some("Ban recent chatters whose names match a pattern".into());
+        }
+
+        None
+    }
+}
+
+impl Invokable for Banwave {
+    fn args(&self, _platform: Platform) -> Vec<Arg> {
+        bulk_mod::invoke_args(Arg {
+            name: "pattern".into(),
+            desc: "Regex matched against names, case insensitive".into(),
+            kind: ArgKind::String,
+            optional: true,
+        })
+    }
+
+    fn hidden(&self, _platform: Platform) -> bool {
+        true
+    }
+}
@@ -0,0 +1,233 @@
+//! When timers and stream announcements hold off. Blackout windows are checked against the clock,
+//! and timers can also wait for the stream to be live and past its intro. Everything goes through
+//! [`Quiet::allows`] right before it's sent, which traces whatever it holds back
+use super::{uptime, OwnedValueError, Value, VerifyConstraint};
+use crate::{
+    cache,
+    error::{self, Error},
+    msg::Platform,
+};
+use std::{
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use time::{OffsetDateTime, UtcOffset};
+
+const DAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+const MINS_PER_DAY: u16 = 24 * 60;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Window {
+    /// Days it starts on, monday first
+    days: [bool; 7],
+    /// Minutes into the day, end exclusive. Ends at or before the start run past midnight
+    start: u16,
+    end: u16,
+}
+
+impl Window {
+    fn contains(&self, weekday: usize, minute: u16) -> bool {
+        if self.start < self.end {
+            return self.days[weekday] && (self.start..self.end).contains(&minute);
+        }
+        // the part after midnight belongs to the day before
+        (self.days[weekday] && minute >= self.start)
+            || (self.days[(weekday + 6) % 7] && minute < self.end)
+    }
+}
+
+fn parse_day(day: &str) -> Result<usize, String> {
+    DAYS.iter()
+        .position(|d| day.eq_ignore_ascii_case(d))
+        .ok_or_else(|| format!("'{}' isn't a day (mon, tue, ..., sun)", day))
+}
+
+fn parse_days(days: &str) -> Result<[bool; 7], String> {
+    if days == "*" {
+        return Ok([true; 7]);
+    }
+    let (first, last) = match days.split_once('-') {
+        Some((first, last)) => (parse_day(first)?, parse_day(last)?),
+        None => (parse_day(days)?, parse_day(days)?),
+    };
+    // sat-mon wraps around the week
+    let mut out = [false; 7];
+    let mut day = first;
+    loop {
+        out[day] = true;
+        if day == last {
+            break;
+        }
+        day = (day + 1) % 7;
+    }
+    Ok(out)
+}
+
+fn parse_time(time: &str) -> Result<u16, String> {
+    let invalid = || format!("'{}' isn't a time (HH:MM)", time);
+    let (hours, mins) = time.split_once(':').ok_or_else(invalid)?;
+    let hours: u16 = hours.parse().map_err(|_| invalid())?;
+    let mins: u16 = mins.parse().map_err(|_| invalid())?;
+    if mins >= 60 {
+        return Err(invalid());
+    }
+    // 24:00 for the end of the day
+    match hours.checked_mul(60).and_then(|h| h.checked_add(mins)) {
+        Some(minute) if minute <= MINS_PER_DAY => Ok(minute),
+        _ => Err(invalid()),
+    }
+}
+
+/// Windows to keep quiet in, e.g. `mon-fri 09:00-17:00, sun, 23:30-01:00`. Each is a day or
+/// range of days, a time range, or both, a missing part meaning all of them
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Blackout {
+    src: String,
+    windows: Vec<Window>,
+}
+
+impl Blackout {
+    fn contains(&self, at: OffsetDateTime) -> bool {
+        let weekday = at.weekday().number_days_from_monday() as usize;
+        let minute = at.hour() as u16 * 60 + at.minute() as u16;
+        self.windows.iter().any(|w| w.contains(weekday, minute))
+    }
+}
+
+impl FromStr for Blackout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut windows = vec![];
+        for window in s.split(',').map(str::trim).filter(|w| !w.is_empty()) {
+            let mut days = [true; 7];
+            let (mut start, mut end) = (0, MINS_PER_DAY);
+            for part in window.split_whitespace() {
+                match part.split_once('-') {
+                    Some((from, to)) if from.contains(':') => {
+                        start = parse_time(from)?;
+                        end = parse_time(to)?;
+                    }
+                    _ => days = parse_days(part)?,
+                }
+            }
+            if start == end {
+                return Err(format!("'{}' is empty", window));
+            }
+            windows.push(Window { days, start, end });
+        }
+        Ok(Self {
+            src: s.to_owned(),
+            windows,
+        })
+    }
+}
+
+impl TryFrom<Value> for Blackout {
+    type Error = Error;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::String(ref s) => s
+                .parse()
+                .map_err(|e| Error::Generic(format!("invalid blackout: {}", e))),
+            _ => Err(OwnedValueError {
+                expected: "String".into(),
+                value,
+            }
+            .into()),
+        }
+    }
+}
+
+impl From<Blackout> for Value {
+    fn from(x: Blackout) -> Self {
+        Self::String(x.src)
+    }
+}
+
+impl VerifyConstraint for Blackout {}
+
+/// Why something was held back
+#[derive(Debug, Clone, Copy)]
+enum Held {
+    Blackout,
+    Offline,
+    Intro,
+}
+
+/// Everything a post waits on
+#[derive(Debug, Clone)]
+pub(crate) struct Quiet {
+    pub(crate) blackout: Blackout,
+    /// Minutes ahead of UTC the blackout's times are. It's fixed, so it doesn't follow daylight
+    /// saving
+    pub(crate) utc_offset: i64,
+    /// Only while one of `streams` is live
+    pub(crate) only_live: bool,
+    /// Secs after going live to keep waiting, past `only_live`
+    pub(crate) intro: u64,
+    pub(crate) streams: Platform,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+impl Quiet {
+    async fn held(&self, cache: &cache::Handle) -> error::Result<Option<Held>> {
+        if !self.blackout.windows.is_empty() {
+            let offset = UtcOffset::from_whole_seconds(self.utc_offset as i32 * 60)
+                .unwrap_or(UtcOffset::UTC);
+            if self
+                .blackout
+                .contains(OffsetDateTime::now_utc().to_offset(offset))
+            {
+                return Ok(Some(Held::Blackout));
+            }
+        }
+        if !self.only_live && self.intro == 0 {
+            return Ok(None);
+        }
+
+        let streams = match self.streams & Platform::STREAM {
+            p if p.is_empty() => Platform::STREAM,
+            p => p,
+        };
+        let mut started_at = None;
+        for platform in [Platform::YOUTUBE, Platform::TWITCH] {
+            if streams.contains(platform) {
+                if let Some(meta) = uptime::metadata(cache, platform).await? {
+                    started_at =
+                        Some(started_at.map_or(meta.started_at, |s: u64| s.min(meta.started_at)));
+                }
+            }
+        }
+        Ok(match started_at {
+            None if self.only_live => Some(Held::Offline),
+            Some(started_at) if now_secs().saturating_sub(started_at) < self.intro => {
+                Some(Held::Intro)
+            }
+            _ => None,
+        })
+    }
+
+    /// Whether `what` can be sent right now. Anything's held back if the stream state can't be
+    /// read when it's needed
+    pub(crate) async fn allows(&self, cache: &cache::Handle, what: &str) -> bool {
+        match self.held(cache).await {
+            Ok(None) => true,
+            Ok(Some(held)) => {
+                tracing::info!(what, reason = ?held, "held back");
+                false
+            }
+            Err(e) => {
+                tracing::error!(what, "couldn't check if it's quiet time: {}", e);
+                false
+            }
+        }
+    }
+}
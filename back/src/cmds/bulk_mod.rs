@@ -0,0 +1,220 @@
+//! What [`Purge`](super::purge::Purge), [`Banwave`](super::banwave::Banwave) and
+//! [`Untimeout`](super::untimeout::Untimeout) share. None of them act straight away: they say what
+//! they'd do, hold onto it for the invoker, and do exactly that once the same command is sent with
+//! `confirm`. Everything done is logged as mod actions
+use super::{util, Arg, ArgKind, ArgValue, Context, Log, ModAction, RunRes};
+use crate::{
+    cache::{Cache, RespType},
+    error::{self, Error},
+    i18n::tr,
+    msg::{ArgMap, ArgMapError, Chat, Location, Payload, Response, User},
+};
+use bb8_redis::redis;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{de::DeserializeOwned, Serialize};
+use std::sync::Arc;
+
+static ARGS_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(\S+)\s+(.+?)\s*$").unwrap());
+
+const CONFIRM: &str = "confirm";
+
+#[derive(Debug)]
+pub(super) enum Args {
+    /// A name, or for Banwave a pattern
+    Name(String),
+    User(Arc<User>),
+    Confirm,
+}
+
+pub(super) fn parse_arguments(
+    prefix: &str,
+    autocorrect: bool,
+    levenshtein: &Option<super::DFAWrapper>,
+    chat: &Chat,
+) -> Option<(bool, Args)> {
+    let captures = ARGS_REGEX.captures(&chat.msg)?;
+
+    // check command prefix
+    let autocorrect = util::check_autocorrect(prefix, &captures[1], autocorrect, levenshtein)?;
+
+    let args = match &captures[2] {
+        CONFIRM => Args::Confirm,
+        rest => Args::Name(rest.to_owned()),
+    };
+    Some((autocorrect, args))
+}
+
+impl TryFrom<&ArgMap> for Args {
+    type Error = ArgMapError;
+
+    fn try_from(value: &ArgMap) -> Result<Self, Self::Error> {
+        if let Some(ArgValue::Bool(true)) = value.get(CONFIRM) {
+            return Ok(Args::Confirm);
+        }
+        match value.get("user").or_else(|| value.get("pattern")) {
+            Some(ArgValue::User(u)) => Ok(Args::User(Arc::new(User {
+                id: u.id.clone(),
+                name: u.name.clone(),
+                ..Default::default()
+            }))),
+            Some(ArgValue::String(s)) if !s.trim().is_empty() => {
+                Ok(Args::Name(s.trim().to_owned()))
+            }
+            _ => Err(ArgMapError),
+        }
+    }
+}
+
+/// The target, and `confirm` to go ahead instead
+pub(super) fn invoke_args(target: Arg) -> Vec<Arg> {
+    vec![
+        target,
+        Arg {
+            name: CONFIRM.into(),
+            desc: "Go ahead with what was asked for last".into(),
+            kind: ArgKind::Bool,
+            optional: true,
+        },
+    ]
+}
+
+pub(super) fn user_arg() -> Arg {
+    Arg {
+        name: "user".into(),
+        desc: "User".into(),
+        kind: ArgKind::User,
+        optional: true,
+    }
+}
+
+pub(super) async fn reply(ctx: &Context<'_>, msg: String) {
+    Response {
+        platform: ctx.platform,
+        channel: crate::channel_name(),
+        corr_id: ctx.corr_id.clone(),
+        payload: Payload::Message {
+            user: ctx.reply_to(),
+            msg: msg.into(),
+            meta: ctx.meta.clone(),
+            hint: None,
+            thread: None,
+        },
+    }
+    .send(Location::Pubsub, ctx.resp)
+    .await;
+}
+
+/// What's waiting on the invoker to confirm, one per command
+pub(super) fn pending_key(lock: &str, name: &str, ctx: &Context<'_>) -> Arc<String> {
+    Arc::new(format!(
+        "{}_{}_{}:{}",
+        lock, name, ctx.platform, ctx.user.id
+    ))
+}
+
+/// Replaces anything already waiting
+pub(super) async fn hold<T: Serialize>(
+    ctx: &Context<'_>,
+    key: Arc<String>,
+    secs: u64,
+    pending: &T,
+) -> error::Result<()> {
+    let pending = serde_json::to_string(pending)?;
+    Cache::Set(key, pending.into(), secs as usize, false)
+        .exec(ctx.cache)
+        .await?;
+    Ok(())
+}
+
+/// None if nothing's waiting, or it was held too long
+pub(super) async fn take<T: DeserializeOwned>(
+    ctx: &Context<'_>,
+    key: Arc<String>,
+) -> error::Result<Option<T>> {
+    match Cache::GetDel(key).exec(ctx.cache).await {
+        Ok(RespType::String(pending)) => Ok(Some(serde_json::from_str(&pending)?)),
+        Ok(_) => unreachable!(),
+        Err(Error::Redis(e)) if e.kind() == redis::ErrorKind::TypeError => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Log the mod action and send it to the platform, with who confirmed it as the reason
+pub(super) async fn enact(ctx: &Context<'_>, cmd: &str, user: Arc<User>, action: ModAction) {
+    let reason = Arc::new(format!("{} by {}", cmd, ctx.user.name));
+    Log::mod_action(
+        ctx.db.clone(),
+        ctx.platform,
+        user.id.clone(),
+        action,
+        reason.clone(),
+    );
+    Response {
+        platform: ctx.platform,
+        channel: crate::channel_name(),
+        corr_id: ctx.corr_id.clone(),
+        payload: Payload::ModAction(user, action, reason),
+    }
+    .send(Location::Broadcast, ctx.resp)
+    .await;
+}
+
+/// A mod action on one user
+pub(super) struct Single<'a> {
+    pub(super) cmd: &'static str,
+    pub(super) lock: &'a str,
+    pub(super) name: &'a str,
+    pub(super) prefix: &'a str,
+    pub(super) confirm_secs: u64,
+    pub(super) action: ModAction,
+    /// i18n keys for asking to confirm, and for once it's done
+    pub(super) confirm_key: &'static str,
+    pub(super) done_key: &'static str,
+}
+
+impl Single<'_> {
+    pub(super) async fn run(&self, ctx: &Context<'_>, args: Args) -> error::Result<RunRes> {
+        let key = pending_key(self.lock, self.name, ctx);
+
+        let user = match args {
+            Args::Confirm => {
+                let msg = match take::<Arc<User>>(ctx, key).await? {
+                    Some(user) => {
+                        let msg = tr(self.done_key, &[("user", &user.name)]);
+                        enact(ctx, self.cmd, user, self.action).await;
+                        msg
+                    }
+                    None => tr("bulkmod.nothing_pending", &[("secs", &self.confirm_secs)]),
+                };
+                reply(ctx, msg).await;
+                return Ok(RunRes::Ok);
+            }
+            Args::User(user) => user,
+            Args::Name(name) => match ctx.resolve_user(name.trim_start_matches('@')).await? {
+                Some(user) => Arc::new(User {
+                    id: user.id,
+                    name: user.name,
+                    ..Default::default()
+                }),
+                None => {
+                    let msg = tr("bulkmod.unknown_user", &[("user", &name)]);
+                    reply(ctx, msg).await;
+                    return Ok(RunRes::Ok);
+                }
+            },
+        };
+
+        hold(ctx, key, self.confirm_secs, &user).await?;
+        let msg = tr(
+            self.confirm_key,
+            &[
+                ("user", &user.name),
+                ("prefix", &self.prefix),
+                ("secs", &self.confirm_secs),
+            ],
+        );
+        reply(ctx, msg).await;
+        Ok(RunRes::Ok)
+    }
+}
@@ -0,0 +1,239 @@
+use super::{util, Arg, ArgKind, ArgValue, CmdDesc, Context, Invokable, RunRes};
+use crate::{
+    error,
+    i18n::tr,
+    msg::{
+        discord::{ChannelHint, DiscordAction},
+        ArgMap, ArgMapError, Chat, Invocation, Location, Payload, Permissions, Platform, Response,
+    },
+};
+use back_derive::command;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::sync::Arc;
+
+/// `<#id>` mentions, bare ids, or "default"
+static CHANNEL_ROUTE_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)^(\S+)\s+(\S+)\s+(?:<#(\d+)>|(\d+)|(default))\s*$").unwrap());
+
+#[derive(Debug)]
+struct Args {
+    hint: String,
+    /// None to go back to the default channel
+    id: Option<Arc<String>>,
+}
+
+#[command(cmd)]
+/// Point a logical Discord channel (announce, mod-log or bot-spam) somewhere else
+pub struct ChannelRoute {
+    /// Command prefix
+    #[cmd(def("!setchannel"), constr(non_empty))]
+    prefix: String,
+    /// Autocorrect prefix
+    autocorrect: bool,
+    /// Platforms
+    #[cmd(defl("Platform::DISCORD"))]
+    platforms: Platform,
+    /// Permissions
+    #[cmd(defl("Permissions::ADMIN"))]
+    perms: Permissions,
+}
+
+impl ChannelRoute {
+    fn parse_arguments(&self, chat: &Chat) -> Option<(bool, Args)> {
+        let captures = CHANNEL_ROUTE_REGEX.captures(&chat.msg)?;
+
+        // check command prefix
+        let autocorrect = util::check_autocorrect(
+            &self.prefix,
+            &captures[1],
+            self.autocorrect,
+            &self.levenshtein,
+        )?;
+
+        let id = captures
+            .get(3)
+            .or_else(|| captures.get(4))
+            .map(|m| Arc::new(m.as_str().to_owned()));
+
+        Some((
+            autocorrect,
+            Args {
+                hint: captures[2].to_owned(),
+                id,
+            },
+        ))
+    }
+
+    fn can_run(&self, ctx: &Context<'_>) -> Option<()> {
+        if !self.enabled {
+            return None;
+        }
+
+        // check if platform is applicable
+        if !self.platforms.contains(ctx.platform) {
+            return None;
+        }
+
+        // check perms
+        if ctx.user.perms < self.perms {
+            return None;
+        }
+
+        Some(())
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn chat(&self, ctx: &Context<'_>, chat: &Chat) -> error::Result<RunRes> {
+        if self.can_run(ctx).is_none() {
+            return Ok(RunRes::Disabled);
+        }
+
+        let (autocorrect, args) = match self.parse_arguments(chat) {
+            Some(t) => t,
+            None if util::starts_with_prefix(&self.prefix, &chat.msg) => {
+                return Ok(RunRes::InvalidArgs)
+            }
+            None => return Ok(RunRes::Noop),
+        };
+
+        if autocorrect {
+            return Ok(RunRes::Autocorrect(self.prefix.clone()));
+        }
+
+        self.run(ctx, args).await
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn invoke(
+        &self,
+        ctx: &Context<'_>,
+        invocation: &Invocation,
+    ) -> Option<RunRes> {
+        self.can_run(ctx)?;
+
+        super::check_invoke_prefix(&self.prefix, &invocation.cmd)?;
+
+        let args = match Args::try_from(&invocation.args) {
+            Ok(args) => args,
+            Err(_) => return Some(RunRes::InvalidArgs),
+        };
+
+        match self.run(ctx, args).await {
+            Ok(r) => Some(r),
+            Err(e) => {
+                tracing::error!("{}", e);
+                None
+            }
+        }
+    }
+
+    #[tracing::instrument(level = "trace", skip_all, name = "ChannelRoute")]
+    async fn run(&self, ctx: &Context<'_>, args: Args) -> error::Result<RunRes> {
+        tracing::debug!(name = self.name.as_str(), user = ctx.user.name.as_str(), args = ?args);
+
+        let msg = match args.hint.parse::<ChannelHint>() {
+            Ok(hint) => {
+                let msg = match args.id {
+                    Some(ref id) => tr("channel.routed", &[("hint", &hint), ("id", id)]),
+                    None => tr("channel.reset", &[("hint", &hint)]),
+                };
+
+                Response {
+                    platform: Platform::DISCORD,
+                    channel: crate::channel_name(),
+                    corr_id: ctx.corr_id.clone(),
+                    payload: Payload::Discord(DiscordAction::SetChannel(hint, args.id)),
+                }
+                .send(Location::Pubsub, ctx.resp)
+                .await;
+
+                msg
+            }
+            Err(()) => tr("channel.unknown", &[("hint", &args.hint)]),
+        };
+
+        Response {
+            platform: ctx.platform,
+            channel: crate::channel_name(),
+            corr_id: ctx.corr_id.clone(),
+            payload: Payload::Message {
+                user: ctx.reply_to(),
+                msg: msg.into(),
+                meta: ctx.meta.clone(),
+                hint: None,
+                thread: None,
+            },
+        }
+        .send(Location::Pubsub, ctx.resp)
+        .await;
+
+        Ok(RunRes::Ok)
+    }
+}
+
+impl CmdDesc for ChannelRoute {
+    #[inline]
+    fn platform(&self) -> Platform {
+        self.platforms
+    }
+
+    #[inline]
+    fn description(&self, platform: Platform) -> Option<String> {
+        if platform.contains(Platform::DISCORD) {
+            return Some("Choose where announcements, the mod log or bot spam go".into());
+        }
+
+        None
+    }
+}
+
+impl Invokable for ChannelRoute {
+    fn args(&self, _platform: Platform) -> Vec<Arg> {
+        vec![
+            Arg {
+                name: "channel".into(),
+                desc: "announce, mod-log or bot-spam".into(),
+                kind: ArgKind::String,
+                optional: false,
+            },
+            Arg {
+                name: "to".into(),
+                desc: "Channel id, or default".into(),
+                kind: ArgKind::String,
+                optional: false,
+            },
+        ]
+    }
+
+    fn hidden(&self, _platform: Platform) -> bool {
+        true
+    }
+}
+
+impl TryFrom<&ArgMap> for Args {
+    type Error = ArgMapError;
+
+    fn try_from(value: &ArgMap) -> Result<Self, Self::Error> {
+        let hint = match value.get("channel") {
+            Some(ArgValue::String(s)) => s.trim().to_owned(),
+            _ => return Err(ArgMapError),
+        };
+
+        let to = match value.get("to") {
+            Some(ArgValue::String(s)) => s.trim(),
+            _ => return Err(ArgMapError),
+        };
+        let id = if to.eq_ignore_ascii_case("default") {
+            None
+        } else {
+            let id = to.trim_start_matches("<#").trim_end_matches('>');
+            if id.is_empty() || !id.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(ArgMapError);
+            }
+            Some(Arc::new(id.to_owned()))
+        };
+
+        Ok(Args { hint, id })
+    }
+}
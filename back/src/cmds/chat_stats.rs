@@ -0,0 +1,226 @@
+use super::{Context, RespHandle, RunRes};
+pub use crate::proto::cmds::ChatStatsSnapshot;
+use crate::{
+    cache::{self, Cache, RespType},
+    error,
+    msg::{corr_id, Chat, Invocation, Location, Payload, Platform, Response},
+};
+use back_derive::command;
+use once_cell::sync::Lazy;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{sync::watch, task::JoinHandle};
+use tracing::{info_span, Instrument};
+
+/// Every message in the last keep_for secs, scored by when it was sent (in ms)
+static SAMPLES_KEY: Lazy<Arc<String>> =
+    Lazy::new(|| Arc::new(format!("aussiebot!{}!chatstats", crate::channel_name())));
+
+/// One chat message, as kept in the sorted set
+#[derive(Debug, Serialize, Deserialize)]
+struct Sample {
+    /// Unix ms, keeps repeated messages apart
+    at: u64,
+    /// platform:id
+    user: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    emotes: Vec<String>,
+}
+
+fn unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[command(timer)]
+/// Track chat velocity, chatters and emotes over a sliding window, sent to overlays every so often
+pub struct ChatStats {
+    /// Platforms
+    #[cmd(defl("Platform::CHAT"))]
+    platforms: Platform,
+    /// Window the stats cover (in seconds)
+    #[cmd(def(300u64), constr(range = "60..=3600"))]
+    window: u64,
+    /// How often the stats are sent (in seconds)
+    #[cmd(def(10u64), constr(range = "5..=300"))]
+    interval: u64,
+    /// Emotes listed
+    #[cmd(def(5u64), constr(range = "0..=20"))]
+    top_emotes: u64,
+    /// How far back messages are kept for querying (in seconds)
+    #[cmd(def(86400u64), constr(range = "3600..=604800"))]
+    keep_for: u64,
+}
+
+impl ChatStats {
+    /// Implicit chat fn to record the message
+    #[tracing::instrument(level = "trace", skip_all, name = "ChatStats")]
+    pub(super) async fn chat(&self, ctx: &Context<'_>, chat: &Chat) -> error::Result<RunRes> {
+        if !self.enabled || !self.platforms.contains(ctx.platform) {
+            return Ok(RunRes::Disabled);
+        }
+
+        let sample = Sample {
+            at: unix_ms(),
+            user: format!("{}:{}", ctx.platform, chat.user.id),
+            emotes: chat.emote_names().into_iter().map(str::to_owned).collect(),
+        };
+        Cache::Zadd(
+            SAMPLES_KEY.clone(),
+            sample.at.to_string().into(),
+            serde_json::to_string(&sample)?.into(),
+        )
+        .exec(ctx.cache)
+        .await?;
+
+        Ok(RunRes::Noop)
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn invoke(
+        &self,
+        _ctx: &Context<'_>,
+        _invocation: &Invocation,
+    ) -> Option<RunRes> {
+        None
+    }
+
+    /// Stats for a window of chat, for the dashboard to look back on
+    pub(crate) async fn history(
+        &self,
+        cache: &cache::Handle,
+        from: u64,
+        to: u64,
+    ) -> error::Result<ChatStatsSnapshot> {
+        Self::query(cache, from, to, self.top_emotes as usize).await
+    }
+
+    /// Stats for messages sent from `from` up to `to` (unix secs), with up to `top` emotes
+    pub(crate) async fn query(
+        cache: &cache::Handle,
+        from: u64,
+        to: u64,
+        top: usize,
+    ) -> error::Result<ChatStatsSnapshot> {
+        let to = to.max(from);
+        let samples = match Cache::Zrangebyscore(
+            SAMPLES_KEY.clone(),
+            (from * 1000).to_string().into(),
+            format!("({}", to * 1000).into(),
+        )
+        .exec(cache)
+        .await?
+        {
+            RespType::VecString(samples) => samples,
+            _ => unreachable!(),
+        };
+
+        let mut per_minute = vec![0; to.saturating_sub(from).div_ceil(60) as usize];
+        let mut chatters = HashSet::new();
+        let mut emotes: HashMap<String, u64> = HashMap::new();
+        for sample in &samples {
+            let sample = match serde_json::from_str::<Sample>(sample) {
+                Ok(sample) => sample,
+                Err(e) => {
+                    tracing::warn!("bad chat sample: {}", e);
+                    continue;
+                }
+            };
+            let minute = (sample.at / 1000).saturating_sub(from) / 60;
+            if let Some(count) = per_minute.get_mut(minute as usize) {
+                *count += 1;
+            }
+            for emote in sample.emotes {
+                *emotes.entry(emote).or_default() += 1;
+            }
+            chatters.insert(sample.user);
+        }
+
+        let mut top_emotes: Vec<_> = emotes.into_iter().collect();
+        top_emotes.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        top_emotes.truncate(top);
+
+        Ok(ChatStatsSnapshot {
+            from,
+            to,
+            messages: per_minute.iter().sum(),
+            per_minute,
+            chatters: chatters.len() as u64,
+            top_emotes,
+        })
+    }
+
+    /// Spawn the task that sends the stats every `interval` and forgets old messages
+    pub(crate) fn init(
+        &self,
+        cancel_chan: watch::Receiver<()>,
+        cache: &cache::Handle,
+        resp: &RespHandle,
+    ) -> Option<JoinHandle<()>> {
+        if !self.enabled || self.platforms.is_empty() {
+            return None;
+        }
+
+        let (cache, resp) = (cache.clone(), resp.clone());
+        let (interval, window, keep_for, top) = (
+            Duration::from_secs(self.interval),
+            self.window,
+            self.keep_for,
+            self.top_emotes as usize,
+        );
+
+        let handle = tokio::spawn(
+            async move {
+                loop {
+                    tokio::time::sleep(interval).await;
+                    match cancel_chan.has_changed() {
+                        Ok(false) => {}
+                        _ => {
+                            // value changed or channel closed
+                            tracing::info!("\x1b[93maborting\x1b[0m");
+                            return;
+                        }
+                    }
+
+                    let now = unix_ms() / 1000;
+                    if let Err(e) = Cache::Zremrangebyscore(
+                        SAMPLES_KEY.clone(),
+                        "-inf".to_owned().into(),
+                        format!("({}", now.saturating_sub(keep_for) * 1000).into(),
+                    )
+                    .exec(&cache)
+                    .await
+                    {
+                        tracing::error!("{}", e);
+                    }
+
+                    let snapshot =
+                        match Self::query(&cache, now.saturating_sub(window), now, top).await {
+                            Ok(snapshot) => snapshot,
+                            Err(e) => {
+                                tracing::error!("{}", e);
+                                continue;
+                            }
+                        };
+                    Response {
+                        platform: Platform::WEB,
+                        channel: crate::channel_name(),
+                        corr_id: corr_id(),
+                        payload: Payload::ChatStats(snapshot),
+                    }
+                    .send(Location::Websockets(None), &resp)
+                    .await;
+                }
+            }
+            .instrument(info_span!("ChatStats")),
+        );
+
+        Some(handle)
+    }
+}
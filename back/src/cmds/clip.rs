@@ -0,0 +1,257 @@
+use super::{util, CmdDesc, Context, Invokable, RunRes};
+use crate::{
+    error,
+    i18n::tr,
+    msg::{
+        discord::ChannelHint, Chat, Invocation, Location, Payload, Permissions, Platform, Response,
+    },
+    twitch,
+};
+use back_derive::command;
+use std::time::Duration;
+
+/// Twitch says clips can take up to 15 seconds to process
+const CLIP_POLLS: u32 = 8;
+const CLIP_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+enum Created {
+    Offline,
+    /// Still not watchable after waiting
+    Processing,
+    Ready(twitch::Clip),
+}
+
+#[command(locks(rate, creating))]
+/// Clip the last few seconds of the twitch stream
+pub struct Clip {
+    /// Command prefix
+    #[cmd(def("!clip"), constr(non_empty))]
+    prefix: String,
+    /// Autocorrect prefix
+    autocorrect: bool,
+    /// Platforms
+    #[cmd(defl("Platform::CHAT"))]
+    platforms: Platform,
+    /// Permissions, Mod to only let mods clip
+    #[cmd(defl("Permissions::NONE"))]
+    perms: Permissions,
+    /// Cooldown per user (in seconds)
+    #[cmd(def(60u64), constr(range = "0..=86400"), group("Cooldowns"))]
+    ratelimit_user: u64,
+    /// Post clips on Discord too
+    #[cmd(def(true))]
+    announce: bool,
+    /// Discord announcement ({user} and {url} are filled in)
+    #[cmd(def("{user} clipped {url}"), constr(range = "1..=500"))]
+    announcement: String,
+    /// Discord channel to announce in (announce, mod-log or bot-spam, unset for announce)
+    discord_channel: Option<String>,
+}
+
+impl Clip {
+    fn parse_arguments(&self, chat: &Chat) -> Option<bool> {
+        let captures = util::PREFIX_REGEX.captures(&chat.msg)?;
+
+        util::check_autocorrect(
+            &self.prefix,
+            &captures[1],
+            self.autocorrect,
+            &self.levenshtein,
+        )
+    }
+
+    fn can_run(&self, ctx: &Context<'_>) -> Option<()> {
+        if !self.enabled {
+            return None;
+        }
+
+        // check if platform is applicable
+        if !self.platforms.contains(ctx.platform) {
+            return None;
+        }
+
+        // check perms
+        if ctx.user.perms < self.perms {
+            return None;
+        }
+
+        Some(())
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn chat(&self, ctx: &Context<'_>, chat: &Chat) -> error::Result<RunRes> {
+        if self.can_run(ctx).is_none() {
+            return Ok(RunRes::Disabled);
+        }
+
+        let autocorrect = match self.parse_arguments(chat) {
+            Some(a) => a,
+            None => return Ok(RunRes::Noop),
+        };
+
+        if autocorrect {
+            return Ok(RunRes::Autocorrect(self.prefix.clone()));
+        }
+
+        if util::ratelimit_user(
+            ctx,
+            self.ratelimit_user,
+            stringify!(Clip),
+            &self.name,
+            &*CLIP_LOCK_RATE,
+        )
+        .await?
+        {
+            return Ok(RunRes::Ratelimited { global: false });
+        }
+
+        self.run(ctx).await
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn invoke(
+        &self,
+        ctx: &Context<'_>,
+        invocation: &Invocation,
+    ) -> Option<RunRes> {
+        self.can_run(ctx)?;
+
+        super::check_invoke_prefix(&self.prefix, &invocation.cmd)?;
+
+        match self.run(ctx).await {
+            Ok(r) => Some(r),
+            Err(e) => {
+                tracing::error!("{}", e);
+                None
+            }
+        }
+    }
+
+    async fn reply(&self, ctx: &Context<'_>, msg: String) {
+        Response {
+            platform: ctx.platform,
+            channel: crate::channel_name(),
+            corr_id: ctx.corr_id.clone(),
+            payload: Payload::Message {
+                user: ctx.reply_to(),
+                msg: msg.into(),
+                meta: ctx.meta.clone(),
+                hint: None,
+                thread: None,
+            },
+        }
+        .send(Location::Pubsub, ctx.resp)
+        .await;
+    }
+
+    /// Ask for a clip and wait for it to be watchable
+    async fn create(helix: &twitch::Helix) -> error::Result<Created> {
+        let created = match helix.create_clip(twitch::login()).await? {
+            Some(created) => created,
+            None => return Ok(Created::Offline),
+        };
+        tracing::info!(
+            id = created.id.as_str(),
+            edit_url = created.edit_url.as_str(),
+            "clip created"
+        );
+
+        for _ in 0..CLIP_POLLS {
+            tokio::time::sleep(CLIP_POLL_INTERVAL).await;
+            if let Some(clip) = helix.clip(&created.id).await? {
+                return Ok(Created::Ready(clip));
+            }
+        }
+        Ok(Created::Processing)
+    }
+
+    #[tracing::instrument(level = "trace", skip_all, name = "Clip")]
+    async fn run(&self, ctx: &Context<'_>) -> error::Result<RunRes> {
+        tracing::debug!(name = self.name.as_str(), user = ctx.user.name.as_str());
+
+        let helix = match twitch::HELIX.as_ref().filter(|h| h.can_clip()) {
+            Some(helix) => helix,
+            None => {
+                self.reply(ctx, tr("clip.unavailable", &[])).await;
+                return Ok(RunRes::Ok);
+            }
+        };
+
+        // one clip at a time, so a chat full of !clip doesn't make a dozen of the same moment
+        let creating_key = format!("{}_{}", &*CLIP_LOCK_CREATING, self.name);
+        if !ctx
+            .lock
+            .lock(
+                &creating_key,
+                CLIP_POLLS as u64 * CLIP_POLL_INTERVAL.as_secs() + 5,
+            )
+            .await?
+        {
+            self.reply(ctx, tr("clip.in_progress", &[])).await;
+            return Ok(RunRes::Ok);
+        }
+
+        let res = Self::create(helix).await;
+        let _ = ctx.lock.unlock(&creating_key).await;
+
+        let clip = match res? {
+            Created::Ready(clip) => clip,
+            Created::Processing => {
+                self.reply(ctx, tr("clip.failed", &[])).await;
+                return Ok(RunRes::Ok);
+            }
+            Created::Offline => {
+                self.reply(ctx, tr("clip.offline", &[])).await;
+                return Ok(RunRes::Ok);
+            }
+        };
+
+        tracing::info!(url = clip.url.as_str(), "clip ready");
+        let msg = tr("clip.created", &[("url", &clip.url)]);
+        self.reply(ctx, msg).await;
+
+        if self.announce {
+            let announcement = self
+                .announcement
+                .replace("{user}", &ctx.user.name)
+                .replace("{url}", &clip.url);
+            Response {
+                platform: Platform::ANNOUNCE,
+                channel: crate::channel_name(),
+                corr_id: ctx.corr_id.clone(),
+                payload: Payload::Message {
+                    user: None,
+                    msg: announcement.into(),
+                    meta: None,
+                    hint: Some(
+                        ChannelHint::from_config(&self.discord_channel)
+                            .unwrap_or(ChannelHint::Announce),
+                    ),
+                    thread: None,
+                },
+            }
+            .send(Location::Pubsub, ctx.resp)
+            .await;
+        }
+
+        Ok(RunRes::Ok)
+    }
+}
+
+impl CmdDesc for Clip {
+    #[inline]
+    fn platform(&self) -> Platform {
+        self.platforms
+    }
+
+    #[inline]
+    fn description(&self, platform: Platform) -> Option<String> {
+        if platform.contains(Platform::DISCORD) {
+            return Some("Clip the last few seconds of the twitch stream".into());
+        }
+
+        None
+    }
+}
+
+impl Invokable for Clip {}
@@ -0,0 +1,337 @@
+use super::{util, Arg, ArgKind, ArgValue, Context, Invokable, RunRes};
+use crate::{
+    cache::{self, Cache, RespType},
+    db::{self, Db, Resp},
+    error::{self, Error},
+    msg::{
+        ArgMap, ArgMapError, Chat, Invocation, Location, Payload, Permissions, Platform, Response,
+    },
+};
+use back_derive::command;
+use bb8_redis::redis;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::{sync::Arc, time::Duration};
+use tokio::sync::watch;
+use tracing::{debug_span, Instrument};
+
+static COUNTER_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(\S+?)(\+)?(?:\s+(set)\s+(\d+))?(?:\s|$)").unwrap());
+
+#[derive(Debug)]
+enum Args {
+    Read,
+    Increment,
+    /// Never below 0
+    Set(i64),
+}
+
+#[command(locks(rate))]
+/// Named counter, e.g. for deaths
+pub struct Counter {
+    /// Command prefix. Append `+` to increment, or `set <n>` to set
+    #[cmd(def("!deaths"), constr(non_empty))]
+    prefix: String,
+    /// Autocorrect prefix
+    autocorrect: bool,
+    /// Platforms
+    #[cmd(defl("Platform::CHAT"))]
+    platforms: Platform,
+    /// Permissions to read the counter
+    #[cmd(defl("Permissions::NONE"))]
+    perms: Permissions,
+    /// Permissions to increment or set the counter
+    #[cmd(defl("Permissions::MOD"))]
+    edit_perms: Permissions,
+    /// Name shown in responses
+    #[cmd(def("Deaths"), constr(range = "1..=100"))]
+    label: String,
+    /// Response. Accepts {name} and {count}
+    #[cmd(def("{name} is now {count}"), constr(range = "1..=500"))]
+    message: String,
+    /// Cooldown per user (in seconds)
+    #[cmd(constr(pos), group("Cooldowns"))]
+    ratelimit_user: u64,
+    /// How often to save the count to the database (in seconds)
+    #[cmd(def(60_u64), constr(range = "10..=3600"))]
+    flush_interval: u64,
+}
+
+impl Counter {
+    fn parse_arguments(&self, chat: &Chat) -> Option<(bool, Args)> {
+        let captures = COUNTER_REGEX.captures(&chat.msg)?;
+
+        // check command prefix
+        let autocorrect = util::check_autocorrect(
+            &self.prefix,
+            &captures[1],
+            self.autocorrect,
+            &self.levenshtein,
+        )?;
+
+        let args = match (captures.get(2), captures.get(4)) {
+            (Some(_), _) => Args::Increment,
+            (_, Some(n)) => Args::Set(n.as_str().parse().ok()?),
+            _ => Args::Read,
+        };
+
+        Some((autocorrect, args))
+    }
+
+    fn can_run(&self, ctx: &Context<'_>) -> Option<()> {
+        if !self.enabled {
+            return None;
+        }
+
+        // check if platform is applicable
+        if !self.platforms.contains(ctx.platform) {
+            return None;
+        }
+
+        // check perms
+        if ctx.user.perms < self.perms {
+            return None;
+        }
+
+        Some(())
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn chat(&self, ctx: &Context<'_>, chat: &Chat) -> error::Result<RunRes> {
+        if self.can_run(ctx).is_none() {
+            return Ok(RunRes::Disabled);
+        }
+
+        let (autocorrect, args) = match self.parse_arguments(chat) {
+            Some(t) => t,
+            None => return Ok(RunRes::Noop),
+        };
+
+        if autocorrect {
+            return Ok(RunRes::Autocorrect(self.prefix.clone()));
+        }
+
+        match util::ratelimit_user(
+            ctx,
+            self.ratelimit_user,
+            stringify!(Counter),
+            &self.name,
+            &*COUNTER_LOCK_RATE,
+        )
+        .await
+        {
+            Ok(false) => {}
+            Ok(true) => return Ok(RunRes::Ratelimited { global: false }),
+            Err(e) => return Err(e),
+        }
+
+        self.run(ctx, args).await
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn invoke(
+        &self,
+        ctx: &Context<'_>,
+        invocation: &Invocation,
+    ) -> Option<RunRes> {
+        self.can_run(ctx)?;
+
+        super::check_invoke_prefix(&self.prefix, &invocation.cmd)?;
+
+        let args = Args::try_from(&invocation.args).ok()?;
+
+        match util::ratelimit_user(
+            ctx,
+            self.ratelimit_user,
+            stringify!(Counter),
+            &self.name,
+            &*COUNTER_LOCK_RATE,
+        )
+        .await
+        {
+            Ok(false) => {}
+            Ok(true) => return None,
+            Err(e) => {
+                tracing::error!("{}", e);
+                return None;
+            }
+        }
+
+        match self.run(ctx, args).await {
+            Ok(r) => Some(r),
+            Err(e) => {
+                tracing::error!("{}", e);
+                None
+            }
+        }
+    }
+
+    fn cache_key(name: &str) -> Arc<String> {
+        Arc::new(format!(
+            "aussiebot!{}!counter!{}",
+            crate::channel_name(),
+            name
+        ))
+    }
+
+    /// Current count, 0 if unset
+    async fn get(cache: &cache::Handle, key: Arc<String>) -> error::Result<i64> {
+        match Cache::Get(key).exec(cache).await {
+            Ok(RespType::String(s)) => Ok(s.parse()?),
+            Ok(_) => unreachable!(),
+            Err(Error::Redis(e)) if e.kind() == redis::ErrorKind::TypeError => Ok(0),
+            Err(e) => Err(e),
+        }
+    }
+
+    #[tracing::instrument(level = "trace", skip_all, name = "Counter")]
+    async fn run(&self, ctx: &Context<'_>, args: Args) -> error::Result<RunRes> {
+        tracing::debug!(name = self.name.as_str(), user = ctx.user.name.as_str(), args = ?args);
+
+        if !matches!(args, Args::Read) && ctx.user.perms < self.edit_perms {
+            return Ok(RunRes::InsufficientPerms);
+        }
+
+        let key = Self::cache_key(&self.name);
+
+        let count = match args {
+            Args::Read => Self::get(ctx.cache, key).await?,
+            Args::Increment => match Cache::Increment(key, 1, 0).exec(ctx.cache).await? {
+                RespType::U64(n) => n.try_into()?,
+                _ => unreachable!(),
+            },
+            Args::Set(n) => {
+                Cache::Set(key, n.to_string().into(), 0, false)
+                    .exec(ctx.cache)
+                    .await?;
+                n
+            }
+        };
+
+        let msg = self
+            .message
+            .replace("{name}", &self.label)
+            .replace("{count}", &count.to_string());
+
+        Response {
+            platform: ctx.platform,
+            channel: crate::channel_name(),
+            corr_id: ctx.corr_id.clone(),
+            payload: Payload::Message {
+                user: ctx.reply_to(),
+                msg: msg.into(),
+                meta: ctx.meta.clone(),
+                hint: None,
+                thread: None,
+            },
+        }
+        .send(Location::Broadcast, ctx.resp)
+        .await;
+
+        Ok(RunRes::Ok)
+    }
+
+    async fn flush(name: &str, cache: &cache::Handle, db: &db::Handle) -> error::Result<()> {
+        let count = Self::get(cache, Self::cache_key(name)).await?;
+        let resp = Db::SetCounter(name.to_owned().into(), count)
+            .exec(db)
+            .await?;
+        assert!(matches!(resp, Resp::Ok));
+        Ok(())
+    }
+
+    /// Restore the count from the database, then periodically save it back
+    pub(crate) fn init(
+        &self,
+        mut cancel_chan: watch::Receiver<()>,
+        cache: &cache::Handle,
+        db: &db::Handle,
+    ) -> Option<()> {
+        if !self.enabled {
+            return None;
+        }
+
+        let name = self.name.clone();
+        let flush_interval = self.flush_interval;
+        let cache = cache.clone();
+        let db = db.clone();
+
+        tracing::info!(
+            "\x1b[93mSpawning Counter flush task with interval: {}s\x1b[0m",
+            flush_interval
+        );
+
+        tokio::task::spawn(
+            async move {
+                // the cache is authoritative, only fill it if it's empty
+                match Db::GetCounter(name.clone().into()).exec(&db).await {
+                    Ok(Resp::Counter(Some(count))) => {
+                        let _ =
+                            Cache::Set(Self::cache_key(&name), count.to_string().into(), 0, true)
+                                .exec(&cache)
+                                .await;
+                    }
+                    Ok(_) => {}
+                    Err(e) => tracing::error!("{}", e),
+                }
+
+                loop {
+                    tokio::select! {
+                        _ = tokio::time::sleep(Duration::from_secs(flush_interval)) => {}
+                        _ = cancel_chan.changed() => {
+                            // value changed or channel closed, save one last time
+                            if let Err(e) = Self::flush(&name, &cache, &db).await {
+                                tracing::error!("{}", e);
+                            }
+                            tracing::info!("\x1b[93maborting\x1b[0m");
+                            return;
+                        }
+                    }
+
+                    if let Err(e) = Self::flush(&name, &cache, &db).await {
+                        tracing::error!("{}", e);
+                    }
+                }
+            }
+            .instrument(debug_span!("Counter flush task")),
+        );
+
+        Some(())
+    }
+}
+
+impl Invokable for Counter {
+    fn args(&self, _platform: Platform) -> Vec<Arg> {
+        vec![
+            Arg {
+                name: "increment".into(),
+                desc: "Add one to the counter".into(),
+                kind: ArgKind::Bool,
+                optional: true,
+            },
+            Arg {
+                name: "set".into(),
+                desc: "Set the counter".into(),
+                kind: ArgKind::Integer {
+                    min: Some(0),
+                    max: None,
+                },
+                optional: true,
+            },
+        ]
+    }
+}
+
+impl TryFrom<&ArgMap> for Args {
+    type Error = error::Error;
+
+    fn try_from(value: &ArgMap) -> Result<Self, Self::Error> {
+        match (value.get("increment"), value.get("set")) {
+            // counts can't go below 0
+            (_, Some(ArgValue::Integer(n))) if *n >= 0 => Ok(Args::Set(*n)),
+            (Some(ArgValue::Bool(true)), None) => Ok(Args::Increment),
+            (Some(ArgValue::Bool(false)), None) | (None, None) => Ok(Args::Read),
+            _ => Err(ArgMapError.into()),
+        }
+    }
+}
@@ -0,0 +1,562 @@
+//! Adding, editing and removing [`Text`](super::text::Text) commands from chat, e.g.
+//! `!addcmd !discord --perms mod --cooldown 30 "Join our discord: ..."`. The command only builds
+//! the change, the server saves it the same way as a PatchConfig and says when it's done
+use super::{
+    unbang_prefix, util, Arg, ArgKind, ArgValue, CmdDesc, Command, ConfigEdit, ConfigPatch,
+    Context, Invokable, ListPatch, RunRes, Value,
+};
+use crate::{
+    error,
+    i18n::tr,
+    msg::{ArgMap, Chat, Invocation, Location, Payload, Permissions, Platform, Response},
+};
+use back_derive::command;
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+static ARGS_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(\S+)\s+(\S+)(?:\s+(.+?))?\s*$").unwrap());
+
+const TEXT: &str = "Text";
+
+/// Same as Text's own limits
+const MAX_TEXT_LEN: usize = 500;
+const MAX_PREFIX_LEN: usize = 32;
+const MAX_COOLDOWN: u64 = 86400;
+
+#[derive(Debug, Clone, Copy)]
+enum Action {
+    Add,
+    Edit,
+    Remove,
+}
+
+#[command(cmd)]
+/// Add text commands from chat, e.g. `!addcmd !discord --perms mod --cooldown 30 "Join our
+/// discord: ..."`. Options are --perms, --cooldown, --user-cooldown and --platforms.
+/// Ones added this way can be changed with e.g. `!editcmd !discord --cooldown 60` (the reply's
+/// replaced if one is given) and removed with `!delcmd !discord`, others can't
+pub struct CustomCmds {
+    /// Add command prefix
+    #[cmd(def("!addcmd"), constr(non_empty))]
+    prefix: String,
+    /// Edit command prefix
+    #[cmd(def("!editcmd"), constr(non_empty))]
+    edit_prefix: String,
+    /// Remove command prefix
+    #[cmd(def("!delcmd"), constr(non_empty))]
+    del_prefix: String,
+    /// Autocorrect prefix
+    autocorrect: bool,
+    /// Platforms
+    #[cmd(defl("Platform::CHAT"))]
+    platforms: Platform,
+    /// Permissions
+    #[cmd(defl("Permissions::MOD"))]
+    perms: Permissions,
+}
+
+impl CustomCmds {
+    fn can_run(&self, ctx: &Context<'_>) -> Option<()> {
+        if !self.enabled {
+            return None;
+        }
+
+        // check if platform is applicable
+        if !self.platforms.contains(ctx.platform) {
+            return None;
+        }
+
+        // check perms
+        if ctx.user.perms < self.perms {
+            return None;
+        }
+
+        Some(())
+    }
+
+    /// None if it's not the command, or Some error to reply with
+    fn parse_arguments(&self, chat: &Chat) -> Option<(bool, Action, Result<Args, String>)> {
+        let captures = ARGS_REGEX.captures(&chat.msg)?;
+
+        // check command prefix
+        let (autocorrect, action) = if captures[1].eq_ignore_ascii_case(&self.edit_prefix) {
+            (false, Action::Edit)
+        } else if captures[1].eq_ignore_ascii_case(&self.del_prefix) {
+            (false, Action::Remove)
+        } else {
+            let autocorrect = util::check_autocorrect(
+                &self.prefix,
+                &captures[1],
+                self.autocorrect,
+                &self.levenshtein,
+            )?;
+            (autocorrect, Action::Add)
+        };
+
+        let args = Options::parse(captures.get(3).map_or("", |m| m.as_str())).map(|options| Args {
+            prefix: captures[2].to_owned(),
+            options,
+        });
+        Some((autocorrect, action, args))
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn chat(&self, ctx: &Context<'_>, chat: &Chat) -> error::Result<RunRes> {
+        if self.can_run(ctx).is_none() {
+            return Ok(RunRes::Disabled);
+        }
+
+        let (autocorrect, action, args) = match self.parse_arguments(chat) {
+            Some(t) => t,
+            None if [&self.prefix, &self.edit_prefix, &self.del_prefix]
+                .iter()
+                .any(|prefix| util::starts_with_prefix(prefix, &chat.msg)) =>
+            {
+                return Ok(RunRes::InvalidArgs)
+            }
+            None => return Ok(RunRes::Noop),
+        };
+
+        if autocorrect {
+            return Ok(RunRes::Autocorrect(self.prefix.clone()));
+        }
+
+        self.run(ctx, action, args).await
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn invoke(
+        &self,
+        ctx: &Context<'_>,
+        invocation: &Invocation,
+    ) -> Option<RunRes> {
+        self.can_run(ctx)?;
+
+        super::check_invoke_prefix(&self.prefix, &invocation.cmd)?;
+
+        let subcommand = [
+            ("add", Action::Add),
+            ("edit", Action::Edit),
+            ("remove", Action::Remove),
+        ]
+        .into_iter()
+        .find_map(|(name, action)| match invocation.args.get(name) {
+            Some(ArgValue::SubCommand(args)) => Some((action, args)),
+            _ => None,
+        });
+        let (action, args) = match subcommand
+            .and_then(|(action, args)| invocation_args(args).map(|args| (action, args)))
+        {
+            Some(t) => t,
+            None => return Some(RunRes::InvalidArgs),
+        };
+
+        match self.run(ctx, action, args).await {
+            Ok(r) => Some(r),
+            Err(e) => {
+                tracing::error!("{}", e);
+                None
+            }
+        }
+    }
+
+    #[tracing::instrument(level = "trace", skip_all, name = "CustomCmds")]
+    async fn run(
+        &self,
+        ctx: &Context<'_>,
+        action: Action,
+        args: Result<Args, String>,
+    ) -> error::Result<RunRes> {
+        tracing::debug!(name = self.name.as_str(), user = ctx.user.name.as_str(), action = ?action, args = ?args);
+
+        let edit = args.and_then(|args| match action {
+            Action::Add => add(ctx, args),
+            Action::Edit => edit(ctx, args),
+            Action::Remove => remove(ctx, args),
+        });
+        match edit {
+            Ok(edit) => Ok(RunRes::EditConfig(Box::new(edit))),
+            Err(msg) => {
+                reply(ctx, msg).await;
+                Ok(RunRes::Ok)
+            }
+        }
+    }
+}
+
+impl CmdDesc for CustomCmds {
+    #[inline]
+    fn platform(&self) -> Platform {
+        self.platforms
+    }
+
+    #[inline]
+    fn description(&self, platform: Platform) -> Option<String> {
+        if platform.contains(Platform::DISCORD) {
+            return Some("Add, edit or remove a text command".into());
+        }
+
+        None
+    }
+}
+
+impl Invokable for CustomCmds {
+    fn args(&self, _platform: Platform) -> Vec<Arg> {
+        let subcommand = |name: &str, desc: &str, args| Arg {
+            name: name.into(),
+            desc: desc.into(),
+            kind: ArgKind::SubCommand(args),
+            optional: true,
+        };
+        vec![
+            subcommand("add", "Add a text command", invoke_args(true)),
+            subcommand(
+                "edit",
+                "Edit a text command added from chat",
+                invoke_args(false),
+            ),
+            subcommand(
+                "remove",
+                "Remove a text command added from chat",
+                vec![prefix_arg()],
+            ),
+        ]
+    }
+
+    fn hidden(&self, _platform: Platform) -> bool {
+        true
+    }
+}
+
+/// Settings given to a Text command, anything unset is left as it is (or the default)
+#[derive(Debug, Default)]
+struct Options {
+    text: Option<String>,
+    perms: Option<Permissions>,
+    ratelimit: Option<u64>,
+    ratelimit_user: Option<u64>,
+    platforms: Option<Platform>,
+}
+
+impl Options {
+    fn is_empty(&self) -> bool {
+        self.text.is_none()
+            && self.perms.is_none()
+            && self.ratelimit.is_none()
+            && self.ratelimit_user.is_none()
+            && self.platforms.is_none()
+    }
+
+    fn set(&mut self, flag: &str, value: &str) -> Result<(), String> {
+        let invalid = || tr("customcmd.bad_value", &[("flag", &flag), ("value", &value)]);
+        let secs = || match value.parse() {
+            Ok(secs) if secs <= MAX_COOLDOWN => Ok(secs),
+            _ => Err(invalid()),
+        };
+        match flag {
+            "perms" => self.perms = Some(parse_perms(value).ok_or_else(invalid)?),
+            "cooldown" => self.ratelimit = Some(secs()?),
+            "user-cooldown" | "user_cooldown" => self.ratelimit_user = Some(secs()?),
+            "platforms" => {
+                let platforms = value
+                    .split(',')
+                    .map(|p| p.trim().parse::<Platform>())
+                    .try_fold(Platform::empty(), |all, p| p.map(|p| all | p))
+                    .map_err(|_| invalid())?;
+                match platforms & Platform::CHAT {
+                    p if p.is_empty() => return Err(invalid()),
+                    p => self.platforms = Some(p),
+                }
+            }
+            _ => return Err(tr("customcmd.bad_flag", &[("flag", &flag)])),
+        }
+        Ok(())
+    }
+
+    /// `--flag value` or `--flag=value` pairs, then the text, quoted or not
+    fn parse(mut rest: &str) -> Result<Self, String> {
+        let mut options = Self::default();
+        loop {
+            rest = rest.trim_start();
+            if !rest.starts_with("--") {
+                break;
+            }
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            let (flag, after) = rest[2..].split_at(end - 2);
+            rest = after;
+            let (flag, value) = match flag.split_once('=') {
+                Some(pair) => pair,
+                None => {
+                    rest = rest.trim_start();
+                    let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                    let (value, after) = rest.split_at(end);
+                    rest = after;
+                    (flag, value)
+                }
+            };
+            options.set(&flag.to_lowercase(), value)?;
+        }
+
+        let text = match rest.strip_prefix('"').and_then(|r| r.strip_suffix('"')) {
+            Some(quoted) => quoted.trim(),
+            None => rest.trim(),
+        };
+        if text.chars().count() > MAX_TEXT_LEN {
+            return Err(tr("customcmd.too_long", &[("max", &MAX_TEXT_LEN)]));
+        }
+        if !text.is_empty() {
+            options.text = Some(text.to_owned());
+        }
+        Ok(options)
+    }
+}
+
+fn parse_perms(level: &str) -> Option<Permissions> {
+    Some(match level.to_lowercase().as_str() {
+        "none" | "everyone" => Permissions::NONE,
+        "member" => Permissions::MEMBER,
+        "mod" => Permissions::MOD,
+        "admin" => Permissions::ADMIN,
+        "owner" => Permissions::OWNER,
+        _ => return None,
+    })
+}
+
+/// The prefix of the Text command being added, edited or removed, and its settings
+#[derive(Debug)]
+struct Args {
+    prefix: String,
+    options: Options,
+}
+
+/// None if the prefix is missing
+fn invocation_args(args: &ArgMap) -> Option<Result<Args, String>> {
+    let prefix = match args.get("prefix") {
+        Some(ArgValue::String(prefix)) if !prefix.trim().is_empty() => prefix.trim().to_owned(),
+        _ => return None,
+    };
+    let mut text = String::new();
+    let mut options = Options::default();
+    for (name, value) in args {
+        let res = match (name.as_str(), value) {
+            ("text", ArgValue::String(t)) => {
+                text = format!("\"{}\"", t.trim());
+                Ok(())
+            }
+            (flag, ArgValue::String(value)) if flag != "prefix" => options.set(flag, value),
+            (flag, ArgValue::Integer(value)) => options.set(flag, &value.to_string()),
+            _ => Ok(()),
+        };
+        if let Err(e) = res {
+            return Some(Err(e));
+        }
+    }
+    // goes through the same checks as text from chat
+    let res = Options::parse(&text).map(|parsed| {
+        options.text = parsed.text;
+        Args { prefix, options }
+    });
+    Some(res)
+}
+
+/// `prefix` and the settings for adding or editing, `text` only required when adding
+fn invoke_args(text_required: bool) -> Vec<Arg> {
+    let cooldown = |name: &str, desc: &str| Arg {
+        name: name.into(),
+        desc: desc.into(),
+        kind: ArgKind::Integer {
+            min: Some(0),
+            max: Some(MAX_COOLDOWN as i64),
+        },
+        optional: true,
+    };
+    vec![
+        prefix_arg(),
+        Arg {
+            name: "text".into(),
+            desc: "Reply".into(),
+            kind: ArgKind::String,
+            optional: !text_required,
+        },
+        Arg {
+            name: "perms".into(),
+            desc: "Who can use it: everyone, member, mod, admin or owner".into(),
+            kind: ArgKind::String,
+            optional: true,
+        },
+        cooldown("cooldown", "Cooldown (in seconds)"),
+        cooldown("user_cooldown", "Cooldown per user (in seconds)"),
+        Arg {
+            name: "platforms".into(),
+            desc: "Comma separated, e.g. twitch,youtube".into(),
+            kind: ArgKind::String,
+            optional: true,
+        },
+    ]
+}
+
+fn prefix_arg() -> Arg {
+    Arg {
+        name: "prefix".into(),
+        desc: "Command prefix, e.g. !discord".into(),
+        kind: ArgKind::String,
+        optional: false,
+    }
+}
+
+async fn reply(ctx: &Context<'_>, msg: String) {
+    Response {
+        platform: ctx.platform,
+        channel: crate::channel_name(),
+        corr_id: ctx.corr_id.clone(),
+        payload: Payload::Message {
+            user: ctx.reply_to(),
+            msg: msg.into(),
+            meta: ctx.meta.clone(),
+            hint: None,
+            thread: None,
+        },
+    }
+    .send(Location::Pubsub, ctx.resp)
+    .await;
+}
+
+/// Bang added if it's missing, e.g. `discord` is `!discord`
+fn full_prefix(prefix: &str) -> Result<String, String> {
+    let prefix = match prefix.chars().next() {
+        Some(c) if c.is_alphanumeric() => format!("!{}", prefix),
+        _ => prefix.to_owned(),
+    };
+    let len = unbang_prefix(&prefix).chars().count();
+    if len == 0 || len > MAX_PREFIX_LEN {
+        return Err(tr("customcmd.bad_prefix", &[("max", &MAX_PREFIX_LEN)]));
+    }
+    Ok(prefix)
+}
+
+fn same_prefix(a: &str, b: &str) -> bool {
+    unbang_prefix(a).eq_ignore_ascii_case(unbang_prefix(b))
+}
+
+/// Only ones added from chat, the rest are left to the web UI
+fn find_text<'a>(ctx: &'a Context<'_>, prefix: &str) -> Option<&'a Command> {
+    ctx.commands.iter().find(|c| match c {
+        Command::Text(text) if text.added_from_chat() => {
+            c.prefix().is_some_and(|p| same_prefix(p, prefix))
+        }
+        _ => false,
+    })
+}
+
+fn patch(set: Vec<super::CmdDump>, remove: Vec<(String, String)>) -> ConfigPatch {
+    ConfigPatch {
+        commands: ListPatch { set, remove },
+        ..Default::default()
+    }
+}
+
+fn set_value(values: &mut Vec<(String, Value)>, key: &str, value: Value) {
+    match values.iter_mut().find(|(k, _)| k == key) {
+        Some((_, v)) => *v = value,
+        None => values.push((key.to_owned(), value)),
+    }
+}
+
+/// Write the options over the command's values
+fn apply(values: &mut Vec<(String, Value)>, options: Options) {
+    let Options {
+        text,
+        perms,
+        ratelimit,
+        ratelimit_user,
+        platforms,
+    } = options;
+    let changes = [
+        ("text", text.map(Value::from)),
+        ("perms", perms.map(Value::from)),
+        ("ratelimit", ratelimit.map(Value::from)),
+        ("ratelimit_user", ratelimit_user.map(Value::from)),
+        ("platforms", platforms.map(Value::from)),
+    ];
+    for (key, value) in changes {
+        if let Some(value) = value {
+            set_value(values, key, value);
+        }
+    }
+}
+
+fn add(ctx: &Context<'_>, args: Args) -> Result<ConfigEdit, String> {
+    let prefix = full_prefix(&args.prefix)?;
+    if args.options.text.is_none() {
+        return Err(tr("customcmd.no_text", &[("prefix", &prefix)]));
+    }
+    let platforms = args.options.platforms.unwrap_or(Platform::CHAT);
+    let taken = ctx.commands.iter().find(|c| {
+        c.enabled()
+            && !(c.platform() & platforms).is_empty()
+            && c.prefixes().iter().any(|p| same_prefix(p, &prefix))
+    });
+    if let Some(cmd) = taken {
+        return Err(tr(
+            "customcmd.taken",
+            &[("prefix", &prefix), ("name", &cmd.name())],
+        ));
+    }
+
+    // named after the prefix, numbered if a disabled one has it already
+    let base = unbang_prefix(&prefix).to_lowercase();
+    let name = (1..)
+        .map(|n| match n {
+            1 => base.clone(),
+            n => format!("{}_{}", base, n),
+        })
+        .find(|name| {
+            !ctx.commands
+                .iter()
+                .any(|c| c.kind() == TEXT && c.name() == name)
+        })
+        .unwrap();
+
+    let mut values = vec![
+        ("enabled".to_owned(), Value::from(true)),
+        ("prefix".to_owned(), Value::from(prefix.clone())),
+        ("platforms".to_owned(), Value::from(platforms)),
+        ("from_chat".to_owned(), Value::from(true)),
+    ];
+    apply(&mut values, args.options);
+    Ok(ConfigEdit {
+        patch: patch(vec![(TEXT.to_owned(), name, values)], vec![]),
+        action: "AddCmd",
+        done: tr("customcmd.added", &[("prefix", &prefix)]),
+    })
+}
+
+fn edit(ctx: &Context<'_>, args: Args) -> Result<ConfigEdit, String> {
+    let prefix = full_prefix(&args.prefix)?;
+    let cmd =
+        find_text(ctx, &prefix).ok_or_else(|| tr("customcmd.not_found", &[("prefix", &prefix)]))?;
+    if args.options.is_empty() {
+        return Err(tr("customcmd.nothing", &[("prefix", &prefix)]));
+    }
+
+    let mut dump = cmd.dump();
+    apply(&mut dump.2, args.options);
+    Ok(ConfigEdit {
+        patch: patch(vec![dump], vec![]),
+        action: "EditCmd",
+        done: tr("customcmd.edited", &[("prefix", &prefix)]),
+    })
+}
+
+fn remove(ctx: &Context<'_>, args: Args) -> Result<ConfigEdit, String> {
+    let prefix = full_prefix(&args.prefix)?;
+    let cmd =
+        find_text(ctx, &prefix).ok_or_else(|| tr("customcmd.not_found", &[("prefix", &prefix)]))?;
+
+    Ok(ConfigEdit {
+        patch: patch(vec![], vec![(TEXT.to_owned(), cmd.name().to_owned())]),
+        action: "DelCmd",
+        done: tr("customcmd.removed", &[("prefix", &prefix)]),
+    })
+}
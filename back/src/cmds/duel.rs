@@ -0,0 +1,503 @@
+use super::{util, Arg, ArgKind, ArgValue, CmdDesc, Context, Invokable, RunRes};
+use crate::{
+    cache::{Cache, RespType},
+    db::{
+        give::{GiveError, GiveOp, GiveSource, GiveTarget},
+        Db, Resp,
+    },
+    error::{self, Error},
+    i18n::tr,
+    msg::{
+        ArgMap, ArgMapError, Chat, Invocation, Location, Payload, Permissions, Platform, Response,
+    },
+};
+use back_derive::command;
+use bb8_redis::redis;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_derive::{Deserialize, Serialize};
+use std::sync::Arc;
+
+static DUEL_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(\S+)\s+@?(.+?)\s+(\d+)\s*$").unwrap());
+
+#[derive(Debug)]
+enum Target {
+    Name(String),
+    /// id, name
+    User(Arc<String>, Arc<String>),
+}
+
+#[derive(Debug)]
+enum Args {
+    Challenge { target: Target, amount: i64 },
+    Accept,
+    Decline,
+}
+
+/// Waiting on the target to answer, expires after accept_secs
+#[derive(Debug, Serialize, Deserialize)]
+struct Challenge {
+    id: Arc<String>,
+    name: Arc<String>,
+    amount: i32,
+}
+
+#[command(locks(rate))]
+/// Challenge someone to a coin flip, the loser pays the winner the wager
+pub struct Duel {
+    /// Command prefix, followed by who to challenge and the wager
+    #[cmd(def("!duel"), constr(non_empty))]
+    prefix: String,
+    /// Command to accept a challenge with
+    #[cmd(def("!accept"), constr(non_empty))]
+    accept_prefix: String,
+    /// Command to decline a challenge with
+    #[cmd(def("!decline"), constr(non_empty))]
+    decline_prefix: String,
+    /// Autocorrect prefix
+    autocorrect: bool,
+    /// Platforms
+    #[cmd(defl("Platform::CHAT"))]
+    platforms: Platform,
+    /// Permissions
+    #[cmd(defl("Permissions::NONE"))]
+    perms: Permissions,
+    /// Cooldown per user between challenges (in seconds)
+    #[cmd(def(60u64), constr(range = "0..=86400"), group("Cooldowns"))]
+    ratelimit_user: u64,
+    /// How long a challenge can be accepted for (in seconds)
+    #[cmd(def(60u64), constr(range = "10..=600"))]
+    accept_secs: u64,
+    /// Min wager
+    #[cmd(def(10_i64), constr(pos))]
+    min_amount: i64,
+    /// Max wager
+    #[cmd(def(10_000_i64), constr(pos))]
+    max_amount: i64,
+}
+
+impl Duel {
+    fn parse_arguments(&self, chat: &Chat) -> Option<(bool, Args)> {
+        let msg = chat.msg.trim();
+        if msg.eq_ignore_ascii_case(&self.accept_prefix) {
+            return Some((false, Args::Accept));
+        }
+        if msg.eq_ignore_ascii_case(&self.decline_prefix) {
+            return Some((false, Args::Decline));
+        }
+
+        let captures = DUEL_REGEX.captures(msg)?;
+
+        // check command prefix
+        let autocorrect = util::check_autocorrect(
+            &self.prefix,
+            &captures[1],
+            self.autocorrect,
+            &self.levenshtein,
+        )?;
+
+        let amount = captures[3].parse().ok()?;
+        let target = Target::Name(captures[2].to_owned());
+
+        Some((autocorrect, Args::Challenge { target, amount }))
+    }
+
+    fn can_run(&self, ctx: &Context<'_>) -> Option<()> {
+        if !self.enabled {
+            return None;
+        }
+
+        // check if platform is applicable
+        if !self.platforms.contains(ctx.platform) {
+            return None;
+        }
+
+        // check perms
+        if ctx.user.perms < self.perms {
+            return None;
+        }
+
+        Some(())
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn chat(&self, ctx: &Context<'_>, chat: &Chat) -> error::Result<RunRes> {
+        if self.can_run(ctx).is_none() {
+            return Ok(RunRes::Disabled);
+        }
+
+        let (autocorrect, args) = match self.parse_arguments(chat) {
+            Some(t) => t,
+            None if util::starts_with_prefix(&self.prefix, &chat.msg) => {
+                return Ok(RunRes::InvalidArgs)
+            }
+            None => return Ok(RunRes::Noop),
+        };
+
+        if autocorrect {
+            return Ok(RunRes::Autocorrect(self.prefix.clone()));
+        }
+
+        if let Some(res) = self.ratelimit(ctx, &args).await? {
+            return Ok(res);
+        }
+
+        self.run(ctx, args).await
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn invoke(
+        &self,
+        ctx: &Context<'_>,
+        invocation: &Invocation,
+    ) -> Option<RunRes> {
+        self.can_run(ctx)?;
+
+        super::check_invoke_prefix(&self.prefix, &invocation.cmd)?;
+
+        let args = match Args::try_from(&invocation.args) {
+            Ok(args) => args,
+            Err(_) => return Some(RunRes::InvalidArgs),
+        };
+
+        match self.ratelimit(ctx, &args).await {
+            Ok(None) => {}
+            Ok(Some(_)) => return None,
+            Err(e) => {
+                tracing::error!("{}", e);
+                return None;
+            }
+        }
+
+        match self.run(ctx, args).await {
+            Ok(r) => Some(r),
+            Err(e) => {
+                tracing::error!("{}", e);
+                None
+            }
+        }
+    }
+
+    /// Only challenges are rate limited, answering one never is
+    async fn ratelimit(&self, ctx: &Context<'_>, args: &Args) -> error::Result<Option<RunRes>> {
+        if !matches!(args, Args::Challenge { .. }) {
+            return Ok(None);
+        }
+        let limited = util::ratelimit_user(
+            ctx,
+            self.ratelimit_user,
+            stringify!(Duel),
+            &self.name,
+            &DUEL_LOCK_RATE,
+        )
+        .await?;
+        Ok(limited.then_some(RunRes::Ratelimited { global: false }))
+    }
+
+    /// The challenge waiting on a user, one at a time
+    fn challenge_key(&self, platform: Platform, id: &str) -> Arc<String> {
+        Arc::new(format!(
+            "aussiebot!{}!duel!{}!{}:{}",
+            crate::channel_name(),
+            self.name,
+            platform,
+            id
+        ))
+    }
+
+    async fn reply(ctx: &Context<'_>, msg: String) {
+        Response {
+            platform: ctx.platform,
+            channel: crate::channel_name(),
+            corr_id: ctx.corr_id.clone(),
+            payload: Payload::Message {
+                user: ctx.reply_to(),
+                msg: msg.into(),
+                meta: ctx.meta.clone(),
+                hint: None,
+                thread: None,
+            },
+        }
+        .send(Location::Pubsub, ctx.resp)
+        .await;
+    }
+
+    /// To the whole chat rather than whoever ran it
+    async fn announce(ctx: &Context<'_>, msg: String) {
+        Response {
+            platform: ctx.platform,
+            channel: crate::channel_name(),
+            corr_id: ctx.corr_id.clone(),
+            payload: Payload::Message {
+                user: None,
+                msg: msg.into(),
+                meta: ctx.meta.clone(),
+                hint: None,
+                thread: None,
+            },
+        }
+        .send(Location::Pubsub, ctx.resp)
+        .await;
+    }
+
+    /// Points on the platform the duel's on
+    async fn balance(ctx: &Context<'_>, id: &Arc<String>) -> error::Result<i32> {
+        match Db::GetPoints(ctx.platform, id.clone()).exec(ctx.db).await? {
+            Resp::GetPoints(points) => Ok(points
+                .iter()
+                .find(|(platform, _)| *platform == ctx.platform)
+                .and_then(|(_, points)| *points)
+                .unwrap_or_default()),
+            _ => unreachable!(),
+        }
+    }
+
+    /// The challenge waiting on whoever ran it, taken so it can only be answered once
+    async fn take_challenge(&self, ctx: &Context<'_>) -> error::Result<Option<Challenge>> {
+        let key = self.challenge_key(ctx.platform, &ctx.user.id);
+        match Cache::GetDel(key).exec(ctx.cache).await {
+            Ok(RespType::String(json)) => Ok(Some(serde_json::from_str(&json)?)),
+            Ok(_) => unreachable!(),
+            Err(Error::Redis(e)) if e.kind() == redis::ErrorKind::TypeError => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    #[tracing::instrument(level = "trace", skip_all, name = "Duel")]
+    async fn run(&self, ctx: &Context<'_>, args: Args) -> error::Result<RunRes> {
+        tracing::debug!(name = self.name.as_str(), user = ctx.user.name.as_str(), args = ?args);
+
+        match args {
+            Args::Challenge { target, amount } => self.challenge(ctx, target, amount).await,
+            Args::Accept => self.accept(ctx).await,
+            Args::Decline => {
+                let msg = match self.take_challenge(ctx).await? {
+                    Some(challenge) => tr(
+                        "duel.declined",
+                        &[("name", &ctx.user.name), ("challenger", &challenge.name)],
+                    ),
+                    None => tr("duel.none", &[]),
+                };
+                Self::reply(ctx, msg).await;
+                Ok(RunRes::Ok)
+            }
+        }
+    }
+
+    async fn challenge(
+        &self,
+        ctx: &Context<'_>,
+        target: Target,
+        amount: i64,
+    ) -> error::Result<RunRes> {
+        if !(self.min_amount..=self.max_amount).contains(&amount) {
+            return Ok(RunRes::OutOfRange {
+                min: self.min_amount,
+                max: self.max_amount,
+            });
+        }
+        let amount = amount as i32;
+
+        let (id, name) = match target {
+            Target::User(id, name) => (id, name),
+            Target::Name(name) => match ctx.resolve_user(&name).await? {
+                Some(found) => (found.id, found.name),
+                None => {
+                    let msg = tr("duel.unknown", &[("name", &name)]);
+                    Self::reply(ctx, msg).await;
+                    return Ok(RunRes::Ok);
+                }
+            },
+        };
+        if id == ctx.user.id {
+            return Ok(RunRes::InvalidArgs);
+        }
+
+        if Self::balance(ctx, &ctx.user.id).await? < amount {
+            let msg = tr("duel.insufficient", &[("currency", &ctx.currency.plural)]);
+            Self::reply(ctx, msg).await;
+            return Ok(RunRes::Ok);
+        }
+
+        let challenge = Challenge {
+            id: ctx.user.id.clone(),
+            name: ctx.user.name.clone(),
+            amount,
+        };
+        let set = Cache::Set(
+            self.challenge_key(ctx.platform, &id),
+            serde_json::to_string(&challenge)?.into(),
+            self.accept_secs as usize,
+            true,
+        )
+        .exec(ctx.cache)
+        .await?;
+        if !matches!(set, RespType::Bool(true)) {
+            let msg = tr("duel.pending", &[("name", &name)]);
+            Self::reply(ctx, msg).await;
+            return Ok(RunRes::Ok);
+        }
+
+        tracing::info!(target = name.as_str(), amount, "duel issued");
+        let msg = tr(
+            "duel.challenge",
+            &[
+                ("challenger", &ctx.user.name),
+                ("name", &name),
+                ("amount", &ctx.currency.format(amount)),
+                ("accept", &self.accept_prefix),
+                ("decline", &self.decline_prefix),
+                ("secs", &self.accept_secs),
+            ],
+        );
+        Self::announce(ctx, msg).await;
+        Ok(RunRes::Ok)
+    }
+
+    async fn accept(&self, ctx: &Context<'_>) -> error::Result<RunRes> {
+        let challenge = match self.take_challenge(ctx).await? {
+            Some(challenge) => challenge,
+            None => {
+                let msg = tr("duel.none", &[]);
+                Self::reply(ctx, msg).await;
+                return Ok(RunRes::Ok);
+            }
+        };
+
+        // checked up front so neither side can only ever win
+        if Self::balance(ctx, &ctx.user.id).await? < challenge.amount {
+            let msg = tr("duel.insufficient", &[("currency", &ctx.currency.plural)]);
+            Self::reply(ctx, msg).await;
+            return Ok(RunRes::Ok);
+        }
+        if Self::balance(ctx, &challenge.id).await? < challenge.amount {
+            let msg = tr("duel.called_off", &[("name", &challenge.name)]);
+            Self::announce(ctx, msg).await;
+            return Ok(RunRes::Ok);
+        }
+
+        let target = (ctx.user.id.clone(), ctx.user.name.clone());
+        let challenger = (challenge.id, challenge.name);
+        let ((winner_id, winner), (loser_id, loser)) = match rand::random::<bool>() {
+            true => (target, challenger),
+            false => (challenger, target),
+        };
+
+        // the pot moves in one transaction, failing if the loser spent it in the meantime
+        let op = GiveOp {
+            amount: challenge.amount,
+            from: GiveSource::Id(ctx.platform, loser_id),
+            to: GiveTarget::User(ctx.platform, winner_id, winner.clone()),
+            min: 0,
+            max: challenge.amount as i64,
+        };
+        let amount = match Db::Give(op).exec(ctx.db).await {
+            Ok(Resp::Give(amount)) => amount,
+            Ok(_) => unreachable!(),
+            Err(Error::GiveOp(GiveError::Deduct)) => {
+                let msg = tr("duel.called_off", &[("name", &loser)]);
+                Self::announce(ctx, msg).await;
+                return Ok(RunRes::Ok);
+            }
+            Err(e) => return Err(e),
+        };
+
+        tracing::info!(
+            winner = winner.as_str(),
+            loser = loser.as_str(),
+            amount,
+            "duel settled"
+        );
+        let msg = tr(
+            "duel.won",
+            &[
+                ("winner", &winner),
+                ("loser", &loser),
+                ("amount", &ctx.currency.format(amount)),
+            ],
+        );
+        Self::announce(ctx, msg).await;
+        Ok(RunRes::Ok)
+    }
+}
+
+impl CmdDesc for Duel {
+    #[inline]
+    fn platform(&self) -> Platform {
+        self.platforms
+    }
+
+    #[inline]
+    fn description(&self, platform: Platform) -> Option<String> {
+        if platform.contains(Platform::DISCORD) {
+            return Some("Wager points on a coin flip against someone".into());
+        }
+
+        None
+    }
+}
+
+impl Invokable for Duel {
+    fn args(&self, _platform: Platform) -> Vec<Arg> {
+        vec![
+            Arg {
+                name: "challenge".into(),
+                desc: "Challenge someone".into(),
+                kind: ArgKind::SubCommand(vec![
+                    Arg {
+                        name: "user".into(),
+                        desc: "Person to challenge".into(),
+                        kind: ArgKind::User,
+                        optional: false,
+                    },
+                    Arg {
+                        name: "amount".into(),
+                        desc: "Wager".into(),
+                        kind: ArgKind::Integer {
+                            min: Some(self.min_amount),
+                            max: Some(self.max_amount),
+                        },
+                        optional: false,
+                    },
+                ]),
+                optional: true,
+            },
+            Arg {
+                name: "accept".into(),
+                desc: "Accept the challenge waiting on you".into(),
+                kind: ArgKind::SubCommand(vec![]),
+                optional: true,
+            },
+            Arg {
+                name: "decline".into(),
+                desc: "Decline the challenge waiting on you".into(),
+                kind: ArgKind::SubCommand(vec![]),
+                optional: true,
+            },
+        ]
+    }
+}
+
+impl TryFrom<&ArgMap> for Args {
+    type Error = ArgMapError;
+
+    fn try_from(value: &ArgMap) -> Result<Self, Self::Error> {
+        if let Some(ArgValue::SubCommand(args)) = value.get("challenge") {
+            let target = match args.get("user") {
+                Some(ArgValue::User(u)) => Target::User(u.id.clone(), u.name.clone()),
+                _ => return Err(ArgMapError),
+            };
+            let amount = match args.get("amount") {
+                Some(ArgValue::Integer(amount)) => *amount,
+                _ => return Err(ArgMapError),
+            };
+            return Ok(Args::Challenge { target, amount });
+        }
+
+        match (value.get("accept"), value.get("decline")) {
+            (Some(ArgValue::SubCommand(_)), _) => Ok(Args::Accept),
+            (_, Some(ArgValue::SubCommand(_))) => Ok(Args::Decline),
+            _ => Err(ArgMapError),
+        }
+    }
+}
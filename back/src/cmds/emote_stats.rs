@@ -0,0 +1,105 @@
+use super::{Context, RunRes};
+use crate::{
+    cache::{self, Cache},
+    error,
+    msg::{Chat, Invocation, Platform},
+};
+use back_derive::command;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::{sync::watch, task::JoinHandle};
+use tracing::{info_span, Instrument};
+
+#[command(timer, locks(tally))]
+/// Tally the emotes used in chat, for !topemotes
+pub struct EmoteStats {
+    /// Platforms
+    #[cmd(defl("Platform::STREAM"))]
+    platforms: Platform,
+    /// Emotes kept in the tally, the least used are dropped past this
+    #[cmd(def(200u64), constr(range = "10..=10000"))]
+    keep: u64,
+    /// How often to drop the least used emotes (in seconds)
+    #[cmd(def(600u64), constr(range = "60..=86400"))]
+    trim_interval: u64,
+}
+
+impl EmoteStats {
+    /// Shared by every EmoteStats, so there's one tally for the channel
+    pub(crate) fn tally_key() -> Arc<String> {
+        Arc::new(EMOTESTATS_LOCK_TALLY.clone())
+    }
+
+    /// Implicit chat fn to count the message's emotes
+    #[tracing::instrument(level = "trace", skip_all, name = "EmoteStats")]
+    pub(super) async fn chat(&self, ctx: &Context<'_>, chat: &Chat) -> error::Result<RunRes> {
+        if !self.enabled || !self.platforms.contains(ctx.platform) {
+            return Ok(RunRes::Disabled);
+        }
+
+        let mut counts: HashMap<&str, isize> = HashMap::new();
+        for name in chat.emote_names() {
+            *counts.entry(name).or_default() += 1;
+        }
+
+        let key = Self::tally_key();
+        for (name, count) in counts {
+            Cache::Zincrby(key.clone(), Arc::new(name.to_owned()), count)
+                .exec(ctx.cache)
+                .await?;
+        }
+
+        Ok(RunRes::Noop)
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn invoke(
+        &self,
+        _ctx: &Context<'_>,
+        _invocation: &Invocation,
+    ) -> Option<RunRes> {
+        None
+    }
+
+    /// Spawn the task that keeps the tally to `keep` emotes
+    pub(crate) fn init(
+        &self,
+        cancel_chan: watch::Receiver<()>,
+        cache: &cache::Handle,
+    ) -> Option<JoinHandle<()>> {
+        if !self.enabled || self.platforms.is_empty() {
+            return None;
+        }
+
+        let cache = cache.clone();
+        let keep = self.keep as isize;
+        let interval = Duration::from_secs(self.trim_interval);
+
+        let handle = tokio::spawn(
+            async move {
+                loop {
+                    tokio::time::sleep(interval).await;
+                    match cancel_chan.has_changed() {
+                        Ok(false) => {}
+                        _ => {
+                            // value changed or channel closed
+                            tracing::info!("\x1b[93maborting\x1b[0m");
+                            return;
+                        }
+                    }
+
+                    // ranks go lowest first, so this leaves the top `keep`
+                    match Cache::Zremrangebyrank(Self::tally_key(), 0, -(keep + 1))
+                        .exec(&cache)
+                        .await
+                    {
+                        Ok(removed) => tracing::debug!(removed = ?removed, "trimmed"),
+                        Err(e) => tracing::error!("{}", e),
+                    }
+                }
+            }
+            .instrument(info_span!("EmoteStats")),
+        );
+
+        Some(handle)
+    }
+}
@@ -1,10 +1,10 @@
-use super::{Context, FilterCache, ModAction, RunRes};
+use super::{normalize::Normalizer, Context, FilterCache, ModAction, RunRes};
 use crate::{
     error,
     msg::{Chat, Invocation, Permissions, Platform},
 };
 use back_derive::command;
-use std::sync::Arc;
+use std::{borrow::Cow, sync::Arc};
 
 #[command(filter)]
 /// Filter chat based on username and message
@@ -24,6 +24,15 @@ pub struct Filter {
     msg_contains: String,
     /// User id contains  (case-sensitive)
     id_contains: String,
+    /// Ignore case in username and message
+    #[cmd(def(true), group("Matching"))]
+    ignore_case: bool,
+    /// Unicode NFKC normalization, e.g. fullwidth or math-styled letters count as plain ones
+    #[cmd(group("Matching"))]
+    nfkc: bool,
+    /// Fold lookalike letters from other scripts into latin ones, and ignore invisible characters
+    #[cmd(group("Matching"))]
+    fold_confusables: bool,
 }
 
 impl Filter {
@@ -45,6 +54,26 @@ impl Filter {
         Some(())
     }
 
+    fn normalizer(&self) -> Normalizer {
+        Normalizer {
+            ignore_case: self.ignore_case,
+            nfkc: self.nfkc,
+            fold_confusables: self.fold_confusables,
+        }
+    }
+
+    /// The cached lowercase copy if that's all that's asked for
+    fn normalized<'a>(normalizer: &Normalizer, cached: &'a str, raw: &'a str) -> Cow<'a, str> {
+        let lowercase = Normalizer {
+            ignore_case: true,
+            ..Default::default()
+        };
+        match *normalizer == lowercase {
+            true => Cow::Borrowed(cached),
+            false => normalizer.apply(raw),
+        }
+    }
+
     /// cache lowercase copies of chat.user's fields
     pub(crate) fn fill_cache(ctx: &Context<'_>, chat: &Chat) {
         // fill filter cache if empty
@@ -52,7 +81,7 @@ impl Filter {
             *ctx.filter_cache.write() = Some(FilterCache {
                 id: Arc::new(chat.user.id.to_lowercase()),
                 name: Arc::new(chat.user.name.to_lowercase()),
-                msg: Arc::new(chat.msg.to_lowercase()),
+                msg: Arc::new(chat.text_without_emotes().to_lowercase()),
             });
         }
     }
@@ -88,10 +117,12 @@ impl Filter {
 
         let filter_action = RunRes::Filtered(self.action);
         let mut triggered: [Option<bool>; 3] = [None; 3];
+        let normalizer = self.normalizer();
 
         if let Some(ref cache) = *ctx.filter_cache.read() {
             if !self.user_contains.is_empty() {
-                let cond = cache.name.contains(&self.user_contains);
+                let name = Self::normalized(&normalizer, &cache.name, &chat.user.name);
+                let cond = name.contains(&*normalizer.apply(&self.user_contains));
                 if cond {
                     tracing::info!(
                         "\x1b[91mUsername {} contains '{}'\x1b[0m",
@@ -115,7 +146,9 @@ impl Filter {
             }
 
             if !self.msg_contains.is_empty() {
-                let cond = cache.msg.contains(&self.msg_contains);
+                let text = chat.text_without_emotes();
+                let msg = Self::normalized(&normalizer, &cache.msg, &text);
+                let cond = msg.contains(&*normalizer.apply(&self.msg_contains));
                 if cond {
                     tracing::info!(
                         "\x1b[91mMessage from {} contains '{}'\x1b[0m",
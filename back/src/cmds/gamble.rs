@@ -0,0 +1,554 @@
+use super::{
+    util, Arg, ArgKind, ArgValue, CmdDesc, Context, Invokable, OwnedValueError, RunRes, Value,
+};
+use crate::{
+    cache::{Cache, RespType},
+    db::{
+        give::{BetOp, GiveError},
+        Db, Resp,
+    },
+    error::{self, Error},
+    i18n::tr,
+    msg::{
+        ArgMap, ArgMapError, Chat, Invocation, Location, Payload, Permissions, Platform, Response,
+    },
+};
+use back_derive::command;
+use bb8_redis::redis;
+use once_cell::sync::Lazy;
+use rand::Rng;
+use regex::Regex;
+use std::{str::FromStr, sync::Arc};
+
+static GAMBLE_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(\S+)\s(\d+|all)\s*").unwrap());
+
+/// Rolls are 1 to this, inclusive
+const MAX_ROLL: u8 = 100;
+
+#[derive(Debug)]
+struct Args {
+    amount: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Payout {
+    /// x wager, in hundredths
+    Multiplier(u32),
+    /// Wager back plus whatever's in the pot
+    Jackpot,
+}
+
+/// What each roll pays out, e.g. `1-50:0,51-90:2x,91-99:3x,100:jackpot`.
+/// Every roll from 1 to 100 has to be covered exactly once
+#[derive(Debug, Clone)]
+pub(crate) struct PayoutTable {
+    src: String,
+    /// (first roll, last roll, payout)
+    entries: Vec<(u8, u8, Payout)>,
+}
+
+impl PayoutTable {
+    fn payout(&self, roll: u8) -> Payout {
+        self.entries
+            .iter()
+            .find(|(first, last, _)| (*first..=*last).contains(&roll))
+            .map(|(_, _, payout)| *payout)
+            .unwrap_or(Payout::Multiplier(0))
+    }
+
+    fn has_jackpot(&self) -> bool {
+        self.entries.iter().any(|(_, _, p)| *p == Payout::Jackpot)
+    }
+}
+
+impl Default for PayoutTable {
+    fn default() -> Self {
+        "1-50:0,51-90:2x,91-99:3x,100:jackpot".parse().unwrap()
+    }
+}
+
+impl FromStr for PayoutTable {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut entries = vec![];
+        let mut covered = [false; MAX_ROLL as usize];
+
+        for entry in s.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (rolls, payout) = entry
+                .split_once(':')
+                .ok_or_else(|| format!("expected <rolls>:<payout> in '{}'", entry))?;
+
+            let roll = |r: &str| match r.trim().parse::<u8>() {
+                Ok(r) if (1..=MAX_ROLL).contains(&r) => Ok(r),
+                _ => Err(format!(
+                    "rolls go from 1 to {}, got '{}'",
+                    MAX_ROLL,
+                    r.trim()
+                )),
+            };
+            let (first, last) = match rolls.split_once('-') {
+                Some((first, last)) => (roll(first)?, roll(last)?),
+                None => (roll(rolls)?, roll(rolls)?),
+            };
+            if first > last {
+                return Err(format!("backwards range in '{}'", entry));
+            }
+
+            let payout = payout.trim().to_lowercase();
+            let payout = if payout == "jackpot" {
+                Payout::Jackpot
+            } else {
+                match payout.trim_end_matches('x').parse::<f64>() {
+                    Ok(m) if (0.0..=1000.0).contains(&m) => {
+                        Payout::Multiplier((m * 100.0).round() as u32)
+                    }
+                    _ => return Err(format!("invalid payout in '{}'", entry)),
+                }
+            };
+
+            for roll in first..=last {
+                let covered = &mut covered[roll as usize - 1];
+                if *covered {
+                    return Err(format!("roll {} is covered more than once", roll));
+                }
+                *covered = true;
+            }
+
+            entries.push((first, last, payout));
+        }
+
+        if let Some(roll) = covered.iter().position(|c| !c) {
+            return Err(format!("roll {} isn't covered", roll + 1));
+        }
+
+        Ok(Self {
+            src: s.to_owned(),
+            entries,
+        })
+    }
+}
+
+impl TryFrom<Value> for PayoutTable {
+    type Error = Error;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::String(ref s) => s
+                .parse()
+                .map_err(|e| Error::Generic(format!("invalid payout table: {}", e))),
+            _ => Err(OwnedValueError {
+                expected: "String".into(),
+                value,
+            }
+            .into()),
+        }
+    }
+}
+
+impl From<PayoutTable> for Value {
+    fn from(x: PayoutTable) -> Self {
+        Self::String(x.src)
+    }
+}
+
+impl VerifyConstraint for PayoutTable {}
+
+#[command(locks(rate, jackpot))]
+/// Roll for a payout on a wager, with a jackpot that grows with every loss
+pub struct Gamble {
+    /// Command prefix
+    #[cmd(def("!gamble"), constr(non_empty))]
+    prefix: String,
+    /// Autocorrect prefix
+    autocorrect: bool,
+    /// Platforms
+    #[cmd(defl("Platform::CHAT"))]
+    platforms: Platform,
+    /// Permissions
+    #[cmd(defl("Permissions::NONE"))]
+    perms: Permissions,
+    /// Cooldown per user (in seconds)
+    #[cmd(constr(pos), group("Cooldowns"))]
+    ratelimit_user: u64,
+    /// Min amount
+    #[cmd(def(10i64), constr(pos))]
+    min_amount: i64,
+    /// Max amount
+    #[cmd(def(10_000i64), constr(pos))]
+    max_amount: i64,
+    /// Payouts by roll out of 100 (e.g. 1-50:0,51-90:2x,91-99:3x,100:jackpot)
+    #[cmd(defl("PayoutTable::default()"))]
+    payouts: PayoutTable,
+    /// Payouts used instead when the flag below is on, to try new odds on some of the rolls
+    #[cmd(defl("PayoutTable::default()"), group("Rollout"))]
+    trial_payouts: PayoutTable,
+    /// Feature flag for trial_payouts, which are never used if this is empty
+    #[cmd(group("Rollout"))]
+    trial_flag: String,
+    /// % of lost wagers that goes into the jackpot
+    #[cmd(def(10u64), constr(range = "0..=100"))]
+    jackpot_pct: u64,
+}
+
+impl Gamble {
+    fn parse_arguments(&self, chat: &Chat) -> Option<(bool, Args)> {
+        let captures = GAMBLE_REGEX.captures(&chat.msg)?;
+
+        // check command prefix
+        let autocorrect = util::check_autocorrect(
+            &self.prefix,
+            &captures[1],
+            self.autocorrect,
+            &self.levenshtein,
+        )?;
+
+        // parse wager
+        let amount = if &captures[2] == "all" {
+            -1
+        } else {
+            captures[2].parse::<i32>().unwrap_or(i32::MAX)
+        };
+
+        Some((autocorrect, Args { amount }))
+    }
+
+    fn can_run(&self, ctx: &Context<'_>) -> Option<()> {
+        if !self.enabled {
+            return None;
+        }
+
+        // check if platform is applicable
+        if !self.platforms.contains(ctx.platform) {
+            return None;
+        }
+
+        // check perms
+        if ctx.user.perms < self.perms {
+            return None;
+        }
+
+        Some(())
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn chat(&self, ctx: &Context<'_>, chat: &Chat) -> error::Result<RunRes> {
+        if self.can_run(ctx).is_none() {
+            return Ok(RunRes::Disabled);
+        }
+
+        let (autocorrect, args) = match self.parse_arguments(chat) {
+            Some(t) => t,
+            None if util::starts_with_prefix(&self.prefix, &chat.msg) => {
+                return Ok(RunRes::InvalidArgs)
+            }
+            None => return Ok(RunRes::Noop),
+        };
+
+        if autocorrect {
+            return Ok(RunRes::Autocorrect(self.prefix.clone()));
+        }
+
+        match util::ratelimit_user(
+            ctx,
+            self.ratelimit_user,
+            stringify!(Gamble),
+            &self.name,
+            &*GAMBLE_LOCK_RATE,
+        )
+        .await
+        {
+            Ok(false) => {}
+            Ok(true) => return Ok(RunRes::Ratelimited { global: false }),
+            Err(e) => return Err(e),
+        }
+
+        self.run(ctx, args).await
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn invoke(
+        &self,
+        ctx: &Context<'_>,
+        invocation: &Invocation,
+    ) -> Option<RunRes> {
+        self.can_run(ctx)?;
+
+        super::check_invoke_prefix(&self.prefix, &invocation.cmd)?;
+
+        let args = match Args::try_from(&invocation.args) {
+            Ok(args) => args,
+            Err(_) => return Some(RunRes::InvalidArgs),
+        };
+
+        match util::ratelimit_user(
+            ctx,
+            self.ratelimit_user,
+            stringify!(Gamble),
+            &self.name,
+            &*GAMBLE_LOCK_RATE,
+        )
+        .await
+        {
+            Ok(false) => {}
+            Ok(true) => return None,
+            Err(e) => {
+                tracing::error!("{}", e);
+                return None;
+            }
+        }
+
+        match self.run(ctx, args).await {
+            Ok(r) => Some(r),
+            Err(e) => {
+                tracing::error!("{}", e);
+                None
+            }
+        }
+    }
+
+    /// How much of the pot can be paid on top of a returned wager without going over what the
+    /// points column holds, and what's left over
+    fn split_pot(&self, pot: i64) -> (i32, i64) {
+        let room = i32::MAX as i64 - self.max_amount.clamp(0, i32::MAX as i64);
+        let paid = pot.clamp(0, room);
+        (paid as i32, pot.max(0) - paid)
+    }
+
+    fn jackpot_key(name: &str) -> Arc<String> {
+        Arc::new(format!("{}_{}", &*GAMBLE_LOCK_JACKPOT, name))
+    }
+
+    async fn reply(ctx: &Context<'_>, msg: String) {
+        Response {
+            platform: ctx.platform,
+            channel: crate::channel_name(),
+            corr_id: ctx.corr_id.clone(),
+            payload: Payload::Message {
+                user: ctx.reply_to(),
+                msg: msg.into(),
+                meta: ctx.meta.clone(),
+                hint: None,
+                thread: None,
+            },
+        }
+        .send(Location::Pubsub, ctx.resp)
+        .await;
+    }
+
+    #[tracing::instrument(level = "trace", skip_all, name = "Gamble")]
+    async fn run(&self, ctx: &Context<'_>, args: Args) -> error::Result<RunRes> {
+        tracing::debug!(name = self.name.as_str(), user = ctx.user.name.as_str(), args = ?args);
+
+        let user = ctx.user;
+        let out_of_range = RunRes::OutOfRange {
+            min: self.min_amount,
+            max: self.max_amount,
+        };
+
+        // "all" is clamped to the max instead
+        if args.amount != -1 && !(self.min_amount..=self.max_amount).contains(&(args.amount as i64))
+        {
+            return Ok(out_of_range);
+        }
+
+        let roll = rand::thread_rng().gen_range(1..=MAX_ROLL);
+        let payouts = match self.trial_flag.is_empty() {
+            false if ctx.flag(&self.trial_flag).await => &self.trial_payouts,
+            _ => &self.payouts,
+        };
+        let payout = payouts.payout(roll);
+        let jackpot_key = Self::jackpot_key(&self.name);
+
+        let (payout_pct, jackpot) = match payout {
+            Payout::Multiplier(m) => (m as i64, None),
+            Payout::Jackpot => {
+                // take the whole pot, so nobody else can win it too
+                let pot = match Cache::GetDel(jackpot_key.clone()).exec(ctx.cache).await {
+                    Ok(RespType::String(pot)) => pot.parse::<i64>().unwrap_or_default(),
+                    Ok(_) => unreachable!(),
+                    Err(Error::Redis(e)) if e.kind() == redis::ErrorKind::TypeError => 0,
+                    Err(e) => {
+                        tracing::error!("{}", e);
+                        0
+                    }
+                };
+                let (pot, rest) = self.split_pot(pot);
+                // what doesn't fit stays in it for the next winner
+                if rest > 0 {
+                    if let Err(e) = Cache::Increment(jackpot_key.clone(), rest as usize, 0)
+                        .exec(ctx.cache)
+                        .await
+                    {
+                        tracing::error!(rest, "couldn't put back the rest of the jackpot: {}", e);
+                    }
+                }
+                (100, Some(pot))
+            }
+        };
+
+        // the wager's taken and paid out together
+        let op = BetOp {
+            platform: ctx.platform,
+            id: user.id.clone(),
+            name: user.name.clone(),
+            amount: args.amount,
+            min: self.min_amount,
+            max: self.max_amount,
+            payout_pct,
+            bonus: jackpot.unwrap_or_default(),
+        };
+
+        let res = Db::Bet(op).exec(ctx.db).await;
+        if let (Err(_), Some(pot)) = (&res, jackpot) {
+            // nobody won it, so put it back
+            if pot > 0 {
+                if let Err(e) = Cache::Increment(jackpot_key.clone(), pot as usize, 0)
+                    .exec(ctx.cache)
+                    .await
+                {
+                    tracing::error!(pot, "couldn't restore the jackpot: {}", e);
+                }
+            }
+        }
+
+        let (amount, winnings) = match res {
+            Ok(Resp::Bet(amount, winnings)) => (amount, winnings),
+            Ok(_) => unreachable!(),
+            Err(Error::GiveOp(GiveError::AmountBelowMin { .. })) => return Ok(out_of_range),
+            Err(Error::GiveOp(GiveError::Deduct)) => {
+                let msg = tr("gamble.insufficient", &[("currency", &ctx.currency.plural)]);
+                Self::reply(ctx, msg).await;
+                return Ok(RunRes::Ok);
+            }
+            Err(e) => return Err(e),
+        };
+
+        tracing::info!(roll, ?payout, amount, winnings, "gambled");
+
+        let msg = if let Some(jackpot) = jackpot {
+            tr(
+                "gamble.jackpot",
+                &[("roll", &roll), ("jackpot", &ctx.currency.format(jackpot))],
+            )
+        } else if winnings > amount {
+            let won = winnings - amount;
+            tr(
+                "gamble.won",
+                &[("roll", &roll), ("amount", &ctx.currency.format(won))],
+            )
+        } else if winnings == amount {
+            tr("gamble.even", &[("roll", &roll)])
+        } else {
+            let lost = amount - winnings;
+            let contribution = lost as i64 * self.jackpot_pct as i64 / 100;
+            let pot = if contribution > 0 && payouts.has_jackpot() {
+                match Cache::Increment(jackpot_key, contribution as usize, 0)
+                    .exec(ctx.cache)
+                    .await
+                {
+                    Ok(RespType::U64(pot)) => Some(pot),
+                    Ok(_) => unreachable!(),
+                    Err(e) => {
+                        tracing::error!("{}", e);
+                        None
+                    }
+                }
+            } else {
+                None
+            };
+
+            match pot {
+                Some(pot) => tr(
+                    "gamble.lost_jackpot",
+                    &[
+                        ("roll", &roll),
+                        ("amount", &ctx.currency.format(lost)),
+                        ("jackpot", &ctx.currency.format(pot)),
+                    ],
+                ),
+                None => tr(
+                    "gamble.lost",
+                    &[("roll", &roll), ("amount", &ctx.currency.format(lost))],
+                ),
+            }
+        };
+
+        Self::reply(ctx, msg).await;
+
+        Ok(RunRes::Ok)
+    }
+}
+
+impl CmdDesc for Gamble {
+    #[inline]
+    fn platform(&self) -> Platform {
+        self.platforms
+    }
+
+    #[inline]
+    fn description(&self, platform: Platform) -> Option<String> {
+        if platform.contains(Platform::DISCORD) {
+            return Some("Roll for a payout, or the jackpot".into());
+        }
+
+        None
+    }
+}
+
+impl Invokable for Gamble {
+    fn args(&self, _platform: Platform) -> Vec<Arg> {
+        vec![Arg {
+            name: "amount".into(),
+            desc: "Amount to wager (leaving this blank means max)".into(),
+            kind: ArgKind::Integer {
+                min: Some(self.min_amount),
+                max: Some(self.max_amount),
+            },
+            optional: true,
+        }]
+    }
+}
+
+impl TryFrom<&ArgMap> for Args {
+    type Error = ArgMapError;
+
+    fn try_from(value: &ArgMap) -> Result<Self, Self::Error> {
+        let amount = match value.get("amount") {
+            Some(ArgValue::Integer(x)) => (*x).clamp(0, i32::MAX as i64) as i32,
+            Some(_) => return Err(ArgMapError),
+            None => -1,
+        };
+
+        Ok(Args { amount })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gamble(max_amount: i64) -> Gamble {
+        Gamble {
+            max_amount,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn pays_the_whole_pot_when_it_fits() {
+        assert_eq!(gamble(1000).split_pot(5000), (5000, 0));
+        assert_eq!(gamble(1000).split_pot(0), (0, 0));
+        assert_eq!(gamble(1000).split_pot(-5), (0, 0));
+    }
+
+    #[test]
+    fn keeps_what_doesnt_fit() {
+        let pot = i32::MAX as i64 * 2;
+        let (paid, rest) = gamble(1000).split_pot(pot);
+        assert_eq!(paid as i64, i32::MAX as i64 - 1000);
+        assert_eq!(paid as i64 + rest, pot);
+        // the wager comes back on top of it
+        assert!(paid as i64 + 1000 <= i32::MAX as i64);
+    }
+}
@@ -3,6 +3,7 @@ use crate::db::give::{GiveSource, GiveTarget};
 use crate::db::Resp;
 use crate::db::{give::GiveOp, Db};
 use crate::error;
+use crate::i18n::tr;
 use crate::msg::{
     ArgMap, ArgMapError, Chat, Invocation, Location, Payload, Permissions, Platform, Response,
 };
@@ -33,7 +34,7 @@ pub struct Give {
     #[cmd(defl("Permissions::NONE"))]
     perms: Permissions,
     /// Cooldown per user (in seconds)
-    #[cmd(constr(pos))]
+    #[cmd(constr(pos), group("Cooldowns"))]
     ratelimit_user: u64,
     /// Min amount
     #[cmd(def(10_i64), constr(pos))]
@@ -79,7 +80,10 @@ impl Give {
         let amount = if &captures[3] == "all" {
             -1
         } else {
-            captures[3].parse::<i32>()?
+            match captures[3].parse::<i32>() {
+                Ok(n) => n,
+                Err(_) => return Ok(None),
+            }
         };
 
         Ok(Some((autocorrect, Args { amount, to })))
@@ -111,6 +115,9 @@ impl Give {
 
         let (autocorrect, args) = match self.parse_arguments(ctx, chat)? {
             Some(t) => t,
+            None if util::starts_with_prefix(&self.prefix, &chat.msg) => {
+                return Ok(RunRes::InvalidArgs)
+            }
             None => return Ok(RunRes::Noop),
         };
 
@@ -145,18 +152,21 @@ impl Give {
 
         super::check_invoke_prefix(&self.prefix, &invocation.cmd)?;
 
-        let args = Args::try_from(&invocation.args).ok()?;
+        let args = match Args::try_from(&invocation.args) {
+            Ok(args) => args,
+            Err(_) => return Some(RunRes::InvalidArgs),
+        };
 
         match args.to {
             GiveTarget::Name(platform, name)
                 if platform == ctx.platform && *name == *ctx.user.name =>
             {
-                return None
+                return Some(RunRes::InvalidArgs)
             }
             GiveTarget::User(platform, id, _)
                 if platform == ctx.platform && *id == *ctx.user.id =>
             {
-                return None
+                return Some(RunRes::InvalidArgs)
             }
             _ => {}
         }
@@ -191,16 +201,33 @@ impl Give {
     async fn run(&self, ctx: &Context<'_>, args: Args) -> error::Result<RunRes> {
         tracing::debug!(name = self.name.as_str(), user = ctx.user.name.as_str(), args = ?args);
 
-        let to_name = match args.to {
+        // names are looked up through the user cache rather than in the give's transaction
+        let to = match args.to {
+            GiveTarget::Name(platform, name) if platform == ctx.platform => {
+                match ctx.resolve_user(&name).await? {
+                    Some(user) => GiveTarget::User(platform, user.id, user.name),
+                    None => GiveTarget::Name(platform, name),
+                }
+            }
+            to => to,
+        };
+
+        let to_name = match to {
             GiveTarget::Name(_, ref name) => name.clone(),
-            GiveTarget::User(_, _, ref name) => name.clone(),
+            GiveTarget::User(platform, ref id, ref name) => {
+                // the name might've been another casing or a mention of themselves
+                if platform == ctx.platform && *id == ctx.user.id {
+                    return Ok(RunRes::InvalidArgs);
+                }
+                name.clone()
+            }
             _ => unreachable!(),
         };
 
         let op = GiveOp {
             amount: args.amount,
             from: GiveSource::Id(ctx.platform, ctx.user.id.clone()),
-            to: args.to, //: GiveTarget::Name(ctx.platform, to.clone()),
+            to, //: GiveTarget::Name(ctx.platform, to.clone()),
             min: self.min_amount,
             max: self.max_amount,
         };
@@ -210,20 +237,21 @@ impl Give {
         match resp {
             Resp::Give(amount) => {
                 // send reply
-                let msg = format!(
-                    "gave {} {} point{}",
-                    to_name,
-                    amount,
-                    if args.amount != 1 { "s" } else { "" },
+                let msg = tr(
+                    "give.success",
+                    &[("name", &to_name), ("amount", &ctx.currency.format(amount))],
                 );
 
                 Response {
                     platform: ctx.platform,
-                    channel: &*crate::CHANNEL_NAME,
+                    channel: crate::channel_name(),
+                    corr_id: ctx.corr_id.clone(),
                     payload: Payload::Message {
-                        user: Some((ctx.platform, ctx.user.clone())),
+                        user: ctx.reply_to(),
                         msg: msg.into(),
                         meta: ctx.meta.clone(),
+                        hint: None,
+                        thread: None,
                     },
                 }
                 .send(Location::Broadcast, ctx.resp)
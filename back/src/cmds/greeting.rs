@@ -0,0 +1,175 @@
+use super::{CmdDesc, Context, Invokable, RunRes};
+use crate::{
+    cache::{Cache, RespType},
+    db::{
+        give::{GiveOp, GiveSource, GiveTarget},
+        Db, Resp,
+    },
+    error::{self, Error},
+    msg::{Chat, Invocation, Location, Payload, Platform, Response},
+};
+use back_derive::command;
+use bb8_redis::redis;
+use std::sync::Arc;
+
+/// Outlives any one stream, sessions are keyed by stream id anyway
+const SESSION_TTL: usize = 60 * 60 * 48;
+
+#[command(cmd)]
+/// Greet viewers on their first message of the stream
+pub struct Greeting {
+    /// Platforms
+    #[cmd(defl("Platform::STREAM"))]
+    platforms: Platform,
+    /// Greeting for returning viewers, leave blank to not greet them
+    #[cmd(def("Welcome back {user}!"), constr(range = "0..=500"))]
+    returning_message: String,
+    /// Greeting for first-time chatters, leave blank to not greet them
+    #[cmd(
+        def("Welcome to the stream {user}! Here's {bonus} points to get you started"),
+        constr(range = "0..=500")
+    )]
+    first_message: String,
+    /// Points given to first-time chatters
+    #[cmd(constr(range = "0..=1000000"))]
+    welcome_bonus: i64,
+}
+
+impl Greeting {
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn chat(&self, ctx: &Context<'_>, _chat: &Chat) -> error::Result<RunRes> {
+        if !self.enabled || !self.platforms.contains(ctx.platform) {
+            return Ok(RunRes::Disabled);
+        }
+
+        self.run(ctx).await
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn invoke(
+        &self,
+        _ctx: &Context<'_>,
+        _invocation: &Invocation,
+    ) -> Option<RunRes> {
+        None
+    }
+
+    /// The stream being followed, set on StreamEvent::Started
+    async fn stream_id(ctx: &Context<'_>) -> error::Result<Option<String>> {
+        let key = format!(
+            "aussiebot!{}!streamid!{}",
+            crate::channel_name(),
+            ctx.platform
+        );
+        match Cache::Get(key.into()).exec(ctx.cache).await {
+            Ok(RespType::String(id)) => Ok(Some(id)),
+            Ok(_) => unreachable!(),
+            Err(Error::Redis(e)) if e.kind() == redis::ErrorKind::TypeError => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    #[tracing::instrument(level = "trace", skip_all, name = "Greeting")]
+    async fn run(&self, ctx: &Context<'_>) -> error::Result<RunRes> {
+        let user = ctx.user;
+
+        let stream_id = match Self::stream_id(ctx).await? {
+            Some(id) => id,
+            None => return Ok(RunRes::Noop),
+        };
+        let session_key = Arc::new(format!(
+            "aussiebot!{}!greeted!{}!{}",
+            crate::channel_name(),
+            ctx.platform,
+            stream_id
+        ));
+
+        // most messages are from users already greeted, so check before writing
+        match Cache::SetIsMember(session_key.clone(), user.id.clone())
+            .exec(ctx.cache)
+            .await?
+        {
+            RespType::Bool(true) => return Ok(RunRes::Noop),
+            RespType::Bool(false) => {}
+            _ => unreachable!(),
+        }
+
+        // another message may have beaten us to it
+        match Cache::SetAdd(session_key, user.id.clone(), SESSION_TTL)
+            .exec(ctx.cache)
+            .await?
+        {
+            RespType::Bool(true) => {}
+            RespType::Bool(false) => return Ok(RunRes::Noop),
+            _ => unreachable!(),
+        }
+
+        let first_ever = match Db::FirstSeen(ctx.platform, user.id.clone())
+            .exec(ctx.db)
+            .await?
+        {
+            Resp::FirstSeen(b) => b,
+            _ => unreachable!(),
+        };
+
+        tracing::debug!(
+            name = self.name.as_str(),
+            user = user.name.as_str(),
+            first_ever,
+            "first message of the stream"
+        );
+
+        if first_ever && self.welcome_bonus > 0 {
+            Db::Give(GiveOp {
+                amount: self.welcome_bonus.try_into()?,
+                from: GiveSource::None,
+                to: GiveTarget::User(ctx.platform, user.id.clone(), user.name.clone()),
+                min: 0,
+                max: 0,
+            })
+            .exec(ctx.db)
+            .await?;
+        }
+
+        let template = if first_ever {
+            &self.first_message
+        } else {
+            &self.returning_message
+        };
+
+        // greeting isn't running a command, so it mustn't hold back autocorrect for the message
+        if template.is_empty() {
+            return Ok(RunRes::Noop);
+        }
+
+        let msg = template
+            .replace("{user}", &user.name)
+            .replace("{bonus}", &self.welcome_bonus.to_string());
+
+        Response {
+            platform: ctx.platform,
+            channel: crate::channel_name(),
+            corr_id: ctx.corr_id.clone(),
+            payload: Payload::Message {
+                user: Some((ctx.platform, user.clone())),
+                msg: msg.into(),
+                meta: ctx.meta.clone(),
+                hint: None,
+                thread: None,
+            },
+        }
+        .send(Location::Pubsub, ctx.resp)
+        .await;
+
+        Ok(RunRes::Noop)
+    }
+}
+
+impl CmdDesc for Greeting {
+    #[inline]
+    fn platform(&self) -> Platform {
+        self.platforms
+    }
+}
+
+impl Invokable for Greeting {}
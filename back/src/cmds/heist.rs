@@ -0,0 +1,579 @@
+use super::{util, Arg, ArgKind, ArgValue, CmdDesc, Context, Invokable, RespHandle, RunRes};
+use crate::{
+    cache::{self, Cache, RespType},
+    db::{
+        self,
+        give::{GiveOp, GiveSource, GiveTarget},
+        Db, Resp,
+    },
+    error,
+    i18n::{plural, tr},
+    lock,
+    msg::{
+        corr_id, ArgMap, ArgMapError, Chat, Invocation, Location, Payload, Permissions, Platform,
+        Response, User,
+    },
+};
+use back_derive::command;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use rand::Rng;
+use regex::Regex;
+use std::{sync::Arc, time::Duration};
+use tokio::sync::watch;
+use tracing::{debug_span, Instrument};
+
+static HEIST_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(\S+)\s(\d+|all)\s*").unwrap());
+
+#[derive(Debug)]
+struct Args {
+    amount: i32,
+}
+
+/// (platform, user, wager)
+type Member = (Platform, Arc<User>, i32);
+type Handles = (cache::Handle, db::Handle, lock::Handle, RespHandle);
+
+#[command(locks(rate, active, members))]
+/// Pool points with chat for a shot at a bigger payout
+pub struct Heist {
+    /// Command prefix
+    #[cmd(def("!heist"), constr(non_empty))]
+    prefix: String,
+    /// Autocorrect prefix
+    autocorrect: bool,
+    /// Platforms
+    #[cmd(defl("Platform::CHAT"))]
+    platforms: Platform,
+    /// Permissions
+    #[cmd(defl("Permissions::NONE"))]
+    perms: Permissions,
+    /// Time to join after a heist starts (in seconds)
+    #[cmd(def(60u64), constr(range = "10..=3600"))]
+    duration: u64,
+    /// Cooldown per user (in seconds)
+    #[cmd(constr(pos), group("Cooldowns"))]
+    ratelimit_user: u64,
+    /// Min amount
+    #[cmd(def(10i64), constr(pos))]
+    min_amount: i64,
+    /// Max amount
+    #[cmd(def(10_000i64), constr(pos))]
+    max_amount: i64,
+    /// % chance of success with one member
+    #[cmd(def(40u64), constr(range = "0..=100"))]
+    win_prob_pct: u64,
+    /// Extra % chance of success per additional member
+    #[cmd(def(2u64), constr(range = "0..=100"))]
+    member_bonus_pct: u64,
+    /// Max % chance of success
+    #[cmd(def(80u64), constr(range = "0..=100"))]
+    max_win_prob_pct: u64,
+    /// Payoff (x wager)
+    #[cmd(def(2u64), constr(pos))]
+    payoff: u64,
+    /// Calls off running heists when the config changes
+    #[cmd(skip)]
+    cancel_chan: RwLock<Option<watch::Receiver<()>>>,
+}
+
+impl Heist {
+    fn parse_arguments(&self, chat: &Chat) -> Option<(bool, Args)> {
+        let captures = HEIST_REGEX.captures(&chat.msg)?;
+
+        // check command prefix
+        let autocorrect = util::check_autocorrect(
+            &self.prefix,
+            &captures[1],
+            self.autocorrect,
+            &self.levenshtein,
+        )?;
+
+        // parse and validate wager
+        let amount = if &captures[2] == "all" {
+            -1
+        } else {
+            captures[2].parse::<i32>().ok()?
+        };
+
+        Some((autocorrect, Args { amount }))
+    }
+
+    fn can_run(&self, ctx: &Context<'_>) -> Option<()> {
+        if !self.enabled {
+            return None;
+        }
+
+        // check if platform is applicable
+        if !self.platforms.contains(ctx.platform) {
+            return None;
+        }
+
+        // check perms
+        if ctx.user.perms < self.perms {
+            return None;
+        }
+
+        Some(())
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn chat(&self, ctx: &Context<'_>, chat: &Chat) -> error::Result<RunRes> {
+        if self.can_run(ctx).is_none() {
+            return Ok(RunRes::Disabled);
+        }
+
+        let (autocorrect, args) = match self.parse_arguments(chat) {
+            Some(t) => t,
+            None if util::starts_with_prefix(&self.prefix, &chat.msg) => {
+                return Ok(RunRes::InvalidArgs)
+            }
+            None => return Ok(RunRes::Noop),
+        };
+
+        if autocorrect {
+            return Ok(RunRes::Autocorrect(self.prefix.clone()));
+        }
+
+        match util::ratelimit_user(
+            ctx,
+            self.ratelimit_user,
+            stringify!(Heist),
+            &self.name,
+            &*HEIST_LOCK_RATE,
+        )
+        .await
+        {
+            Ok(false) => {}
+            Ok(true) => return Ok(RunRes::Ratelimited { global: false }),
+            Err(e) => return Err(e),
+        }
+
+        self.run(ctx, args).await
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn invoke(
+        &self,
+        ctx: &Context<'_>,
+        invocation: &Invocation,
+    ) -> Option<RunRes> {
+        self.can_run(ctx)?;
+
+        super::check_invoke_prefix(&self.prefix, &invocation.cmd)?;
+
+        let args = match Args::try_from(&invocation.args) {
+            Ok(args) => args,
+            Err(_) => return Some(RunRes::InvalidArgs),
+        };
+
+        match util::ratelimit_user(
+            ctx,
+            self.ratelimit_user,
+            stringify!(Heist),
+            &self.name,
+            &*HEIST_LOCK_RATE,
+        )
+        .await
+        {
+            Ok(false) => {}
+            Ok(true) => return None,
+            Err(e) => {
+                tracing::error!("{}", e);
+                return None;
+            }
+        }
+
+        match self.run(ctx, args).await {
+            Ok(r) => Some(r),
+            Err(e) => {
+                tracing::error!("{}", e);
+                None
+            }
+        }
+    }
+
+    fn keys(name: &str) -> (Arc<String>, Arc<String>) {
+        (
+            Arc::new(format!("{}_{}", &*HEIST_LOCK_MEMBERS, name)),
+            Arc::new(format!("{}_{}", &*HEIST_LOCK_ACTIVE, name)),
+        )
+    }
+
+    async fn reply(ctx: &Context<'_>, msg: String) {
+        Response {
+            platform: ctx.platform,
+            channel: crate::channel_name(),
+            corr_id: ctx.corr_id.clone(),
+            payload: Payload::Message {
+                user: ctx.reply_to(),
+                msg: msg.into(),
+                meta: ctx.meta.clone(),
+                hint: None,
+                thread: None,
+            },
+        }
+        .send(Location::Pubsub, ctx.resp)
+        .await;
+    }
+
+    #[tracing::instrument(level = "trace", skip_all, name = "Heist")]
+    async fn run(&self, ctx: &Context<'_>, args: Args) -> error::Result<RunRes> {
+        tracing::debug!(name = self.name.as_str(), user = ctx.user.name.as_str(), args = ?args);
+
+        let user = ctx.user;
+        let (member_key, active_key) = Self::keys(&self.name);
+
+        // consume wager
+        let op = GiveOp {
+            amount: args.amount,
+            from: GiveSource::Id(ctx.platform, user.id.clone()),
+            to: GiveTarget::Spend,
+            min: self.min_amount,
+            max: self.max_amount,
+        };
+
+        let amount = match Db::Give(op).exec(ctx.db).await? {
+            Resp::Give(amount) => amount,
+            _ => unreachable!(),
+        };
+
+        let member: Member = (ctx.platform, user.clone(), amount);
+        let member = tokio::task::spawn_blocking(move || serde_json::to_string(&member)).await??;
+
+        // join, at most once per heist
+        let joined = Cache::HashSet(member_key.clone(), user.id.clone(), member, true)
+            .exec(ctx.cache)
+            .await;
+
+        match joined {
+            Ok(RespType::Bool(true)) => {}
+            Ok(RespType::Bool(false)) => {
+                Self::refund(ctx.db, ctx.platform, user, amount).await?;
+                Self::reply(ctx, tr("heist.already_joined", &[])).await;
+                return Ok(RunRes::Ok);
+            }
+            Ok(_) => unreachable!(),
+            Err(e) => {
+                Self::refund(ctx.db, ctx.platform, user, amount).await?;
+                return Err(e);
+            }
+        }
+
+        // the first member starts the heist
+        let starting = match ctx.lock.lock(&*active_key, self.duration + 5).await {
+            Ok(b) => b,
+            Err(e) => {
+                Self::refund(ctx.db, ctx.platform, user, amount).await?;
+                return Err(e);
+            }
+        };
+
+        let msg = if starting {
+            let handles = (
+                ctx.cache.clone(),
+                ctx.db.clone(),
+                ctx.lock.clone(),
+                ctx.resp.clone(),
+            );
+            let cancel_chan = self.cancel_chan.read().clone();
+            let duration = self.duration;
+            let odds = (
+                self.win_prob_pct,
+                self.member_bonus_pct,
+                self.max_win_prob_pct,
+            );
+            let payoff = self.payoff as i32;
+
+            tokio::spawn(
+                async move {
+                    let cancelled = async move {
+                        match cancel_chan {
+                            Some(mut chan) => {
+                                let _ = chan.changed().await;
+                            }
+                            None => futures_util::future::pending().await,
+                        }
+                    };
+
+                    let res = tokio::select! {
+                        _ = tokio::time::sleep(Duration::from_secs(duration)) => {
+                            Self::handle_end(member_key, active_key, odds, payoff, handles).await
+                        }
+                        _ = cancelled => {
+                            // value changed or channel closed
+                            tracing::info!("\x1b[93maborting\x1b[0m");
+                            Self::handle_cancel(member_key, active_key, handles).await
+                        }
+                    };
+
+                    if let Err(e) = res {
+                        tracing::error!("{}", e);
+                    }
+                }
+                .instrument(debug_span!("Heist task")),
+            );
+
+            tr(
+                "heist.started",
+                &[
+                    ("amount", &amount),
+                    ("s", &plural(amount)),
+                    ("prefix", &self.prefix),
+                    ("duration", &self.duration),
+                ],
+            )
+        } else {
+            tr(
+                "heist.joined",
+                &[("amount", &amount), ("s", &plural(amount))],
+            )
+        };
+
+        tracing::info!("{}", msg);
+        Self::reply(ctx, msg).await;
+
+        Ok(RunRes::Ok)
+    }
+
+    async fn refund(
+        db: &db::Handle,
+        platform: Platform,
+        user: &User,
+        amount: i32,
+    ) -> error::Result<db::Resp> {
+        Db::Give(GiveOp {
+            amount,
+            from: GiveSource::None,
+            to: GiveTarget::User(platform, user.id.clone(), user.name.clone()),
+            min: 0,
+            max: 0,
+        })
+        .exec(db)
+        .await
+    }
+
+    /// Pay everyone in one go, so either all of them are paid or none are
+    async fn pay(db: &db::Handle, payouts: &[Member]) -> error::Result<()> {
+        if payouts.is_empty() {
+            return Ok(());
+        }
+        let ops = payouts
+            .iter()
+            .map(|(platform, user, amount)| GiveOp {
+                amount: *amount,
+                from: GiveSource::None,
+                to: GiveTarget::User(*platform, user.id.clone(), user.name.clone()),
+                min: 0,
+                max: 0,
+            })
+            .collect();
+        Db::GiveAll(ops).exec(db).await.map(|_| ())
+    }
+
+    /// Takes the members, so they can't be paid twice
+    async fn members(cache: &cache::Handle, member_key: Arc<String>) -> error::Result<Vec<Member>> {
+        let members = match Cache::HashDrain(member_key).exec(cache).await? {
+            RespType::VecStringString(members) => members,
+            _ => unreachable!(),
+        };
+
+        tokio::task::spawn_blocking(move || {
+            members
+                .iter()
+                .filter_map(|(_id, member)| serde_json::from_str::<Member>(member).ok())
+                .collect()
+        })
+        .await
+        .map_err(Into::into)
+    }
+
+    async fn handle_end(
+        member_key: Arc<String>,
+        active_key: Arc<String>,
+        (win_prob_pct, member_bonus_pct, max_win_prob_pct): (u64, u64, u64),
+        payoff: i32,
+        (cache, db, lock, resp): Handles,
+    ) -> error::Result<()> {
+        let members = Self::close(&cache, &db, &lock, member_key, active_key).await?;
+
+        if members.is_empty() {
+            return Ok(());
+        }
+
+        // bigger crews have better odds
+        let win_prob_pct = win_prob_pct
+            .saturating_add(member_bonus_pct.saturating_mul(members.len() as u64 - 1))
+            .min(max_win_prob_pct)
+            .min(100);
+        let success = rand::thread_rng().gen_ratio(win_prob_pct as u32, 100);
+
+        tracing::info!(members = members.len(), win_prob_pct, success, "heist over");
+
+        let msg = if success {
+            let winners: Vec<Member> = members
+                .iter()
+                .map(|(platform, user, amount)| {
+                    (*platform, user.clone(), amount.saturating_mul(payoff))
+                })
+                .collect();
+            if let Err(e) = Self::pay(&db, &winners).await {
+                // nobody was paid, so at least give the wagers back
+                Self::pay(&db, &members).await?;
+                return Err(e);
+            }
+
+            let separator = tr("list.separator", &[]);
+            let crew = winners
+                .iter()
+                .map(|(_, user, amount)| {
+                    tr("heist.member", &[("name", &user.name), ("amount", amount)])
+                })
+                .collect::<Vec<_>>()
+                .join(&separator);
+
+            tr("heist.success", &[("crew", &crew)])
+        } else {
+            let count = members.len();
+            tr("heist.failure", &[("count", &count), ("s", &plural(count))])
+        };
+
+        Response {
+            platform: Platform::CHAT,
+            channel: crate::channel_name(),
+            corr_id: corr_id(),
+            payload: Payload::Message {
+                user: None,
+                msg: msg.into(),
+                meta: None,
+                hint: None,
+                thread: None,
+            },
+        }
+        .send(Location::Pubsub, &resp)
+        .await;
+
+        Ok(())
+    }
+
+    /// Give everyone their wagers back
+    async fn handle_cancel(
+        member_key: Arc<String>,
+        active_key: Arc<String>,
+        (cache, db, lock, _resp): Handles,
+    ) -> error::Result<()> {
+        let members = Self::close(&cache, &db, &lock, member_key, active_key).await?;
+        Self::pay(&db, &members).await?;
+
+        tracing::info!(members = members.len(), "refunded");
+
+        Ok(())
+    }
+
+    /// Take the members and end the heist. Anyone who joins while it's closing is refunded
+    async fn close(
+        cache: &cache::Handle,
+        db: &db::Handle,
+        lock: &lock::Handle,
+        member_key: Arc<String>,
+        active_key: Arc<String>,
+    ) -> error::Result<Vec<Member>> {
+        let members = Self::members(cache, member_key.clone()).await?;
+        let _ = lock.unlock(&*active_key).await;
+
+        let late = Self::members(cache, member_key).await?;
+        if !late.is_empty() {
+            tracing::info!(count = late.len(), "refunding late joiners");
+            Self::pay(db, &late).await?;
+        }
+
+        Ok(members)
+    }
+
+    /// Keep the cancel chan for heists started from now on, and refund any heist
+    /// left over from before a restart
+    pub(crate) fn init(
+        &self,
+        cancel_chan: watch::Receiver<()>,
+        cache: &cache::Handle,
+        db: &db::Handle,
+        lock: &lock::Handle,
+        resp: &RespHandle,
+    ) -> Option<()> {
+        *self.cancel_chan.write() = Some(cancel_chan);
+
+        if !self.enabled {
+            return None;
+        }
+
+        let (member_key, active_key) = Self::keys(&self.name);
+        let handles = (cache.clone(), db.clone(), lock.clone(), resp.clone());
+
+        tokio::spawn(
+            async move {
+                // only stale if nobody's running it
+                match handles.2.lock(&*active_key, 5).await {
+                    Ok(true) => {}
+                    Ok(false) => return,
+                    Err(e) => {
+                        tracing::error!("{}", e);
+                        return;
+                    }
+                }
+                if let Err(e) = Self::handle_cancel(member_key, active_key, handles).await {
+                    tracing::error!("{}", e);
+                }
+            }
+            .instrument(debug_span!("Heist cleanup")),
+        );
+
+        Some(())
+    }
+}
+
+impl CmdDesc for Heist {
+    #[inline]
+    fn platform(&self) -> Platform {
+        self.platforms
+    }
+
+    #[inline]
+    fn description(&self, platform: Platform) -> Option<String> {
+        if platform.contains(Platform::DISCORD) {
+            return Some(format!(
+                "Team up for a {}x payout, the more the merrier",
+                self.payoff
+            ));
+        }
+
+        None
+    }
+}
+
+impl Invokable for Heist {
+    fn args(&self, _platform: Platform) -> Vec<Arg> {
+        vec![Arg {
+            name: "amount".into(),
+            desc: "Amount to wager (leaving this blank means max)".into(),
+            kind: ArgKind::Integer {
+                min: Some(self.min_amount),
+                max: Some(self.max_amount),
+            },
+            optional: true,
+        }]
+    }
+}
+
+impl TryFrom<&ArgMap> for Args {
+    type Error = ArgMapError;
+
+    fn try_from(value: &ArgMap) -> Result<Self, Self::Error> {
+        let amount = match value.get("amount") {
+            Some(ArgValue::Integer(x)) => *x as i32,
+            Some(_) => return Err(ArgMapError),
+            None => -1,
+        };
+
+        Ok(Args { amount })
+    }
+}
@@ -0,0 +1,358 @@
+use super::{secret::Secret, Command, Context, ModAction, OwnedValueError, RunRes, Value};
+use crate::{
+    cache::{self, Cache, RespType},
+    db::{self, Db, Resp},
+    error::{self, Error},
+    msg::{Chat, Invocation, Platform, User},
+};
+use back_derive::command;
+use once_cell::sync::Lazy;
+use std::{str::FromStr, sync::Arc, time::Duration};
+
+static CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .unwrap()
+});
+
+/// Longest wait between attempts
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Something that happened that hooks can fire on
+#[derive(Debug, Clone)]
+pub(crate) enum HookEvent {
+    /// A command ran successfully
+    Command { name: Arc<String> },
+    /// A filter was tripped
+    ModAction {
+        action: ModAction,
+        filter: Arc<String>,
+    },
+}
+
+impl HookEvent {
+    fn kind(&self) -> EventKind {
+        match self {
+            HookEvent::Command { .. } => EventKind::Command,
+            HookEvent::ModAction { .. } => EventKind::ModAction,
+        }
+    }
+
+    /// Name of the command run or filter tripped
+    fn name(&self) -> &str {
+        match self {
+            HookEvent::Command { name } => name,
+            HookEvent::ModAction { filter, .. } => filter,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EventKind {
+    Command,
+    ModAction,
+}
+
+impl EventKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::Command => "command",
+            EventKind::ModAction => "mod_action",
+        }
+    }
+}
+
+/// Events a hook fires on, e.g. `command:give,mod_action`.
+/// A name after the colon narrows it down to one command or filter
+#[derive(Debug, Clone)]
+pub(crate) struct HookEvents {
+    src: String,
+    /// (kind, lowercased command/filter name)
+    entries: Vec<(EventKind, Option<String>)>,
+}
+
+impl HookEvents {
+    fn matches(&self, event: &HookEvent) -> bool {
+        let kind = event.kind();
+        self.entries.iter().any(|(k, name)| {
+            *k == kind
+                && name
+                    .as_ref()
+                    .is_none_or(|n| n.eq_ignore_ascii_case(event.name()))
+        })
+    }
+}
+
+impl FromStr for HookEvents {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut entries = vec![];
+
+        for entry in s.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (kind, name) = match entry.split_once(':') {
+                Some((kind, name)) if !name.trim().is_empty() => {
+                    (kind.trim(), Some(name.trim().to_lowercase()))
+                }
+                Some(_) => return Err(format!("missing name in '{}'", entry)),
+                None => (entry, None),
+            };
+            let kind = match kind.to_lowercase().as_str() {
+                "command" => EventKind::Command,
+                "mod_action" => EventKind::ModAction,
+                _ => return Err(format!("expected command or mod_action, got '{}'", kind)),
+            };
+            entries.push((kind, name));
+        }
+
+        Ok(Self {
+            src: s.to_owned(),
+            entries,
+        })
+    }
+}
+
+impl Default for HookEvents {
+    fn default() -> Self {
+        "mod_action".parse().unwrap()
+    }
+}
+
+impl TryFrom<Value> for HookEvents {
+    type Error = Error;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::String(ref s) => s
+                .parse()
+                .map_err(|e| Error::Generic(format!("invalid hook events: {}", e))),
+            _ => Err(OwnedValueError {
+                expected: "String".into(),
+                value,
+            }
+            .into()),
+        }
+    }
+}
+
+impl From<HookEvents> for Value {
+    fn from(x: HookEvents) -> Self {
+        Self::String(x.src)
+    }
+}
+
+impl VerifyConstraint for HookEvents {}
+
+#[command(locks(fired))]
+/// Post to a webhook when commands are run or filters are tripped
+pub struct Hook {
+    /// Events to fire on (e.g. command:give,mod_action, leave the name off for any)
+    #[cmd(defl("HookEvents::default()"))]
+    events: HookEvents,
+    /// Platforms
+    #[cmd(defl("Platform::CHAT"))]
+    platforms: Platform,
+    /// Webhook URL, encrypted when saved since it usually has a token in it
+    url: Secret,
+    /// JSON body ({event}, {name}, {action}, {platform}, {user}, {user_id} and {points} are filled in)
+    #[cmd(
+        def(r#"{"content": "{user} on {platform}: {event} {name} {action}"}"#),
+        constr(range = "1..=2000")
+    )]
+    body: String,
+    /// Only fire for users with at least this many points, across linked accounts (0 for anyone)
+    #[cmd(constr(pos))]
+    min_points: u64,
+    /// Only fire once per user, e.g. for point milestones. Deliveries that fail don't count
+    once_per_user: bool,
+    /// Attempts before giving up on a webhook that's down
+    #[cmd(def(3u64), constr(range = "1..=10"))]
+    attempts: u64,
+}
+
+/// Everything needed to post a hook once the event's been handled
+struct Delivery {
+    hook: Arc<String>,
+    url: String,
+    body: String,
+    attempts: u64,
+    /// (key, user id) the user was claimed under for a once per user hook, they're let go
+    /// again if it's never delivered so it can fire for them next time
+    claim: Option<(Arc<String>, Arc<String>)>,
+}
+
+impl Hook {
+    /// Hooks don't react to chat directly, see [`fire`]
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn chat(&self, _ctx: &Context<'_>, _chat: &Chat) -> error::Result<RunRes> {
+        Ok(RunRes::Noop)
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn invoke(
+        &self,
+        _ctx: &Context<'_>,
+        _invocation: &Invocation,
+    ) -> Option<RunRes> {
+        None
+    }
+
+    fn can_fire(&self, platform: Platform, event: &HookEvent) -> bool {
+        self.enabled
+            && self.url.is_set()
+            && self.platforms.contains(platform)
+            && self.events.matches(event)
+    }
+
+    fn fired_key(name: &str) -> Arc<String> {
+        Arc::new(format!("{}_{}", &*HOOK_LOCK_FIRED, name))
+    }
+
+    /// Check the user's points and whether they've been seen before, then fill in the body.
+    /// None if the hook shouldn't fire
+    async fn prepare(
+        &self,
+        db: &db::Handle,
+        cache: &cache::Handle,
+        platform: Platform,
+        user: &User,
+        event: &HookEvent,
+    ) -> error::Result<Option<Delivery>> {
+        let points = if self.min_points > 0 || self.body.contains("{points}") {
+            match Db::GetPoints(platform, user.id.clone()).exec(db).await? {
+                Resp::GetPoints(l) => l.iter().filter_map(|(_, p)| *p).map(i64::from).sum(),
+                _ => unreachable!(),
+            }
+        } else {
+            0
+        };
+
+        if points < self.min_points as i64 {
+            return Ok(None);
+        }
+
+        let url = self.url.reveal()?;
+
+        // claimed up front so events close together don't both fire it
+        let claim = if self.once_per_user {
+            let key = Self::fired_key(&self.name);
+            match Cache::HashSet(key.clone(), user.id.clone(), "1".into(), true)
+                .exec(cache)
+                .await?
+            {
+                RespType::Bool(true) => Some((key, user.id.clone())),
+                RespType::Bool(false) => return Ok(None),
+                _ => unreachable!(),
+            }
+        } else {
+            None
+        };
+
+        let action = match event {
+            HookEvent::ModAction { action, .. } => action.to_string(),
+            HookEvent::Command { .. } => String::new(),
+        };
+
+        let body = self
+            .body
+            .replace("{event}", event.kind().as_str())
+            .replace("{name}", &json_escape(event.name()))
+            .replace("{action}", &json_escape(&action))
+            .replace("{platform}", &json_escape(&platform.to_string()))
+            .replace("{user}", &json_escape(&user.name))
+            .replace("{user_id}", &json_escape(&user.id))
+            .replace("{points}", &points.to_string());
+
+        Ok(Some(Delivery {
+            hook: Arc::new(self.name.clone()),
+            url,
+            body,
+            attempts: self.attempts,
+            claim,
+        }))
+    }
+}
+
+/// Escape a value for use inside a JSON string
+fn json_escape(s: &str) -> String {
+    let quoted = serde_json::to_string(s).unwrap();
+    quoted[1..quoted.len() - 1].to_owned()
+}
+
+/// Fire every enabled hook listening for `event`, in the background
+pub(crate) fn fire(ctx: &Context<'_>, event: HookEvent) {
+    let platform = ctx.platform;
+    let listening =
+        |cmd: &Command| matches!(cmd, Command::Hook(hook) if hook.can_fire(platform, &event));
+    if !ctx.commands.iter().any(listening) {
+        return;
+    }
+
+    let commands = ctx.commands.clone();
+    let db = ctx.db.clone();
+    let cache = ctx.cache.clone();
+    let user = ctx.user.clone();
+
+    tokio::spawn(async move {
+        for cmd in commands.iter() {
+            let hook = match cmd {
+                Command::Hook(hook) if hook.can_fire(platform, &event) => hook,
+                _ => continue,
+            };
+            // each delivery retries on its own, so a dead webhook doesn't hold up the rest
+            match hook.prepare(&db, &cache, platform, &user, &event).await {
+                Ok(Some(delivery)) => {
+                    tokio::spawn(deliver(delivery, cache.clone()));
+                }
+                Ok(None) => {}
+                Err(e) => tracing::error!(hook = hook.name.as_str(), "{}", e),
+            }
+        }
+    });
+}
+
+/// POST the body, backing off and retrying on connection errors, 429s and 5xxs
+#[tracing::instrument(level = "debug", skip_all, fields(hook = delivery.hook.as_str()))]
+async fn deliver(delivery: Delivery, cache: cache::Handle) {
+    let mut backoff = Duration::from_secs(1);
+
+    for attempt in 1..=delivery.attempts {
+        let res = CLIENT
+            .post(&delivery.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(delivery.body.clone())
+            .send()
+            .await;
+
+        let retry = match res {
+            Ok(resp) if resp.status().is_success() => {
+                tracing::debug!(attempt, "delivered");
+                return;
+            }
+            Ok(resp) => {
+                let status = resp.status();
+                tracing::warn!(attempt, %status, "webhook rejected hook");
+                status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+            }
+            Err(e) => {
+                tracing::warn!(attempt, "{}", e);
+                true
+            }
+        };
+
+        if !retry || attempt == delivery.attempts {
+            break;
+        }
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+
+    tracing::error!("giving up on hook");
+
+    if let Some((key, user_id)) = delivery.claim {
+        if let Err(e) = Cache::HashDelete(key, user_id).exec(&cache).await {
+            tracing::error!("couldn't let the user go for next time: {}", e);
+        }
+    }
+}
@@ -2,6 +2,7 @@ use super::{util, Context, RunRes};
 use crate::{
     db::{hours::HoursOp, Db, Resp},
     error,
+    i18n::{plural, tr},
     msg::{Chat, Invocation, Location, Payload, Permissions, Platform, Response},
 };
 use back_derive::command;
@@ -10,6 +11,22 @@ struct Args {
     user_asked: bool,
 }
 
+/// e.g. "2 hours 5 minutes"
+fn watchtime(secs: u64) -> String {
+    let hours = secs / 3600;
+    let minutes = (secs - (hours * 3600)) / 60;
+
+    tr(
+        "hours.watchtime",
+        &[
+            ("hours", &hours),
+            ("hours_s", &plural(hours)),
+            ("minutes", &minutes),
+            ("minutes_s", &plural(minutes)),
+        ],
+    )
+}
+
 #[command(locks(rate, update_rate))]
 /// Accumulate and check watch time
 pub struct Hours {
@@ -25,10 +42,10 @@ pub struct Hours {
     #[cmd(defl("Permissions::NONE"))]
     perms: Permissions,
     /// Cooldown per user (in seconds)
-    #[cmd(constr(pos))]
+    #[cmd(constr(pos), group("Cooldowns"))]
     ratelimit_user: u64,
     /// Cooldown for adding points
-    #[cmd(constr(pos))]
+    #[cmd(constr(pos), group("Cooldowns"))]
     ratelimit_update: u64,
     /// Max. duration between messages (in seconds)
     #[cmd(defl("60*60*2"), constr(pos))]
@@ -134,7 +151,7 @@ impl Hours {
             let cooldown = self.ratelimit_update as u64;
             let user_ratelimit_key = format!("{}_{}", &*HOURS_LOCK_UPDATE_RATE, user.id);
 
-            if !ctx.lock.lock(&user_ratelimit_key, cooldown).await? {
+            if !ctx.lock.cooldown(&user_ratelimit_key, cooldown).await? {
                 tracing::info!("\x1b[33mHours update rate-limited locally\x1b[0m");
                 return Ok(RunRes::Ratelimited { global: false });
             }
@@ -157,29 +174,34 @@ impl Hours {
         tracing::info!(watch_time = new_watchtime);
 
         if user_asked {
-            // send reply
-            let new_watchtime = new_watchtime as u64;
-
-            let hours = new_watchtime / 3600;
-            let minutes = (new_watchtime - (hours * 3600)) / 60;
-
-            let msg = format!(
-                "{} hour{} {} minute{}",
-                hours,
-                if hours != 1 { "s" } else { "" },
-                minutes,
-                if minutes != 1 { "s" } else { "" },
-            );
+            let mut msg = watchtime(new_watchtime as u64);
+
+            // time in voice channels is kept separately
+            if platform == Platform::DISCORD {
+                let voice = match Db::VoiceTime(user.id.clone()).exec(ctx.db).await? {
+                    Resp::VoiceTime(secs) => secs as u64,
+                    _ => unreachable!(),
+                };
+                if voice > 0 {
+                    msg = tr(
+                        "hours.with_voice",
+                        &[("watchtime", &msg), ("voice", &watchtime(voice))],
+                    );
+                }
+            }
             tracing::debug!("{}", &msg);
 
             // send reply
             Response {
                 platform,
-                channel: &*crate::CHANNEL_NAME,
+                channel: crate::channel_name(),
+                corr_id: ctx.corr_id.clone(),
                 payload: Payload::Message {
                     user: Some((platform, user.clone())),
                     msg: msg.into(),
                     meta: ctx.meta.clone(),
+                    hint: None,
+                    thread: None,
                 },
             }
             .send(Location::Pubsub, ctx.resp)
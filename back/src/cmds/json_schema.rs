@@ -0,0 +1,316 @@
+//! The config schema as standard JSON Schema, for tools that don't want to
+//! interpret SchemaDump's (key, desc, default value, constraint, optional, hint) tuples
+
+use super::{schema, CmdType, Constraint, KeyHint, ModAction, Value};
+use crate::msg::{Permissions, Platform};
+use serde_json::{json, Map, Value as Json};
+
+const DRAFT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// Every command type's CmdDump, `[type, name, [[key, value], ..]]`, with each value in its
+/// Value tag as it's saved. Keys can come in any order and any left out keep their defaults
+pub(crate) fn json_schema(platform: Platform) -> Json {
+    let mut defs = Map::new();
+    defs.insert("Platforms".into(), platforms());
+    defs.insert("Permissions".into(), permissions());
+
+    let mut refs = vec![];
+    for (cmd, desc, cmd_type, keys) in schema(platform) {
+        let mut pairs = vec![];
+        for (key, desc, default, constraint, optional, hint) in keys {
+            let mut value = tagged(&default, &constraint);
+            if optional {
+                value = nullable(value);
+            }
+            pairs.push(with_hint(pair(&key, &desc, value), &hint));
+        }
+
+        let def = json!({
+            "type": "array",
+            "title": cmd,
+            "description": desc,
+            "x-cmd-type": cmd_type_name(&cmd_type),
+            "prefixItems": [
+                { "const": cmd, "description": "Command type" },
+                { "type": "string", "description": "Command name" },
+                {
+                    "type": "array",
+                    "description": "Config keys, any left out keep their defaults",
+                    "items": { "oneOf": pairs },
+                },
+            ],
+            "minItems": 3,
+            "items": false,
+        });
+        refs.push(json!({ "$ref": format!("#/$defs/{}", cmd) }));
+        defs.insert(cmd, def);
+    }
+
+    json!({
+        "$schema": DRAFT,
+        "title": "Command config",
+        "oneOf": refs,
+        "$defs": defs,
+    })
+}
+
+/// A `[key, value]` pair
+fn pair(key: &str, desc: &str, value: Json) -> Json {
+    json!({
+        "type": "array",
+        "description": desc,
+        "prefixItems": [{ "const": key }, value],
+        "minItems": 2,
+        "items": false,
+    })
+}
+
+fn cmd_type_name(cmd_type: &CmdType) -> &'static str {
+    match cmd_type {
+        CmdType::Command => "Command",
+        CmdType::Filter => "Filter",
+        CmdType::Timer => "Timer",
+    }
+}
+
+/// Bitflags, any combination goes
+fn platforms() -> Json {
+    let flags = [
+        ("YOUTUBE", Platform::YOUTUBE),
+        ("TWITCH", Platform::TWITCH),
+        ("DISCORD", Platform::DISCORD),
+        ("WEB", Platform::WEB),
+    ];
+    let all = flags.iter().fold(0, |all, (_, p)| all | p.bits());
+    json!({
+        "type": "integer",
+        "minimum": 0,
+        "maximum": all,
+        "description": "Any of the flags OR-ed together",
+        "x-flags": flags.iter().map(|(n, p)| (n.to_string(), json!(p.bits()))).collect::<Map<_, _>>(),
+    })
+}
+
+/// A single level, each one includes the ones below it
+fn permissions() -> Json {
+    let levels = [
+        ("NONE", Permissions::NONE),
+        ("MEMBER", Permissions::MEMBER),
+        ("MOD", Permissions::MOD),
+        ("ADMIN", Permissions::ADMIN),
+        ("OWNER", Permissions::OWNER),
+    ];
+    json!({
+        "oneOf": levels
+            .iter()
+            .map(|(n, p)| json!({ "const": p.bits(), "title": n }))
+            .collect::<Vec<_>>(),
+    })
+}
+
+/// (min, max) allowed by a range constraint
+fn bounds(constraint: &Constraint) -> Option<(i64, i64)> {
+    match constraint {
+        Constraint::RangeClosed(r) => Some((*r.start(), *r.end())),
+        Constraint::RangeHalfOpen(r) => Some((r.start, r.end - 1)),
+        _ => None,
+    }
+}
+
+fn string(constraint: &Constraint) -> Map<String, Json> {
+    let mut s = Map::new();
+    s.insert("type".into(), "string".into());
+    if matches!(constraint, Constraint::NonEmpty) {
+        s.insert("minLength".into(), 1.into());
+    }
+    if let Some((min, max)) = bounds(constraint) {
+        s.insert("minLength".into(), min.max(0).into());
+        s.insert("maxLength".into(), max.into());
+    }
+    s
+}
+
+fn integer(constraint: &Constraint) -> Map<String, Json> {
+    let mut n = Map::new();
+    n.insert("type".into(), "integer".into());
+    match constraint {
+        Constraint::Positive => {
+            n.insert("minimum".into(), 0.into());
+        }
+        Constraint::Negative => {
+            n.insert("exclusiveMaximum".into(), 0.into());
+        }
+        _ => {}
+    }
+    if let Some((min, max)) = bounds(constraint) {
+        n.insert("minimum".into(), min.into());
+        n.insert("maximum".into(), max.into());
+    }
+    n
+}
+
+/// Externally tagged, only Timeout carries a value
+fn mod_action(constraint: &Constraint) -> Map<String, Json> {
+    let mut timeout = integer(constraint);
+    timeout.entry("minimum").or_insert_with(|| 0.into());
+    let unit = [
+        ModAction::None,
+        ModAction::Warn,
+        ModAction::Remove,
+        ModAction::Kick,
+        ModAction::Ban,
+        ModAction::Purge,
+        ModAction::Untimeout,
+    ]
+    .map(|a| a.to_string());
+
+    let mut m = Map::new();
+    m.insert(
+        "oneOf".into(),
+        json!([
+            { "enum": unit },
+            {
+                "type": "object",
+                "properties": { "Timeout": timeout },
+                "required": ["Timeout"],
+                "additionalProperties": false,
+            },
+        ]),
+    );
+    m
+}
+
+/// Layout hints go on the key's pair, alongside its description
+fn with_hint(mut f: Json, hint: &KeyHint) -> Json {
+    if let Some(obj) = f.as_object_mut() {
+        if let Some(group) = &hint.group {
+            obj.insert("x-group".into(), group.as_str().into());
+        }
+        if hint.advanced {
+            obj.insert("x-advanced".into(), true.into());
+        }
+        obj.insert("x-order".into(), hint.order.into());
+    }
+    f
+}
+
+/// Optional fields can also be Value::None, and are unset by default
+fn nullable(mut f: Json) -> Json {
+    let obj = match f.as_object_mut() {
+        Some(obj) => obj,
+        None => return f,
+    };
+    let value_type = obj.remove("x-value-type");
+    obj.remove("default");
+
+    let mut n = Map::new();
+    n.insert("anyOf".into(), json!([f, unset()]));
+    if let Some(value_type) = value_type {
+        n.insert("x-value-type".into(), value_type);
+    }
+    n.insert("default".into(), "None".into());
+    n.into()
+}
+
+/// Value::None, which serializes as just its tag
+fn unset() -> Json {
+    json!({ "const": "None" })
+}
+
+/// A Value of the default's type, as an object with its tag as the only key
+fn tagged(default: &Value, constraint: &Constraint) -> Json {
+    let inner = match default {
+        Value::String(_) => string(constraint),
+        Value::Regex(_) => {
+            let mut s = string(constraint);
+            s.insert("format".into(), "regex".into());
+            s
+        }
+        Value::Secret(_) => {
+            // never sent back as it's saved, see secret::REDACTED
+            let mut s = string(constraint);
+            s.insert("writeOnly".into(), true.into());
+            s
+        }
+        Value::Number(_) => integer(constraint),
+        Value::Bool(_) => {
+            let mut b = Map::new();
+            b.insert("type".into(), "boolean".into());
+            match constraint {
+                Constraint::Positive => b.insert("const".into(), true.into()),
+                Constraint::Negative => b.insert("const".into(), false.into()),
+                _ => None,
+            };
+            b
+        }
+        Value::Platforms(_) => {
+            let mut p = Map::new();
+            p.insert("$ref".into(), "#/$defs/Platforms".into());
+            p
+        }
+        Value::Permissions(_) => {
+            let mut p = Map::new();
+            p.insert("$ref".into(), "#/$defs/Permissions".into());
+            p
+        }
+        Value::ModAction(_) => mod_action(constraint),
+        Value::List(_) => {
+            let mut l = Map::new();
+            l.insert("type".into(), "array".into());
+            l.insert("items".into(), string(constraint).into());
+            if matches!(constraint, Constraint::NonEmpty) {
+                l.insert("minItems".into(), 1.into());
+            }
+            l
+        }
+        Value::None => return unset(),
+    };
+
+    let tag = match serde_json::to_value(default) {
+        Ok(Json::Object(tagged)) => tagged.into_iter().next().map(|(tag, _)| tag),
+        _ => None,
+    };
+    let tag = match tag {
+        Some(tag) => tag,
+        None => return inner.into(),
+    };
+    let mut properties = Map::new();
+    properties.insert(tag.clone(), inner.into());
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": [tag],
+        "additionalProperties": false,
+        "x-value-type": tag,
+        "default": default,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describes_every_command_dump() {
+        let json = json_schema(Platform::all());
+        for (cmd, _, _, keys) in schema(Platform::all()) {
+            let items = &json["$defs"][&cmd]["prefixItems"];
+            assert_eq!(items[0]["const"], *cmd);
+            let pairs = items[2]["items"]["oneOf"].as_array().unwrap();
+            assert_eq!(pairs.len(), keys.len(), "{}", cmd);
+
+            for ((key, _, default, ..), pair) in keys.iter().zip(pairs) {
+                assert_eq!(pair["prefixItems"][0]["const"], **key, "{}", cmd);
+                // the value's schema is under the same tag it's saved with
+                if let Ok(Json::Object(tagged)) = serde_json::to_value(default) {
+                    let (tag, _) = tagged.into_iter().next().unwrap();
+                    assert_eq!(
+                        pair["prefixItems"][1]["x-value-type"], *tag,
+                        "{}.{}",
+                        cmd, key
+                    );
+                }
+            }
+        }
+    }
+}
@@ -1,10 +1,15 @@
-use super::{util, Arg, ArgKind, Context, Invokable, RunRes};
+use super::{util, Arg, ArgKind, ArgValue, Context, Invokable, RunRes};
 use crate::{
     cache::{Cache, RespType},
-    db::{link::LinkOp, Db, Resp},
+    db::{
+        link::{LinkOp, UnlinkOp},
+        Db, Resp,
+    },
     error::{self, Error},
+    i18n::tr,
     msg::{
-        ArgMap, Chat, Invocation, Location, Payload, Permissions, Ping, Platform, Response, User,
+        ArgMap, ArgMapError, Chat, ChatMeta, Invocation, Location, Payload, Permissions, Ping,
+        Platform, Response, User,
     },
 };
 use back_derive::command;
@@ -14,17 +19,20 @@ use rand::Rng;
 use regex::Regex;
 use std::sync::Arc;
 
-static LINK_REGEX: Lazy<Regex> =
-    Lazy::new(|| Regex::new(r"^(\S+)(?:\s([[:xdigit:]]{4}-[[:xdigit:]]{4}))?\s*").unwrap());
+static LINK_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^(\S+)(?:\s(?:(unlink)|([[:xdigit:]]{4}-[[:xdigit:]]{4})))?\s*").unwrap()
+});
 
 #[derive(Debug)]
 struct Args {
     code: Option<String>,
+    unlink: bool,
 }
 
 #[derive(Debug)]
 pub enum LinkError {
     InvalidCode,
+    TooManyAttempts,
 }
 
 impl std::fmt::Display for LinkError {
@@ -48,11 +56,14 @@ pub struct Link {
     #[cmd(defl("Permissions::NONE"))]
     perms: Permissions,
     /// Cooldown per user (in seconds)
-    #[cmd(constr(pos))]
+    #[cmd(constr(pos), group("Cooldowns"))]
     ratelimit_user: u64,
     /// Duration before code expires (in seconds)
     #[cmd(def(30_u64), constr(range = "10..=600"))]
     expiry: u64,
+    /// Invalid codes allowed per user before they have to wait out the expiry
+    #[cmd(def(5_u64), constr(range = "1..=100"))]
+    max_attempts: u64,
 }
 
 /// yt || twitch:
@@ -74,6 +85,10 @@ pub struct Link {
 /// (<DISCORD_ID>, <PLATFORM_ID>) = aussiebot_otp_<OTP>
 /// req and keys' PLATFORM_IDs match => link
 ///
+/// any platform:
+/// user: !link unlink
+/// drops the user's links (all of them if on discord)
+///
 impl Link {
     fn parse_arguments(&self, chat: &Chat) -> Option<(bool, Args)> {
         let captures = LINK_REGEX.captures(&chat.msg)?;
@@ -86,9 +101,10 @@ impl Link {
             &self.levenshtein,
         )?;
 
-        let code = captures.get(2).map(|m| m.as_str().to_owned());
+        let unlink = captures.get(2).is_some();
+        let code = captures.get(3).map(|m| m.as_str().to_uppercase());
 
-        Some((autocorrect, Args { code }))
+        Some((autocorrect, Args { code, unlink }))
     }
 
     fn can_run(&self, ctx: &Context<'_>) -> Option<()> {
@@ -185,19 +201,26 @@ impl Link {
     async fn run(&self, ctx: &Context<'_>, args: Args) -> error::Result<RunRes> {
         tracing::debug!(name = self.name.as_str(), user = ctx.user.name.as_str(), args = ?args);
 
+        if args.unlink {
+            return self.handle_unlink(ctx).await;
+        }
+
         let from_discord = ctx.platform.contains(Platform::DISCORD);
 
         match (from_discord, args.code) {
             (false, None) => {
                 /* yt: !link, tell user to dm !link on discord */
-                let msg = "DM Aussiebot with or type \"!link\" in the discord server".to_owned();
+                let msg = tr("link.dm_prompt", &[]);
                 Response {
                     platform: ctx.platform,
-                    channel: &*crate::CHANNEL_NAME,
+                    channel: crate::channel_name(),
+                    corr_id: ctx.corr_id.clone(),
                     payload: Payload::Message {
-                        user: Some((ctx.platform, ctx.user.clone())),
+                        user: ctx.reply_to(),
                         msg: msg.into(),
                         meta: ctx.meta.clone(),
+                        hint: None,
+                        thread: None,
                     },
                 }
                 .send(Location::Broadcast, ctx.resp)
@@ -207,11 +230,16 @@ impl Link {
                 // generate OTP
                 let otp_code = self.handle_gen_otp(ctx).await?;
                 // send reply with code
-                let msg = format!("Type `!link {}` within {} sec(s) in the stream's live chat to link that account with your discord",otp_code, self.expiry);
+                let msg = tr(
+                    "link.code",
+                    &[("code", &otp_code), ("expiry", &self.expiry)],
+                );
                 Response {
                     platform: ctx.platform,
-                    channel: &*crate::CHANNEL_NAME,
+                    channel: crate::channel_name(),
+                    corr_id: ctx.corr_id.clone(),
                     payload: Payload::Ping(Ping {
+                        id: None,
                         pinger: None,
                         pingee: ctx.user.clone(),
                         msg: Some(msg.into()),
@@ -223,18 +251,35 @@ impl Link {
             }
             (false, Some(code)) => {
                 // check OTP, upsert link if valid
-                let discord_id = self.handle_recv_otp(ctx, code).await?;
+                let discord_id = match self.handle_recv_otp(ctx, code).await {
+                    Ok(id) => id,
+                    Err(Error::Link(e)) => {
+                        let msg = match e {
+                            LinkError::InvalidCode => tr("link.invalid_code", &[]),
+                            LinkError::TooManyAttempts => tr("link.too_many_attempts", &[]),
+                        };
+                        self.reply(ctx, &msg).await;
+                        return Ok(match e {
+                            LinkError::InvalidCode => RunRes::InvalidArgs,
+                            LinkError::TooManyAttempts => RunRes::Ratelimited { global: false },
+                        });
+                    }
+                    Err(e) => return Err(e),
+                };
                 // send success dm
-                let msg = "Successfully linked!".to_string();
+                let msg = tr("link.linked", &[]);
                 Response {
                     platform: Platform::DISCORD,
-                    channel: &*crate::CHANNEL_NAME,
+                    channel: crate::channel_name(),
+                    corr_id: ctx.corr_id.clone(),
                     payload: Payload::Ping(Ping {
+                        id: None,
                         pinger: Some((ctx.platform, ctx.user.clone())),
                         pingee: Arc::new(User {
                             id: discord_id,
                             name: "".to_owned().into(),
                             perms: Permissions::NONE,
+                            roles: vec![],
                         }),
                         msg: Some(msg.into()),
                         meta: ctx.meta.clone(),
@@ -243,12 +288,92 @@ impl Link {
                 .send(Location::Broadcast, ctx.resp)
                 .await;
             }
-            (true, _) => { /* discord: !link <code>, ignore for now */ }
+            (true, Some(_)) => {
+                // codes are only handed out on discord, so they're redeemed elsewhere
+                self.reply(ctx, &tr("link.wrong_platform", &[])).await;
+            }
+        }
+
+        Ok(RunRes::Ok)
+    }
+
+    async fn reply(&self, ctx: &Context<'_>, msg: &str) {
+        // keep replies to interactions private
+        let payload = if matches!(ctx.meta, Some(ChatMeta::DiscordInteraction(..))) {
+            Payload::Ping(Ping {
+                id: None,
+                pinger: None,
+                pingee: ctx.user.clone(),
+                msg: Some(msg.to_owned().into()),
+                meta: ctx.meta.clone(),
+            })
+        } else {
+            Payload::Message {
+                user: ctx.reply_to(),
+                msg: msg.to_owned().into(),
+                meta: ctx.meta.clone(),
+                hint: None,
+                thread: None,
+            }
+        };
+
+        Response {
+            platform: ctx.platform,
+            channel: crate::channel_name(),
+            corr_id: ctx.corr_id.clone(),
+            payload,
         }
+        .send(Location::Broadcast, ctx.resp)
+        .await;
+    }
+
+    async fn handle_unlink(&self, ctx: &Context<'_>) -> error::Result<RunRes> {
+        let resp = Db::Unlink(UnlinkOp {
+            platform: ctx.platform,
+            id: ctx.user.id.clone(),
+        })
+        .exec(ctx.db)
+        .await?;
+
+        let removed = match resp {
+            Resp::Unlink(n) => n,
+            _ => unreachable!(),
+        };
+
+        tracing::info!(removed = removed, "unlinked");
+
+        let msg = if removed > 0 {
+            tr("link.unlinked", &[])
+        } else {
+            tr("link.nothing_to_unlink", &[])
+        };
+        self.reply(ctx, &msg).await;
 
         Ok(RunRes::Ok)
     }
 
+    /// Count an attempt at redeeming a code, erroring once the user's out of attempts
+    async fn check_attempts(&self, ctx: &Context<'_>) -> error::Result<()> {
+        if ctx.user.perms >= Permissions::MOD {
+            return Ok(());
+        }
+
+        let attempts_key = Arc::new(format!(
+            "{}_attempts_{}_{}",
+            &*LINK_LOCK_OTP, ctx.platform, ctx.user.id
+        ));
+
+        let resp = Cache::Increment(attempts_key, 1, self.expiry as usize)
+            .exec(ctx.cache)
+            .await?;
+
+        match resp {
+            RespType::U64(n) if n > self.max_attempts => Err(LinkError::TooManyAttempts.into()),
+            RespType::U64(_) => Ok(()),
+            _ => unreachable!(),
+        }
+    }
+
     async fn handle_gen_otp(&self, ctx: &Context<'_>) -> error::Result<String> {
         const MAX_RETRY: usize = 10;
 
@@ -297,6 +422,8 @@ impl Link {
         ctx: &Context<'_>,
         otp_code: String,
     ) -> error::Result<Arc<String>> {
+        self.check_attempts(ctx).await?;
+
         let otp_key = Arc::new(format!("{}_{}", &*LINK_LOCK_OTP, otp_code));
 
         // take code if it exists
@@ -330,14 +457,23 @@ impl Link {
 impl Invokable for Link {
     //fn args<'a>() -> &'a [Arg] {
     fn args(&self, platform: Platform) -> Vec<Arg> {
+        let unlink = Arg {
+            name: "unlink".into(),
+            desc: "Remove existing links instead".into(),
+            kind: ArgKind::Bool,
+            optional: true,
+        };
         match platform {
-            Platform::DISCORD => vec![],
-            _ => vec![Arg {
-                name: "code".into(),
-                desc: "Code (if any, leave blank if on Discord)".into(),
-                kind: ArgKind::String,
-                optional: true,
-            }],
+            Platform::DISCORD => vec![unlink],
+            _ => vec![
+                Arg {
+                    name: "code".into(),
+                    desc: "Code (if any, leave blank if on Discord)".into(),
+                    kind: ArgKind::String,
+                    optional: true,
+                },
+                unlink,
+            ],
         }
     }
 
@@ -349,7 +485,17 @@ impl Invokable for Link {
 impl TryFrom<&ArgMap> for Args {
     type Error = error::Error;
 
-    fn try_from(_value: &ArgMap) -> Result<Self, Self::Error> {
-        Ok(Args { code: None })
+    fn try_from(value: &ArgMap) -> Result<Self, Self::Error> {
+        let code = match value.get("code") {
+            Some(ArgValue::String(s)) => Some(s.trim().to_uppercase()),
+            Some(_) => return Err(ArgMapError.into()),
+            None => None,
+        };
+        let unlink = match value.get("unlink") {
+            Some(ArgValue::Bool(b)) => *b,
+            Some(_) => return Err(ArgMapError.into()),
+            None => false,
+        };
+        Ok(Args { code, unlink })
     }
 }
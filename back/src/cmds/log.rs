@@ -3,7 +3,7 @@ use crate::{
     cache::{self, Cache, RespType},
     db::{self, modaction::ModActionDump, Db, Resp},
     error,
-    msg::{Chat, Invocation, Platform, CHAT_PLATFORMS},
+    msg::{corr_id, Chat, Invocation, Location, Payload, Platform, Response, CHAT_PLATFORMS},
 };
 use back_derive::command;
 use once_cell::sync::Lazy;
@@ -11,7 +11,10 @@ use std::{
     sync::Arc,
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
-use tokio::sync::watch;
+use tokio::{
+    sync::{mpsc, watch},
+    task::JoinHandle,
+};
 use tracing::{debug_span, Instrument};
 
 static YT_KEY: Lazy<String> = Lazy::new(|| format!("{}_{:?}", &*LOG_LOCK_LIST, Platform::YOUTUBE));
@@ -19,6 +22,9 @@ static DISCORD_KEY: Lazy<String> =
     Lazy::new(|| format!("{}_{:?}", &*LOG_LOCK_LIST, Platform::DISCORD));
 static TWITCH_KEY: Lazy<String> =
     Lazy::new(|| format!("{}_{:?}", &*LOG_LOCK_LIST, Platform::TWITCH));
+/// Messages per LogExport chunk
+const EXPORT_CHUNK: isize = 500;
+
 static _AUSSIEBOT_KEY: Lazy<String> = Lazy::new(|| format!("{}_ab", &*LOG_LOCK_LIST));
 
 #[command(locks(list))]
@@ -28,8 +34,11 @@ pub struct Log {
     #[cmd(defl("Platform::CHAT"))]
     platforms: Platform,
     /// Duration to keep a message for (in seconds)
-    #[cmd(def(10u64), constr(range = "10..=3600"))]
+    #[cmd(def(10u64), constr(range = "10..=604800"))]
     keep_for: u64,
+    /// How often to remove expired messages (in seconds)
+    #[cmd(def(60u64), constr(range = "10..=3600"))]
+    cleanup_interval: u64,
 }
 
 impl Log {
@@ -153,21 +162,31 @@ impl Log {
         Ok(())
     }
 
-    /// Get all currently stored messages for a specific platform
+    /// Get a page of currently stored messages for a specific platform, oldest first.
+    /// `offset` skips the latest messages, so consecutive pages go back in time
     pub(crate) async fn list(
         cache: &cache::Handle,
         platform: &Platform,
+        offset: usize,
+        limit: Option<usize>,
     ) -> Option<Vec<(Platform, Vec<String>)>> {
-        // ZRANGE aussiebot_aussiegg_log_list_YOUTUBE 0 -1 WITHSCORES
+        // ZRANGE aussiebot_aussiegg_log_list_YOUTUBE -(offset+limit) -(offset+1)
         let list_keys = Self::get_keys(platform);
 
         if list_keys.is_empty() {
             return None;
         }
 
+        let stop = -isize::try_from(offset).ok()?.checked_add(1)?;
+        let start = match limit {
+            Some(0) => return Some(list_keys.iter().map(|key| (key.0, vec![])).collect()),
+            Some(limit) => stop.checked_sub(isize::try_from(limit).ok()? - 1)?,
+            None => 0,
+        };
+
         let futures = list_keys
             .iter()
-            .map(|key| Cache::Zrange(key.1.to_owned().into(), 0, -1).exec(cache));
+            .map(|key| Cache::Zrange(key.1.to_owned().into(), start, stop).exec(cache));
 
         let res = futures_util::future::join_all(futures).await;
 
@@ -186,13 +205,82 @@ impl Log {
         Some(platform_logs)
     }
 
+    /// Every stored message for a platform, oldest first. Unlike [`Log::list`], failing to read
+    /// any of them is an error rather than leaving that platform out
+    pub(crate) async fn all(
+        cache: &cache::Handle,
+        platform: &Platform,
+    ) -> error::Result<Vec<(Platform, Vec<String>)>> {
+        let mut logs = vec![];
+        for (platform, key) in Self::get_keys(platform) {
+            match Cache::Zrange(key.to_owned().into(), 0, -1)
+                .exec(cache)
+                .await?
+            {
+                RespType::VecString(list) => logs.push((platform, list)),
+                _ => unreachable!(),
+            }
+        }
+        Ok(logs)
+    }
+
+    /// Stream every stored message for a platform to `location`, oldest first
+    pub(crate) async fn export(
+        cache: &cache::Handle,
+        platform: &Platform,
+        location: Location,
+        resp: &mpsc::Sender<(Location, Response)>,
+    ) -> error::Result<()> {
+        for (platform, key) in Self::get_keys(platform) {
+            let key: Arc<String> = key.to_owned().into();
+            let mut start = 0;
+            loop {
+                let list = match Cache::Zrange(key.clone(), start, start + EXPORT_CHUNK - 1)
+                    .exec(cache)
+                    .await?
+                {
+                    RespType::VecString(list) => list,
+                    _ => unreachable!(),
+                };
+
+                // messages are stored as json, so one per line
+                let done = (list.len() as isize) < EXPORT_CHUNK;
+                let mut chunk = list.join("\n");
+                if !chunk.is_empty() {
+                    chunk.push('\n');
+                }
+
+                Response {
+                    platform,
+                    channel: crate::channel_name(),
+                    corr_id: corr_id(),
+                    payload: Payload::LogExport {
+                        platform,
+                        chunk: chunk.into(),
+                        done,
+                    },
+                }
+                .send(location.clone(), resp)
+                .await;
+
+                if done {
+                    break;
+                }
+                start += EXPORT_CHUNK;
+            }
+        }
+
+        Ok(())
+    }
+
     pub(crate) fn init(
         &self,
         cancel_chan: watch::Receiver<()>,
         cache: &cache::Handle,
         //resp: &mpsc::Sender<(Location, Response)>,
-    ) -> Option<()> {
+    ) -> Option<JoinHandle<()>> {
         let keep_for = self.keep_for as u64;
+        let interval = self.cleanup_interval.min(keep_for);
         let platforms = self.platforms;
 
         let platform_list: Vec<Platform> = CHAT_PLATFORMS
@@ -205,14 +293,14 @@ impl Log {
 
         tracing::info!(
             "\x1b[93mSpawning Log cleanup task with interval: {}s\x1b[0m",
-            keep_for
+            interval
         );
 
         // spawn task to clear messages older than keep_of (task interval keepof?)
-        tokio::task::spawn(
+        let handle = tokio::task::spawn(
             async move {
                 loop {
-                    tokio::time::sleep(Duration::from_secs(keep_for)).await;
+                    tokio::time::sleep(Duration::from_secs(interval)).await;
                     match cancel_chan.has_changed() {
                         Ok(false) => {}
                         _ => {
@@ -239,7 +327,7 @@ impl Log {
             .instrument(debug_span!("Log cleanup task")),
         );
 
-        Some(())
+        Some(handle)
     }
 
     /// Log mod actions
@@ -1,19 +1,30 @@
-use super::{util, Arg, ArgKind, ArgValue, CmdDesc, Context, Invokable, RunRes};
+use super::{
+    autocomplete::{Choices, PartialArg},
+    util, Arg, ArgKind, ArgValue, CmdDesc, Context, Invokable, RunRes,
+};
+pub use crate::proto::cmds::PendingMeme;
 use crate::{
     cache::{self, Cache, RespType},
     error,
+    i18n::{plural, tr},
     msg::{
-        ArgMap, ArgMapError, Autocomplete, Chat, ChatMeta, Invocation, InvocationKind, Payload,
-        Permissions, Ping, Platform, Response,
+        ArgMap, ArgMapError, Chat, ChatMeta, Invocation, Location, Payload, Permissions, Ping,
+        Platform, Response, User,
     },
 };
 use back_derive::command;
+use futures_util::future::{BoxFuture, FutureExt};
+use once_cell::sync::Lazy;
+use regex::Regex;
 use std::{
     sync::Arc,
     time::{SystemTime, UNIX_EPOCH},
 };
 use url::Url;
 
+static MEMEBANK_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)^(\S+)\s+(approve|reject)\s+(\d+)\s*$").unwrap());
+
 #[derive(Debug)]
 enum Args {
     Search(String),
@@ -31,12 +42,17 @@ enum Args {
         search: String,
         name: Option<String>,
     },
+    Queue,
+    Moderate {
+        id: u64,
+        approve: bool,
+    },
 }
 
 /// (link, name)
 type Item = (String, String);
 
-#[command(locks(rate, cache))]
+#[command(locks(rate, cache, queue, queue_id, rejected))]
 /// Store memes for future use
 pub struct MemeBank {
     /// Command prefix
@@ -48,16 +64,35 @@ pub struct MemeBank {
     #[cmd(defl("Permissions::NONE"))]
     perms: Permissions,
     /// Cooldown per user (in seconds)
-    #[cmd(constr(pos))]
+    #[cmd(constr(pos), group("Cooldowns"))]
     ratelimit_user: u64,
     /// Automatically add sent attachments
     #[cmd(def(true))]
     scrape_attachments: bool,
+    /// Hold memes from non-mods until a mod approves them
+    #[cmd(def(true))]
+    moderate: bool,
+    /// Put a user on the mods' watchlist once this many of their memes are rejected (0 to never)
+    #[cmd(def(3u64), constr(range = "0..=100"))]
+    rejections_note: u64,
 }
 
 impl MemeBank {
-    fn _parse_arguments(&self, _chat: &Chat) -> Option<(bool, Args)> {
-        None
+    /// Only approving and rejecting are typed in chat, the rest are slash commands
+    fn parse_arguments(&self, chat: &Chat) -> Option<(bool, Args)> {
+        let captures = MEMEBANK_REGEX.captures(&chat.msg)?;
+
+        let autocorrect = util::check_autocorrect(
+            &self.prefix,
+            &captures[1],
+            self.autocorrect,
+            &self.levenshtein,
+        )?;
+
+        let approve = captures[2].eq_ignore_ascii_case("approve");
+        let id = captures[3].parse().ok()?;
+
+        Some((autocorrect, Args::Moderate { id, approve }))
     }
 
     fn can_run(&self, ctx: &Context<'_>) -> Option<()> {
@@ -79,8 +114,19 @@ impl MemeBank {
     }
 
     #[tracing::instrument(level = "trace", skip_all)]
-    pub(super) async fn chat(&self, ctx: &Context<'_>, _chat: &Chat) -> error::Result<RunRes> {
-        if self.can_run(ctx).is_none() || !self.scrape_attachments {
+    pub(super) async fn chat(&self, ctx: &Context<'_>, chat: &Chat) -> error::Result<RunRes> {
+        if self.can_run(ctx).is_none() {
+            return Ok(RunRes::Disabled);
+        }
+
+        if let Some((autocorrect, args)) = self.parse_arguments(chat) {
+            if autocorrect {
+                return Ok(RunRes::Autocorrect(self.prefix.clone()));
+            }
+            return self.run(ctx, args).await;
+        }
+
+        if !self.scrape_attachments {
             return Ok(RunRes::Disabled);
         }
 
@@ -90,7 +136,13 @@ impl MemeBank {
         };
 
         let attachments = match meta {
-            ChatMeta::Discord2(_, _, att, _) | ChatMeta::Discord3(att, _) if !att.is_empty() => att,
+            ChatMeta::Discord2(_, _, att, _)
+            | ChatMeta::Discord3(att, _)
+            | ChatMeta::DiscordThread(_, _, _, _, att, _)
+                if !att.is_empty() =>
+            {
+                att
+            }
             _ => return Ok(RunRes::Noop),
         };
 
@@ -106,7 +158,6 @@ impl MemeBank {
                     name: name.to_owned(),
                     silent: true,
                 },
-                None,
             )
         });
 
@@ -152,7 +203,7 @@ impl MemeBank {
         //     // only trust mods and up for now
         //     Response {
         //         platform: ctx.platform,
-        //         channel: &*crate::CHANNEL_NAME,
+        //         channel: crate::channel_name(),
         //         payload: Payload::Ping(Ping {
         //             pinger: None,
         //             pingee: ctx.user.clone(),
@@ -166,7 +217,7 @@ impl MemeBank {
         //     return None;
         // }
 
-        match self.run(ctx, args, invocation.kind.as_ref()).await {
+        match self.run(ctx, args).await {
             Ok(r) => Some(r),
             Err(e) => {
                 tracing::error!("{}", e);
@@ -217,18 +268,12 @@ impl MemeBank {
         }))
     }
 
-    async fn autocomplete(
-        res: impl Iterator<Item = (isize, Item)>,
-        search: impl AsRef<str>,
-    ) -> Vec<(String, String)> {
+    /// Every meme by name, filtered by the caller
+    fn choices(res: impl Iterator<Item = (isize, Item)>) -> Choices {
         res.into_iter()
             .enumerate()
-            .filter_map(|(i, (_ts, r))| match r {
-                (_link, name) if name.starts_with(search.as_ref()) => {
-                    Some((name, i.to_string())) // value's max length is 100, so use index instead
-                }
-                _ => None,
-            })
+            // value's max length is 100, so use index instead
+            .map(|(i, (_ts, (_link, name)))| (name, i.to_string()))
             .collect()
     }
 
@@ -251,16 +296,182 @@ impl MemeBank {
         }
     }
 
-    #[tracing::instrument(skip(self, ctx), name = "MemeBank")]
-    async fn run(
+    fn bank_key(user_id: &str) -> Arc<String> {
+        Arc::new(format!("{}_{}", &*MEMEBANK_LOCK_CACHE, user_id))
+    }
+
+    fn rejected_key(user_id: &str) -> Arc<String> {
+        Arc::new(format!("{}_{}", &*MEMEBANK_LOCK_REJECTED, user_id))
+    }
+
+    async fn rejections(user_id: &str, delta: usize, cache: &cache::Handle) -> error::Result<u64> {
+        // incrementing by 0 reads the count, 0 if there's none yet
+        match Cache::Increment(Self::rejected_key(user_id), delta, 0)
+            .exec(cache)
+            .await?
+        {
+            RespType::U64(count) => Ok(count),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Queue a meme for a mod to look at
+    async fn submit(ctx: &Context<'_>, item: Item) -> error::Result<PendingMeme> {
+        let id = match Cache::Increment(MEMEBANK_LOCK_QUEUE_ID.clone().into(), 1, 0)
+            .exec(ctx.cache)
+            .await?
+        {
+            RespType::U64(id) => id,
+            _ => unreachable!(),
+        };
+
+        let (link, name) = item;
+        let pending = PendingMeme {
+            id,
+            user_id: ctx.user.id.clone(),
+            user_name: ctx.user.name.clone(),
+            link,
+            name,
+            submitted: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            rejected: 0,
+        };
+        let value = serde_json::to_string(&pending)?;
+
+        Cache::HashSet(
+            MEMEBANK_LOCK_QUEUE.clone().into(),
+            id.to_string().into(),
+            value,
+            true,
+        )
+        .exec(ctx.cache)
+        .await?;
+
+        Ok(pending)
+    }
+
+    /// Memes waiting on a mod, oldest first
+    pub(crate) async fn queue(cache: &cache::Handle) -> error::Result<Vec<PendingMeme>> {
+        let res = match Cache::HashGetAll(MEMEBANK_LOCK_QUEUE.clone().into())
+            .exec(cache)
+            .await?
+        {
+            RespType::VecStringString(list) => list,
+            _ => unreachable!(),
+        };
+
+        let mut queue = res
+            .into_iter()
+            .filter_map(
+                |(_id, value)| match serde_json::from_str::<PendingMeme>(&value) {
+                    Ok(pending) => Some(pending),
+                    Err(e) => {
+                        tracing::error!("{}", e);
+                        None
+                    }
+                },
+            )
+            .collect::<Vec<_>>();
+        queue.sort_by_key(|p| p.id);
+
+        for pending in queue.iter_mut() {
+            pending.rejected = Self::rejections(&pending.user_id, 0, cache).await?;
+        }
+
+        Ok(queue)
+    }
+
+    /// Approve or reject a queued meme, None if it's not in the queue (anymore)
+    pub(crate) async fn moderate(
         &self,
         ctx: &Context<'_>,
-        args: Args,
-        kind: Option<&InvocationKind>,
-    ) -> error::Result<RunRes> {
+        id: u64,
+        approve: bool,
+    ) -> error::Result<Option<PendingMeme>> {
+        let cache = ctx.cache;
+        let mut pending = match Self::queue(cache).await?.into_iter().find(|p| p.id == id) {
+            Some(pending) => pending,
+            None => return Ok(None),
+        };
+
+        // whoever removes it gets to decide, in case two mods get to it at once
+        match Cache::HashDelete(MEMEBANK_LOCK_QUEUE.clone().into(), id.to_string().into())
+            .exec(cache)
+            .await?
+        {
+            RespType::Bool(true) => {}
+            RespType::Bool(false) => return Ok(None),
+            _ => unreachable!(),
+        }
+
+        tracing::info!(
+            id,
+            approve,
+            user = pending.user_name.as_str(),
+            moderator = ctx.user.name.as_str(),
+            "meme moderated"
+        );
+
+        if approve {
+            let item = (pending.link.clone(), pending.name.clone());
+            Self::add(item, Self::bank_key(&pending.user_id), cache).await?;
+            return Ok(Some(pending));
+        }
+
+        pending.rejected = Self::rejections(&pending.user_id, 1, cache).await?;
+        if self.rejections_note > 0 && pending.rejected == self.rejections_note {
+            if let Err(e) = Self::watch(ctx, &pending).await {
+                tracing::error!("couldn't put {} on the watchlist: {}", pending.user_name, e);
+            }
+        }
+
+        Ok(Some(pending))
+    }
+
+    /// Note a repeat offender through ModNotes, which flags them to mods from then on.
+    /// Runs as the moderator, so it's only noted if they could've added the note themselves
+    async fn watch(ctx: &Context<'_>, pending: &PendingMeme) -> error::Result<()> {
+        let user = ArgValue::User(User {
+            id: pending.user_id.clone(),
+            name: pending.user_name.clone(),
+            perms: Permissions::NONE,
+            roles: vec![],
+        });
+        let note = tr("memebank.rejected_note", &[("count", &pending.rejected)]);
+        let add = [
+            ("user".to_owned(), user),
+            ("note".to_owned(), ArgValue::String(note)),
+        ];
+        let args = ArgMap::from([("add".to_owned(), ArgValue::SubCommand(add.into()))]);
+
+        match ctx.invoke_internal("note", args).await? {
+            Some(_) => {}
+            None => tracing::info!("no notes command the moderator can run"),
+        }
+        Ok(())
+    }
+
+    async fn ping(ctx: &Context<'_>, msg: String) {
+        Response {
+            platform: ctx.platform,
+            channel: crate::channel_name(),
+            corr_id: ctx.corr_id.clone(),
+            payload: Payload::Ping(Ping {
+                id: None,
+                pinger: None,
+                pingee: ctx.user.clone(),
+                msg: Some(msg.into()),
+                meta: ctx.meta.clone(),
+            }),
+        }
+        .send(ctx.location.clone(), ctx.resp)
+        .await;
+    }
+
+    #[tracing::instrument(skip(self, ctx), name = "MemeBank")]
+    async fn run(&self, ctx: &Context<'_>, args: Args) -> error::Result<RunRes> {
         tracing::debug!(name = self.name.as_str(), user = ctx.user.name.as_str(), args = ?args);
 
-        let key = Arc::new(format!("{}_{}", &*MEMEBANK_LOCK_CACHE, ctx.user.id));
+        let key = Self::bank_key(&ctx.user.id);
 
         match args {
             Args::Search(search) => {
@@ -268,122 +479,87 @@ impl MemeBank {
 
                 let res = Self::get_all(key, ctx.cache).await?;
 
-                match kind {
-                    Some(&InvocationKind::Autocomplete) => {
-                        // filter and get matches
-                        let choices: Vec<(String, String)> = Self::autocomplete(res, search).await;
-                        tracing::debug!(choices=?choices);
-                        Response {
-                            platform: ctx.platform,
-                            channel: &*crate::CHANNEL_NAME,
-                            payload: Payload::Autocomplete(Autocomplete {
-                                choices,
-                                meta: ctx.meta.clone(),
-                            }),
-                        }
-                        .send(ctx.location.clone(), ctx.resp)
-                        .await;
-                    }
-                    Some(_) => unimplemented!(),
-                    // implicit InvocationKind::Invoke
-                    None => {
-                        // try to parse as index into choices
-                        let (_ts, (link, name)) = Self::parse_choice(res, search)
-                            .await
-                            .unwrap_or((0, ("⚠ Not found".to_owned(), "".to_owned())));
+                // try to parse as index into choices
+                let (_ts, (link, name)) = Self::parse_choice(res, search)
+                    .await
+                    .unwrap_or((0, (tr("memebank.not_found", &[]), "".to_owned())));
 
-                        if !name.is_empty() {
-                            tracing::debug!(link=%link, name=%name, "FOUND");
-                        }
+                if !name.is_empty() {
+                    tracing::debug!(link=%link, name=%name, "FOUND");
+                }
 
-                        Response {
-                            platform: ctx.platform,
-                            channel: &*crate::CHANNEL_NAME,
-                            payload: Payload::Ping(Ping {
-                                pinger: None,
-                                pingee: ctx.user.clone(),
-                                msg: Some(link.into()),
-                                meta: ctx.meta.clone(),
-                            }),
-                        }
-                        .send(ctx.location.clone(), ctx.resp)
-                        .await;
-                    }
+                Response {
+                    platform: ctx.platform,
+                    channel: crate::channel_name(),
+                    corr_id: ctx.corr_id.clone(),
+                    payload: Payload::Ping(Ping {
+                        id: None,
+                        pinger: None,
+                        pingee: ctx.user.clone(),
+                        msg: Some(link.into()),
+                        meta: ctx.meta.clone(),
+                    }),
                 }
+                .send(ctx.location.clone(), ctx.resp)
+                .await;
             }
             Args::EditSearch { search, name } => {
                 tracing::debug!(search=%search, "edit-searching");
 
                 let res = Self::get_all(key.clone(), ctx.cache).await?;
 
-                match kind {
-                    Some(&InvocationKind::Autocomplete) => {
-                        let choices: Vec<(String, String)> = Self::autocomplete(res, search).await;
-                        tracing::debug!(choices=?choices);
-                        Response {
-                            platform: ctx.platform,
-                            channel: &*crate::CHANNEL_NAME,
-                            payload: Payload::Autocomplete(Autocomplete {
-                                choices,
-                                meta: ctx.meta.clone(),
-                            }),
-                        }
-                        .send(ctx.location.clone(), ctx.resp)
-                        .await;
-                    }
-                    Some(_) => unimplemented!(),
-                    // implicit InvocationKind::Invoke
+                // try to parse as index into choices
+                let (ts, (link, _name)) = match Self::parse_choice(res, search).await {
+                    Some(x) => x,
                     None => {
-                        // try to parse as index into choices
-                        let (ts, (link, _name)) = match Self::parse_choice(res, search).await {
-                            Some(x) => x,
-                            None => {
-                                Response {
-                                    platform: ctx.platform,
-                                    channel: &*crate::CHANNEL_NAME,
-                                    payload: Payload::Ping(Ping {
-                                        pinger: None,
-                                        pingee: ctx.user.clone(),
-                                        msg: Some("⚠ Not found".to_owned().into()),
-                                        meta: ctx.meta.clone(),
-                                    }),
-                                }
-                                .send(ctx.location.clone(), ctx.resp)
-                                .await;
-                                return Ok(RunRes::Noop);
-                            }
-                        };
-
-                        let ts = Arc::new(ts.to_string());
-
-                        // remove old key by score (ts)
-                        Cache::Zremrangebyscore(key.clone(), ts.clone(), ts.clone())
-                            .exec(ctx.cache)
-                            .await?;
-
-                        // add if applicable
-                        let msg = if let Some(name) = name {
-                            let msg = format!("Renamed `{}` to `{}`", _name, name);
-                            Self::add((link, name), key, ctx.cache).await?;
-                            msg
-                        } else {
-                            format!("Removed `{}`: {}", _name, link)
-                        };
-
                         Response {
                             platform: ctx.platform,
-                            channel: &*crate::CHANNEL_NAME,
+                            channel: crate::channel_name(),
+                            corr_id: ctx.corr_id.clone(),
                             payload: Payload::Ping(Ping {
+                                id: None,
                                 pinger: None,
                                 pingee: ctx.user.clone(),
-                                msg: Some(msg.into()),
+                                msg: Some(tr("memebank.not_found", &[]).into()),
                                 meta: ctx.meta.clone(),
                             }),
                         }
                         .send(ctx.location.clone(), ctx.resp)
                         .await;
+                        return Ok(RunRes::Noop);
                     }
+                };
+
+                let ts = Arc::new(ts.to_string());
+
+                // remove old key by score (ts)
+                Cache::Zremrangebyscore(key.clone(), ts.clone(), ts.clone())
+                    .exec(ctx.cache)
+                    .await?;
+
+                // add if applicable
+                let msg = if let Some(name) = name {
+                    let msg = tr("memebank.renamed", &[("old", &_name), ("new", &name)]);
+                    Self::add((link, name), key, ctx.cache).await?;
+                    msg
+                } else {
+                    tr("memebank.removed", &[("name", &_name), ("link", &link)])
+                };
+
+                Response {
+                    platform: ctx.platform,
+                    channel: crate::channel_name(),
+                    corr_id: ctx.corr_id.clone(),
+                    payload: Payload::Ping(Ping {
+                        id: None,
+                        pinger: None,
+                        pingee: ctx.user.clone(),
+                        msg: Some(msg.into()),
+                        meta: ctx.meta.clone(),
+                    }),
                 }
+                .send(ctx.location.clone(), ctx.resp)
+                .await;
             }
             Args::List => {
                 let res = match Cache::Zrangewithscores(key, 0, -1).exec(ctx.cache).await? {
@@ -406,20 +582,20 @@ impl MemeBank {
                         .filter_map(|r| match r {
                             Ok(Ok((_link, name))) => {
                                 count += 1;
-                                Some(format!(":small_orange_diamond: {}\n", name) /*format!("`{}`: {}\n", name, link)*/)
+                                Some(tr("memebank.list_item", &[("name", &name)]) + "\n" /*format!("`{}`: {}\n", name, link)*/)
                                 // could very easily exceed max length of 2000, so no links for now
                             }
                             _ => None,
                         })
                         .collect()
                 } else {
-                    "⚠ No items saved".to_owned()
+                    tr("memebank.empty", &[])
                 };
 
-                choices.push_str(&format!(
-                    "\n(_{} item{} in total_)",
-                    count,
-                    if count != 1 { "s" } else { "" }
+                choices.push('\n');
+                choices.push_str(&tr(
+                    "memebank.total",
+                    &[("count", &count), ("s", &plural(count))],
                 ));
                 let choices = choices;
 
@@ -427,8 +603,10 @@ impl MemeBank {
 
                 Response {
                     platform: ctx.platform,
-                    channel: &*crate::CHANNEL_NAME,
+                    channel: crate::channel_name(),
+                    corr_id: ctx.corr_id.clone(),
                     payload: Payload::Ping(Ping {
+                        id: None,
                         pinger: None,
                         pingee: ctx.user.clone(),
                         msg: Some(choices.into()),
@@ -456,19 +634,21 @@ impl MemeBank {
                         .await??;
 
                 let msg = if let Some(name) = name {
-                    let msg = format!("Renamed `{}` to `{}`", _name, name);
+                    let msg = tr("memebank.renamed", &[("old", &_name), ("new", &name)]);
 
                     Self::add((link, name), key, ctx.cache).await?;
 
                     msg
                 } else {
-                    format!("Removed `{}`: {}", _name, link)
+                    tr("memebank.removed", &[("name", &_name), ("link", &link)])
                 };
 
                 Response {
                     platform: ctx.platform,
-                    channel: &*crate::CHANNEL_NAME,
+                    channel: crate::channel_name(),
+                    corr_id: ctx.corr_id.clone(),
                     payload: Payload::Ping(Ping {
+                        id: None,
                         pinger: None,
                         pingee: ctx.user.clone(),
                         msg: Some(msg.into()),
@@ -489,23 +669,46 @@ impl MemeBank {
                         | "tenor.com"
                         | "giphy.com",
                     ) => {
-                        let msg = format!("Added `{}`: {}", name, link);
+                        if self.moderate && ctx.user.perms < Permissions::MOD {
+                            let pending = Self::submit(ctx, (link, name)).await?;
+                            let msg = tr(
+                                "memebank.submitted",
+                                &[("name", &pending.name), ("id", &pending.id)],
+                            );
+
+                            // keep the dashboard's queue current
+                            let queue = Self::queue(ctx.cache).await?;
+                            Response {
+                                platform: ctx.platform,
+                                channel: crate::channel_name(),
+                                corr_id: ctx.corr_id.clone(),
+                                payload: Payload::MemeQueue(queue),
+                            }
+                            .send(Location::Websockets(None), ctx.resp)
+                            .await;
+
+                            msg
+                        } else {
+                            let msg = tr("memebank.added", &[("name", &name), ("link", &link)]);
 
-                        Self::add((link, name), key, ctx.cache).await?;
+                            Self::add((link, name), key, ctx.cache).await?;
 
-                        msg
+                            msg
+                        }
                     }
                     _ => {
                         tracing::warn!(link=%link,"invalid link");
-                        "⚠ Invalid link".to_owned()
+                        tr("memebank.invalid_link", &[])
                     }
                 };
 
                 if !silent {
                     Response {
                         platform: ctx.platform,
-                        channel: &*crate::CHANNEL_NAME,
+                        channel: crate::channel_name(),
+                        corr_id: ctx.corr_id.clone(),
                         payload: Payload::Ping(Ping {
+                            id: None,
                             pinger: None,
                             pingee: ctx.user.clone(),
                             msg: Some(msg.into()),
@@ -516,16 +719,63 @@ impl MemeBank {
                     .await;
                 }
             }
+            Args::Queue | Args::Moderate { .. } if ctx.user.perms < Permissions::MOD => {
+                Self::ping(ctx, tr("memebank.mods_only", &[])).await;
+                return Ok(RunRes::Noop);
+            }
+            Args::Queue => {
+                let queue = Self::queue(ctx.cache).await?;
+
+                let msg = if queue.is_empty() {
+                    tr("memebank.queue_empty", &[])
+                } else {
+                    queue
+                        .iter()
+                        .map(|p| {
+                            tr(
+                                "memebank.queue_item",
+                                &[
+                                    ("id", &p.id),
+                                    ("name", &p.name),
+                                    ("link", &p.link),
+                                    ("user", &p.user_name),
+                                    ("rejected", &p.rejected),
+                                ],
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                };
+
+                Self::ping(ctx, msg).await;
+            }
+            Args::Moderate { id, approve } => {
+                let msg = match self.moderate(ctx, id, approve).await? {
+                    Some(p) if approve => tr(
+                        "memebank.approved",
+                        &[("id", &id), ("name", &p.name), ("user", &p.user_name)],
+                    ),
+                    Some(p) => tr(
+                        "memebank.rejected",
+                        &[("id", &id), ("name", &p.name), ("user", &p.user_name)],
+                    ),
+                    None => tr("memebank.not_queued", &[("id", &id)]),
+                };
+
+                Self::ping(ctx, msg).await;
+            }
             Args::Clear => {
                 Cache::Delete(key).exec(ctx.cache).await?;
 
                 Response {
                     platform: ctx.platform,
-                    channel: &*crate::CHANNEL_NAME,
+                    channel: crate::channel_name(),
+                    corr_id: ctx.corr_id.clone(),
                     payload: Payload::Ping(Ping {
+                        id: None,
                         pinger: None,
                         pingee: ctx.user.clone(),
-                        msg: Some("Items cleared".to_owned().into()),
+                        msg: Some(tr("memebank.cleared", &[]).into()),
                         meta: ctx.meta.clone(),
                     }),
                 }
@@ -609,6 +859,16 @@ impl Invokable for MemeBank {
 
         let edit_subcmds = edit_subcmds;
 
+        let id_arg = Arg {
+            name: "id".into(),
+            desc: "Queue number".into(),
+            kind: ArgKind::Integer {
+                min: Some(1),
+                max: None,
+            },
+            optional: false,
+        };
+
         vec![
             Arg {
                 name: "get".into(),
@@ -658,12 +918,50 @@ impl Invokable for MemeBank {
                 kind: ArgKind::SubCommand(vec![]),
                 optional: true,
             },
+            Arg {
+                name: "queue".into(),
+                desc: "Memes waiting on a mod (mods only)".into(),
+                kind: ArgKind::SubCommandGroup(vec![
+                    Arg {
+                        name: "list".into(),
+                        desc: "List memes waiting on a mod".into(),
+                        kind: ArgKind::SubCommand(vec![]),
+                        optional: true,
+                    },
+                    Arg {
+                        name: "approve".into(),
+                        desc: "Approve a meme".into(),
+                        kind: ArgKind::SubCommand(vec![id_arg.clone()]),
+                        optional: true,
+                    },
+                    Arg {
+                        name: "reject".into(),
+                        desc: "Reject a meme".into(),
+                        kind: ArgKind::SubCommand(vec![id_arg]),
+                        optional: true,
+                    },
+                ]),
+                optional: true,
+            },
         ]
     }
 
     fn hidden(&self, _platform: Platform) -> bool {
         true
     }
+
+    fn autocomplete<'a>(
+        &'a self,
+        ctx: &'a Context<'_>,
+        _arg: PartialArg<'a>,
+    ) -> BoxFuture<'a, error::Result<Choices>> {
+        async move {
+            // every autocompleted arg is a search
+            let res = Self::get_all(Self::bank_key(&ctx.user.id), ctx.cache).await?;
+            Ok(Self::choices(res))
+        }
+        .boxed()
+    }
 }
 
 impl TryFrom<&ArgMap> for Args {
@@ -725,6 +1023,20 @@ impl TryFrom<&ArgMap> for Args {
             })
         } else if let Some(ArgValue::SubCommand(_c)) = value.get("clear") {
             Ok(Args::Clear)
+        } else if let Some(ArgValue::SubCommand(c)) = value.get("queue") {
+            if let Some(ArgValue::SubCommand(_c)) = c.get("list") {
+                return Ok(Args::Queue);
+            }
+            let (c, approve) = match (c.get("approve"), c.get("reject")) {
+                (Some(ArgValue::SubCommand(c)), _) => (c, true),
+                (_, Some(ArgValue::SubCommand(c))) => (c, false),
+                _ => return Err(ArgMapError),
+            };
+            let id = match c.get("id") {
+                Some(ArgValue::Integer(x)) => u64::try_from(*x).map_err(|_| ArgMapError)?,
+                _ => return Err(ArgMapError),
+            };
+            Ok(Args::Moderate { id, approve })
         } else {
             Err(ArgMapError)
         }
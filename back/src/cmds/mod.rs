@@ -1,35 +1,103 @@
+pub(crate) mod alerts;
+pub(crate) mod autocomplete;
+pub(crate) mod banwave;
+pub(crate) mod blackout;
+pub(crate) mod bulk_mod;
+pub(crate) mod channel_route;
+pub(crate) mod chat_stats;
+pub(crate) mod clip;
+pub(crate) mod counter;
+pub(crate) mod custom_cmds;
+pub(crate) mod duel;
+pub(crate) mod emote_stats;
 pub(crate) mod filter;
+pub(crate) mod gamble;
 pub(crate) mod give;
+pub(crate) mod greeting;
+pub(crate) mod heist;
+pub(crate) mod hook;
 pub(crate) mod hours;
+pub(crate) mod json_schema;
 pub(crate) mod levenshtein;
 pub(crate) mod link;
 pub(crate) mod log;
 pub(crate) mod memebank;
+pub(crate) mod mod_notes;
+pub(crate) mod multiplier;
+pub(crate) mod normalize;
+pub(crate) mod perms;
 pub(crate) mod ping;
 pub(crate) mod points;
+pub(crate) mod poll;
+pub(crate) mod prediction;
+pub(crate) mod purge;
+pub(crate) mod queue;
 pub(crate) mod quote;
+pub(crate) mod raid_guard;
 pub(crate) mod reaction_role;
 pub(crate) mod regex_filter;
+pub(crate) mod role_reward;
 pub(crate) mod russian_roulette;
+pub(crate) mod secret;
+pub(crate) mod shop;
+pub(crate) mod shop_item;
+pub(crate) mod shoutout;
+pub(crate) mod slow_mode;
+pub(crate) mod stats;
 pub(crate) mod stream;
+pub(crate) mod stream_meta;
 pub(crate) mod streamlabs;
+pub(crate) mod text;
+pub(crate) mod thanks;
 pub(crate) mod timer;
+pub(crate) mod top_emotes;
 pub(crate) mod transfer;
+pub(crate) mod trivia;
+pub(crate) mod untimeout;
+pub(crate) mod uptime;
+pub(crate) mod user_cache;
 pub(crate) mod util;
-
+pub(crate) mod validate;
+pub(crate) mod voice_points;
+pub(crate) mod wordlist_filter;
+
+pub(crate) use crate::proto::cmds::ArgDump;
+pub use crate::proto::cmds::{
+    Arg, ArgKind, ArgValue, ArgsDump, ChangeKind, CmdDump, ConfigChange, ConfigDump, ConfigPatch,
+    ListPatch, ModAction, PrefixConflict, Value,
+};
 use crate::{
-    cache, db,
+    cache,
+    db::{self, users::UserKey},
     error::{self, Error},
     lock,
-    msg::{self, Location, Permissions, Platform, Response, User},
+    msg::{
+        self, discord::ChannelHint, profile::TagStates, Location, Permissions, Platform, Response,
+        User,
+    },
 };
+use futures_util::future::{BoxFuture, FutureExt};
 use levenshtein_automata::{LevenshteinAutomatonBuilder, DFA};
 use once_cell::sync::Lazy;
 use parking_lot::RwLock;
 use regex::Regex;
 use serde_derive::{Deserialize, Serialize};
-use std::{collections::HashMap, fmt::Display, path::Path, sync::Arc};
+use serde_json::value::RawValue;
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap, BTreeSet, HashMap},
+    fmt::Write as _,
+    hash::{Hash, Hasher},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 use tokio::{fs, sync::mpsc};
+use tracing::Instrument;
+
+/// Command runs cut off for going over their timeout
+pub static COMMANDS_TIMED_OUT: AtomicU64 = AtomicU64::new(0);
 
 /// cache lowercase versions of chat msg
 #[derive(Debug, Clone)]
@@ -46,14 +114,141 @@ pub(crate) struct Context<'a> {
     pub(crate) platform: msg::Platform,
     pub(crate) location: msg::Location,
     pub(crate) user: &'a Arc<msg::User>,
+    /// Set when the backend invoked the command itself, e.g. for a stream starting
+    pub(crate) actor: Option<msg::SystemActor>,
     pub(crate) meta: &'a Option<msg::ChatMeta>,
     pub(crate) db: &'a db::Handle,
     pub(crate) cache: &'a cache::Handle,
     pub(crate) lock: &'a lock::Handle,
     pub(crate) resp: &'a RespHandle, // response channel
     pub(crate) filter_cache: RwLock<Option<FilterCache>>, // cached filtercontext
+    pub(crate) commands: Arc<Vec<Command>>,
+    /// How amounts of points are written in replies
+    pub(crate) currency: Arc<msg::currency::Currency>,
+    /// Number of internal invocations leading up to this one
+    pub(crate) depth: u8,
+    /// Correlation id of the message being handled
+    pub(crate) corr_id: Option<Arc<String>>,
+}
+
+/// Stops commands from invoking each other forever
+const MAX_INVOKE_DEPTH: u8 = 4;
+
+#[derive(Debug)]
+pub struct InvokeDepthError {
+    cmd: String,
 }
 
+impl std::fmt::Display for InvokeDepthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!(
+            "max invocation depth ({}) reached invoking {}",
+            MAX_INVOKE_DEPTH, self.cmd
+        ))
+    }
+}
+
+impl Context<'_> {
+    /// Invoke another command by its unbanged prefix, so commands can reuse each other.
+    /// The user, and so their permissions, carry over. Resolves to the first result, if any command ran
+    pub(crate) fn invoke_internal<'b>(
+        &'b self,
+        cmd: &str,
+        args: msg::ArgMap,
+    ) -> BoxFuture<'b, error::Result<Option<RunRes>>> {
+        let cmd = cmd.to_owned();
+        async move {
+            if self.depth >= MAX_INVOKE_DEPTH {
+                return Err(InvokeDepthError { cmd }.into());
+            }
+
+            tracing::debug!(
+                cmd = cmd.as_str(),
+                depth = self.depth,
+                "invoking internally"
+            );
+
+            let invocation = msg::Invocation {
+                user: self.user.clone(),
+                cmd: Arc::new(cmd),
+                args,
+                meta: self.meta.clone(),
+                kind: None,
+            };
+
+            let ctx = Context {
+                platform: self.platform,
+                location: self.location.clone(),
+                user: self.user,
+                actor: self.actor,
+                meta: self.meta,
+                db: self.db,
+                cache: self.cache,
+                lock: self.lock,
+                resp: self.resp,
+                filter_cache: RwLock::new(None),
+                commands: self.commands.clone(),
+                currency: self.currency.clone(),
+                depth: self.depth + 1,
+                corr_id: self.corr_id.clone(),
+            };
+
+            let res = futures_util::future::join_all(
+                self.commands.iter().map(|c| c.invoke(&ctx, &invocation)),
+            )
+            .await;
+
+            Ok(res.into_iter().flatten().next())
+        }
+        .boxed()
+    }
+
+    /// Whether the named feature flag is on for this invocation, off if it's unset. Percentage
+    /// flags are rolled on every call
+    pub(crate) async fn flag(&self, name: &str) -> bool {
+        crate::flags::enabled(self.cache, name).await
+    }
+
+    /// Who a reply is addressed to. Nobody for system invocations, their user is a stand-in
+    pub(crate) fn reply_to(&self) -> Option<(Platform, Arc<msg::User>)> {
+        match self.actor {
+            Some(_) => None,
+            None => Some((self.platform, self.user.clone())),
+        }
+    }
+
+    /// Discord channel a reply goes to. System invocations have no chat to reply in,
+    /// so they go to the configured channel or the announce channel
+    pub(crate) fn reply_hint(&self, configured: Option<ChannelHint>) -> Option<ChannelHint> {
+        match self.actor {
+            Some(_) => configured.or(Some(ChannelHint::Announce)),
+            None => configured,
+        }
+    }
+
+    /// Look up a user on this platform by display name, `@name`, or `<@id>` on Discord.
+    /// None if they've never been seen
+    pub(crate) async fn resolve_user(
+        &self,
+        name_or_mention: &str,
+    ) -> error::Result<Option<user_cache::CachedUser>> {
+        let name_or_mention = name_or_mention.trim();
+        let key = match MENTION_REGEX.captures(name_or_mention) {
+            Some(captures) if self.platform == Platform::DISCORD => {
+                UserKey::Id(Arc::new(captures[1].to_owned()))
+            }
+            _ => match name_or_mention.trim_start_matches('@') {
+                "" => return Ok(None),
+                name => UserKey::Name(Arc::new(name.to_owned())),
+            },
+        };
+        user_cache::find(self.cache, self.db, self.platform, key).await
+    }
+}
+
+/// A Discord user mention
+static MENTION_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^<@!?(\d+)>$").unwrap());
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum CmdType {
     Command,
@@ -61,7 +256,7 @@ pub enum CmdType {
     Timer,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Constraint {
     None,
     NonEmpty,
@@ -84,6 +279,13 @@ impl<T: VerifyConstraint> VerifyConstraint for Arc<T> {
     }
 }
 
+/// Unset optional fields always pass
+impl<T: VerifyConstraint> VerifyConstraint for Option<T> {
+    fn verify(&self, constraint: Constraint) -> bool {
+        self.as_ref().is_none_or(|v| v.verify(constraint))
+    }
+}
+
 impl VerifyConstraint for String {
     fn verify(&self, constraint: Constraint) -> bool {
         match constraint {
@@ -96,6 +298,16 @@ impl VerifyConstraint for String {
     }
 }
 
+/// Constraints apply to each item, NonEmpty also requires at least one
+impl VerifyConstraint for Vec<String> {
+    fn verify(&self, constraint: Constraint) -> bool {
+        if matches!(constraint, Constraint::NonEmpty) && self.is_empty() {
+            return false;
+        }
+        self.iter().all(|s| s.verify(constraint.clone()))
+    }
+}
+
 impl VerifyConstraint for Regex {
     fn verify(&self, constraint: Constraint) -> bool {
         match constraint {
@@ -171,24 +383,6 @@ impl Default for Constraint {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-pub enum Value {
-    None,
-    String(String),
-    Number(i64),
-    Bool(bool),
-    Permissions(u32),
-    Platforms(u32),
-    Regex(String),
-    ModAction(ModAction),
-}
-
-impl Default for Value {
-    fn default() -> Self {
-        Value::None
-    }
-}
-
 // impl Value {
 //     fn verify(&self, constraint: Constraint) -> bool {
 //         println!("verify {:?}, constr: {:?}", self, constraint);
@@ -255,6 +449,7 @@ impl VerifyConstraint for Value {
                 range.contains(&(s.len() as i64))
             }
             (Value::Regex(s), Constraint::NonEmpty) => !s.is_empty(),
+            (Value::List(l), constraint) => l.verify(constraint),
             (Value::Number(n), Constraint::Positive) => *n >= 0,
             (Value::Number(n), Constraint::Negative) => *n < 0,
             (Value::Number(n), Constraint::RangeClosed(range)) => range.contains(n),
@@ -335,6 +530,23 @@ impl TryFrom<Value> for bool {
     }
 }
 
+impl TryFrom<Value> for Vec<String> {
+    type Error = OwnedValueError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::List(x) => Ok(x),
+            // fields that used to hold a single string
+            Value::String(x) if x.is_empty() => Ok(vec![]),
+            Value::String(x) => Ok(vec![x]),
+            _ => Err(OwnedValueError {
+                expected: "List".into(),
+                value,
+            }),
+        }
+    }
+}
+
 impl TryFrom<Value> for Regex {
     type Error = OwnedValueError;
 
@@ -393,6 +605,12 @@ impl From<String> for Value {
     }
 }
 
+impl From<Vec<String>> for Value {
+    fn from(x: Vec<String>) -> Self {
+        Self::List(x)
+    }
+}
+
 impl From<u64> for Value {
     fn from(x: u64) -> Self {
         Self::Number(x as i64)
@@ -435,32 +653,15 @@ impl From<ModAction> for Value {
     }
 }
 
-impl<T: Into<Value>> From<Arc<T>> for Value {
-    fn from(x: Arc<T>) -> Self {
-        x.into()
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(x: Option<T>) -> Self {
+        x.map_or(Self::None, Into::into)
     }
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Clone, Copy, Serialize, Deserialize)]
-pub enum ModAction {
-    None,
-    Warn,
-    Remove,
-    Timeout(u32),
-    Kick,
-    Ban,
-}
-
-impl Display for ModAction {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            ModAction::None => write!(f, "None"),
-            ModAction::Warn => write!(f, "Warn"),
-            ModAction::Remove => write!(f, "Remove"),
-            ModAction::Timeout(t) => write!(f, "Timeout ({}s)", t),
-            ModAction::Kick => write!(f, "Kick"),
-            ModAction::Ban => write!(f, "Ban"),
-        }
+impl<T: Into<Value>> From<Arc<T>> for Value {
+    fn from(x: Arc<T>) -> Self {
+        x.into()
     }
 }
 
@@ -471,19 +672,36 @@ pub enum RunRes {
     Filtered(ModAction),
     Autocorrect(String),
     Disabled,
-    Ratelimited { global: bool },
+    Ratelimited {
+        global: bool,
+    },
     InsufficientPerms,
     InvalidArgs,
+    /// Amount outside of what the command accepts
+    OutOfRange {
+        min: i64,
+        max: i64,
+    },
+    /// Config change made from chat, see [`ConfigEdit`]
+    EditConfig(Box<ConfigEdit>),
 }
 
-type KeySchema = (String, String, Value, Constraint); // (key, desc, default value (doubles as type, constraint)
+/// How the UI lays out a key, set with `#[cmd(group("..."), advanced, order(n))]`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyHint {
+    /// Keys sharing a group are shown together under it
+    pub group: Option<String>,
+    /// Collapsed by default
+    pub advanced: bool,
+    /// Lowest first, declaration order unless overridden
+    pub order: u32,
+}
+
+type KeySchema = (String, String, Value, Constraint, bool, KeyHint); // (key, desc, default value (doubles as type), constraint, optional, UI hint)
 
 /// (cmd, desc, keys)
 type CmdSchema = (String, String, CmdType, Vec<KeySchema>);
 pub type SchemaDump = Vec<CmdSchema>;
-/// (cmd type, cmd name, (config key-value pairs))
-pub type CmdDump = (String, String, Vec<(String, Value)>);
-
 /// wrapper to impl Debug for DFA
 pub(crate) struct DFAWrapper(DFA);
 
@@ -493,11 +711,40 @@ impl std::fmt::Debug for DFAWrapper {
     }
 }
 
-static DFA_BUILDER: Lazy<LevenshteinAutomatonBuilder> =
-    Lazy::new(|| LevenshteinAutomatonBuilder::new(2, true));
+/// Shortest prefix (without the bang) that's autocorrected, anything shorter is a typo away from most words
+const MIN_AUTOCORRECT_LEN: usize = 3;
+
+/// One per max edit distance (1..=3), each is only built once a command needs it
+static DFA_BUILDERS: [Lazy<LevenshteinAutomatonBuilder>; 3] = [
+    Lazy::new(|| LevenshteinAutomatonBuilder::new(1, true)),
+    Lazy::new(|| LevenshteinAutomatonBuilder::new(2, true)),
+    Lazy::new(|| LevenshteinAutomatonBuilder::new(3, true)),
+];
+
+impl DFAWrapper {
+    /// None if the prefix is too short to autocorrect
+    pub(crate) fn build(prefix: &str, distance: u64) -> Option<Self> {
+        if prefix.is_empty() || unbang_prefix(prefix).chars().count() < MIN_AUTOCORRECT_LEN {
+            return None;
+        }
+        let builder = &DFA_BUILDERS[distance.clamp(1, 3) as usize - 1];
+        Some(Self(builder.build_dfa(prefix)))
+    }
+}
 
 trait Commandable {
     fn schema(platform: Platform) -> CmdSchema;
+    fn prefix(&self) -> Option<&str> {
+        None
+    }
+    /// Every prefix it answers to, starting with `prefix`
+    fn prefixes(&self) -> Vec<&str> {
+        vec![]
+    }
+    /// Whether failed runs should be explained to the user
+    fn verbose_errors(&self) -> bool {
+        false
+    }
     fn args_schema(&self, _platform: Platform) -> Option<ArgDump> {
         None
     }
@@ -529,76 +776,83 @@ macro_rules! impl_cmddesc {
 }
 
 use crate::cmds::levenshtein::Levenshtein;
+use alerts::Alerts;
+use banwave::Banwave;
+use channel_route::ChannelRoute;
+use chat_stats::ChatStats;
+use clip::Clip;
+use counter::Counter;
+use custom_cmds::CustomCmds;
+use duel::Duel;
+use emote_stats::EmoteStats;
 use filter::Filter;
+use gamble::Gamble;
 use give::Give;
+use greeting::Greeting;
+use heist::Heist;
+use hook::Hook;
 use hours::Hours;
 use link::Link;
 use log::Log;
 use memebank::MemeBank;
+use mod_notes::ModNotes;
+use multiplier::Multiplier;
+use perms::Perms;
 use ping::Ping;
 use points::Points;
+use poll::Poll;
+use prediction::Prediction;
+use purge::Purge;
+use queue::Queue;
 use quote::Quote;
+use raid_guard::RaidGuard;
 use reaction_role::ReactionRole;
 use regex_filter::RegexFilter;
+use role_reward::RoleReward;
 use russian_roulette::RussianRoulette;
+use shop::Shop;
+use shop_item::ShopItem;
+use shoutout::Shoutout;
+use slow_mode::SlowMode;
+use stats::Stats;
 use stream::Stream;
+use stream_meta::StreamMeta;
 use streamlabs::Streamlabs;
+use text::Text;
+use thanks::Thanks;
 use timer::Timer;
+use top_emotes::TopEmotes;
 use transfer::Transfer;
+use trivia::Trivia;
+use untimeout::Untimeout;
+use uptime::Uptime;
+use voice_points::VoicePoints;
+use wordlist_filter::WordlistFilter;
 
 impl_cmddesc![
+    ChatStats,
+    Counter,
+    EmoteStats,
     Filter,
     Give,
+    Hook,
     Hours,
     Levenshtein,
     Link,
     Log,
+    Multiplier,
+    Perms,
     Points,
     Quote,
+    RaidGuard,
     RegexFilter,
+    SlowMode,
+    Text,
     Timer,
-    Transfer
+    Transfer,
+    WordlistFilter
 ];
 
-/// prefix, desc, hidden (ephemeral), perms, arg
-type ArgDump = (String, String, bool, Permissions, Vec<Arg>);
-pub type ArgsDump = Vec<ArgDump>;
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Arg {
-    pub kind: ArgKind,
-    pub optional: bool,
-    pub name: String,
-    pub desc: String,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum ArgKind {
-    String,
-    Integer {
-        #[serde(skip_serializing_if = "Option::is_none")]
-        min: Option<i64>,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        max: Option<i64>,
-    },
-    Bool,
-    User,
-    Platform,
-    SubCommandGroup(Vec<Arg>), // Arg should only be of ArgKind::SubCommand
-    SubCommand(Vec<Arg>),
-    Autocomplete,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub enum ArgValue {
-    String(String),
-    Integer(i64),
-    Bool(bool),
-    User(msg::User),
-    Platform(msg::Platform),
-    SubCommand(HashMap<String, ArgValue>),
-}
-
 impl From<User> for ArgValue {
     fn from(user: User) -> Self {
         Self::User(user)
@@ -627,6 +881,21 @@ trait Invokable {
     fn hidden(&self, _platform: Platform) -> bool {
         false
     }
+
+    /// Whether it can be run from a DM or whisper, with the response sent back privately
+    fn allow_dm(&self, _platform: Platform) -> bool {
+        false
+    }
+
+    /// Choices for an argument of ArgKind::Autocomplete being typed in.
+    /// Choices are filtered, sorted and capped by the caller
+    fn autocomplete<'a>(
+        &'a self,
+        ctx: &'a Context<'_>,
+        arg: autocomplete::PartialArg<'a>,
+    ) -> BoxFuture<'a, error::Result<autocomplete::Choices>> {
+        autocomplete::builtin(ctx, arg).boxed()
+    }
 }
 
 macro_rules! impl_invokable {
@@ -638,20 +907,27 @@ macro_rules! impl_invokable {
 }
 
 impl_invokable![
+    ChatStats,
+    EmoteStats,
     Filter,
+    Hook,
     Hours,
     Levenshtein,
     Log,
-    Points,
+    Multiplier,
     Quote,
+    RaidGuard,
     RegexFilter,
+    SlowMode,
     Streamlabs,
-    Timer
+    Text,
+    Timer,
+    WordlistFilter
 ];
 
 #[inline]
 /// Removes first non-alphanum char (prefix assumed to be non-empty)
-fn unbang_prefix(prefix: &str) -> &str {
+pub(crate) fn unbang_prefix(prefix: &str) -> &str {
     let has_bang = !prefix.chars().next().unwrap().is_alphanumeric();
     if has_bang {
         &prefix[1..] // strip bang if present
@@ -692,6 +968,13 @@ macro_rules! declare_cmds {
         }
       }
 
+      /// The command's type, as in its CmdDump
+      pub fn kind(&self) -> &'static str {
+        match self {
+          $(Command::$cmd(_) => stringify!($cmd) ),*,
+        }
+      }
+
       pub fn new((cmd_type, name, mut values): CmdDump) -> Option<Self> {
         match cmd_type.as_str() {
           $(
@@ -701,18 +984,77 @@ macro_rules! declare_cmds {
         }
       }
 
+      /// Cut off after the command's timeout, see [`Command::timed_out`]
       pub(crate) async fn chat(&self, ctx: &Context<'_>, chat: &msg::Chat) -> error::Result<RunRes> {
-        match self {
-          $(
-            Self::$cmd(c) => c.chat(ctx, chat).await
-          ),*
+        let run = async {
+          match self {
+            $(
+              Self::$cmd(c) => c.chat(ctx, chat)
+                .instrument(tracing::debug_span!("command", kind = stringify!($cmd), command = c.name.as_str()))
+                .await
+            ),*
+          }
+        };
+        match ctx.lock.timed(self.timeout(), run).await {
+          Some(res) => res,
+          None => Err(Error::Generic(self.timed_out())),
         }
       }
 
+      /// Cut off after the command's timeout, see [`Command::timed_out`]
       pub(crate) async fn invoke(&self, ctx: &Context<'_>, invocation: &msg::Invocation) -> Option<RunRes> {
+        let run = async {
+          match self {
+            $(
+              Self::$cmd(c) => c.invoke(ctx, invocation)
+                .instrument(tracing::debug_span!("command", kind = stringify!($cmd), command = c.name.as_str()))
+                .await
+            ),*
+          }
+        };
+        match ctx.lock.timed(self.timeout(), run).await {
+          Some(res) => res,
+          None => {
+            self.timed_out();
+            None
+          }
+        }
+      }
+
+      fn timeout(&self) -> Duration {
+        let secs = match self {
+          $(Command::$cmd(c) => c.timeout ),*,
+        };
+        match secs {
+          0 => crate::config::server().command_timeout,
+          secs => Duration::from_secs(secs),
+        }
+      }
+
+      /// Log and count a run that was cut off, returning why
+      fn timed_out(&self) -> String {
+        let timed_out = COMMANDS_TIMED_OUT.fetch_add(1, Ordering::Relaxed) + 1;
+        let why = format!("timed out after {}s", self.timeout().as_secs());
+        tracing::warn!(kind = self.kind(), name = self.name(), timed_out, "{}", why);
+        why
+      }
+
+      /// None unless this is the command being typed into, and the user can run it
+      pub(crate) async fn autocomplete(
+        &self,
+        ctx: &Context<'_>,
+        cmd: &str,
+        arg: autocomplete::PartialArg<'_>,
+      ) -> Option<error::Result<autocomplete::Choices>> {
         match self {
           $(
-            Self::$cmd(c) => c.invoke(ctx, invocation).await
+            Self::$cmd(c) => {
+              let (prefix, _, _, perms, _) = c.args_schema(ctx.platform)?;
+              if prefix != cmd || ctx.user.perms < perms {
+                return None;
+              }
+              Some(Invokable::autocomplete(c, ctx, arg).await)
+            }
           ),*
         }
       }
@@ -724,10 +1066,103 @@ macro_rules! declare_cmds {
           ),*
         }
       }
+
+      pub(crate) fn prefix(&self) -> Option<&str> {
+        match self {
+          $(
+            Self::$cmd(c) => c.prefix()
+          ),*
+        }
+      }
+
+      pub(crate) fn prefixes(&self) -> Vec<&str> {
+        match self {
+          $(
+            Self::$cmd(c) => c.prefixes()
+          ),*
+        }
+      }
+
+      pub(crate) fn allow_dm(&self, platform: Platform) -> bool {
+        match self {
+          $(
+            Self::$cmd(c) => Invokable::allow_dm(c, platform)
+          ),*
+        }
+      }
+
+      pub(crate) fn verbose_errors(&self) -> bool {
+        match self {
+          $(
+            Self::$cmd(c) => c.verbose_errors()
+          ),*
+        }
+      }
+
+      pub(crate) fn enabled(&self) -> bool {
+        match self {
+          $(Command::$cmd(c) => c.enabled ),*,
+        }
+      }
+
+      pub(crate) fn tags(&self) -> &[String] {
+        match self {
+          $(Command::$cmd(c) => &c.tags ),*,
+        }
+      }
+
+      pub(crate) fn platform(&self) -> Platform {
+        match self {
+          $(
+            Self::$cmd(c) => c.platform()
+          ),*
+        }
+      }
     }
   };
 }
 
+impl Command {
+    /// Whether the user tried to run it and only their perms stopped them. Commands turn them
+    /// away the same as when they're off, so this checks the perms before anything else would.
+    /// `tried` says whether a prefix is what they ran
+    pub(crate) fn lacks_perms(&self, ctx: &Context<'_>, tried: impl Fn(&str) -> bool) -> bool {
+        let perms = match self.args_schema(ctx.platform) {
+            Some((_, _, _, perms, _)) => perms,
+            None => return false,
+        };
+        ctx.user.perms < perms
+            && self
+                .prefixes()
+                .into_iter()
+                .filter(|prefix| !prefix.is_empty())
+                .any(tried)
+    }
+
+    /// e.g. `!give <to> <amount>`, built from the command's args
+    pub(crate) fn usage(&self, platform: Platform) -> Option<String> {
+        let prefix = self.prefix()?;
+        let (_, _, _, _, args) = self.args_schema(platform)?;
+
+        let mut usage = prefix.to_owned();
+        let mut subcommands = vec![];
+        for arg in &args {
+            match arg.kind {
+                ArgKind::SubCommand(_) | ArgKind::SubCommandGroup(_) => {
+                    subcommands.push(arg.name.as_str())
+                }
+                _ if arg.optional => write!(usage, " [{}]", arg.name).unwrap(),
+                _ => write!(usage, " <{}>", arg.name).unwrap(),
+            }
+        }
+        if !subcommands.is_empty() {
+            write!(usage, " <{}>", subcommands.join("|")).unwrap();
+        }
+
+        Some(usage)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct CommandConfig {
     pub(crate) filters: Arc<Vec<Command>>,
@@ -735,11 +1170,268 @@ pub struct CommandConfig {
     pub(crate) timers: Arc<Vec<Command>>,
 }
 
-#[derive(Serialize, Deserialize)]
-pub(crate) struct ConfigDump {
-    pub(crate) filters: Vec<CmdDump>,
-    pub(crate) commands: Vec<CmdDump>,
-    pub(crate) timers: Vec<CmdDump>,
+/// Commands added, edited or removed from chat, e.g. with !addcmd. The server applies it like a
+/// PatchConfig and replies with `done` once it's saved
+#[derive(Debug)]
+pub struct ConfigEdit {
+    pub(crate) patch: ConfigPatch,
+    pub(crate) action: &'static str,
+    pub(crate) done: String,
+}
+
+/// Keys in `new` that aren't set the same in `old`
+fn edited_keys(old: &[(String, Value)], new: &[(String, Value)]) -> Vec<String> {
+    let json = |value: &Value| serde_json::to_value(value).ok();
+    new.iter()
+        .filter(|(key, value)| {
+            old.iter().find(|(k, _)| k == key).map(|(_, v)| json(v)) != Some(json(value))
+        })
+        .map(|(key, _)| key.clone())
+        .collect()
+}
+
+impl CommandConfig {
+    /// What changed going from this config to `new`, matching commands by type and name
+    pub(crate) fn diff(&self, new: &CommandConfig) -> Vec<ConfigChange> {
+        let dumps = |list: &[Command]| {
+            list.iter()
+                .map(|cmd| {
+                    let (kind, name, values) = cmd.dump();
+                    ((kind, name), values)
+                })
+                .collect::<BTreeMap<_, _>>()
+        };
+
+        let mut changes = vec![];
+        for (list, old, new) in [
+            ("filters", &self.filters, &new.filters),
+            ("commands", &self.commands, &new.commands),
+            ("timers", &self.timers, &new.timers),
+        ] {
+            let (old, new) = (dumps(old), dumps(new));
+            let change = |(kind, name): &(String, String), change| ConfigChange {
+                list: list.to_owned(),
+                kind: kind.clone(),
+                name: name.clone(),
+                change,
+            };
+            for (id, values) in &new {
+                match old.get(id) {
+                    None => changes.push(change(id, ChangeKind::Added)),
+                    Some(old_values) => {
+                        let edited = edited_keys(old_values, values);
+                        if !edited.is_empty() {
+                            changes.push(change(id, ChangeKind::Edited(edited)));
+                        }
+                    }
+                }
+            }
+            for id in old.keys().filter(|id| !new.contains_key(*id)) {
+                changes.push(change(id, ChangeKind::Removed));
+            }
+        }
+        changes
+    }
+
+    /// Prefixes claimed by more than one enabled command, which would all reply to the same message.
+    /// Every prefix a command answers to counts, e.g. the shop's redeem prefix.
+    /// Compared without the bang, since Discord invokes commands that way
+    pub(crate) fn prefix_conflicts(&self) -> Vec<PrefixConflict> {
+        let mut by_prefix: BTreeMap<String, Vec<&Command>> = BTreeMap::new();
+        for cmd in self.commands.iter().filter(|c| c.enabled()) {
+            // once each, a command can't conflict with itself
+            let prefixes: BTreeSet<_> = cmd
+                .prefixes()
+                .into_iter()
+                .filter(|p| !p.is_empty())
+                .map(|p| unbang_prefix(p).to_lowercase())
+                .collect();
+            for prefix in prefixes {
+                by_prefix.entry(prefix).or_default().push(cmd);
+            }
+        }
+
+        let mut conflicts = vec![];
+        for (prefix, cmds) in by_prefix {
+            let mut platforms = Platform::empty();
+            let mut involved = vec![false; cmds.len()];
+            for (i, a) in cmds.iter().enumerate() {
+                for (j, b) in cmds.iter().enumerate().skip(i + 1) {
+                    let both = a.platform() & b.platform();
+                    if !both.is_empty() {
+                        platforms |= both;
+                        involved[i] = true;
+                        involved[j] = true;
+                    }
+                }
+            }
+
+            if !platforms.is_empty() {
+                conflicts.push(PrefixConflict {
+                    prefix,
+                    commands: cmds
+                        .iter()
+                        .zip(involved)
+                        .filter(|(_, involved)| *involved)
+                        .map(|(c, _)| c.name().to_owned())
+                        .collect(),
+                    platforms,
+                });
+            }
+        }
+        conflicts
+    }
+}
+
+/// The list with commands switched on or off by their tags.
+/// Turning a tag off wins over turning another of the command's tags on
+fn with_tags(list: &Arc<Vec<Command>>, states: &TagStates) -> Arc<Vec<Command>> {
+    let rebuilt = list.iter().map(|cmd| {
+        let enabled = cmd
+            .tags()
+            .iter()
+            .filter_map(|tag| states.get(tag))
+            .copied()
+            .reduce(|a, b| a && b);
+        let (kind, name, mut values) = cmd.dump();
+        if let Some(enabled) = enabled {
+            for (key, value) in values.iter_mut() {
+                if key == "enabled" {
+                    *value = Value::Bool(enabled);
+                }
+            }
+        }
+        // commands aren't Clone, so the rest are rebuilt too
+        Command::new((kind, name, values))
+    });
+    Arc::new(rebuilt.flatten().collect())
+}
+
+impl CommandConfig {
+    /// `config` with every command carrying one of the tags switched on or off
+    pub(crate) fn with_tags(&self, states: &TagStates) -> CommandConfig {
+        CommandConfig {
+            filters: with_tags(&self.filters, states),
+            commands: with_tags(&self.commands, states),
+            timers: with_tags(&self.timers, states),
+        }
+    }
+}
+
+impl CommandConfig {
+    /// Dumped with secret fields decrypted, or redacted for those who can't see them
+    pub(crate) fn dump_secrets(&self, reveal: bool) -> ConfigDump {
+        let dump = |list: &Arc<Vec<Command>>| {
+            list.iter()
+                .map(|cmd| {
+                    let mut dump = cmd.dump();
+                    secret::expose(&mut dump, reveal);
+                    dump
+                })
+                .collect()
+        };
+        ConfigDump {
+            filters: dump(&self.filters),
+            commands: dump(&self.commands),
+            timers: dump(&self.timers),
+        }
+    }
+}
+
+impl ConfigDump {
+    /// Put back the secrets the sender only saw redacted, from `config`
+    pub(crate) fn keep_redacted(&mut self, config: &CommandConfig) {
+        for (dumps, list) in [
+            (&mut self.filters, &config.filters),
+            (&mut self.commands, &config.commands),
+            (&mut self.timers, &config.timers),
+        ] {
+            dumps
+                .iter_mut()
+                .for_each(|dump| secret::keep_redacted(dump, list));
+        }
+    }
+}
+
+impl ConfigPatch {
+    /// Put back the secrets the sender only saw redacted, from `config`
+    pub(crate) fn keep_redacted(&mut self, config: &CommandConfig) {
+        for (patch, list) in [
+            (&mut self.filters, &config.filters),
+            (&mut self.commands, &config.commands),
+            (&mut self.timers, &config.timers),
+        ] {
+            patch
+                .set
+                .iter_mut()
+                .for_each(|dump| secret::keep_redacted(dump, list));
+        }
+    }
+}
+
+impl ListPatch {
+    /// The list with the patch applied, or the (type, name) of set commands whose type doesn't exist
+    fn apply(self, list: &Arc<Vec<Command>>) -> Result<Arc<Vec<Command>>, Vec<(String, String)>> {
+        if self.set.is_empty() && self.remove.is_empty() {
+            return Ok(list.clone());
+        }
+
+        let mut unknown = vec![];
+        let mut set = Vec::with_capacity(self.set.len());
+        for dump in self.set {
+            let id = (dump.0.clone(), dump.1.clone());
+            match Command::new(dump) {
+                Some(cmd) => set.push((id, Some(cmd))),
+                None => unknown.push(id),
+            }
+        }
+        if !unknown.is_empty() {
+            return Err(unknown);
+        }
+
+        let mut merged = Vec::with_capacity(list.len() + set.len());
+        for cmd in list.iter() {
+            let is_cmd = |(kind, name): &(String, String)| kind == cmd.kind() && name == cmd.name();
+            if self.remove.iter().any(is_cmd) {
+                continue;
+            }
+            match set.iter_mut().find(|(id, new)| new.is_some() && is_cmd(id)) {
+                Some((_, new)) => merged.extend(new.take()),
+                // commands aren't Clone, the untouched ones are rebuilt like a full ConfigDump would
+                None => merged.extend(Command::new(cmd.dump())),
+            }
+        }
+        // the rest are new
+        merged.extend(set.into_iter().filter_map(|(_, new)| new));
+
+        Ok(Arc::new(merged))
+    }
+}
+
+impl ConfigPatch {
+    /// `config` with the patch applied, or the (type, name) of set commands whose type doesn't exist
+    pub(crate) fn apply(
+        self,
+        config: &CommandConfig,
+    ) -> Result<CommandConfig, Vec<(String, String)>> {
+        let (filters, commands, timers) = (
+            self.filters.apply(&config.filters),
+            self.commands.apply(&config.commands),
+            self.timers.apply(&config.timers),
+        );
+        match (filters, commands, timers) {
+            (Ok(filters), Ok(commands), Ok(timers)) => Ok(CommandConfig {
+                filters,
+                commands,
+                timers,
+            }),
+            (filters, commands, timers) => Err([filters.err(), commands.err(), timers.err()]
+                .into_iter()
+                .flatten()
+                .flatten()
+                .collect()),
+        }
+    }
 }
 
 declare_cmds! {
@@ -759,15 +1451,79 @@ declare_cmds! {
   Quote,
   MemeBank,
   ReactionRole,
-  Stream
+  Stream,
+  Thanks,
+  Counter,
+  RoleReward,
+  Heist,
+  Uptime,
+  Greeting,
+  WordlistFilter,
+  Shop,
+  ShopItem,
+  Shoutout,
+  Gamble,
+  Hook,
+  Poll,
+  ModNotes,
+  Clip,
+  Stats,
+  SlowMode,
+  EmoteStats,
+  TopEmotes,
+  Trivia,
+  StreamMeta,
+  Prediction,
+  Queue,
+  Alerts,
+  Perms,
+  ChatStats,
+  Duel,
+  Multiplier,
+  RaidGuard,
+  VoicePoints,
+  Purge,
+  Banwave,
+  Untimeout,
+  ChannelRoute,
+  Text,
+  CustomCmds
 }
 
-#[derive(Debug)]
+/// (version hash, serialized schema)
+pub(crate) type VersionedSchema = (Arc<String>, Arc<RawValue>);
+
+/// The schema only changes with the binary, so it's serialized once per platform
+static SCHEMA_CACHE: Lazy<RwLock<HashMap<Platform, VersionedSchema>>> = Lazy::new(Default::default);
+
+pub(crate) fn versioned_schema(platform: Platform) -> error::Result<VersionedSchema> {
+    if let Some(cached) = SCHEMA_CACHE.read().get(&platform) {
+        return Ok(cached.clone());
+    }
+
+    let json = serde_json::to_string(&schema(platform))?;
+    let mut hasher = DefaultHasher::new();
+    json.hash(&mut hasher);
+    let version = Arc::new(format!("{:016x}", hasher.finish()));
+    let schema: Arc<RawValue> = RawValue::from_string(json)?.into();
+
+    SCHEMA_CACHE
+        .write()
+        .insert(platform, (version.clone(), schema.clone()));
+
+    Ok((version, schema))
+}
+
+#[derive(Debug, Clone, Copy)]
 pub enum ConfigFile {
     Commands,
     Filters,
     Timers,
     Users,
+    ServiceAccounts,
+    Currency,
+    Profiles,
+    PermMap,
 }
 
 pub fn config_path(cfg_type: ConfigFile) -> &'static str {
@@ -776,13 +1532,16 @@ pub fn config_path(cfg_type: ConfigFile) -> &'static str {
         ConfigFile::Filters => "filters.json",
         ConfigFile::Timers => "timers.json",
         ConfigFile::Users => "users.json",
+        ConfigFile::ServiceAccounts => "service_accounts.json",
+        ConfigFile::Currency => "currency.json",
+        ConfigFile::Profiles => "profiles.json",
+        ConfigFile::PermMap => "perm_map.json",
     }
 }
 
 #[tracing::instrument]
 pub async fn load(cfg_type: ConfigFile) -> error::Result<Vec<Command>> {
-    let contents =
-        fs::read_to_string(Path::new(&*crate::CONFIG_DIR).join(config_path(cfg_type))).await?;
+    let contents = fs::read_to_string(crate::config_dir().join(config_path(cfg_type))).await?;
 
     // deserialise
     let inflated: Vec<CmdDump> = serde_json::from_str(&contents)?;
@@ -800,12 +1559,9 @@ pub async fn load(cfg_type: ConfigFile) -> error::Result<Vec<Command>> {
 async fn save(cmds: &[Command], cfg_type: ConfigFile) -> error::Result<()> {
     let dump: Vec<CmdDump> = cmds.iter().map(|c| c.dump()).collect();
     let dump = serde_json::to_string_pretty(&dump)?;
-    fs::write(
-        Path::new(&*crate::CONFIG_DIR).join(config_path(cfg_type)),
-        dump,
-    )
-    .await
-    .map_err(Error::Io)
+    fs::write(crate::config_dir().join(config_path(cfg_type)), dump)
+        .await
+        .map_err(Error::Io)
 }
 
 pub async fn save_cmds(cmds: &[Command]) -> error::Result<()> {
@@ -819,3 +1575,99 @@ pub async fn save_filters(cmds: &[Command]) -> error::Result<()> {
 pub async fn save_timers(cmds: &[Command]) -> error::Result<()> {
     save(cmds, ConfigFile::Timers).await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Handles {
+        db: db::Handle,
+        cache: cache::Handle,
+        lock: lock::Handle,
+        resp: RespHandle,
+    }
+
+    fn handles() -> Handles {
+        Handles {
+            db: db::Handle::detached(),
+            cache: cache::Handle::memory(),
+            lock: lock::Handle::memory(),
+            resp: mpsc::channel(8).0,
+        }
+    }
+
+    fn user(perms: Permissions) -> Arc<User> {
+        Arc::new(User {
+            id: Arc::new("1".to_owned()),
+            name: Arc::new("someone".to_owned()),
+            perms,
+            roles: vec![],
+        })
+    }
+
+    /// `!note`, which only mods can run. Without args it turns them away before touching the db
+    fn notes() -> Command {
+        let mut notes = ModNotes::default();
+        notes.name = "notes".to_owned();
+        notes.enabled = true;
+        notes.timeout = 5;
+        Command::ModNotes(notes)
+    }
+
+    fn ctx<'a>(h: &'a Handles, user: &'a Arc<User>, depth: u8) -> Context<'a> {
+        static META: Option<msg::ChatMeta> = None;
+        Context {
+            platform: Platform::TWITCH,
+            location: Location::Pubsub,
+            user,
+            actor: None,
+            meta: &META,
+            db: &h.db,
+            cache: &h.cache,
+            lock: &h.lock,
+            resp: &h.resp,
+            filter_cache: RwLock::new(None),
+            commands: Arc::new(vec![notes()]),
+            currency: Default::default(),
+            depth,
+            corr_id: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn internal_invocations_run_as_the_caller() {
+        let h = handles();
+        let moderator = user(Permissions::MOD);
+        let res = ctx(&h, &moderator, 0)
+            .invoke_internal("note", msg::ArgMap::new())
+            .await;
+        assert!(matches!(res, Ok(Some(RunRes::InvalidArgs))));
+    }
+
+    #[tokio::test]
+    async fn internal_invocations_dont_gain_perms() {
+        let h = handles();
+        let chatter = user(Permissions::NONE);
+        let res = ctx(&h, &chatter, 0)
+            .invoke_internal("note", msg::ArgMap::new())
+            .await;
+        assert!(matches!(res, Ok(None)));
+    }
+
+    #[tokio::test]
+    async fn depth_limit_trips() {
+        let h = handles();
+        let moderator = user(Permissions::MOD);
+
+        // the deepest one allowed still runs
+        let res = ctx(&h, &moderator, MAX_INVOKE_DEPTH - 1)
+            .invoke_internal("note", msg::ArgMap::new())
+            .await;
+        assert!(matches!(res, Ok(Some(RunRes::InvalidArgs))));
+
+        let res = ctx(&h, &moderator, MAX_INVOKE_DEPTH)
+            .invoke_internal("note", msg::ArgMap::new())
+            .await;
+        assert!(matches!(res, Err(Error::InvokeDepth(_))));
+    }
+}
@@ -0,0 +1,488 @@
+use super::{util, Arg, ArgKind, ArgValue, CmdDesc, Context, Invokable, RunRes};
+use crate::{
+    cache::{Cache, RespType},
+    db::{
+        self,
+        notes::{ModNote, NoteOp, NoteTarget},
+        Db, Resp,
+    },
+    error,
+    i18n::{plural, tr},
+    msg::{
+        ArgMap, ArgMapError, Chat, Invocation, Location, Payload, Permissions, Platform, Response,
+    },
+};
+use back_derive::command;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use regex::Regex;
+use std::{collections::HashSet, sync::Arc};
+use tracing::{debug_span, Instrument};
+
+static NOTE_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(\S+)\s+(add|list|remove)\s+(.+?)\s*$").unwrap());
+
+/// `@user "note"`, names can have spaces if the note's quoted
+static QUOTED_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r#"^@?(.+?)\s+"([^"]+)"$"#).unwrap());
+
+/// `@user note`
+static UNQUOTED_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^@?(\S+)\s+(.+)$").unwrap());
+
+#[derive(Debug)]
+enum Args {
+    Add { target: NoteTarget, note: String },
+    List(NoteTarget),
+    Remove(i64),
+}
+
+/// (platform, id) of users with notes on them
+type Watched = Arc<RwLock<HashSet<(Platform, String)>>>;
+
+#[command(locks(alerted))]
+/// Let mods keep notes on users, and flag them when those users chat
+pub struct ModNotes {
+    /// Command prefix
+    #[cmd(def("!note"), constr(non_empty))]
+    prefix: String,
+    /// Autocorrect prefix
+    autocorrect: bool,
+    /// Platforms
+    #[cmd(defl("Platform::CHAT"))]
+    platforms: Platform,
+    /// Permissions
+    #[cmd(defl("Permissions::MOD"))]
+    perms: Permissions,
+    /// Min time between flagging the same user (in seconds)
+    #[cmd(def(600u64), constr(range = "1..=86400"))]
+    alert_cooldown: u64,
+    #[cmd(skip)]
+    watched: Watched,
+}
+
+impl ModNotes {
+    fn parse_arguments(&self, chat: &Chat) -> Option<(bool, Args)> {
+        let captures = NOTE_REGEX.captures(&chat.msg)?;
+
+        // check command prefix
+        let autocorrect = util::check_autocorrect(
+            &self.prefix,
+            &captures[1],
+            self.autocorrect,
+            &self.levenshtein,
+        )?;
+
+        let rest = &captures[3];
+        let args = match &captures[2] {
+            "add" => {
+                let captures = QUOTED_REGEX
+                    .captures(rest)
+                    .or_else(|| UNQUOTED_REGEX.captures(rest))?;
+                Args::Add {
+                    target: NoteTarget::Name(Arc::new(captures[1].to_owned())),
+                    note: captures[2].trim().to_owned(),
+                }
+            }
+            "list" => Args::List(NoteTarget::Name(Arc::new(
+                rest.trim_start_matches('@').to_owned(),
+            ))),
+            _ => Args::Remove(rest.trim_start_matches('#').parse().ok()?),
+        };
+
+        Some((autocorrect, args))
+    }
+
+    fn can_run(&self, ctx: &Context<'_>) -> Option<()> {
+        if !self.enabled {
+            return None;
+        }
+
+        // check if platform is applicable
+        if !self.platforms.contains(ctx.platform) {
+            return None;
+        }
+
+        // check perms
+        if ctx.user.perms < self.perms {
+            return None;
+        }
+
+        Some(())
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn chat(&self, ctx: &Context<'_>, chat: &Chat) -> error::Result<RunRes> {
+        if !self.enabled || !self.platforms.contains(ctx.platform) {
+            return Ok(RunRes::Disabled);
+        }
+
+        if let Err(e) = self.alert(ctx, chat).await {
+            tracing::error!("{}", e);
+        }
+
+        if self.can_run(ctx).is_none() {
+            return Ok(RunRes::Disabled);
+        }
+
+        let (autocorrect, args) = match self.parse_arguments(chat) {
+            Some(t) => t,
+            None if util::starts_with_prefix(&self.prefix, &chat.msg) => {
+                return Ok(RunRes::InvalidArgs)
+            }
+            None => return Ok(RunRes::Noop),
+        };
+
+        if autocorrect {
+            return Ok(RunRes::Autocorrect(self.prefix.clone()));
+        }
+
+        self.run(ctx, args).await
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn invoke(
+        &self,
+        ctx: &Context<'_>,
+        invocation: &Invocation,
+    ) -> Option<RunRes> {
+        self.can_run(ctx)?;
+
+        super::check_invoke_prefix(&self.prefix, &invocation.cmd)?;
+
+        let args = match Args::try_from(&invocation.args) {
+            Ok(args) => args,
+            Err(_) => return Some(RunRes::InvalidArgs),
+        };
+
+        match self.run(ctx, args).await {
+            Ok(r) => Some(r),
+            Err(e) => {
+                tracing::error!("{}", e);
+                None
+            }
+        }
+    }
+
+    async fn reply(ctx: &Context<'_>, msg: String) {
+        Response {
+            platform: ctx.platform,
+            channel: crate::channel_name(),
+            corr_id: ctx.corr_id.clone(),
+            payload: Payload::Message {
+                user: ctx.reply_to(),
+                msg: msg.into(),
+                meta: ctx.meta.clone(),
+                hint: None,
+                thread: None,
+            },
+        }
+        .send(Location::Pubsub, ctx.resp)
+        .await;
+    }
+
+    async fn notes(db: &db::Handle, args: NoteOp) -> error::Result<Vec<ModNote>> {
+        match Db::ModNotes(args).exec(db).await? {
+            Resp::Notes(notes) => Ok(notes),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Show mods the notes on a watched user who's chatting, at most once per cooldown
+    async fn alert(&self, ctx: &Context<'_>, chat: &Chat) -> error::Result<()> {
+        let member = (ctx.platform, ctx.user.id.to_string());
+        if !self.watched.read().contains(&member) {
+            return Ok(());
+        }
+
+        let key = Arc::new(format!(
+            "{}_{}_{}:{}",
+            &*MODNOTES_LOCK_ALERTED, self.name, ctx.platform, ctx.user.id
+        ));
+        match Cache::Set(
+            key,
+            "1".to_owned().into(),
+            self.alert_cooldown as usize,
+            true,
+        )
+        .exec(ctx.cache)
+        .await?
+        {
+            RespType::Bool(true) => {}
+            RespType::Bool(false) => return Ok(()),
+            _ => unreachable!(),
+        }
+
+        let target = NoteTarget::User(ctx.user.id.clone(), ctx.user.name.clone());
+        let notes = Self::notes(ctx.db, NoteOp::List(ctx.platform, target)).await?;
+        if notes.is_empty() {
+            // removed elsewhere since the watchlist was loaded
+            self.watched.write().remove(&member);
+            return Ok(());
+        }
+
+        tracing::info!(
+            user = ctx.user.name.as_str(),
+            notes = notes.len(),
+            "watched user chatted"
+        );
+
+        Response {
+            platform: ctx.platform,
+            channel: crate::channel_name(),
+            corr_id: ctx.corr_id.clone(),
+            payload: Payload::WatchlistAlert {
+                platform: ctx.platform,
+                user: ctx.user.clone(),
+                msg: chat.msg.clone(),
+                notes,
+            },
+        }
+        .send(Location::Websockets(None), ctx.resp)
+        .await;
+
+        Ok(())
+    }
+
+    /// Look names up through the user cache, the db still picks up any it doesn't know
+    async fn resolve(ctx: &Context<'_>, target: NoteTarget) -> error::Result<NoteTarget> {
+        Ok(match target {
+            NoteTarget::Name(name) => match ctx.resolve_user(&name).await? {
+                Some(user) => NoteTarget::User(user.id, user.name),
+                None => NoteTarget::Name(name),
+            },
+            target => target,
+        })
+    }
+
+    #[tracing::instrument(level = "trace", skip_all, name = "ModNotes")]
+    async fn run(&self, ctx: &Context<'_>, args: Args) -> error::Result<RunRes> {
+        tracing::debug!(name = self.name.as_str(), user = ctx.user.name.as_str(), args = ?args);
+
+        match args {
+            Args::Add { target, note } => {
+                let target = Self::resolve(ctx, target).await?;
+                let name = match &target {
+                    NoteTarget::Name(name) | NoteTarget::User(_, name) => name.clone(),
+                };
+                let op = NoteOp::Add {
+                    platform: ctx.platform,
+                    target,
+                    note,
+                    author: ctx.user.name.clone(),
+                };
+                let msg = match Self::notes(ctx.db, op).await?.pop() {
+                    Some(added) => {
+                        self.watched
+                            .write()
+                            .insert((added.platform, added.user_id.clone()));
+                        let user = added.user_name.as_deref().unwrap_or(&added.user_id);
+                        tr("notes.added", &[("id", &added.id), ("user", &user)])
+                    }
+                    None => tr("notes.unknown_user", &[("user", &name)]),
+                };
+                Self::reply(ctx, msg).await;
+            }
+            Args::List(target) => {
+                let target = Self::resolve(ctx, target).await?;
+                let name = match &target {
+                    NoteTarget::Name(name) | NoteTarget::User(_, name) => name.clone(),
+                };
+                let notes = Self::notes(ctx.db, NoteOp::List(ctx.platform, target)).await?;
+                let count = notes.len();
+
+                let msg = if count == 0 {
+                    tr("notes.none", &[("user", &name)])
+                } else if ctx.platform == Platform::DISCORD {
+                    let separator = tr("list.separator", &[]);
+                    let entries = notes
+                        .iter()
+                        .map(|n| {
+                            tr(
+                                "notes.entry",
+                                &[("id", &n.id), ("note", &n.note), ("author", &n.author)],
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                        .join(&separator);
+                    tr("notes.list", &[("user", &name), ("notes", &entries)])
+                } else {
+                    // stream chat is public, the notes themselves only go to the web UI
+                    tr(
+                        "notes.sent",
+                        &[("user", &name), ("count", &count), ("s", &plural(count))],
+                    )
+                };
+
+                if count > 0 {
+                    Response {
+                        platform: ctx.platform,
+                        channel: crate::channel_name(),
+                        corr_id: ctx.corr_id.clone(),
+                        payload: Payload::ModNotes {
+                            platform: ctx.platform,
+                            notes,
+                        },
+                    }
+                    .send(Location::Websockets(None), ctx.resp)
+                    .await;
+                }
+                Self::reply(ctx, msg).await;
+            }
+            Args::Remove(id) => {
+                let msg = match Self::notes(ctx.db, NoteOp::Remove(id)).await?.pop() {
+                    Some(removed) => {
+                        let target = NoteTarget::User(
+                            Arc::new(removed.user_id.clone()),
+                            Arc::new(removed.user_name.clone().unwrap_or_default()),
+                        );
+                        // unwatch them once their last note is gone
+                        if Self::notes(ctx.db, NoteOp::List(removed.platform, target))
+                            .await?
+                            .is_empty()
+                        {
+                            self.watched
+                                .write()
+                                .remove(&(removed.platform, removed.user_id.clone()));
+                        }
+                        let user = removed.user_name.as_deref().unwrap_or(&removed.user_id);
+                        tr("notes.removed", &[("id", &id), ("user", &user)])
+                    }
+                    None => tr("notes.not_found", &[("id", &id)]),
+                };
+                Self::reply(ctx, msg).await;
+            }
+        }
+
+        Ok(RunRes::Ok)
+    }
+
+    /// Load everyone with notes on them into the watchlist
+    pub(crate) fn init(&self, db: &db::Handle) -> Option<()> {
+        if !self.enabled {
+            return None;
+        }
+
+        let watched = self.watched.clone();
+        let db = db.clone();
+
+        tokio::spawn(
+            async move {
+                match Db::Watchlist.exec(&db).await {
+                    Ok(Resp::Watchlist(users)) => {
+                        tracing::debug!(users = users.len(), "loaded watchlist");
+                        watched.write().extend(users);
+                    }
+                    Ok(_) => unreachable!(),
+                    Err(e) => tracing::error!("{}", e),
+                }
+            }
+            .instrument(debug_span!("ModNotes watchlist")),
+        );
+
+        Some(())
+    }
+}
+
+impl CmdDesc for ModNotes {
+    #[inline]
+    fn platform(&self) -> Platform {
+        self.platforms
+    }
+
+    #[inline]
+    fn description(&self, platform: Platform) -> Option<String> {
+        if platform.contains(Platform::DISCORD) {
+            return Some("Keep notes on users, mods are shown them when the user chats".into());
+        }
+
+        None
+    }
+}
+
+impl Invokable for ModNotes {
+    fn args(&self, _platform: Platform) -> Vec<Arg> {
+        vec![
+            Arg {
+                name: "add".into(),
+                desc: "Add a note".into(),
+                kind: ArgKind::SubCommand(vec![
+                    Arg {
+                        name: "user".into(),
+                        desc: "User".into(),
+                        kind: ArgKind::User,
+                        optional: false,
+                    },
+                    Arg {
+                        name: "note".into(),
+                        desc: "Note".into(),
+                        kind: ArgKind::String,
+                        optional: false,
+                    },
+                ]),
+                optional: true,
+            },
+            Arg {
+                name: "list".into(),
+                desc: "List notes on a user".into(),
+                kind: ArgKind::SubCommand(vec![Arg {
+                    name: "user".into(),
+                    desc: "User".into(),
+                    kind: ArgKind::User,
+                    optional: false,
+                }]),
+                optional: true,
+            },
+            Arg {
+                name: "remove".into(),
+                desc: "Remove a note".into(),
+                kind: ArgKind::SubCommand(vec![Arg {
+                    name: "id".into(),
+                    desc: "Note number".into(),
+                    kind: ArgKind::Integer {
+                        min: Some(1),
+                        max: None,
+                    },
+                    optional: false,
+                }]),
+                optional: true,
+            },
+        ]
+    }
+
+    fn hidden(&self, _platform: Platform) -> bool {
+        true
+    }
+}
+
+fn user_target(args: &ArgMap) -> Result<NoteTarget, ArgMapError> {
+    match args.get("user") {
+        // TODO: dont assume platform
+        Some(ArgValue::User(u)) => Ok(NoteTarget::User(u.id.clone(), u.name.clone())),
+        _ => Err(ArgMapError),
+    }
+}
+
+impl TryFrom<&ArgMap> for Args {
+    type Error = ArgMapError;
+
+    fn try_from(value: &ArgMap) -> Result<Self, Self::Error> {
+        if let Some(ArgValue::SubCommand(c)) = value.get("add") {
+            let note = match c.get("note") {
+                Some(ArgValue::String(x)) if !x.trim().is_empty() => x.trim().to_owned(),
+                _ => return Err(ArgMapError),
+            };
+            Ok(Args::Add {
+                target: user_target(c)?,
+                note,
+            })
+        } else if let Some(ArgValue::SubCommand(c)) = value.get("list") {
+            Ok(Args::List(user_target(c)?))
+        } else if let Some(ArgValue::SubCommand(c)) = value.get("remove") {
+            match c.get("id") {
+                Some(ArgValue::Integer(id)) => Ok(Args::Remove(*id)),
+                _ => Err(ArgMapError),
+            }
+        } else {
+            Err(ArgMapError)
+        }
+    }
+}
@@ -0,0 +1,449 @@
+use super::{util, Context, RunRes};
+pub use crate::proto::cmds::Active;
+use crate::{
+    cache::{self, Cache, RespType},
+    error::{self, Error},
+    i18n::{plural, tr},
+    msg::{
+        currency::Currency, Chat, Invocation, Location, Payload, Permissions, Platform, Response,
+    },
+};
+use back_derive::command;
+use bb8_redis::redis;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Multipliers are re-read from redis this often, to pick up ones started by other instances
+const REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Highest factor the dashboard can set, chat is held to the command's max_factor
+pub(crate) const MAX_FACTOR: f64 = 10.0;
+
+/// Longest the dashboard can run one for (in seconds), chat is held to the command's max_hours
+pub(crate) const MAX_SECS: u64 = 24 * 60 * 60;
+
+/// How long the bonus tally outlives the multiplier, so it can still be looked up
+const BONUS_TTL: u64 = 60 * 60;
+
+/// Platforms a multiplier can be on
+const PLATFORMS: [Platform; 3] = [Platform::YOUTUBE, Platform::TWITCH, Platform::DISCORD];
+
+/// Bonus points multipliers have granted since startup
+pub static BONUS_POINTS_GRANTED: AtomicU64 = AtomicU64::new(0);
+
+/// (fetched, multiplier)
+type Cached = (Instant, Option<Active>);
+
+/// Per platform, the multiplier last read from redis
+static ACTIVE: Lazy<RwLock<HashMap<Platform, Cached>>> = Lazy::new(Default::default);
+
+impl Active {
+    fn remaining_secs(&self) -> u64 {
+        self.until.saturating_sub(now_secs())
+    }
+
+    /// e.g. "1 hour 5 minutes", rounded up so it doesn't say 0 minutes while it's still on
+    pub(crate) fn remaining(&self) -> String {
+        let minutes = self.remaining_secs().div_ceil(60);
+        let (hours, minutes) = (minutes / 60, minutes % 60);
+        tr(
+            "uptime.elapsed",
+            &[
+                ("hours", &hours),
+                ("hours_s", &plural(hours)),
+                ("minutes", &minutes),
+                ("minutes_s", &plural(minutes)),
+            ],
+        )
+    }
+
+    /// e.g. "2x" or "1.5x"
+    pub(crate) fn factor(&self) -> String {
+        format!("{}x", self.factor)
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn key(platform: Platform) -> Arc<String> {
+    Arc::new(format!(
+        "aussiebot!{}!multiplier!{}",
+        crate::channel_name(),
+        platform
+    ))
+}
+
+fn bonus_key(platform: Platform) -> Arc<String> {
+    Arc::new(format!(
+        "aussiebot!{}!multiplier!{}!bonus",
+        crate::channel_name(),
+        platform
+    ))
+}
+
+fn platforms(platforms: Platform) -> impl Iterator<Item = Platform> {
+    PLATFORMS
+        .into_iter()
+        .filter(move |p| platforms.contains(*p))
+}
+
+async fn get_cached(cache: &cache::Handle, key: Arc<String>) -> error::Result<Option<String>> {
+    match Cache::Get(key).exec(cache).await {
+        Ok(RespType::String(s)) => Ok(Some(s)),
+        Ok(_) => unreachable!(),
+        Err(Error::Redis(e)) if e.kind() == redis::ErrorKind::TypeError => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// The multiplier running on the platform, if any
+pub(crate) async fn get(
+    cache: &cache::Handle,
+    platform: Platform,
+) -> error::Result<Option<Active>> {
+    if let Some((fetched, active)) = ACTIVE.read().get(&platform) {
+        if fetched.elapsed() < REFRESH_INTERVAL {
+            return Ok(active.clone().filter(|a| a.until > now_secs()));
+        }
+    }
+
+    let active = get_cached(cache, key(platform))
+        .await?
+        .and_then(|s| serde_json::from_str::<Active>(&s).ok())
+        .filter(|a| a.until > now_secs());
+
+    ACTIVE
+        .write()
+        .insert(platform, (Instant::now(), active.clone()));
+
+    Ok(active)
+}
+
+/// The first multiplier running on any of the platforms
+pub(crate) async fn any(cache: &cache::Handle, on: Platform) -> error::Result<Option<Active>> {
+    for platform in platforms(on) {
+        if let Some(active) = get(cache, platform).await? {
+            return Ok(Some(active));
+        }
+    }
+    Ok(None)
+}
+
+/// Every running multiplier, with what it's granted so far
+pub(crate) async fn dump(cache: &cache::Handle) -> error::Result<Vec<(Platform, Active)>> {
+    let mut dump = vec![];
+    for platform in PLATFORMS {
+        if let Some(mut active) = get(cache, platform).await? {
+            active.bonus = get_cached(cache, bonus_key(platform))
+                .await?
+                .and_then(|s| s.parse().ok())
+                .unwrap_or_default();
+            dump.push((platform, active));
+        }
+    }
+    Ok(dump)
+}
+
+/// Replaces whatever's running on the platforms, starting their bonus tallies over
+pub(crate) async fn start(
+    cache: &cache::Handle,
+    on: Platform,
+    factor: f64,
+    secs: u64,
+    by: Arc<String>,
+) -> error::Result<Active> {
+    let active = Active {
+        factor,
+        until: now_secs() + secs,
+        by,
+        bonus: 0,
+    };
+    let value: Arc<String> = Arc::new(serde_json::to_string(&active)?);
+    for platform in platforms(on) {
+        Cache::Set(key(platform), value.clone(), secs as usize, false)
+            .exec(cache)
+            .await?;
+        Cache::Delete(bonus_key(platform)).exec(cache).await?;
+        ACTIVE
+            .write()
+            .insert(platform, (Instant::now(), Some(active.clone())));
+    }
+    tracing::info!(factor, secs, platforms = ?on, by = active.by.as_str(), "multiplier started");
+    Ok(active)
+}
+
+/// Bonus points each platform's multiplier granted
+pub(crate) async fn stop(
+    cache: &cache::Handle,
+    on: Platform,
+) -> error::Result<Vec<(Platform, u64)>> {
+    let mut granted = vec![];
+    for platform in platforms(on) {
+        if get(cache, platform).await?.is_none() {
+            continue;
+        }
+        Cache::Delete(key(platform)).exec(cache).await?;
+        let bonus = match Cache::GetDel(bonus_key(platform)).exec(cache).await {
+            Ok(RespType::String(s)) => s.parse().unwrap_or_default(),
+            Ok(_) => unreachable!(),
+            Err(Error::Redis(e)) if e.kind() == redis::ErrorKind::TypeError => 0,
+            Err(e) => return Err(e),
+        };
+        ACTIVE.write().insert(platform, (Instant::now(), None));
+        tracing::info!(platform = %platform, bonus, "multiplier stopped");
+        granted.push((platform, bonus));
+    }
+    Ok(granted)
+}
+
+/// Points to award once the platform's multiplier is applied, tallying the bonus
+pub(crate) async fn apply(
+    cache: &cache::Handle,
+    platform: Platform,
+    points: u64,
+) -> error::Result<u64> {
+    let active = match get(cache, platform).await? {
+        Some(active) => active,
+        None => return Ok(points),
+    };
+
+    let total = (points as f64 * active.factor).round() as u64;
+    let bonus = total.saturating_sub(points);
+    if bonus > 0 {
+        let granted = BONUS_POINTS_GRANTED.fetch_add(bonus, Ordering::Relaxed) + bonus;
+        tracing::debug!(platform = %platform, bonus, granted, "multiplier bonus");
+        let ttl = active.remaining_secs() + BONUS_TTL;
+        Cache::Increment(bonus_key(platform), bonus as usize, ttl as usize)
+            .exec(cache)
+            .await?;
+    }
+    Ok(total)
+}
+
+/// "2x", "1.5x" or "2"
+fn parse_factor(s: &str) -> Option<f64> {
+    let s = s.strip_suffix(['x', 'X']).unwrap_or(s);
+    let factor = s.parse::<f64>().ok()?;
+    (factor.is_finite() && factor > 1.0).then_some(factor)
+}
+
+/// "1h", "90m", "1h30m", or minutes on their own. None if it doesn't fit
+fn parse_secs(s: &str) -> Option<u64> {
+    if let Ok(mins) = s.parse::<u64>() {
+        return mins.checked_mul(60);
+    }
+
+    let mut secs: u64 = 0;
+    let mut rest = s;
+    while !rest.is_empty() {
+        let split = rest.find(|c: char| !c.is_ascii_digit())?;
+        let n = rest[..split].parse::<u64>().ok()?;
+        let unit = rest[split..].chars().next()?;
+        let unit_secs = match unit.to_ascii_lowercase() {
+            'h' => 60 * 60,
+            'm' => 60,
+            _ => return None,
+        };
+        secs = secs.checked_add(n.checked_mul(unit_secs)?)?;
+        rest = &rest[split + unit.len_utf8()..];
+    }
+    Some(secs)
+}
+
+enum Args {
+    /// factor, seconds, on
+    Start(f64, u64, Platform),
+    Stop(Platform),
+    Status,
+}
+
+#[command(cmd)]
+/// Multiply the points chatters earn for a while
+pub struct Multiplier {
+    /// Command prefix, followed by the factor and how long for (e.g. 2x 1h), or off
+    #[cmd(def("!multi"), constr(non_empty))]
+    prefix: String,
+    /// Platforms
+    #[cmd(defl("Platform::CHAT"))]
+    platforms: Platform,
+    /// Permissions for starting and stopping multipliers, anyone can check on them
+    #[cmd(defl("Permissions::ADMIN"))]
+    perms: Permissions,
+    /// Highest factor that can be set
+    #[cmd(def(3_u64), constr(range = "2..=10"))]
+    max_factor: u64,
+    /// Longest a multiplier can run for (in hours)
+    #[cmd(def(4_u64), constr(range = "1..=24"))]
+    max_hours: u64,
+}
+
+impl Multiplier {
+    /// A platform at the end only applies it there, otherwise it's on every platform the command is
+    fn parse_arguments(&self, ctx: &Context<'_>, chat: &Chat) -> Option<Args> {
+        let mut parts = chat.msg.split_whitespace();
+        if !parts.next()?.eq_ignore_ascii_case(&self.prefix) {
+            return None;
+        }
+
+        let args = match parts.next() {
+            None => return Some(Args::Status),
+            Some(arg) if arg.eq_ignore_ascii_case("off") => Args::Stop(self.target(parts.next())?),
+            Some(factor) => {
+                let factor = parse_factor(factor).filter(|f| *f <= self.max_factor as f64)?;
+                let secs = parse_secs(parts.next()?)
+                    .filter(|s| *s > 0 && *s <= self.max_hours * 60 * 60)?;
+                Args::Start(factor, secs, self.target(parts.next())?)
+            }
+        };
+
+        match parts.next() {
+            Some(_) => None,
+            None if ctx.user.perms < self.perms => None,
+            None => Some(args),
+        }
+    }
+
+    fn target(&self, platform: Option<&str>) -> Option<Platform> {
+        match platform {
+            Some(p) => Platform::from_str(p)
+                .ok()
+                .filter(|p| self.platforms.contains(*p)),
+            None => Some(self.platforms & Platform::CHAT),
+        }
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn chat(&self, ctx: &Context<'_>, chat: &Chat) -> error::Result<RunRes> {
+        if !self.enabled || !self.platforms.contains(ctx.platform) {
+            return Ok(RunRes::Disabled);
+        }
+
+        let args = match self.parse_arguments(ctx, chat) {
+            Some(args) => args,
+            None if util::starts_with_prefix(&self.prefix, &chat.msg) => {
+                if ctx.user.perms < self.perms {
+                    return Ok(RunRes::InsufficientPerms);
+                }
+                let msg = tr(
+                    "multiplier.usage",
+                    &[("prefix", &self.prefix), ("max", &self.max_factor)],
+                );
+                self.reply(ctx, msg).await;
+                return Ok(RunRes::InvalidArgs);
+            }
+            None => return Ok(RunRes::Noop),
+        };
+
+        self.run(ctx, args).await
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn invoke(
+        &self,
+        _ctx: &Context<'_>,
+        _invocation: &Invocation,
+    ) -> Option<RunRes> {
+        None
+    }
+
+    #[tracing::instrument(level = "trace", skip_all, name = "Multiplier")]
+    async fn run(&self, ctx: &Context<'_>, args: Args) -> error::Result<RunRes> {
+        let msg = match args {
+            Args::Start(factor, secs, on) => {
+                let active = start(ctx.cache, on, factor, secs, ctx.user.name.clone()).await?;
+                started_msg(&active, on)
+            }
+            Args::Stop(on) => {
+                let granted = stop(ctx.cache, on).await?;
+                stopped_msg(&granted, &ctx.currency)
+            }
+            Args::Status => match get(ctx.cache, ctx.platform).await? {
+                Some(active) => tr(
+                    "multiplier.status",
+                    &[
+                        ("factor", &active.factor()),
+                        ("remaining", &active.remaining()),
+                    ],
+                ),
+                None => tr("multiplier.none", &[]),
+            },
+        };
+
+        self.reply(ctx, msg).await;
+        Ok(RunRes::Ok)
+    }
+
+    async fn reply(&self, ctx: &Context<'_>, msg: String) {
+        Response {
+            platform: ctx.platform,
+            channel: crate::channel_name(),
+            corr_id: ctx.corr_id.clone(),
+            payload: Payload::Message {
+                user: ctx.reply_to(),
+                msg: msg.into(),
+                meta: ctx.meta.clone(),
+                hint: None,
+                thread: None,
+            },
+        }
+        .send(Location::Pubsub, ctx.resp)
+        .await;
+    }
+}
+
+/// Announces a multiplier to chat
+pub(crate) fn started_msg(active: &Active, on: Platform) -> String {
+    let on: Vec<String> = platforms(on).map(|p| p.to_string()).collect();
+    tr(
+        "multiplier.started",
+        &[
+            ("factor", &active.factor()),
+            ("remaining", &active.remaining()),
+            ("platforms", &on.join(&tr("list.separator", &[]))),
+        ],
+    )
+}
+
+pub(crate) fn stopped_msg(granted: &[(Platform, u64)], currency: &Currency) -> String {
+    if granted.is_empty() {
+        return tr("multiplier.none", &[]);
+    }
+    let bonus: u64 = granted.iter().map(|(_, b)| b).sum();
+    tr("multiplier.stopped", &[("bonus", &currency.format(bonus))])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_secs("90"), Some(90 * 60));
+        assert_eq!(parse_secs("1h"), Some(60 * 60));
+        assert_eq!(parse_secs("1h30m"), Some(90 * 60));
+        assert_eq!(parse_secs("45M"), Some(45 * 60));
+        assert_eq!(parse_secs("1d"), None);
+        assert_eq!(parse_secs("h"), None);
+    }
+
+    #[test]
+    fn too_long_doesnt_overflow() {
+        assert_eq!(parse_secs("400000000000000000"), None);
+        assert_eq!(parse_secs("6000000000000000h"), None);
+        assert_eq!(parse_secs(&format!("{}m1m", u64::MAX / 60)), None);
+    }
+}
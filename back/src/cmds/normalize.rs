@@ -0,0 +1,130 @@
+//! Text normalization for the filters. Whatever a filter turns on is applied to its patterns as
+//! well as to the text they're matched against, so e.g. a fullwidth or Cyrillic lookalike in
+//! either one still lines up with the plain latin letter in the other.
+use std::borrow::Cow;
+use unicode_normalization::UnicodeNormalization;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub(crate) struct Normalizer {
+    /// Lowercase everything
+    pub(crate) ignore_case: bool,
+    /// Unicode NFKC, e.g. fullwidth, circled and math-styled letters become plain ones
+    pub(crate) nfkc: bool,
+    /// Map lookalike letters from other scripts to latin and drop invisible characters
+    pub(crate) fold_confusables: bool,
+}
+
+impl Normalizer {
+    pub(crate) fn is_noop(&self) -> bool {
+        !(self.ignore_case || self.nfkc || self.fold_confusables)
+    }
+
+    pub(crate) fn apply<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        // ascii is left alone by everything except lowercasing
+        if self.is_noop()
+            || text.is_ascii()
+                && !(self.ignore_case && text.bytes().any(|b| b.is_ascii_uppercase()))
+        {
+            return Cow::Borrowed(text);
+        }
+
+        let mut text = Cow::Borrowed(text);
+        if self.nfkc {
+            text = Cow::Owned(text.nfkc().collect());
+        }
+        if self.fold_confusables {
+            text = Cow::Owned(text.chars().filter_map(fold_confusable).collect());
+        }
+        if self.ignore_case {
+            text = Cow::Owned(text.to_lowercase());
+        }
+        text
+    }
+}
+
+/// The latin letter `c` passes for, None if it's invisible. Case is kept so folding doesn't
+/// depend on ignore_case
+fn fold_confusable(c: char) -> Option<char> {
+    let folded = match c {
+        // zero width space/non-joiner/joiner, word joiner, bom, soft hyphen
+        '\u{200B}'..='\u{200D}' | '\u{2060}' | '\u{FEFF}' | '\u{00AD}' => return None,
+        // fullwidth ascii, for when nfkc is off
+        '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+        // cyrillic
+        'А' => 'A',
+        'В' => 'B',
+        'Е' | 'Ё' => 'E',
+        'К' => 'K',
+        'М' => 'M',
+        'Н' => 'H',
+        'О' => 'O',
+        'Р' => 'P',
+        'С' => 'C',
+        'Т' => 'T',
+        'Х' => 'X',
+        'У' | 'Ү' => 'Y',
+        'Ѕ' => 'S',
+        'І' | 'Ӏ' => 'I',
+        'Ј' => 'J',
+        'Ԛ' => 'Q',
+        'Ԝ' => 'W',
+        'а' => 'a',
+        'в' => 'b',
+        'е' | 'ё' => 'e',
+        'к' => 'k',
+        'м' => 'm',
+        'н' => 'h',
+        'о' => 'o',
+        'р' => 'p',
+        'с' => 'c',
+        'т' => 't',
+        'х' => 'x',
+        'у' | 'ү' => 'y',
+        'ѕ' => 's',
+        'і' => 'i',
+        'ј' => 'j',
+        'ԁ' => 'd',
+        'ԛ' => 'q',
+        'ԝ' => 'w',
+        'һ' => 'h',
+        'ѵ' => 'v',
+        // greek
+        'Α' => 'A',
+        'Β' => 'B',
+        'Ε' => 'E',
+        'Ζ' => 'Z',
+        'Η' => 'H',
+        'Ι' => 'I',
+        'Κ' => 'K',
+        'Μ' => 'M',
+        'Ν' => 'N',
+        'Ο' => 'O',
+        'Ρ' => 'P',
+        'Τ' => 'T',
+        'Υ' => 'Y',
+        'Χ' => 'X',
+        'α' => 'a',
+        'ι' => 'i',
+        'κ' => 'k',
+        'ν' => 'v',
+        'ο' => 'o',
+        'ρ' => 'p',
+        'τ' => 't',
+        'υ' => 'u',
+        'χ' => 'x',
+        // latin letters that aren't decomposable
+        'ı' => 'i',
+        'ȷ' => 'j',
+        'ɑ' => 'a',
+        'ɡ' => 'g',
+        'ɩ' => 'i',
+        'ʏ' => 'y',
+        'ᴄ' => 'c',
+        'ᴏ' => 'o',
+        'ᴠ' => 'v',
+        'ᴡ' => 'w',
+        'ᴢ' => 'z',
+        c => c,
+    };
+    Some(folded)
+}
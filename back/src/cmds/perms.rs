@@ -0,0 +1,240 @@
+use super::{util, Arg, ArgKind, ArgValue, Context, Invokable, RunRes};
+use crate::{
+    error,
+    i18n::tr,
+    msg::{
+        perm_map::{self, PermSource},
+        ArgMap, ArgMapError, Chat, Invocation, Location, Payload, Permissions, Platform, Response,
+    },
+};
+use back_derive::command;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::sync::Arc;
+
+static PERMS_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(\S+)(?:\s+(.+?))?\s*$").unwrap());
+
+#[derive(Debug)]
+enum Target {
+    /// Whoever asked
+    Caller,
+    Name(String),
+    /// id, name
+    User(Arc<String>, Arc<String>),
+}
+
+#[derive(Debug)]
+struct Args {
+    target: Target,
+}
+
+#[command(locks(rate))]
+/// Show someone's permission level and where it came from, for checking role mappings
+pub struct Perms {
+    /// Command prefix
+    #[cmd(def("!perms"), constr(non_empty))]
+    prefix: String,
+    /// Autocorrect prefix
+    autocorrect: bool,
+    /// Platforms
+    #[cmd(defl("Platform::CHAT"))]
+    platforms: Platform,
+    /// Permissions
+    #[cmd(defl("Permissions::MOD"))]
+    perms: Permissions,
+    /// Cooldown per user (in seconds)
+    #[cmd(constr(pos), group("Cooldowns"))]
+    ratelimit_user: u64,
+}
+
+impl Perms {
+    fn parse_arguments(&self, chat: &Chat) -> Option<(bool, Args)> {
+        let captures = PERMS_REGEX.captures(&chat.msg)?;
+
+        // check command prefix
+        let autocorrect = util::check_autocorrect(
+            &self.prefix,
+            &captures[1],
+            self.autocorrect,
+            &self.levenshtein,
+        )?;
+
+        let target = match captures.get(2) {
+            Some(name) => Target::Name(name.as_str().to_owned()),
+            None => Target::Caller,
+        };
+
+        Some((autocorrect, Args { target }))
+    }
+
+    fn can_run(&self, ctx: &Context<'_>) -> Option<()> {
+        if !self.enabled {
+            return None;
+        }
+
+        // check if platform is applicable
+        if !self.platforms.contains(ctx.platform) {
+            return None;
+        }
+
+        // check perms
+        if ctx.user.perms < self.perms {
+            return None;
+        }
+
+        Some(())
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn chat(&self, ctx: &Context<'_>, chat: &Chat) -> error::Result<RunRes> {
+        if self.can_run(ctx).is_none() {
+            return Ok(RunRes::Disabled);
+        }
+
+        let (autocorrect, args) = match self.parse_arguments(chat) {
+            Some(t) => t,
+            None => return Ok(RunRes::Noop),
+        };
+
+        if autocorrect {
+            return Ok(RunRes::Autocorrect(self.prefix.clone()));
+        }
+
+        match util::ratelimit_user(
+            ctx,
+            self.ratelimit_user,
+            stringify!(Perms),
+            &self.name,
+            &PERMS_LOCK_RATE,
+        )
+        .await
+        {
+            Ok(false) => {}
+            Ok(true) => return Ok(RunRes::Ratelimited { global: false }),
+            Err(e) => return Err(e),
+        }
+
+        self.run(ctx, args).await
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn invoke(
+        &self,
+        ctx: &Context<'_>,
+        invocation: &Invocation,
+    ) -> Option<RunRes> {
+        self.can_run(ctx)?;
+
+        super::check_invoke_prefix(&self.prefix, &invocation.cmd)?;
+
+        let args = match Args::try_from(&invocation.args) {
+            Ok(args) => args,
+            Err(_) => return Some(RunRes::InvalidArgs),
+        };
+
+        match util::ratelimit_user(
+            ctx,
+            self.ratelimit_user,
+            stringify!(Perms),
+            &self.name,
+            &PERMS_LOCK_RATE,
+        )
+        .await
+        {
+            Ok(false) => {}
+            Ok(true) => return None,
+            Err(e) => {
+                tracing::error!("{}", e);
+                return None;
+            }
+        }
+
+        match self.run(ctx, args).await {
+            Ok(r) => Some(r),
+            Err(e) => {
+                tracing::error!("{}", e);
+                None
+            }
+        }
+    }
+
+    #[tracing::instrument(level = "trace", skip_all, name = "Perms")]
+    async fn run(&self, ctx: &Context<'_>, args: Args) -> error::Result<RunRes> {
+        tracing::debug!(name = self.name.as_str(), user = ctx.user.name.as_str(), args = ?args);
+
+        // the name's kept to say who wasn't found
+        let target = match args.target {
+            Target::Caller => Ok((ctx.user.id.clone(), ctx.user.name.clone())),
+            Target::User(id, name) => Ok((id, name)),
+            Target::Name(name) => match ctx.resolve_user(&name).await? {
+                Some(found) => Ok((found.id, found.name)),
+                None => Err(name),
+            },
+        };
+
+        let msg = match target {
+            Ok((id, name)) => match perm_map::recall(ctx.cache, ctx.platform, &id).await? {
+                Some(synced) => {
+                    let source = match synced.source {
+                        PermSource::Platform => {
+                            tr("perms.from_platform", &[("platform", &ctx.platform)])
+                        }
+                        PermSource::Rule(rule) => tr("perms.from_rule", &[("rule", &rule)]),
+                    };
+                    tr(
+                        "perms.level",
+                        &[
+                            ("user", &name),
+                            ("level", &format!("{:?}", synced.perms)),
+                            ("source", &source),
+                        ],
+                    )
+                }
+                None => tr("perms.unknown", &[("user", &name)]),
+            },
+            Err(name) => tr("perms.unknown", &[("user", &name)]),
+        };
+
+        Response {
+            platform: ctx.platform,
+            channel: crate::channel_name(),
+            corr_id: ctx.corr_id.clone(),
+            payload: Payload::Message {
+                user: ctx.reply_to(),
+                msg: msg.into(),
+                meta: ctx.meta.clone(),
+                hint: None,
+                thread: None,
+            },
+        }
+        .send(Location::Broadcast, ctx.resp)
+        .await;
+
+        Ok(RunRes::Ok)
+    }
+}
+
+impl Invokable for Perms {
+    fn args(&self, _platform: Platform) -> Vec<Arg> {
+        vec![Arg {
+            name: "user".into(),
+            desc: "Person to check (leaving this blank means you)".into(),
+            kind: ArgKind::User,
+            optional: true,
+        }]
+    }
+}
+
+impl TryFrom<&ArgMap> for Args {
+    type Error = error::Error;
+
+    fn try_from(value: &ArgMap) -> Result<Self, Self::Error> {
+        let target = match value.get("user") {
+            Some(ArgValue::User(u)) => Target::User(u.id.clone(), u.name.clone()),
+            Some(_) => return Err(ArgMapError.into()),
+            None => Target::Caller,
+        };
+
+        Ok(Args { target })
+    }
+}
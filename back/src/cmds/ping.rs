@@ -1,6 +1,8 @@
-use super::{util, Arg, ArgKind, ArgValue, CmdDesc, Context, Invokable, RunRes};
+use super::{util, Arg, ArgKind, ArgValue, CmdDesc, Context, Invokable, RespHandle, RunRes};
 use crate::{
+    cache::{self, Cache, RespType},
     error,
+    i18n::tr,
     msg::{
         self, ArgMap, ArgMapError, Chat, Invocation, Location, Payload, Permissions, Platform,
         Response, User,
@@ -8,8 +10,77 @@ use crate::{
 };
 use back_derive::command;
 use once_cell::sync::Lazy;
+use rand::Rng;
 use regex::Regex;
-use std::sync::Arc;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// How long an undelivered ping waits for its platform to come back (in seconds)
+const PING_TTL: u64 = 60 * 60 * 24;
+/// How long to wait for a receipt before sending again
+const RECEIPT_TIMEOUT: Duration = Duration::from_secs(15);
+/// Sends before a ping is left queued until its platform starts again
+const MAX_ATTEMPTS: u32 = 3;
+
+/// (ping id, Pending)
+static PENDING_KEY: Lazy<Arc<String>> =
+    Lazy::new(|| Arc::new(format!("aussiebot!{}!pings", crate::channel_name())));
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// A ping that hasn't been confirmed by its platform yet
+#[derive(Debug, Serialize, Deserialize)]
+struct Pending {
+    /// The pingee's
+    platform: Platform,
+    ping: msg::Ping,
+    corr_id: Option<Arc<String>>,
+    /// Unix time it was first sent
+    queued: u64,
+}
+
+impl Pending {
+    async fn send(&self, resp: &RespHandle) {
+        Response {
+            platform: self.platform,
+            channel: crate::channel_name(),
+            corr_id: self.corr_id.clone(),
+            payload: Payload::Ping(self.ping.clone()),
+        }
+        .send(Location::Broadcast, resp)
+        .await;
+    }
+
+    /// Let the pinger know how it went
+    async fn reply(&self, resp: &RespHandle, msg: String) {
+        let (platform, pinger) = match &self.ping.pinger {
+            Some(pinger) => pinger.clone(),
+            None => return,
+        };
+        Response {
+            platform,
+            channel: crate::channel_name(),
+            corr_id: self.corr_id.clone(),
+            payload: Payload::Message {
+                user: Some((platform, pinger)),
+                msg: msg.into(),
+                meta: self.ping.meta.clone(),
+                hint: None,
+                thread: None,
+            },
+        }
+        .send(Location::Pubsub, resp)
+        .await;
+    }
+}
 
 #[derive(Debug)]
 struct Args {
@@ -31,10 +102,10 @@ pub struct Ping {
     #[cmd(defl("Permissions::NONE"))]
     perms: Permissions,
     /// Cooldown per user (in seconds)
-    #[cmd(constr(pos))]
+    #[cmd(constr(pos), group("Cooldowns"))]
     ratelimit_user: u64,
     /// Cooldown per use (in seconds)
-    #[cmd(constr(pos))]
+    #[cmd(constr(pos), group("Cooldowns"))]
     ratelimit: u64,
     /// Target platform (choose one)
     #[cmd(defl("Platform::DISCORD"))]
@@ -165,25 +236,149 @@ impl Ping {
     async fn run(&self, ctx: &Context<'_>, args: Args) -> error::Result<RunRes> {
         tracing::debug!(name = self.name.as_str(), user = ctx.user.name.as_str(), args = ?args);
 
-        Response {
+        let id = Arc::new(format!("{:016x}", rand::thread_rng().gen::<u64>()));
+        let pending = Pending {
             platform: self.pingee_platform,
-            channel: &*crate::CHANNEL_NAME,
-            payload: Payload::Ping(msg::Ping {
+            ping: msg::Ping {
+                id: Some(id.clone()),
                 pinger: Some((ctx.platform, ctx.user.clone())),
                 pingee: Arc::new(User {
                     id: Arc::new(self.pingee_id.to_owned()),
                     name: Arc::new(self.pingee_name.to_owned()),
                     perms: Permissions::NONE,
+                    roles: vec![],
                 }),
                 msg: args.msg.map(Arc::new),
                 meta: ctx.meta.clone(),
-            }),
-        }
-        .send(Location::Broadcast, ctx.resp)
-        .await;
+            },
+            corr_id: ctx.corr_id.clone(),
+            queued: now_secs(),
+        };
+
+        // queued first, so a fast receipt always finds it
+        Cache::HashSet(
+            PENDING_KEY.clone(),
+            id.clone(),
+            serde_json::to_string(&pending)?,
+            false,
+        )
+        .exec(ctx.cache)
+        .await?;
+        pending.send(ctx.resp).await;
+        tokio::spawn(Self::await_receipt(ctx.cache.clone(), ctx.resp.clone(), id));
 
         Ok(RunRes::Ok)
     }
+
+    /// Send the ping again until its platform confirms it, then leave it queued
+    async fn await_receipt(cache: cache::Handle, resp: RespHandle, id: Arc<String>) {
+        for attempt in 1..=MAX_ATTEMPTS {
+            tokio::time::sleep(RECEIPT_TIMEOUT).await;
+            let pending = match Self::queued(&cache).await {
+                Ok(queued) => queued.into_iter().find(|(i, _)| i == id.as_str()),
+                Err(e) => {
+                    tracing::error!("{}", e);
+                    return;
+                }
+            };
+            let pending = match pending {
+                Some((_, pending)) => pending,
+                None => return,
+            };
+
+            if attempt == MAX_ATTEMPTS {
+                tracing::warn!(id = id.as_str(), platform = %pending.platform, "no receipt for ping, queued until the platform starts");
+                let msg = tr("ping.queued", &[("platform", &pending.platform)]);
+                pending.reply(&resp, msg).await;
+                return;
+            }
+            tracing::info!(
+                id = id.as_str(),
+                attempt,
+                "no receipt for ping, sending again"
+            );
+            pending.send(&resp).await;
+        }
+    }
+
+    /// Every ping still waiting on a receipt, dropping any that have waited too long
+    async fn queued(cache: &cache::Handle) -> error::Result<Vec<(String, Pending)>> {
+        let entries = match Cache::HashGetAll(PENDING_KEY.clone()).exec(cache).await? {
+            RespType::VecStringString(entries) => entries,
+            _ => unreachable!(),
+        };
+
+        let now = now_secs();
+        let mut queued = Vec::with_capacity(entries.len());
+        for (id, json) in entries {
+            match serde_json::from_str::<Pending>(&json) {
+                Ok(pending) if now.saturating_sub(pending.queued) < PING_TTL => {
+                    queued.push((id, pending));
+                    continue;
+                }
+                Ok(_) => tracing::warn!(id = id.as_str(), "dropping undelivered ping"),
+                Err(e) => tracing::error!(id = id.as_str(), "dropping unreadable ping: {}", e),
+            }
+            Cache::HashDelete(PENDING_KEY.clone(), Arc::new(id))
+                .exec(cache)
+                .await?;
+        }
+        Ok(queued)
+    }
+
+    /// A platform's answer to a ping, `failure` is why it couldn't be delivered
+    pub(crate) async fn receipt(
+        cache: &cache::Handle,
+        resp: &RespHandle,
+        id: Arc<String>,
+        failure: Option<Arc<String>>,
+    ) -> error::Result<()> {
+        let pending = Self::queued(cache)
+            .await?
+            .into_iter()
+            .find(|(i, _)| i == id.as_str());
+        let pending = match pending {
+            Some((_, pending)) => pending,
+            None => return Ok(()),
+        };
+        // resent pings get a receipt each, only the first is passed on
+        match Cache::HashDelete(PENDING_KEY.clone(), id.clone())
+            .exec(cache)
+            .await?
+        {
+            RespType::Bool(true) => {}
+            _ => return Ok(()),
+        }
+
+        let platform = &pending.platform;
+        let msg = match failure {
+            None => {
+                tracing::info!(id = id.as_str(), "ping delivered");
+                tr("ping.delivered", &[("platform", platform)])
+            }
+            Some(reason) => {
+                tracing::warn!(id = id.as_str(), reason = reason.as_str(), "ping failed");
+                tr("ping.failed", &[("platform", platform)])
+            }
+        };
+        pending.reply(resp, msg).await;
+        Ok(())
+    }
+
+    /// Send the pings queued for `platform` again, now that it's started
+    pub(crate) async fn redeliver(
+        cache: &cache::Handle,
+        resp: &RespHandle,
+        platform: Platform,
+    ) -> error::Result<()> {
+        for (id, pending) in Self::queued(cache).await? {
+            if pending.platform == platform {
+                tracing::info!(id = id.as_str(), "redelivering ping");
+                pending.send(resp).await;
+            }
+        }
+        Ok(())
+    }
 }
 
 impl CmdDesc for Ping {
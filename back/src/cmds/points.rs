@@ -1,13 +1,13 @@
-use super::{util, Context, RunRes};
+use super::{multiplier, user_cache, util, Context, Invokable, RunRes};
 use crate::{
     db::{self, Db},
     error,
+    i18n::tr,
     msg::{Chat, ChatMeta, Invocation, Location, Payload, Permissions, Platform, Response},
 };
 use back_derive::command;
 use once_cell::sync::Lazy;
 use regex::Regex;
-use std::fmt::Write as _;
 
 static CHAT_DONO_AMT_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"\{amount\}").unwrap());
 
@@ -35,10 +35,10 @@ pub struct Points {
     /// Message to send in response to donations
     dono_msg: String,
     /// Cooldown per user (in seconds)
-    #[cmd(constr(pos))]
+    #[cmd(constr(pos), group("Cooldowns"))]
     ratelimit_user: u64,
     /// Cooldown for adding points
-    #[cmd(constr(pos))]
+    #[cmd(constr(pos), group("Cooldowns"))]
     ratelimit_update: u64,
 }
 
@@ -101,11 +101,14 @@ impl Points {
         // send reply
         Response {
             platform: ctx.platform,
-            channel: &*crate::CHANNEL_NAME,
+            channel: crate::channel_name(),
+            corr_id: ctx.corr_id.clone(),
             payload: Payload::Message {
-                user: Some((ctx.platform, ctx.user.clone())),
+                user: ctx.reply_to(),
                 msg: rep.into_owned().into(),
                 meta: ctx.meta.clone(),
+                hint: None,
+                thread: None,
             },
         }
         .send(Location::Broadcast, ctx.resp)
@@ -124,6 +127,12 @@ impl Points {
         let user = ctx.user;
         let platform = ctx.platform;
 
+        // DMs are only for checking points, not earning them
+        let dm = ctx.meta.as_ref().is_some_and(ChatMeta::is_dm);
+        if dm && !user_asked {
+            return Ok(RunRes::Noop);
+        }
+
         // Different ratelimits for hours updating and Points as a cmd
         if user_asked {
             // check perms
@@ -148,23 +157,25 @@ impl Points {
             let cooldown = self.ratelimit_update as u64;
             let user_ratelimit_key = format!("{}_{}", &*POINTS_LOCK_UPDATE_RATE, &user.id);
 
-            if !ctx.lock.lock(user_ratelimit_key, cooldown).await? {
+            if !ctx.lock.cooldown(user_ratelimit_key, cooldown).await? {
                 tracing::info!("\x1b[33mPoints update rate-limited locally\x1b[0m");
                 return Ok(RunRes::Ratelimited { global: false });
             }
         }
 
         // increment points if applicable
-        if self.points > 0 {
+        if self.points > 0 && !dm {
+            let points = multiplier::apply(ctx.cache, ctx.platform, self.points).await?;
             let resp = Db::Upsert(
                 ctx.platform,
                 user.id.clone(),
                 user.name.clone(),
-                self.points as i32,
+                points.min(i32::MAX as u64) as i32,
             )
             .exec(ctx.db)
             .await?;
             assert!(matches!(resp, db::Resp::Ok));
+            user_cache::invalidate(ctx.cache, ctx.platform, Some(&user.id), &user.name).await;
         }
 
         if user_asked {
@@ -178,25 +189,36 @@ impl Points {
             };
 
             let mut msg = String::new();
+            let separator = tr("list.separator", &[]);
 
             for (platform, points) in &points_list {
                 if let Some(points) = points {
-                    write!(msg, "{} ({}), ", points, platform).unwrap();
+                    msg.push_str(&tr(
+                        "points.entry",
+                        &[
+                            ("points", &ctx.currency.format(*points)),
+                            ("platform", platform),
+                        ],
+                    ));
+                    msg.push_str(&separator);
                 }
             }
 
-            if !points_list.is_empty() {
-                msg.truncate(msg.chars().count() - 2);
+            if !msg.is_empty() {
+                msg.truncate(msg.len() - separator.len());
             }
 
             // send reply
             Response {
                 platform,
-                channel: &*crate::CHANNEL_NAME,
+                channel: crate::channel_name(),
+                corr_id: ctx.corr_id.clone(),
                 payload: Payload::Message {
                     user: Some((platform, user.clone())),
                     msg: msg.into(),
                     meta: ctx.meta.clone(),
+                    hint: None,
+                    thread: None,
                 },
             }
             .send(Location::Pubsub, ctx.resp)
@@ -216,3 +238,9 @@ impl Points {
         Ok(RunRes::Noop)
     }
 }
+
+impl Invokable for Points {
+    fn allow_dm(&self, _platform: Platform) -> bool {
+        true
+    }
+}
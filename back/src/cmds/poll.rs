@@ -0,0 +1,745 @@
+use super::{util, Arg, ArgKind, ArgValue, CmdDesc, Context, Invokable, RespHandle, RunRes};
+use crate::{
+    cache::{self, Cache, RespType},
+    error::{self, Error},
+    i18n::{plural, tr},
+    msg::{
+        corr_id, ArgMap, ArgMapError, Chat, Invocation, Location, Payload, Permissions, Platform,
+        Response,
+    },
+};
+use back_derive::command;
+use bb8_redis::redis;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use regex::Regex;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::watch;
+use tracing::{debug_span, Instrument};
+
+static POLL_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(\S+)(?:\s+(start|end)\b\s*(.*))?$").unwrap());
+
+/// Quoted phrases or single words
+static TOKEN_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r#""([^"]+)"|(\S+)"#).unwrap());
+
+const MIN_OPTIONS: usize = 2;
+
+/// How long polls without a duration are kept around for (in seconds)
+const MAX_POLL_SECS: u64 = 86400;
+
+#[derive(Debug)]
+enum Args {
+    /// Restate the running poll
+    Show,
+    Start {
+        question: String,
+        options: Vec<String>,
+    },
+    End,
+}
+
+/// The running poll, kept in the cache so it outlives restarts and config changes
+#[derive(Debug, Serialize, Deserialize)]
+struct PollState {
+    /// Unix millis it was started at, tells polls apart
+    id: u64,
+    question: String,
+    options: Vec<String>,
+    /// Unix secs, None if it runs until ended
+    ends: Option<u64>,
+}
+
+/// The running poll with its tallies, mirrored in memory so chat doesn't hit the cache for every message
+#[derive(Debug)]
+struct Active {
+    state: PollState,
+    tallies: Vec<AtomicU64>,
+}
+
+impl Active {
+    fn votes(&self) -> Vec<u64> {
+        self.tallies
+            .iter()
+            .map(|t| t.load(Ordering::Relaxed))
+            .collect()
+    }
+}
+
+type Current = Arc<RwLock<Option<Arc<Active>>>>;
+
+#[command(locks(state, voters, tally))]
+/// Let chat vote on a question by number or keyword
+pub struct Poll {
+    /// Command prefix
+    #[cmd(def("!poll"), constr(non_empty))]
+    prefix: String,
+    /// Autocorrect prefix
+    autocorrect: bool,
+    /// Platforms
+    #[cmd(defl("Platform::CHAT"))]
+    platforms: Platform,
+    /// Permissions to start and end polls
+    #[cmd(defl("Permissions::MOD"))]
+    perms: Permissions,
+    /// Permissions to vote
+    #[cmd(defl("Permissions::NONE"))]
+    vote_perms: Permissions,
+    /// How long polls run for (in seconds, 0 to run until ended)
+    #[cmd(def(120u64), constr(range = "0..=86400"))]
+    duration: u64,
+    /// Max number of options
+    #[cmd(def(5u64), constr(range = "2..=10"))]
+    max_options: u64,
+    #[cmd(skip)]
+    current: Current,
+    /// Stops poll timers when the config changes, init picks them back up
+    #[cmd(skip)]
+    cancel_chan: RwLock<Option<watch::Receiver<()>>>,
+}
+
+impl Poll {
+    fn parse_arguments(&self, chat: &Chat) -> Option<(bool, Args)> {
+        let captures = POLL_REGEX.captures(&chat.msg)?;
+
+        // check command prefix
+        let autocorrect = util::check_autocorrect(
+            &self.prefix,
+            &captures[1],
+            self.autocorrect,
+            &self.levenshtein,
+        )?;
+
+        let args = match captures.get(2).map(|m| m.as_str()) {
+            None => Args::Show,
+            Some("end") => Args::End,
+            Some(_) => {
+                let mut tokens = TOKEN_REGEX.captures_iter(&captures[3]).filter_map(|c| {
+                    c.get(1)
+                        .or_else(|| c.get(2))
+                        .map(|m| m.as_str().trim().to_owned())
+                });
+                Args::Start {
+                    question: tokens.next()?,
+                    options: tokens.collect(),
+                }
+            }
+        };
+
+        Some((autocorrect, args))
+    }
+
+    fn can_run(&self, ctx: &Context<'_>) -> Option<()> {
+        if !self.enabled {
+            return None;
+        }
+
+        // check if platform is applicable
+        if !self.platforms.contains(ctx.platform) {
+            return None;
+        }
+
+        // check perms
+        if ctx.user.perms < self.perms {
+            return None;
+        }
+
+        Some(())
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn chat(&self, ctx: &Context<'_>, chat: &Chat) -> error::Result<RunRes> {
+        if !self.enabled || !self.platforms.contains(ctx.platform) {
+            return Ok(RunRes::Disabled);
+        }
+
+        let parsed = self.parse_arguments(chat);
+
+        // anything else might be a vote
+        if parsed.is_none() && !util::starts_with_prefix(&self.prefix, &chat.msg) {
+            return self.vote(ctx, chat.msg.trim()).await;
+        }
+
+        if self.can_run(ctx).is_none() {
+            return Ok(RunRes::Disabled);
+        }
+
+        let (autocorrect, args) = match parsed {
+            Some(t) => t,
+            None => return Ok(RunRes::InvalidArgs),
+        };
+
+        if autocorrect {
+            return Ok(RunRes::Autocorrect(self.prefix.clone()));
+        }
+
+        self.run(ctx, args).await
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn invoke(
+        &self,
+        ctx: &Context<'_>,
+        invocation: &Invocation,
+    ) -> Option<RunRes> {
+        self.can_run(ctx)?;
+
+        super::check_invoke_prefix(&self.prefix, &invocation.cmd)?;
+
+        let args = match Args::try_from(&invocation.args) {
+            Ok(args) => args,
+            Err(_) => return Some(RunRes::InvalidArgs),
+        };
+
+        match self.run(ctx, args).await {
+            Ok(r) => Some(r),
+            Err(e) => {
+                tracing::error!("{}", e);
+                None
+            }
+        }
+    }
+
+    /// (state, voters, tally prefix)
+    fn keys(name: &str) -> (Arc<String>, Arc<String>, Arc<String>) {
+        (
+            Arc::new(format!("{}_{}", &*POLL_LOCK_STATE, name)),
+            Arc::new(format!("{}_{}", &*POLL_LOCK_VOTERS, name)),
+            Arc::new(format!("{}_{}", &*POLL_LOCK_TALLY, name)),
+        )
+    }
+
+    fn tally_key(prefix: &str, option: usize) -> Arc<String> {
+        Arc::new(format!("{}_{}", prefix, option))
+    }
+
+    /// Index of the option picked, by number or name
+    fn choice(options: &[String], choice: &str) -> Option<usize> {
+        match choice.parse::<usize>() {
+            Ok(n) if (1..=options.len()).contains(&n) => Some(n - 1),
+            _ => options.iter().position(|o| o.eq_ignore_ascii_case(choice)),
+        }
+    }
+
+    async fn reply(ctx: &Context<'_>, msg: String) {
+        Response {
+            platform: ctx.platform,
+            channel: crate::channel_name(),
+            corr_id: ctx.corr_id.clone(),
+            payload: Payload::Message {
+                user: ctx.reply_to(),
+                msg: msg.into(),
+                meta: ctx.meta.clone(),
+                hint: None,
+                thread: None,
+            },
+        }
+        .send(Location::Pubsub, ctx.resp)
+        .await;
+    }
+
+    /// Tell web clients how the poll's going
+    async fn update(
+        resp: &RespHandle,
+        name: &str,
+        state: &PollState,
+        votes: Vec<u64>,
+        closed: bool,
+    ) {
+        Response {
+            platform: Platform::WEB,
+            channel: crate::channel_name(),
+            corr_id: corr_id(),
+            payload: Payload::PollUpdate {
+                name: name.to_owned(),
+                question: state.question.clone(),
+                options: state.options.clone(),
+                votes,
+                closed,
+            },
+        }
+        .send(Location::Websockets(None), resp)
+        .await;
+    }
+
+    fn options_list(options: &[String]) -> String {
+        let separator = tr("list.separator", &[]);
+        options
+            .iter()
+            .enumerate()
+            .map(|(i, option)| tr("poll.option", &[("n", &(i + 1)), ("option", option)]))
+            .collect::<Vec<_>>()
+            .join(&separator)
+    }
+
+    #[tracing::instrument(level = "trace", skip_all, name = "Poll")]
+    async fn run(&self, ctx: &Context<'_>, args: Args) -> error::Result<RunRes> {
+        tracing::debug!(name = self.name.as_str(), user = ctx.user.name.as_str(), args = ?args);
+
+        match args {
+            Args::Show => {
+                let active = self.current.read().clone();
+                let msg = match active {
+                    Some(active) => {
+                        let total = active.votes().iter().sum::<u64>();
+                        tr(
+                            "poll.running",
+                            &[
+                                ("question", &active.state.question),
+                                ("options", &Self::options_list(&active.state.options)),
+                                ("total", &total),
+                                ("s", &plural(total)),
+                            ],
+                        )
+                    }
+                    None => tr("poll.not_running", &[]),
+                };
+                Self::reply(ctx, msg).await;
+            }
+            Args::Start { question, options } => self.start(ctx, question, options).await?,
+            Args::End => {
+                let ended =
+                    Self::finish(&self.name, None, &self.current, ctx.cache, ctx.resp).await?;
+                if !ended {
+                    Self::reply(ctx, tr("poll.not_running", &[])).await;
+                }
+            }
+        }
+
+        Ok(RunRes::Ok)
+    }
+
+    async fn start(
+        &self,
+        ctx: &Context<'_>,
+        question: String,
+        options: Vec<String>,
+    ) -> error::Result<()> {
+        let max = self.max_options as usize;
+        if !(MIN_OPTIONS..=max).contains(&options.len()) {
+            let msg = tr("poll.options", &[("min", &MIN_OPTIONS), ("max", &max)]);
+            Self::reply(ctx, msg).await;
+            return Ok(());
+        }
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+        let state = PollState {
+            id: now.as_millis() as u64,
+            question,
+            options,
+            ends: (self.duration > 0).then(|| now.as_secs() + self.duration),
+        };
+
+        let (state_key, voters_key, tally_prefix) = Self::keys(&self.name);
+        let json = serde_json::to_string(&state)?;
+        let ttl = match self.duration {
+            0 => MAX_POLL_SECS,
+            duration => duration + 60,
+        };
+
+        // only one poll at a time
+        match Cache::Set(state_key, json.into(), ttl as usize, true)
+            .exec(ctx.cache)
+            .await?
+        {
+            RespType::Bool(true) => {}
+            RespType::Bool(false) => {
+                Self::reply(ctx, tr("poll.already_running", &[])).await;
+                return Ok(());
+            }
+            _ => unreachable!(),
+        }
+
+        // clear out votes left over from a poll that expired instead of ending
+        Cache::Delete(voters_key).exec(ctx.cache).await?;
+        for i in 0..state.options.len() {
+            Cache::Delete(Self::tally_key(&tally_prefix, i))
+                .exec(ctx.cache)
+                .await?;
+        }
+
+        tracing::info!(question = state.question.as_str(), options = ?state.options, "poll started");
+
+        let msg = tr(
+            "poll.started",
+            &[
+                ("question", &state.question),
+                ("options", &Self::options_list(&state.options)),
+            ],
+        );
+        let active = Arc::new(Active {
+            tallies: state.options.iter().map(|_| AtomicU64::new(0)).collect(),
+            state,
+        });
+        *self.current.write() = Some(active.clone());
+
+        if self.duration > 0 {
+            Self::schedule_end(
+                self.name.clone(),
+                active.state.id,
+                Duration::from_secs(self.duration),
+                self.current.clone(),
+                self.cancel_chan.read().clone(),
+                ctx.cache.clone(),
+                ctx.resp.clone(),
+            );
+        }
+
+        Self::reply(ctx, msg).await;
+        Self::update(ctx.resp, &self.name, &active.state, active.votes(), false).await;
+
+        Ok(())
+    }
+
+    /// Count a chat message as a vote if it picks one of the options, once per user
+    async fn vote(&self, ctx: &Context<'_>, choice: &str) -> error::Result<RunRes> {
+        let active = match self.current.read().clone() {
+            Some(active) => active,
+            None => return Ok(RunRes::Noop),
+        };
+
+        let index = match Self::choice(&active.state.options, choice) {
+            Some(i) => i,
+            None => return Ok(RunRes::Noop),
+        };
+
+        if ctx.user.perms < self.vote_perms {
+            return Ok(RunRes::Disabled);
+        }
+
+        let (_, voters_key, tally_prefix) = Self::keys(&self.name);
+        let voter = Arc::new(format!("{}:{}", ctx.platform, ctx.user.id));
+        match Cache::HashSet(voters_key, voter, index.to_string(), true)
+            .exec(ctx.cache)
+            .await?
+        {
+            RespType::Bool(true) => {}
+            RespType::Bool(false) => return Ok(RunRes::Noop),
+            _ => unreachable!(),
+        }
+
+        let votes = match Cache::Increment(
+            Self::tally_key(&tally_prefix, index),
+            1,
+            MAX_POLL_SECS as usize,
+        )
+        .exec(ctx.cache)
+        .await?
+        {
+            RespType::U64(votes) => votes,
+            _ => unreachable!(),
+        };
+        active.tallies[index].store(votes, Ordering::Relaxed);
+
+        tracing::debug!(option = index, votes, "voted");
+        Self::update(ctx.resp, &self.name, &active.state, active.votes(), false).await;
+
+        Ok(RunRes::Ok)
+    }
+
+    async fn tallies(
+        cache: &cache::Handle,
+        tally_prefix: &str,
+        options: usize,
+    ) -> error::Result<Vec<u64>> {
+        let mut tallies = Vec::with_capacity(options);
+        for i in 0..options {
+            let votes = match Cache::Get(Self::tally_key(tally_prefix, i))
+                .exec(cache)
+                .await
+            {
+                Ok(RespType::String(votes)) => votes.parse().unwrap_or_default(),
+                Ok(_) => unreachable!(),
+                Err(Error::Redis(e)) if e.kind() == redis::ErrorKind::TypeError => 0,
+                Err(e) => return Err(e),
+            };
+            tallies.push(votes);
+        }
+        Ok(tallies)
+    }
+
+    /// End the poll and announce the results, false if there wasn't one.
+    /// Timers pass the id of the poll they were started for, so they don't end a newer one
+    async fn finish(
+        name: &str,
+        id: Option<u64>,
+        current: &Current,
+        cache: &cache::Handle,
+        resp: &RespHandle,
+    ) -> error::Result<bool> {
+        if let Some(id) = id {
+            if current.read().as_ref().map(|a| a.state.id) != Some(id) {
+                return Ok(false);
+            }
+        }
+
+        let (state_key, voters_key, tally_prefix) = Self::keys(name);
+
+        // whoever takes the state gets to announce the results
+        let state = match Cache::GetDel(state_key).exec(cache).await {
+            Ok(RespType::String(state)) => serde_json::from_str::<PollState>(&state)?,
+            Ok(_) => unreachable!(),
+            Err(Error::Redis(e)) if e.kind() == redis::ErrorKind::TypeError => {
+                *current.write() = None;
+                return Ok(false);
+            }
+            Err(e) => return Err(e),
+        };
+        *current.write() = None;
+
+        let votes = Self::tallies(cache, &tally_prefix, state.options.len()).await?;
+        Cache::Delete(voters_key).exec(cache).await?;
+        for i in 0..state.options.len() {
+            Cache::Delete(Self::tally_key(&tally_prefix, i))
+                .exec(cache)
+                .await?;
+        }
+
+        let total = votes.iter().sum::<u64>();
+        let separator = tr("list.separator", &[]);
+        let results = state
+            .options
+            .iter()
+            .zip(&votes)
+            .enumerate()
+            .map(|(i, (option, votes))| {
+                let pct = if total > 0 {
+                    (*votes as f64 * 100.0 / total as f64).round() as u64
+                } else {
+                    0
+                };
+                tr(
+                    "poll.result",
+                    &[
+                        ("n", &(i + 1)),
+                        ("option", option),
+                        ("votes", votes),
+                        ("pct", &pct),
+                    ],
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(&separator);
+
+        tracing::info!(question = state.question.as_str(), ?votes, "poll ended");
+
+        let msg = tr(
+            "poll.ended",
+            &[
+                ("question", &state.question),
+                ("results", &results),
+                ("total", &total),
+                ("s", &plural(total)),
+            ],
+        );
+        Response {
+            platform: Platform::CHAT,
+            channel: crate::channel_name(),
+            corr_id: corr_id(),
+            payload: Payload::Message {
+                user: None,
+                msg: msg.into(),
+                meta: None,
+                hint: None,
+                thread: None,
+            },
+        }
+        .send(Location::Pubsub, resp)
+        .await;
+
+        Self::update(resp, name, &state, votes, true).await;
+
+        Ok(true)
+    }
+
+    fn schedule_end(
+        name: String,
+        id: u64,
+        after: Duration,
+        current: Current,
+        cancel_chan: Option<watch::Receiver<()>>,
+        cache: cache::Handle,
+        resp: RespHandle,
+    ) {
+        tokio::spawn(
+            async move {
+                let cancelled = async move {
+                    match cancel_chan {
+                        Some(mut chan) => {
+                            let _ = chan.changed().await;
+                        }
+                        None => futures_util::future::pending().await,
+                    }
+                };
+
+                tokio::select! {
+                    _ = tokio::time::sleep(after) => {
+                        if let Err(e) = Self::finish(&name, Some(id), &current, &cache, &resp).await {
+                            tracing::error!("{}", e);
+                        }
+                    }
+                    // the poll's still in the cache, init picks it back up
+                    _ = cancelled => {}
+                }
+            }
+            .instrument(debug_span!("Poll timer")),
+        );
+    }
+
+    /// Keep the cancel chan for polls started from now on, and pick up any poll
+    /// left running from before a restart or config change
+    pub(crate) fn init(
+        &self,
+        cancel_chan: watch::Receiver<()>,
+        cache: &cache::Handle,
+        resp: &RespHandle,
+    ) -> Option<()> {
+        *self.cancel_chan.write() = Some(cancel_chan.clone());
+
+        if !self.enabled {
+            return None;
+        }
+
+        let name = self.name.clone();
+        let current = self.current.clone();
+        let (cache, resp) = (cache.clone(), resp.clone());
+
+        tokio::spawn(
+            async move {
+                let (state_key, _, tally_prefix) = Self::keys(&name);
+                let state = match Cache::Get(state_key).exec(&cache).await {
+                    Ok(RespType::String(state)) => state,
+                    Ok(_) => unreachable!(),
+                    Err(Error::Redis(e)) if e.kind() == redis::ErrorKind::TypeError => return,
+                    Err(e) => {
+                        tracing::error!("{}", e);
+                        return;
+                    }
+                };
+                let state = match serde_json::from_str::<PollState>(&state) {
+                    Ok(state) => state,
+                    Err(e) => {
+                        tracing::error!("{}", e);
+                        return;
+                    }
+                };
+                let votes = match Self::tallies(&cache, &tally_prefix, state.options.len()).await {
+                    Ok(votes) => votes,
+                    Err(e) => {
+                        tracing::error!("{}", e);
+                        return;
+                    }
+                };
+
+                let (id, ends) = (state.id, state.ends);
+                *current.write() = Some(Arc::new(Active {
+                    state,
+                    tallies: votes.into_iter().map(AtomicU64::new).collect(),
+                }));
+                tracing::info!(id, "poll resumed");
+
+                if let Some(ends) = ends {
+                    let now = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map(|d| d.as_secs())
+                        .unwrap_or_default();
+                    let after = Duration::from_secs(ends.saturating_sub(now));
+                    Self::schedule_end(name, id, after, current, Some(cancel_chan), cache, resp);
+                }
+            }
+            .instrument(debug_span!("Poll resume")),
+        );
+
+        Some(())
+    }
+}
+
+impl CmdDesc for Poll {
+    #[inline]
+    fn platform(&self) -> Platform {
+        self.platforms
+    }
+
+    #[inline]
+    fn description(&self, platform: Platform) -> Option<String> {
+        if platform.contains(Platform::DISCORD) {
+            return Some("Start or end a poll, chat votes by number or option".into());
+        }
+
+        None
+    }
+}
+
+impl Invokable for Poll {
+    fn args(&self, _platform: Platform) -> Vec<Arg> {
+        vec![
+            Arg {
+                name: "start".into(),
+                desc: "Start a poll".into(),
+                kind: ArgKind::SubCommand(vec![
+                    Arg {
+                        name: "question".into(),
+                        desc: "Question".into(),
+                        kind: ArgKind::String,
+                        optional: false,
+                    },
+                    Arg {
+                        name: "options".into(),
+                        desc: "Options, separated by commas".into(),
+                        kind: ArgKind::String,
+                        optional: false,
+                    },
+                ]),
+                optional: true,
+            },
+            Arg {
+                name: "end".into(),
+                desc: "End the poll and announce the results".into(),
+                kind: ArgKind::SubCommand(vec![]),
+                optional: true,
+            },
+            Arg {
+                name: "show".into(),
+                desc: "Show the running poll".into(),
+                kind: ArgKind::SubCommand(vec![]),
+                optional: true,
+            },
+        ]
+    }
+}
+
+impl TryFrom<&ArgMap> for Args {
+    type Error = ArgMapError;
+
+    fn try_from(value: &ArgMap) -> Result<Self, Self::Error> {
+        if let Some(ArgValue::SubCommand(c)) = value.get("start") {
+            let question = match c.get("question") {
+                Some(ArgValue::String(x)) if !x.trim().is_empty() => x.trim().to_owned(),
+                _ => return Err(ArgMapError),
+            };
+            let options = match c.get("options") {
+                Some(ArgValue::String(x)) => x
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|o| !o.is_empty())
+                    .map(str::to_owned)
+                    .collect(),
+                _ => return Err(ArgMapError),
+            };
+            Ok(Args::Start { question, options })
+        } else if let Some(ArgValue::SubCommand(_c)) = value.get("end") {
+            Ok(Args::End)
+        } else if let Some(ArgValue::SubCommand(_c)) = value.get("show") {
+            Ok(Args::Show)
+        } else {
+            Err(ArgMapError)
+        }
+    }
+}
@@ -0,0 +1,874 @@
+use super::{util, Arg, ArgKind, ArgValue, CmdDesc, Context, Invokable, RespHandle, RunRes};
+use crate::{
+    cache::{self, Cache, RespType},
+    db::{
+        self,
+        give::GiveError,
+        prediction::{OutcomeTotal, PredictionError, StakeOp},
+        Db, Resp,
+    },
+    error::{self, Error},
+    i18n::{plural, tr},
+    msg::{
+        corr_id, ArgMap, ArgMapError, Chat, Invocation, Location, Payload, Permissions, Platform,
+        Response,
+    },
+};
+use back_derive::command;
+use bb8_redis::redis;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use regex::Regex;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::watch;
+use tracing::{debug_span, Instrument};
+
+static PREDICT_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(\S+)(?:\s+(open|resolve|cancel)\b\s*(.*))?$").unwrap());
+
+static BET_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(\S+)\s+(\d+)\s+(.+)$").unwrap());
+
+/// Quoted phrases or single words
+static TOKEN_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r#""([^"]+)"|(\S+)"#).unwrap());
+
+const MIN_OUTCOMES: usize = 2;
+
+#[derive(Debug)]
+enum Args {
+    /// Restate the running prediction
+    Show,
+    Open {
+        question: String,
+        outcomes: Vec<String>,
+    },
+    Bet {
+        amount: i32,
+        outcome: String,
+    },
+    /// Outcome that won, by number or name
+    Resolve(String),
+    /// Refund everyone
+    Cancel,
+}
+
+/// The running prediction, kept in the cache until it's resolved so it outlives restarts.
+/// Stakes are in the db, which has the final say on whether bets are still taken
+#[derive(Debug, Serialize, Deserialize)]
+struct PredictionState {
+    /// Unix millis it was opened at, tells predictions apart
+    id: u64,
+    question: String,
+    outcomes: Vec<String>,
+    /// Unix secs bets are taken until
+    closes: u64,
+}
+
+impl PredictionState {
+    /// What the stakes are recorded against
+    fn db_id(&self, name: &str) -> Arc<String> {
+        Arc::new(format!("{}_{}", name, self.id))
+    }
+
+    fn betting(&self) -> bool {
+        now_secs() < self.closes
+    }
+
+    /// (points, bettors) in outcome order
+    fn totals(&self, totals: Vec<OutcomeTotal>) -> (Vec<i64>, Vec<u64>) {
+        let mut points = vec![0; self.outcomes.len()];
+        let mut bettors = vec![0; self.outcomes.len()];
+        for (outcome, staked, count) in totals {
+            if let Some(i) = usize::try_from(outcome)
+                .ok()
+                .filter(|i| *i < self.outcomes.len())
+            {
+                points[i] = staked;
+                bettors[i] = count.max(0) as u64;
+            }
+        }
+        (points, bettors)
+    }
+}
+
+type Current = Arc<RwLock<Option<Arc<PredictionState>>>>;
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[command(locks(state))]
+/// Let chat bet points on how something turns out, winners split the pot
+pub struct Prediction {
+    /// Command prefix
+    #[cmd(def("!predict"), constr(non_empty))]
+    prefix: String,
+    /// Command to bet with
+    #[cmd(def("!bet"), constr(non_empty))]
+    bet_prefix: String,
+    /// Autocorrect prefix
+    autocorrect: bool,
+    /// Platforms
+    #[cmd(defl("Platform::CHAT"))]
+    platforms: Platform,
+    /// Permissions to open, resolve and cancel predictions
+    #[cmd(defl("Permissions::MOD"))]
+    perms: Permissions,
+    /// Permissions to bet
+    #[cmd(defl("Permissions::NONE"))]
+    bet_perms: Permissions,
+    /// How long bets are taken for (in seconds)
+    #[cmd(def(120u64), constr(range = "10..=3600"))]
+    window: u64,
+    /// Max number of outcomes
+    #[cmd(def(5u64), constr(range = "2..=10"))]
+    max_outcomes: u64,
+    /// Min bet
+    #[cmd(def(10i64), constr(pos))]
+    min_amount: i64,
+    /// Max bet
+    #[cmd(def(10_000i64), constr(pos))]
+    max_amount: i64,
+    #[cmd(skip)]
+    current: Current,
+    /// Stops betting timers when the config changes, init picks them back up
+    #[cmd(skip)]
+    cancel_chan: RwLock<Option<watch::Receiver<()>>>,
+}
+
+impl Prediction {
+    fn parse_arguments(&self, chat: &Chat) -> Option<(bool, Args)> {
+        let captures = PREDICT_REGEX.captures(&chat.msg)?;
+
+        // check command prefix
+        let autocorrect = util::check_autocorrect(
+            &self.prefix,
+            &captures[1],
+            self.autocorrect,
+            &self.levenshtein,
+        )?;
+
+        let args = match captures.get(2).map(|m| m.as_str()) {
+            None => Args::Show,
+            Some("cancel") => Args::Cancel,
+            Some("resolve") => Args::Resolve(
+                Some(captures[3].trim())
+                    .filter(|o| !o.is_empty())?
+                    .to_owned(),
+            ),
+            Some(_) => {
+                let mut tokens = TOKEN_REGEX.captures_iter(&captures[3]).filter_map(|c| {
+                    c.get(1)
+                        .or_else(|| c.get(2))
+                        .map(|m| m.as_str().trim().to_owned())
+                });
+                Args::Open {
+                    question: tokens.next()?,
+                    outcomes: tokens.collect(),
+                }
+            }
+        };
+
+        Some((autocorrect, args))
+    }
+
+    /// None if the message isn't a bet
+    fn parse_bet(&self, chat: &Chat) -> Option<Option<Args>> {
+        let msg = chat.msg.trim();
+        let first = msg.split_whitespace().next()?;
+        if !first.eq_ignore_ascii_case(&self.bet_prefix) {
+            return None;
+        }
+
+        let args = BET_REGEX.captures(msg).map(|captures| Args::Bet {
+            amount: captures[2].parse::<i32>().unwrap_or(i32::MAX),
+            outcome: captures[3].trim().to_owned(),
+        });
+        Some(args)
+    }
+
+    fn can_run(&self, ctx: &Context<'_>) -> Option<()> {
+        if !self.enabled {
+            return None;
+        }
+
+        // check if platform is applicable
+        if !self.platforms.contains(ctx.platform) {
+            return None;
+        }
+
+        Some(())
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn chat(&self, ctx: &Context<'_>, chat: &Chat) -> error::Result<RunRes> {
+        if self.can_run(ctx).is_none() {
+            return Ok(RunRes::Disabled);
+        }
+
+        if let Some(bet) = self.parse_bet(chat) {
+            return match bet {
+                Some(args) => self.run(ctx, args).await,
+                None => Ok(RunRes::InvalidArgs),
+            };
+        }
+
+        let (autocorrect, args) = match self.parse_arguments(chat) {
+            Some(t) => t,
+            None if util::starts_with_prefix(&self.prefix, &chat.msg) => {
+                return Ok(RunRes::InvalidArgs)
+            }
+            None => return Ok(RunRes::Noop),
+        };
+
+        if autocorrect {
+            return Ok(RunRes::Autocorrect(self.prefix.clone()));
+        }
+
+        self.run(ctx, args).await
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn invoke(
+        &self,
+        ctx: &Context<'_>,
+        invocation: &Invocation,
+    ) -> Option<RunRes> {
+        self.can_run(ctx)?;
+
+        super::check_invoke_prefix(&self.prefix, &invocation.cmd)?;
+
+        let args = match Args::try_from(&invocation.args) {
+            Ok(args) => args,
+            Err(_) => return Some(RunRes::InvalidArgs),
+        };
+
+        match self.run(ctx, args).await {
+            Ok(r) => Some(r),
+            Err(e) => {
+                tracing::error!("{}", e);
+                None
+            }
+        }
+    }
+
+    fn state_key(name: &str) -> Arc<String> {
+        Arc::new(format!("{}_{}", &*PREDICTION_LOCK_STATE, name))
+    }
+
+    /// Index of the outcome picked, by number or name
+    fn choice(outcomes: &[String], choice: &str) -> Option<usize> {
+        match choice.parse::<usize>() {
+            Ok(n) if (1..=outcomes.len()).contains(&n) => Some(n - 1),
+            _ => outcomes.iter().position(|o| o.eq_ignore_ascii_case(choice)),
+        }
+    }
+
+    async fn reply(ctx: &Context<'_>, msg: String) {
+        Response {
+            platform: ctx.platform,
+            channel: crate::channel_name(),
+            corr_id: ctx.corr_id.clone(),
+            payload: Payload::Message {
+                user: ctx.reply_to(),
+                msg: msg.into(),
+                meta: ctx.meta.clone(),
+                hint: None,
+                thread: None,
+            },
+        }
+        .send(Location::Pubsub, ctx.resp)
+        .await;
+    }
+
+    async fn announce(resp: &RespHandle, msg: String) {
+        Response {
+            platform: Platform::CHAT,
+            channel: crate::channel_name(),
+            corr_id: corr_id(),
+            payload: Payload::Message {
+                user: None,
+                msg: msg.into(),
+                meta: None,
+                hint: None,
+                thread: None,
+            },
+        }
+        .send(Location::Pubsub, resp)
+        .await;
+    }
+
+    /// Tell web clients how the stakes stand
+    async fn update(
+        resp: &RespHandle,
+        name: &str,
+        state: &PredictionState,
+        totals: Vec<OutcomeTotal>,
+        ended: bool,
+        winner: Option<usize>,
+    ) {
+        let (points, bettors) = state.totals(totals);
+        Response {
+            platform: Platform::WEB,
+            channel: crate::channel_name(),
+            corr_id: corr_id(),
+            payload: Payload::PredictionUpdate {
+                name: name.to_owned(),
+                question: state.question.clone(),
+                outcomes: state.outcomes.clone(),
+                points,
+                bettors,
+                betting: !ended && state.betting(),
+                ended,
+                winner,
+            },
+        }
+        .send(Location::Websockets(None), resp)
+        .await;
+    }
+
+    async fn totals(db: &db::Handle, id: Arc<String>) -> error::Result<Vec<OutcomeTotal>> {
+        match Db::PredictionTotals(id).exec(db).await? {
+            Resp::PredictionTotals(totals) => Ok(totals),
+            _ => unreachable!(),
+        }
+    }
+
+    #[tracing::instrument(level = "trace", skip_all, name = "Prediction")]
+    async fn run(&self, ctx: &Context<'_>, args: Args) -> error::Result<RunRes> {
+        tracing::debug!(name = self.name.as_str(), user = ctx.user.name.as_str(), args = ?args);
+
+        // check perms
+        let perms = match args {
+            Args::Show => Permissions::NONE,
+            Args::Bet { .. } => self.bet_perms,
+            _ => self.perms,
+        };
+        if ctx.user.perms < perms {
+            return Ok(RunRes::Disabled);
+        }
+
+        match args {
+            Args::Show => {
+                let current = self.current.read().clone();
+                let msg = match current {
+                    Some(state) => {
+                        let totals = Self::totals(ctx.db, state.db_id(&self.name)).await?;
+                        let (points, bettors) = state.totals(totals);
+                        let separator = tr("list.separator", &[]);
+                        let totals = state
+                            .outcomes
+                            .iter()
+                            .zip(points.iter().zip(&bettors))
+                            .enumerate()
+                            .map(|(i, (outcome, (points, bettors)))| {
+                                tr(
+                                    "prediction.total",
+                                    &[
+                                        ("n", &(i + 1)),
+                                        ("outcome", outcome),
+                                        ("amount", &ctx.currency.format(*points)),
+                                        ("bettors", bettors),
+                                    ],
+                                )
+                            })
+                            .collect::<Vec<_>>()
+                            .join(&separator);
+                        tr(
+                            "prediction.running",
+                            &[("question", &state.question), ("totals", &totals)],
+                        )
+                    }
+                    None => tr("prediction.not_running", &[]),
+                };
+                Self::reply(ctx, msg).await;
+            }
+            Args::Open { question, outcomes } => self.open(ctx, question, outcomes).await?,
+            Args::Bet { amount, outcome } => return self.bet(ctx, amount, &outcome).await,
+            Args::Resolve(outcome) => {
+                let current = self.current.read().clone();
+                let winner = match current {
+                    Some(state) => Self::choice(&state.outcomes, &outcome),
+                    None => {
+                        Self::reply(ctx, tr("prediction.not_running", &[])).await;
+                        return Ok(RunRes::Ok);
+                    }
+                };
+                match winner {
+                    Some(winner) => self.settle(ctx, Some(winner)).await?,
+                    None => Self::reply(ctx, tr("prediction.unknown_outcome", &[])).await,
+                }
+            }
+            Args::Cancel => self.settle(ctx, None).await?,
+        }
+
+        Ok(RunRes::Ok)
+    }
+
+    async fn open(
+        &self,
+        ctx: &Context<'_>,
+        question: String,
+        outcomes: Vec<String>,
+    ) -> error::Result<()> {
+        let max = self.max_outcomes as usize;
+        if !(MIN_OUTCOMES..=max).contains(&outcomes.len()) {
+            let msg = tr(
+                "prediction.outcomes",
+                &[("min", &MIN_OUTCOMES), ("max", &max)],
+            );
+            Self::reply(ctx, msg).await;
+            return Ok(());
+        }
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+        let state = PredictionState {
+            id: now.as_millis() as u64,
+            question,
+            outcomes,
+            closes: now.as_secs() + self.window,
+        };
+
+        // only one prediction at a time, kept until it's resolved since its stakes are held till then
+        let state_key = Self::state_key(&self.name);
+        let json = serde_json::to_string(&state)?;
+        match Cache::Set(state_key.clone(), json.into(), 0, true)
+            .exec(ctx.cache)
+            .await?
+        {
+            RespType::Bool(true) => {}
+            RespType::Bool(false) => {
+                Self::reply(ctx, tr("prediction.already_running", &[])).await;
+                return Ok(());
+            }
+            _ => unreachable!(),
+        }
+
+        let closes = UNIX_EPOCH + Duration::from_secs(state.closes);
+        let opened = Db::OpenPrediction(
+            state.db_id(&self.name),
+            Arc::new(state.question.clone()),
+            closes,
+        )
+        .exec(ctx.db)
+        .await;
+        if let Err(e) = opened {
+            // nothing's staked yet, let it be opened again
+            Cache::Delete(state_key).exec(ctx.cache).await?;
+            return Err(e);
+        }
+
+        tracing::info!(question = state.question.as_str(), outcomes = ?state.outcomes, "prediction opened");
+
+        let separator = tr("list.separator", &[]);
+        let outcomes = state
+            .outcomes
+            .iter()
+            .enumerate()
+            .map(|(i, outcome)| {
+                tr(
+                    "prediction.option",
+                    &[("n", &(i + 1)), ("outcome", outcome)],
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(&separator);
+        let msg = tr(
+            "prediction.opened",
+            &[
+                ("question", &state.question),
+                ("bet", &self.bet_prefix),
+                ("window", &self.window),
+                ("outcomes", &outcomes),
+            ],
+        );
+
+        let state = Arc::new(state);
+        *self.current.write() = Some(state.clone());
+        Self::schedule_close(
+            self.name.clone(),
+            state.clone(),
+            self.current.clone(),
+            self.cancel_chan.read().clone(),
+            ctx.db.clone(),
+            ctx.resp.clone(),
+        );
+
+        Self::reply(ctx, msg).await;
+        Self::update(ctx.resp, &self.name, &state, vec![], false, None).await;
+
+        Ok(())
+    }
+
+    async fn bet(&self, ctx: &Context<'_>, amount: i32, outcome: &str) -> error::Result<RunRes> {
+        let current = self.current.read().clone();
+        let state = match current {
+            Some(state) => state,
+            None => {
+                Self::reply(ctx, tr("prediction.not_running", &[])).await;
+                return Ok(RunRes::Ok);
+            }
+        };
+
+        if !(self.min_amount..=self.max_amount).contains(&(amount as i64)) {
+            return Ok(RunRes::OutOfRange {
+                min: self.min_amount,
+                max: self.max_amount,
+            });
+        }
+
+        let index = match Self::choice(&state.outcomes, outcome) {
+            Some(i) => i,
+            None => {
+                Self::reply(ctx, tr("prediction.unknown_outcome", &[])).await;
+                return Ok(RunRes::Ok);
+            }
+        };
+
+        let op = StakeOp {
+            prediction: state.db_id(&self.name),
+            platform: ctx.platform,
+            id: ctx.user.id.clone(),
+            name: ctx.user.name.clone(),
+            outcome: index as i32,
+            amount,
+        };
+        let totals = match Db::Stake(op).exec(ctx.db).await {
+            Ok(Resp::PredictionTotals(totals)) => totals,
+            Ok(_) => unreachable!(),
+            Err(e) => {
+                let msg = match e {
+                    Error::GiveOp(GiveError::Deduct) => tr(
+                        "prediction.insufficient",
+                        &[("currency", &ctx.currency.plural)],
+                    ),
+                    Error::Prediction(PredictionError::Closed) => tr("prediction.closed", &[]),
+                    Error::Prediction(PredictionError::OtherOutcome(other)) => {
+                        let other = usize::try_from(other)
+                            .ok()
+                            .and_then(|i| state.outcomes.get(i))
+                            .map_or("?", String::as_str);
+                        tr("prediction.other_outcome", &[("outcome", &other)])
+                    }
+                    e => return Err(e),
+                };
+                Self::reply(ctx, msg).await;
+                return Ok(RunRes::Ok);
+            }
+        };
+
+        tracing::debug!(outcome = index, amount, "bet");
+
+        let msg = tr(
+            "prediction.bet",
+            &[
+                ("amount", &ctx.currency.format(amount)),
+                ("outcome", &state.outcomes[index]),
+            ],
+        );
+        Self::reply(ctx, msg).await;
+        Self::update(ctx.resp, &self.name, &state, totals, false, None).await;
+
+        Ok(RunRes::Ok)
+    }
+
+    /// Pay out the winner, or refund everyone if None
+    async fn settle(&self, ctx: &Context<'_>, winner: Option<usize>) -> error::Result<()> {
+        // whoever takes the state gets to settle it
+        let state_key = Self::state_key(&self.name);
+        let json = match Cache::GetDel(state_key.clone()).exec(ctx.cache).await {
+            Ok(RespType::String(json)) => json,
+            Ok(_) => unreachable!(),
+            Err(Error::Redis(e)) if e.kind() == redis::ErrorKind::TypeError => {
+                *self.current.write() = None;
+                Self::reply(ctx, tr("prediction.not_running", &[])).await;
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+        let state = serde_json::from_str::<PredictionState>(&json)?;
+
+        let id = state.db_id(&self.name);
+        let settled = Db::SettlePrediction(id.clone(), winner.map(|w| w as i32))
+            .exec(ctx.db)
+            .await;
+        let settlement = match settled {
+            Ok(Resp::Settled(settlement)) => settlement,
+            Ok(_) => unreachable!(),
+            Err(e) => {
+                // still holding everyone's stakes, so it has to stay resolvable
+                let _ = Cache::Set(state_key, json.into(), 0, true)
+                    .exec(ctx.cache)
+                    .await;
+                return Err(e);
+            }
+        };
+        *self.current.write() = None;
+
+        tracing::info!(
+            question = state.question.as_str(),
+            ?winner,
+            ?settlement,
+            "prediction settled"
+        );
+
+        let pool = ctx.currency.format(settlement.pool);
+        let msg = match winner {
+            Some(winner) if settlement.refunded => tr(
+                "prediction.refunded",
+                &[("outcome", &state.outcomes[winner]), ("pool", &pool)],
+            ),
+            Some(winner) => tr(
+                "prediction.resolved",
+                &[
+                    ("outcome", &state.outcomes[winner]),
+                    ("users", &settlement.users),
+                    ("s", &plural(settlement.users)),
+                    ("pool", &pool),
+                ],
+            ),
+            None => tr("prediction.cancelled", &[("pool", &pool)]),
+        };
+        Self::announce(ctx.resp, msg).await;
+
+        let totals = Self::totals(ctx.db, id).await.unwrap_or_else(|e| {
+            tracing::error!("{}", e);
+            vec![]
+        });
+        Self::update(ctx.resp, &self.name, &state, totals, true, winner).await;
+
+        Ok(())
+    }
+
+    /// Say when bets close, the db stops taking them either way
+    fn schedule_close(
+        name: String,
+        state: Arc<PredictionState>,
+        current: Current,
+        cancel_chan: Option<watch::Receiver<()>>,
+        db: db::Handle,
+        resp: RespHandle,
+    ) {
+        let after = Duration::from_secs(state.closes.saturating_sub(now_secs()));
+        tokio::spawn(
+            async move {
+                let cancelled = async move {
+                    match cancel_chan {
+                        Some(mut chan) => {
+                            let _ = chan.changed().await;
+                        }
+                        None => futures_util::future::pending().await,
+                    }
+                };
+
+                tokio::select! {
+                    _ = tokio::time::sleep(after) => {}
+                    // the prediction's still in the cache, init picks it back up
+                    _ = cancelled => return,
+                }
+
+                // settled in the meantime
+                if current.read().as_ref().map(|s| s.id) != Some(state.id) {
+                    return;
+                }
+
+                let totals = match Self::totals(&db, state.db_id(&name)).await {
+                    Ok(totals) => totals,
+                    Err(e) => {
+                        tracing::error!("{}", e);
+                        return;
+                    }
+                };
+                let bettors: i64 = totals.iter().map(|(_, _, count)| count).sum();
+                let msg = tr(
+                    "prediction.locked",
+                    &[
+                        ("question", &state.question),
+                        ("bettors", &bettors),
+                        ("s", &plural(bettors)),
+                    ],
+                );
+                Self::announce(&resp, msg).await;
+                Self::update(&resp, &name, &state, totals, false, None).await;
+            }
+            .instrument(debug_span!("Prediction timer")),
+        );
+    }
+
+    /// Keep the cancel chan for predictions opened from now on, and pick up any prediction
+    /// left running from before a restart or config change
+    pub(crate) fn init(
+        &self,
+        cancel_chan: watch::Receiver<()>,
+        cache: &cache::Handle,
+        db: &db::Handle,
+        resp: &RespHandle,
+    ) -> Option<()> {
+        *self.cancel_chan.write() = Some(cancel_chan.clone());
+
+        if !self.enabled {
+            return None;
+        }
+
+        let name = self.name.clone();
+        let current = self.current.clone();
+        let (cache, db, resp) = (cache.clone(), db.clone(), resp.clone());
+
+        tokio::spawn(
+            async move {
+                let state = match Cache::Get(Self::state_key(&name)).exec(&cache).await {
+                    Ok(RespType::String(state)) => state,
+                    Ok(_) => unreachable!(),
+                    Err(Error::Redis(e)) if e.kind() == redis::ErrorKind::TypeError => return,
+                    Err(e) => {
+                        tracing::error!("{}", e);
+                        return;
+                    }
+                };
+                let state = match serde_json::from_str::<PredictionState>(&state) {
+                    Ok(state) => Arc::new(state),
+                    Err(e) => {
+                        tracing::error!("{}", e);
+                        return;
+                    }
+                };
+
+                *current.write() = Some(state.clone());
+                tracing::info!(id = state.id, "prediction resumed");
+
+                if state.betting() {
+                    Self::schedule_close(name, state, current, Some(cancel_chan), db, resp);
+                }
+            }
+            .instrument(debug_span!("Prediction resume")),
+        );
+
+        Some(())
+    }
+}
+
+impl CmdDesc for Prediction {
+    #[inline]
+    fn platform(&self) -> Platform {
+        self.platforms
+    }
+
+    #[inline]
+    fn description(&self, platform: Platform) -> Option<String> {
+        if platform.contains(Platform::DISCORD) {
+            return Some("Bet points on how something turns out".into());
+        }
+
+        None
+    }
+}
+
+impl Invokable for Prediction {
+    fn args(&self, _platform: Platform) -> Vec<Arg> {
+        let outcome = |desc: &str| Arg {
+            name: "outcome".into(),
+            desc: desc.into(),
+            kind: ArgKind::String,
+            optional: false,
+        };
+        vec![
+            Arg {
+                name: "open".into(),
+                desc: "Open a prediction".into(),
+                kind: ArgKind::SubCommand(vec![
+                    Arg {
+                        name: "question".into(),
+                        desc: "Question".into(),
+                        kind: ArgKind::String,
+                        optional: false,
+                    },
+                    Arg {
+                        name: "outcomes".into(),
+                        desc: "Outcomes, separated by commas".into(),
+                        kind: ArgKind::String,
+                        optional: false,
+                    },
+                ]),
+                optional: true,
+            },
+            Arg {
+                name: "bet".into(),
+                desc: "Bet on an outcome".into(),
+                kind: ArgKind::SubCommand(vec![
+                    Arg {
+                        name: "amount".into(),
+                        desc: "Amount to bet".into(),
+                        kind: ArgKind::Integer {
+                            min: Some(self.min_amount),
+                            max: Some(self.max_amount),
+                        },
+                        optional: false,
+                    },
+                    outcome("Outcome, by number or name"),
+                ]),
+                optional: true,
+            },
+            Arg {
+                name: "resolve".into(),
+                desc: "Pay out the winning outcome".into(),
+                kind: ArgKind::SubCommand(vec![outcome("Winning outcome, by number or name")]),
+                optional: true,
+            },
+            Arg {
+                name: "cancel".into(),
+                desc: "Cancel the prediction and refund everyone".into(),
+                kind: ArgKind::SubCommand(vec![]),
+                optional: true,
+            },
+            Arg {
+                name: "show".into(),
+                desc: "Show the running prediction".into(),
+                kind: ArgKind::SubCommand(vec![]),
+                optional: true,
+            },
+        ]
+    }
+}
+
+impl TryFrom<&ArgMap> for Args {
+    type Error = ArgMapError;
+
+    fn try_from(value: &ArgMap) -> Result<Self, Self::Error> {
+        let string = |c: &ArgMap, name: &str| match c.get(name) {
+            Some(ArgValue::String(x)) if !x.trim().is_empty() => Ok(x.trim().to_owned()),
+            _ => Err(ArgMapError),
+        };
+
+        if let Some(ArgValue::SubCommand(c)) = value.get("open") {
+            let question = string(c, "question")?;
+            let outcomes = string(c, "outcomes")?
+                .split(',')
+                .map(str::trim)
+                .filter(|o| !o.is_empty())
+                .map(str::to_owned)
+                .collect();
+            Ok(Args::Open { question, outcomes })
+        } else if let Some(ArgValue::SubCommand(c)) = value.get("bet") {
+            let amount = match c.get("amount") {
+                Some(ArgValue::Integer(x)) => (*x).clamp(0, i32::MAX as i64) as i32,
+                _ => return Err(ArgMapError),
+            };
+            let outcome = string(c, "outcome")?;
+            Ok(Args::Bet { amount, outcome })
+        } else if let Some(ArgValue::SubCommand(c)) = value.get("resolve") {
+            Ok(Args::Resolve(string(c, "outcome")?))
+        } else if let Some(ArgValue::SubCommand(_c)) = value.get("cancel") {
+            Ok(Args::Cancel)
+        } else if let Some(ArgValue::SubCommand(_c)) = value.get("show") {
+            Ok(Args::Show)
+        } else {
+            Err(ArgMapError)
+        }
+    }
+}
@@ -0,0 +1,498 @@
+use super::{util, Arg, ArgKind, ArgValue, CmdDesc, Context, Invokable, RespHandle, RunRes};
+use crate::{
+    cache::{Cache, RespType},
+    error::{self, Error},
+    i18n::{plural, tr},
+    msg::{
+        corr_id, ArgMap, ArgMapError, Chat, Invocation, Location, Payload, Permissions, Platform,
+        Response,
+    },
+};
+use back_derive::command;
+use bb8_redis::redis;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+static QUEUE_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(\S+)(?:\s+(clear)\b)?").unwrap());
+
+#[derive(Debug)]
+enum Args {
+    /// List who's next
+    Show,
+    Join,
+    Leave,
+    Position,
+    /// Call up whoever's first
+    Next,
+    /// Empty the queue
+    Clear,
+}
+
+#[command(locks(entrants))]
+/// Let viewers line up to play with the streamer, first come first served
+pub struct Queue {
+    /// Command prefix, lists who's next
+    #[cmd(def("!queue"), constr(non_empty))]
+    prefix: String,
+    /// Command to join with
+    #[cmd(def("!join"), constr(non_empty))]
+    join_prefix: String,
+    /// Command to leave with
+    #[cmd(def("!leave"), constr(non_empty))]
+    leave_prefix: String,
+    /// Command to check your place with
+    #[cmd(def("!position"), constr(non_empty))]
+    position_prefix: String,
+    /// Command to call up the next viewer with
+    #[cmd(def("!next"), constr(non_empty))]
+    next_prefix: String,
+    /// Autocorrect prefix
+    autocorrect: bool,
+    /// Platforms
+    #[cmd(defl("Platform::CHAT"))]
+    platforms: Platform,
+    /// Permissions to call up the next viewer and clear the queue
+    #[cmd(defl("Permissions::MOD"))]
+    perms: Permissions,
+    /// Permissions to join
+    #[cmd(defl("Permissions::NONE"))]
+    join_perms: Permissions,
+    /// Max viewers waiting
+    #[cmd(def(50u64), constr(range = "1..=1000"))]
+    max_size: u64,
+    /// Members and subs are placed as if they'd joined this much earlier (in seconds)
+    member_head_start: u64,
+    /// Viewers listed by the prefix
+    #[cmd(def(5u64), constr(range = "1..=20"))]
+    shown: u64,
+}
+
+/// Unix millis, earlier joins are called up first
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+impl Queue {
+    fn parse_arguments(&self, chat: &Chat) -> Option<(bool, Args)> {
+        let first = chat.msg.split_whitespace().next()?;
+        let args = [
+            (&self.join_prefix, Args::Join),
+            (&self.leave_prefix, Args::Leave),
+            (&self.position_prefix, Args::Position),
+            (&self.next_prefix, Args::Next),
+        ]
+        .into_iter()
+        .find_map(|(prefix, args)| first.eq_ignore_ascii_case(prefix).then_some(args));
+        if let Some(args) = args {
+            return Some((false, args));
+        }
+
+        let captures = QUEUE_REGEX.captures(&chat.msg)?;
+
+        // check command prefix
+        let autocorrect = util::check_autocorrect(
+            &self.prefix,
+            &captures[1],
+            self.autocorrect,
+            &self.levenshtein,
+        )?;
+
+        let args = match captures.get(2) {
+            Some(_) => Args::Clear,
+            None => Args::Show,
+        };
+
+        Some((autocorrect, args))
+    }
+
+    fn can_run(&self, ctx: &Context<'_>) -> Option<()> {
+        if !self.enabled {
+            return None;
+        }
+
+        // check if platform is applicable
+        if !self.platforms.contains(ctx.platform) {
+            return None;
+        }
+
+        Some(())
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn chat(&self, ctx: &Context<'_>, chat: &Chat) -> error::Result<RunRes> {
+        if self.can_run(ctx).is_none() {
+            return Ok(RunRes::Disabled);
+        }
+
+        let (autocorrect, args) = match self.parse_arguments(chat) {
+            Some(t) => t,
+            None => return Ok(RunRes::Noop),
+        };
+
+        if autocorrect {
+            return Ok(RunRes::Autocorrect(self.prefix.clone()));
+        }
+
+        self.run(ctx, args).await
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn invoke(
+        &self,
+        ctx: &Context<'_>,
+        invocation: &Invocation,
+    ) -> Option<RunRes> {
+        self.can_run(ctx)?;
+
+        super::check_invoke_prefix(&self.prefix, &invocation.cmd)?;
+
+        let args = match Args::try_from(&invocation.args) {
+            Ok(args) => args,
+            Err(_) => return Some(RunRes::InvalidArgs),
+        };
+
+        match self.run(ctx, args).await {
+            Ok(r) => Some(r),
+            Err(e) => {
+                tracing::error!("{}", e);
+                None
+            }
+        }
+    }
+
+    /// Sorted set of entrants, scored by when they're up
+    fn entrants_key(&self) -> Arc<String> {
+        Arc::new(format!("{}_{}", &*QUEUE_LOCK_ENTRANTS, self.name))
+    }
+
+    /// Hash of entrants to their names at the time they joined
+    fn names_key(&self) -> Arc<String> {
+        Arc::new(format!("{}_{}_names", &*QUEUE_LOCK_ENTRANTS, self.name))
+    }
+
+    fn entrant(ctx: &Context<'_>) -> Arc<String> {
+        Arc::new(format!("{}:{}", ctx.platform, ctx.user.id))
+    }
+
+    async fn reply(ctx: &Context<'_>, msg: String) {
+        Response {
+            platform: ctx.platform,
+            channel: crate::channel_name(),
+            corr_id: ctx.corr_id.clone(),
+            payload: Payload::Message {
+                user: ctx.reply_to(),
+                msg: msg.into(),
+                meta: ctx.meta.clone(),
+                hint: None,
+                thread: None,
+            },
+        }
+        .send(Location::Pubsub, ctx.resp)
+        .await;
+    }
+
+    async fn announce(resp: &RespHandle, msg: String) {
+        Response {
+            platform: Platform::CHAT,
+            channel: crate::channel_name(),
+            corr_id: corr_id(),
+            payload: Payload::Message {
+                user: None,
+                msg: msg.into(),
+                meta: None,
+                hint: None,
+                thread: None,
+            },
+        }
+        .send(Location::Pubsub, resp)
+        .await;
+    }
+
+    /// (platform, name) of everyone waiting, in the order they're up
+    async fn list(&self, ctx: &Context<'_>) -> error::Result<Vec<(Platform, String)>> {
+        let entrants = match Cache::Zrange(self.entrants_key(), 0, -1)
+            .exec(ctx.cache)
+            .await?
+        {
+            RespType::VecString(entrants) => entrants,
+            _ => unreachable!(),
+        };
+        let names: HashMap<String, String> =
+            match Cache::HashGetAll(self.names_key()).exec(ctx.cache).await? {
+                RespType::VecStringString(names) => names.into_iter().collect(),
+                _ => unreachable!(),
+            };
+
+        Ok(entrants
+            .into_iter()
+            .filter_map(|entrant| {
+                let (platform, id) = entrant.split_once(':')?;
+                let platform = Platform::from_str(platform).ok()?;
+                let name = names.get(&entrant).map_or(id, String::as_str).to_owned();
+                Some((platform, name))
+            })
+            .collect())
+    }
+
+    /// Tell web clients who's waiting
+    async fn update(&self, ctx: &Context<'_>) -> error::Result<()> {
+        let entrants = self.list(ctx).await?;
+        Response {
+            platform: Platform::WEB,
+            channel: crate::channel_name(),
+            corr_id: corr_id(),
+            payload: Payload::ViewerQueue {
+                name: self.name.clone(),
+                entrants,
+                max_size: self.max_size,
+            },
+        }
+        .send(Location::Websockets(None), ctx.resp)
+        .await;
+        Ok(())
+    }
+
+    /// 1-based place in the queue, None if not in it
+    async fn position(&self, ctx: &Context<'_>) -> error::Result<Option<u64>> {
+        match Cache::Zrank(self.entrants_key(), Self::entrant(ctx))
+            .exec(ctx.cache)
+            .await
+        {
+            Ok(RespType::U64(rank)) => Ok(Some(rank + 1)),
+            Ok(_) => unreachable!(),
+            Err(Error::Redis(e)) if e.kind() == redis::ErrorKind::TypeError => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    #[tracing::instrument(level = "trace", skip_all, name = "Queue")]
+    async fn run(&self, ctx: &Context<'_>, args: Args) -> error::Result<RunRes> {
+        tracing::debug!(name = self.name.as_str(), user = ctx.user.name.as_str(), args = ?args);
+
+        // check perms
+        let perms = match args {
+            Args::Show | Args::Leave | Args::Position => Permissions::NONE,
+            Args::Join => self.join_perms,
+            Args::Next | Args::Clear => self.perms,
+        };
+        if ctx.user.perms < perms {
+            return Ok(RunRes::InsufficientPerms);
+        }
+
+        match args {
+            Args::Show => {
+                let entrants = self.list(ctx).await?;
+                let msg = if entrants.is_empty() {
+                    tr("queue.empty", &[])
+                } else {
+                    let separator = tr("list.separator", &[]);
+                    let next = entrants
+                        .iter()
+                        .take(self.shown as usize)
+                        .enumerate()
+                        .map(|(i, (_, name))| {
+                            tr("queue.entrant", &[("n", &(i + 1)), ("name", name)])
+                        })
+                        .collect::<Vec<_>>()
+                        .join(&separator);
+                    tr(
+                        "queue.list",
+                        &[
+                            ("next", &next),
+                            ("size", &entrants.len()),
+                            ("max", &self.max_size),
+                        ],
+                    )
+                };
+                Self::reply(ctx, msg).await;
+            }
+            Args::Join => {
+                if let Some(position) = self.position(ctx).await? {
+                    let msg = tr("queue.already_joined", &[("position", &position)]);
+                    Self::reply(ctx, msg).await;
+                    return Ok(RunRes::Ok);
+                }
+                let size = match Cache::Zcard(self.entrants_key()).exec(ctx.cache).await? {
+                    RespType::U64(size) => size,
+                    _ => unreachable!(),
+                };
+                if size >= self.max_size {
+                    let msg = tr("queue.full", &[("max", &self.max_size)]);
+                    Self::reply(ctx, msg).await;
+                    return Ok(RunRes::Ok);
+                }
+
+                let mut score = now_millis();
+                if ctx.user.perms >= Permissions::MEMBER {
+                    score -= self.member_head_start as i64 * 1000;
+                }
+                let entrant = Self::entrant(ctx);
+                Cache::HashSet(
+                    self.names_key(),
+                    entrant.clone(),
+                    ctx.user.name.to_string(),
+                    false,
+                )
+                .exec(ctx.cache)
+                .await?;
+                Cache::Zadd(self.entrants_key(), Arc::new(score.to_string()), entrant)
+                    .exec(ctx.cache)
+                    .await?;
+
+                let position = self.position(ctx).await?.unwrap_or(size + 1);
+                tracing::debug!(position, "joined queue");
+                let msg = tr("queue.joined", &[("position", &position)]);
+                Self::reply(ctx, msg).await;
+                self.update(ctx).await?;
+            }
+            Args::Leave => {
+                let entrant = Self::entrant(ctx);
+                let removed = match Cache::Zrem(self.entrants_key(), entrant.clone())
+                    .exec(ctx.cache)
+                    .await?
+                {
+                    RespType::Bool(removed) => removed,
+                    _ => unreachable!(),
+                };
+                if !removed {
+                    let msg = tr("queue.not_joined", &[("join", &self.join_prefix)]);
+                    Self::reply(ctx, msg).await;
+                    return Ok(RunRes::Ok);
+                }
+                Cache::HashDelete(self.names_key(), entrant)
+                    .exec(ctx.cache)
+                    .await?;
+                Self::reply(ctx, tr("queue.left", &[])).await;
+                self.update(ctx).await?;
+            }
+            Args::Position => {
+                let msg = match self.position(ctx).await? {
+                    Some(position) => tr("queue.position", &[("position", &position)]),
+                    None => tr("queue.not_joined", &[("join", &self.join_prefix)]),
+                };
+                Self::reply(ctx, msg).await;
+            }
+            Args::Next => {
+                // popped in one go, so two mods calling next at once get different viewers
+                let popped = match Cache::Zpopmin(self.entrants_key(), 1)
+                    .exec(ctx.cache)
+                    .await?
+                {
+                    RespType::VecStringScore(popped) => popped,
+                    _ => unreachable!(),
+                };
+                let entrant = match popped.into_iter().next() {
+                    Some((entrant, _)) => entrant,
+                    None => {
+                        Self::reply(ctx, tr("queue.empty", &[])).await;
+                        return Ok(RunRes::Ok);
+                    }
+                };
+
+                let names: HashMap<String, String> =
+                    match Cache::HashGetAll(self.names_key()).exec(ctx.cache).await? {
+                        RespType::VecStringString(names) => names.into_iter().collect(),
+                        _ => unreachable!(),
+                    };
+                let name = match names.get(&entrant) {
+                    Some(name) => name.clone(),
+                    None => entrant
+                        .split_once(':')
+                        .map_or(&*entrant, |(_, id)| id)
+                        .to_owned(),
+                };
+                Cache::HashDelete(self.names_key(), Arc::new(entrant))
+                    .exec(ctx.cache)
+                    .await?;
+
+                let waiting = match Cache::Zcard(self.entrants_key()).exec(ctx.cache).await? {
+                    RespType::U64(size) => size,
+                    _ => unreachable!(),
+                };
+                tracing::info!(name = name.as_str(), waiting, "called up from queue");
+                let msg = tr(
+                    "queue.next",
+                    &[
+                        ("name", &name),
+                        ("waiting", &waiting),
+                        ("s", &plural(waiting)),
+                    ],
+                );
+                Self::announce(ctx.resp, msg).await;
+                self.update(ctx).await?;
+            }
+            Args::Clear => {
+                Cache::Delete(self.entrants_key()).exec(ctx.cache).await?;
+                Cache::Delete(self.names_key()).exec(ctx.cache).await?;
+                tracing::info!("queue cleared");
+                Self::reply(ctx, tr("queue.cleared", &[])).await;
+                self.update(ctx).await?;
+            }
+        }
+
+        Ok(RunRes::Ok)
+    }
+}
+
+impl CmdDesc for Queue {
+    #[inline]
+    fn platform(&self) -> Platform {
+        self.platforms
+    }
+
+    #[inline]
+    fn description(&self, platform: Platform) -> Option<String> {
+        if platform.contains(Platform::DISCORD) {
+            return Some("Line up to play with the streamer".into());
+        }
+
+        None
+    }
+}
+
+impl Invokable for Queue {
+    fn args(&self, _platform: Platform) -> Vec<Arg> {
+        let subcommand = |name: &str, desc: &str| Arg {
+            name: name.into(),
+            desc: desc.into(),
+            kind: ArgKind::SubCommand(vec![]),
+            optional: true,
+        };
+        vec![
+            subcommand("join", "Join the queue"),
+            subcommand("leave", "Leave the queue"),
+            subcommand("position", "Check your place in the queue"),
+            subcommand("show", "List who's next"),
+            subcommand("next", "Call up the next viewer"),
+            subcommand("clear", "Empty the queue"),
+        ]
+    }
+}
+
+impl TryFrom<&ArgMap> for Args {
+    type Error = ArgMapError;
+
+    fn try_from(value: &ArgMap) -> Result<Self, Self::Error> {
+        let args = [
+            ("join", Args::Join),
+            ("leave", Args::Leave),
+            ("position", Args::Position),
+            ("show", Args::Show),
+            ("next", Args::Next),
+            ("clear", Args::Clear),
+        ];
+        args.into_iter()
+            .find(|(name, _)| matches!(value.get(*name), Some(ArgValue::SubCommand(_))))
+            .map(|(_, args)| args)
+            .ok_or(ArgMapError)
+    }
+}
@@ -21,10 +21,10 @@ pub struct Quote {
     #[cmd(defl("Permissions::NONE"))]
     perms: Permissions,
     /// Cooldown per user (in seconds)
-    #[cmd(constr(pos))]
+    #[cmd(constr(pos), group("Cooldowns"))]
     ratelimit_user: u64,
     /// Cooldown per use (in seconds)
-    #[cmd(constr(pos))]
+    #[cmd(constr(pos), group("Cooldowns"))]
     ratelimit: u64,
     /// Message
     #[cmd(def("<placeholder text - change me>"), constr(range = "1..=500"))]
@@ -51,7 +51,7 @@ impl Quote {
         Some(autocorrect)
     }
 
-    fn can_run(&self, ctx: &Context<'_>) -> Option<()> {
+    pub(super) fn can_run(&self, ctx: &Context<'_>) -> Option<()> {
         if !self.enabled || self.message.is_empty() {
             return None;
         }
@@ -157,11 +157,14 @@ impl Quote {
 
         Response {
             platform,
-            channel: &*crate::CHANNEL_NAME,
+            channel: crate::channel_name(),
+            corr_id: ctx.corr_id.clone(),
             payload: Payload::Message {
                 user,
                 msg: self.message.to_owned().into(),
                 meta: ctx.meta.clone(),
+                hint: None,
+                thread: None,
             },
         }
         .send(Location::Broadcast, ctx.resp)
@@ -0,0 +1,367 @@
+use super::{util, Context, ModAction, RunRes};
+use crate::{
+    cache::{self, Cache, RespType},
+    error::{self, Error},
+    i18n::{plural, tr},
+    msg::{corr_id, Chat, Invocation, Location, Payload, Permissions, Platform, Response},
+};
+use back_derive::command;
+use bb8_redis::redis;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::mpsc;
+
+/// Raid mode is re-read from redis this often, to pick up changes made by other instances
+const REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long chatters are remembered for, so they aren't counted as new again
+const SEEN_TTL: usize = 24 * 60 * 60;
+
+/// (fetched, when raid mode ends in unix secs)
+type Cached = (Instant, Option<u64>);
+
+/// Per platform, whether raid mode was on when last read from redis
+static ACTIVE: Lazy<RwLock<HashMap<Platform, Cached>>> = Lazy::new(Default::default);
+
+enum Edit {
+    /// minutes, 0 for the configured duration
+    On(u64),
+    Off,
+    Status,
+}
+
+#[command(filter)]
+/// Lock chat down when it looks like a raid, automatically or by a mod, and open it back up after
+pub struct RaidGuard {
+    /// Apply to anyone below permission level
+    #[cmd(defl("Permissions::MEMBER"))]
+    apply_to: Permissions,
+    /// Platforms
+    #[cmd(defl("Platform::STREAM"))]
+    platforms: Platform,
+    /// Mod action for messages caught while raid mode is on
+    #[cmd(defl("ModAction::Remove"), constr(range = "1..=86400"))]
+    action: ModAction,
+    /// Command prefix for turning raid mode on or off
+    #[cmd(def("!raidmode"), constr(non_empty))]
+    prefix: String,
+    /// Permissions for turning raid mode on or off
+    #[cmd(defl("Permissions::MOD"))]
+    perms: Permissions,
+    /// Window new chatters and messages are counted over (in seconds)
+    #[cmd(def(30u64), constr(range = "5..=600"), group("Triggers"))]
+    window: u64,
+    /// New chatters in a window that turn raid mode on (0 to not count them)
+    #[cmd(def(15u64), constr(range = "0..=10000"), group("Triggers"))]
+    max_new_chatters: u64,
+    /// Messages in a window that turn raid mode on (0 to not count them)
+    #[cmd(def(0u64), constr(range = "0..=100000"), group("Triggers"))]
+    max_messages: u64,
+    /// How long raid mode stays on (in minutes)
+    #[cmd(def(10u64), constr(range = "1..=1440"))]
+    duration: u64,
+    /// While on, only let through chatters at apply_to or above
+    #[cmd(group("While on"))]
+    members_only: bool,
+    /// While on, catch messages with links in them
+    #[cmd(def(true), group("While on"))]
+    block_links: bool,
+    /// While on, seconds between each chatter's messages (0 for no limit)
+    #[cmd(def(10u64), constr(range = "0..=3600"), group("While on"))]
+    slow_mode: u64,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+impl RaidGuard {
+    fn key(platform: Platform, what: &str) -> Arc<String> {
+        Arc::new(format!(
+            "aussiebot!{}!raidguard!{}!{}",
+            crate::channel_name(),
+            platform,
+            what
+        ))
+    }
+
+    fn parse_edit(&self, msg: &str) -> Option<Edit> {
+        let mut parts = msg.split_whitespace();
+        if !parts.next()?.eq_ignore_ascii_case(&self.prefix) {
+            return None;
+        }
+
+        let edit = match parts.next() {
+            None => Edit::Status,
+            Some(arg) if arg.eq_ignore_ascii_case("off") => Edit::Off,
+            Some(arg) if arg.eq_ignore_ascii_case("on") => match parts.next() {
+                Some(mins) => Edit::On(mins.parse::<u64>().ok().filter(|m| *m > 0)?),
+                None => Edit::On(0),
+            },
+            Some(_) => return None,
+        };
+
+        match parts.next() {
+            Some(_) => None,
+            None => Some(edit),
+        }
+    }
+
+    /// When raid mode ends on the platform (unix secs), if it's on
+    async fn until(cache: &cache::Handle, platform: Platform) -> error::Result<Option<u64>> {
+        if let Some((fetched, until)) = ACTIVE.read().get(&platform) {
+            if fetched.elapsed() < REFRESH_INTERVAL {
+                return Ok(until.filter(|u| *u > now_secs()));
+            }
+        }
+
+        let until = match Cache::Get(Self::key(platform, "active")).exec(cache).await {
+            Ok(RespType::String(until)) => until.parse().ok(),
+            Ok(_) => unreachable!(),
+            Err(Error::Redis(e)) if e.kind() == redis::ErrorKind::TypeError => None,
+            Err(e) => return Err(e),
+        }
+        .filter(|u| *u > now_secs());
+
+        ACTIVE.write().insert(platform, (Instant::now(), until));
+
+        Ok(until)
+    }
+
+    /// Turn raid mode on for `mins`, false if it already was.
+    /// Only one instance gets to turn it on, so it's only announced once
+    async fn start(&self, ctx: &Context<'_>, mins: u64, why: String) -> error::Result<bool> {
+        let secs = mins * 60;
+        let until = now_secs() + secs;
+        let set = Cache::Set(
+            Self::key(ctx.platform, "active"),
+            Arc::new(until.to_string()),
+            secs as usize,
+            true,
+        )
+        .exec(ctx.cache)
+        .await?;
+        if !matches!(set, RespType::Bool(true)) {
+            return Ok(false);
+        }
+        ACTIVE
+            .write()
+            .insert(ctx.platform, (Instant::now(), Some(until)));
+        tracing::warn!(platform = %ctx.platform, mins, why = why.as_str(), "raid mode on");
+
+        let msg = tr(
+            "raidguard.on",
+            &[("mins", &mins), ("s", &plural(mins)), ("why", &why)],
+        );
+        announce(ctx.resp, ctx.platform, msg).await;
+
+        // announce it lapsing, unless it's been turned off or on again since
+        let (cache, resp, platform) = (ctx.cache.clone(), ctx.resp.clone(), ctx.platform);
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_secs(secs)).await;
+            match Cache::Get(Self::key(platform, "active")).exec(&cache).await {
+                Err(Error::Redis(e)) if e.kind() == redis::ErrorKind::TypeError => {
+                    ACTIVE.write().insert(platform, (Instant::now(), None));
+                    tracing::info!(platform = %platform, "raid mode over");
+                    let msg = tr("raidguard.over", &[]);
+                    announce(&resp, platform, msg).await;
+                }
+                Err(e) => tracing::error!("{}", e),
+                Ok(_) => {}
+            }
+        });
+
+        Ok(true)
+    }
+
+    /// False if it wasn't on
+    async fn stop(ctx: &Context<'_>) -> error::Result<bool> {
+        let was_on = Self::until(ctx.cache, ctx.platform).await?.is_some();
+        Cache::Delete(Self::key(ctx.platform, "active"))
+            .exec(ctx.cache)
+            .await?;
+        ACTIVE.write().insert(ctx.platform, (Instant::now(), None));
+        if was_on {
+            tracing::info!(platform = %ctx.platform, user = ctx.user.name.as_str(), "raid mode off");
+        }
+        Ok(was_on)
+    }
+
+    /// Count the message and whether its chatter is new, turning raid mode on if either goes over
+    async fn watch(&self, ctx: &Context<'_>, chat: &Chat) -> error::Result<()> {
+        if self.max_new_chatters == 0 && self.max_messages == 0 {
+            return Ok(());
+        }
+
+        let bucket = now_secs() / self.window;
+        let expiry = (self.window * 2) as usize;
+
+        let mut tripped = None;
+        if self.max_messages > 0 {
+            let key = Self::key(ctx.platform, &format!("messages!{}", bucket));
+            match Cache::Increment(key, 1, expiry).exec(ctx.cache).await? {
+                RespType::U64(count) if count > self.max_messages => {
+                    tripped = Some(tr("raidguard.why_messages", &[("count", &count)]));
+                }
+                _ => {}
+            }
+        }
+        if self.max_new_chatters > 0 {
+            let seen = Cache::SetAdd(
+                Self::key(ctx.platform, "seen"),
+                chat.user.id.clone(),
+                SEEN_TTL,
+            )
+            .exec(ctx.cache)
+            .await?;
+            if let RespType::Bool(true) = seen {
+                let key = Self::key(ctx.platform, &format!("new!{}", bucket));
+                match Cache::Increment(key, 1, expiry).exec(ctx.cache).await? {
+                    RespType::U64(count) if count > self.max_new_chatters => {
+                        tripped = Some(tr("raidguard.why_new", &[("count", &count)]));
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        if let Some(why) = tripped {
+            self.start(ctx, self.duration, why).await?;
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn chat(&self, ctx: &Context<'_>, chat: &Chat) -> error::Result<RunRes> {
+        if !self.enabled || !self.platforms.contains(ctx.platform) {
+            return Ok(RunRes::Disabled);
+        }
+
+        if ctx.user.perms >= self.perms && util::starts_with_prefix(&self.prefix, &chat.msg) {
+            return self.edit_from_chat(ctx, chat).await;
+        }
+
+        match Self::until(ctx.cache, ctx.platform).await? {
+            Some(_) if ctx.user.perms < self.apply_to => self.run(ctx, chat).await,
+            Some(_) => Ok(RunRes::Ok),
+            None => {
+                self.watch(ctx, chat).await?;
+                Ok(RunRes::Ok)
+            }
+        }
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn invoke(
+        &self,
+        _ctx: &Context<'_>,
+        _invocation: &Invocation,
+    ) -> Option<RunRes> {
+        None
+    }
+
+    /// Raid mode's on and the chatter's below apply_to
+    #[tracing::instrument(level = "trace", skip_all, name = "RaidGuard")]
+    async fn run(&self, ctx: &Context<'_>, chat: &Chat) -> error::Result<RunRes> {
+        if util::is_permitted(ctx.cache, ctx.platform, &chat.user.id).await? {
+            return Ok(RunRes::Ok);
+        }
+
+        if self.members_only {
+            tracing::info!(
+                user = chat.user.name.as_str(),
+                "members only during raid mode"
+            );
+            return Ok(RunRes::Filtered(self.action));
+        }
+
+        if self.block_links && crate::urls::find(&chat.msg).next().is_some() {
+            tracing::info!(user = chat.user.name.as_str(), "link during raid mode");
+            return Ok(RunRes::Filtered(self.action));
+        }
+
+        if self.slow_mode > 0 {
+            let key = Self::key(ctx.platform, &format!("last!{}", chat.user.id));
+            let set = Cache::Set(key, Arc::new("1".into()), self.slow_mode as usize, true)
+                .exec(ctx.cache)
+                .await?;
+            if let RespType::Bool(false) = set {
+                tracing::info!(user = chat.user.name.as_str(), "too soon during raid mode");
+                return Ok(RunRes::Filtered(self.action));
+            }
+        }
+
+        Ok(RunRes::Ok)
+    }
+
+    async fn edit_from_chat(&self, ctx: &Context<'_>, chat: &Chat) -> error::Result<RunRes> {
+        let msg = match self.parse_edit(&chat.msg) {
+            Some(Edit::On(mins)) => {
+                let mins = if mins == 0 { self.duration } else { mins };
+                // a mod turning it on again picks the new duration
+                Self::stop(ctx).await?;
+                let why = tr("raidguard.why_manual", &[("user", &ctx.user.name)]);
+                self.start(ctx, mins, why).await?;
+                return Ok(RunRes::Ok);
+            }
+            Some(Edit::Off) => match Self::stop(ctx).await? {
+                true => {
+                    let msg = tr("raidguard.off", &[]);
+                    announce(ctx.resp, ctx.platform, msg).await;
+                    return Ok(RunRes::Ok);
+                }
+                false => tr("raidguard.not_on", &[]),
+            },
+            Some(Edit::Status) => match Self::until(ctx.cache, ctx.platform).await? {
+                Some(until) => {
+                    let mins = until.saturating_sub(now_secs()).div_ceil(60);
+                    tr("raidguard.status", &[("mins", &mins), ("s", &plural(mins))])
+                }
+                None => tr("raidguard.not_on", &[]),
+            },
+            None => tr("raidguard.usage", &[("prefix", &self.prefix)]),
+        };
+
+        Response {
+            platform: ctx.platform,
+            channel: crate::channel_name(),
+            corr_id: ctx.corr_id.clone(),
+            payload: Payload::Message {
+                user: ctx.reply_to(),
+                msg: msg.into(),
+                meta: ctx.meta.clone(),
+                hint: None,
+                thread: None,
+            },
+        }
+        .send(Location::Pubsub, ctx.resp)
+        .await;
+
+        Ok(RunRes::Ok)
+    }
+}
+
+async fn announce(resp: &mpsc::Sender<(Location, Response)>, platform: Platform, msg: String) {
+    Response {
+        platform,
+        channel: crate::channel_name(),
+        corr_id: corr_id(),
+        payload: Payload::Message {
+            user: None,
+            msg: msg.into(),
+            meta: None,
+            hint: None,
+            thread: None,
+        },
+    }
+    .send(Location::Pubsub, resp)
+    .await;
+}
@@ -1,24 +1,41 @@
 use super::{CmdDesc, Context, Invokable, RunRes};
 use crate::{
-    error,
+    cache::{self, Cache, RespType},
+    error::{self, Error},
     msg::{
-        discord::{self, DiscordAction},
-        Chat, ChatMeta, Invocation, InvocationKind, Payload, Platform, Response,
+        discord::{self, DiscordAction, RoleMenu},
+        Chat, ChatMeta, Invocation, InvocationKind, Location, Payload, Platform, Response,
     },
 };
 use back_derive::command;
+use bb8_redis::redis;
 use std::sync::Arc;
 
-#[command(cmd)]
+#[command(cmd, locks(menu))]
 /// Let users self-assign a role by reacting to a message (Discord-specific)
 pub struct ReactionRole {
     /// Emoji (or ID if custom)
     #[cmd(def("🤔"))]
     emoji: String,
-    /// Message ID to watch for reactions on
+    /// Message ID to watch for reactions on (leave empty for the bot to post a role menu in channel_id)
     message_id: String,
     /// Role ID to add/remove
     role_id: String,
+    /// Channel ID of the role menu, its reactions and roles are synced when the bot starts and the config changes
+    channel_id: Option<String>,
+    /// Role menu posted by the bot ({roles} is filled in with the choices)
+    #[cmd(def("React to pick your roles:\n{roles}"), constr(range = "1..=2000"))]
+    template: String,
+    /// More choices, one per entry as `emoji role_id`, or `emoji role_id group` to only let users have one role of the group
+    roles: Vec<String>,
+}
+
+/// One emoji → role choice
+#[derive(Debug)]
+struct Entry<'a> {
+    emoji: &'a str,
+    role_id: &'a str,
+    group: Option<&'a str>,
 }
 
 impl ReactionRole {
@@ -33,31 +50,34 @@ impl ReactionRole {
         ctx: &Context<'_>,
         invocation: &Invocation,
     ) -> Option<RunRes> {
-        if !self.enabled || self.role_id.is_empty() {
+        if !self.enabled {
             return None;
         }
 
-        // check message id and eomji
-        match invocation.kind {
+        let res = match invocation.kind {
+            Some(InvocationKind::Init) => self.sync(ctx).await,
             Some(InvocationKind::Reaction {
                 ref message_id,
                 ref emoji,
-            }) if self.message_id == *message_id && self.emoji == *emoji => {}
-            _ => return None,
-        }
+            }) => {
+                let is_add = match invocation.cmd.as_str() {
+                    "@reaction_add" => true,
+                    "@reaction_rem" => false,
+                    _ => return None,
+                };
 
-        let is_add = match invocation.cmd.as_str() {
-            "@reaction_add" => true,
-            "@reaction_rem" => false,
-            _ => return None,
-        };
+                let guild_id = match invocation.meta {
+                    Some(ChatMeta::Discord4(ref guild_id)) => Some(guild_id.clone()),
+                    _ => None,
+                };
 
-        let guild_id = match invocation.meta {
-            Some(ChatMeta::Discord4(ref guild_id)) => Some(guild_id.clone()),
-            _ => None,
+                self.run(ctx, message_id, emoji, is_add, guild_id).await
+            }
+            _ => return None,
         };
 
-        match self.run(ctx, is_add, guild_id).await {
+        match res {
+            Ok(RunRes::Noop) => None,
             Ok(r) => Some(r),
             Err(e) => {
                 tracing::error!("{}", e);
@@ -66,19 +86,155 @@ impl ReactionRole {
         }
     }
 
+    /// The single emoji/role_id pair, then the ones in `roles`. Malformed entries are skipped
+    fn entries(&self) -> Vec<Entry<'_>> {
+        let single = (!self.role_id.is_empty()).then(|| Entry {
+            emoji: &self.emoji,
+            role_id: &self.role_id,
+            group: None,
+        });
+
+        let listed = self.roles.iter().filter_map(|entry| {
+            let mut parts = entry.split_whitespace();
+            match (parts.next(), parts.next(), parts.next(), parts.next()) {
+                (Some(emoji), Some(role_id), group, None) => Some(Entry {
+                    emoji,
+                    role_id,
+                    group,
+                }),
+                _ => {
+                    tracing::warn!(
+                        name = self.name.as_str(),
+                        entry = entry.as_str(),
+                        "invalid role menu entry"
+                    );
+                    None
+                }
+            }
+        });
+
+        single.into_iter().chain(listed).collect()
+    }
+
+    fn menu_key(name: &str) -> Arc<String> {
+        Arc::new(format!("{}_{}", &*REACTIONROLE_LOCK_MENU, name))
+    }
+
+    /// Remember the role menu the bot posted for the ReactionRole named `name`
+    pub(crate) async fn posted(
+        cache: &cache::Handle,
+        name: &str,
+        message_id: Arc<String>,
+    ) -> error::Result<()> {
+        tracing::info!(name, message_id = message_id.as_str(), "role menu posted");
+        Cache::Set(Self::menu_key(name), message_id, 0, false)
+            .exec(cache)
+            .await?;
+        Ok(())
+    }
+
+    /// The configured message, or the menu the bot posted if there isn't one
+    async fn message_id(&self, cache: &cache::Handle) -> error::Result<Option<Arc<String>>> {
+        if !self.message_id.is_empty() {
+            return Ok(Some(self.message_id.clone().into()));
+        }
+        if self.channel_id.is_none() {
+            return Ok(None);
+        }
+
+        match Cache::Get(Self::menu_key(&self.name)).exec(cache).await {
+            Ok(RespType::String(id)) => Ok(Some(id.into())),
+            Ok(_) => unreachable!(),
+            Err(Error::Redis(e)) if e.kind() == redis::ErrorKind::TypeError => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn content(&self, entries: &[Entry<'_>]) -> String {
+        let roles = entries
+            .iter()
+            .map(|entry| {
+                // custom emojis are configured by id, any name renders
+                let emoji = if entry.emoji.chars().all(|c| c.is_ascii_digit()) {
+                    format!("<:_:{}>", entry.emoji)
+                } else {
+                    entry.emoji.to_owned()
+                };
+                format!("{} <@&{}>", emoji, entry.role_id)
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        self.template
+            .replace("{roles}", &roles)
+            .replace("\\n", "\n")
+    }
+
+    /// Have the discord bot post or update the menu and bring roles in line with the reactions
+    #[tracing::instrument(skip_all, name = "ReactionRole sync")]
+    async fn sync(&self, ctx: &Context<'_>) -> error::Result<RunRes> {
+        let channel_id = match self.channel_id {
+            Some(ref channel_id) => channel_id,
+            None => return Ok(RunRes::Noop),
+        };
+        let entries = self.entries();
+        if entries.is_empty() {
+            return Ok(RunRes::Noop);
+        }
+
+        let menu = RoleMenu {
+            name: self.name.clone().into(),
+            channel_id: channel_id.clone().into(),
+            message_id: self.message_id(ctx.cache).await?,
+            content: self.content(&entries).into(),
+            roles: entries
+                .iter()
+                .map(|entry| {
+                    (
+                        entry.emoji.to_owned().into(),
+                        entry.role_id.to_owned().into(),
+                    )
+                })
+                .collect(),
+        };
+        tracing::debug!(name = self.name.as_str(), menu = ?menu);
+
+        Response {
+            platform: Platform::DISCORD,
+            channel: crate::channel_name(),
+            corr_id: ctx.corr_id.clone(),
+            payload: Payload::Discord(DiscordAction::SyncRoleMenu(menu)),
+        }
+        .send(Location::Pubsub, ctx.resp)
+        .await;
+
+        Ok(RunRes::Ok)
+    }
+
     #[tracing::instrument(skip(self, ctx), name = "ReactionRole")]
     async fn run(
         &self,
         ctx: &Context<'_>,
+        message_id: &str,
+        emoji: &str,
         is_add: bool,
         guild_id: Option<Arc<String>>,
     ) -> error::Result<RunRes> {
-        tracing::debug!(name = self.name.as_str(), user = ctx.user.id.as_str(), role=%self.role_id, is_add = %is_add);
+        let entries = self.entries();
+        let entry = match entries.iter().find(|entry| entry.emoji == emoji) {
+            Some(entry) => entry,
+            None => return Ok(RunRes::Noop),
+        };
+        let menu_id = match self.message_id(ctx.cache).await? {
+            Some(id) if id.as_str() == message_id => id,
+            _ => return Ok(RunRes::Noop),
+        };
 
-        let inner = discord::Role {
+        tracing::debug!(name = self.name.as_str(), user = ctx.user.id.as_str(), role = entry.role_id, is_add = %is_add);
+
+        let role = |role_id: &str| discord::Role {
             user_id: ctx.user.id.clone(),
-            role_id: self.role_id.clone().into(),
-            guild_id,
+            role_id: role_id.to_owned().into(),
+            guild_id: guild_id.clone(),
             reason: Some(
                 if self.name.is_empty() {
                     "ReactionRole".to_owned()
@@ -89,19 +245,40 @@ impl ReactionRole {
             ),
         };
 
-        let action = if is_add {
-            DiscordAction::AddRole(inner)
+        let mut actions = vec![];
+        if is_add {
+            actions.push(DiscordAction::AddRole(role(entry.role_id)));
+
+            // only one role per group, the others' reactions come off too
+            let others = entries
+                .iter()
+                .filter(|other| other.group.is_some() && other.group == entry.group)
+                .filter(|other| other.emoji != entry.emoji);
+            for other in others {
+                actions.push(DiscordAction::RemoveRole(role(other.role_id)));
+                if let Some(ref channel_id) = self.channel_id {
+                    actions.push(DiscordAction::RemoveReaction(discord::Reaction {
+                        channel_id: channel_id.clone().into(),
+                        message_id: menu_id.clone(),
+                        user_id: ctx.user.id.clone(),
+                        emoji: other.emoji.to_owned().into(),
+                    }));
+                }
+            }
         } else {
-            DiscordAction::RemoveRole(inner)
-        };
+            actions.push(DiscordAction::RemoveRole(role(entry.role_id)));
+        }
 
-        Response {
-            platform: ctx.platform,
-            channel: &*crate::CHANNEL_NAME,
-            payload: Payload::Discord(action),
+        for action in actions {
+            Response {
+                platform: ctx.platform,
+                channel: crate::channel_name(),
+                corr_id: ctx.corr_id.clone(),
+                payload: Payload::Discord(action),
+            }
+            .send(ctx.location.clone(), ctx.resp)
+            .await;
         }
-        .send(ctx.location.clone(), ctx.resp)
-        .await;
 
         Ok(RunRes::Ok)
     }
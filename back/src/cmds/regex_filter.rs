@@ -1,10 +1,16 @@
-use super::{Context, ModAction, RunRes};
+use super::{normalize::Normalizer, util, Context, ModAction, RunRes};
 use crate::{
     error,
     msg::{Chat, Invocation, Permissions, Platform},
 };
 use back_derive::command;
-use regex::Regex;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use regex::{Regex, RegexBuilder};
+use std::collections::HashMap;
+
+/// Patterns recompiled for the filters' matching options, by (pattern, options)
+static COMPILED: Lazy<RwLock<HashMap<(String, Normalizer), Regex>>> = Lazy::new(Default::default);
 
 #[command(filter)]
 /// Filter chat by matching username, id and/or message against regex patterns
@@ -27,6 +33,19 @@ pub struct RegexFilter {
     /// User id matches
     #[cmd(defl(r#"Regex::new("").unwrap()"#))]
     id_pattern: Regex,
+    /// Let through users given a temporary permit, e.g. raiders (for link patterns)
+    allow_permitted: bool,
+    /// Match messages with shortened links expanded (those on URL_EXPAND_HOSTS)
+    expand_links: bool,
+    /// Ignore case in username and message
+    #[cmd(group("Matching"))]
+    ignore_case: bool,
+    /// Unicode NFKC normalization, e.g. fullwidth or math-styled letters count as plain ones
+    #[cmd(group("Matching"))]
+    nfkc: bool,
+    /// Fold lookalike letters from other scripts into latin ones, and ignore invisible characters
+    #[cmd(group("Matching"))]
+    fold_confusables: bool,
 }
 
 impl RegexFilter {
@@ -48,12 +67,64 @@ impl RegexFilter {
         Some(())
     }
 
+    /// Applied to text and patterns alike, case is left to the regex
+    fn normalizer(&self) -> Normalizer {
+        Normalizer {
+            ignore_case: false,
+            nfkc: self.nfkc,
+            fold_confusables: self.fold_confusables,
+        }
+    }
+
+    /// `pattern` normalized and made case-insensitive if asked to, compiled once
+    fn compiled(&self, pattern: &Regex) -> Regex {
+        let normalizer = self.normalizer();
+        if normalizer.is_noop() && !self.ignore_case {
+            return pattern.clone();
+        }
+
+        let key = (
+            pattern.as_str().to_owned(),
+            Normalizer {
+                ignore_case: self.ignore_case,
+                ..normalizer
+            },
+        );
+        if let Some(regex) = COMPILED.read().get(&key) {
+            return regex.clone();
+        }
+
+        let regex = match RegexBuilder::new(&normalizer.apply(pattern.as_str()))
+            .case_insensitive(self.ignore_case)
+            .build()
+        {
+            Ok(regex) => regex,
+            Err(e) => {
+                tracing::warn!("couldn't recompile '{}', matching as is: {}", pattern, e);
+                pattern.clone()
+            }
+        };
+        COMPILED.write().insert(key, regex.clone());
+        regex
+    }
+
     #[tracing::instrument(level = "trace", skip_all)]
     pub(super) async fn chat(&self, ctx: &Context<'_>, chat: &Chat) -> error::Result<RunRes> {
         if self.can_run(ctx).is_none() {
             return Ok(RunRes::Disabled);
         }
-        self.run(chat).await
+        let res = self.run(chat).await?;
+
+        // only checked once tripped, to keep the lookup off every message
+        if self.allow_permitted
+            && matches!(res, RunRes::Filtered(_))
+            && util::is_permitted(ctx.cache, ctx.platform, &chat.user.id).await?
+        {
+            tracing::info!(user = chat.user.name.as_str(), "permitted past filter");
+            return Ok(RunRes::Ok);
+        }
+
+        Ok(res)
     }
 
     #[tracing::instrument(level = "trace", skip_all)]
@@ -72,10 +143,13 @@ impl RegexFilter {
 
         let filter_action = RunRes::Filtered(self.action);
         let mut triggered: [Option<bool>; 3] = [None; 3];
+        let normalizer = self.normalizer();
 
         //if let Some(ref cache) = *ctx.filter_cache.read() {
         if !self.user_pattern.as_str().is_empty() {
-            let cond = self.user_pattern.is_match(&chat.user.name);
+            let cond = self
+                .compiled(&self.user_pattern)
+                .is_match(&normalizer.apply(&chat.user.name));
             if cond {
                 tracing::info!(
                     "\x1b[91mUsername {} matches '{}'\x1b[0m",
@@ -99,7 +173,14 @@ impl RegexFilter {
         }
 
         if !self.msg_pattern.as_str().is_empty() {
-            let cond = self.msg_pattern.is_match(&chat.msg);
+            let text = chat.text_without_emotes();
+            let text = match self.expand_links {
+                true => crate::urls::expand_all(&text).await,
+                false => text,
+            };
+            let cond = self
+                .compiled(&self.msg_pattern)
+                .is_match(&normalizer.apply(&text));
             if cond {
                 tracing::info!(
                     "\x1b[91mMessage from {} matches '{}'\x1b[0m",
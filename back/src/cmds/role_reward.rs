@@ -0,0 +1,356 @@
+use super::{util, CmdDesc, Context, Invokable, RunRes};
+use crate::{
+    cache::{self, Cache, RespType},
+    db::{self, Db, Resp},
+    error,
+    i18n::tr,
+    msg::{
+        corr_id,
+        discord::{self, DiscordAction},
+        Chat, Invocation, Location, Payload, Permissions, Platform, Response,
+    },
+};
+use back_derive::command;
+use std::{collections::HashSet, sync::Arc, time::Duration};
+use tokio::sync::{mpsc, watch};
+use tracing::{debug_span, Instrument};
+
+type RespHandle = mpsc::Sender<(Location, Response)>;
+
+#[command(locks(rate, awarded))]
+/// Give a Discord role to users with enough points, across linked accounts
+pub struct RoleReward {
+    /// Command prefix for manually claiming the role
+    #[cmd(def("!redeemrole"), constr(non_empty))]
+    prefix: String,
+    /// Autocorrect prefix
+    autocorrect: bool,
+    /// Permissions
+    #[cmd(defl("Permissions::NONE"))]
+    perms: Permissions,
+    /// Cooldown per user (in seconds)
+    #[cmd(constr(pos), group("Cooldowns"))]
+    ratelimit_user: u64,
+    /// Points needed for the role
+    #[cmd(def(1000_u64), constr(pos))]
+    threshold: u64,
+    /// Role ID to add/remove
+    role_id: String,
+    /// Role name shown in responses
+    #[cmd(def("the reward role"))]
+    label: String,
+    /// Remove the role from users who drop below the threshold
+    remove_below: bool,
+    /// How often to sync roles (in seconds, 0 to only sync on claims)
+    #[cmd(def(600_u64), constr(range = "0..=86400"))]
+    sync_interval: u64,
+}
+
+impl RoleReward {
+    fn parse_arguments(&self, chat: &Chat) -> Option<bool> {
+        let captures = util::PREFIX_REGEX.captures(&chat.msg)?;
+
+        // check command prefix
+        let autocorrect = util::check_autocorrect(
+            &self.prefix,
+            &captures[1],
+            self.autocorrect,
+            &self.levenshtein,
+        )?;
+
+        Some(autocorrect)
+    }
+
+    fn can_run(&self, ctx: &Context<'_>) -> Option<()> {
+        if !self.enabled || self.role_id.is_empty() {
+            return None;
+        }
+
+        // roles only exist on discord
+        if ctx.platform != Platform::DISCORD {
+            return None;
+        }
+
+        // check perms
+        if ctx.user.perms < self.perms {
+            return None;
+        }
+
+        Some(())
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn chat(&self, ctx: &Context<'_>, chat: &Chat) -> error::Result<RunRes> {
+        if self.can_run(ctx).is_none() {
+            return Ok(RunRes::Disabled);
+        }
+
+        let autocorrect = match self.parse_arguments(chat) {
+            Some(t) => t,
+            None => return Ok(RunRes::Noop),
+        };
+
+        if autocorrect {
+            return Ok(RunRes::Autocorrect(self.prefix.clone()));
+        }
+
+        match util::ratelimit_user(
+            ctx,
+            self.ratelimit_user,
+            stringify!(RoleReward),
+            &self.name,
+            &*ROLEREWARD_LOCK_RATE,
+        )
+        .await
+        {
+            Ok(false) => {}
+            Ok(true) => return Ok(RunRes::Ratelimited { global: false }),
+            Err(e) => return Err(e),
+        }
+
+        self.run(ctx).await
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn invoke(
+        &self,
+        ctx: &Context<'_>,
+        invocation: &Invocation,
+    ) -> Option<RunRes> {
+        self.can_run(ctx)?;
+
+        super::check_invoke_prefix(&self.prefix, &invocation.cmd)?;
+
+        match util::ratelimit_user(
+            ctx,
+            self.ratelimit_user,
+            stringify!(RoleReward),
+            &self.name,
+            &*ROLEREWARD_LOCK_RATE,
+        )
+        .await
+        {
+            Ok(false) => {}
+            Ok(true) => return None,
+            Err(e) => {
+                tracing::error!("{}", e);
+                return None;
+            }
+        }
+
+        match self.run(ctx).await {
+            Ok(r) => Some(r),
+            Err(e) => {
+                tracing::error!("{}", e);
+                None
+            }
+        }
+    }
+
+    /// Manual claim
+    #[tracing::instrument(level = "trace", skip_all, name = "RoleReward")]
+    async fn run(&self, ctx: &Context<'_>) -> error::Result<RunRes> {
+        tracing::debug!(name = self.name.as_str(), user = ctx.user.name.as_str());
+
+        let resp = Db::GetPoints(Platform::DISCORD, ctx.user.id.clone())
+            .exec(ctx.db)
+            .await?;
+
+        let total: i64 = match resp {
+            db::Resp::GetPoints(l) => l.iter().filter_map(|(_, p)| *p).map(i64::from).sum(),
+            _ => unreachable!(),
+        };
+
+        let msg = if total >= self.threshold as i64 {
+            let key = Self::awarded_key(&self.name);
+            Cache::HashSet(key, ctx.user.id.clone(), "1".into(), false)
+                .exec(ctx.cache)
+                .await?;
+            Self::send_role(
+                ctx.resp,
+                &self.name,
+                &self.role_id,
+                ctx.user.id.clone(),
+                true,
+            )
+            .await;
+            tr("role_reward.awarded", &[("label", &self.label)])
+        } else {
+            tr(
+                "role_reward.short",
+                &[
+                    ("points", &(self.threshold as i64 - total)),
+                    ("label", &self.label),
+                ],
+            )
+        };
+
+        Response {
+            platform: ctx.platform,
+            channel: crate::channel_name(),
+            corr_id: ctx.corr_id.clone(),
+            payload: Payload::Message {
+                user: ctx.reply_to(),
+                msg: msg.into(),
+                meta: ctx.meta.clone(),
+                hint: None,
+                thread: None,
+            },
+        }
+        .send(Location::Pubsub, ctx.resp)
+        .await;
+
+        Ok(RunRes::Ok)
+    }
+
+    fn awarded_key(name: &str) -> Arc<String> {
+        Arc::new(format!("{}_{}", &*ROLEREWARD_LOCK_AWARDED, name))
+    }
+
+    async fn send_role(
+        resp: &RespHandle,
+        name: &str,
+        role_id: &str,
+        user_id: Arc<String>,
+        is_add: bool,
+    ) {
+        let inner = discord::Role {
+            user_id,
+            role_id: role_id.to_owned().into(),
+            guild_id: None,
+            reason: Some(
+                if name.is_empty() {
+                    "RoleReward".to_owned()
+                } else {
+                    format!("RoleReward ({})", name)
+                }
+                .into(),
+            ),
+        };
+
+        let action = if is_add {
+            DiscordAction::AddRole(inner)
+        } else {
+            DiscordAction::RemoveRole(inner)
+        };
+
+        Response {
+            platform: Platform::DISCORD,
+            channel: crate::channel_name(),
+            corr_id: corr_id(),
+            payload: Payload::Discord(action),
+        }
+        .send(Location::Pubsub, resp)
+        .await;
+    }
+
+    /// Add the role to users who crossed the threshold since the last sync,
+    /// and remove it from those who fell below if configured to
+    async fn sync(
+        name: &str,
+        role_id: &str,
+        threshold: i32,
+        remove_below: bool,
+        cache: &cache::Handle,
+        db: &db::Handle,
+        resp: &RespHandle,
+    ) -> error::Result<()> {
+        let eligible: HashSet<String> = match Db::DiscordPointsAbove(threshold).exec(db).await? {
+            Resp::Ids(ids) => ids.into_iter().collect(),
+            _ => unreachable!(),
+        };
+
+        let key = Self::awarded_key(name);
+        let awarded: HashSet<String> = match Cache::HashGetAll(key.clone()).exec(cache).await? {
+            RespType::VecStringString(fields) => fields.into_iter().map(|(id, _)| id).collect(),
+            _ => unreachable!(),
+        };
+
+        let mut added = 0;
+        for id in eligible.difference(&awarded) {
+            let id: Arc<String> = id.to_owned().into();
+            Cache::HashSet(key.clone(), id.clone(), "1".into(), false)
+                .exec(cache)
+                .await?;
+            Self::send_role(resp, name, role_id, id, true).await;
+            added += 1;
+        }
+
+        let mut removed = 0;
+        if remove_below {
+            for id in awarded.difference(&eligible) {
+                let id: Arc<String> = id.to_owned().into();
+                Cache::HashDelete(key.clone(), id.clone())
+                    .exec(cache)
+                    .await?;
+                Self::send_role(resp, name, role_id, id, false).await;
+                removed += 1;
+            }
+        }
+
+        tracing::info!(added = added, removed = removed, "synced");
+
+        Ok(())
+    }
+
+    pub(crate) fn init(
+        &self,
+        cancel_chan: watch::Receiver<()>,
+        cache: &cache::Handle,
+        db: &db::Handle,
+        resp: &RespHandle,
+    ) -> Option<()> {
+        if !self.enabled || self.role_id.is_empty() || self.sync_interval == 0 {
+            return None;
+        }
+
+        let name = self.name.clone();
+        let role_id = self.role_id.clone();
+        let threshold = i32::try_from(self.threshold).ok()?;
+        let remove_below = self.remove_below;
+        let sync_interval = self.sync_interval;
+        let cache = cache.clone();
+        let db = db.clone();
+        let resp = resp.clone();
+
+        tracing::info!(
+            "\x1b[93mSpawning RoleReward sync task with interval: {}s\x1b[0m",
+            sync_interval
+        );
+
+        tokio::task::spawn(
+            async move {
+                loop {
+                    tokio::time::sleep(Duration::from_secs(sync_interval)).await;
+                    match cancel_chan.has_changed() {
+                        Ok(false) => {}
+                        _ => {
+                            // value changed or channel closed
+                            tracing::info!("\x1b[93maborting\x1b[0m");
+                            return;
+                        }
+                    }
+
+                    if let Err(e) =
+                        Self::sync(&name, &role_id, threshold, remove_below, &cache, &db, &resp)
+                            .await
+                    {
+                        tracing::error!("{}", e);
+                    }
+                }
+            }
+            .instrument(debug_span!("RoleReward sync task")),
+        );
+
+        Some(())
+    }
+}
+
+impl CmdDesc for RoleReward {
+    #[inline]
+    fn platform(&self) -> Platform {
+        Platform::DISCORD
+    }
+}
+
+impl Invokable for RoleReward {}
@@ -8,30 +8,58 @@ use crate::{
         give::{GiveOp, GiveSource, GiveTarget},
         Db, Resp,
     },
-    error, lock,
+    error::{self, Error},
+    i18n::{plural, tr},
+    lock,
     msg::{
-        ArgMap, ArgMapError, Chat, Invocation, Location, Payload, Permissions, Platform, Response,
-        User,
+        corr_id, ArgMap, ArgMapError, Chat, Invocation, Location, Payload, Permissions, Platform,
+        Response, User,
     },
 };
 use back_derive::command;
+use bb8_redis::redis;
 use once_cell::sync::Lazy;
-use rand::{distributions::Bernoulli, prelude::Distribution};
+use parking_lot::RwLock;
+use rand::{distributions::Bernoulli, prelude::Distribution, Rng};
 use regex::Regex;
-use std::fmt::Write as _;
 use std::{sync::Arc, time::Duration}; // import without risk of name clashing
+use tokio::sync::watch;
+use tracing::{debug_span, Instrument};
 
 static RR_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(\S+)\s(\d+|all)\s*").unwrap());
+static LOBBY_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(\S+)\s+(start|join)\b(?:\s+(\d+))?\s*$").unwrap());
 
 #[derive(Debug)]
-struct Args {
-    amount: i32,
+enum Args {
+    /// Everyone who pulls within the duration either wins or gets the penalty
+    Pull(i32),
+    /// Open a lobby with this buy-in (the min amount if None), the last one standing takes the pot
+    Start(Option<i32>),
+    Join,
 }
 
+/// (platform, user, wager or buy-in)
 type Heister = (Platform, Arc<User>, i32);
 type Handles = (cache::Handle, db::Handle, lock::Handle, RespHandle);
 
-#[command(locks(rate, active, members))]
+/// A lobby's keys and settings, as they were when it was started
+struct Lobby {
+    /// Hash of the players who've joined, until joining closes
+    players_key: Arc<String>,
+    /// Hash of the players still standing, once joining's closed
+    playing_key: Arc<String>,
+    /// Held from the start until the last round
+    lobby_key: Arc<String>,
+    /// Expires when joining closes
+    buy_in_key: Arc<String>,
+    duration: u64,
+    min_players: u64,
+    round_delay: u64,
+    penalty: ModAction,
+}
+
+#[command(locks(rate, active, members, lobby, players))]
 /// Win big or get timed out/banned (either way, there is no mod abuse 👀)
 pub struct RussianRoulette {
     /// Command prefix
@@ -49,7 +77,7 @@ pub struct RussianRoulette {
     #[cmd(def(10u64), constr(pos))]
     duration: u64,
     /// Cooldown per user (in seconds)
-    #[cmd(constr(pos))]
+    #[cmd(constr(pos), group("Cooldowns"))]
     ratelimit_user: u64,
     /// Min amount
     #[cmd(def(10i64), constr(pos))]
@@ -66,10 +94,44 @@ pub struct RussianRoulette {
     /// Penalty on loss
     #[cmd(defl("ModAction::Timeout(300)"), constr(range = "1..=86400"))]
     penalty: ModAction,
+    /// Time to join a lobby after it's started (in seconds)
+    #[cmd(def(60u64), constr(range = "10..=3600"))]
+    lobby_duration: u64,
+    /// Fewest players for a lobby to go ahead, everyone's refunded otherwise
+    #[cmd(def(2u64), constr(range = "2..=100"))]
+    min_players: u64,
+    /// Time between rounds of a lobby (in seconds)
+    #[cmd(def(5u64), constr(range = "1..=60"))]
+    round_delay: u64,
+    /// Calls off running lobbies when the config changes
+    #[cmd(skip)]
+    cancel_chan: RwLock<Option<watch::Receiver<()>>>,
 }
 
 impl RussianRoulette {
     fn parse_arguments(&self, chat: &Chat) -> error::Result<Option<(bool, Args)>> {
+        if let Some(captures) = LOBBY_REGEX.captures(&chat.msg) {
+            let autocorrect = match util::check_autocorrect(
+                &self.prefix,
+                &captures[1],
+                self.autocorrect,
+                &self.levenshtein,
+            ) {
+                Some(a) => a,
+                None => return Ok(None),
+            };
+            let args = match &captures[2] {
+                "start" => Args::Start(
+                    captures
+                        .get(3)
+                        .map(|buy_in| buy_in.as_str().parse::<i32>())
+                        .transpose()?,
+                ),
+                _ => Args::Join,
+            };
+            return Ok(Some((autocorrect, args)));
+        }
+
         let captures = match RR_REGEX.captures(&chat.msg) {
             Some(cap) => cap,
             None => return Ok(None),
@@ -93,7 +155,7 @@ impl RussianRoulette {
             captures[2].parse::<i32>()?
         };
 
-        Ok(Some((autocorrect, Args { amount })))
+        Ok(Some((autocorrect, Args::Pull(amount))))
     }
 
     fn can_run(&self, ctx: &Context<'_>) -> Option<()> {
@@ -188,11 +250,22 @@ impl RussianRoulette {
     async fn run(&self, ctx: &Context<'_>, args: Args) -> error::Result<RunRes> {
         tracing::debug!(name = self.name.as_str(), user = ctx.user.name.as_str(), args = ?args);
 
+        match args {
+            Args::Pull(amount) => self.pull(ctx, amount).await,
+            Args::Start(buy_in) => {
+                let buy_in = buy_in.unwrap_or(self.min_amount as i32);
+                self.start_lobby(ctx, buy_in).await
+            }
+            Args::Join => self.join_lobby(ctx).await,
+        }
+    }
+
+    async fn pull(&self, ctx: &Context<'_>, amount: i32) -> error::Result<RunRes> {
         let user = ctx.user;
 
         // consume amount
         let op = GiveOp {
-            amount: args.amount,
+            amount,
             from: GiveSource::Id(ctx.platform, user.id.clone()),
             to: GiveTarget::Spend,
             min: self.min_amount,
@@ -246,9 +319,9 @@ impl RussianRoulette {
         };
 
         let immunity_msg = if user.perms < Permissions::MOD {
-            ""
+            "".to_owned()
         } else {
-            "(immune) "
+            tr("russian_roulette.immune", &[])
         };
 
         let duration = self.duration as u64;
@@ -277,32 +350,38 @@ impl RussianRoulette {
                 }
             });
 
-            format!(
-                "{}started a game of russian roulette with the '{}' penalty for {} point{}!",
-                immunity_msg,
-                self.penalty,
-                amount,
-                if amount != 1 { "s" } else { "" },
+            tr(
+                "russian_roulette.started",
+                &[
+                    ("immune", &immunity_msg),
+                    ("penalty", &self.penalty),
+                    ("amount", &amount),
+                    ("s", &plural(amount)),
+                ],
             )
         } else {
-            format!(
-                "{}joined the russian roulette game with {} point{}!",
-                immunity_msg,
-                amount,
-                if amount != 1 { "s" } else { "" },
+            tr(
+                "russian_roulette.joined",
+                &[
+                    ("immune", &immunity_msg),
+                    ("amount", &amount),
+                    ("s", &plural(amount)),
+                ],
             )
-        }
-        .to_owned();
+        };
 
         tracing::info!("{}", msg);
 
         Response {
             platform: Platform::CHAT,
-            channel: &*crate::CHANNEL_NAME,
+            channel: crate::channel_name(),
+            corr_id: ctx.corr_id.clone(),
             payload: Payload::Message {
                 user: Some((ctx.platform, user.clone())),
                 msg: msg.into(),
                 meta: ctx.meta.clone(),
+                hint: None,
+                thread: None,
             },
         }
         .send(Location::Pubsub, ctx.resp)
@@ -333,59 +412,68 @@ impl RussianRoulette {
     ) -> error::Result<()> {
         tokio::time::sleep(Duration::from_secs(duration)).await;
 
-        // get all heisters
-        let resp = Cache::HashGetAll(member_key.clone()).exec(&cache).await?;
-
-        let heisters = match resp {
-            RespType::VecStringString(survivors) => survivors,
-            _ => unreachable!(),
-        };
+        // taken in one step, so nobody can join once fates are being decided
+        let heisters = Self::players(&cache, Cache::HashDrain(member_key.clone())).await?;
+        let _ = lock.unlock(&*active_key).await;
+        // anyone who got in while it was closing
+        let late = Self::players(&cache, Cache::HashDrain(member_key)).await?;
+        if !late.is_empty() {
+            tracing::info!(count = late.len(), "refunding late joiners");
+            Self::pay(&db, &late).await?;
+        }
 
         // decide fates
-        let heisters: Vec<((String, String), bool)> = {
+        let (survivors, losers): (Vec<(Heister, bool)>, _) = {
             let mut rng = rand::thread_rng();
             let fates = Bernoulli::new(win_prob).unwrap().sample_iter(&mut rng);
             // collect here because rng is !Send
-            heisters.into_iter().zip(fates).collect()
+            heisters
+                .into_iter()
+                .zip(fates)
+                .partition(|(_, survived)| *survived)
         };
+        let survivors: Vec<Heister> = survivors.into_iter().map(|(s, _)| s).collect();
 
-        let futures = heisters
-            .into_iter()
-            .map(|s| Self::handle_heister(s, db.clone(), resp_handle.clone(), penalty));
-
-        let res: Vec<(Arc<String>, i32)> = futures_util::future::join_all(futures)
-            .await
-            .into_iter()
-            .flatten()
-            .collect();
+        Self::pay(&db, &survivors).await?;
+        for ((platform, user, _), _) in losers {
+            Self::penalise(db.clone(), &resp_handle, platform, user, penalty).await;
+        }
 
-        let num_survivors = res.len();
+        let num_survivors = survivors.len();
 
         let msg = if num_survivors == 0 {
-            "The game is over, there were no survivors monkaW".to_owned()
+            tr("russian_roulette.no_survivors", &[])
         } else {
-            let mut survivor_msg = "The game is over! Survivors: ".to_owned();
+            let (separator, and) = (tr("list.separator", &[]), tr("list.and", &[]));
+            let mut survivors_msg = String::new();
             let penultimate_i = num_survivors.saturating_sub(2);
-            let mut res = res.into_iter().enumerate().peekable();
-            while let Some((i, (name, amount))) = res.next() {
+            let mut res = survivors.iter().enumerate().peekable();
+            while let Some((i, (_, user, amount))) = res.next() {
                 // add survivors' names and winnings to reply
-                write!(survivor_msg, "{} ({})", name, amount).unwrap();
+                survivors_msg.push_str(&tr(
+                    "russian_roulette.survivor",
+                    &[("name", &user.name), ("amount", amount)],
+                ));
                 if res.peek().is_some() {
-                    survivor_msg.push_str(if i != penultimate_i { ", " } else { " and " });
+                    survivors_msg.push_str(if i != penultimate_i { &separator } else { &and });
                 }
             }
-            survivor_msg
+            tr(
+                "russian_roulette.survivors",
+                &[("survivors", &survivors_msg)],
+            )
         };
 
-        let _ = tokio::join!(lock.unlock(&*member_key), lock.unlock(&*active_key));
-
         Response {
             platform: Platform::CHAT,
-            channel: &*crate::CHANNEL_NAME,
+            channel: crate::channel_name(),
+            corr_id: corr_id(),
             payload: Payload::Message {
                 user: None,
                 msg: msg.into(),
                 meta: None,
+                hint: None,
+                thread: None,
             },
         }
         .send(Location::Pubsub, &resp_handle)
@@ -394,54 +482,437 @@ impl RussianRoulette {
         Ok(())
     }
 
-    #[tracing::instrument(skip(heister, db, resp))]
-    async fn handle_heister(
-        ((_id, heister), survived): ((String, String), bool),
+    /// Enact the penalty on a loser, mods are immune
+    async fn penalise(
         db: db::Handle,
-        resp: RespHandle,
+        resp: &RespHandle,
+        platform: Platform,
+        user: Arc<User>,
         action: ModAction,
-    ) -> Option<(Arc<String>, i32)> {
-        let heister =
-            tokio::task::spawn_blocking(move || serde_json::from_str::<Heister>(&heister).unwrap())
-                .await
-                .ok()?;
+    ) {
+        if user.perms >= Permissions::MOD {
+            return;
+        }
+        let reason = Arc::new("RussianRoulette".to_owned());
+        tracing::info!(action=%action, "\x1b[91menacting penalty\x1b[0m");
+        // log mod action
+        super::Log::mod_action(db, platform, user.id.clone(), action, reason.clone());
+        // enact penalty
+        Response {
+            platform,
+            channel: crate::channel_name(),
+            corr_id: corr_id(),
+            payload: Payload::ModAction(user, action, reason),
+        }
+        .send(Location::Broadcast, resp)
+        .await;
+    }
 
-        tracing::debug!("heister: {:?} survived: {}", heister, survived);
+    fn lobby(&self) -> Lobby {
+        Lobby {
+            players_key: Arc::new(format!("{}_{}", &*RUSSIANROULETTE_LOCK_PLAYERS, self.name)),
+            playing_key: Arc::new(format!(
+                "{}_{}_playing",
+                &*RUSSIANROULETTE_LOCK_PLAYERS, self.name
+            )),
+            lobby_key: Arc::new(format!("{}_{}", &*RUSSIANROULETTE_LOCK_LOBBY, self.name)),
+            buy_in_key: Arc::new(format!(
+                "{}_{}_buyin",
+                &*RUSSIANROULETTE_LOCK_LOBBY, self.name
+            )),
+            duration: self.lobby_duration,
+            min_players: self.min_players,
+            round_delay: self.round_delay,
+            penalty: self.penalty,
+        }
+    }
 
-        let (platform, user, amount) = heister;
+    async fn reply(ctx: &Context<'_>, msg: String) {
+        Response {
+            platform: ctx.platform,
+            channel: crate::channel_name(),
+            corr_id: ctx.corr_id.clone(),
+            payload: Payload::Message {
+                user: ctx.reply_to(),
+                msg: msg.into(),
+                meta: ctx.meta.clone(),
+                hint: None,
+                thread: None,
+            },
+        }
+        .send(Location::Pubsub, ctx.resp)
+        .await;
+    }
 
-        if survived {
-            // deposit payoff
-            Db::Give(GiveOp {
-                amount,
+    async fn announce(resp: &RespHandle, msg: String) {
+        Response {
+            platform: Platform::CHAT,
+            channel: crate::channel_name(),
+            corr_id: corr_id(),
+            payload: Payload::Message {
+                user: None,
+                msg: msg.into(),
+                meta: None,
+                hint: None,
+                thread: None,
+            },
+        }
+        .send(Location::Pubsub, resp)
+        .await;
+    }
+
+    /// Take the buy-in, at exactly that amount
+    async fn buy_in(ctx: &Context<'_>, buy_in: i32) -> error::Result<Heister> {
+        Db::Give(GiveOp {
+            amount: buy_in,
+            from: GiveSource::Id(ctx.platform, ctx.user.id.clone()),
+            to: GiveTarget::Spend,
+            min: buy_in as i64,
+            max: buy_in as i64,
+        })
+        .exec(ctx.db)
+        .await?;
+        Ok((ctx.platform, ctx.user.clone(), buy_in))
+    }
+
+    async fn start_lobby(&self, ctx: &Context<'_>, buy_in: i32) -> error::Result<RunRes> {
+        if (buy_in as i64) < self.min_amount || (buy_in as i64) > self.max_amount {
+            let msg = tr(
+                "russian_roulette.lobby.buy_in",
+                &[("min", &self.min_amount), ("max", &self.max_amount)],
+            );
+            Self::reply(ctx, msg).await;
+            return Ok(RunRes::Ok);
+        }
+
+        let lobby = self.lobby();
+        // one lobby at a time, extended once joining closes
+        if !ctx.lock.lock(&*lobby.lobby_key, lobby.duration + 5).await? {
+            let msg = tr(
+                "russian_roulette.lobby.running",
+                &[("prefix", &self.prefix)],
+            );
+            Self::reply(ctx, msg).await;
+            return Ok(RunRes::Ok);
+        }
+
+        let player = match Self::buy_in(ctx, buy_in).await {
+            Ok(player) => player,
+            Err(e) => {
+                let _ = ctx.lock.unlock(&*lobby.lobby_key).await;
+                return Err(e);
+            }
+        };
+        let player = tokio::task::spawn_blocking(move || serde_json::to_string(&player)).await??;
+
+        let opened = async {
+            Cache::Set(
+                lobby.buy_in_key.clone(),
+                Arc::new(buy_in.to_string()),
+                lobby.duration as usize,
+                false,
+            )
+            .exec(ctx.cache)
+            .await?;
+            Cache::HashSet(
+                lobby.players_key.clone(),
+                ctx.user.id.clone(),
+                player,
+                false,
+            )
+            .exec(ctx.cache)
+            .await
+        }
+        .await;
+        if let Err(e) = opened {
+            Self::refund(ctx, buy_in).await?;
+            let _ = ctx.lock.unlock(&*lobby.lobby_key).await;
+            return Err(e);
+        }
+
+        let handles = (
+            ctx.cache.clone(),
+            ctx.db.clone(),
+            ctx.lock.clone(),
+            ctx.resp.clone(),
+        );
+        let cancel_chan = self.cancel_chan.read().clone();
+        tokio::spawn(
+            async move {
+                let cancelled = async move {
+                    match cancel_chan {
+                        Some(mut chan) => {
+                            let _ = chan.changed().await;
+                        }
+                        None => futures_util::future::pending().await,
+                    }
+                };
+
+                let res = tokio::select! {
+                    res = Self::play(&lobby, &handles) => res,
+                    _ = cancelled => {
+                        // value changed or channel closed
+                        tracing::info!("\x1b[93maborting\x1b[0m");
+                        Self::cancel_lobby(&lobby, &handles).await.map(|_| ())
+                    }
+                };
+
+                if let Err(e) = res {
+                    tracing::error!("{}", e);
+                }
+            }
+            .instrument(debug_span!("RussianRoulette lobby")),
+        );
+
+        let msg = tr(
+            "russian_roulette.lobby.started",
+            &[
+                ("amount", &buy_in),
+                ("s", &plural(buy_in)),
+                ("prefix", &self.prefix),
+                ("duration", &self.lobby_duration),
+            ],
+        );
+        tracing::info!("{}", msg);
+        Self::reply(ctx, msg).await;
+
+        Ok(RunRes::Ok)
+    }
+
+    async fn join_lobby(&self, ctx: &Context<'_>) -> error::Result<RunRes> {
+        let lobby = self.lobby();
+
+        let buy_in = match Cache::Get(lobby.buy_in_key.clone()).exec(ctx.cache).await {
+            Ok(RespType::String(buy_in)) => buy_in.parse::<i32>()?,
+            Ok(_) => unreachable!(),
+            Err(Error::Redis(e)) if e.kind() == redis::ErrorKind::TypeError => {
+                let msg = tr("russian_roulette.lobby.none", &[("prefix", &self.prefix)]);
+                Self::reply(ctx, msg).await;
+                return Ok(RunRes::Ok);
+            }
+            Err(e) => return Err(e),
+        };
+
+        let player = Self::buy_in(ctx, buy_in).await?;
+        let player = tokio::task::spawn_blocking(move || serde_json::to_string(&player)).await??;
+
+        // join, at most once per lobby
+        let joined = Cache::HashSet(lobby.players_key.clone(), ctx.user.id.clone(), player, true)
+            .exec(ctx.cache)
+            .await;
+        match joined {
+            Ok(RespType::Bool(true)) => {}
+            Ok(RespType::Bool(false)) => {
+                Self::refund(ctx, buy_in).await?;
+                Self::reply(ctx, tr("russian_roulette.lobby.already_joined", &[])).await;
+                return Ok(RunRes::Ok);
+            }
+            Ok(_) => unreachable!(),
+            Err(e) => {
+                Self::refund(ctx, buy_in).await?;
+                return Err(e);
+            }
+        }
+
+        let msg = tr(
+            "russian_roulette.lobby.joined",
+            &[("amount", &buy_in), ("s", &plural(buy_in))],
+        );
+        tracing::info!("{}", msg);
+        Self::reply(ctx, msg).await;
+
+        Ok(RunRes::Ok)
+    }
+
+    /// Players from a HashGetAll or HashDrain
+    async fn players(cache: &cache::Handle, op: Cache) -> error::Result<Vec<Heister>> {
+        let players = match op.exec(cache).await? {
+            RespType::VecStringString(players) => players,
+            _ => unreachable!(),
+        };
+
+        tokio::task::spawn_blocking(move || {
+            players
+                .iter()
+                .filter_map(|(_id, player)| serde_json::from_str::<Heister>(player).ok())
+                .collect()
+        })
+        .await
+        .map_err(Into::into)
+    }
+
+    /// Pay everyone in one go, so either all of them are paid or none are
+    async fn pay(db: &db::Handle, payouts: &[Heister]) -> error::Result<()> {
+        if payouts.is_empty() {
+            return Ok(());
+        }
+        let ops = payouts
+            .iter()
+            .map(|(platform, user, amount)| GiveOp {
+                amount: *amount,
                 from: GiveSource::None,
-                to: GiveTarget::User(platform, user.id.clone(), user.name.clone()),
+                to: GiveTarget::User(*platform, user.id.clone(), user.name.clone()),
                 min: 0,
                 max: 0,
             })
-            .exec(&db)
-            .await;
+            .collect();
+        Db::GiveAll(ops).exec(db).await.map(|_| ())
+    }
 
-            Some((user.name.clone(), amount))
-        } else if user.perms < Permissions::MOD {
-            let reason = Arc::new("RussianRoulette".to_owned());
-            tracing::info!(action=%action, "\x1b[91menacting penalty\x1b[0m");
-            // log mod action
-            super::Log::mod_action(db, platform, user.id.clone(), action, reason.clone());
-            // enact penalty
-            Response {
-                platform,
-                channel: &*crate::CHANNEL_NAME,
-                payload: Payload::ModAction(user, action, reason),
-            }
-            .send(Location::Broadcast, &resp)
+    /// Refund anyone who got in after joining closed
+    async fn refund_late(
+        lobby: &Lobby,
+        cache: &cache::Handle,
+        db: &db::Handle,
+    ) -> error::Result<()> {
+        let late = Self::players(cache, Cache::HashDrain(lobby.players_key.clone())).await?;
+        if !late.is_empty() {
+            tracing::info!(count = late.len(), "refunding late joiners");
+            Self::pay(db, &late).await?;
+        }
+        Ok(())
+    }
+
+    /// Wait for joining to close, then knock players out a round at a time until one's left
+    async fn play(lobby: &Lobby, handles: &Handles) -> error::Result<()> {
+        let (cache, db, lock, resp) = handles;
+        tokio::time::sleep(Duration::from_secs(lobby.duration)).await;
+        let _ = Cache::Delete(lobby.buy_in_key.clone()).exec(cache).await;
+
+        // close joining in one step, the players are whoever made it in
+        Cache::Rename(lobby.players_key.clone(), lobby.playing_key.clone())
+            .exec(cache)
+            .await?;
+        Self::refund_late(lobby, cache, db).await?;
+
+        let mut players =
+            Self::players(cache, Cache::HashGetAll(lobby.playing_key.clone())).await?;
+        if (players.len() as u64) < lobby.min_players {
+            let count = Self::cancel_lobby(lobby, handles).await?;
+            let msg = tr(
+                "russian_roulette.lobby.too_few",
+                &[("count", &count), ("min", &lobby.min_players)],
+            );
+            Self::announce(resp, msg).await;
+            return Ok(());
+        }
+
+        // hold the lobby through every round
+        let rounds = players.len() as u64 - 1;
+        let _ = lock.unlock(&*lobby.lobby_key).await;
+        let _ = lock
+            .lock(&*lobby.lobby_key, lobby.round_delay * (rounds + 1) + 5)
             .await;
 
-            None
-        } else {
-            // Some((user.name.clone(), 0))
-            None
+        let pot = players.iter().map(|(_, _, buy_in)| *buy_in).sum::<i32>();
+        tracing::info!(players = players.len(), pot, "lobby closed");
+        let msg = tr(
+            "russian_roulette.lobby.closed",
+            &[("count", &players.len()), ("pot", &pot)],
+        );
+        Self::announce(resp, msg).await;
+
+        for round in 1..=rounds {
+            tokio::time::sleep(Duration::from_secs(lobby.round_delay)).await;
+            let msg = tr(
+                "russian_roulette.lobby.round",
+                &[("round", &round), ("left", &players.len())],
+            );
+            Self::announce(resp, msg).await;
+            tokio::time::sleep(Duration::from_secs(lobby.round_delay)).await;
+
+            let (platform, user, _) =
+                players.swap_remove(rand::thread_rng().gen_range(0..players.len()));
+            Cache::HashDelete(lobby.playing_key.clone(), user.id.clone())
+                .exec(cache)
+                .await?;
+            let msg = tr("russian_roulette.lobby.eliminated", &[("name", &user.name)]);
+            Self::announce(resp, msg).await;
+            Self::penalise(db.clone(), resp, platform, user, lobby.penalty).await;
+        }
+
+        // off the list before it's paid, so a cancel doesn't refund it too
+        let (platform, user, _) = players.pop().expect("at least min_players");
+        Cache::HashDelete(lobby.playing_key.clone(), user.id.clone())
+            .exec(cache)
+            .await?;
+        Db::Give(GiveOp {
+            amount: pot,
+            from: GiveSource::None,
+            to: GiveTarget::User(platform, user.id.clone(), user.name.clone()),
+            min: 0,
+            max: 0,
+        })
+        .exec(db)
+        .await?;
+        let _ = lock.unlock(&*lobby.lobby_key).await;
+        Self::refund_late(lobby, cache, db).await?;
+
+        tracing::info!(winner = user.name.as_str(), pot, "lobby over");
+        let msg = tr(
+            "russian_roulette.lobby.winner",
+            &[("name", &user.name), ("pot", &pot)],
+        );
+        Self::announce(resp, msg).await;
+
+        Ok(())
+    }
+
+    /// Give everyone still standing their buy-in back, returning how many there were
+    async fn cancel_lobby(
+        lobby: &Lobby,
+        (cache, db, lock, _resp): &Handles,
+    ) -> error::Result<usize> {
+        let _ = Cache::Delete(lobby.buy_in_key.clone()).exec(cache).await;
+        let mut players = Self::players(cache, Cache::HashDrain(lobby.playing_key.clone())).await?;
+        players.extend(Self::players(cache, Cache::HashDrain(lobby.players_key.clone())).await?);
+        let _ = lock.unlock(&*lobby.lobby_key).await;
+
+        Self::pay(db, &players).await?;
+
+        tracing::info!(players = players.len(), "refunded");
+
+        Ok(players.len())
+    }
+
+    /// Keep the cancel chan for lobbies started from now on, and refund any lobby
+    /// left over from before a restart
+    pub(crate) fn init(
+        &self,
+        cancel_chan: watch::Receiver<()>,
+        cache: &cache::Handle,
+        db: &db::Handle,
+        lock: &lock::Handle,
+        resp: &RespHandle,
+    ) -> Option<()> {
+        *self.cancel_chan.write() = Some(cancel_chan);
+
+        if !self.enabled {
+            return None;
         }
+
+        let lobby = self.lobby();
+        let handles = (cache.clone(), db.clone(), lock.clone(), resp.clone());
+
+        tokio::spawn(
+            async move {
+                // only stale if nobody's running it
+                match handles.2.lock(&*lobby.lobby_key, 5).await {
+                    Ok(true) => {}
+                    Ok(false) => return,
+                    Err(e) => {
+                        tracing::error!("{}", e);
+                        return;
+                    }
+                }
+                if let Err(e) = Self::cancel_lobby(&lobby, &handles).await {
+                    tracing::error!("{}", e);
+                }
+            }
+            .instrument(debug_span!("RussianRoulette cleanup")),
+        );
+
+        Some(())
     }
 }
 
@@ -467,15 +938,41 @@ impl CmdDesc for RussianRoulette {
 impl Invokable for RussianRoulette {
     //fn args<'a>() -> &'a [Arg] {
     fn args(&self, _platform: Platform) -> Vec<Arg> {
-        vec![Arg {
-            name: "amount".into(),
-            desc: "Amount to gamble (leaving this blank means max)".into(),
+        let amount = |name: &str, desc: &str| Arg {
+            name: name.into(),
+            desc: desc.into(),
             kind: ArgKind::Integer {
                 min: Some(self.min_amount),
                 max: Some(self.max_amount),
             },
             optional: true,
-        }]
+        };
+        vec![
+            Arg {
+                name: "pull".into(),
+                desc: "Win big or get the penalty".into(),
+                kind: ArgKind::SubCommand(vec![amount(
+                    "amount",
+                    "Amount to gamble (leaving this blank means max)",
+                )]),
+                optional: true,
+            },
+            Arg {
+                name: "start".into(),
+                desc: "Open a lobby, the last one standing takes the pot".into(),
+                kind: ArgKind::SubCommand(vec![amount(
+                    "buy_in",
+                    "Points each player puts in (leaving this blank means min)",
+                )]),
+                optional: true,
+            },
+            Arg {
+                name: "join".into(),
+                desc: "Join the open lobby".into(),
+                kind: ArgKind::SubCommand(vec![]),
+                optional: true,
+            },
+        ]
     }
 }
 
@@ -483,12 +980,24 @@ impl TryFrom<&ArgMap> for Args {
     type Error = ArgMapError;
 
     fn try_from(value: &ArgMap) -> Result<Self, Self::Error> {
-        let amount = match value.get("amount") {
-            Some(ArgValue::Integer(x)) => *x as i32,
-            Some(_) => return Err(ArgMapError),
-            None => -1,
+        let integer = |c: &ArgMap, name: &str| match c.get(name) {
+            Some(ArgValue::Integer(x)) => Ok(Some(*x as i32)),
+            Some(_) => Err(ArgMapError),
+            None => Ok(None),
+        };
+
+        if let Some(ArgValue::SubCommand(c)) = value.get("start") {
+            return Ok(Args::Start(integer(c, "buy_in")?));
+        }
+        if let Some(ArgValue::SubCommand(_)) = value.get("join") {
+            return Ok(Args::Join);
+        }
+        // registered before lobbies, the amount was a top level arg
+        let amount = match value.get("pull") {
+            Some(ArgValue::SubCommand(c)) => integer(c, "amount")?,
+            _ => integer(value, "amount")?,
         };
 
-        Ok(Args { amount })
+        Ok(Args::Pull(amount.unwrap_or(-1)))
     }
 }
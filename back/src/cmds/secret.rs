@@ -0,0 +1,186 @@
+//! Command fields holding credentials, e.g. webhook URLs with a token in them.
+//!
+//! They're sealed with ChaCha20-Poly1305 under a key derived from CONFIG_SECRET, and kept that
+//! way everywhere but inside the command that uses them. Sealed values look like
+//! `enc:<hex nonce><hex ciphertext>`, anything else is a value saved before a key was set,
+//! which gets sealed the next time the config is loaded
+
+use super::{CmdDump, Command, OwnedValueError, Value, VerifyConstraint};
+use crate::error::{self, Error};
+use once_cell::sync::Lazy;
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN},
+    digest,
+    rand::{SecureRandom, SystemRandom},
+};
+
+const SEALED_PREFIX: &str = "enc:";
+/// Sent in place of a secret to those who can't see it, and sent back for "leave it as it is"
+pub(crate) const REDACTED: &str = "********";
+
+static KEY: Lazy<Option<LessSafeKey>> = Lazy::new(|| {
+    let secret = crate::config::server().config_secret.as_ref()?;
+    let hash = digest::digest(&digest::SHA256, secret.0.as_bytes());
+    let key = UnboundKey::new(&CHACHA20_POLY1305, hash.as_ref()).expect("SHA-256 is 32 bytes");
+    Some(LessSafeKey::new(key))
+});
+static RNG: Lazy<SystemRandom> = Lazy::new(SystemRandom::new);
+
+/// A sealed field value, see [`Secret::reveal`] for the plaintext
+#[derive(Clone, Default, PartialEq, Eq)]
+pub(crate) struct Secret(String);
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Secret(..)")
+    }
+}
+
+impl Secret {
+    /// Stored as-is without CONFIG_SECRET
+    fn seal(plain: String) -> Self {
+        let key = match &*KEY {
+            Some(key) => key,
+            None => {
+                if !plain.is_empty() {
+                    tracing::warn!("CONFIG_SECRET isn't set, secret fields are stored unencrypted");
+                }
+                return Self(plain);
+            }
+        };
+
+        let mut nonce = [0u8; NONCE_LEN];
+        RNG.fill(&mut nonce).expect("system RNG failed");
+        let mut sealed = plain.into_bytes();
+        key.seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::empty(),
+            &mut sealed,
+        )
+        .expect("plaintext too long");
+
+        let mut s = String::with_capacity(SEALED_PREFIX.len() + 2 * (NONCE_LEN + sealed.len()));
+        s.push_str(SEALED_PREFIX);
+        for b in nonce.iter().chain(&sealed) {
+            s.push_str(&format!("{:02x}", b));
+        }
+        Self(s)
+    }
+
+    pub(crate) fn is_set(&self) -> bool {
+        !self.0.is_empty()
+    }
+
+    /// The plaintext, only for the command that owns it to use
+    pub(crate) fn reveal(&self) -> error::Result<String> {
+        let hex = match self.0.strip_prefix(SEALED_PREFIX) {
+            Some(hex) => hex,
+            None => return Ok(self.0.clone()),
+        };
+        let key = KEY
+            .as_ref()
+            .ok_or_else(|| Error::Generic("CONFIG_SECRET is needed to decrypt a field".into()))?;
+
+        let undecryptable = || Error::Generic("couldn't decrypt a secret field".into());
+        let mut bytes = from_hex(hex).ok_or_else(undecryptable)?;
+        if bytes.len() < NONCE_LEN {
+            return Err(undecryptable());
+        }
+        let mut sealed = bytes.split_off(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(&bytes).map_err(|_| undecryptable())?;
+        let plain = key
+            .open_in_place(nonce, Aad::empty(), &mut sealed)
+            .map_err(|_| undecryptable())?;
+        String::from_utf8(plain.to_vec()).map_err(|_| undecryptable())
+    }
+
+    /// For dumps to those who can't see it
+    pub(crate) fn redacted(&self) -> Self {
+        match self.is_set() {
+            true => Self(REDACTED.into()),
+            false => Self::default(),
+        }
+    }
+}
+
+/// Decrypt or redact a dumped command's secret fields, for sending it to the web UI
+pub(crate) fn expose(dump: &mut CmdDump, reveal: bool) {
+    for (key, value) in dump.2.iter_mut() {
+        let sealed = match value {
+            Value::Secret(s) => Secret(std::mem::take(s)),
+            _ => continue,
+        };
+        let shown = match reveal {
+            true => sealed.reveal().unwrap_or_else(|e| {
+                tracing::warn!(cmd = dump.1.as_str(), field = key.as_str(), "{}", e);
+                sealed.redacted().0
+            }),
+            false => sealed.redacted().0,
+        };
+        *value = Value::Secret(shown);
+    }
+}
+
+/// Swap redacted fields in a dump coming back from the web UI for what the command of the same
+/// type and name in `list` has, or leave them empty if there isn't one
+pub(crate) fn keep_redacted(dump: &mut CmdDump, list: &[Command]) {
+    let (kind, name, values) = dump;
+    let is_redacted = |v: &Value| matches!(v, Value::Secret(s) | Value::String(s) if s == REDACTED);
+    if !values.iter().any(|(_, v)| is_redacted(v)) {
+        return;
+    }
+
+    let current = list
+        .iter()
+        .find(|cmd| cmd.kind() == kind.as_str() && cmd.name() == name.as_str())
+        .map(|cmd| cmd.dump().2)
+        .unwrap_or_default();
+    for (key, value) in values.iter_mut().filter(|(_, v)| is_redacted(v)) {
+        *value = current
+            .iter()
+            .find(|(k, _)| k == key)
+            .and_then(|(_, v)| match v {
+                Value::Secret(s) => Some(Value::Secret(s.clone())),
+                _ => None,
+            })
+            .unwrap_or_else(|| Value::Secret(String::new()));
+    }
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Plain strings are taken as new values and sealed, so fields that used to be Strings carry over
+impl TryFrom<Value> for Secret {
+    type Error = OwnedValueError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Secret(s) | Value::String(s)
+                if s.starts_with(SEALED_PREFIX) || s == REDACTED || s.is_empty() =>
+            {
+                Ok(Self(s))
+            }
+            Value::Secret(s) | Value::String(s) => Ok(Self::seal(s)),
+            _ => Err(OwnedValueError {
+                expected: "Secret".into(),
+                value,
+            }),
+        }
+    }
+}
+
+impl From<Secret> for Value {
+    fn from(x: Secret) -> Self {
+        Self::Secret(x.0)
+    }
+}
+
+impl VerifyConstraint for Secret {}
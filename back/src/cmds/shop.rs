@@ -0,0 +1,361 @@
+use super::{
+    autocomplete::{Choices, PartialArg},
+    shop_item::ShopItem,
+    util, Arg, ArgKind, ArgValue, CmdDesc, Command, Context, Invokable, RunRes,
+};
+use crate::{
+    db::{
+        give::GiveError,
+        shop::{RedeemOp, ShopError},
+        Db, Resp,
+    },
+    error::{self, Error},
+    i18n::tr,
+    msg::{
+        discord::{self, DiscordAction},
+        ArgMap, ArgMapError, Chat, Invocation, Location, Payload, Permissions, Platform, Response,
+    },
+};
+use back_derive::command;
+use futures_util::future::{BoxFuture, FutureExt};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::sync::Arc;
+
+static SHOP_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(\S+)(?:\s+(.{1,100}?))?\s*$").unwrap());
+
+#[derive(Debug)]
+struct Args {
+    /// None to list items
+    item: Option<String>,
+}
+
+#[command(locks(rate))]
+/// List and redeem shop items for points
+pub struct Shop {
+    /// Command prefix
+    #[cmd(def("!shop"), constr(non_empty))]
+    prefix: String,
+    /// Redeem command prefix
+    #[cmd(def("!redeem"), constr(non_empty))]
+    redeem_prefix: String,
+    /// Autocorrect prefix
+    autocorrect: bool,
+    /// Platforms
+    #[cmd(defl("Platform::CHAT"))]
+    platforms: Platform,
+    /// Permissions
+    #[cmd(defl("Permissions::NONE"))]
+    perms: Permissions,
+    /// Cooldown per user (in seconds)
+    #[cmd(constr(pos), group("Cooldowns"))]
+    ratelimit_user: u64,
+}
+
+impl Shop {
+    fn parse_arguments(&self, chat: &Chat) -> Option<(bool, Args)> {
+        let captures = SHOP_REGEX.captures(&chat.msg)?;
+        let item = captures.get(2).map(|m| m.as_str().to_owned());
+
+        if captures[1].eq_ignore_ascii_case(&self.redeem_prefix) {
+            return Some((false, Args { item }));
+        }
+
+        // check command prefix
+        let autocorrect = util::check_autocorrect(
+            &self.prefix,
+            &captures[1],
+            self.autocorrect,
+            &self.levenshtein,
+        )?;
+
+        Some((autocorrect, Args { item }))
+    }
+
+    fn can_run(&self, ctx: &Context<'_>) -> Option<()> {
+        if !self.enabled {
+            return None;
+        }
+
+        // check if platform is applicable
+        if !self.platforms.contains(ctx.platform) {
+            return None;
+        }
+
+        // check perms
+        if ctx.user.perms < self.perms {
+            return None;
+        }
+
+        Some(())
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn chat(&self, ctx: &Context<'_>, chat: &Chat) -> error::Result<RunRes> {
+        if self.can_run(ctx).is_none() {
+            return Ok(RunRes::Disabled);
+        }
+
+        let (autocorrect, args) = match self.parse_arguments(chat) {
+            Some(t) => t,
+            None => return Ok(RunRes::Noop),
+        };
+
+        if autocorrect {
+            return Ok(RunRes::Autocorrect(self.prefix.clone()));
+        }
+
+        // there's nothing to list under the redeem prefix
+        if args.item.is_none() && util::starts_with_prefix(&self.redeem_prefix, &chat.msg) {
+            return Ok(RunRes::InvalidArgs);
+        }
+
+        if util::ratelimit_user(
+            ctx,
+            self.ratelimit_user,
+            stringify!(Shop),
+            &self.name,
+            &*SHOP_LOCK_RATE,
+        )
+        .await?
+        {
+            return Ok(RunRes::Ratelimited { global: false });
+        }
+
+        self.run(ctx, args).await
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn invoke(
+        &self,
+        ctx: &Context<'_>,
+        invocation: &Invocation,
+    ) -> Option<RunRes> {
+        self.can_run(ctx)?;
+
+        super::check_invoke_prefix(&self.prefix, &invocation.cmd)?;
+
+        let args = match Args::try_from(&invocation.args) {
+            Ok(args) => args,
+            Err(_) => return Some(RunRes::InvalidArgs),
+        };
+
+        match util::ratelimit_user(
+            ctx,
+            self.ratelimit_user,
+            stringify!(Shop),
+            &self.name,
+            &*SHOP_LOCK_RATE,
+        )
+        .await
+        {
+            Ok(false) => {}
+            Ok(true) => return None,
+            Err(e) => {
+                tracing::error!("{}", e);
+                return None;
+            }
+        }
+
+        match self.run(ctx, args).await {
+            Ok(r) => Some(r),
+            Err(e) => {
+                tracing::error!("{}", e);
+                None
+            }
+        }
+    }
+
+    fn items<'a>(ctx: &'a Context<'_>) -> impl Iterator<Item = &'a ShopItem> {
+        ctx.commands.iter().filter_map(move |cmd| match cmd {
+            Command::ShopItem(item) if item.available(ctx) => Some(item),
+            _ => None,
+        })
+    }
+
+    #[tracing::instrument(level = "trace", skip_all, name = "Shop")]
+    async fn run(&self, ctx: &Context<'_>, args: Args) -> error::Result<RunRes> {
+        tracing::debug!(name = self.name.as_str(), user = ctx.user.name.as_str(), args = ?args);
+
+        let msg = match args.item {
+            Some(ref item) => {
+                let item = Self::items(ctx).find(|i| i.item.eq_ignore_ascii_case(item.trim()));
+                match item {
+                    Some(item) => Self::redeem(ctx, item).await?,
+                    None => tr("shop.not_found", &[]),
+                }
+            }
+            None => {
+                let separator = tr("list.separator", &[]);
+                let items = Self::items(ctx)
+                    .map(|i| tr("shop.item", &[("item", &i.item), ("cost", &i.cost)]))
+                    .collect::<Vec<_>>();
+                if items.is_empty() {
+                    tr("shop.empty", &[])
+                } else {
+                    tr(
+                        "shop.list",
+                        &[
+                            ("items", &items.join(&separator)),
+                            ("prefix", &self.redeem_prefix),
+                        ],
+                    )
+                }
+            }
+        };
+
+        Response {
+            platform: ctx.platform,
+            channel: crate::channel_name(),
+            corr_id: ctx.corr_id.clone(),
+            payload: Payload::Message {
+                user: ctx.reply_to(),
+                msg: msg.into(),
+                meta: ctx.meta.clone(),
+                hint: None,
+                thread: None,
+            },
+        }
+        .send(Location::Pubsub, ctx.resp)
+        .await;
+
+        Ok(RunRes::Ok)
+    }
+
+    /// Returns the reply
+    async fn redeem(ctx: &Context<'_>, item: &ShopItem) -> error::Result<String> {
+        let user = ctx.user;
+        let grants_role = !item.discord_role.is_empty() && ctx.platform == Platform::DISCORD;
+        let cost = match i32::try_from(item.cost) {
+            Ok(cost) => cost,
+            Err(_) => {
+                tracing::warn!(
+                    item = item.item.as_str(),
+                    cost = item.cost,
+                    "cost out of range"
+                );
+                return Ok(tr("shop.unavailable", &[]));
+            }
+        };
+
+        let op = RedeemOp {
+            platform: ctx.platform,
+            id: user.id.clone(),
+            name: user.name.clone(),
+            item: Arc::new(item.item.clone()),
+            cost,
+            stock: (item.stock > 0).then_some(item.stock),
+            completed: grants_role,
+        };
+
+        let id = match Db::Redeem(op).exec(ctx.db).await {
+            Ok(Resp::Redeemed(id)) => id,
+            Ok(_) => unreachable!(),
+            Err(Error::GiveOp(GiveError::Deduct)) => {
+                return Ok(tr("shop.insufficient", &[("cost", &item.cost)]))
+            }
+            Err(Error::Shop(ShopError::OutOfStock)) => return Ok(tr("shop.out_of_stock", &[])),
+            Err(e) => return Err(e),
+        };
+
+        tracing::info!(
+            id,
+            item = item.item.as_str(),
+            user = user.name.as_str(),
+            "redeemed"
+        );
+
+        if grants_role {
+            let role = discord::Role {
+                user_id: user.id.clone(),
+                role_id: item.discord_role.clone().into(),
+                guild_id: None,
+                reason: Some(format!("Shop ({})", item.item).into()),
+            };
+            Response {
+                platform: Platform::DISCORD,
+                channel: crate::channel_name(),
+                corr_id: ctx.corr_id.clone(),
+                payload: Payload::Discord(DiscordAction::AddRole(role)),
+            }
+            .send(Location::Pubsub, ctx.resp)
+            .await;
+        } else if let Ok(Resp::Redemptions(queue)) = Db::PendingRedemptions.exec(ctx.db).await {
+            // let the dashboard know there's something to fulfill
+            Response {
+                platform: ctx.platform,
+                channel: crate::channel_name(),
+                corr_id: ctx.corr_id.clone(),
+                payload: Payload::RedemptionQueue(queue),
+            }
+            .send(Location::Websockets(None), ctx.resp)
+            .await;
+        }
+
+        Ok(if !item.fulfillment.is_empty() {
+            item.fulfillment
+                .replace("{user}", &user.name)
+                .replace("{item}", &item.item)
+        } else if grants_role {
+            tr("shop.redeemed", &[("item", &item.item)])
+        } else {
+            tr("shop.pending", &[("item", &item.item)])
+        })
+    }
+}
+
+impl CmdDesc for Shop {
+    #[inline]
+    fn platform(&self) -> Platform {
+        self.platforms
+    }
+
+    #[inline]
+    fn description(&self, platform: Platform) -> Option<String> {
+        if platform.contains(Platform::DISCORD) {
+            return Some("See what's in the shop, or redeem an item".into());
+        }
+
+        None
+    }
+}
+
+impl Invokable for Shop {
+    fn args(&self, _platform: Platform) -> Vec<Arg> {
+        vec![Arg {
+            name: "item".into(),
+            desc: "Item to redeem (leaving this blank lists them)".into(),
+            kind: ArgKind::Autocomplete,
+            optional: true,
+        }]
+    }
+
+    fn autocomplete<'a>(
+        &'a self,
+        ctx: &'a Context<'_>,
+        _arg: PartialArg<'a>,
+    ) -> BoxFuture<'a, error::Result<Choices>> {
+        let items = Self::items(ctx)
+            .map(|i| {
+                let shown = tr("shop.item", &[("item", &i.item), ("cost", &i.cost)]);
+                (shown, i.item.clone())
+            })
+            .collect();
+        futures_util::future::ready(Ok(items)).boxed()
+    }
+}
+
+impl TryFrom<&ArgMap> for Args {
+    type Error = ArgMapError;
+
+    fn try_from(value: &ArgMap) -> Result<Self, Self::Error> {
+        let item = match value.get("item") {
+            Some(ArgValue::String(s)) => Some(s.clone()),
+            Some(_) => return Err(ArgMapError),
+            None => None,
+        };
+
+        Ok(Args { item })
+    }
+}
@@ -0,0 +1,60 @@
+use super::{CmdDesc, Context, Invokable, RunRes};
+use crate::{
+    error,
+    msg::{Chat, Invocation, Permissions, Platform},
+};
+use back_derive::command;
+
+#[command(cmd)]
+/// An item that can be redeemed from the Shop
+pub struct ShopItem {
+    /// Item name, as typed to redeem it
+    #[cmd(def("item"), constr(non_empty))]
+    item: String,
+    /// Cost (in points)
+    #[cmd(def(100i64), constr(pos))]
+    cost: i64,
+    /// Platforms
+    #[cmd(defl("Platform::CHAT"))]
+    platforms: Platform,
+    /// Permissions
+    #[cmd(defl("Permissions::NONE"))]
+    perms: Permissions,
+    /// Stock (0 for unlimited)
+    #[cmd(constr(pos))]
+    stock: i64,
+    /// Reply on redemption ({user} and {item} are filled in), leave blank for the default
+    #[cmd(constr(range = "0..=500"))]
+    fulfillment: String,
+    /// Discord role given on redemption, these are fulfilled right away on Discord
+    discord_role: String,
+}
+
+impl ShopItem {
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn chat(&self, _ctx: &Context<'_>, _chat: &Chat) -> error::Result<RunRes> {
+        Ok(RunRes::Noop)
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn invoke(
+        &self,
+        _ctx: &Context<'_>,
+        _invocation: &Invocation,
+    ) -> Option<RunRes> {
+        None
+    }
+
+    pub(super) fn available(&self, ctx: &Context<'_>) -> bool {
+        self.enabled && self.platforms.contains(ctx.platform) && ctx.user.perms >= self.perms
+    }
+}
+
+impl CmdDesc for ShopItem {
+    #[inline]
+    fn platform(&self) -> Platform {
+        self.platforms
+    }
+}
+
+impl Invokable for ShopItem {}
@@ -0,0 +1,286 @@
+use super::{util, Arg, ArgKind, ArgValue, CmdDesc, Context, Invokable, RunRes};
+use crate::{
+    error,
+    i18n::tr,
+    msg::{
+        ArgMap, ArgMapError, Chat, Invocation, InvocationKind, Location, Payload, Permissions,
+        Platform, Response, StreamEvent,
+    },
+    twitch,
+};
+use back_derive::command;
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+/// Twitch logins are at most 25 characters
+static SHOUTOUT_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(\S+)(?:\s+@?(\w{1,25}))?\s*$").unwrap());
+
+#[derive(Debug)]
+struct Args {
+    login: String,
+}
+
+#[command(cmd)]
+/// Shout out another channel, and raiders as they come in
+pub struct Shoutout {
+    /// Command prefix
+    #[cmd(def("!so"), constr(non_empty))]
+    prefix: String,
+    /// Autocorrect prefix
+    autocorrect: bool,
+    /// Platforms
+    #[cmd(defl("Platform::TWITCH"))]
+    platforms: Platform,
+    /// Permissions
+    #[cmd(defl("Permissions::MOD"))]
+    perms: Permissions,
+    /// Shoutout ({channel}, {login}, {game} and {title} are filled in)
+    #[cmd(
+        def("Go check out {channel} at https://twitch.tv/{login}, they were last playing {game}: {title}"),
+        constr(range = "1..=500")
+    )]
+    message: String,
+    /// Shoutout for raids ({viewers} is also filled in), leave blank to not shout out raiders
+    #[cmd(
+        def("{channel} is raiding with {viewers} viewers! Go check them out at https://twitch.tv/{login}"),
+        constr(range = "0..=500")
+    )]
+    raid_message: String,
+    /// Raids smaller than this aren't shouted out
+    #[cmd(constr(pos))]
+    min_raid_viewers: i64,
+    /// How long raiders are let past filters that allow it (in seconds)
+    #[cmd(def(300i64), constr(range = "0..=86400"))]
+    raid_permit: i64,
+}
+
+impl Shoutout {
+    fn parse_arguments(&self, chat: &Chat) -> Option<(bool, Option<Args>)> {
+        let captures = SHOUTOUT_REGEX.captures(&chat.msg)?;
+
+        // check command prefix
+        let autocorrect = util::check_autocorrect(
+            &self.prefix,
+            &captures[1],
+            self.autocorrect,
+            &self.levenshtein,
+        )?;
+
+        let args = captures.get(2).map(|m| Args {
+            login: m.as_str().to_lowercase(),
+        });
+
+        Some((autocorrect, args))
+    }
+
+    fn can_run(&self, ctx: &Context<'_>) -> Option<()> {
+        if !self.enabled {
+            return None;
+        }
+
+        // check if platform is applicable
+        if !self.platforms.contains(ctx.platform) {
+            return None;
+        }
+
+        // check perms
+        if ctx.user.perms < self.perms {
+            return None;
+        }
+
+        Some(())
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn chat(&self, ctx: &Context<'_>, chat: &Chat) -> error::Result<RunRes> {
+        if self.can_run(ctx).is_none() {
+            return Ok(RunRes::Disabled);
+        }
+
+        let (autocorrect, args) = match self.parse_arguments(chat) {
+            Some(t) => t,
+            None if util::starts_with_prefix(&self.prefix, &chat.msg) => {
+                return Ok(RunRes::InvalidArgs)
+            }
+            None => return Ok(RunRes::Noop),
+        };
+
+        if autocorrect {
+            return Ok(RunRes::Autocorrect(self.prefix.clone()));
+        }
+
+        match args {
+            Some(args) => self.run(ctx, args).await,
+            None => Ok(RunRes::InvalidArgs),
+        }
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn invoke(
+        &self,
+        ctx: &Context<'_>,
+        invocation: &Invocation,
+    ) -> Option<RunRes> {
+        if let Some(InvocationKind::StreamEvent(StreamEvent::Raid { from, viewers })) =
+            &invocation.kind
+        {
+            if !self.enabled || !self.platforms.contains(ctx.platform) {
+                return None;
+            }
+            return match self.raid(ctx, &from.name, *viewers).await {
+                Ok(r) => Some(r),
+                Err(e) => {
+                    tracing::error!("{}", e);
+                    None
+                }
+            };
+        }
+
+        self.can_run(ctx)?;
+
+        super::check_invoke_prefix(&self.prefix, &invocation.cmd)?;
+
+        let args = match Args::try_from(&invocation.args) {
+            Ok(args) => args,
+            Err(_) => return Some(RunRes::InvalidArgs),
+        };
+
+        match self.run(ctx, args).await {
+            Ok(r) => Some(r),
+            Err(e) => {
+                tracing::error!("{}", e);
+                None
+            }
+        }
+    }
+
+    #[tracing::instrument(level = "trace", skip_all, name = "Shoutout")]
+    async fn run(&self, ctx: &Context<'_>, args: Args) -> error::Result<RunRes> {
+        tracing::debug!(name = self.name.as_str(), user = ctx.user.name.as_str(), args = ?args);
+
+        let msg = match Self::fill(&self.message, &args.login, None).await? {
+            Some(msg) => msg,
+            None => tr("shoutout.not_found", &[("login", &args.login)]),
+        };
+
+        self.send(ctx, msg).await;
+
+        Ok(RunRes::Ok)
+    }
+
+    #[tracing::instrument(level = "trace", skip_all, name = "Shoutout")]
+    async fn raid(&self, ctx: &Context<'_>, from: &str, viewers: u64) -> error::Result<RunRes> {
+        tracing::info!(name = self.name.as_str(), from, viewers, "raided");
+
+        if self.raid_permit > 0 {
+            util::permit(
+                ctx.cache,
+                ctx.platform,
+                &ctx.user.id,
+                self.raid_permit as u64,
+            )
+            .await?;
+        }
+
+        if self.raid_message.is_empty() || viewers < self.min_raid_viewers as u64 {
+            return Ok(RunRes::Noop);
+        }
+
+        let login = from.to_lowercase();
+        if let Some(msg) = Self::fill(&self.raid_message, &login, Some(viewers)).await? {
+            self.send(ctx, msg).await;
+        }
+
+        Ok(RunRes::Ok)
+    }
+
+    /// None if the channel doesn't exist. Falls back to the login if channel info can't be fetched
+    async fn fill(
+        template: &str,
+        login: &str,
+        viewers: Option<u64>,
+    ) -> error::Result<Option<String>> {
+        let info = match *twitch::HELIX {
+            Some(ref helix) => match helix.channel_info(login).await? {
+                Some(info) => Some(info),
+                None => return Ok(None),
+            },
+            None => None,
+        };
+
+        let unknown = tr("shoutout.unknown", &[]);
+        let (channel, login, game, title) = match info {
+            Some(ref info) => (
+                info.display_name.as_str(),
+                info.login.as_str(),
+                info.game.as_str(),
+                info.title.as_str(),
+            ),
+            None => (login, login, unknown.as_str(), unknown.as_str()),
+        };
+
+        let mut msg = template
+            .replace("{channel}", channel)
+            .replace("{login}", login)
+            .replace("{game}", if game.is_empty() { &unknown } else { game })
+            .replace("{title}", title);
+        if let Some(viewers) = viewers {
+            msg = msg.replace("{viewers}", &viewers.to_string());
+        }
+
+        Ok(Some(msg))
+    }
+
+    async fn send(&self, ctx: &Context<'_>, msg: String) {
+        Response {
+            platform: ctx.platform,
+            channel: crate::channel_name(),
+            corr_id: ctx.corr_id.clone(),
+            payload: Payload::Message {
+                user: None,
+                msg: msg.into(),
+                meta: ctx.meta.clone(),
+                hint: ctx.reply_hint(None),
+                thread: None,
+            },
+        }
+        .send(Location::Pubsub, ctx.resp)
+        .await;
+    }
+}
+
+impl CmdDesc for Shoutout {
+    #[inline]
+    fn platform(&self) -> Platform {
+        self.platforms
+    }
+}
+
+impl Invokable for Shoutout {
+    fn args(&self, _platform: Platform) -> Vec<Arg> {
+        vec![Arg {
+            name: "channel".into(),
+            desc: "Twitch channel to shout out".into(),
+            kind: ArgKind::String,
+            optional: false,
+        }]
+    }
+}
+
+impl TryFrom<&ArgMap> for Args {
+    type Error = ArgMapError;
+
+    fn try_from(value: &ArgMap) -> Result<Self, Self::Error> {
+        let login = match value.get("channel") {
+            Some(ArgValue::String(s)) => s.trim().trim_start_matches('@').to_lowercase(),
+            _ => return Err(ArgMapError),
+        };
+
+        if login.is_empty() {
+            return Err(ArgMapError);
+        }
+
+        Ok(Args { login })
+    }
+}
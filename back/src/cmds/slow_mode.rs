@@ -0,0 +1,273 @@
+use super::{util, Context, ModAction, RunRes};
+use crate::{
+    cache::{self, Cache, RespType},
+    error::{self, Error},
+    i18n::{plural, tr},
+    msg::{Chat, Invocation, Location, Payload, Permissions, Platform, Response},
+};
+use back_derive::command;
+use bb8_redis::redis;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// Overrides are re-read from redis this often, to pick up ones set by other instances
+const REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Longest interval mods can set from chat
+const MAX_INTERVAL: u64 = 3600;
+
+/// (fetched, seconds between messages, 0 for off)
+type Override = (Instant, Option<u64>);
+
+/// Per filter name, the interval mods set from chat, if any
+static OVERRIDES: Lazy<RwLock<HashMap<String, Override>>> = Lazy::new(Default::default);
+
+#[command(filter)]
+/// Hold chatters to a minimum interval between messages
+pub struct SlowMode {
+    /// Apply to anyone below permission level
+    #[cmd(defl("Permissions::MEMBER"))]
+    apply_to: Permissions,
+    /// Platforms
+    #[cmd(defl("Platform::CHAT"))]
+    platforms: Platform,
+    /// Mod action for messages sent too soon
+    #[cmd(defl("ModAction::Remove"), constr(range = "1..=86400"))]
+    action: ModAction,
+    /// Seconds between messages (0 for no limit)
+    #[cmd(def(3u64), constr(range = "0..=3600"))]
+    interval: u64,
+    /// Seconds between messages for members (0 for no limit)
+    #[cmd(def(0u64), constr(range = "0..=3600"))]
+    member_interval: u64,
+    /// Command prefix for changing the interval live
+    #[cmd(def("!slow"), constr(non_empty))]
+    prefix: String,
+    /// Permissions for changing the interval
+    #[cmd(defl("Permissions::MOD"))]
+    perms: Permissions,
+}
+
+enum Edit {
+    /// seconds, minutes (0 until reset)
+    Set(u64, u64),
+    Off,
+    Reset,
+    Status,
+}
+
+impl SlowMode {
+    fn key(name: &str) -> Arc<String> {
+        Arc::new(format!(
+            "aussiebot!{}!slowmode!{}",
+            crate::channel_name(),
+            name
+        ))
+    }
+
+    fn last_key(&self, platform: Platform, user_id: &str) -> Arc<String> {
+        Arc::new(format!(
+            "aussiebot!{}!slowmode!{}!{}!{}",
+            crate::channel_name(),
+            self.name,
+            platform,
+            user_id
+        ))
+    }
+
+    fn parse_edit(&self, msg: &str) -> Option<Edit> {
+        let mut parts = msg.split_whitespace();
+        if !parts.next()?.eq_ignore_ascii_case(&self.prefix) {
+            return None;
+        }
+
+        let edit = match parts.next() {
+            None => Edit::Status,
+            Some(arg) if arg.eq_ignore_ascii_case("off") => Edit::Off,
+            Some(arg) if arg.eq_ignore_ascii_case("reset") => Edit::Reset,
+            Some(secs) => {
+                let secs = secs.parse::<u64>().ok().filter(|s| *s <= MAX_INTERVAL)?;
+                let mins = match parts.next() {
+                    Some(mins) => mins.parse::<u64>().ok().filter(|m| *m > 0)?,
+                    None => 0,
+                };
+                Edit::Set(secs, mins)
+            }
+        };
+
+        match parts.next() {
+            Some(_) => None,
+            None => Some(edit),
+        }
+    }
+
+    /// The interval set from chat if there is one, otherwise the configured one for the user
+    async fn interval(&self, ctx: &Context<'_>) -> error::Result<u64> {
+        if let Some(secs) = Self::get_override(ctx.cache, &self.name).await? {
+            return Ok(secs);
+        }
+
+        Ok(if ctx.user.perms >= Permissions::MEMBER {
+            self.member_interval
+        } else {
+            self.interval
+        })
+    }
+
+    async fn get_override(cache: &cache::Handle, name: &str) -> error::Result<Option<u64>> {
+        if let Some((fetched, secs)) = OVERRIDES.read().get(name) {
+            if fetched.elapsed() < REFRESH_INTERVAL {
+                return Ok(*secs);
+            }
+        }
+
+        let secs = match Cache::Get(Self::key(name)).exec(cache).await {
+            Ok(RespType::String(secs)) => secs.parse().ok(),
+            Ok(_) => unreachable!(),
+            Err(Error::Redis(e)) if e.kind() == redis::ErrorKind::TypeError => None,
+            Err(e) => return Err(e),
+        };
+
+        OVERRIDES
+            .write()
+            .insert(name.to_owned(), (Instant::now(), secs));
+
+        Ok(secs)
+    }
+
+    /// Some to set (expiring after `ttl` seconds if non-zero), None to go back to the config
+    async fn set_override(
+        cache: &cache::Handle,
+        name: &str,
+        secs: Option<u64>,
+        ttl: u64,
+    ) -> error::Result<()> {
+        match secs {
+            Some(secs) => {
+                Cache::Set(
+                    Self::key(name),
+                    Arc::new(secs.to_string()),
+                    ttl as usize,
+                    false,
+                )
+                .exec(cache)
+                .await?
+            }
+            None => Cache::Delete(Self::key(name)).exec(cache).await?,
+        };
+
+        // an expiring override is left to lapse through a refresh
+        OVERRIDES
+            .write()
+            .insert(name.to_owned(), (Instant::now(), secs));
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn chat(&self, ctx: &Context<'_>, chat: &Chat) -> error::Result<RunRes> {
+        if !self.enabled || !self.platforms.contains(ctx.platform) {
+            return Ok(RunRes::Disabled);
+        }
+
+        if ctx.user.perms >= self.perms && util::starts_with_prefix(&self.prefix, &chat.msg) {
+            return self.edit_from_chat(ctx, chat).await;
+        }
+
+        if ctx.user.perms > self.apply_to {
+            return Ok(RunRes::Disabled);
+        }
+
+        self.run(ctx, chat).await
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn invoke(
+        &self,
+        _ctx: &Context<'_>,
+        _invocation: &Invocation,
+    ) -> Option<RunRes> {
+        None
+    }
+
+    #[tracing::instrument(level = "trace", skip_all, name = "SlowMode")]
+    async fn run(&self, ctx: &Context<'_>, chat: &Chat) -> error::Result<RunRes> {
+        let interval = self.interval(ctx).await?;
+        if interval == 0 {
+            return Ok(RunRes::Ok);
+        }
+
+        // only set if it's not already, so messages sent too soon don't push the wait back
+        let key = self.last_key(ctx.platform, &chat.user.id);
+        match Cache::Set(key, Arc::new("1".into()), interval as usize, true)
+            .exec(ctx.cache)
+            .await?
+        {
+            RespType::Bool(true) => Ok(RunRes::Ok),
+            RespType::Bool(false) => {
+                tracing::info!(
+                    "\x1b[91mMessage from {} within {}s of their last\x1b[0m",
+                    chat.user.name,
+                    interval
+                );
+                Ok(RunRes::Filtered(self.action))
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    async fn edit_from_chat(&self, ctx: &Context<'_>, chat: &Chat) -> error::Result<RunRes> {
+        let msg = match self.parse_edit(&chat.msg) {
+            Some(Edit::Set(secs, mins)) => {
+                Self::set_override(ctx.cache, &self.name, Some(secs), mins * 60).await?;
+                tracing::info!(secs, mins, user = ctx.user.name.as_str(), "slow mode set");
+                if mins > 0 {
+                    tr(
+                        "slowmode.set_for",
+                        &[("secs", &secs), ("mins", &mins), ("s", &plural(mins))],
+                    )
+                } else {
+                    tr("slowmode.set", &[("secs", &secs)])
+                }
+            }
+            Some(Edit::Off) => {
+                Self::set_override(ctx.cache, &self.name, Some(0), 0).await?;
+                tracing::info!(user = ctx.user.name.as_str(), "slow mode off");
+                tr("slowmode.off", &[])
+            }
+            Some(Edit::Reset) => {
+                Self::set_override(ctx.cache, &self.name, None, 0).await?;
+                tracing::info!(user = ctx.user.name.as_str(), "slow mode reset");
+                tr("slowmode.reset", &[("secs", &self.interval)])
+            }
+            Some(Edit::Status) => match Self::get_override(ctx.cache, &self.name).await? {
+                Some(0) => tr("slowmode.off", &[]),
+                Some(secs) => tr("slowmode.status", &[("secs", &secs)]),
+                None => tr("slowmode.status", &[("secs", &self.interval)]),
+            },
+            None => tr("slowmode.usage", &[("prefix", &self.prefix)]),
+        };
+
+        Response {
+            platform: ctx.platform,
+            channel: crate::channel_name(),
+            corr_id: ctx.corr_id.clone(),
+            payload: Payload::Message {
+                user: ctx.reply_to(),
+                msg: msg.into(),
+                meta: ctx.meta.clone(),
+                hint: None,
+                thread: None,
+            },
+        }
+        .send(Location::Pubsub, ctx.resp)
+        .await;
+
+        Ok(RunRes::Ok)
+    }
+}
@@ -0,0 +1,223 @@
+use super::{util, CmdDesc, Context, Invokable, RunRes};
+use crate::{
+    db::{
+        usage::{UsageRange, UsageStats},
+        Db, Resp,
+    },
+    error,
+    i18n::tr,
+    msg::{
+        discord::ThreadReply, Chat, Invocation, Location, Payload, Permissions, Platform, Response,
+    },
+};
+use back_derive::command;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::str::FromStr;
+
+static STATS_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(\S+)(?:\s+(\S+))?\s*$").unwrap());
+
+/// Entries in each list in chat, the dashboard gets the rest
+const CHAT_TOP: usize = 3;
+
+#[command(locks(rate))]
+/// Show the most used commands and the most active users
+pub struct Stats {
+    /// Command prefix
+    #[cmd(def("!stats"), constr(non_empty))]
+    prefix: String,
+    /// Autocorrect prefix
+    autocorrect: bool,
+    /// Platforms
+    #[cmd(defl("Platform::CHAT"))]
+    platforms: Platform,
+    /// Permissions
+    #[cmd(defl("Permissions::NONE"))]
+    perms: Permissions,
+    /// Cooldown per user (in seconds)
+    #[cmd(def(30u64), constr(range = "0..=86400"), group("Cooldowns"))]
+    ratelimit_user: u64,
+    /// Where to reply on Discord (parent or new-thread, unset to reply where it was asked)
+    discord_thread: Option<String>,
+}
+
+impl Stats {
+    fn parse_arguments(&self, chat: &Chat) -> Option<(bool, Option<UsageRange>)> {
+        let captures = STATS_REGEX.captures(&chat.msg)?;
+
+        let autocorrect = util::check_autocorrect(
+            &self.prefix,
+            &captures[1],
+            self.autocorrect,
+            &self.levenshtein,
+        )?;
+
+        // day, week, month or all, anything else is invalid
+        let range = match captures.get(2) {
+            None => Some(UsageRange::default()),
+            Some(range) => UsageRange::from_str(range.as_str()).ok(),
+        };
+
+        Some((autocorrect, range))
+    }
+
+    fn can_run(&self, ctx: &Context<'_>) -> Option<()> {
+        if !self.enabled {
+            return None;
+        }
+
+        // check if platform is applicable
+        if !self.platforms.contains(ctx.platform) {
+            return None;
+        }
+
+        // check perms
+        if ctx.user.perms < self.perms {
+            return None;
+        }
+
+        Some(())
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn chat(&self, ctx: &Context<'_>, chat: &Chat) -> error::Result<RunRes> {
+        if self.can_run(ctx).is_none() {
+            return Ok(RunRes::Disabled);
+        }
+
+        let (autocorrect, range) = match self.parse_arguments(chat) {
+            Some(t) => t,
+            None => return Ok(RunRes::Noop),
+        };
+
+        if autocorrect {
+            return Ok(RunRes::Autocorrect(self.prefix.clone()));
+        }
+
+        let range = match range {
+            Some(range) => range,
+            None => return Ok(RunRes::InvalidArgs),
+        };
+
+        if util::ratelimit_user(
+            ctx,
+            self.ratelimit_user,
+            stringify!(Stats),
+            &self.name,
+            &*STATS_LOCK_RATE,
+        )
+        .await?
+        {
+            return Ok(RunRes::Ratelimited { global: false });
+        }
+
+        self.run(ctx, range).await
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn invoke(
+        &self,
+        ctx: &Context<'_>,
+        invocation: &Invocation,
+    ) -> Option<RunRes> {
+        self.can_run(ctx)?;
+
+        super::check_invoke_prefix(&self.prefix, &invocation.cmd)?;
+
+        match self.run(ctx, UsageRange::default()).await {
+            Ok(r) => Some(r),
+            Err(e) => {
+                tracing::error!("{}", e);
+                None
+            }
+        }
+    }
+
+    fn format(stats: &UsageStats) -> String {
+        let range = tr(
+            match stats.range {
+                UsageRange::Day => "stats.day",
+                UsageRange::Week => "stats.week",
+                UsageRange::Month => "stats.month",
+                UsageRange::All => "stats.all",
+            },
+            &[],
+        );
+
+        if stats.commands.is_empty() {
+            return tr("stats.empty", &[("range", &range)]);
+        }
+
+        let entry =
+            |name: &str, count: i64| tr("stats.entry", &[("name", &name), ("count", &count)]);
+        let commands = stats
+            .commands
+            .iter()
+            .take(CHAT_TOP)
+            .map(|(name, count)| entry(name, *count))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let users = stats
+            .users
+            .iter()
+            .take(CHAT_TOP)
+            .map(|u| entry(u.name.as_deref().unwrap_or(&u.id), u.count))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        tr(
+            "stats.summary",
+            &[
+                ("range", &range),
+                ("commands", &commands),
+                ("users", &users),
+            ],
+        )
+    }
+
+    #[tracing::instrument(level = "trace", skip_all, name = "Stats")]
+    async fn run(&self, ctx: &Context<'_>, range: UsageRange) -> error::Result<RunRes> {
+        tracing::debug!(name = self.name.as_str(), user = ctx.user.name.as_str(), range = ?range);
+
+        let stats = match Db::Usage(range).exec(ctx.db).await? {
+            Resp::Usage(stats) => stats,
+            _ => unreachable!(),
+        };
+
+        let msg = Self::format(&stats);
+        Response {
+            platform: ctx.platform,
+            channel: crate::channel_name(),
+            corr_id: ctx.corr_id.clone(),
+            payload: Payload::Message {
+                user: ctx.reply_to(),
+                msg: msg.into(),
+                meta: ctx.meta.clone(),
+                hint: None,
+                thread: ThreadReply::from_config(&self.discord_thread),
+            },
+        }
+        .send(Location::Pubsub, ctx.resp)
+        .await;
+
+        Ok(RunRes::Ok)
+    }
+}
+
+impl CmdDesc for Stats {
+    #[inline]
+    fn platform(&self) -> Platform {
+        self.platforms
+    }
+
+    #[inline]
+    fn description(&self, platform: Platform) -> Option<String> {
+        if platform.contains(Platform::DISCORD) {
+            return Some("See this week's most used commands and most active users".into());
+        }
+
+        None
+    }
+}
+
+impl Invokable for Stats {}
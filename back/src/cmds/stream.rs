@@ -1,8 +1,14 @@
-use super::{CmdDesc, Context, Invokable, RunRes};
+use super::{
+    blackout::{Blackout, Quiet},
+    CmdDesc, Context, Invokable, RunRes,
+};
 use crate::{
     //cache::{Cache, RespType},
     error::{self},
-    msg::{Chat, Invocation, InvocationKind, Location, Payload, Platform, Response, StreamEvent},
+    msg::{
+        discord::ChannelHint, Chat, Invocation, InvocationKind, Location, Payload, Platform,
+        Response, StreamEvent,
+    },
 };
 use back_derive::command;
 //use bb8_redis::redis;
@@ -17,6 +23,14 @@ pub struct Stream {
     /// Announcement message
     #[cmd(def("Hey @everyone <:PogChampGG:795488853091811389> <:PogChampGG:795488853091811389> <:PogChampGG:795488853091811389> today **AussieGG** brings you:\n{url}", constr(range = "1..=500")))]
     message: String,
+    /// Discord channel to announce in (announce, mod-log or bot-spam, unset for announce)
+    discord_channel: Option<String>,
+    /// When not to announce, e.g. mon-fri 09:00-17:00, sun, 23:30-01:00
+    #[cmd(defl("Blackout::default()"), group("Schedule"))]
+    blackout: Blackout,
+    /// Minutes ahead of UTC the blackout's times are (e.g. 600 for AEST), not moved for daylight saving
+    #[cmd(constr(range = "-720..=840"), group("Schedule"))]
+    utc_offset: i64,
 }
 
 impl Stream {
@@ -73,11 +87,27 @@ impl Stream {
     async fn announce(&self, ctx: &Context<'_>, url: Arc<String>) {
         let message = self.message.replace("{url}", &*url).replace("\\n", "\n");
         let message = Arc::new(message);
+        let quiet = Quiet {
+            blackout: self.blackout.clone(),
+            utc_offset: self.utc_offset,
+            // it's just gone live
+            only_live: false,
+            intro: 0,
+            streams: self.platforms,
+        };
+        if !quiet.allows(ctx.cache, &self.name).await {
+            return;
+        }
         tracing::info!(message = %message, "announcing stream");
         Response {
             platform: self.platforms,
-            channel: &*crate::CHANNEL_NAME,
-            payload: Payload::StreamAnnouncement(url.clone(), message.clone()),
+            channel: crate::channel_name(),
+            corr_id: ctx.corr_id.clone(),
+            payload: Payload::StreamAnnouncement(
+                url.clone(),
+                message.clone(),
+                ChannelHint::from_config(&self.discord_channel).unwrap_or(ChannelHint::Announce),
+            ),
         }
         .send(Location::Pubsub, ctx.resp)
         .await;
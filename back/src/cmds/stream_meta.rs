@@ -0,0 +1,295 @@
+use super::{util, Arg, ArgKind, ArgValue, CmdDesc, Context, Invokable, ModAction, RunRes};
+use crate::{
+    error,
+    i18n::tr,
+    msg::{
+        connector::{TwitchAction, YoutubeAction},
+        ArgMap, ArgMapError, Chat, Invocation, Location, Payload, Permissions, Platform, Response,
+    },
+};
+use back_derive::command;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy)]
+enum Field {
+    Title,
+    Game,
+}
+
+impl Field {
+    const ALL: [Field; 2] = [Field::Title, Field::Game];
+
+    /// Appended to the prefix in chat, and the subcommand name when invoked
+    fn name(self) -> &'static str {
+        match self {
+            Field::Title => "title",
+            Field::Game => "game",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Args {
+    field: Field,
+    value: String,
+}
+
+#[command(cmd)]
+/// Let admins change the stream title and game from chat, with !settitle and !setgame
+pub struct StreamMeta {
+    /// Command prefix, followed by title or game
+    #[cmd(def("!set"), constr(non_empty))]
+    prefix: String,
+    /// Autocorrect prefix
+    autocorrect: bool,
+    /// Platforms
+    #[cmd(defl("Platform::CHAT"))]
+    platforms: Platform,
+    /// Permissions, Admin at the least whatever's set here
+    #[cmd(defl("Permissions::ADMIN"))]
+    perms: Permissions,
+    /// Streams changed from Discord (stream chats change their own)
+    #[cmd(defl("Platform::STREAM"))]
+    streams: Platform,
+    /// Longest title or game accepted
+    #[cmd(def(140u64), constr(range = "1..=500"))]
+    max_len: u64,
+}
+
+impl StreamMeta {
+    /// None if the message isn't for this command, otherwise
+    /// (autocorrected, what's being set, its new value if one was given)
+    fn parse_arguments(&self, chat: &Chat) -> Option<(bool, Field, Option<String>)> {
+        let msg = chat.msg.trim();
+        let (first, rest) = msg.split_once(char::is_whitespace).unwrap_or((msg, ""));
+
+        let lower = first.to_lowercase();
+        let (field, input) = Field::ALL.iter().find_map(|field| {
+            let input = lower.strip_suffix(field.name())?;
+            Some((*field, &first[..input.len()]))
+        })?;
+
+        // check command prefix
+        let autocorrect =
+            util::check_autocorrect(&self.prefix, input, self.autocorrect, &self.levenshtein)?;
+
+        let value = Some(rest.trim())
+            .filter(|v| !v.is_empty())
+            .map(str::to_owned);
+        Some((autocorrect, field, value))
+    }
+
+    fn can_run(&self, ctx: &Context<'_>) -> Option<()> {
+        if !self.enabled {
+            return None;
+        }
+
+        // check if platform is applicable
+        if !self.platforms.contains(ctx.platform) {
+            return None;
+        }
+
+        Some(())
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn chat(&self, ctx: &Context<'_>, chat: &Chat) -> error::Result<RunRes> {
+        if self.can_run(ctx).is_none() {
+            return Ok(RunRes::Disabled);
+        }
+
+        let (autocorrect, field, value) = match self.parse_arguments(chat) {
+            Some(t) => t,
+            None => return Ok(RunRes::Noop),
+        };
+
+        if autocorrect {
+            return Ok(RunRes::Autocorrect(format!(
+                "{}{}",
+                self.prefix,
+                field.name()
+            )));
+        }
+
+        match value {
+            Some(value) => self.run(ctx, Args { field, value }).await,
+            None => Ok(RunRes::InvalidArgs),
+        }
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn invoke(
+        &self,
+        ctx: &Context<'_>,
+        invocation: &Invocation,
+    ) -> Option<RunRes> {
+        self.can_run(ctx)?;
+
+        super::check_invoke_prefix(&self.prefix, &invocation.cmd)?;
+
+        let args = match Args::try_from(&invocation.args) {
+            Ok(args) => args,
+            Err(_) => return Some(RunRes::InvalidArgs),
+        };
+
+        match self.run(ctx, args).await {
+            Ok(r) => Some(r),
+            Err(e) => {
+                tracing::error!("{}", e);
+                None
+            }
+        }
+    }
+
+    #[tracing::instrument(level = "trace", skip_all, name = "StreamMeta")]
+    async fn run(&self, ctx: &Context<'_>, args: Args) -> error::Result<RunRes> {
+        tracing::debug!(name = self.name.as_str(), user = ctx.user.name.as_str(), args = ?args);
+
+        // only ever admins, whatever else the config says
+        if ctx.user.perms < Permissions::ADMIN || ctx.user.perms < self.perms {
+            return Ok(RunRes::InsufficientPerms);
+        }
+
+        let max = self.max_len as i64;
+        if args.value.chars().count() as i64 > max {
+            return Ok(RunRes::OutOfRange { min: 1, max });
+        }
+
+        // stream chats change their own stream
+        let streams = if Platform::STREAM.contains(ctx.platform) {
+            ctx.platform
+        } else {
+            self.streams & Platform::STREAM
+        };
+        if streams.is_empty() {
+            return Ok(RunRes::Disabled);
+        }
+
+        let value = Arc::new(args.value);
+        let mut names = Vec::with_capacity(2);
+        for platform in [Platform::TWITCH, Platform::YOUTUBE] {
+            if !streams.contains(platform) {
+                continue;
+            }
+            names.push(platform.to_string());
+            let payload = match (platform, args.field) {
+                (Platform::TWITCH, Field::Title) => {
+                    Payload::Twitch(TwitchAction::SetTitle(value.clone()))
+                }
+                (Platform::TWITCH, Field::Game) => {
+                    Payload::Twitch(TwitchAction::SetGame(value.clone()))
+                }
+                (_, Field::Title) => Payload::Youtube(YoutubeAction::SetTitle(value.clone())),
+                (_, Field::Game) => Payload::Youtube(YoutubeAction::SetGame(value.clone())),
+            };
+            Response {
+                platform,
+                channel: crate::channel_name(),
+                corr_id: ctx.corr_id.clone(),
+                payload,
+            }
+            .send(Location::Pubsub, ctx.resp)
+            .await;
+        }
+
+        let streams = names.join(&tr("list.and", &[]));
+        tracing::info!(
+            streams = streams.as_str(),
+            field = args.field.name(),
+            value = value.as_str(),
+            "stream meta changed"
+        );
+
+        // kept in the mod log alongside filter actions, against the admin who made the change
+        let reason = format!(
+            "StreamMeta: {} {} set to {}",
+            streams,
+            args.field.name(),
+            value
+        );
+        super::Log::mod_action(
+            ctx.db.clone(),
+            ctx.platform,
+            ctx.user.id.clone(),
+            ModAction::None,
+            Arc::new(reason),
+        );
+
+        let key = match args.field {
+            Field::Title => "stream_meta.title",
+            Field::Game => "stream_meta.game",
+        };
+        let msg = tr(key, &[("streams", &streams), ("value", &value)]);
+        Response {
+            platform: ctx.platform,
+            channel: crate::channel_name(),
+            corr_id: ctx.corr_id.clone(),
+            payload: Payload::Message {
+                user: ctx.reply_to(),
+                msg: msg.into(),
+                meta: ctx.meta.clone(),
+                hint: None,
+                thread: None,
+            },
+        }
+        .send(Location::Pubsub, ctx.resp)
+        .await;
+
+        Ok(RunRes::Ok)
+    }
+}
+
+impl CmdDesc for StreamMeta {
+    #[inline]
+    fn platform(&self) -> Platform {
+        self.platforms
+    }
+
+    #[inline]
+    fn description(&self, platform: Platform) -> Option<String> {
+        if platform.contains(Platform::DISCORD) {
+            return Some("Change the stream title or game".into());
+        }
+
+        None
+    }
+}
+
+impl Invokable for StreamMeta {
+    fn args(&self, _platform: Platform) -> Vec<Arg> {
+        Field::ALL
+            .iter()
+            .map(|field| Arg {
+                name: field.name().into(),
+                desc: format!("Set the stream {}", field.name()),
+                kind: ArgKind::SubCommand(vec![Arg {
+                    name: "value".into(),
+                    desc: format!("New {}", field.name()),
+                    kind: ArgKind::String,
+                    optional: false,
+                }]),
+                optional: true,
+            })
+            .collect()
+    }
+}
+
+impl TryFrom<&ArgMap> for Args {
+    type Error = ArgMapError;
+
+    fn try_from(value: &ArgMap) -> Result<Self, Self::Error> {
+        Field::ALL
+            .iter()
+            .find_map(|field| match value.get(field.name()) {
+                Some(ArgValue::SubCommand(c)) => match c.get("value") {
+                    Some(ArgValue::String(x)) if !x.trim().is_empty() => Some(Args {
+                        field: *field,
+                        value: x.trim().to_owned(),
+                    }),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .ok_or(ArgMapError)
+    }
+}
@@ -1,4 +1,4 @@
-use super::{CmdDesc, Context, RunRes};
+use super::{user_cache, CmdDesc, Context, RunRes};
 use crate::{
     db::Db,
     error,
@@ -7,6 +7,7 @@ use crate::{
 use back_derive::command;
 use once_cell::sync::Lazy;
 use regex::Regex;
+use std::sync::Arc;
 
 /*
 
@@ -68,9 +69,11 @@ impl Streamlabs {
     }
 
     async fn handle_points(&self, ctx: &Context<'_>, name: &str, points: i32) -> error::Result<()> {
-        Db::SetPoints(ctx.platform, name.to_owned().into(), points)
+        let name = Arc::new(name.to_owned());
+        Db::SetPoints(ctx.platform, name.clone(), points)
             .exec(ctx.db)
             .await?;
+        user_cache::invalidate(ctx.cache, ctx.platform, None, &name).await;
         Ok(())
     }
 
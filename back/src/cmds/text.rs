@@ -0,0 +1,157 @@
+use super::{util, Context, RunRes};
+use crate::{
+    error,
+    msg::{Chat, Invocation, Location, Payload, Permissions, Platform, Response},
+};
+use back_derive::command;
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+static PREFIX_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(\S+)(?:\s|$)").unwrap());
+
+#[command(locks(rate))]
+/// Reply with the same text every time, e.g. a link. Ones added from chat with !addcmd are these
+pub struct Text {
+    /// Command prefix
+    #[cmd(def("!discord"), constr(non_empty))]
+    prefix: String,
+    /// Autocorrect prefix
+    autocorrect: bool,
+    /// Platforms
+    #[cmd(defl("Platform::CHAT"))]
+    platforms: Platform,
+    /// Permissions
+    #[cmd(defl("Permissions::NONE"))]
+    perms: Permissions,
+    /// Reply
+    #[cmd(def("Join the discord!"), constr(range = "1..=500"))]
+    text: String,
+    /// Cooldown (in seconds)
+    #[cmd(constr(pos), group("Cooldowns"))]
+    ratelimit: u64,
+    /// Cooldown per user (in seconds)
+    #[cmd(constr(pos), group("Cooldowns"))]
+    ratelimit_user: u64,
+    /// Added from chat, only these can be edited or removed from chat
+    #[cmd(advanced)]
+    from_chat: bool,
+}
+
+impl Text {
+    pub(super) fn added_from_chat(&self) -> bool {
+        self.from_chat
+    }
+
+    fn can_run(&self, ctx: &Context<'_>) -> Option<()> {
+        if !self.enabled {
+            return None;
+        }
+
+        // check if platform is applicable
+        if !self.platforms.contains(ctx.platform) {
+            return None;
+        }
+
+        // check perms
+        if ctx.user.perms < self.perms {
+            return None;
+        }
+
+        Some(())
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn chat(&self, ctx: &Context<'_>, chat: &Chat) -> error::Result<RunRes> {
+        if self.can_run(ctx).is_none() {
+            return Ok(RunRes::Disabled);
+        }
+
+        let captures = match PREFIX_REGEX.captures(&chat.msg) {
+            Some(captures) => captures,
+            None => return Ok(RunRes::Noop),
+        };
+
+        // check command prefix
+        let autocorrect = match util::check_autocorrect(
+            &self.prefix,
+            &captures[1],
+            self.autocorrect,
+            &self.levenshtein,
+        ) {
+            Some(autocorrect) => autocorrect,
+            None => return Ok(RunRes::Noop),
+        };
+
+        if autocorrect {
+            return Ok(RunRes::Autocorrect(self.prefix.clone()));
+        }
+
+        if self.ratelimited(ctx).await? {
+            return Ok(RunRes::Ratelimited { global: true });
+        }
+
+        self.run(ctx).await
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn invoke(
+        &self,
+        ctx: &Context<'_>,
+        invocation: &Invocation,
+    ) -> Option<RunRes> {
+        self.can_run(ctx)?;
+
+        super::check_invoke_prefix(&self.prefix, &invocation.cmd)?;
+
+        match self.ratelimited(ctx).await {
+            Ok(false) => {}
+            Ok(true) => return None,
+            Err(e) => {
+                tracing::error!("{}", e);
+                return None;
+            }
+        }
+
+        match self.run(ctx).await {
+            Ok(r) => Some(r),
+            Err(e) => {
+                tracing::error!("{}", e);
+                None
+            }
+        }
+    }
+
+    async fn ratelimited(&self, ctx: &Context<'_>) -> error::Result<bool> {
+        util::ratelimit_global(
+            ctx,
+            self.ratelimit,
+            self.ratelimit_user,
+            stringify!(Text),
+            &self.name,
+            &TEXT_LOCK_RATE,
+        )
+        .await
+    }
+
+    #[tracing::instrument(level = "trace", skip_all, name = "Text")]
+    async fn run(&self, ctx: &Context<'_>) -> error::Result<RunRes> {
+        tracing::debug!(name = self.name.as_str(), user = ctx.user.name.as_str());
+
+        Response {
+            platform: ctx.platform,
+            channel: crate::channel_name(),
+            corr_id: ctx.corr_id.clone(),
+            payload: Payload::Message {
+                user: ctx.reply_to(),
+                msg: self.text.as_str().into(),
+                meta: ctx.meta.clone(),
+                hint: None,
+                thread: None,
+            },
+        }
+        .send(Location::Pubsub, ctx.resp)
+        .await;
+
+        Ok(RunRes::Ok)
+    }
+}
@@ -0,0 +1,235 @@
+use super::{user_cache, CmdDesc, Context, Invokable, RunRes};
+use crate::{
+    db::{self, Db},
+    error, i18n,
+    msg::{Chat, Invocation, InvocationKind, Location, Monetization, Payload, Platform, Response},
+};
+use back_derive::command;
+use std::fmt::Display;
+
+#[command(cmd)]
+/// Thank and reward subs, bits, memberships and superchats
+pub struct Thanks {
+    /// Platforms
+    #[cmd(defl("Platform::STREAM"))]
+    platforms: Platform,
+    /// Points awarded per sub, multiplied by tier (Prime counts as tier 1)
+    #[cmd(def(500_u64), constr(pos))]
+    sub_points: u64,
+    /// Points awarded per 100 bits
+    #[cmd(def(100_u64), constr(pos))]
+    bits_points: u64,
+    /// Points awarded per membership
+    #[cmd(def(500_u64), constr(pos))]
+    member_points: u64,
+    /// Points awarded per whole unit of superchat currency
+    #[cmd(def(10_u64), constr(pos))]
+    superchat_points: u64,
+    /// Sub message. Accepts {name}, {tier}, {months}, {points}
+    #[cmd(
+        def("Thanks for the tier {tier} sub {name}! Enjoy {points} points"),
+        constr(range = "0..=500")
+    )]
+    sub_msg: String,
+    /// Gifted sub message. Accepts {name}, {tier}, {points}
+    #[cmd(
+        def("Thanks for the gifted sub {name}! Enjoy {points} points"),
+        constr(range = "0..=500")
+    )]
+    gift_msg: String,
+    /// Bits message. Accepts {name}, {amount}, {points}
+    #[cmd(
+        def("Thanks for the {amount} bits {name}! Enjoy {points} points"),
+        constr(range = "0..=500")
+    )]
+    bits_msg: String,
+    /// Membership message. Accepts {name}, {level}, {months}, {points}
+    #[cmd(
+        def("Welcome to {level} {name}! Enjoy {points} points"),
+        constr(range = "0..=500")
+    )]
+    member_msg: String,
+    /// Superchat message. Accepts {name}, {amount}, {points}
+    #[cmd(
+        def("Thanks for the {amount} superchat {name}! Enjoy {points} points"),
+        constr(range = "0..=500")
+    )]
+    superchat_msg: String,
+}
+
+impl Thanks {
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn chat(&self, _ctx: &Context<'_>, _chat: &Chat) -> error::Result<RunRes> {
+        Ok(RunRes::Noop)
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn invoke(
+        &self,
+        ctx: &Context<'_>,
+        invocation: &Invocation,
+    ) -> Option<RunRes> {
+        let event = match invocation.kind {
+            Some(InvocationKind::Monetization(ref evt)) => evt,
+            _ => return None,
+        };
+
+        match self.run(ctx, event).await {
+            Ok(r) => Some(r),
+            Err(e) => {
+                tracing::error!("{}", e);
+                None
+            }
+        }
+    }
+
+    /// Points to award and the filled-in thank-you template
+    fn reward(&self, name: &str, event: &Monetization) -> (u64, String) {
+        match event {
+            Monetization::Subscription {
+                tier,
+                months,
+                gifted,
+            } => {
+                let points = self.sub_points.saturating_mul((*tier).max(1) as u64);
+                let template = if *gifted {
+                    &self.gift_msg
+                } else {
+                    &self.sub_msg
+                };
+                let msg = Self::fill(
+                    template,
+                    &[
+                        ("name", &name),
+                        ("tier", tier),
+                        ("months", months),
+                        ("amount", &""),
+                        ("points", &points),
+                    ],
+                );
+                (points, msg)
+            }
+            Monetization::Bits(amount) => {
+                let points = self.bits_points.saturating_mul(*amount as u64) / 100;
+                let msg = Self::fill(
+                    &self.bits_msg,
+                    &[("name", &name), ("amount", amount), ("points", &points)],
+                );
+                (points, msg)
+            }
+            Monetization::Membership { level, months } => {
+                let points = self.member_points;
+                let msg = Self::fill(
+                    &self.member_msg,
+                    &[
+                        ("name", &name),
+                        ("level", level),
+                        ("months", months),
+                        ("amount", &""),
+                        ("points", &points),
+                    ],
+                );
+                (points, msg)
+            }
+            Monetization::Superchat {
+                amount_micros,
+                display,
+                ..
+            } => {
+                let points = self
+                    .superchat_points
+                    .saturating_mul(amount_micros / 1_000_000);
+                let msg = Self::fill(
+                    &self.superchat_msg,
+                    &[("name", &name), ("amount", display), ("points", &points)],
+                );
+                (points, msg)
+            }
+        }
+    }
+
+    /// In one pass, so placeholders in names or levels are left as they are
+    fn fill(template: &str, vars: &[(&str, &dyn Display)]) -> String {
+        i18n::fill(&template.replace("\\n", "\n"), vars)
+    }
+
+    #[tracing::instrument(skip(self, ctx), name = "Thanks")]
+    async fn run(&self, ctx: &Context<'_>, event: &Monetization) -> error::Result<RunRes> {
+        if !self.enabled || !self.platforms.contains(ctx.platform) {
+            return Ok(RunRes::Disabled);
+        }
+
+        let user = ctx.user;
+        let (points, msg) = self.reward(&user.name, event);
+
+        tracing::info!(points = points, event = ?event, "thanking {}", user.name);
+
+        if points > 0 {
+            let resp = Db::Upsert(
+                ctx.platform,
+                user.id.clone(),
+                user.name.clone(),
+                // big enough cheers or superchats could go over what the column holds
+                i32::try_from(points).unwrap_or(i32::MAX),
+            )
+            .exec(ctx.db)
+            .await?;
+            assert!(matches!(resp, db::Resp::Ok));
+            user_cache::invalidate(ctx.cache, ctx.platform, Some(&user.id), &user.name).await;
+        }
+
+        if !msg.is_empty() {
+            Response {
+                platform: ctx.platform,
+                channel: crate::channel_name(),
+                corr_id: ctx.corr_id.clone(),
+                payload: Payload::Message {
+                    user: None,
+                    msg: msg.into(),
+                    meta: ctx.meta.clone(),
+                    hint: None,
+                    thread: None,
+                },
+            }
+            .send(Location::Pubsub, ctx.resp)
+            .await;
+        }
+
+        Ok(RunRes::Ok)
+    }
+}
+
+impl CmdDesc for Thanks {
+    #[inline]
+    fn platform(&self) -> Platform {
+        Platform::empty()
+    }
+}
+
+impl Invokable for Thanks {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn names_arent_filled_in() {
+        let thanks = Thanks::default();
+        let (points, msg) = thanks.reward("{points}{amount}", &Monetization::Bits(500));
+        assert_eq!(points, 500);
+        assert_eq!(
+            msg,
+            "Thanks for the 500 bits {points}{amount}! Enjoy 500 points"
+        );
+
+        let (_, msg) = thanks.reward(
+            "someone",
+            &Monetization::Membership {
+                level: Arc::new("{name}".into()),
+                months: 2,
+            },
+        );
+        assert_eq!(msg, "Welcome to {name} someone! Enjoy 500 points");
+    }
+}
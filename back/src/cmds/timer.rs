@@ -1,16 +1,61 @@
-use super::{Context, RunRes};
+use super::{
+    blackout::{Blackout, Quiet},
+    multiplier,
+    uptime::{self, Uptime},
+    Command, Context, RunRes,
+};
 use crate::{
     cache::{self, Cache, RespType},
-    error,
-    msg::{Chat, Invocation, Location, Payload, Platform, Response},
+    error::{self, Error},
+    i18n::tr,
+    msg::{corr_id, discord::ChannelHint, Chat, Invocation, Location, Payload, Platform, Response},
 };
 use back_derive::command;
+use bb8_redis::redis;
+use once_cell::sync::Lazy;
 use rand::{distributions::Uniform, prelude::*};
+use regex::Regex;
+use serde_derive::{Deserialize, Serialize};
 use std::{sync::Arc, time::Duration};
-use tokio::sync::{mpsc, watch};
+use tokio::{
+    sync::{mpsc, watch},
+    task::JoinHandle,
+};
 use tracing::{info_span, Instrument};
 
-#[command(timer, locks(count))]
+static PREFIX_VAR_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"\{prefix:([^}]+)\}").unwrap());
+
+/// Where a timer is in its list of messages, kept in the cache so restarts carry on from there
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Rotation {
+    /// Messages left this round when picking at random, next one last
+    queue: Vec<usize>,
+    last: Option<usize>,
+}
+
+impl Rotation {
+    fn next(&mut self, len: usize, random: bool) -> usize {
+        let next = if random {
+            // drop anything past the end in case messages were removed
+            self.queue.retain(|&i| i < len);
+            if self.queue.is_empty() {
+                self.queue = (0..len).collect();
+                self.queue.shuffle(&mut rand::thread_rng());
+                // don't start the new round with the message that ended the last one
+                if len > 1 && self.queue.last() == self.last.as_ref() {
+                    self.queue.swap(0, len - 1);
+                }
+            }
+            self.queue.pop().unwrap()
+        } else {
+            self.last.map_or(0, |last| (last + 1) % len)
+        };
+        self.last = Some(next);
+        next
+    }
+}
+
+#[command(timer, locks(count, rotation, last))]
 /// Send a message at preset intervals
 pub struct Timer {
     /// Platforms
@@ -19,14 +64,30 @@ pub struct Timer {
     /// Repetition interval (in seconds)
     #[cmd(constr(pos))]
     interval: u64,
-    /// Max random delay (in seconds)
+    /// Max random delay (in seconds, unset for none)
     #[cmd(constr(pos))]
-    jitter: u64,
-    /// Message to send
-    msg: String,
+    jitter: Option<u64>,
+    /// Messages to send, one per post ({uptime}, {viewer_count}, {multiplier} and {prefix:<command name>} are filled in)
+    msg: Vec<String>,
+    /// Pick messages in random order instead of going down the list
+    random: bool,
     /// Min. number of chat messages required (Setting this to 0 will cause messages to be sent regardless of whether anyone's talking in chat, which may not be what you want)
     #[cmd(def(1_u64), constr(pos))]
     msg_count: u64,
+    /// Discord channel to post in (announce, mod-log or bot-spam, unset for the bot channel)
+    discord_channel: Option<String>,
+    /// Only post while the stream's live
+    #[cmd(group("Schedule"))]
+    only_live: bool,
+    /// Hold off for this long after the stream goes live, e.g. for the intro (in seconds)
+    #[cmd(constr(pos), group("Schedule"))]
+    intro: u64,
+    /// When not to post, e.g. mon-fri 09:00-17:00, sun, 23:30-01:00
+    #[cmd(defl("Blackout::default()"), group("Schedule"))]
+    blackout: Blackout,
+    /// Minutes ahead of UTC the blackout's times are (e.g. 600 for AEST), not moved for daylight saving
+    #[cmd(constr(range = "-720..=840"), group("Schedule"))]
+    utc_offset: i64,
 }
 
 impl Timer {
@@ -57,18 +118,161 @@ impl Timer {
         None
     }
 
+    /// Fill in {prefix:<command name>}, which only changes with the config
+    fn fill_prefixes(msg: &str, commands: &[Command]) -> String {
+        PREFIX_VAR_REGEX
+            .replace_all(msg, |captures: &regex::Captures| {
+                let name = captures[1].trim();
+                match commands
+                    .iter()
+                    .find(|c| c.name().eq_ignore_ascii_case(name))
+                    .and_then(|c| c.prefix())
+                {
+                    Some(prefix) => prefix.to_owned(),
+                    None => {
+                        tracing::warn!(command = name, "no prefix to fill in");
+                        captures[0].to_owned()
+                    }
+                }
+            })
+            .into_owned()
+    }
+
+    /// Fill in {uptime} and {viewer_count} from the timer's live streams.
+    /// None if the message needs them and nothing's live
+    fn fill_stream(msg: &str, live: &[uptime::Metadata]) -> error::Result<Option<String>> {
+        let wants_uptime = msg.contains("{uptime}");
+        let wants_viewers = msg.contains("{viewer_count}");
+        if !wants_uptime && !wants_viewers {
+            return Ok(Some(msg.to_owned()));
+        }
+
+        let started_at = match live.iter().map(|m| m.started_at).min() {
+            Some(started_at) => started_at,
+            None => return Ok(None),
+        };
+        let mut msg = msg.to_owned();
+        if wants_uptime {
+            msg = msg.replace("{uptime}", &Uptime::elapsed(started_at)?);
+        }
+        if wants_viewers {
+            let viewers: u64 = live.iter().map(|m| m.viewer_count).sum();
+            msg = msg.replace("{viewer_count}", &viewers.to_string());
+        }
+        Ok(Some(msg))
+    }
+
+    /// Fill in {multiplier} with the points multiplier and how long it has left.
+    /// None if the message needs it and there isn't one
+    fn fill_multiplier(msg: String, active: Option<&multiplier::Active>) -> Option<String> {
+        if !msg.contains("{multiplier}") {
+            return Some(msg);
+        }
+        let announce = tr(
+            "multiplier.announce",
+            &[
+                ("factor", &active?.factor()),
+                ("remaining", &active?.remaining()),
+            ],
+        );
+        Some(msg.replace("{multiplier}", &announce))
+    }
+
+    async fn get(cache: &cache::Handle, key: &Arc<String>) -> error::Result<Option<String>> {
+        match Cache::Get(key.clone()).exec(cache).await {
+            Ok(RespType::String(s)) => Ok(Some(s)),
+            Ok(_) => unreachable!(),
+            Err(Error::Redis(e)) if e.kind() == redis::ErrorKind::TypeError => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Pick the next message to post, skipping ones that can't be filled in
+    /// or that would repeat the last timer post. None if there's nothing to post
+    async fn next_msg(
+        cache: &cache::Handle,
+        rotation_key: &Arc<String>,
+        msgs: &[String],
+        random: bool,
+        platforms: Platform,
+    ) -> error::Result<Option<String>> {
+        let last_key = Arc::new(TIMER_LOCK_LAST.clone());
+
+        let mut rotation: Rotation = match Self::get(cache, rotation_key).await? {
+            Some(s) => serde_json::from_str(&s).unwrap_or_default(),
+            None => Rotation::default(),
+        };
+        let last_post = Self::get(cache, &last_key).await?;
+
+        // stream chats get their own stream, elsewhere every live stream counts
+        let mut live = vec![];
+        if msgs
+            .iter()
+            .any(|m| m.contains("{uptime}") || m.contains("{viewer_count}"))
+        {
+            let streams = match platforms & Platform::STREAM {
+                p if p.is_empty() => Platform::STREAM,
+                p => p,
+            };
+            for platform in [Platform::YOUTUBE, Platform::TWITCH] {
+                if streams.contains(platform) {
+                    live.extend(uptime::metadata(cache, platform).await?);
+                }
+            }
+        }
+
+        let active = match msgs.iter().any(|m| m.contains("{multiplier}")) {
+            true => multiplier::any(cache, platforms).await?,
+            false => None,
+        };
+
+        let mut picked = None;
+        for _ in 0..msgs.len() {
+            let index = rotation.next(msgs.len(), random);
+            let msg = Self::fill_stream(&msgs[index], &live)?
+                .and_then(|msg| Self::fill_multiplier(msg, active.as_ref()));
+            match msg {
+                Some(msg) if last_post.as_deref() != Some(msg.as_str()) || msgs.len() == 1 => {
+                    picked = Some(msg);
+                    break;
+                }
+                _ => {}
+            }
+        }
+
+        let rotation = serde_json::to_string(&rotation)?;
+        Cache::Set(rotation_key.clone(), rotation.into(), 0, false)
+            .exec(cache)
+            .await?;
+        if let Some(msg) = &picked {
+            Cache::Set(last_key, msg.clone().into(), 0, false)
+                .exec(cache)
+                .await?;
+        }
+
+        Ok(picked)
+    }
+
     pub(crate) fn init(
         &self,
         cancel_chan: watch::Receiver<()>,
         cache: &cache::Handle,
         resp: &mpsc::Sender<(Location, Response)>,
-    ) -> Option<()> {
-        if !self.enabled || self.platforms.is_empty() || self.interval == 0 || self.msg.is_empty() {
+        commands: &[Command],
+    ) -> Option<JoinHandle<()>> {
+        let msgs: Vec<String> = self
+            .msg
+            .iter()
+            .filter(|m| !m.trim().is_empty())
+            .map(|m| Self::fill_prefixes(m, commands))
+            .collect();
+
+        if !self.enabled || self.platforms.is_empty() || self.interval == 0 || msgs.is_empty() {
             return None;
         }
 
         tracing::info!(
-            "\x1b[93mSpawning Timer {:?} with interval: {}s, max jitter: {}s\x1b[0m",
+            "\x1b[93mSpawning Timer {:?} with interval: {}s, max jitter: {:?}s\x1b[0m",
             self.name,
             self.interval,
             self.jitter
@@ -79,21 +283,29 @@ impl Timer {
 
         let timer_name = self.name.clone();
         let interval = self.interval as u64;
-        let jitter = self.jitter as u64;
         let trigger_count = self.msg_count as u64;
         let platform = self.platforms;
-        let msg = Arc::new(self.msg.clone());
+        let random = self.random;
+        let hint = ChannelHint::from_config(&self.discord_channel);
+        let quiet = Quiet {
+            blackout: self.blackout.clone(),
+            utc_offset: self.utc_offset,
+            only_live: self.only_live,
+            intro: self.intro,
+            streams: self.platforms,
+        };
+        let rotation_key = Arc::new(format!("{}_{}", &*TIMER_LOCK_ROTATION, self.name));
 
-        let jitter_dist = Uniform::from(0..=jitter);
+        let jitter_dist = self.jitter.map(|jitter| Uniform::from(0..=jitter));
         let count_key = Arc::new(format!("{}_{}", &*TIMER_LOCK_COUNT, self.name));
 
         let zero: Arc<String> = Arc::new("0".into());
 
-        tokio::spawn(
+        let handle = tokio::spawn(
             async move {
                 loop {
-                    // sleep with random jitter
-                    let jitter = jitter_dist.sample(&mut rand::thread_rng());
+                    // sleep with random jitter, if there's any
+                    let jitter = jitter_dist.map_or(0, |d| d.sample(&mut rand::thread_rng()));
                     tokio::time::sleep(Duration::from_secs(interval.saturating_add(jitter))).await;
 
                     match cancel_chan.has_changed() {
@@ -128,14 +340,33 @@ impl Timer {
                         );
                     }
 
+                    // before next_msg, so held back posts don't use up the rotation
+                    if !quiet.allows(&cache, &timer_name).await {
+                        continue;
+                    }
+
+                    let msg = match Self::next_msg(&cache, &rotation_key, &msgs, random, platform)
+                        .await
+                    {
+                        Ok(Some(msg)) => msg,
+                        Ok(None) => continue,
+                        Err(e) => {
+                            tracing::error!(timer_name = %timer_name, "{}", e);
+                            continue;
+                        }
+                    };
+
                     // broadcast msg to any applicable chatbot
                     Response {
                         platform,
-                        channel: &*crate::CHANNEL_NAME,
+                        channel: crate::channel_name(),
+                        corr_id: corr_id(),
                         payload: Payload::Message {
                             user: None,
-                            msg: msg.clone(),
+                            msg: msg.into(),
                             meta: None,
+                            hint,
+                            thread: None,
                         },
                     }
                     .send(Location::Pubsub, &resp)
@@ -145,6 +376,6 @@ impl Timer {
             .instrument(info_span!("Timer")),
         );
 
-        Some(())
+        Some(handle)
     }
 }
@@ -0,0 +1,174 @@
+use super::{emote_stats::EmoteStats, util, CmdDesc, Context, Invokable, RunRes};
+use crate::{
+    cache::{Cache, RespType},
+    error,
+    i18n::tr,
+    msg::{
+        discord::ThreadReply, Chat, Invocation, Location, Payload, Permissions, Platform, Response,
+    },
+};
+use back_derive::command;
+
+#[command(locks(rate))]
+/// Show the most used emotes, as tallied by EmoteStats
+pub struct TopEmotes {
+    /// Command prefix
+    #[cmd(def("!topemotes"), constr(non_empty))]
+    prefix: String,
+    /// Autocorrect prefix
+    autocorrect: bool,
+    /// Platforms
+    #[cmd(defl("Platform::CHAT"))]
+    platforms: Platform,
+    /// Permissions
+    #[cmd(defl("Permissions::NONE"))]
+    perms: Permissions,
+    /// Cooldown per user (in seconds)
+    #[cmd(def(30u64), constr(range = "0..=86400"), group("Cooldowns"))]
+    ratelimit_user: u64,
+    /// Emotes to list
+    #[cmd(def(5u64), constr(range = "1..=20"))]
+    count: u64,
+    /// Where to reply on Discord (parent or new-thread, unset to reply where it was asked)
+    discord_thread: Option<String>,
+}
+
+impl TopEmotes {
+    fn parse_arguments(&self, chat: &Chat) -> Option<bool> {
+        let captures = util::PREFIX_REGEX.captures(&chat.msg)?;
+
+        util::check_autocorrect(
+            &self.prefix,
+            &captures[1],
+            self.autocorrect,
+            &self.levenshtein,
+        )
+    }
+
+    fn can_run(&self, ctx: &Context<'_>) -> Option<()> {
+        if !self.enabled {
+            return None;
+        }
+
+        // check if platform is applicable
+        if !self.platforms.contains(ctx.platform) {
+            return None;
+        }
+
+        // check perms
+        if ctx.user.perms < self.perms {
+            return None;
+        }
+
+        Some(())
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn chat(&self, ctx: &Context<'_>, chat: &Chat) -> error::Result<RunRes> {
+        if self.can_run(ctx).is_none() {
+            return Ok(RunRes::Disabled);
+        }
+
+        let autocorrect = match self.parse_arguments(chat) {
+            Some(a) => a,
+            None => return Ok(RunRes::Noop),
+        };
+
+        if autocorrect {
+            return Ok(RunRes::Autocorrect(self.prefix.clone()));
+        }
+
+        if util::ratelimit_user(
+            ctx,
+            self.ratelimit_user,
+            stringify!(TopEmotes),
+            &self.name,
+            &*TOPEMOTES_LOCK_RATE,
+        )
+        .await?
+        {
+            return Ok(RunRes::Ratelimited { global: false });
+        }
+
+        self.run(ctx).await
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn invoke(
+        &self,
+        ctx: &Context<'_>,
+        invocation: &Invocation,
+    ) -> Option<RunRes> {
+        self.can_run(ctx)?;
+
+        super::check_invoke_prefix(&self.prefix, &invocation.cmd)?;
+
+        match self.run(ctx).await {
+            Ok(r) => Some(r),
+            Err(e) => {
+                tracing::error!("{}", e);
+                None
+            }
+        }
+    }
+
+    #[tracing::instrument(level = "trace", skip_all, name = "TopEmotes")]
+    async fn run(&self, ctx: &Context<'_>) -> error::Result<RunRes> {
+        tracing::debug!(name = self.name.as_str(), user = ctx.user.name.as_str());
+
+        let top =
+            match Cache::Zrevrangewithscores(EmoteStats::tally_key(), 0, self.count as isize - 1)
+                .exec(ctx.cache)
+                .await?
+            {
+                RespType::VecStringScore(top) => top,
+                _ => unreachable!(),
+            };
+
+        let msg = if top.is_empty() {
+            tr("emotes.empty", &[])
+        } else {
+            let emotes = top
+                .iter()
+                .map(|(name, count)| tr("emotes.entry", &[("name", name), ("count", count)]))
+                .collect::<Vec<_>>()
+                .join(&tr("list.separator", &[]));
+            tr("emotes.top", &[("emotes", &emotes)])
+        };
+
+        Response {
+            platform: ctx.platform,
+            channel: crate::channel_name(),
+            corr_id: ctx.corr_id.clone(),
+            payload: Payload::Message {
+                user: ctx.reply_to(),
+                msg: msg.into(),
+                meta: ctx.meta.clone(),
+                hint: None,
+                thread: ThreadReply::from_config(&self.discord_thread),
+            },
+        }
+        .send(Location::Pubsub, ctx.resp)
+        .await;
+
+        Ok(RunRes::Ok)
+    }
+}
+
+impl CmdDesc for TopEmotes {
+    #[inline]
+    fn platform(&self) -> Platform {
+        self.platforms
+    }
+
+    #[inline]
+    fn description(&self, platform: Platform) -> Option<String> {
+        if platform.contains(Platform::DISCORD) {
+            return Some("See the most used emotes in chat".into());
+        }
+
+        None
+    }
+}
+
+impl Invokable for TopEmotes {}
@@ -3,6 +3,7 @@ use crate::db::give::{GiveSource, GiveTarget};
 use crate::db::Resp;
 use crate::db::{give::GiveOp, Db};
 use crate::error;
+use crate::i18n::tr;
 use crate::msg::{
     ArgMap, ArgMapError, Chat, Invocation, Location, Payload, Permissions, Platform, Response,
 };
@@ -36,7 +37,7 @@ pub struct Transfer {
     #[cmd(defl("Permissions::NONE"))]
     perms: Permissions,
     /// Cooldown per user (in seconds)
-    #[cmd(constr(pos))]
+    #[cmd(constr(pos), group("Cooldowns"))]
     ratelimit_user: u64,
     /// Min amount
     #[cmd(def(10i64), constr(pos))]
@@ -68,11 +69,19 @@ impl Transfer {
         let amount = if &captures[2] == "all" {
             -1
         } else {
-            captures[2].parse::<i32>()?
+            match captures[2].parse::<i32>() {
+                Ok(n) => n,
+                Err(_) => return Ok(None),
+            }
         };
 
-        let from = Platform::from_str(&captures[3]).unwrap();
-        let to = Platform::from_str(&captures[4]).unwrap();
+        let (from, to) = match (
+            Platform::from_str(&captures[3]),
+            Platform::from_str(&captures[4]),
+        ) {
+            (Ok(from), Ok(to)) => (from, to),
+            _ => return Ok(None),
+        };
 
         Ok(Some((autocorrect, Args { amount, from, to })))
     }
@@ -103,6 +112,9 @@ impl Transfer {
 
         let (autocorrect, args) = match self.parse_arguments(chat)? {
             Some(t) => t,
+            None if util::starts_with_prefix(&self.prefix, &chat.msg) => {
+                return Ok(RunRes::InvalidArgs)
+            }
             None => return Ok(RunRes::Noop),
         };
 
@@ -137,7 +149,10 @@ impl Transfer {
 
         super::check_invoke_prefix(&self.prefix, &invocation.cmd)?;
 
-        let args = Args::try_from(&invocation.args).ok()?;
+        let args = match Args::try_from(&invocation.args) {
+            Ok(args) => args,
+            Err(_) => return Some(RunRes::InvalidArgs),
+        };
 
         match util::ratelimit_user(
             ctx,
@@ -181,21 +196,25 @@ impl Transfer {
         match Db::Give(op).exec(ctx.db).await? {
             Resp::Give(amount) => {
                 // send reply
-                let msg = format!(
-                    "transferred {} point{} from {} to {}",
-                    amount,
-                    if args.amount != 1 { "s" } else { "" },
-                    args.from,
-                    args.to
+                let msg = tr(
+                    "transfer.success",
+                    &[
+                        ("amount", &ctx.currency.format(amount)),
+                        ("from", &args.from),
+                        ("to", &args.to),
+                    ],
                 );
 
                 Response {
                     platform: ctx.platform,
-                    channel: &*crate::CHANNEL_NAME,
+                    channel: crate::channel_name(),
+                    corr_id: ctx.corr_id.clone(),
                     payload: Payload::Message {
-                        user: Some((ctx.platform, ctx.user.clone())),
+                        user: ctx.reply_to(),
                         msg: msg.into(),
                         meta: ctx.meta.clone(),
+                        hint: None,
+                        thread: None,
                     },
                 }
                 .send(Location::Pubsub, ctx.resp)
@@ -0,0 +1,937 @@
+use super::{
+    util, wordlist_filter::normalize, Arg, ArgKind, ArgValue, CmdDesc, Context, Invokable,
+    RespHandle, RunRes,
+};
+use crate::{
+    cache::{self, Cache, RespType},
+    db::{
+        give::{GiveOp, GiveSource, GiveTarget},
+        Db,
+    },
+    error::{self, Error},
+    i18n::tr,
+    msg::{
+        corr_id, session, ArgMap, ArgMapError, Chat, Invocation, Location, Payload, Permissions,
+        Platform, Response,
+    },
+};
+use back_derive::command;
+use bb8_redis::redis;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use rand::Rng;
+use regex::Regex;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{fs, sync::watch, task::JoinHandle};
+use tracing::{debug_span, info_span, Instrument};
+use url::Url;
+
+static TRIVIA_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(\S+)(?:\s+(skip|scores|reset)\b)?\s*$").unwrap());
+
+static CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .unwrap()
+});
+
+/// Loaded question banks by source, dropped on config changes so edits to them get picked up
+static BANKS: Lazy<RwLock<HashMap<String, Arc<Vec<Question>>>>> = Lazy::new(Default::default);
+
+/// Entries shown with the scores
+const SCORES_TOP: isize = 5;
+
+#[derive(Debug)]
+enum Args {
+    /// Ask a question, or restate the running one
+    Ask,
+    Skip,
+    Scores,
+    Reset,
+}
+
+/// A question bank entry, the first answer is the one announced
+#[derive(Debug, Clone, Deserialize)]
+struct Question {
+    question: String,
+    answers: Vec<String>,
+}
+
+/// The running question, kept in the cache so it outlives restarts and config changes
+#[derive(Debug, Serialize, Deserialize)]
+struct TriviaState {
+    /// Unix millis it was asked at, tells questions apart
+    id: u64,
+    question: String,
+    answers: Vec<String>,
+    /// Unix secs
+    ends: u64,
+}
+
+impl TriviaState {
+    fn answer(&self) -> &str {
+        self.answers.first().map_or("", String::as_str)
+    }
+}
+
+/// The running question with its answers normalized, mirrored in memory so chat doesn't hit the cache for every message
+#[derive(Debug)]
+struct Active {
+    id: u64,
+    question: String,
+    answers: Vec<String>,
+}
+
+impl From<&TriviaState> for Active {
+    fn from(state: &TriviaState) -> Self {
+        Self {
+            id: state.id,
+            question: state.question.clone(),
+            answers: state.answers.iter().map(|a| normalize(a)).collect(),
+        }
+    }
+}
+
+impl Active {
+    /// Whether the message is one of the answers, ignoring case, diacritics and punctuation
+    fn accepts(&self, msg: &str) -> bool {
+        self.answers.contains(&normalize(msg))
+    }
+}
+
+type Current = Arc<RwLock<Option<Arc<Active>>>>;
+
+/// Where a question bank is loaded from
+#[derive(Debug, PartialEq)]
+enum BankSource {
+    Url(Url),
+    /// Relative to the config dir
+    File(PathBuf),
+}
+
+impl BankSource {
+    /// Only http(s) URLs, and files that stay inside the config dir
+    fn parse(bank: &str) -> error::Result<Self> {
+        if bank.contains("://") {
+            let url = Url::parse(bank).map_err(|e| Error::Generic(e.to_string()))?;
+            return match url.scheme() {
+                "http" | "https" => Ok(Self::Url(url)),
+                scheme => Err(Error::Generic(format!(
+                    "question banks can't be fetched over {}",
+                    scheme
+                ))),
+            };
+        }
+
+        let path = Path::new(bank);
+        if !path
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(Error::Generic(
+                "question bank files have to be in the config dir".into(),
+            ));
+        }
+        Ok(Self::File(path.to_owned()))
+    }
+}
+
+/// Everything a question needs to be asked and ended away from chat, by its timer or auto mode
+#[derive(Clone)]
+struct Round {
+    name: String,
+    platforms: Platform,
+    bank: String,
+    duration: u64,
+    current: Current,
+    cancel_chan: Option<watch::Receiver<()>>,
+    cache: cache::Handle,
+    resp: RespHandle,
+}
+
+#[command(locks(state, scores))]
+/// Ask chat trivia questions from a question bank, first correct answer wins
+pub struct Trivia {
+    /// Command prefix
+    #[cmd(def("!trivia"), constr(non_empty))]
+    prefix: String,
+    /// Autocorrect prefix
+    autocorrect: bool,
+    /// Platforms
+    #[cmd(defl("Platform::CHAT"))]
+    platforms: Platform,
+    /// Permissions to ask a question (skipping and resetting the scores is for mods)
+    #[cmd(defl("Permissions::NONE"))]
+    perms: Permissions,
+    /// Question bank, a JSON file in the config dir or an http(s) URL, as [{"question": "...", "answers": ["...", ...]}, ...]
+    #[cmd(def("trivia.json"), constr(non_empty))]
+    bank: String,
+    /// How long chat gets to answer (in seconds)
+    #[cmd(def(30u64), constr(range = "5..=600"))]
+    duration: u64,
+    /// Points for the first correct answer
+    #[cmd(def(100u64), constr(range = "0..=1000000"))]
+    reward: u64,
+    /// Ask a question every this many seconds (unset to only ask when someone uses the command)
+    #[cmd(constr(pos))]
+    auto_interval: Option<u64>,
+    #[cmd(skip)]
+    current: Current,
+    /// Stops question timers when the config changes, init picks them back up
+    #[cmd(skip)]
+    cancel_chan: RwLock<Option<watch::Receiver<()>>>,
+}
+
+impl Trivia {
+    fn parse_arguments(&self, chat: &Chat) -> Option<(bool, Args)> {
+        let captures = TRIVIA_REGEX.captures(&chat.msg)?;
+
+        // check command prefix
+        let autocorrect = util::check_autocorrect(
+            &self.prefix,
+            &captures[1],
+            self.autocorrect,
+            &self.levenshtein,
+        )?;
+
+        let args = match captures.get(2).map(|m| m.as_str()) {
+            None => Args::Ask,
+            Some("skip") => Args::Skip,
+            Some("scores") => Args::Scores,
+            Some(_) => Args::Reset,
+        };
+
+        Some((autocorrect, args))
+    }
+
+    fn can_run(&self, ctx: &Context<'_>) -> Option<()> {
+        if !self.enabled {
+            return None;
+        }
+
+        // check if platform is applicable
+        if !self.platforms.contains(ctx.platform) {
+            return None;
+        }
+
+        // check perms
+        if ctx.user.perms < self.perms {
+            return None;
+        }
+
+        Some(())
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn chat(&self, ctx: &Context<'_>, chat: &Chat) -> error::Result<RunRes> {
+        if !self.enabled || !self.platforms.contains(ctx.platform) {
+            return Ok(RunRes::Disabled);
+        }
+
+        let parsed = self.parse_arguments(chat);
+
+        // anything else might be an answer
+        if parsed.is_none() && !util::starts_with_prefix(&self.prefix, &chat.msg) {
+            return self.answer(ctx, &chat.msg).await;
+        }
+
+        if self.can_run(ctx).is_none() {
+            return Ok(RunRes::Disabled);
+        }
+
+        let (autocorrect, args) = match parsed {
+            Some(t) => t,
+            None => return Ok(RunRes::InvalidArgs),
+        };
+
+        if autocorrect {
+            return Ok(RunRes::Autocorrect(self.prefix.clone()));
+        }
+
+        self.run(ctx, args).await
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn invoke(
+        &self,
+        ctx: &Context<'_>,
+        invocation: &Invocation,
+    ) -> Option<RunRes> {
+        self.can_run(ctx)?;
+
+        super::check_invoke_prefix(&self.prefix, &invocation.cmd)?;
+
+        let args = match Args::try_from(&invocation.args) {
+            Ok(args) => args,
+            Err(_) => return Some(RunRes::InvalidArgs),
+        };
+
+        match self.run(ctx, args).await {
+            Ok(r) => Some(r),
+            Err(e) => {
+                tracing::error!("{}", e);
+                None
+            }
+        }
+    }
+
+    fn state_key(name: &str) -> Arc<String> {
+        Arc::new(format!("{}_{}", &*TRIVIA_LOCK_STATE, name))
+    }
+
+    /// (scores, names, session the scores are from)
+    fn score_keys(name: &str) -> (Arc<String>, Arc<String>, Arc<String>) {
+        (
+            Arc::new(format!("{}_{}", &*TRIVIA_LOCK_SCORES, name)),
+            Arc::new(format!("{}_{}_names", &*TRIVIA_LOCK_SCORES, name)),
+            Arc::new(format!("{}_{}_session", &*TRIVIA_LOCK_SCORES, name)),
+        )
+    }
+
+    fn round(&self, cache: &cache::Handle, resp: &RespHandle) -> Round {
+        Round {
+            name: self.name.clone(),
+            platforms: self.platforms,
+            bank: self.bank.clone(),
+            duration: self.duration,
+            current: self.current.clone(),
+            cancel_chan: self.cancel_chan.read().clone(),
+            cache: cache.clone(),
+            resp: resp.clone(),
+        }
+    }
+
+    async fn reply(ctx: &Context<'_>, msg: String) {
+        Response {
+            platform: ctx.platform,
+            channel: crate::channel_name(),
+            corr_id: ctx.corr_id.clone(),
+            payload: Payload::Message {
+                user: ctx.reply_to(),
+                msg: msg.into(),
+                meta: ctx.meta.clone(),
+                hint: None,
+                thread: None,
+            },
+        }
+        .send(Location::Pubsub, ctx.resp)
+        .await;
+    }
+
+    async fn announce(resp: &RespHandle, platforms: Platform, msg: String) {
+        Response {
+            platform: platforms,
+            channel: crate::channel_name(),
+            corr_id: corr_id(),
+            payload: Payload::Message {
+                user: None,
+                msg: msg.into(),
+                meta: None,
+                hint: None,
+                thread: None,
+            },
+        }
+        .send(Location::Pubsub, resp)
+        .await;
+    }
+
+    #[tracing::instrument(level = "trace", skip_all, name = "Trivia")]
+    async fn run(&self, ctx: &Context<'_>, args: Args) -> error::Result<RunRes> {
+        tracing::debug!(name = self.name.as_str(), user = ctx.user.name.as_str(), args = ?args);
+
+        let is_mod = ctx.user.perms >= Permissions::MOD;
+
+        match args {
+            Args::Ask => {
+                let active = self.current.read().clone();
+                if let Some(active) = active {
+                    let msg = tr("trivia.running", &[("question", &active.question)]);
+                    Self::reply(ctx, msg).await;
+                    return Ok(RunRes::Ok);
+                }
+                match self.round(ctx.cache, ctx.resp).ask().await {
+                    Ok(true) => {}
+                    // raced with another !trivia or auto mode, which announced theirs
+                    Ok(false) => return Ok(RunRes::Noop),
+                    Err(e) => {
+                        tracing::error!(bank = self.bank.as_str(), "{}", e);
+                        Self::reply(ctx, tr("trivia.unavailable", &[])).await;
+                    }
+                }
+            }
+            Args::Skip if is_mod => {
+                let round = self.round(ctx.cache, ctx.resp);
+                match round.finish(None).await? {
+                    Some(state) => {
+                        let msg = tr("trivia.skipped", &[("answer", &state.answer())]);
+                        Self::announce(ctx.resp, self.platforms, msg).await;
+                    }
+                    None => Self::reply(ctx, tr("trivia.not_running", &[])).await,
+                }
+            }
+            Args::Scores => {
+                let scores = Self::scores(ctx.cache, &self.name).await?;
+                let msg = if scores.is_empty() {
+                    tr("trivia.no_scores", &[])
+                } else {
+                    let separator = tr("list.separator", &[]);
+                    let scores = scores
+                        .iter()
+                        .map(|(name, count)| {
+                            tr("trivia.score", &[("name", name), ("count", count)])
+                        })
+                        .collect::<Vec<_>>()
+                        .join(&separator);
+                    tr("trivia.scores", &[("scores", &scores)])
+                };
+                Self::reply(ctx, msg).await;
+            }
+            Args::Reset if is_mod => {
+                let (scores_key, names_key, _) = Self::score_keys(&self.name);
+                Cache::Delete(scores_key).exec(ctx.cache).await?;
+                Cache::Delete(names_key).exec(ctx.cache).await?;
+                tracing::info!("trivia scores reset");
+                Self::reply(ctx, tr("trivia.reset", &[])).await;
+            }
+            Args::Skip | Args::Reset => return Ok(RunRes::Disabled),
+        }
+
+        Ok(RunRes::Ok)
+    }
+
+    /// End the question with `ctx.user` as the winner if the message answers it
+    async fn answer(&self, ctx: &Context<'_>, msg: &str) -> error::Result<RunRes> {
+        let active = match self.current.read().clone() {
+            Some(active) => active,
+            None => return Ok(RunRes::Noop),
+        };
+
+        if !active.accepts(msg) {
+            return Ok(RunRes::Noop);
+        }
+
+        // someone else might've beaten them to it
+        let state = match self
+            .round(ctx.cache, ctx.resp)
+            .finish(Some(active.id))
+            .await?
+        {
+            Some(state) => state,
+            None => return Ok(RunRes::Noop),
+        };
+
+        let reward = self.reward.min(i32::MAX as u64) as i32;
+        if reward > 0 {
+            Db::Give(GiveOp {
+                amount: reward,
+                from: GiveSource::None,
+                to: GiveTarget::User(ctx.platform, ctx.user.id.clone(), ctx.user.name.clone()),
+                min: 0,
+                max: 0,
+            })
+            .exec(ctx.db)
+            .await?;
+        }
+        if let Err(e) = Self::score(ctx, &self.name).await {
+            tracing::error!("couldn't update trivia scores: {}", e);
+        }
+
+        tracing::info!(
+            user = ctx.user.name.as_str(),
+            reward,
+            "trivia question answered"
+        );
+
+        let msg = if reward > 0 {
+            tr(
+                "trivia.won_points",
+                &[
+                    ("name", &ctx.user.name),
+                    ("answer", &state.answer()),
+                    ("amount", &ctx.currency.format(reward)),
+                ],
+            )
+        } else {
+            tr(
+                "trivia.won",
+                &[("name", &ctx.user.name), ("answer", &state.answer())],
+            )
+        };
+        Self::announce(ctx.resp, self.platforms, msg).await;
+
+        Ok(RunRes::Ok)
+    }
+
+    /// Scores only count towards the stream session they were won in,
+    /// so they're cleared the first time they're touched in a new one
+    async fn check_session(cache: &cache::Handle, name: &str) -> error::Result<()> {
+        let (scores_key, names_key, session_key) = Self::score_keys(name);
+        let session = session::started_at(cache).await?.unwrap_or_default();
+        let session = Arc::new(session.to_string());
+
+        let stale = match Cache::SetGet(session_key, session.clone(), 0)
+            .exec(cache)
+            .await
+        {
+            Ok(RespType::String(last)) => last != *session,
+            Ok(_) => unreachable!(),
+            Err(Error::Redis(e)) if e.kind() == redis::ErrorKind::TypeError => true,
+            Err(e) => return Err(e),
+        };
+        if stale {
+            Cache::Delete(scores_key).exec(cache).await?;
+            Cache::Delete(names_key).exec(cache).await?;
+        }
+        Ok(())
+    }
+
+    async fn score(ctx: &Context<'_>, name: &str) -> error::Result<()> {
+        Self::check_session(ctx.cache, name).await?;
+
+        let (scores_key, names_key, _) = Self::score_keys(name);
+        let member = Arc::new(format!("{}:{}", ctx.platform, ctx.user.id));
+        Cache::Zincrby(scores_key, member.clone(), 1)
+            .exec(ctx.cache)
+            .await?;
+        Cache::HashSet(names_key, member, ctx.user.name.to_string(), false)
+            .exec(ctx.cache)
+            .await?;
+        Ok(())
+    }
+
+    /// (name, correct answers) this session, most first
+    async fn scores(cache: &cache::Handle, name: &str) -> error::Result<Vec<(String, isize)>> {
+        Self::check_session(cache, name).await?;
+
+        let (scores_key, names_key, _) = Self::score_keys(name);
+        let scores = match Cache::Zrevrangewithscores(scores_key, 0, SCORES_TOP - 1)
+            .exec(cache)
+            .await?
+        {
+            RespType::VecStringScore(scores) => scores,
+            _ => unreachable!(),
+        };
+        if scores.is_empty() {
+            return Ok(scores);
+        }
+
+        let names: HashMap<_, _> = match Cache::HashGetAll(names_key).exec(cache).await? {
+            RespType::VecStringString(names) => names.into_iter().collect(),
+            _ => unreachable!(),
+        };
+        Ok(scores
+            .into_iter()
+            .map(|(member, count)| (names.get(&member).cloned().unwrap_or(member), count))
+            .collect())
+    }
+
+    /// Keep the cancel chan for questions asked from now on, and pick up any question
+    /// left running from before a restart or config change
+    pub(crate) fn init(
+        &self,
+        cancel_chan: watch::Receiver<()>,
+        cache: &cache::Handle,
+        resp: &RespHandle,
+    ) -> Option<()> {
+        *self.cancel_chan.write() = Some(cancel_chan);
+        BANKS.write().remove(&self.bank);
+
+        if !self.enabled {
+            return None;
+        }
+
+        let round = self.round(cache, resp);
+        tokio::spawn(
+            async move {
+                let state = match Cache::Get(Self::state_key(&round.name))
+                    .exec(&round.cache)
+                    .await
+                {
+                    Ok(RespType::String(state)) => state,
+                    Ok(_) => unreachable!(),
+                    Err(Error::Redis(e)) if e.kind() == redis::ErrorKind::TypeError => return,
+                    Err(e) => {
+                        tracing::error!("{}", e);
+                        return;
+                    }
+                };
+                let state = match serde_json::from_str::<TriviaState>(&state) {
+                    Ok(state) => state,
+                    Err(e) => {
+                        tracing::error!("{}", e);
+                        return;
+                    }
+                };
+
+                *round.current.write() = Some(Arc::new(Active::from(&state)));
+                tracing::info!(id = state.id, "trivia question resumed");
+
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or_default();
+                round.schedule_end(
+                    state.id,
+                    Duration::from_secs(state.ends.saturating_sub(now)),
+                );
+            }
+            .instrument(debug_span!("Trivia resume")),
+        );
+
+        Some(())
+    }
+
+    /// Ask a question every `auto_interval`, unless one's already running
+    pub(crate) fn auto(
+        &self,
+        cancel_chan: watch::Receiver<()>,
+        cache: &cache::Handle,
+        resp: &RespHandle,
+    ) -> Option<JoinHandle<()>> {
+        let interval = match self.auto_interval {
+            Some(interval) if self.enabled && interval > 0 && !self.platforms.is_empty() => {
+                interval
+            }
+            _ => return None,
+        };
+
+        tracing::info!(
+            "\x1b[93mSpawning Trivia {:?} with interval: {}s\x1b[0m",
+            self.name,
+            interval
+        );
+
+        let round = Round {
+            cancel_chan: Some(cancel_chan.clone()),
+            ..self.round(cache, resp)
+        };
+
+        let handle = tokio::spawn(
+            async move {
+                loop {
+                    tokio::time::sleep(Duration::from_secs(interval)).await;
+
+                    match cancel_chan.has_changed() {
+                        Ok(false) => {}
+                        _ => {
+                            // value changed or channel closed
+                            tracing::info!(name = %round.name, "\x1b[93maborting\x1b[0m");
+                            return;
+                        }
+                    }
+
+                    if round.current.read().is_some() {
+                        continue;
+                    }
+                    if let Err(e) = round.ask().await {
+                        tracing::error!(name = %round.name, bank = round.bank.as_str(), "{}", e);
+                    }
+                }
+            }
+            .instrument(info_span!("Trivia")),
+        );
+
+        Some(handle)
+    }
+}
+
+impl Round {
+    /// The question bank, loaded on first use
+    async fn bank(&self) -> error::Result<Arc<Vec<Question>>> {
+        if let Some(bank) = BANKS.read().get(&self.bank) {
+            return Ok(bank.clone());
+        }
+
+        let json = match BankSource::parse(&self.bank)? {
+            BankSource::Url(url) => {
+                CLIENT
+                    .get(url)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .text()
+                    .await?
+            }
+            BankSource::File(path) => fs::read_to_string(crate::config_dir().join(path)).await?,
+        };
+
+        let questions = serde_json::from_str::<Vec<Question>>(&json)?
+            .into_iter()
+            .filter(|q| !q.question.trim().is_empty())
+            .map(|mut q| {
+                q.answers.retain(|a| !normalize(a).trim().is_empty());
+                q
+            })
+            .filter(|q| !q.answers.is_empty())
+            .collect::<Vec<_>>();
+        if questions.is_empty() {
+            return Err(Error::Generic("no usable questions in the bank".into()));
+        }
+        tracing::info!(
+            bank = self.bank.as_str(),
+            count = questions.len(),
+            "question bank loaded"
+        );
+
+        let questions = Arc::new(questions);
+        BANKS.write().insert(self.bank.clone(), questions.clone());
+        Ok(questions)
+    }
+
+    /// Ask a random question and announce it, false if there's one running already
+    async fn ask(&self) -> error::Result<bool> {
+        let bank = self.bank().await?;
+        let state_key = Trivia::state_key(&self.name);
+
+        // don't ask the last question again straight away
+        let last = match Cache::Get(Arc::new(format!("{}_last", state_key)))
+            .exec(&self.cache)
+            .await
+        {
+            Ok(RespType::String(last)) => last.parse::<usize>().ok(),
+            Ok(_) => unreachable!(),
+            Err(Error::Redis(e)) if e.kind() == redis::ErrorKind::TypeError => None,
+            Err(e) => return Err(e),
+        };
+        let index = {
+            let mut rng = rand::thread_rng();
+            match last {
+                Some(last) if bank.len() > 1 && last < bank.len() => {
+                    (last + rng.gen_range(1..bank.len())) % bank.len()
+                }
+                _ => rng.gen_range(0..bank.len()),
+            }
+        };
+        let question = &bank[index];
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+        let state = TriviaState {
+            id: now.as_millis() as u64,
+            question: question.question.clone(),
+            answers: question.answers.clone(),
+            ends: now.as_secs() + self.duration,
+        };
+
+        // only one question at a time
+        let json = serde_json::to_string(&state)?;
+        match Cache::Set(
+            state_key.clone(),
+            json.into(),
+            self.duration as usize + 60,
+            true,
+        )
+        .exec(&self.cache)
+        .await?
+        {
+            RespType::Bool(true) => {}
+            RespType::Bool(false) => return Ok(false),
+            _ => unreachable!(),
+        }
+        Cache::Set(
+            Arc::new(format!("{}_last", state_key)),
+            index.to_string().into(),
+            0,
+            false,
+        )
+        .exec(&self.cache)
+        .await?;
+
+        tracing::info!(question = state.question.as_str(), "trivia question asked");
+
+        *self.current.write() = Some(Arc::new(Active::from(&state)));
+        self.schedule_end(state.id, Duration::from_secs(self.duration));
+
+        let msg = tr(
+            "trivia.question",
+            &[("question", &state.question), ("duration", &self.duration)],
+        );
+        Trivia::announce(&self.resp, self.platforms, msg).await;
+
+        Ok(true)
+    }
+
+    /// End the question, returning it if it was still running so the caller can announce how it went.
+    /// Whoever takes the state from the cache wins, timers and answers pass the id of the question they're for,
+    /// so they don't end a newer one
+    async fn finish(&self, id: Option<u64>) -> error::Result<Option<TriviaState>> {
+        if let Some(id) = id {
+            if self.current.read().as_ref().map(|a| a.id) != Some(id) {
+                return Ok(None);
+            }
+        }
+
+        let state = match Cache::GetDel(Trivia::state_key(&self.name))
+            .exec(&self.cache)
+            .await
+        {
+            Ok(RespType::String(state)) => serde_json::from_str::<TriviaState>(&state)?,
+            Ok(_) => unreachable!(),
+            Err(Error::Redis(e)) if e.kind() == redis::ErrorKind::TypeError => {
+                *self.current.write() = None;
+                return Ok(None);
+            }
+            Err(e) => return Err(e),
+        };
+        *self.current.write() = None;
+
+        Ok(Some(state))
+    }
+
+    fn schedule_end(&self, id: u64, after: Duration) {
+        let round = self.clone();
+        tokio::spawn(
+            async move {
+                let cancelled = async {
+                    match round.cancel_chan.clone() {
+                        Some(mut chan) => {
+                            let _ = chan.changed().await;
+                        }
+                        None => futures_util::future::pending().await,
+                    }
+                };
+
+                tokio::select! {
+                    _ = tokio::time::sleep(after) => {
+                        match round.finish(Some(id)).await {
+                            Ok(Some(state)) => {
+                                tracing::info!(id, "trivia question timed out");
+                                let msg = tr("trivia.timeout", &[("answer", &state.answer())]);
+                                Trivia::announce(&round.resp, round.platforms, msg).await;
+                            }
+                            Ok(None) => {}
+                            Err(e) => tracing::error!("{}", e),
+                        }
+                    }
+                    // the question's still in the cache, init picks it back up
+                    _ = cancelled => {}
+                }
+            }
+            .instrument(debug_span!("Trivia timer")),
+        );
+    }
+}
+
+impl CmdDesc for Trivia {
+    #[inline]
+    fn platform(&self) -> Platform {
+        self.platforms
+    }
+
+    #[inline]
+    fn description(&self, platform: Platform) -> Option<String> {
+        if platform.contains(Platform::DISCORD) {
+            return Some("Ask a trivia question, first correct answer in chat wins".into());
+        }
+
+        None
+    }
+}
+
+impl Invokable for Trivia {
+    fn args(&self, _platform: Platform) -> Vec<Arg> {
+        vec![
+            Arg {
+                name: "ask".into(),
+                desc: "Ask a question".into(),
+                kind: ArgKind::SubCommand(vec![]),
+                optional: true,
+            },
+            Arg {
+                name: "skip".into(),
+                desc: "End the question without a winner".into(),
+                kind: ArgKind::SubCommand(vec![]),
+                optional: true,
+            },
+            Arg {
+                name: "scores".into(),
+                desc: "Show this stream's scores".into(),
+                kind: ArgKind::SubCommand(vec![]),
+                optional: true,
+            },
+            Arg {
+                name: "reset".into(),
+                desc: "Clear this stream's scores".into(),
+                kind: ArgKind::SubCommand(vec![]),
+                optional: true,
+            },
+        ]
+    }
+}
+
+impl TryFrom<&ArgMap> for Args {
+    type Error = ArgMapError;
+
+    fn try_from(value: &ArgMap) -> Result<Self, Self::Error> {
+        if let Some(ArgValue::SubCommand(_c)) = value.get("ask") {
+            Ok(Args::Ask)
+        } else if let Some(ArgValue::SubCommand(_c)) = value.get("skip") {
+            Ok(Args::Skip)
+        } else if let Some(ArgValue::SubCommand(_c)) = value.get("scores") {
+            Ok(Args::Scores)
+        } else if let Some(ArgValue::SubCommand(_c)) = value.get("reset") {
+            Ok(Args::Reset)
+        } else {
+            Err(ArgMapError)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn active(answers: &[&str]) -> Active {
+        Active::from(&TriviaState {
+            id: 1,
+            question: "?".into(),
+            answers: answers.iter().map(|a| a.to_string()).collect(),
+            ends: 0,
+        })
+    }
+
+    #[test]
+    fn answers_ignore_case_and_diacritics() {
+        let active = active(&["Pokémon", "Crème Brûlée"]);
+        assert!(active.accepts("pokemon"));
+        assert!(active.accepts("POKÉMON!"));
+        assert!(active.accepts("  creme   brulee "));
+        assert!(!active.accepts("pokemons"));
+        assert!(!active.accepts("creme"));
+    }
+
+    #[test]
+    fn bank_files_stay_in_the_config_dir() {
+        assert_eq!(
+            BankSource::parse("trivia.json").unwrap(),
+            BankSource::File("trivia.json".into())
+        );
+        assert_eq!(
+            BankSource::parse("./banks/music.json").unwrap(),
+            BankSource::File("./banks/music.json".into())
+        );
+        assert!(BankSource::parse("/etc/passwd").is_err());
+        assert!(BankSource::parse("../secrets.json").is_err());
+        assert!(BankSource::parse("banks/../../secrets.json").is_err());
+    }
+
+    #[test]
+    fn banks_are_only_fetched_over_http() {
+        assert!(matches!(
+            BankSource::parse("https://example.com/trivia.json"),
+            Ok(BankSource::Url(_))
+        ));
+        assert!(matches!(
+            BankSource::parse("http://example.com/trivia.json"),
+            Ok(BankSource::Url(_))
+        ));
+        assert!(BankSource::parse("file:///etc/passwd").is_err());
+        assert!(BankSource::parse("ftp://example.com/trivia.json").is_err());
+    }
+}
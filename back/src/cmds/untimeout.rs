@@ -0,0 +1,142 @@
+use super::{
+    bulk_mod::{self, Args, Single},
+    util, Arg, CmdDesc, Context, Invokable, ModAction, RunRes,
+};
+use crate::{
+    error,
+    msg::{Chat, Invocation, Permissions, Platform},
+};
+use back_derive::command;
+
+#[command(locks(pending))]
+/// Lift a user's timeout, once confirmed
+pub struct Untimeout {
+    /// Command prefix
+    #[cmd(def("!untimeout"), constr(non_empty))]
+    prefix: String,
+    /// Autocorrect prefix
+    autocorrect: bool,
+    /// Platforms
+    #[cmd(defl("Platform::CHAT"))]
+    platforms: Platform,
+    /// Permissions
+    #[cmd(defl("Permissions::ADMIN"))]
+    perms: Permissions,
+    /// How long there is to confirm (in seconds)
+    #[cmd(def(60u64), constr(range = "10..=600"))]
+    confirm_secs: u64,
+}
+
+impl Untimeout {
+    fn can_run(&self, ctx: &Context<'_>) -> Option<()> {
+        if !self.enabled {
+            return None;
+        }
+
+        // check if platform is applicable
+        if !self.platforms.contains(ctx.platform) {
+            return None;
+        }
+
+        // check perms
+        if ctx.user.perms < self.perms {
+            return None;
+        }
+
+        Some(())
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn chat(&self, ctx: &Context<'_>, chat: &Chat) -> error::Result<RunRes> {
+        if self.can_run(ctx).is_none() {
+            return Ok(RunRes::Disabled);
+        }
+
+        let (autocorrect, args) = match bulk_mod::parse_arguments(
+            &self.prefix,
+            self.autocorrect,
+            &self.levenshtein,
+            chat,
+        ) {
+            Some(t) => t,
+            None if util::starts_with_prefix(&self.prefix, &chat.msg) => {
+                return Ok(RunRes::InvalidArgs)
+            }
+            None => return Ok(RunRes::Noop),
+        };
+
+        if autocorrect {
+            return Ok(RunRes::Autocorrect(self.prefix.clone()));
+        }
+
+        self.run(ctx, args).await
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn invoke(
+        &self,
+        ctx: &Context<'_>,
+        invocation: &Invocation,
+    ) -> Option<RunRes> {
+        self.can_run(ctx)?;
+
+        super::check_invoke_prefix(&self.prefix, &invocation.cmd)?;
+
+        let args = match Args::try_from(&invocation.args) {
+            Ok(args) => args,
+            Err(_) => return Some(RunRes::InvalidArgs),
+        };
+
+        match self.run(ctx, args).await {
+            Ok(r) => Some(r),
+            Err(e) => {
+                tracing::error!("{}", e);
+                None
+            }
+        }
+    }
+
+    #[tracing::instrument(level = "trace", skip_all, name = "Untimeout")]
+    async fn run(&self, ctx: &Context<'_>, args: Args) -> error::Result<RunRes> {
+        tracing::debug!(name = self.name.as_str(), user = ctx.user.name.as_str(), args = ?args);
+
+        Single {
+            cmd: "Untimeout",
+            lock: &UNTIMEOUT_LOCK_PENDING,
+            name: &self.name,
+            prefix: &self.prefix,
+            confirm_secs: self.confirm_secs,
+            action: ModAction::Untimeout,
+            confirm_key: "bulkmod.confirm_untimeout",
+            done_key: "bulkmod.untimed_out",
+        }
+        .run(ctx, args)
+        .await
+    }
+}
+
+impl CmdDesc for Untimeout {
+    #[inline]
+    fn platform(&self) -> Platform {
+        self.platforms
+    }
+
+    #[inline]
+    fn description(&self, platform: Platform) -> Option<String> {
+        if platform.contains(Platform::DISCORD) {
+            return Some("Lift a user's timeout".into());
+        }
+
+        None
+    }
+}
+
+impl Invokable for Untimeout {
+    fn args(&self, _platform: Platform) -> Vec<Arg> {
+        bulk_mod::invoke_args(bulk_mod::user_arg())
+    }
+
+    fn hidden(&self, _platform: Platform) -> bool {
+        true
+    }
+}
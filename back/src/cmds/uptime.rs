@@ -0,0 +1,269 @@
+use super::{util, CmdDesc, Context, Invokable, RunRes};
+use crate::{
+    cache::{self, Cache, RespType},
+    error::{self, Error},
+    i18n::{plural, tr},
+    msg::{Chat, Invocation, Location, Payload, Permissions, Platform, Response},
+};
+use back_derive::command;
+use bb8_redis::redis;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Connectors republish well within this, so stale metadata expires on its own
+pub(crate) const METADATA_TTL: usize = 60 * 10;
+
+/// Latest StreamEvent::Metadata for a platform
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Metadata {
+    pub(crate) title: Arc<String>,
+    pub(crate) started_at: u64,
+    pub(crate) viewer_count: u64,
+}
+
+pub(crate) fn metadata_key(platform: Platform) -> String {
+    format!(
+        "aussiebot!{}!streammeta!{}",
+        crate::channel_name(),
+        platform
+    )
+}
+
+/// None if the platform isn't live
+pub(crate) async fn metadata(
+    cache: &cache::Handle,
+    platform: Platform,
+) -> error::Result<Option<Metadata>> {
+    match Cache::Get(metadata_key(platform).into()).exec(cache).await {
+        Ok(RespType::String(s)) => Ok(Some(serde_json::from_str(&s)?)),
+        Ok(_) => unreachable!(),
+        // not live, or the connector hasn't published yet
+        Err(Error::Redis(e)) if e.kind() == redis::ErrorKind::TypeError => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Args {
+    Uptime,
+    Title,
+    /// Both, for slash commands
+    All,
+}
+
+#[command(locks(rate))]
+/// Show how long the stream has been live, and its title
+pub struct Uptime {
+    /// Command prefix
+    #[cmd(def("!uptime"), constr(non_empty))]
+    prefix: String,
+    /// Title command prefix
+    #[cmd(def("!title"), constr(non_empty))]
+    title_prefix: String,
+    /// Autocorrect prefix
+    autocorrect: bool,
+    /// Platforms
+    #[cmd(defl("Platform::CHAT"))]
+    platforms: Platform,
+    /// Permissions
+    #[cmd(defl("Permissions::NONE"))]
+    perms: Permissions,
+    /// Cooldown per user (in seconds)
+    #[cmd(constr(pos), group("Cooldowns"))]
+    ratelimit_user: u64,
+}
+
+impl Uptime {
+    fn parse_arguments(&self, chat: &Chat) -> Option<(bool, Args)> {
+        let captures = util::PREFIX_REGEX.captures(&chat.msg)?;
+
+        if captures[1].eq_ignore_ascii_case(&self.title_prefix) {
+            return Some((false, Args::Title));
+        }
+
+        // check command prefix
+        let autocorrect = util::check_autocorrect(
+            &self.prefix,
+            &captures[1],
+            self.autocorrect,
+            &self.levenshtein,
+        )?;
+
+        Some((autocorrect, Args::Uptime))
+    }
+
+    fn can_run(&self, ctx: &Context<'_>) -> Option<()> {
+        if !self.enabled {
+            return None;
+        }
+
+        // check if platform is applicable
+        if !self.platforms.contains(ctx.platform) {
+            return None;
+        }
+
+        // check perms
+        if ctx.user.perms < self.perms {
+            return None;
+        }
+
+        Some(())
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn chat(&self, ctx: &Context<'_>, chat: &Chat) -> error::Result<RunRes> {
+        if self.can_run(ctx).is_none() {
+            return Ok(RunRes::Disabled);
+        }
+
+        let (autocorrect, args) = match self.parse_arguments(chat) {
+            Some(t) => t,
+            None => return Ok(RunRes::Noop),
+        };
+
+        if autocorrect {
+            return Ok(RunRes::Autocorrect(self.prefix.clone()));
+        }
+
+        if util::ratelimit_user(
+            ctx,
+            self.ratelimit_user,
+            stringify!(Uptime),
+            &self.name,
+            &*UPTIME_LOCK_RATE,
+        )
+        .await?
+        {
+            return Ok(RunRes::Ratelimited { global: false });
+        }
+
+        self.run(ctx, args).await
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn invoke(
+        &self,
+        ctx: &Context<'_>,
+        invocation: &Invocation,
+    ) -> Option<RunRes> {
+        self.can_run(ctx)?;
+
+        super::check_invoke_prefix(&self.prefix, &invocation.cmd)?;
+
+        match self.run(ctx, Args::All).await {
+            Ok(r) => Some(r),
+            Err(e) => {
+                tracing::error!("{}", e);
+                None
+            }
+        }
+    }
+
+    pub(crate) fn elapsed(started_at: u64) -> error::Result<String> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let minutes = now.saturating_sub(started_at) / 60;
+        let (hours, minutes) = (minutes / 60, minutes % 60);
+
+        Ok(tr(
+            "uptime.elapsed",
+            &[
+                ("hours", &hours),
+                ("hours_s", &plural(hours)),
+                ("minutes", &minutes),
+                ("minutes_s", &plural(minutes)),
+            ],
+        ))
+    }
+
+    fn format(args: Args, platform: Platform, metadata: &Metadata) -> error::Result<String> {
+        let viewers = metadata.viewer_count;
+        Ok(match args {
+            Args::Uptime => tr(
+                "uptime.live",
+                &[
+                    ("platform", &platform),
+                    ("elapsed", &Self::elapsed(metadata.started_at)?),
+                    ("viewers", &viewers),
+                    ("s", &plural(viewers)),
+                ],
+            ),
+            Args::Title => tr(
+                "uptime.title",
+                &[("platform", &platform), ("title", &metadata.title)],
+            ),
+            Args::All => tr(
+                "uptime.all",
+                &[
+                    ("platform", &platform),
+                    ("title", &metadata.title),
+                    ("elapsed", &Self::elapsed(metadata.started_at)?),
+                    ("viewers", &viewers),
+                    ("s", &plural(viewers)),
+                ],
+            ),
+        })
+    }
+
+    #[tracing::instrument(level = "trace", skip_all, name = "Uptime")]
+    async fn run(&self, ctx: &Context<'_>, args: Args) -> error::Result<RunRes> {
+        tracing::debug!(name = self.name.as_str(), user = ctx.user.name.as_str(), args = ?args);
+
+        // stream chats get their own stream, elsewhere every live stream is listed
+        let platforms: Vec<Platform> = if Platform::STREAM.contains(ctx.platform) {
+            vec![ctx.platform]
+        } else {
+            vec![Platform::YOUTUBE, Platform::TWITCH]
+        };
+
+        let mut lines = vec![];
+        for platform in platforms {
+            if let Some(metadata) = metadata(ctx.cache, platform).await? {
+                lines.push(Self::format(args, platform, &metadata)?);
+            }
+        }
+
+        let msg = if lines.is_empty() {
+            tr("uptime.offline", &[])
+        } else {
+            lines.join("\n")
+        };
+
+        Response {
+            platform: ctx.platform,
+            channel: crate::channel_name(),
+            corr_id: ctx.corr_id.clone(),
+            payload: Payload::Message {
+                user: ctx.reply_to(),
+                msg: msg.into(),
+                meta: ctx.meta.clone(),
+                hint: None,
+                thread: None,
+            },
+        }
+        .send(Location::Pubsub, ctx.resp)
+        .await;
+
+        Ok(RunRes::Ok)
+    }
+}
+
+impl CmdDesc for Uptime {
+    #[inline]
+    fn platform(&self) -> Platform {
+        self.platforms
+    }
+
+    #[inline]
+    fn description(&self, platform: Platform) -> Option<String> {
+        if platform.contains(Platform::DISCORD) {
+            return Some("Check the stream's title and how long it's been live".into());
+        }
+
+        None
+    }
+}
+
+impl Invokable for Uptime {}
@@ -0,0 +1,97 @@
+//! Users looked up by id or display name, kept in the cache for a little while so commands
+//! taking a name don't each go to the db for it. Entries are dropped whenever the db
+//! might pick someone else for a name, i.e. when a user chats or their points are set by name
+
+use crate::{
+    cache::{self, Cache, RespType},
+    db::{self, users::UserKey, Db, Resp},
+    error::{self, Error},
+    msg::Platform,
+};
+use bb8_redis::redis;
+use serde_derive::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// How long a looked up user is kept for (in seconds)
+const USER_TTL: usize = 60;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct CachedUser {
+    pub(crate) platform: Platform,
+    pub(crate) id: Arc<String>,
+    pub(crate) name: Arc<String>,
+}
+
+fn key(platform: Platform, key: &UserKey) -> Arc<String> {
+    let (kind, value) = match key {
+        UserKey::Id(id) => ("id", id.to_string()),
+        // names are matched ignoring case
+        UserKey::Name(name) => ("name", name.to_lowercase()),
+    };
+    Arc::new(format!(
+        "aussiebot!{}!user!{}!{}!{}",
+        crate::channel_name(),
+        platform,
+        kind,
+        value
+    ))
+}
+
+/// Look a user up, from the cache if they were recently. None if they've never been seen
+pub(crate) async fn find(
+    cache: &cache::Handle,
+    db: &db::Handle,
+    platform: Platform,
+    user: UserKey,
+) -> error::Result<Option<CachedUser>> {
+    let cache_key = key(platform, &user);
+    match Cache::Get(cache_key.clone()).exec(cache).await {
+        Ok(RespType::String(cached)) => match serde_json::from_str(&cached) {
+            Ok(cached) => return Ok(Some(cached)),
+            Err(e) => tracing::warn!(key = cache_key.as_str(), "bad cached user: {}", e),
+        },
+        Ok(_) => unreachable!(),
+        Err(Error::Redis(e)) if e.kind() == redis::ErrorKind::TypeError => {}
+        Err(e) => return Err(e),
+    }
+
+    // users who haven't been seen aren't kept, so they're found as soon as they chat
+    let (id, name) = match Db::FindUser(platform, user.clone()).exec(db).await? {
+        Resp::User(Some(found)) => found,
+        Resp::User(None) => return Ok(None),
+        _ => unreachable!(),
+    };
+    let found = CachedUser {
+        platform,
+        id: id.into(),
+        name: name.into(),
+    };
+
+    let json: Arc<String> = serde_json::to_string(&found)?.into();
+    let id_key = key(platform, &UserKey::Id(found.id.clone()));
+    for k in [cache_key, id_key] {
+        Cache::Set(k, json.clone(), USER_TTL, false)
+            .exec(cache)
+            .await?;
+    }
+
+    Ok(Some(found))
+}
+
+/// Drop what's kept for a user, after a write that could change who the db picks for their name
+pub(crate) async fn invalidate(
+    cache: &cache::Handle,
+    platform: Platform,
+    id: Option<&Arc<String>>,
+    name: &Arc<String>,
+) {
+    let mut keys = vec![key(platform, &UserKey::Name(name.clone()))];
+    if let Some(id) = id {
+        keys.push(key(platform, &UserKey::Id(id.clone())));
+    }
+    for k in keys {
+        if let Err(e) = Cache::Delete(k).exec(cache).await {
+            tracing::warn!(platform = %platform, "couldn't invalidate cached user: {}", e);
+        }
+    }
+}
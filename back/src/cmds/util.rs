@@ -1,5 +1,11 @@
 use super::{CmdDump, Command, CommandConfig, ConfigDump, Context, DFAWrapper};
-use crate::{error, msg::Permissions};
+use crate::{
+    cache::{self, Cache, RespType},
+    error::{self, Error},
+    msg::{Permissions, Platform},
+};
+use bb8_redis::redis;
+use levenshtein_automata::Distance;
 use once_cell::sync::Lazy;
 use regex::Regex;
 use serde::{ser::Serialize, Deserialize, Deserializer, Serializer};
@@ -25,19 +31,23 @@ impl<'de> Deserialize<'de> for CommandConfig {
     where
         D: Deserializer<'de>,
     {
-        let dump = ConfigDump::deserialize(deserializer)?;
+        ConfigDump::deserialize(deserializer).map(CommandConfig::from)
+    }
+}
 
+impl From<ConfigDump> for CommandConfig {
+    fn from(dump: ConfigDump) -> Self {
         let ConfigDump {
             filters,
             commands,
             timers,
         } = dump;
 
-        Ok(CommandConfig {
+        CommandConfig {
             filters: reinflate(filters),
             commands: reinflate(commands),
             timers: reinflate(timers),
-        })
+        }
     }
 }
 
@@ -49,9 +59,8 @@ fn reinflate(deflated: Vec<CmdDump>) -> Arc<Vec<Command>> {
 #[inline]
 pub(crate) fn can_autocorrect(prefix: &str, dfaw: &Option<DFAWrapper>) -> Option<bool> {
     if let Some(DFAWrapper(dfa)) = dfaw {
-        // check similarity
-        let edit_distance = dfa.eval(prefix).to_u8();
-        Some(edit_distance <= 2)
+        // anything past the command's max distance comes out as AtLeast
+        Some(matches!(dfa.eval(prefix), Distance::Exact(_)))
     } else {
         None
     }
@@ -59,6 +68,51 @@ pub(crate) fn can_autocorrect(prefix: &str, dfaw: &Option<DFAWrapper>) -> Option
 
 pub(crate) static PREFIX_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(\S+)\s*$").unwrap());
 
+/// Whether a message starts with exactly `prefix`, to tell invalid arguments apart from unrelated chat
+pub(crate) fn starts_with_prefix(prefix: &str, msg: &str) -> bool {
+    msg.split_whitespace().next() == Some(prefix)
+}
+
+fn permit_key(platform: Platform, user_id: &str) -> Arc<String> {
+    Arc::new(format!(
+        "aussiebot!{}!permit!{}!{}",
+        crate::channel_name(),
+        platform,
+        user_id
+    ))
+}
+
+/// Let a user past filters that allow it for a while, e.g. a raider posting their channel link
+pub(crate) async fn permit(
+    cache: &cache::Handle,
+    platform: Platform,
+    user_id: &str,
+    secs: u64,
+) -> error::Result<()> {
+    Cache::Set(
+        permit_key(platform, user_id),
+        Arc::new("1".into()),
+        secs as usize,
+        false,
+    )
+    .exec(cache)
+    .await?;
+    Ok(())
+}
+
+pub(crate) async fn is_permitted(
+    cache: &cache::Handle,
+    platform: Platform,
+    user_id: &str,
+) -> error::Result<bool> {
+    match Cache::Get(permit_key(platform, user_id)).exec(cache).await {
+        Ok(RespType::String(_)) => Ok(true),
+        Ok(_) => unreachable!(),
+        Err(Error::Redis(e)) if e.kind() == redis::ErrorKind::TypeError => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
 pub(crate) async fn ratelimit_user<'a>(
     ctx: &Context<'a>,
     ratelimit_user: u64,
@@ -72,7 +126,7 @@ pub(crate) async fn ratelimit_user<'a>(
         return Ok(false);
     }
     // check if rate-limited locally
-    if !ctx.lock.lock(&key, ratelimit_user).await? {
+    if !ctx.lock.cooldown(&key, ratelimit_user).await? {
         tracing::debug!(concat!("\x1b[33m{} rate-limited locally\x1b[0m"), ctype);
         return Ok(true);
     }
@@ -95,7 +149,7 @@ pub(crate) async fn ratelimit_global<'a>(
         let ratelimit_key = format!("{}_{}", lock, cname);
 
         // check if rate-limited globally
-        if ratelimit > 0 && !ctx.lock.lock(&ratelimit_key, ratelimit).await? {
+        if ratelimit > 0 && !ctx.lock.cooldown(&ratelimit_key, ratelimit).await? {
             //println!(concat!("\x1b[33m{} rate-limited globally\x1b[0m"), ctype);
             tracing::debug!(concat!("\x1b[33m{} rate-limited globally\x1b[0m"), ctype);
             return Ok(true);
@@ -105,7 +159,7 @@ pub(crate) async fn ratelimit_global<'a>(
         if ratelimit_user > 0
             && !ctx
                 .lock
-                .lock(&format!("{}_{}", &ratelimit_key, &user.id), ratelimit_user)
+                .cooldown(&format!("{}_{}", &ratelimit_key, &user.id), ratelimit_user)
                 .await?
         {
             tracing::debug!(concat!("\x1b[33m{} rate-limited locally\x1b[0m"), ctype);
@@ -0,0 +1,107 @@
+use super::{Arg, ArgKind, ArgValue};
+use crate::msg::{ArgMap, Platform, PLATFORMS};
+pub use crate::proto::cmds::ArgTypeError;
+use std::str::FromStr;
+
+/// What's wrong with an invocation's arguments
+#[derive(Debug, Default)]
+pub(crate) struct Invalid {
+    /// Required args that weren't given. A missing subcommand is listed as `a|b|c`
+    pub(crate) missing: Vec<String>,
+    pub(crate) type_errors: Vec<ArgTypeError>,
+}
+
+/// Check an invocation's args against the command's args schema, None if they fit
+pub(crate) fn check(schema: &[Arg], args: &ArgMap) -> Option<Invalid> {
+    let mut invalid = Invalid::default();
+    check_in(schema, args, "", &mut invalid);
+
+    if invalid.missing.is_empty() && invalid.type_errors.is_empty() {
+        None
+    } else {
+        Some(invalid)
+    }
+}
+
+fn qualify(path: &str, name: &str) -> String {
+    if path.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{}.{}", path, name)
+    }
+}
+
+fn check_in(schema: &[Arg], args: &ArgMap, path: &str, invalid: &mut Invalid) {
+    let mut subcmds = vec![];
+    let mut chose_subcmd = false;
+
+    for arg in schema {
+        let name = qualify(path, &arg.name);
+        let is_subcmd = matches!(
+            arg.kind,
+            ArgKind::SubCommand(_) | ArgKind::SubCommandGroup(_)
+        );
+        if is_subcmd {
+            subcmds.push(name.clone());
+        }
+
+        match args.get(&arg.name) {
+            // subcommands are all optional, one of them is checked for below
+            None if !arg.optional && !is_subcmd => invalid.missing.push(name),
+            None => {}
+            Some(value) => {
+                chose_subcmd |= is_subcmd;
+                check_value(&arg.kind, value, name, invalid);
+            }
+        }
+    }
+
+    if !subcmds.is_empty() && !chose_subcmd {
+        invalid.missing.push(subcmds.join("|"));
+    }
+
+    for name in args.keys() {
+        if !schema.iter().any(|arg| arg.name == *name) {
+            invalid
+                .type_errors
+                .push(ArgTypeError::Unknown(qualify(path, name)));
+        }
+    }
+}
+
+fn check_value(kind: &ArgKind, value: &ArgValue, name: String, invalid: &mut Invalid) {
+    match (kind, value) {
+        (ArgKind::String | ArgKind::Autocomplete, ArgValue::String(_))
+        | (ArgKind::Bool, ArgValue::Bool(_))
+        | (ArgKind::User, ArgValue::User(_))
+        | (ArgKind::Platform, ArgValue::Platform(_)) => {}
+        // Discord sends platforms as string choices
+        (ArgKind::Platform, ArgValue::String(s)) if Platform::from_str(s).is_ok() => {}
+        (ArgKind::Integer { min, max }, ArgValue::Integer(i)) => match (min, max) {
+            (Some(min), _) if i < min => {
+                invalid.type_errors.push(ArgTypeError::TooSmall(name, *min))
+            }
+            (_, Some(max)) if i > max => {
+                invalid.type_errors.push(ArgTypeError::TooLarge(name, *max))
+            }
+            _ => {}
+        },
+        (
+            ArgKind::SubCommand(schema) | ArgKind::SubCommandGroup(schema),
+            ArgValue::SubCommand(args),
+        ) => check_in(schema, args, &name, invalid),
+        (kind, _) => {
+            let expected = match kind {
+                ArgKind::String | ArgKind::Autocomplete => "text".into(),
+                ArgKind::Integer { .. } => "a whole number".into(),
+                ArgKind::Bool => "true or false".into(),
+                ArgKind::User => "a user".into(),
+                ArgKind::Platform => format!("one of {}", PLATFORMS.join(", ")),
+                ArgKind::SubCommand(_) | ArgKind::SubCommandGroup(_) => "a subcommand".into(),
+            };
+            invalid
+                .type_errors
+                .push(ArgTypeError::WrongType(name, expected));
+        }
+    }
+}
@@ -0,0 +1,353 @@
+use super::{multiplier, user_cache, CmdDesc, Context, Invokable, RespHandle, RunRes};
+use crate::{
+    cache::{self, Cache, RespType},
+    db::{
+        self,
+        voice::{VoiceBatch, VoiceTime},
+        Db,
+    },
+    error::{self, Error},
+    msg::{
+        corr_id,
+        discord::{DiscordAction, VoicePresence},
+        Chat, Invocation, Location, Payload, Platform, Response, User,
+    },
+};
+use back_derive::command;
+use bb8_redis::redis;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::watch;
+use tracing::{debug_span, Instrument};
+
+#[command(cmd)]
+/// Points for time spent in Discord voice channels, credited every so often while members stay in
+pub struct VoicePoints {
+    /// Points per minute in voice
+    #[cmd(def(1u64), constr(range = "0..=1000"))]
+    rate: u64,
+    /// Voice channel IDs that count, every channel if empty
+    channels: Vec<String>,
+    /// Don't count time in the server's AFK channel
+    #[cmd(def(true))]
+    exclude_afk: bool,
+    /// How often voice time is credited and saved to the database (in seconds)
+    #[cmd(def(300u64), constr(range = "60..=3600"))]
+    flush_interval: u64,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+impl VoicePoints {
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn chat(&self, _ctx: &Context<'_>, _chat: &Chat) -> error::Result<RunRes> {
+        Ok(RunRes::Noop)
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn invoke(
+        &self,
+        _ctx: &Context<'_>,
+        _invocation: &Invocation,
+    ) -> Option<RunRes> {
+        None
+    }
+
+    /// joined: id -> when their uncredited stretch started (unix secs),
+    /// names: id -> name, secs: id -> seconds credited but not yet saved
+    fn key(name: &str, what: &str) -> Arc<String> {
+        Arc::new(format!(
+            "aussiebot!{}!voicepoints!{}!{}",
+            crate::channel_name(),
+            name,
+            what
+        ))
+    }
+
+    /// Whether time spent where they are now counts
+    fn counts(&self, presence: &VoicePresence) -> bool {
+        let channel_id = match presence.channel_id {
+            Some(ref channel_id) => channel_id,
+            None => return false,
+        };
+        if self.exclude_afk && presence.afk {
+            return false;
+        }
+        self.channels.is_empty() || self.channels.iter().any(|c| c == channel_id.as_str())
+    }
+
+    /// Credit the stretch up to when they joined, moved or left, then start a new one if they're
+    /// somewhere that counts
+    #[tracing::instrument(skip(self, cache), fields(name = self.name.as_str(), user = user.name.as_str()))]
+    pub(crate) async fn presence(
+        &self,
+        cache: &cache::Handle,
+        user: &User,
+        presence: &VoicePresence,
+    ) -> error::Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+
+        let joined = Self::key(&self.name, "joined");
+        match Cache::HashTake(joined, user.id.clone()).exec(cache).await {
+            Ok(RespType::String(started)) => {
+                Self::credit(cache, &self.name, &user.id, &started, now_secs()).await?
+            }
+            Ok(_) => unreachable!(),
+            Err(Error::Redis(e)) if e.kind() == redis::ErrorKind::TypeError => {}
+            Err(e) => return Err(e),
+        }
+
+        if self.counts(presence) {
+            self.start(cache, user).await?;
+        }
+        tracing::debug!(counting = self.counts(presence), "voice presence");
+        Ok(())
+    }
+
+    /// Catch up on who's in voice after the bot was away. Anyone tracked who isn't somewhere that
+    /// counts anymore left at some point while it was, what they'd built up since the last flush
+    /// is dropped
+    #[tracing::instrument(skip_all, fields(name = self.name.as_str()))]
+    pub(crate) async fn sync(
+        &self,
+        cache: &cache::Handle,
+        present: &[(Arc<User>, VoicePresence)],
+    ) -> error::Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+
+        let counting: Vec<_> = present
+            .iter()
+            .filter(|(_, presence)| self.counts(presence))
+            .map(|(user, _)| user)
+            .collect();
+        let ids: HashSet<_> = counting.iter().map(|user| user.id.as_str()).collect();
+
+        let joined = Self::key(&self.name, "joined");
+        let tracked = match Cache::HashGetAll(joined.clone()).exec(cache).await? {
+            RespType::VecStringString(tracked) => tracked,
+            _ => unreachable!(),
+        };
+        let mut left = 0;
+        for (id, _) in tracked {
+            if !ids.contains(id.as_str()) {
+                Cache::HashDelete(joined.clone(), id.into())
+                    .exec(cache)
+                    .await?;
+                left += 1;
+            }
+        }
+        // ongoing stretches are kept
+        for user in &counting {
+            self.start(cache, user).await?;
+        }
+
+        tracing::info!(in_voice = counting.len(), left, "synced voice presence");
+        Ok(())
+    }
+
+    async fn start(&self, cache: &cache::Handle, user: &User) -> error::Result<()> {
+        Cache::HashSet(
+            Self::key(&self.name, "joined"),
+            user.id.clone(),
+            now_secs().to_string(),
+            true,
+        )
+        .exec(cache)
+        .await?;
+        Cache::HashSet(
+            Self::key(&self.name, "names"),
+            user.id.clone(),
+            user.name.to_string(),
+            false,
+        )
+        .exec(cache)
+        .await?;
+        Ok(())
+    }
+
+    async fn credit(
+        cache: &cache::Handle,
+        name: &str,
+        id: &Arc<String>,
+        started: &str,
+        now: u64,
+    ) -> error::Result<()> {
+        let secs = now.saturating_sub(started.parse().unwrap_or(now));
+        if secs > 0 {
+            Cache::Zincrby(Self::key(name, "secs"), id.clone(), secs as isize)
+                .exec(cache)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Credit everyone still in voice up to now, then save whole minutes and the points they
+    /// earned. Leftover seconds wait for the next flush, or are dropped once the member's left
+    async fn flush(
+        name: &str,
+        rate: u64,
+        cache: &cache::Handle,
+        db: &db::Handle,
+    ) -> error::Result<()> {
+        let joined = Self::key(name, "joined");
+        let now = now_secs();
+        let in_voice = match Cache::HashGetAll(joined.clone()).exec(cache).await? {
+            RespType::VecStringString(in_voice) => in_voice,
+            _ => unreachable!(),
+        };
+        let in_voice: HashSet<_> = in_voice.into_iter().map(|(id, _)| id).collect();
+        for id in &in_voice {
+            let id = Arc::new(id.clone());
+            // only if they're still in, otherwise leaving credited it
+            match Cache::HashReplace(joined.clone(), id.clone(), now.to_string().into())
+                .exec(cache)
+                .await
+            {
+                Ok(RespType::String(started)) => {
+                    Self::credit(cache, name, &id, &started, now).await?
+                }
+                Ok(_) => unreachable!(),
+                Err(Error::Redis(e)) if e.kind() == redis::ErrorKind::TypeError => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        let secs_key = Self::key(name, "secs");
+        let credited = match Cache::Zrangewithscores(secs_key.clone(), 0, -1)
+            .exec(cache)
+            .await?
+        {
+            RespType::VecStringScore(credited) => credited,
+            _ => unreachable!(),
+        };
+        let names_key = Self::key(name, "names");
+        let names: HashMap<_, _> = match Cache::HashGetAll(names_key.clone()).exec(cache).await? {
+            RespType::VecStringString(names) => names.into_iter().collect(),
+            _ => unreachable!(),
+        };
+
+        let mut batch = Vec::with_capacity(credited.len());
+        for (id, secs) in credited {
+            let minutes = secs as u64 / 60;
+            if minutes == 0 {
+                continue;
+            }
+            let points = multiplier::apply(cache, Platform::DISCORD, minutes * rate).await?;
+            batch.push(VoiceTime {
+                name: names.get(&id).unwrap_or(&id).clone().into(),
+                id: id.into(),
+                secs: (minutes * 60).min(i32::MAX as u64) as i32,
+                points: points.min(i32::MAX as u64) as i32,
+            });
+        }
+        if batch.is_empty() {
+            return Ok(());
+        }
+
+        let users = batch.len();
+        Db::AddVoiceTime(VoiceBatch(batch.clone())).exec(db).await?;
+
+        // only what was saved, anything credited since stays for next time
+        for time in batch {
+            let left =
+                match Cache::Zincrby(secs_key.clone(), time.id.clone(), -(time.secs as isize))
+                    .exec(cache)
+                    .await?
+                {
+                    RespType::U64(left) => left,
+                    _ => unreachable!(),
+                };
+            if left < 60 && !in_voice.contains(time.id.as_str()) {
+                Cache::Zrem(secs_key.clone(), time.id.clone())
+                    .exec(cache)
+                    .await?;
+                Cache::HashDelete(names_key.clone(), time.id.clone())
+                    .exec(cache)
+                    .await?;
+            }
+            if time.points > 0 {
+                user_cache::invalidate(cache, Platform::DISCORD, Some(&time.id), &time.name).await;
+            }
+        }
+
+        tracing::info!(users, "saved voice time");
+        Ok(())
+    }
+
+    /// Ask the discord bot who's in voice, then credit and save voice time every flush_interval
+    pub(crate) fn init(
+        &self,
+        mut cancel_chan: watch::Receiver<()>,
+        cache: &cache::Handle,
+        db: &db::Handle,
+        resp: &RespHandle,
+    ) -> Option<()> {
+        if !self.enabled {
+            return None;
+        }
+
+        let (name, rate, flush_interval) = (self.name.clone(), self.rate, self.flush_interval);
+        let (cache, db, resp) = (cache.clone(), db.clone(), resp.clone());
+
+        tracing::info!(
+            "\x1b[93mSpawning VoicePoints flush task with interval: {}s\x1b[0m",
+            flush_interval
+        );
+
+        tokio::task::spawn(
+            async move {
+                // anyone who joined or left while no one was listening
+                Response {
+                    platform: Platform::DISCORD,
+                    channel: crate::channel_name(),
+                    corr_id: corr_id(),
+                    payload: Payload::Discord(DiscordAction::SyncVoice),
+                }
+                .send(Location::Pubsub, &resp)
+                .await;
+
+                loop {
+                    tokio::select! {
+                        _ = tokio::time::sleep(Duration::from_secs(flush_interval)) => {}
+                        _ = cancel_chan.changed() => {
+                            // value changed or channel closed, save one last time
+                            if let Err(e) = Self::flush(&name, rate, &cache, &db).await {
+                                tracing::error!("{}", e);
+                            }
+                            tracing::info!("\x1b[93maborting\x1b[0m");
+                            return;
+                        }
+                    }
+
+                    if let Err(e) = Self::flush(&name, rate, &cache, &db).await {
+                        tracing::error!("{}", e);
+                    }
+                }
+            }
+            .instrument(debug_span!("VoicePoints flush task")),
+        );
+
+        Some(())
+    }
+}
+
+impl CmdDesc for VoicePoints {
+    #[inline]
+    fn platform(&self) -> Platform {
+        Platform::empty()
+    }
+}
+
+impl Invokable for VoicePoints {}
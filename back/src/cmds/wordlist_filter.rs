@@ -0,0 +1,328 @@
+use super::{normalize::Normalizer, util, Context, ModAction, RunRes};
+use crate::{
+    cache::{self, Cache, RespType},
+    error,
+    i18n::tr,
+    msg::{Chat, Invocation, Location, Payload, Permissions, Platform, Response},
+};
+use back_derive::command;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+/// Lists are re-read from redis this often, to pick up edits made elsewhere
+const REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+const MAX_PHRASE_LEN: usize = 100;
+
+/// (normalized phrase padded with spaces, tier)
+type Wordlist = Arc<Vec<(String, u8)>>;
+
+/// Per filter name, shared so edits from the web UI are seen right away
+static WORDLISTS: Lazy<RwLock<HashMap<String, (Instant, Wordlist)>>> = Lazy::new(Default::default);
+
+/// Lowercase, strip diacritics, undo common leetspeak and collapse everything else
+/// into single spaces, padded so phrases only match whole words
+pub(crate) fn normalize(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    let mut prev_space = true;
+    out.push(' ');
+
+    let chars = text
+        .nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(char::to_lowercase);
+    for c in chars {
+        let c = match c {
+            '0' => 'o',
+            '1' => 'i',
+            '3' => 'e',
+            '4' | '@' => 'a',
+            '5' | '$' => 's',
+            '7' => 't',
+            '8' => 'b',
+            c => c,
+        };
+        if c.is_alphanumeric() {
+            out.push(c);
+            prev_space = false;
+        } else if !prev_space {
+            out.push(' ');
+            prev_space = true;
+        }
+    }
+
+    if !prev_space {
+        out.push(' ');
+    }
+    out
+}
+
+#[command(filter)]
+/// Filter chat against a word list kept in redis, so mods can edit it from chat
+pub struct WordlistFilter {
+    /// Apply to anyone below permission level
+    #[cmd(defl("Permissions::NONE"))]
+    apply_to: Permissions,
+    /// Platforms
+    #[cmd(defl("Platform::CHAT"))]
+    platforms: Platform,
+    /// Mod action for tier 1 words
+    #[cmd(defl("ModAction::Remove"), constr(range = "1..=86400"))]
+    tier1_action: ModAction,
+    /// Mod action for tier 2 words
+    #[cmd(defl("ModAction::Timeout(300)"), constr(range = "1..=86400"))]
+    tier2_action: ModAction,
+    /// Mod action for tier 3 words
+    #[cmd(defl("ModAction::Ban"), constr(range = "1..=86400"))]
+    tier3_action: ModAction,
+    /// Command prefix for editing the list
+    #[cmd(def("!wordlist"), constr(non_empty))]
+    prefix: String,
+    /// Permissions for editing the list
+    #[cmd(defl("Permissions::MOD"))]
+    perms: Permissions,
+    /// Unicode NFKC normalization, e.g. fullwidth or math-styled letters count as plain ones
+    #[cmd(group("Matching"))]
+    nfkc: bool,
+    /// Fold lookalike letters from other scripts into latin ones, and ignore invisible characters
+    #[cmd(group("Matching"))]
+    fold_confusables: bool,
+}
+
+enum Edit<'a> {
+    Add(u8, &'a str),
+    Remove(&'a str),
+    Count,
+}
+
+impl WordlistFilter {
+    fn key(name: &str) -> Arc<String> {
+        Arc::new(format!(
+            "aussiebot!{}!wordlist!{}",
+            crate::channel_name(),
+            name
+        ))
+    }
+
+    fn action(&self, tier: u8) -> ModAction {
+        match tier {
+            1 => self.tier1_action,
+            2 => self.tier2_action,
+            _ => self.tier3_action,
+        }
+    }
+
+    fn parse_edit<'a>(&self, msg: &'a str) -> Option<Edit<'a>> {
+        let mut parts = msg.trim().splitn(3, char::is_whitespace);
+        if !parts.next()?.eq_ignore_ascii_case(&self.prefix) {
+            return None;
+        }
+
+        match parts.next() {
+            None => Some(Edit::Count),
+            Some("add") => {
+                let rest = parts.next()?.trim();
+                // tier is optional, defaulting to 1
+                let (tier, phrase) = match rest.split_once(char::is_whitespace) {
+                    Some((tier, phrase)) => match tier.parse::<u8>() {
+                        Ok(tier @ 1..=3) => (tier, phrase.trim()),
+                        Ok(_) => return None,
+                        Err(_) => (1, rest),
+                    },
+                    None => (1, rest),
+                };
+                Some(Edit::Add(tier, phrase))
+            }
+            Some("remove") => Some(Edit::Remove(parts.next()?.trim())),
+            _ => None,
+        }
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn chat(&self, ctx: &Context<'_>, chat: &Chat) -> error::Result<RunRes> {
+        if !self.enabled || !self.platforms.contains(ctx.platform) {
+            return Ok(RunRes::Disabled);
+        }
+
+        // edits come before filtering, they'd usually trip it
+        if ctx.user.perms >= self.perms && util::starts_with_prefix(&self.prefix, &chat.msg) {
+            return self.edit_from_chat(ctx, chat).await;
+        }
+
+        if ctx.user.perms > self.apply_to {
+            return Ok(RunRes::Disabled);
+        }
+
+        self.run(ctx, chat).await
+    }
+
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(super) async fn invoke(
+        &self,
+        _ctx: &Context<'_>,
+        _invocation: &Invocation,
+    ) -> Option<RunRes> {
+        None
+    }
+
+    #[tracing::instrument(level = "trace", skip_all, name = "WordlistFilter")]
+    async fn run(&self, ctx: &Context<'_>, chat: &Chat) -> error::Result<RunRes> {
+        let words = Self::words(ctx.cache, &self.name).await?;
+        if words.is_empty() {
+            return Ok(RunRes::Ok);
+        }
+
+        let msg = chat.text_without_emotes().into_owned();
+        // lists are stored lowercase, so case is always ignored
+        let normalizer = Normalizer {
+            ignore_case: true,
+            nfkc: self.nfkc,
+            fold_confusables: self.fold_confusables,
+        };
+        let tier = tokio::task::spawn_blocking(move || {
+            let msg = normalize(&normalizer.apply(&msg));
+            words
+                .iter()
+                .filter(|(phrase, _)| msg.contains(&*normalizer.apply(phrase)))
+                .map(|(_, tier)| *tier)
+                .max()
+        })
+        .await?;
+
+        match tier {
+            Some(tier) => {
+                tracing::info!(
+                    "\x1b[91mMessage from {} matches a tier {} word\x1b[0m",
+                    chat.user.name,
+                    tier
+                );
+                Ok(RunRes::Filtered(self.action(tier)))
+            }
+            None => Ok(RunRes::Ok),
+        }
+    }
+
+    async fn edit_from_chat(&self, ctx: &Context<'_>, chat: &Chat) -> error::Result<RunRes> {
+        let msg = match self.parse_edit(&chat.msg) {
+            Some(Edit::Add(tier, phrase)) => {
+                let count =
+                    Self::edit(ctx.cache, &self.name, vec![(phrase.into(), tier)], vec![]).await?;
+                tr("wordlist.added", &[("tier", &tier), ("count", &count)])
+            }
+            Some(Edit::Remove(phrase)) => {
+                let before = Self::words(ctx.cache, &self.name).await?.len();
+                let count = Self::edit(ctx.cache, &self.name, vec![], vec![phrase.into()]).await?;
+                if count < before {
+                    tr("wordlist.removed", &[("count", &count)])
+                } else {
+                    tr("wordlist.not_found", &[])
+                }
+            }
+            Some(Edit::Count) => {
+                let count = Self::words(ctx.cache, &self.name).await?.len();
+                tr("wordlist.count", &[("count", &count)])
+            }
+            None => tr("wordlist.usage", &[("prefix", &self.prefix)]),
+        };
+
+        // phrases aren't echoed back, the point is to keep them out of chat
+        Response {
+            platform: ctx.platform,
+            channel: crate::channel_name(),
+            corr_id: ctx.corr_id.clone(),
+            payload: Payload::Message {
+                user: ctx.reply_to(),
+                msg: msg.into(),
+                meta: ctx.meta.clone(),
+                hint: None,
+                thread: None,
+            },
+        }
+        .send(Location::Pubsub, ctx.resp)
+        .await;
+
+        Ok(RunRes::Ok)
+    }
+
+    /// The filter's list, re-read from redis if stale
+    async fn words(cache: &cache::Handle, name: &str) -> error::Result<Wordlist> {
+        if let Some((fetched, words)) = WORDLISTS.read().get(name) {
+            if fetched.elapsed() < REFRESH_INTERVAL {
+                return Ok(words.clone());
+            }
+        }
+
+        let words = match Cache::HashGetAll(Self::key(name)).exec(cache).await? {
+            RespType::VecStringString(words) => words,
+            _ => unreachable!(),
+        };
+        let words: Wordlist = Arc::new(
+            words
+                .into_iter()
+                .filter_map(|(phrase, tier)| Some((format!(" {} ", phrase), tier.parse().ok()?)))
+                .collect(),
+        );
+
+        WORDLISTS
+            .write()
+            .insert(name.to_owned(), (Instant::now(), words.clone()));
+
+        Ok(words)
+    }
+
+    /// (normalized phrase, tier), sorted by phrase
+    pub(crate) async fn dump(
+        cache: &cache::Handle,
+        name: &str,
+    ) -> error::Result<Vec<(String, u8)>> {
+        let mut words: Vec<(String, u8)> = Self::words(cache, name)
+            .await?
+            .iter()
+            .map(|(phrase, tier)| (phrase.trim().to_owned(), *tier))
+            .collect();
+        words.sort_unstable();
+        Ok(words)
+    }
+
+    /// Add and remove phrases, returning the new number of entries.
+    /// Phrases are stored normalized, re-adding one changes its tier
+    pub(crate) async fn edit(
+        cache: &cache::Handle,
+        name: &str,
+        add: Vec<(String, u8)>,
+        remove: Vec<String>,
+    ) -> error::Result<usize> {
+        let key = Self::key(name);
+
+        let normalized = |phrase: &str| {
+            let phrase = normalize(phrase);
+            let phrase = phrase.trim();
+            (!phrase.is_empty() && phrase.len() <= MAX_PHRASE_LEN).then(|| phrase.to_owned())
+        };
+
+        for (phrase, tier) in add {
+            if let (Some(phrase), 1..=3) = (normalized(&phrase), tier) {
+                Cache::HashSet(key.clone(), phrase.into(), tier.to_string(), false)
+                    .exec(cache)
+                    .await?;
+            }
+        }
+        for phrase in remove {
+            if let Some(phrase) = normalized(&phrase) {
+                Cache::HashDelete(key.clone(), phrase.into())
+                    .exec(cache)
+                    .await?;
+            }
+        }
+
+        // force a re-read
+        WORDLISTS.write().remove(name);
+        Ok(Self::words(cache, name).await?.len())
+    }
+}
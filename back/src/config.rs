@@ -0,0 +1,1024 @@
+use crate::msg::Platform;
+use once_cell::sync::OnceCell;
+use std::{
+    fmt::Display,
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    str::FromStr,
+    time::Duration,
+};
+
+static CONFIG: OnceCell<Config> = OnceCell::new();
+static SERVER_CONFIG: OnceCell<ServerConfig> = OnceCell::new();
+static DISCORD_CONFIG: OnceCell<DiscordConfig> = OnceCell::new();
+
+/// Shortest PUBSUB_SECRET and CONFIG_SECRET allowed, as long as the HMAC-SHA256 output
+const MIN_SECRET_LEN: usize = 32;
+/// Seconds, three of the frontend's missed pings
+const DEFAULT_WS_IDLE_TIMEOUT: u64 = 90;
+const DEFAULT_WS_RATE_LIMIT: RateLimit = RateLimit {
+    count: 60,
+    per: Duration::from_secs(10),
+};
+const DEFAULT_WS_DUMP_RATE_LIMIT: RateLimit = RateLimit {
+    count: 10,
+    per: Duration::from_secs(60),
+};
+/// Seconds
+const DEFAULT_WS_MUTE: u64 = 30;
+/// Seconds
+const DEFAULT_READY_TIMEOUT: u64 = 30;
+/// Seconds, enough for a clip to finish processing
+const DEFAULT_COMMAND_TIMEOUT: u64 = 30;
+/// Link shorteners whose links are expanded, unless URL_EXPAND_HOSTS says otherwise
+const DEFAULT_URL_EXPAND_HOSTS: &str =
+    "bit.ly,t.co,tinyurl.com,is.gd,goo.gl,ow.ly,rebrand.ly,cutt.ly";
+/// Seconds
+const DEFAULT_URL_TIMEOUT: u64 = 3;
+/// Characters
+const DEFAULT_URL_SHORTEN_OVER: usize = 60;
+/// Entries, a few minutes of busy chat
+const DEFAULT_UPSTREAM_STREAM_MAX_LEN: usize = 10_000;
+const DEFAULT_CHAT_CONCURRENCY: usize = 64;
+/// Messages per second
+const DEFAULT_CHAT_SHED_THRESHOLD: u32 = 50;
+const DEFAULT_CHAT_QUEUE_LIMIT: usize = 1024;
+const DEFAULT_LOGIN_RATE_LIMIT: usize = 10;
+const DEFAULT_LOGIN_RATE_LIMIT_BURST: usize = 20;
+/// Seconds, a week
+const DEFAULT_SESSION_TTL: usize = 7 * 24 * 60 * 60;
+/// Seconds
+const DEFAULT_TWITCH_POLL_INTERVAL: u64 = 60;
+/// Seconds, so the poller stays well under Helix's rate limit
+const MIN_TWITCH_POLL_INTERVAL: u64 = 10;
+
+/// Settings every service needs
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Lowercased
+    pub channel_name: String,
+    /// Lowercased
+    pub upstream_chan: String,
+    /// Lowercased
+    pub downstream_chan: String,
+    /// How chat gets to the back server on UPSTREAM_CHAN, responses are always published
+    pub upstream_transport: Transport,
+    pub log_dir: PathBuf,
+    /// Response language, loaded from `CONFIG_DIR/locales/<lang>.json`
+    pub language: String,
+    /// Always set, except for the back server when it stores everything in SQLite
+    pub redis: Option<RedisConfig>,
+    /// Export spans over OTLP, if set
+    pub telemetry: Option<TelemetryConfig>,
+    /// Shared by everything on the pubsub channels to sign messages, they're taken as-is if unset
+    pub pubsub_secret: Option<Secret>,
+    /// Serve `/healthz` and `/metrics` over HTTP, if set
+    pub health: Option<HealthConfig>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    /// Redis pubsub, anything published while the back server is down is lost
+    Pubsub,
+    /// A redis stream read by a consumer group, so entries wait for the back server to come back
+    Stream {
+        /// Older entries are trimmed once there are about this many
+        max_len: usize,
+    },
+}
+
+/// Kept out of the logs
+#[derive(Clone)]
+pub struct Secret(pub String);
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Secret(..)")
+    }
+}
+
+/// Settings only the back server needs
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub ws_bind: SocketAddr,
+    pub config_dir: PathBuf,
+    pub storage: StorageConfig,
+    /// Serve websockets over TLS, if set
+    pub tls: Option<TlsConfig>,
+    /// How often to check points against the ledger, never if None
+    pub points_audit_interval: Option<Duration>,
+    /// Whether scheduled audits also fix what they find
+    pub points_audit_repair: bool,
+    /// Websocket peers not heard from in this long are dropped, never if None
+    pub ws_idle_timeout: Option<Duration>,
+    /// What each websocket peer may send, heartbeats aside
+    pub ws_rate_limit: RateLimit,
+    /// For requests that are expensive to answer, e.g. DumpLog, instead of ws_rate_limit
+    pub ws_dump_rate_limit: RateLimit,
+    /// How long a peer's messages are dropped for once it goes over a limit
+    pub ws_mute: Duration,
+    /// Proxies whose x-real-ip or x-forwarded-for is believed, a peer's address is its socket's otherwise
+    pub ws_trusted_proxies: Vec<IpAddr>,
+    /// How long a command gets to handle a message before it's cut off, unless it sets its own
+    pub command_timeout: Duration,
+    /// How long everything has at startup to come up, before what hasn't is logged
+    pub ready_timeout: Duration,
+    /// Connectors whose NotifyStart chat waits on, besides the cache, database and config
+    pub ready_platforms: Platform,
+    /// Serve `/readyz` over HTTP here, if set
+    pub readyz_bind: Option<SocketAddr>,
+    /// Whose leases are whose when there's more than one instance, random if unset
+    pub instance: String,
+    /// Split chat with other instances on UPSTREAM_CHAN, if set
+    pub shards: Option<ShardConfig>,
+    pub urls: UrlConfig,
+    /// Encrypts secret command fields on disk, they're stored as-is if unset
+    pub config_secret: Option<Secret>,
+    /// Lowercased web UI users shown secret command fields, everyone else gets them redacted
+    pub config_owners: Vec<String>,
+    /// Where Backup writes archives and Restore reads them from, they're only streamed if unset
+    pub backup_dir: Option<PathBuf>,
+    pub chat_load: ChatLoadConfig,
+    pub auth: AuthConfig,
+    pub twitch: TwitchConfig,
+}
+
+/// How much chat gets to run commands at once, see [`crate::msg::load::ChatLoad`]
+#[derive(Debug, Clone, Copy)]
+pub struct ChatLoadConfig {
+    /// Messages running commands at once
+    pub concurrency: usize,
+    /// Messages per second past which repeats are shed, never if 0
+    pub shed_threshold: u32,
+    /// Messages waiting for a turn past which everything but commands is shed
+    pub queue_limit: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct AuthConfig {
+    /// Login attempts per ip before it's locked out
+    pub ratelimit_count: usize,
+    /// How long the attempts are counted for (in seconds)
+    pub ratelimit_burst: usize,
+    /// How long a web UI login lasts (in seconds)
+    pub session_ttl: usize,
+    /// Discord and Twitch logins, if OAUTH_REDIRECT_URI is set
+    pub oauth: Option<OAuthConfig>,
+}
+
+#[derive(Debug, Clone)]
+pub struct OAuthConfig {
+    /// Where providers send users back to with a code
+    pub redirect_uri: String,
+    pub discord: Option<DiscordOAuthConfig>,
+    /// The same app as the Helix client
+    pub twitch: Option<OAuthApp>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DiscordOAuthConfig {
+    pub app: OAuthApp,
+    pub guild_id: String,
+    /// Role ids that are let in
+    pub roles: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct OAuthApp {
+    pub client_id: String,
+    pub client_secret: Secret,
+}
+
+#[derive(Debug, Clone)]
+pub struct TwitchConfig {
+    /// Lowercased, the channel name unless TWITCH_LOGIN is set
+    pub login: String,
+    /// How often the stream's live status is checked
+    pub poll_interval: Duration,
+    /// Helix credentials, nothing's asked of twitch if unset
+    pub app: Option<OAuthApp>,
+    /// User access token with the clips:edit scope, for the same app
+    pub user_token: Option<Secret>,
+    /// Renews the user token when it expires, it's fetched with this on first use if unset
+    pub refresh_token: Option<Secret>,
+}
+
+/// Settings only the discord connector needs
+#[derive(Debug, Clone)]
+pub struct DiscordConfig {
+    pub token: Secret,
+    pub guild_id: u64,
+    /// Owner of the guild
+    pub streamer_id: u64,
+    /// The bot's own user
+    pub bot_id: u64,
+    pub member_role_id: u64,
+    /// Where streams are announced, unless routed elsewhere
+    pub announce_chan: u64,
+    /// Where bot spam goes, unless routed elsewhere
+    pub bot_chan: u64,
+    /// Where the channel routes are kept
+    pub config_dir: PathBuf,
+    /// Set by the restart script if the stream was live when it went down
+    pub was_streaming: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct UrlConfig {
+    /// Lowercased, links on these (and their subdomains) are expanded for filters
+    pub expand_hosts: Vec<String>,
+    /// For each request made to expand or shorten a link
+    pub timeout: Duration,
+    /// Takes `{url}`, answering with the short link as plain text. Nothing's shortened if unset
+    pub shortener: Option<String>,
+    /// Links in responses longer than this are shortened
+    pub shorten_over: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct ShardConfig {
+    /// Users are hashed into this many buckets, every instance has to agree on it
+    pub buckets: u32,
+}
+
+/// At most `count` messages per `per`, written as `<count>/<seconds>`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub count: u32,
+    pub per: Duration,
+}
+
+impl FromStr for RateLimit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (count, secs) = s
+            .split_once('/')
+            .ok_or_else(|| "expected <count>/<seconds>".to_owned())?;
+        let count = count.trim().parse::<u32>().map_err(|e| e.to_string())?;
+        let secs = secs.trim().parse::<u64>().map_err(|e| e.to_string())?;
+        if count == 0 || secs == 0 {
+            return Err("count and seconds have to be at least 1".into());
+        }
+        Ok(Self {
+            count,
+            per: Duration::from_secs(secs),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RedisConfig {
+    pub url: String,
+    pub pool_size: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct HealthConfig {
+    pub bind: SocketAddr,
+    /// A link that's up but hasn't carried anything in this long counts as stuck, never if None
+    pub stale_after: Option<Duration>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TelemetryConfig {
+    /// OTLP gRPC collector, e.g. http://localhost:4317
+    pub endpoint: String,
+    /// Used instead of the binary's name, if set
+    pub service_name: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum StorageConfig {
+    /// Postgres, with the cache and locks in redis
+    Postgres(Box<DatabaseConfig>),
+    /// A local SQLite file, with the cache and locks kept in memory.
+    /// Connectors can still use pubsub if REDIS_URL is set, otherwise it's websocket-only
+    Sqlite(PathBuf),
+}
+
+#[derive(Debug, Clone)]
+pub struct DatabaseConfig {
+    /// TLS is only used with `sslmode=require`
+    pub config: tokio_postgres::Config,
+    pub pool_size: u32,
+    /// How long to wait for a connection from the pool
+    pub connect_timeout: Duration,
+    /// Cancel statements running longer than this, if set
+    pub statement_timeout: Option<Duration>,
+    /// Extra CA certs to trust for TLS, on top of the usual web roots
+    pub tls_ca: Option<PathBuf>,
+    /// A read replica for queries that don't write, if set
+    pub read_config: Option<tokio_postgres::Config>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    /// Server names peers may connect with (lowercased), anything goes if empty
+    pub hosts: Vec<String>,
+}
+
+/// Every missing or invalid variable, not just the first
+#[derive(Debug)]
+pub struct ConfigError {
+    errors: Vec<(&'static str, String)>,
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("invalid configuration:")?;
+        for (var, reason) in &self.errors {
+            write!(f, "\n  {}: {}", var, reason)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Reads env vars, collecting errors instead of stopping at the first
+#[derive(Default)]
+struct Env {
+    errors: Vec<(&'static str, String)>,
+}
+
+impl Env {
+    fn optional(&mut self, var: &'static str) -> Option<String> {
+        match dotenv::var(var) {
+            Ok(v) if v.trim().is_empty() => None,
+            Ok(v) => Some(v.trim().to_owned()),
+            Err(dotenv::Error::EnvVar(std::env::VarError::NotPresent)) => None,
+            Err(e) => {
+                self.errors.push((var, e.to_string()));
+                None
+            }
+        }
+    }
+
+    fn required(&mut self, var: &'static str) -> Option<String> {
+        let value = self.optional(var);
+        if value.is_none() && !self.errors.iter().any(|(v, _)| *v == var) {
+            self.errors.push((var, "missing".into()));
+        }
+        value
+    }
+
+    fn parse<T>(&mut self, var: &'static str, value: Option<String>) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        match value?.parse() {
+            Ok(v) => Some(v),
+            Err(e) => {
+                self.errors.push((var, format!("invalid ({})", e)));
+                None
+            }
+        }
+    }
+
+    fn dir(&mut self, var: &'static str, value: Option<String>) -> Option<PathBuf> {
+        let path = PathBuf::from(value?);
+        if !path.is_dir() {
+            self.errors
+                .push((var, format!("{} isn't a directory", path.display())));
+            return None;
+        }
+        Some(path)
+    }
+
+    fn file(&mut self, var: &'static str, value: Option<String>) -> Option<PathBuf> {
+        let path = PathBuf::from(value?);
+        if !path.is_file() {
+            self.errors
+                .push((var, format!("{} isn't a file", path.display())));
+            return None;
+        }
+        Some(path)
+    }
+
+    fn id(&mut self, var: &'static str) -> Option<u64> {
+        let value = self.required(var);
+        self.parse(var, value)
+    }
+
+    /// Falls back to `default`
+    fn number<T>(&mut self, var: &'static str, default: T) -> T
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let value = self.optional(var);
+        self.parse(var, value).unwrap_or(default)
+    }
+
+    fn list(&mut self, var: &'static str) -> Vec<String> {
+        self.optional(var)
+            .map(|v| {
+                v.split(',')
+                    .map(|v| v.trim().to_owned())
+                    .filter(|v| !v.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Both or neither of the id and secret
+    fn app(&mut self, id_var: &'static str, secret_var: &'static str) -> Option<Option<OAuthApp>> {
+        match (self.optional(id_var), self.optional(secret_var)) {
+            (None, None) => Some(None),
+            (Some(client_id), Some(secret)) => Some(Some(OAuthApp {
+                client_id,
+                client_secret: Secret(secret),
+            })),
+            (id, _) => {
+                let missing = if id.is_none() { id_var } else { secret_var };
+                self.errors
+                    .push((missing, "has to be set along with the other".into()));
+                None
+            }
+        }
+    }
+
+    fn pool_size(&mut self, var: &'static str) -> u32 {
+        let value = self.optional(var);
+        match self.parse::<u32>(var, value) {
+            Some(0) => {
+                self.errors.push((var, "has to be at least 1".into()));
+                0
+            }
+            Some(size) => size,
+            None => 10,
+        }
+    }
+
+    fn finish<T>(self, config: Option<T>) -> Result<T, ConfigError> {
+        match config {
+            Some(config) if self.errors.is_empty() => Ok(config),
+            _ => Err(ConfigError {
+                errors: self.errors,
+            }),
+        }
+    }
+}
+
+impl Config {
+    /// Redis is always set
+    pub fn load() -> Result<Self, ConfigError> {
+        let mut env = Env::default();
+        let config = Self::read(&mut env, true);
+        env.finish(config)
+    }
+
+    /// Load both, reporting errors from either
+    pub fn load_server() -> Result<(Self, ServerConfig), ConfigError> {
+        let mut env = Env::default();
+        // SQLite storage does without redis, unless it's there for pubsub
+        let needs_redis = env.optional("SQLITE_PATH").is_none();
+        let config = Self::read(&mut env, needs_redis);
+        let server = ServerConfig::read(&mut env);
+        env.finish(config.zip(server))
+    }
+
+    /// For the discord connector, redis is always set
+    pub fn load_discord() -> Result<(Self, DiscordConfig), ConfigError> {
+        let mut env = Env::default();
+        let config = Self::read(&mut env, true);
+        let discord = DiscordConfig::read(&mut env);
+        env.finish(config.zip(discord))
+    }
+
+    fn read(env: &mut Env, needs_redis: bool) -> Option<Self> {
+        let mut chan = |var| env.required(var).map(|c| c.to_lowercase());
+        let channel_name = chan("CHANNEL_NAME");
+        let upstream_chan = chan("UPSTREAM_CHAN");
+        let downstream_chan = chan("DOWNSTREAM_CHAN");
+
+        let log_dir = env.required("LOG_DIR");
+        let log_dir = env.dir("LOG_DIR", log_dir);
+        let language = env.optional("BOT_LANGUAGE").unwrap_or_else(|| "en".into());
+
+        let redis_url = if needs_redis {
+            env.required("REDIS_URL")
+        } else {
+            env.optional("REDIS_URL")
+        };
+        let redis = match redis_url {
+            Some(redis_url) => match env.parse::<url::Url>("REDIS_URL", Some(redis_url)) {
+                Some(url)
+                    if !matches!(url.scheme(), "redis" | "rediss" | "redis+unix" | "unix") =>
+                {
+                    env.errors
+                        .push(("REDIS_URL", format!("unsupported scheme {}", url.scheme())));
+                    None
+                }
+                Some(url) => Some(Some(RedisConfig {
+                    url: url.into(),
+                    pool_size: env.pool_size("REDIS_POOL_SIZE"),
+                })),
+                None => None,
+            },
+            // already reported if it's needed
+            None => Some(None),
+        };
+
+        // the standard OpenTelemetry variables
+        let otlp_endpoint = env.optional("OTEL_EXPORTER_OTLP_ENDPOINT");
+        let telemetry = match otlp_endpoint {
+            Some(endpoint) => {
+                match env.parse::<url::Url>("OTEL_EXPORTER_OTLP_ENDPOINT", Some(endpoint)) {
+                    Some(url) if !matches!(url.scheme(), "http" | "https") => {
+                        env.errors.push((
+                            "OTEL_EXPORTER_OTLP_ENDPOINT",
+                            format!("unsupported scheme {}", url.scheme()),
+                        ));
+                        None
+                    }
+                    Some(url) => Some(Some(TelemetryConfig {
+                        endpoint: url.into(),
+                        service_name: env.optional("OTEL_SERVICE_NAME"),
+                    })),
+                    None => None,
+                }
+            }
+            None => Some(None),
+        };
+
+        let upstream_transport = match env.optional("UPSTREAM_TRANSPORT").as_deref() {
+            None | Some("pubsub") => Some(Transport::Pubsub),
+            Some("stream") => {
+                let max_len = env.optional("UPSTREAM_STREAM_MAX_LEN");
+                match env.parse::<usize>("UPSTREAM_STREAM_MAX_LEN", max_len) {
+                    Some(0) => {
+                        env.errors
+                            .push(("UPSTREAM_STREAM_MAX_LEN", "has to be at least 1".into()));
+                        None
+                    }
+                    max_len => Some(Transport::Stream {
+                        max_len: max_len.unwrap_or(DEFAULT_UPSTREAM_STREAM_MAX_LEN),
+                    }),
+                }
+            }
+            Some(other) => {
+                env.errors.push((
+                    "UPSTREAM_TRANSPORT",
+                    format!("expected pubsub or stream, not {}", other),
+                ));
+                None
+            }
+        };
+        if matches!(upstream_transport, Some(Transport::Stream { .. }))
+            && matches!(redis, Some(None))
+        {
+            env.errors
+                .push(("UPSTREAM_TRANSPORT", "stream needs REDIS_URL".into()));
+        }
+
+        let pubsub_secret = match env.optional("PUBSUB_SECRET") {
+            Some(secret) if secret.len() < MIN_SECRET_LEN => {
+                env.errors.push((
+                    "PUBSUB_SECRET",
+                    format!("has to be at least {} characters", MIN_SECRET_LEN),
+                ));
+                None
+            }
+            secret => Some(secret.map(Secret)),
+        };
+
+        let stale_after = env.optional("HEALTH_STALE_AFTER");
+        let stale_after = match env.parse::<u64>("HEALTH_STALE_AFTER", stale_after) {
+            Some(0) => {
+                env.errors
+                    .push(("HEALTH_STALE_AFTER", "has to be at least 1".into()));
+                None
+            }
+            secs => secs.map(Duration::from_secs),
+        };
+        let health = match env.optional("HEALTH_BIND") {
+            Some(bind) => env
+                .parse::<SocketAddr>("HEALTH_BIND", Some(bind))
+                .map(|bind| Some(HealthConfig { bind, stale_after })),
+            None => Some(None),
+        };
+
+        Some(Self {
+            channel_name: channel_name?,
+            upstream_chan: upstream_chan?,
+            downstream_chan: downstream_chan?,
+            upstream_transport: upstream_transport?,
+            log_dir: log_dir?,
+            language,
+            redis: redis?,
+            telemetry: telemetry?,
+            pubsub_secret: pubsub_secret?,
+            health: health?,
+        })
+    }
+
+    /// Make the config available to the statics in the crate root, returning it
+    pub fn init(self) -> &'static Self {
+        let _ = CONFIG.set(self);
+        CONFIG.get().unwrap()
+    }
+}
+
+impl ServerConfig {
+    fn read(env: &mut Env) -> Option<Self> {
+        let ws_bind = env.required("WS_BIND");
+        let ws_bind = env.parse::<SocketAddr>("WS_BIND", ws_bind);
+
+        let config_dir = env.required("CONFIG_DIR");
+        let config_dir = env.dir("CONFIG_DIR", config_dir);
+
+        let sqlite_path = env.optional("SQLITE_PATH");
+        if sqlite_path.is_some() && cfg!(not(feature = "sqlite")) {
+            env.errors
+                .push(("SQLITE_PATH", "built without the sqlite feature".into()));
+        }
+
+        let database = match sqlite_path {
+            Some(_) => env.optional("DATABASE_CONFIG"),
+            None => env.required("DATABASE_CONFIG"),
+        };
+        let database = env.parse::<tokio_postgres::Config>("DATABASE_CONFIG", database);
+        let database_read = env.optional("DATABASE_READ_CONFIG");
+        let database_read = match database_read {
+            Some(read) => env
+                .parse::<tokio_postgres::Config>("DATABASE_READ_CONFIG", Some(read))
+                .map(Some),
+            None => Some(None),
+        };
+        let database_pool_size = env.pool_size("DATABASE_POOL_SIZE");
+        let database_timeout = env.optional("DATABASE_TIMEOUT");
+        let database_timeout = match env.parse::<u64>("DATABASE_TIMEOUT", database_timeout) {
+            Some(0) => {
+                env.errors
+                    .push(("DATABASE_TIMEOUT", "has to be at least 1".into()));
+                None
+            }
+            secs => Some(Duration::from_secs(secs.unwrap_or(30))),
+        };
+        let statement_timeout = env.optional("DATABASE_STATEMENT_TIMEOUT");
+        let statement_timeout = env
+            .parse::<u64>("DATABASE_STATEMENT_TIMEOUT", statement_timeout)
+            .filter(|secs| *secs > 0)
+            .map(Duration::from_secs);
+        let database_tls_ca = env.optional("DATABASE_TLS_CA");
+        let database_tls_ca = env.file("DATABASE_TLS_CA", database_tls_ca);
+
+        let tls = match (env.optional("WS_TLS_CERT"), env.optional("WS_TLS_KEY")) {
+            (None, None) => Some(None),
+            (Some(cert), Some(key)) => {
+                let cert = env.file("WS_TLS_CERT", Some(cert));
+                let key = env.file("WS_TLS_KEY", Some(key));
+                let hosts = env
+                    .optional("WS_TLS_HOSTS")
+                    .map(|h| {
+                        h.split(',')
+                            .map(|h| h.trim().to_lowercase())
+                            .filter(|h| !h.is_empty())
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                cert.zip(key)
+                    .map(|(cert, key)| Some(TlsConfig { cert, key, hosts }))
+            }
+            (cert, _) => {
+                let missing = if cert.is_none() {
+                    "WS_TLS_CERT"
+                } else {
+                    "WS_TLS_KEY"
+                };
+                env.errors
+                    .push((missing, "has to be set along with the other".into()));
+                None
+            }
+        };
+
+        let points_audit_interval = env.optional("POINTS_AUDIT_INTERVAL");
+        let points_audit_interval = env
+            .parse::<u64>("POINTS_AUDIT_INTERVAL", points_audit_interval)
+            .filter(|secs| *secs > 0)
+            .map(Duration::from_secs);
+        let points_audit_repair = env.optional("POINTS_AUDIT_REPAIR");
+        let points_audit_repair = env
+            .parse::<bool>("POINTS_AUDIT_REPAIR", points_audit_repair)
+            .unwrap_or_default();
+
+        let ws_idle_timeout = match env.optional("WS_IDLE_TIMEOUT") {
+            Some(secs) => env.parse::<u64>("WS_IDLE_TIMEOUT", Some(secs)),
+            None => Some(DEFAULT_WS_IDLE_TIMEOUT),
+        };
+        let ws_idle_timeout = ws_idle_timeout
+            .filter(|secs| *secs > 0)
+            .map(Duration::from_secs);
+
+        let ws_rate_limit = match env.optional("WS_RATE_LIMIT") {
+            Some(limit) => env.parse::<RateLimit>("WS_RATE_LIMIT", Some(limit)),
+            None => Some(DEFAULT_WS_RATE_LIMIT),
+        };
+        let ws_dump_rate_limit = match env.optional("WS_DUMP_RATE_LIMIT") {
+            Some(limit) => env.parse::<RateLimit>("WS_DUMP_RATE_LIMIT", Some(limit)),
+            None => Some(DEFAULT_WS_DUMP_RATE_LIMIT),
+        };
+        let ws_mute = env.optional("WS_MUTE");
+        let ws_mute = env
+            .parse::<u64>("WS_MUTE", ws_mute)
+            .unwrap_or(DEFAULT_WS_MUTE);
+        let ws_trusted_proxies = env
+            .list("WS_TRUSTED_PROXIES")
+            .into_iter()
+            .filter_map(|ip| env.parse::<IpAddr>("WS_TRUSTED_PROXIES", Some(ip)))
+            .collect();
+
+        let command_timeout = env.optional("COMMAND_TIMEOUT");
+        let command_timeout = match env.parse::<u64>("COMMAND_TIMEOUT", command_timeout) {
+            Some(0) => {
+                env.errors
+                    .push(("COMMAND_TIMEOUT", "has to be at least 1".into()));
+                None
+            }
+            secs => Some(Duration::from_secs(secs.unwrap_or(DEFAULT_COMMAND_TIMEOUT))),
+        };
+        let ready_timeout = env.optional("READY_TIMEOUT");
+        let ready_timeout = env
+            .parse::<u64>("READY_TIMEOUT", ready_timeout)
+            .unwrap_or(DEFAULT_READY_TIMEOUT);
+        let ready_platforms = env
+            .optional("READY_PLATFORMS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+            .try_fold(Platform::empty(), |all, p| match p.parse::<Platform>() {
+                Ok(p) if Platform::CHAT.contains(p) => Some(all | p),
+                _ => {
+                    env.errors
+                        .push(("READY_PLATFORMS", format!("{:?} isn't a chat platform", p)));
+                    None
+                }
+            });
+        let readyz_bind = env.optional("READYZ_BIND");
+        let readyz_bind = match readyz_bind {
+            Some(bind) => env.parse::<SocketAddr>("READYZ_BIND", Some(bind)).map(Some),
+            None => Some(None),
+        };
+
+        let instance_id = env.optional("INSTANCE_ID");
+        let shard_buckets = env.optional("SHARD_BUCKETS");
+        let shards = match env.parse::<u32>("SHARD_BUCKETS", shard_buckets) {
+            Some(0) => {
+                env.errors
+                    .push(("SHARD_BUCKETS", "has to be at least 1".into()));
+                None
+            }
+            // leases live in redis, which a SQLite setup doesn't have
+            Some(_) if sqlite_path.is_some() => {
+                env.errors
+                    .push(("SHARD_BUCKETS", "can't be used with SQLITE_PATH".into()));
+                None
+            }
+            // every instance reads the whole stream in its own consumer group, which has to
+            // outlive restarts to pick up where it left off
+            Some(_)
+                if instance_id.is_none()
+                    && env.optional("UPSTREAM_TRANSPORT").as_deref() == Some("stream") =>
+            {
+                env.errors.push((
+                    "INSTANCE_ID",
+                    "has to be set with SHARD_BUCKETS and UPSTREAM_TRANSPORT=stream".into(),
+                ));
+                None
+            }
+            Some(buckets) => Some(Some(ShardConfig { buckets })),
+            None => Some(None),
+        };
+        let instance = instance_id.unwrap_or_else(|| format!("{:08x}", rand::random::<u32>()));
+
+        let expand_hosts = env
+            .optional("URL_EXPAND_HOSTS")
+            .unwrap_or_else(|| DEFAULT_URL_EXPAND_HOSTS.to_owned())
+            .split(',')
+            .map(|h| h.trim().to_lowercase())
+            .filter(|h| !h.is_empty())
+            .collect();
+        let url_timeout = env.optional("URL_TIMEOUT");
+        let url_timeout = match env.parse::<u64>("URL_TIMEOUT", url_timeout) {
+            Some(0) => {
+                env.errors
+                    .push(("URL_TIMEOUT", "has to be at least 1".into()));
+                None
+            }
+            secs => Some(Duration::from_secs(secs.unwrap_or(DEFAULT_URL_TIMEOUT))),
+        };
+        let shortener = match env.optional("URL_SHORTENER") {
+            Some(shortener) if !shortener.contains("{url}") => {
+                env.errors
+                    .push(("URL_SHORTENER", "has to contain {url}".into()));
+                None
+            }
+            Some(shortener) => env
+                .parse::<url::Url>("URL_SHORTENER", Some(shortener.clone()))
+                .map(|_| Some(shortener)),
+            None => Some(None),
+        };
+        let shorten_over = env.optional("URL_SHORTEN_OVER");
+        let shorten_over = env
+            .parse::<usize>("URL_SHORTEN_OVER", shorten_over)
+            .unwrap_or(DEFAULT_URL_SHORTEN_OVER);
+
+        let config_secret = match env.optional("CONFIG_SECRET") {
+            Some(secret) if secret.len() < MIN_SECRET_LEN => {
+                env.errors.push((
+                    "CONFIG_SECRET",
+                    format!("has to be at least {} characters", MIN_SECRET_LEN),
+                ));
+                None
+            }
+            secret => Some(secret.map(Secret)),
+        };
+        let config_owners = env
+            .optional("CONFIG_OWNERS")
+            .map(|o| {
+                o.split(',')
+                    .map(|o| o.trim().to_lowercase())
+                    .filter(|o| !o.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let backup_dir = match env.optional("BACKUP_DIR") {
+            Some(dir) => env.dir("BACKUP_DIR", Some(dir)).map(Some),
+            None => Some(None),
+        };
+
+        let chat_load = ChatLoadConfig {
+            concurrency: env
+                .number("CHAT_CONCURRENCY", DEFAULT_CHAT_CONCURRENCY)
+                .max(1),
+            shed_threshold: env.number("CHAT_SHED_THRESHOLD", DEFAULT_CHAT_SHED_THRESHOLD),
+            queue_limit: env.number("CHAT_QUEUE_LIMIT", DEFAULT_CHAT_QUEUE_LIMIT),
+        };
+
+        let twitch_app = env.app("TWITCH_CLIENT_ID", "TWITCH_CLIENT_SECRET");
+        let oauth = match env.optional("OAUTH_REDIRECT_URI") {
+            Some(redirect_uri) => {
+                let redirect_uri = env
+                    .parse::<url::Url>("OAUTH_REDIRECT_URI", Some(redirect_uri))
+                    .map(String::from);
+                let discord =
+                    match env.app("DISCORD_OAUTH_CLIENT_ID", "DISCORD_OAUTH_CLIENT_SECRET") {
+                        Some(Some(app)) => {
+                            let guild_id = env.required("GUILD_ID");
+                            let roles = env.list("DISCORD_OAUTH_ROLES");
+                            if roles.is_empty() {
+                                env.errors.push((
+                                    "DISCORD_OAUTH_ROLES",
+                                    "has to be set for discord logins".into(),
+                                ));
+                            }
+                            guild_id.map(|guild_id| {
+                                Some(DiscordOAuthConfig {
+                                    app,
+                                    guild_id,
+                                    roles,
+                                })
+                            })
+                        }
+                        other => other.map(|_| None),
+                    };
+                redirect_uri.zip(discord).map(|(redirect_uri, discord)| {
+                    Some(OAuthConfig {
+                        redirect_uri,
+                        discord,
+                        twitch: twitch_app.clone().flatten(),
+                    })
+                })
+            }
+            None => Some(None),
+        };
+        let auth = oauth.map(|oauth| AuthConfig {
+            ratelimit_count: env.number("MAX_AUTH_RATELIMIT_COUNT", DEFAULT_LOGIN_RATE_LIMIT),
+            ratelimit_burst: env.number("MAX_AUTH_RATELIMIT_BURST", DEFAULT_LOGIN_RATE_LIMIT_BURST),
+            session_ttl: env.number("SESSION_TTL", DEFAULT_SESSION_TTL),
+            oauth,
+        });
+
+        let twitch_login = env
+            .optional("TWITCH_LOGIN")
+            .or_else(|| env.optional("CHANNEL_NAME"))
+            .map(|login| login.to_lowercase());
+        let poll_interval = env
+            .number("TWITCH_POLL_INTERVAL", DEFAULT_TWITCH_POLL_INTERVAL)
+            .max(MIN_TWITCH_POLL_INTERVAL);
+        let twitch = twitch_app.map(|app| TwitchConfig {
+            // CHANNEL_NAME is already reported if it's missing
+            login: twitch_login.unwrap_or_default(),
+            poll_interval: Duration::from_secs(poll_interval),
+            app,
+            user_token: env.optional("TWITCH_USER_TOKEN").map(Secret),
+            refresh_token: env.optional("TWITCH_REFRESH_TOKEN").map(Secret),
+        });
+
+        Some(Self {
+            ws_bind: ws_bind?,
+            config_dir: config_dir?,
+            storage: match sqlite_path {
+                Some(path) => StorageConfig::Sqlite(path.into()),
+                None => StorageConfig::Postgres(Box::new(DatabaseConfig {
+                    config: database?,
+                    pool_size: database_pool_size,
+                    connect_timeout: database_timeout?,
+                    statement_timeout,
+                    tls_ca: database_tls_ca,
+                    read_config: database_read?,
+                })),
+            },
+            tls: tls?,
+            points_audit_interval,
+            points_audit_repair,
+            ws_idle_timeout,
+            ws_rate_limit: ws_rate_limit?,
+            ws_dump_rate_limit: ws_dump_rate_limit?,
+            ws_mute: Duration::from_secs(ws_mute),
+            ws_trusted_proxies,
+            command_timeout: command_timeout?,
+            ready_timeout: Duration::from_secs(ready_timeout),
+            ready_platforms: ready_platforms?,
+            readyz_bind: readyz_bind?,
+            instance,
+            shards: shards?,
+            urls: UrlConfig {
+                expand_hosts,
+                timeout: url_timeout?,
+                shortener: shortener?,
+                shorten_over,
+            },
+            config_secret: config_secret?,
+            config_owners,
+            backup_dir: backup_dir?,
+            chat_load,
+            auth: auth?,
+            twitch: twitch?,
+        })
+    }
+
+    /// Make the config available to the statics in the crate root, returning it
+    pub fn init(self) -> &'static Self {
+        let _ = SERVER_CONFIG.set(self);
+        SERVER_CONFIG.get().unwrap()
+    }
+}
+
+impl DiscordConfig {
+    fn read(env: &mut Env) -> Option<Self> {
+        let token = env.required("DISCORD_TOKEN").map(Secret);
+        let guild_id = env.id("GUILD_ID");
+        let streamer_id = env.id("STREAMER_ID");
+        let bot_id = env.id("AUSSIEBOT_ID");
+        let member_role_id = env.id("MEMBER_ROLE_ID");
+        let announce_chan = env.id("STREAM_ANNOUNCE_CHAN_ID");
+        let bot_chan = env.id("BOT_CHAN_ID");
+        let config_dir = env.required("CONFIG_DIR");
+        let config_dir = env.dir("CONFIG_DIR", config_dir);
+        // not from .env, the restart script sets it
+        let was_streaming = std::env::var_os("STARTED").is_some();
+
+        Some(Self {
+            token: token?,
+            guild_id: guild_id?,
+            streamer_id: streamer_id?,
+            bot_id: bot_id?,
+            member_role_id: member_role_id?,
+            announce_chan: announce_chan?,
+            bot_chan: bot_chan?,
+            config_dir: config_dir?,
+            was_streaming,
+        })
+    }
+
+    /// Make the config available to [`discord`], returning it
+    pub fn init(self) -> &'static Self {
+        let _ = DISCORD_CONFIG.set(self);
+        DISCORD_CONFIG.get().unwrap()
+    }
+}
+
+pub fn get() -> &'static Config {
+    CONFIG
+        .get()
+        .expect("settings read before Config::init was called")
+}
+
+pub fn server() -> &'static ServerConfig {
+    SERVER_CONFIG
+        .get()
+        .expect("settings read before ServerConfig::init was called")
+}
+
+pub fn discord() -> &'static DiscordConfig {
+    DISCORD_CONFIG
+        .get()
+        .expect("settings read before DiscordConfig::init was called")
+}
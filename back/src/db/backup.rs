@@ -0,0 +1,136 @@
+//! Rows that go into backups besides points, which go through users::export and users::import
+use crate::{error, msg::Platform, DbPool};
+use serde_derive::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const PLATFORMS: [Platform; 3] = [Platform::YOUTUBE, Platform::DISCORD, Platform::TWITCH];
+
+/// An account linked to a discord account
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LinkRecord {
+    /// Youtube or Twitch
+    pub platform: Platform,
+    pub id: String,
+    pub discord_id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModActionRecord {
+    pub platform: Platform,
+    pub id: String,
+    pub action: String,
+    #[serde(default)]
+    pub reason: Option<String>,
+    /// unix secs
+    pub at: u64,
+}
+
+#[derive(Clone)]
+pub(crate) struct RestoreOp {
+    pub(crate) links: Vec<LinkRecord>,
+    pub(crate) mod_actions: Vec<ModActionRecord>,
+}
+
+// hide potentially massive batches from tracing
+impl std::fmt::Debug for RestoreOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RestoreOp")
+            .field("links", &self.links.len())
+            .field("mod_actions", &self.mod_actions.len())
+            .finish()
+    }
+}
+
+pub(crate) async fn links(db: DbPool) -> error::Result<Vec<LinkRecord>> {
+    let client = db.get().await?;
+    let mut links = vec![];
+    for (platform, sql) in [
+        (
+            Platform::YOUTUBE,
+            include_str!("sql/select/export_link_yt.sql"),
+        ),
+        (
+            Platform::TWITCH,
+            include_str!("sql/select/export_link_tw.sql"),
+        ),
+    ] {
+        for row in client.query(sql, &[]).await? {
+            links.push(LinkRecord {
+                platform,
+                id: row.try_get(0)?,
+                discord_id: row.try_get(1)?,
+            });
+        }
+    }
+    Ok(links)
+}
+
+/// Every mod action, oldest first on each platform
+pub(crate) async fn mod_actions(db: DbPool) -> error::Result<Vec<ModActionRecord>> {
+    let client = db.get().await?;
+    let mut actions = vec![];
+    for platform in PLATFORMS {
+        let sql = match platform {
+            Platform::YOUTUBE => include_str!("sql/select/export_modaction_youtube.sql"),
+            Platform::DISCORD => include_str!("sql/select/export_modaction_discord.sql"),
+            _ => include_str!("sql/select/export_modaction_twitch.sql"),
+        };
+        for row in client.query(sql, &[]).await? {
+            actions.push(ModActionRecord {
+                platform,
+                id: row.try_get(0)?,
+                action: row.try_get(1)?,
+                reason: row.try_get(2)?,
+                at: row
+                    .try_get::<_, SystemTime>(3)?
+                    .duration_since(UNIX_EPOCH)?
+                    .as_secs(),
+            });
+        }
+    }
+    Ok(actions)
+}
+
+/// Upsert links and add mod actions that aren't there yet, in one transaction. Returns how many
+/// rows were written
+pub(crate) async fn restore(db: DbPool, args: RestoreOp) -> error::Result<u64> {
+    let mut client = db.get().await?;
+    let client = client.build_transaction().start().await?;
+
+    let mut written = 0;
+    for link in &args.links {
+        let sql = match link.platform {
+            Platform::YOUTUBE => include_str!("sql/upsert/link_yt.sql"),
+            Platform::TWITCH => include_str!("sql/upsert/link_tw.sql"),
+            _ => continue,
+        };
+        written += client
+            .execute(sql, &[&link.id.as_str(), &link.discord_id.as_str()])
+            .await?;
+    }
+
+    // the same action at the same moment is taken to be one restored before
+    for action in &args.mod_actions {
+        let sql = match action.platform {
+            Platform::YOUTUBE => include_str!("sql/insert/restore_modaction_youtube.sql"),
+            Platform::DISCORD => include_str!("sql/insert/restore_modaction_discord.sql"),
+            Platform::TWITCH => include_str!("sql/insert/restore_modaction_twitch.sql"),
+            _ => continue,
+        };
+        let at = UNIX_EPOCH + Duration::from_secs(action.at);
+        written += client
+            .execute(
+                sql,
+                &[
+                    &action.id.as_str(),
+                    &action.action.as_str(),
+                    &action.reason,
+                    &at,
+                ],
+            )
+            .await?;
+    }
+
+    client.commit().await?;
+    Ok(written)
+}
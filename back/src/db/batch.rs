@@ -0,0 +1,354 @@
+//! Point increments from chat, coalesced per user and written together rather than a
+//! roundtrip per message. The balances and ledger totals come out the same as writing
+//! them one by one: a user's increments are summed, and they keep the last name they
+//! chatted with, which is what consecutive upserts would have left
+
+use super::{Db, Resp, TaskChanPair};
+use crate::{
+    error::{self, Error},
+    msg::Platform,
+    DbPool,
+};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::{
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+    time::Instant,
+};
+
+/// Increments per write
+const UPSERT_BATCH: usize = 100;
+/// Increments are written at most this long after the first one of a batch
+const UPSERT_WINDOW: Duration = Duration::from_millis(500);
+
+/// All of a user's increments in a batch
+#[derive(Debug, Clone)]
+pub(crate) struct Increment {
+    pub(crate) platform: Platform,
+    pub(crate) id: Arc<String>,
+    /// The latest one
+    pub(crate) name: Arc<String>,
+    pub(crate) points: i32,
+}
+
+#[derive(Clone)]
+pub(crate) struct UpsertBatch(pub(crate) Vec<Increment>);
+
+// hide the whole batch from tracing
+impl std::fmt::Debug for UpsertBatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("UpsertBatch").field(&self.0.len()).finish()
+    }
+}
+
+/// (ids, names, points)
+type Columns = (Vec<String>, Vec<String>, Vec<i32>);
+
+impl UpsertBatch {
+    /// The increments on each platform, as columns
+    pub(super) fn by_platform(self) -> HashMap<Platform, Columns> {
+        let mut columns: HashMap<_, Columns> = HashMap::new();
+        for inc in self.0 {
+            let (ids, names, points) = columns.entry(inc.platform).or_default();
+            ids.push(inc.id.to_string());
+            names.push(inc.name.to_string());
+            points.push(inc.points);
+        }
+        columns
+    }
+}
+
+pub(super) async fn write(db: DbPool, batch: UpsertBatch) -> error::Result<()> {
+    let mut client = db.get().await?;
+    let client = client.build_transaction().start().await?;
+
+    for (platform, (ids, names, points)) in batch.by_platform() {
+        let sql = match platform {
+            Platform::YOUTUBE => include_str!("sql/upsert/batch_youtube.sql"),
+            Platform::DISCORD => include_str!("sql/upsert/batch_discord.sql"),
+            Platform::TWITCH => include_str!("sql/upsert/batch_twitch.sql"),
+            _ => unreachable!(),
+        };
+        client.execute(sql, &[&ids, &names, &points]).await?;
+        client
+            .execute(
+                include_str!("sql/insert/point_ledger_batch.sql"),
+                &[&platform.to_string().to_lowercase(), &ids, &points],
+            )
+            .await?;
+        tracing::info!(platform = %platform, users = ids.len(), "incremented points");
+    }
+
+    client.commit().await?;
+    Ok(())
+}
+
+/// Increments waiting to be written, and who's waiting on them
+#[derive(Default)]
+struct Pending {
+    /// index into `increments`
+    users: HashMap<(Platform, Arc<String>), usize>,
+    increments: Vec<Increment>,
+    waiting: Vec<oneshot::Sender<error::Result<Resp>>>,
+    deadline: Option<Instant>,
+}
+
+impl Pending {
+    /// True once the batch is full
+    fn push(
+        &mut self,
+        (platform, id, name, points): (Platform, Arc<String>, Arc<String>, i32),
+        tx: oneshot::Sender<error::Result<Resp>>,
+    ) -> bool {
+        match self.users.get(&(platform, id.clone())) {
+            Some(&i) => {
+                let inc = &mut self.increments[i];
+                inc.name = name;
+                inc.points = inc.points.saturating_add(points);
+            }
+            None => {
+                self.users
+                    .insert((platform, id.clone()), self.increments.len());
+                self.increments.push(Increment {
+                    platform,
+                    id,
+                    name,
+                    points,
+                });
+            }
+        }
+        self.waiting.push(tx);
+        self.deadline
+            .get_or_insert_with(|| Instant::now() + UPSERT_WINDOW);
+        self.waiting.len() >= UPSERT_BATCH
+    }
+
+    /// Hand the batch to `handle` as a Db::UpsertBatch, answering everyone waiting on it once it's
+    /// written. The task doing that is returned, None if there was nothing to write
+    fn flush(&mut self, handle: &impl Fn(TaskChanPair)) -> Option<JoinHandle<()>> {
+        self.users.clear();
+        self.deadline = None;
+        let increments = std::mem::take(&mut self.increments);
+        let waiting = std::mem::take(&mut self.waiting);
+
+        if increments.is_empty() {
+            // only flushes waiting
+            for tx in waiting {
+                let _ = tx.send(Ok(Resp::Ok));
+            }
+            return None;
+        }
+
+        let count = increments.len();
+        let (tx, rx) = oneshot::channel();
+        handle((Db::UpsertBatch(UpsertBatch(increments)), tx));
+
+        Some(tokio::spawn(async move {
+            let res = rx
+                .await
+                .unwrap_or_else(|_| Err(Error::Generic("upsert batch dropped".into())));
+            match res {
+                Ok(_) => tracing::debug!(count, "point increments written"),
+                Err(ref e) => tracing::error!(count, "{}", e),
+            }
+            for tx in waiting {
+                let resp = match res {
+                    Ok(_) => Ok(Resp::Ok),
+                    Err(ref e) => Err(Error::Generic(e.to_string())),
+                };
+                let _ = tx.send(resp);
+            }
+        }))
+    }
+}
+
+/// Pass an actor's tasks to `handle`, except point increments, which are coalesced and passed on
+/// as a Db::UpsertBatch once there are enough of them or the window is up.
+/// Whatever's left is written before returning, once every Handle is dropped
+pub(super) async fn run(mut rx: mpsc::Receiver<TaskChanPair>, handle: impl Fn(TaskChanPair)) {
+    let mut pending = Pending::default();
+
+    loop {
+        let deadline = pending.deadline;
+        let task = tokio::select! {
+            task = rx.recv() => task,
+            _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                pending.flush(&handle);
+                continue;
+            }
+        };
+
+        match task {
+            Some((Db::Upsert(platform, id, name, points), tx)) => {
+                if pending.push((platform, id, name, points), tx) {
+                    pending.flush(&handle);
+                }
+            }
+            Some((Db::FlushPoints, tx)) => {
+                pending.waiting.push(tx);
+                pending.flush(&handle);
+            }
+            Some(task) => handle(task),
+            None => {
+                if let Some(written) = pending.flush(&handle) {
+                    let _ = written.await;
+                }
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+
+    fn upsert(
+        pending: &mut Pending,
+        platform: Platform,
+        id: &str,
+        name: &str,
+        points: i32,
+    ) -> (bool, oneshot::Receiver<error::Result<Resp>>) {
+        let (tx, rx) = oneshot::channel();
+        let full = pending.push(
+            (platform, Arc::new(id.into()), Arc::new(name.into()), points),
+            tx,
+        );
+        (full, rx)
+    }
+
+    #[test]
+    fn sums_points_per_user() {
+        let mut pending = Pending::default();
+        upsert(&mut pending, Platform::TWITCH, "1", "a", 5);
+        upsert(&mut pending, Platform::TWITCH, "2", "b", 1);
+        upsert(&mut pending, Platform::TWITCH, "1", "a", 3);
+        // same id on another platform is another user
+        upsert(&mut pending, Platform::YOUTUBE, "1", "a", 2);
+
+        assert_eq!(pending.increments.len(), 3);
+        assert_eq!(pending.waiting.len(), 4);
+        let points: Vec<_> = pending
+            .increments
+            .iter()
+            .map(|inc| (inc.platform, inc.id.as_str(), inc.points))
+            .collect();
+        assert_eq!(
+            points,
+            [
+                (Platform::TWITCH, "1", 8),
+                (Platform::TWITCH, "2", 1),
+                (Platform::YOUTUBE, "1", 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn points_saturate() {
+        let mut pending = Pending::default();
+        upsert(&mut pending, Platform::TWITCH, "1", "a", i32::MAX);
+        upsert(&mut pending, Platform::TWITCH, "1", "a", 1);
+        assert_eq!(pending.increments[0].points, i32::MAX);
+    }
+
+    #[test]
+    fn last_name_wins() {
+        let mut pending = Pending::default();
+        upsert(&mut pending, Platform::DISCORD, "1", "old", 1);
+        upsert(&mut pending, Platform::DISCORD, "1", "new", 1);
+        assert_eq!(pending.increments[0].name.as_str(), "new");
+    }
+
+    #[test]
+    fn by_platform_splits_into_columns() {
+        let mut pending = Pending::default();
+        upsert(&mut pending, Platform::TWITCH, "1", "a", 1);
+        upsert(&mut pending, Platform::YOUTUBE, "2", "b", 2);
+        upsert(&mut pending, Platform::TWITCH, "3", "c", 3);
+
+        let columns = UpsertBatch(pending.increments).by_platform();
+        assert_eq!(columns.len(), 2);
+        assert_eq!(
+            columns[&Platform::TWITCH],
+            (
+                vec!["1".to_owned(), "3".to_owned()],
+                vec!["a".to_owned(), "c".to_owned()],
+                vec![1, 3]
+            )
+        );
+        assert_eq!(
+            columns[&Platform::YOUTUBE],
+            (vec!["2".to_owned()], vec!["b".to_owned()], vec![2])
+        );
+    }
+
+    #[test]
+    fn full_once_enough_are_waiting() {
+        let mut pending = Pending::default();
+        for i in 1..UPSERT_BATCH {
+            // repeats of one user still count towards a full batch
+            let (full, _) = upsert(&mut pending, Platform::TWITCH, "1", "a", 1);
+            assert!(!full, "full after {}", i);
+        }
+        let (full, _) = upsert(&mut pending, Platform::TWITCH, "1", "a", 1);
+        assert!(full);
+        assert!(pending.deadline.is_some());
+    }
+
+    #[tokio::test]
+    async fn flush_answers_everyone_waiting() {
+        let mut pending = Pending::default();
+        let (_, first) = upsert(&mut pending, Platform::TWITCH, "1", "a", 1);
+        let (_, second) = upsert(&mut pending, Platform::TWITCH, "1", "b", 2);
+
+        let sent = Mutex::new(None);
+        let written = pending
+            .flush(&|(task, tx): TaskChanPair| {
+                if let Db::UpsertBatch(batch) = task {
+                    *sent.lock() = Some(batch);
+                }
+                let _ = tx.send(Ok(Resp::Ok));
+            })
+            .expect("there was something to write");
+
+        // reset for the next batch
+        assert!(pending.users.is_empty());
+        assert!(pending.increments.is_empty());
+        assert!(pending.waiting.is_empty());
+        assert!(pending.deadline.is_none());
+
+        let batch = sent.lock().take().expect("batch was handed on");
+        assert_eq!(batch.0.len(), 1);
+        assert_eq!(batch.0[0].points, 3);
+        assert_eq!(batch.0[0].name.as_str(), "b");
+
+        written.await.unwrap();
+        assert!(matches!(first.await, Ok(Ok(Resp::Ok))));
+        assert!(matches!(second.await, Ok(Ok(Resp::Ok))));
+    }
+
+    #[tokio::test]
+    async fn failed_write_is_passed_on() {
+        let mut pending = Pending::default();
+        let (_, rx) = upsert(&mut pending, Platform::TWITCH, "1", "a", 1);
+        let written = pending
+            .flush(&|(_, tx): TaskChanPair| {
+                let _ = tx.send(Err(Error::Generic("down".into())));
+            })
+            .unwrap();
+        written.await.unwrap();
+        assert!(matches!(rx.await, Ok(Err(_))));
+    }
+
+    #[test]
+    fn empty_flush_only_answers_waiting() {
+        let mut pending = Pending::default();
+        let (tx, mut rx) = oneshot::channel();
+        pending.waiting.push(tx);
+        let written = pending.flush(&|_| panic!("nothing to write"));
+        assert!(written.is_none());
+        assert!(matches!(rx.try_recv(), Ok(Ok(Resp::Ok))));
+    }
+}
@@ -0,0 +1,46 @@
+pub use crate::proto::db::ConfigAudit;
+use crate::{cmds::ConfigChange, error, DbPool};
+use std::sync::Arc;
+use tokio_postgres::Row;
+
+#[derive(Debug, Clone)]
+pub(crate) struct AuditOp {
+    pub(crate) author: Arc<String>,
+    pub(crate) action: &'static str,
+    pub(crate) changes: Vec<ConfigChange>,
+}
+
+impl TryFrom<&Row> for ConfigAudit {
+    type Error = error::Error;
+
+    fn try_from(row: &Row) -> Result<Self, Self::Error> {
+        Ok(ConfigAudit {
+            id: row.try_get(0)?,
+            author: row.try_get(1)?,
+            action: row.try_get(2)?,
+            changes: serde_json::from_str(row.try_get(3)?)?,
+            created: row.try_get(4)?,
+        })
+    }
+}
+
+pub(crate) async fn record(db: DbPool, args: AuditOp) -> error::Result<()> {
+    let client = db.get().await?;
+    let changes = serde_json::to_string(&args.changes)?;
+    client
+        .execute(
+            include_str!("sql/insert/config_audit.sql"),
+            &[&args.author.as_str(), &args.action, &changes],
+        )
+        .await?;
+    Ok(())
+}
+
+/// The latest changes first
+pub(crate) async fn list(db: DbPool, limit: i64) -> error::Result<Vec<ConfigAudit>> {
+    let client = db.get().await?;
+    let rows = client
+        .query(include_str!("sql/select/config_audit.sql"), &[&limit])
+        .await?;
+    rows.iter().map(ConfigAudit::try_from).collect()
+}
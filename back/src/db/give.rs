@@ -1,21 +1,21 @@
+use super::ledger;
 use crate::{
     error::{self, Error},
     msg::Platform,
+    DbPool,
 };
-use bb8::Pool;
-use bb8_postgres::PostgresConnectionManager;
 use std::{fmt::Display, sync::Arc};
-use tokio_postgres::{NoTls, Transaction};
+use tokio_postgres::Transaction;
 
 #[allow(dead_code)]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) enum GiveSource {
     Id(Platform, Arc<String>),
     Linked(Platform, Platform, Arc<String>),
     None,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) enum GiveTarget {
     Name(Platform, Arc<String>),
     User(Platform, Arc<String>, Arc<String>),
@@ -23,7 +23,7 @@ pub(crate) enum GiveTarget {
     Spend,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) struct GiveOp {
     pub(crate) from: GiveSource,
     pub(crate) to: GiveTarget,
@@ -32,6 +32,59 @@ pub(crate) struct GiveOp {
     pub(crate) max: i64,
 }
 
+/// A wager taken and paid out in one transaction
+#[derive(Debug, Clone)]
+pub(crate) struct BetOp {
+    pub(crate) platform: Platform,
+    pub(crate) id: Arc<String>,
+    pub(crate) name: Arc<String>,
+    /// -1 for all of their points
+    pub(crate) amount: i32,
+    pub(crate) min: i64,
+    pub(crate) max: i64,
+    /// Paid back, as a percentage of the wager
+    pub(crate) payout_pct: i64,
+    /// Paid on top, e.g. a jackpot
+    pub(crate) bonus: i32,
+}
+
+impl BetOp {
+    pub(super) fn wager(&self) -> GiveOp {
+        GiveOp {
+            from: GiveSource::Id(self.platform, self.id.clone()),
+            to: GiveTarget::Spend,
+            amount: self.amount,
+            min: self.min,
+            max: self.max,
+        }
+    }
+
+    /// Paying out a wager of `amount`, None if there's nothing to pay
+    pub(super) fn payout(&self, amount: i32) -> Option<GiveOp> {
+        let winnings = (amount as i64 * self.payout_pct / 100 + self.bonus as i64)
+            .clamp(0, i32::MAX as i64) as i32;
+        (winnings > 0).then(|| GiveOp {
+            from: GiveSource::None,
+            to: GiveTarget::User(self.platform, self.id.clone(), self.name.clone()),
+            amount: winnings,
+            min: 0,
+            max: 0,
+        })
+    }
+}
+
+impl GiveOp {
+    /// What the change is recorded as in the ledger
+    pub(super) fn reason(&self) -> &'static str {
+        match (&self.from, &self.to) {
+            (GiveSource::Linked(..), _) => "transfer",
+            (_, GiveTarget::Spend) => "spend",
+            (GiveSource::None, _) => "award",
+            _ => "give",
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum GiveError {
     SamePlatform,
@@ -50,13 +103,47 @@ impl Display for GiveError {
 type Ret = i32;
 
 //impl super::Actor {
-pub(crate) async fn op(
-    db: Pool<PostgresConnectionManager<NoTls>>,
-    args: GiveOp,
-) -> error::Result<Ret> {
+pub(crate) async fn op(db: DbPool, args: GiveOp) -> error::Result<Ret> {
     // start transaction
     let mut client = db.get().await.unwrap();
     let client = client.build_transaction().start().await?;
+    let (client, amount) = apply(client, &args).await?;
+    client.commit().await?;
+    Ok(amount)
+}
+
+/// Every give in one transaction
+pub(crate) async fn op_all(db: DbPool, ops: Vec<GiveOp>) -> error::Result<Vec<Ret>> {
+    let mut client = db.get().await?;
+    let mut client = client.build_transaction().start().await?;
+    let mut amounts = Vec::with_capacity(ops.len());
+    for args in &ops {
+        let amount;
+        (client, amount) = apply(client, args).await?;
+        amounts.push(amount);
+    }
+    client.commit().await?;
+    Ok(amounts)
+}
+
+/// Take the wager and pay it out, answered with both amounts
+pub(crate) async fn bet(db: DbPool, args: BetOp) -> error::Result<(Ret, Ret)> {
+    let mut client = db.get().await?;
+    let client = client.build_transaction().start().await?;
+    let (client, amount) = apply(client, &args.wager()).await?;
+    let (client, winnings) = match args.payout(amount) {
+        Some(payout) => apply(client, &payout).await?,
+        None => (client, 0),
+    };
+    client.commit().await?;
+    Ok((amount, winnings))
+}
+
+async fn apply<'a>(
+    client: Transaction<'a>,
+    args: &GiveOp,
+) -> error::Result<(Transaction<'a>, Ret)> {
+    let reason = args.reason();
 
     match (&args.from, &args.to) {
         (GiveSource::Linked(platorig, platfrom, id), GiveTarget::Linked(platto)) => {
@@ -91,41 +178,36 @@ pub(crate) async fn op(
             // check if 'to' platform is linked
             let to_id = get_id(platto)?;
 
-            let (client, amount) = get_amount(client, platfrom, &from_id, &args).await?;
-            let client = handle_deduct_id(client, platfrom, &from_id, amount).await?;
-            let client = handle_deposit_id(client, platto, &to_id, amount).await?;
-            client.commit().await?;
-            Ok(amount)
+            let (client, amount) = get_amount(client, platfrom, &from_id, args).await?;
+            let client = handle_deduct_id(client, platfrom, &from_id, amount, reason).await?;
+            let client = handle_deposit_id(client, platto, &to_id, amount, reason).await?;
+            Ok((client, amount))
         }
         (GiveSource::Id(platfrom, from_id), GiveTarget::Name(platto, to_name)) => {
-            let (client, amount) = get_amount(client, *platfrom, &**from_id, &args).await?;
-            let client = handle_deduct_id(client, *platfrom, &**from_id, amount).await?;
-            let client = handle_deposit_name(client, *platto, &**to_name, amount).await?;
-            client.commit().await?;
-            Ok(amount)
+            let (client, amount) = get_amount(client, *platfrom, &**from_id, args).await?;
+            let client = handle_deduct_id(client, *platfrom, &**from_id, amount, reason).await?;
+            let client = handle_deposit_name(client, *platto, &**to_name, amount, reason).await?;
+            Ok((client, amount))
         }
         (GiveSource::Id(platfrom, from_id), GiveTarget::User(platto, to_id, _to_name)) => {
-            let (client, amount) = get_amount(client, *platfrom, &**from_id, &args).await?;
-            let client = handle_deduct_id(client, *platfrom, &**from_id, amount).await?;
-            let client = handle_deposit_id(client, *platto, &**to_id, amount).await?;
-            client.commit().await?;
-            Ok(amount)
+            let (client, amount) = get_amount(client, *platfrom, &**from_id, args).await?;
+            let client = handle_deduct_id(client, *platfrom, &**from_id, amount, reason).await?;
+            let client = handle_deposit_id(client, *platto, &**to_id, amount, reason).await?;
+            Ok((client, amount))
         }
         (GiveSource::Id(platfrom, from_id), GiveTarget::Spend) => {
-            let (client, amount) = get_amount(client, *platfrom, &**from_id, &args).await?;
-            let client = handle_deduct_id(client, *platfrom, &**from_id, amount).await?;
-            client.commit().await?;
-            Ok(amount)
+            let (client, amount) = get_amount(client, *platfrom, &**from_id, args).await?;
+            let client = handle_deduct_id(client, *platfrom, &**from_id, amount, reason).await?;
+            Ok((client, amount))
         }
         (GiveSource::None, GiveTarget::Name(platto, to_name)) => {
-            let client = handle_deposit_name(client, *platto, &**to_name, args.amount).await?;
-            client.commit().await?;
-            Ok(args.amount)
+            let client =
+                handle_deposit_name(client, *platto, &**to_name, args.amount, reason).await?;
+            Ok((client, args.amount))
         }
         (GiveSource::None, GiveTarget::User(platto, to_id, _to_name)) => {
-            let client = handle_deposit_id(client, *platto, &**to_id, args.amount).await?;
-            client.commit().await?;
-            Ok(args.amount)
+            let client = handle_deposit_id(client, *platto, &**to_id, args.amount, reason).await?;
+            Ok((client, args.amount))
         }
         (GiveSource::None, GiveTarget::Spend) => panic!("Invalid combination"),
         (_, GiveTarget::Linked(_)) | (GiveSource::Linked(_, _, _), _) => {
@@ -138,7 +220,7 @@ async fn get_amount<'a>(
     client: Transaction<'a>,
     platform: Platform,
     source: impl AsRef<str>,
-    args: &'a GiveOp,
+    args: &GiveOp,
 ) -> error::Result<(Transaction<'a>, i32)> {
     let amount = args.amount;
     let min = args.min as i32;
@@ -174,12 +256,13 @@ async fn get_amount<'a>(
     Ok((client, amount))
 }
 
-async fn handle_deduct_id(
-    client: Transaction<'_>,
+pub(super) async fn handle_deduct_id<'a>(
+    client: Transaction<'a>,
     platform: Platform,
     source: impl AsRef<str>,
     amount: i32,
-) -> error::Result<Transaction<'_>> {
+    reason: &str,
+) -> error::Result<Transaction<'a>> {
     let deduct_sql = match platform {
         Platform::YOUTUBE => include_str!("sql/update/decr_points_youtube.sql"),
         Platform::DISCORD => include_str!("sql/update/decr_points_discord.sql"),
@@ -204,43 +287,49 @@ async fn handle_deduct_id(
         return Err(GiveError::Deduct.into());
     }
 
+    ledger::record(&client, platform, source.as_ref(), -amount, reason).await?;
+
     Ok(client)
 }
 
-async fn handle_deposit_name(
-    client: Transaction<'_>,
+async fn handle_deposit_name<'a>(
+    client: Transaction<'a>,
     platform: Platform,
     target: impl AsRef<str>,
     amount: i32,
-) -> error::Result<Transaction<'_>> {
+    reason: &str,
+) -> error::Result<Transaction<'a>> {
     let deposit_sql = match platform {
         Platform::YOUTUBE => include_str!("sql/update/incr_points_youtube_name.sql"),
         Platform::DISCORD => include_str!("sql/update/incr_points_discord_name.sql"),
         _ => return Err(GiveError::InvalidPlatform.into()),
     };
-    _handle_deposit(client, target, amount, deposit_sql).await
+    _handle_deposit(client, platform, target, amount, deposit_sql, reason).await
 }
 
-async fn handle_deposit_id(
-    client: Transaction<'_>,
+pub(super) async fn handle_deposit_id<'a>(
+    client: Transaction<'a>,
     platform: Platform,
     target: impl AsRef<str>,
     amount: i32,
-) -> error::Result<Transaction<'_>> {
+    reason: &str,
+) -> error::Result<Transaction<'a>> {
     let deposit_sql = match platform {
         Platform::YOUTUBE => include_str!("sql/update/incr_points_youtube_id.sql"),
         Platform::DISCORD => include_str!("sql/update/incr_points_discord_id.sql"),
         Platform::TWITCH => include_str!("sql/update/incr_points_twitch_id.sql"),
         _ => return Err(GiveError::InvalidPlatform.into()),
     };
-    _handle_deposit(client, target, amount, deposit_sql).await
+    _handle_deposit(client, platform, target, amount, deposit_sql, reason).await
 }
 
 async fn _handle_deposit<'a>(
     client: Transaction<'a>,
+    platform: Platform,
     target: impl AsRef<str>,
     amount: i32,
     deposit_sql: &'a str,
+    reason: &str,
 ) -> error::Result<Transaction<'a>> {
     // try depositing into dest
     let incremented = client
@@ -258,5 +347,72 @@ async fn _handle_deposit<'a>(
         return Err(GiveError::Deposit.into());
     }
 
+    // names aren't unique, so go by the ids of whoever was actually credited
+    for row in &incremented {
+        let id: &str = row.try_get(0)?;
+        ledger::record(&client, platform, id, amount, reason).await?;
+    }
+
     Ok(client)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bet(payout_pct: i64, bonus: i32) -> BetOp {
+        BetOp {
+            platform: Platform::TWITCH,
+            id: Arc::new("1".into()),
+            name: Arc::new("a".into()),
+            amount: 100,
+            min: 1,
+            max: 0,
+            payout_pct,
+            bonus,
+        }
+    }
+
+    #[test]
+    fn pays_a_percentage_of_the_wager() {
+        let op = bet(200, 0).payout(50).unwrap();
+        assert_eq!(op.amount, 100);
+        assert!(matches!(op.to, GiveTarget::User(Platform::TWITCH, ..)));
+        assert_eq!(op.reason(), "award");
+
+        // rounds down
+        assert_eq!(bet(150, 0).payout(3).unwrap().amount, 4);
+    }
+
+    #[test]
+    fn adds_the_bonus() {
+        assert_eq!(bet(200, 25).payout(50).unwrap().amount, 125);
+        // a bonus is paid even when the wager isn't
+        assert_eq!(bet(0, 25).payout(50).unwrap().amount, 25);
+    }
+
+    #[test]
+    fn nothing_to_pay() {
+        assert!(bet(0, 0).payout(50).is_none());
+        assert!(bet(200, 0).payout(0).is_none());
+        assert!(bet(1, 0).payout(50).is_none());
+        assert!(bet(-100, 0).payout(50).is_none());
+    }
+
+    #[test]
+    fn clamps_to_i32() {
+        assert_eq!(
+            bet(200, i32::MAX).payout(i32::MAX).unwrap().amount,
+            i32::MAX
+        );
+        assert_eq!(bet(1000, 0).payout(i32::MAX).unwrap().amount, i32::MAX);
+    }
+
+    #[test]
+    fn wager_is_spent() {
+        let op = bet(200, 0).wager();
+        assert_eq!(op.amount, 100);
+        assert!(matches!(op.from, GiveSource::Id(Platform::TWITCH, _)));
+        assert_eq!(op.reason(), "spend");
+    }
+}
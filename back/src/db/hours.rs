@@ -1,20 +1,14 @@
-use crate::{error, msg::Platform};
-use bb8::Pool;
-use bb8_postgres::PostgresConnectionManager;
+use crate::{error, msg::Platform, DbPool};
 use std::{sync::Arc, time::SystemTime};
-use tokio_postgres::NoTls;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) struct HoursOp {
     pub(crate) platform: Platform,
     pub(crate) id: Arc<String>,
     pub(crate) max_diff: i64,
 }
 
-pub(crate) async fn op(
-    db: Pool<PostgresConnectionManager<NoTls>>,
-    args: HoursOp,
-) -> error::Result<i32> {
+pub(crate) async fn op(db: DbPool, args: HoursOp) -> error::Result<i32> {
     let HoursOp {
         platform,
         id,
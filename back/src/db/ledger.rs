@@ -0,0 +1,123 @@
+use super::give::GiveError;
+pub use crate::proto::db::Discrepancy;
+use crate::{error, msg::Platform, DbPool};
+use tokio_postgres::Transaction;
+
+/// Record a change to a user's points, in the same transaction as the change itself
+pub(super) async fn record(
+    client: &Transaction<'_>,
+    platform: Platform,
+    id: &str,
+    delta: i32,
+    reason: &str,
+) -> error::Result<()> {
+    if delta == 0 {
+        return Ok(());
+    }
+    client
+        .execute(
+            include_str!("sql/insert/point_ledger.sql"),
+            &[&platform.to_string().to_lowercase(), &id, &delta, &reason],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Compare everyone's points against the ledger, optionally setting them back to what it says
+pub(crate) async fn audit(db: DbPool, repair: bool) -> error::Result<Vec<Discrepancy>> {
+    let mut discrepancies = vec![];
+
+    for platform in [Platform::YOUTUBE, Platform::DISCORD, Platform::TWITCH] {
+        let sql = match platform {
+            Platform::YOUTUBE => include_str!("sql/select/audit_youtube.sql"),
+            Platform::DISCORD => include_str!("sql/select/audit_discord.sql"),
+            Platform::TWITCH => include_str!("sql/select/audit_twitch.sql"),
+            _ => unreachable!(),
+        };
+
+        let client = db.get().await?;
+        let rows = client.query(sql, &[]).await?;
+        drop(client);
+
+        for row in rows {
+            let present: bool = row.try_get(3)?;
+            let actual = if present {
+                Some(row.try_get::<_, Option<i32>>(1)?.unwrap_or_default())
+            } else {
+                None
+            };
+            let mut discrepancy = Discrepancy {
+                platform,
+                id: row.try_get(0)?,
+                actual,
+                expected: row.try_get(2)?,
+                repaired: false,
+            };
+
+            // users missing entirely can't be recreated without their names
+            if repair && present {
+                discrepancy.repaired = repair_one(&db, platform, &discrepancy.id).await?;
+            }
+
+            tracing::warn!(
+                platform = %platform,
+                id = discrepancy.id.as_str(),
+                actual = ?discrepancy.actual,
+                expected = discrepancy.expected,
+                repaired = discrepancy.repaired,
+                "points don't match ledger"
+            );
+            discrepancies.push(discrepancy);
+        }
+    }
+
+    Ok(discrepancies)
+}
+
+/// Recheck a user under lock in case their points changed since the audit, then fix them.
+/// False if there was nothing to fix anymore, or the ledger total doesn't fit
+async fn repair_one(db: &DbPool, platform: Platform, id: &str) -> error::Result<bool> {
+    let (lock_sql, repair_sql) = match platform {
+        Platform::YOUTUBE => (
+            include_str!("sql/select/youtube_id_lock.sql"),
+            include_str!("sql/update/repair_points_youtube.sql"),
+        ),
+        Platform::DISCORD => (
+            include_str!("sql/select/discord_id_lock.sql"),
+            include_str!("sql/update/repair_points_discord.sql"),
+        ),
+        Platform::TWITCH => (
+            include_str!("sql/select/twitch_id_lock.sql"),
+            include_str!("sql/update/repair_points_twitch.sql"),
+        ),
+        _ => return Err(GiveError::InvalidPlatform.into()),
+    };
+
+    let mut client = db.get().await?;
+    let client = client.build_transaction().start().await?;
+
+    // mutations lock the row before writing to the ledger, so this sees all of theirs
+    let row = match client.query_opt(lock_sql, &[&id]).await? {
+        Some(row) => row,
+        None => return Ok(false),
+    };
+    let actual = row.try_get::<_, Option<i32>>(2)?.unwrap_or_default();
+    let expected: i64 = client
+        .query_one(
+            include_str!("sql/select/ledger_balance.sql"),
+            &[&platform.to_string().to_lowercase(), &id],
+        )
+        .await?
+        .try_get(0)?;
+
+    let expected = match i32::try_from(expected) {
+        Ok(expected) if expected != actual => expected,
+        _ => return Ok(false),
+    };
+
+    client.execute(repair_sql, &[&id, &expected]).await?;
+    client.commit().await?;
+
+    tracing::info!(platform = %platform, id, from = actual, to = expected, "repaired points");
+    Ok(true)
+}
@@ -1,20 +1,22 @@
-use crate::{error, msg::Platform};
-use bb8::Pool;
-use bb8_postgres::PostgresConnectionManager;
+use crate::{error, msg::Platform, DbPool};
 use std::sync::Arc;
-use tokio_postgres::{types::ToSql, NoTls};
+use tokio_postgres::types::ToSql;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) struct LinkOp {
     pub(crate) platform: Platform,
     pub(crate) discord_id: Arc<String>,
     pub(crate) platform_id: Arc<String>,
 }
 
-pub(crate) async fn op(
-    db: Pool<PostgresConnectionManager<NoTls>>,
-    args: LinkOp,
-) -> error::Result<()> {
+#[derive(Debug, Clone)]
+pub(crate) struct UnlinkOp {
+    pub(crate) platform: Platform,
+    /// Discord id if `platform` is Discord, platform id otherwise
+    pub(crate) id: Arc<String>,
+}
+
+pub(crate) async fn op(db: DbPool, args: LinkOp) -> error::Result<()> {
     let delete_sql = [
         include_str!("sql/delete/link_yt.sql"),
         include_str!("sql/delete/link_tw.sql"),
@@ -49,3 +51,29 @@ pub(crate) async fn op(
 
     Ok(())
 }
+
+/// Remove a user's links, returning the number of links removed
+pub(crate) async fn unlink(db: DbPool, args: UnlinkOp) -> error::Result<u64> {
+    let delete_sql: &[&str] = match args.platform {
+        // a discord user may be linked to both
+        Platform::DISCORD => &[
+            include_str!("sql/delete/link_yt.sql"),
+            include_str!("sql/delete/link_tw.sql"),
+        ],
+        Platform::YOUTUBE => &[include_str!("sql/delete/link_yt_id.sql")],
+        Platform::TWITCH => &[include_str!("sql/delete/link_tw_id.sql")],
+        _ => unreachable!(),
+    };
+
+    let mut client = db.get().await?;
+    let client = client.build_transaction().start().await?;
+
+    let mut removed = 0;
+    for sql in delete_sql {
+        removed += client.execute(*sql, &[&args.id.as_str()]).await?;
+    }
+
+    client.commit().await?;
+
+    Ok(removed)
+}
@@ -1,30 +1,120 @@
+pub mod backup;
+mod batch;
+pub(crate) mod config_audit;
 pub(crate) mod give;
 pub(crate) mod hours;
+pub(crate) mod ledger;
 pub(crate) mod link;
 pub(crate) mod modaction;
+pub(crate) mod notes;
+pub(crate) mod prediction;
+pub mod session;
+pub(crate) mod shop;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+pub mod tls;
+pub mod usage;
+pub(crate) mod users;
+pub(crate) mod voice;
 
-use self::{give::GiveOp, hours::HoursOp, link::LinkOp, modaction::ModActionDump};
+use self::{
+    backup::{LinkRecord, ModActionRecord, RestoreOp},
+    batch::{Increment, UpsertBatch},
+    config_audit::{AuditOp, ConfigAudit},
+    give::{BetOp, GiveOp},
+    hours::HoursOp,
+    ledger::Discrepancy,
+    link::{LinkOp, UnlinkOp},
+    modaction::ModActionDump,
+    notes::{ModNote, NoteOp},
+    prediction::{OutcomeTotal, Settlement, StakeOp},
+    session::SessionTotals,
+    shop::{RedeemOp, Redemption},
+    usage::{UsageBatch, UsageRange, UsageStats},
+    users::{ImportOp, UserKey, UserRecord},
+    voice::VoiceBatch,
+};
 use crate::{
     cmds::ModAction,
-    error::{self, ChanSendError},
+    error::{self, ChanSendError, Error},
     msg::Platform,
     DbPool,
 };
-use std::sync::Arc;
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 use tokio::sync::{mpsc, oneshot};
+use tokio_postgres::error::SqlState;
 
 #[allow(dead_code)]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 
 pub(crate) enum Db {
+    /// Checks the database can be reached
+    Ping,
+    /// Batched with other increments, answered once they're written
     Upsert(Platform, Arc<String>, Arc<String>, i32),
+    UpsertBatch(UpsertBatch),
+    /// Write the increments waiting to be batched now
+    FlushPoints,
     GetPoints(Platform, Arc<String>),
     SetPoints(Platform, Arc<String>, i32),
     Give(GiveOp),
+    /// In one transaction, none happen if any of them fails. Answered with each amount, in order
+    GiveAll(Vec<GiveOp>),
+    Bet(BetOp),
     ModAction(Platform, Arc<String>, ModAction, Arc<String>),
     Link(LinkOp),
+    Unlink(UnlinkOp),
     Hours(HoursOp),
     DumpModActions,
+    GetCounter(Arc<String>),
+    /// Discord ids with at least this many points, including linked accounts
+    DiscordPointsAbove(i32),
+    SetCounter(Arc<String>, i64),
+    /// Records a user as seen, true if they never were before
+    FirstSeen(Platform, Arc<String>),
+    Redeem(RedeemOp),
+    PendingRedemptions,
+    /// id, refund
+    ResolveRedemption(i64, bool),
+    /// Recently seen users whose names start with this, up to the limit
+    SearchUsers(Platform, Arc<String>, i64),
+    /// (id, name) of a user, on the primary so users who just chatted are found
+    FindUser(Platform, UserKey),
+    ImportUsers(ImportOp),
+    /// platform, after id, limit
+    ExportUsers(Platform, Arc<String>, i64),
+    /// Check points against the ledger, repairing them if true
+    AuditPoints(bool),
+    ModNotes(NoteOp),
+    /// Everyone with mod notes on them
+    Watchlist,
+    RecordUsage(UsageBatch),
+    Usage(UsageRange),
+    /// Totals since the stream started
+    SessionTotals(SystemTime),
+    /// id, question, when betting closes
+    OpenPrediction(Arc<String>, Arc<String>, SystemTime),
+    /// Answered with the prediction's totals, including the stake
+    Stake(StakeOp),
+    PredictionTotals(Arc<String>),
+    /// Pay out the winning outcome, refunding everyone if None
+    SettlePrediction(Arc<String>, Option<i32>),
+    RecordConfigChange(AuditOp),
+    /// The latest config changes, up to the limit
+    ConfigAudit(i64),
+    /// Voice time and the points it earned, for Discord members
+    AddVoiceTime(VoiceBatch),
+    /// Seconds a Discord member's spent in voice
+    VoiceTime(Arc<String>),
+    /// Every linked account, for backups
+    ExportLinks,
+    /// Every mod action, for backups
+    ExportModActions,
+    /// Links and mod actions from a backup
+    RestoreRecords(RestoreOp),
 }
 
 impl Db {
@@ -32,14 +122,68 @@ impl Db {
     pub(crate) async fn exec(self, handle: &Handle) -> error::Result<Resp> {
         handle.task(self).await
     }
+
+    /// Ops that only read, and don't mind lagging a little behind, can go to the replica.
+    /// Hours stays on the primary since it updates watchtime as it reads it
+    fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            Self::GetPoints(..)
+                | Self::DumpModActions
+                | Self::GetCounter(_)
+                | Self::DiscordPointsAbove(_)
+                | Self::PendingRedemptions
+                | Self::SearchUsers(..)
+                | Self::ExportUsers(..)
+                | Self::AuditPoints(false)
+                | Self::ModNotes(NoteOp::List(..))
+                | Self::Watchlist
+                | Self::Usage(_)
+                | Self::SessionTotals(_)
+                | Self::ConfigAudit(_)
+                | Self::VoiceTime(_)
+                | Self::ExportLinks
+                | Self::ExportModActions
+        )
+    }
 }
 
 pub enum Resp {
     Ok,
     GetPoints([(Platform, Option<i32>); 3]),
     Give(i32),
+    GiveAll(Vec<i32>),
+    /// wager, winnings
+    Bet(i32, i32),
     Hours(i32),
+    Unlink(u64),
     ModActionDump(ModActionDump),
+    Counter(Option<i64>),
+    Ids(Vec<String>),
+    FirstSeen(bool),
+    /// redemption id
+    Redeemed(i64),
+    Redemptions(Vec<Redemption>),
+    /// (id, name)
+    Users(Vec<(String, String)>),
+    /// (id, name)
+    User(Option<(String, String)>),
+    /// rows written
+    Imported(u64),
+    UserRecords(Vec<UserRecord>),
+    Discrepancies(Vec<Discrepancy>),
+    Notes(Vec<ModNote>),
+    /// (platform, id)
+    Watchlist(Vec<(Platform, String)>),
+    Usage(UsageStats),
+    SessionTotals(SessionTotals),
+    PredictionTotals(Vec<OutcomeTotal>),
+    Settled(Settlement),
+    ConfigAudit(Vec<ConfigAudit>),
+    /// seconds
+    VoiceTime(i32),
+    Links(Vec<LinkRecord>),
+    ModActionRecords(Vec<ModActionRecord>),
 }
 
 // hide potentially massive inner value from tracing
@@ -49,7 +193,10 @@ impl std::fmt::Debug for Resp {
             Self::Ok => write!(f, "Ok"),
             Self::GetPoints(arg0) => f.debug_tuple("GetPoints").field(arg0).finish(),
             Self::Give(arg0) => f.debug_tuple("Give").field(arg0).finish(),
+            Self::GiveAll(arg0) => f.debug_tuple("GiveAll").field(arg0).finish(),
+            Self::Bet(arg0, arg1) => f.debug_tuple("Bet").field(arg0).field(arg1).finish(),
             Self::Hours(arg0) => f.debug_tuple("Hours").field(arg0).finish(),
+            Self::Unlink(arg0) => f.debug_tuple("Unlink").field(arg0).finish(),
             Self::ModActionDump(arg0) => {
                 let mut _f = f.debug_tuple("ModActionDump");
                 for (plat, rows) in arg0 {
@@ -57,15 +204,79 @@ impl std::fmt::Debug for Resp {
                 }
                 _f.finish()
             }
+            Self::Counter(arg0) => f.debug_tuple("Counter").field(arg0).finish(),
+            Self::Ids(arg0) => f.debug_tuple("Ids").field(&arg0.len()).finish(),
+            Self::FirstSeen(arg0) => f.debug_tuple("FirstSeen").field(arg0).finish(),
+            Self::Redeemed(arg0) => f.debug_tuple("Redeemed").field(arg0).finish(),
+            Self::Redemptions(arg0) => f.debug_tuple("Redemptions").field(&arg0.len()).finish(),
+            Self::Users(arg0) => f.debug_tuple("Users").field(&arg0.len()).finish(),
+            Self::User(arg0) => f.debug_tuple("User").field(arg0).finish(),
+            Self::Imported(arg0) => f.debug_tuple("Imported").field(arg0).finish(),
+            Self::UserRecords(arg0) => f.debug_tuple("UserRecords").field(&arg0.len()).finish(),
+            Self::Discrepancies(arg0) => f.debug_tuple("Discrepancies").field(&arg0.len()).finish(),
+            Self::Notes(arg0) => f.debug_tuple("Notes").field(&arg0.len()).finish(),
+            Self::Watchlist(arg0) => f.debug_tuple("Watchlist").field(&arg0.len()).finish(),
+            Self::Usage(arg0) => f.debug_tuple("Usage").field(&arg0.range).finish(),
+            Self::SessionTotals(arg0) => f.debug_tuple("SessionTotals").field(arg0).finish(),
+            Self::PredictionTotals(arg0) => f.debug_tuple("PredictionTotals").field(arg0).finish(),
+            Self::Settled(arg0) => f.debug_tuple("Settled").field(arg0).finish(),
+            Self::ConfigAudit(arg0) => f.debug_tuple("ConfigAudit").field(&arg0.len()).finish(),
+            Self::VoiceTime(arg0) => f.debug_tuple("VoiceTime").field(arg0).finish(),
+            Self::Links(arg0) => f.debug_tuple("Links").field(&arg0.len()).finish(),
+            Self::ModActionRecords(arg0) => f
+                .debug_tuple("ModActionRecords")
+                .field(&arg0.len())
+                .finish(),
         }
     }
 }
 
 type TaskChanPair = (Db, oneshot::Sender<error::Result<Resp>>);
 
+const RETRY_ATTEMPTS: u32 = 6;
+const RETRY_DELAY: Duration = Duration::from_millis(250);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(4);
+
+/// Whether the error is from losing the connection rather than the query itself,
+/// so the query never ran or got rolled back
+fn is_transient(e: &Error) -> bool {
+    match e {
+        // couldn't get a connection from the pool
+        Error::Bb8(_) => true,
+        Error::Postgres(e) => {
+            e.is_closed()
+                || e.code().map_or_else(
+                    // no code means it never got to the server
+                    || std::error::Error::source(e).is_some_and(|s| s.is::<std::io::Error>()),
+                    |code| {
+                        code.code().starts_with("08")
+                            || [
+                                SqlState::ADMIN_SHUTDOWN,
+                                SqlState::CRASH_SHUTDOWN,
+                                SqlState::CANNOT_CONNECT_NOW,
+                                SqlState::READ_ONLY_SQL_TRANSACTION,
+                            ]
+                            .contains(code)
+                    },
+                )
+        }
+        _ => false,
+    }
+}
+
+/// Whether the task is safe to run again after failing with `e`. A write that lost its connection
+/// may have committed before it did, so it's only retried if it never got one from the pool
+fn is_retryable(task: &Db, e: &Error) -> bool {
+    match e {
+        Error::Bb8(_) => true,
+        e => task.is_read_only() && is_transient(e),
+    }
+}
+
 struct Actor {
     rx: mpsc::Receiver<TaskChanPair>,
     db: DbPool,
+    read: Option<DbPool>,
 }
 
 /// Database operations
@@ -75,8 +286,18 @@ impl Actor {
     //     Self { rx, db }
     // }
 
-    async fn handle_task(db: DbPool, (task, tx): TaskChanPair) {
-        let resp = Self::_handle_task(db, task).await;
+    async fn handle_task(db: DbPool, read: Option<DbPool>, (task, tx): TaskChanPair) {
+        let resp = match read.filter(|_| task.is_read_only()) {
+            // one go at the replica, the primary's retried as usual
+            Some(read) => match Self::_handle_task(read, task.clone()).await {
+                Err(e) => {
+                    tracing::warn!("replica failed, reading from the primary: {}", e);
+                    Self::retry_task(db, task).await
+                }
+                resp => resp,
+            },
+            None => Self::retry_task(db, task).await,
+        };
         let res: error::Result<()> = tx.send(resp).map_err(|e| {
             ChanSendError {
                 msg: format!("{:?}", e),
@@ -88,8 +309,28 @@ impl Actor {
         }
     }
 
+    /// Retry with backoff while the database is unreachable, e.g. mid-failover, if it's safe to
+    async fn retry_task(db: DbPool, task: Db) -> error::Result<Resp> {
+        let mut delay = RETRY_DELAY;
+        for attempt in 1.. {
+            match Self::_handle_task(db.clone(), task.clone()).await {
+                Err(e) if attempt < RETRY_ATTEMPTS && is_retryable(&task, &e) => {
+                    tracing::warn!(attempt, ?delay, "retrying: {}", e);
+                    tokio::time::sleep(delay).await;
+                    delay = (delay * 2).min(RETRY_MAX_DELAY);
+                }
+                resp => return resp,
+            }
+        }
+        unreachable!()
+    }
+
     async fn _handle_task(db: DbPool, task: Db) -> error::Result<Resp> {
         match task {
+            Db::Ping => {
+                db.get().await?.simple_query("SELECT 1").await?;
+                Ok(Resp::Ok)
+            }
             Db::GetPoints(platform, id) => {
                 let sql = match platform {
                     Platform::YOUTUBE => include_str!("sql/select/points_youtube.sql"),
@@ -125,28 +366,36 @@ impl Actor {
                     Platform::TWITCH => include_str!("sql/update/set_points_twitch.sql"),
                     _ => unreachable!(),
                 };
-                let client = db.get().await?;
-                let _ = client.query_one(sql, &[&name.as_str(), &points]).await;
+                let mut client = db.get().await?;
+                let client = client.build_transaction().start().await?;
+                // only returns rows if points went up
+                let rows = client.query(sql, &[&name.as_str(), &points]).await?;
+                for row in &rows {
+                    let (id, delta): (&str, i32) = (row.try_get(0)?, row.try_get(1)?);
+                    ledger::record(&client, platform, id, delta, "set").await?;
+                }
+                client.commit().await?;
 
                 tracing::info!(to = points, "set points");
                 Ok(Resp::Ok)
             }
             Db::Upsert(platform, id, name, points) => {
-                let sql = match platform {
-                    Platform::YOUTUBE => include_str!("sql/upsert/youtube_id.sql"),
-                    Platform::DISCORD => include_str!("sql/upsert/discord_id.sql"),
-                    Platform::TWITCH => include_str!("sql/upsert/twitch_id.sql"),
-                    _ => unreachable!(),
-                };
-                let client = db.get().await?;
-                let _ = client
-                    .query_one(sql, &[&id.as_str(), &name.as_str(), &points])
-                    .await?;
-
-                tracing::info!(by = points, "incremented points");
-                Ok(Resp::Ok)
+                let batch = UpsertBatch(vec![Increment {
+                    platform,
+                    id,
+                    name,
+                    points,
+                }]);
+                batch::write(db, batch).await.map(|_| Resp::Ok)
             }
+            Db::UpsertBatch(batch) => batch::write(db, batch).await.map(|_| Resp::Ok),
+            // the batching in `run` answers it
+            Db::FlushPoints => Ok(Resp::Ok),
             Db::Give(args) => give::op(db, args).await.map(Resp::Give),
+            Db::GiveAll(ops) => give::op_all(db, ops).await.map(Resp::GiveAll),
+            Db::Bet(args) => give::bet(db, args)
+                .await
+                .map(|(amount, winnings)| Resp::Bet(amount, winnings)),
             Db::ModAction(platform, id, action, reason) => {
                 let sql = match platform {
                     Platform::YOUTUBE => include_str!("sql/insert/modaction_youtube.sql"),
@@ -168,16 +417,110 @@ impl Actor {
                 Ok(Resp::Ok)
             }
             Db::Link(args) => link::op(db, args).await.map(|_| Resp::Ok),
+            Db::Unlink(args) => link::unlink(db, args).await.map(Resp::Unlink),
             Db::Hours(args) => hours::op(db, args).await.map(Resp::Hours),
             Db::DumpModActions => modaction::op(db).await.map(Resp::ModActionDump),
+            Db::GetCounter(name) => {
+                let client = db.get().await?;
+                let row = client
+                    .query_opt(include_str!("sql/select/counter.sql"), &[&name.as_str()])
+                    .await?;
+                Ok(Resp::Counter(row.map(|row| row.get::<_, i64>(0))))
+            }
+            Db::DiscordPointsAbove(points) => {
+                let client = db.get().await?;
+                let rows = client
+                    .query(
+                        include_str!("sql/select/discord_points_above.sql"),
+                        &[&points],
+                    )
+                    .await?;
+                Ok(Resp::Ids(rows.iter().map(|row| row.get(0)).collect()))
+            }
+            Db::SetCounter(name, count) => {
+                let client = db.get().await?;
+                client
+                    .query_one(
+                        include_str!("sql/upsert/counter.sql"),
+                        &[&name.as_str(), &count],
+                    )
+                    .await?;
+                Ok(Resp::Ok)
+            }
+            Db::FirstSeen(platform, id) => {
+                let client = db.get().await?;
+                // only returns a row if this is the first insert
+                let row = client
+                    .query_opt(
+                        include_str!("sql/insert/greeted.sql"),
+                        &[&platform.to_string().to_lowercase(), &id.as_str()],
+                    )
+                    .await?;
+                Ok(Resp::FirstSeen(row.is_some()))
+            }
+            Db::Redeem(args) => shop::redeem(db, args).await.map(Resp::Redeemed),
+            Db::PendingRedemptions => shop::pending(db).await.map(Resp::Redemptions),
+            Db::ResolveRedemption(id, refund) => {
+                shop::resolve(db, id, refund).await.map(|_| Resp::Ok)
+            }
+            Db::SearchUsers(platform, prefix, limit) => {
+                let sql = match platform {
+                    Platform::YOUTUBE => include_str!("sql/select/users_youtube.sql"),
+                    Platform::DISCORD => include_str!("sql/select/users_discord.sql"),
+                    Platform::TWITCH => include_str!("sql/select/users_twitch.sql"),
+                    _ => return Ok(Resp::Users(vec![])),
+                };
+                // match the prefix literally
+                let prefix = prefix
+                    .replace('\\', "\\\\")
+                    .replace('%', "\\%")
+                    .replace('_', "\\_");
+                let client = db.get().await?;
+                let rows = client.query(sql, &[&prefix, &limit]).await?;
+                rows.iter()
+                    .map(|row| Ok((row.try_get(0)?, row.try_get(1)?)))
+                    .collect::<error::Result<_>>()
+                    .map(Resp::Users)
+            }
+            Db::FindUser(platform, key) => users::find(db, platform, key).await.map(Resp::User),
+            Db::ImportUsers(args) => users::import(db, args).await.map(Resp::Imported),
+            Db::ExportUsers(platform, after, limit) => users::export(db, platform, after, limit)
+                .await
+                .map(Resp::UserRecords),
+            Db::AuditPoints(repair) => ledger::audit(db, repair).await.map(Resp::Discrepancies),
+            Db::ModNotes(args) => notes::op(db, args).await.map(Resp::Notes),
+            Db::Watchlist => notes::watchlist(db).await.map(Resp::Watchlist),
+            Db::RecordUsage(batch) => usage::record(db, batch).await.map(|_| Resp::Ok),
+            Db::Usage(range) => usage::stats(db, range).await.map(Resp::Usage),
+            Db::SessionTotals(since) => session::totals(db, since).await.map(Resp::SessionTotals),
+            Db::OpenPrediction(id, question, closes) => prediction::open(db, id, question, closes)
+                .await
+                .map(|_| Resp::Ok),
+            Db::Stake(args) => prediction::stake(db, args)
+                .await
+                .map(Resp::PredictionTotals),
+            Db::PredictionTotals(id) => {
+                prediction::totals(db, id).await.map(Resp::PredictionTotals)
+            }
+            Db::SettlePrediction(id, winner) => {
+                prediction::settle(db, id, winner).await.map(Resp::Settled)
+            }
+            Db::RecordConfigChange(args) => config_audit::record(db, args).await.map(|_| Resp::Ok),
+            Db::ConfigAudit(limit) => config_audit::list(db, limit).await.map(Resp::ConfigAudit),
+            Db::AddVoiceTime(batch) => voice::add(db, batch).await.map(|_| Resp::Ok),
+            Db::VoiceTime(id) => voice::get(db, id).await.map(Resp::VoiceTime),
+            Db::ExportLinks => backup::links(db).await.map(Resp::Links),
+            Db::ExportModActions => backup::mod_actions(db).await.map(Resp::ModActionRecords),
+            Db::RestoreRecords(args) => backup::restore(db, args).await.map(Resp::Imported),
         }
     }
 
-    async fn run(mut self) {
-        while let Some(msg) = self.rx.recv().await {
-            let db = self.db.clone();
-            tokio::spawn(Self::handle_task(db, msg));
-        }
+    async fn run(self) {
+        let (db, read) = (self.db, self.read);
+        batch::run(self.rx, |msg| {
+            tokio::spawn(Self::handle_task(db.clone(), read.clone(), msg));
+        })
+        .await;
     }
 }
 
@@ -193,13 +536,35 @@ impl std::fmt::Debug for Handle {
 }
 
 impl Handle {
-    pub fn new(db: DbPool) -> Self {
+    /// Read-only ops go to `read` if there is one
+    pub fn new(db: DbPool, read: Option<DbPool>) -> Self {
+        let (tx, rx) = mpsc::channel(32);
+        tokio::spawn(Actor { db, read, rx }.run());
+
+        Self { tx }
+    }
+
+    /// Everything goes to the SQLite file at `path`, created if it doesn't exist
+    #[cfg(feature = "sqlite")]
+    pub fn sqlite(path: &std::path::Path) -> error::Result<Self> {
         let (tx, rx) = mpsc::channel(32);
-        tokio::spawn(Actor { db, rx }.run());
+        tokio::spawn(sqlite::Actor::open(path, rx)?.run());
 
+        Ok(Self { tx })
+    }
+
+    /// Not connected to anything, every op fails. For tests that never reach the db
+    #[cfg(test)]
+    pub(crate) fn detached() -> Self {
+        let (tx, _) = mpsc::channel(1);
         Self { tx }
     }
 
+    /// Write any point increments still waiting to be batched, e.g. before exiting
+    pub async fn flush_points(&self) -> error::Result<()> {
+        Db::FlushPoints.exec(self).await.map(|_| ())
+    }
+
     async fn task(&self, task: Db) -> error::Result<Resp> {
         let (tx, rx) = oneshot::channel::<error::Result<Resp>>();
         self.tx.send((task, tx)).await?;
@@ -1,16 +1,13 @@
+pub(crate) use crate::proto::db::{ModActionDump, ModActionRow};
 use crate::{
     error::{self, Error},
     msg::Platform,
+    DbPool,
 };
-use bb8::Pool;
-use bb8_postgres::PostgresConnectionManager;
 use std::time::{SystemTime, UNIX_EPOCH};
-use tokio_postgres::{NoTls, Row};
+use tokio_postgres::Row;
 
-pub(crate) type ModActionRow = (Option<String>, String, String, String, u64);
-pub(crate) type ModActionDump = Vec<(Platform, Vec<ModActionRow>)>;
-
-pub(crate) async fn op(db: Pool<PostgresConnectionManager<NoTls>>) -> error::Result<ModActionDump> {
+pub(crate) async fn op(db: DbPool) -> error::Result<ModActionDump> {
     let client = db.get().await.unwrap();
 
     let (r1, r2, r3) = futures_util::future::join3(
@@ -0,0 +1,123 @@
+pub use crate::proto::db::ModNote;
+use crate::{error, msg::Platform, DbPool};
+use std::{str::FromStr, sync::Arc};
+use tokio_postgres::Row;
+
+#[derive(Debug, Clone)]
+pub(crate) enum NoteTarget {
+    /// Display name, the most recently seen user with it is picked
+    Name(Arc<String>),
+    /// id, name
+    User(Arc<String>, Arc<String>),
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum NoteOp {
+    /// Nothing is added if the user hasn't been seen
+    Add {
+        platform: Platform,
+        target: NoteTarget,
+        note: String,
+        author: Arc<String>,
+    },
+    List(Platform, NoteTarget),
+    Remove(i64),
+}
+
+impl TryFrom<&Row> for ModNote {
+    type Error = error::Error;
+
+    fn try_from(row: &Row) -> Result<Self, Self::Error> {
+        Ok(ModNote {
+            id: row.try_get(0)?,
+            platform: Platform::from_str(row.try_get(1)?)?,
+            user_id: row.try_get(2)?,
+            user_name: row.try_get(3)?,
+            note: row.try_get(4)?,
+            author: row.try_get(5)?,
+            created: row.try_get(6)?,
+        })
+    }
+}
+
+/// Notes added, listed or removed
+pub(crate) async fn op(db: DbPool, args: NoteOp) -> error::Result<Vec<ModNote>> {
+    let client = db.get().await?;
+
+    let rows = match args {
+        NoteOp::Add {
+            platform,
+            target,
+            note,
+            author,
+        } => {
+            let (id, name) = match resolve(&client, platform, target).await? {
+                Some(user) => user,
+                None => return Ok(vec![]),
+            };
+            client
+                .query(
+                    include_str!("sql/insert/mod_note.sql"),
+                    &[
+                        &platform.to_string().to_lowercase(),
+                        &id,
+                        &name,
+                        &note,
+                        &author.as_str(),
+                    ],
+                )
+                .await?
+        }
+        NoteOp::List(platform, target) => {
+            let (id, _) = match resolve(&client, platform, target).await? {
+                Some(user) => user,
+                None => return Ok(vec![]),
+            };
+            client
+                .query(
+                    include_str!("sql/select/mod_notes.sql"),
+                    &[&platform.to_string().to_lowercase(), &id],
+                )
+                .await?
+        }
+        NoteOp::Remove(id) => {
+            client
+                .query(include_str!("sql/delete/mod_note.sql"), &[&id])
+                .await?
+        }
+    };
+
+    rows.iter().map(ModNote::try_from).collect()
+}
+
+/// (id, name) of the user, None if they haven't been seen
+async fn resolve(
+    client: &tokio_postgres::Client,
+    platform: Platform,
+    target: NoteTarget,
+) -> error::Result<Option<(String, String)>> {
+    let name = match target {
+        NoteTarget::User(id, name) => return Ok(Some((id.to_string(), name.to_string()))),
+        NoteTarget::Name(name) => name,
+    };
+    let sql = match platform {
+        Platform::YOUTUBE => include_str!("sql/select/id_by_name_youtube.sql"),
+        Platform::DISCORD => include_str!("sql/select/id_by_name_discord.sql"),
+        Platform::TWITCH => include_str!("sql/select/id_by_name_twitch.sql"),
+        _ => return Ok(None),
+    };
+    let row = client.query_opt(sql, &[&name.as_str()]).await?;
+    row.map(|row| Ok((row.try_get(0)?, row.try_get(1)?)))
+        .transpose()
+}
+
+/// Everyone with notes on them, as (platform, id)
+pub(crate) async fn watchlist(db: DbPool) -> error::Result<Vec<(Platform, String)>> {
+    let client = db.get().await?;
+    let rows = client
+        .query(include_str!("sql/select/watchlist.sql"), &[])
+        .await?;
+    rows.iter()
+        .map(|row| Ok((Platform::from_str(row.try_get(0)?)?, row.try_get(1)?)))
+        .collect()
+}
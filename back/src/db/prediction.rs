@@ -0,0 +1,234 @@
+use super::give::{handle_deduct_id, handle_deposit_id};
+use crate::{error, msg::Platform, DbPool};
+use serde_derive::{Deserialize, Serialize};
+use std::{fmt::Display, str::FromStr, sync::Arc, time::SystemTime};
+
+#[derive(Debug, Clone)]
+pub(crate) struct StakeOp {
+    pub(crate) prediction: Arc<String>,
+    pub(crate) platform: Platform,
+    pub(crate) id: Arc<String>,
+    pub(crate) name: Arc<String>,
+    /// index into the prediction's outcomes
+    pub(crate) outcome: i32,
+    pub(crate) amount: i32,
+}
+
+#[derive(Debug)]
+pub enum PredictionError {
+    /// Resolved, cancelled, or past its betting window
+    Closed,
+    /// Users stick with the first outcome they bet on
+    OtherOutcome(i32),
+}
+
+impl Display for PredictionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("{:?}", self))
+    }
+}
+
+/// (outcome, points staked, bettors)
+pub(crate) type OutcomeTotal = (i32, i64, i64);
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Settlement {
+    /// Everything staked
+    pub pool: i64,
+    /// Users paid out, or refunded
+    pub users: u64,
+    /// Stakes were given back instead, since it was cancelled or nobody picked the winner
+    pub refunded: bool,
+}
+
+/// (platform, id, outcome, staked)
+pub(super) type UserStake = (Platform, String, i32, i64);
+
+/// What each user gets back: winners split the pool by how much they staked,
+/// and everyone gets their stake back if there's no winner or nobody picked it
+pub(super) fn payouts(
+    stakes: Vec<UserStake>,
+    winner: Option<i32>,
+) -> (Settlement, Vec<(Platform, String, i32)>) {
+    let pool: i64 = stakes.iter().map(|(_, _, _, staked)| staked).sum();
+    let winning: i64 = stakes
+        .iter()
+        .filter(|(_, _, outcome, _)| Some(*outcome) == winner)
+        .map(|(_, _, _, staked)| staked)
+        .sum();
+    let refunded = winning == 0;
+
+    let payouts: Vec<_> = stakes
+        .into_iter()
+        .filter_map(|(platform, id, outcome, staked)| {
+            let amount = if refunded {
+                staked
+            } else if Some(outcome) == winner {
+                // rounded down, the rest is lost to the house
+                (staked as i128 * pool as i128 / winning as i128) as i64
+            } else {
+                return None;
+            };
+            Some((platform, id, amount.min(i32::MAX as i64) as i32))
+        })
+        .filter(|(_, _, amount)| *amount > 0)
+        .collect();
+
+    let settlement = Settlement {
+        pool,
+        users: payouts.len() as u64,
+        refunded,
+    };
+    (settlement, payouts)
+}
+
+pub(crate) async fn open(
+    db: DbPool,
+    id: Arc<String>,
+    question: Arc<String>,
+    closes: SystemTime,
+) -> error::Result<()> {
+    let client = db.get().await?;
+    client
+        .execute(
+            include_str!("sql/insert/prediction.sql"),
+            &[&id.as_str(), &question.as_str(), &closes],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Deduct the stake and record it in one go, returning the totals with it
+pub(crate) async fn stake(db: DbPool, args: StakeOp) -> error::Result<Vec<OutcomeTotal>> {
+    let mut client = db.get().await?;
+    let client = client.build_transaction().start().await?;
+
+    // held until commit, so stakes can't land after the prediction's settled
+    let row = client
+        .query_opt(
+            include_str!("sql/select/prediction_lock.sql"),
+            &[&args.prediction.as_str()],
+        )
+        .await?;
+    match row {
+        Some(row) if row.try_get(0)? && row.try_get(1)? => {}
+        _ => return Err(PredictionError::Closed.into()),
+    }
+
+    let platform = args.platform.to_string().to_lowercase();
+    let row = client
+        .query_opt(
+            include_str!("sql/select/prediction_outcome.sql"),
+            &[&args.prediction.as_str(), &platform, &args.id.as_str()],
+        )
+        .await?;
+    if let Some(row) = row {
+        let outcome: i32 = row.try_get(0)?;
+        if outcome != args.outcome {
+            return Err(PredictionError::OtherOutcome(outcome).into());
+        }
+    }
+
+    let client =
+        handle_deduct_id(client, args.platform, &*args.id, args.amount, "prediction").await?;
+    client
+        .execute(
+            include_str!("sql/insert/prediction_stake.sql"),
+            &[
+                &args.prediction.as_str(),
+                &platform,
+                &args.id.as_str(),
+                &args.name.as_str(),
+                &args.outcome,
+                &args.amount,
+            ],
+        )
+        .await?;
+    let totals = client
+        .query(
+            include_str!("sql/select/prediction_totals.sql"),
+            &[&args.prediction.as_str()],
+        )
+        .await?
+        .iter()
+        .map(|row| Ok((row.try_get(0)?, row.try_get(1)?, row.try_get(2)?)))
+        .collect::<error::Result<_>>()?;
+
+    client.commit().await?;
+    Ok(totals)
+}
+
+pub(crate) async fn totals(
+    db: DbPool,
+    prediction: Arc<String>,
+) -> error::Result<Vec<OutcomeTotal>> {
+    let client = db.get().await?;
+    client
+        .query(
+            include_str!("sql/select/prediction_totals.sql"),
+            &[&prediction.as_str()],
+        )
+        .await?
+        .iter()
+        .map(|row| Ok((row.try_get(0)?, row.try_get(1)?, row.try_get(2)?)))
+        .collect()
+}
+
+/// Pay out the winners, or refund everyone if there's no winner
+pub(crate) async fn settle(
+    db: DbPool,
+    prediction: Arc<String>,
+    winner: Option<i32>,
+) -> error::Result<Settlement> {
+    let mut client = db.get().await?;
+    let client = client.build_transaction().start().await?;
+
+    let row = client
+        .query_opt(
+            include_str!("sql/select/prediction_lock.sql"),
+            &[&prediction.as_str()],
+        )
+        .await?;
+    match row {
+        Some(row) if row.try_get(0)? => {}
+        _ => return Err(PredictionError::Closed.into()),
+    }
+
+    let stakes = client
+        .query(
+            include_str!("sql/select/prediction_stakes.sql"),
+            &[&prediction.as_str()],
+        )
+        .await?
+        .iter()
+        .map(|row| {
+            Ok((
+                Platform::from_str(row.try_get(0)?)?,
+                row.try_get(1)?,
+                row.try_get(2)?,
+                row.try_get(3)?,
+            ))
+        })
+        .collect::<error::Result<Vec<UserStake>>>()?;
+
+    let (settlement, payouts) = payouts(stakes, winner);
+    let (status, reason) = if settlement.refunded {
+        ("refunded", "refund")
+    } else {
+        ("resolved", "prediction")
+    };
+    client
+        .execute(
+            include_str!("sql/update/settle_prediction.sql"),
+            &[&prediction.as_str(), &status, &winner],
+        )
+        .await?;
+
+    let mut client = client;
+    for (platform, id, amount) in payouts {
+        client = handle_deposit_id(client, platform, id, amount, reason).await?;
+    }
+
+    client.commit().await?;
+    Ok(settlement)
+}
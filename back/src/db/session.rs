@@ -0,0 +1,51 @@
+use super::usage::USAGE_TOP;
+pub use crate::proto::db::SessionTotals;
+use crate::{error, msg::Platform, DbPool};
+use std::{str::FromStr, time::SystemTime};
+
+pub(crate) async fn totals(db: DbPool, since: SystemTime) -> error::Result<SessionTotals> {
+    let client = db.get().await?;
+
+    let new_chatters = client
+        .query(
+            include_str!("sql/select/session_new_chatters.sql"),
+            &[&since],
+        )
+        .await?
+        .iter()
+        .map(|row| Ok((Platform::from_str(row.try_get(0)?)?, row.try_get(1)?)))
+        .collect::<error::Result<_>>()?;
+
+    let points_awarded = client
+        .query_one(include_str!("sql/select/session_points.sql"), &[&since])
+        .await?
+        .try_get(0)?;
+
+    let filters = client
+        .query(
+            include_str!("sql/select/session_filters.sql"),
+            &[&since, &USAGE_TOP],
+        )
+        .await?
+        .iter()
+        .map(|row| Ok((row.try_get(0)?, row.try_get(1)?)))
+        .collect::<error::Result<_>>()?;
+
+    // runs still waiting in the usage writer's batch are missed
+    let commands = client
+        .query(
+            include_str!("sql/select/usage_commands.sql"),
+            &[&since, &USAGE_TOP],
+        )
+        .await?
+        .iter()
+        .map(|row| Ok((row.try_get(0)?, row.try_get(1)?)))
+        .collect::<error::Result<_>>()?;
+
+    Ok(SessionTotals {
+        new_chatters,
+        points_awarded,
+        filters,
+        commands,
+    })
+}
@@ -0,0 +1,129 @@
+use super::give::{handle_deduct_id, handle_deposit_id};
+pub use crate::proto::db::Redemption;
+use crate::{error, msg::Platform, DbPool};
+use std::{fmt::Display, str::FromStr, sync::Arc};
+
+#[derive(Debug, Clone)]
+pub(crate) struct RedeemOp {
+    pub(crate) platform: Platform,
+    pub(crate) id: Arc<String>,
+    pub(crate) name: Arc<String>,
+    pub(crate) item: Arc<String>,
+    pub(crate) cost: i32,
+    /// None for unlimited
+    pub(crate) stock: Option<i64>,
+    /// Fulfilled right away, instead of waiting on a mod
+    pub(crate) completed: bool,
+}
+
+#[derive(Debug)]
+pub enum ShopError {
+    OutOfStock,
+    NotPending,
+}
+
+impl Display for ShopError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("{:?}", self))
+    }
+}
+
+/// Deduct the cost and record the redemption in one go, returning its id
+pub(crate) async fn redeem(db: DbPool, args: RedeemOp) -> error::Result<i64> {
+    let mut client = db.get().await?;
+    let client = client.build_transaction().start().await?;
+
+    if let Some(stock) = args.stock {
+        // serialize redemptions of the same item until commit
+        client
+            .execute(
+                "SELECT pg_advisory_xact_lock(hashtext($1))",
+                &[&args.item.as_str()],
+            )
+            .await?;
+        let redeemed: i64 = client
+            .query_one(
+                include_str!("sql/select/redemptions_stock.sql"),
+                &[&args.item.as_str()],
+            )
+            .await?
+            .get(0);
+        if redeemed >= stock {
+            return Err(ShopError::OutOfStock.into());
+        }
+    }
+
+    let client = handle_deduct_id(client, args.platform, &*args.id, args.cost, "redeem").await?;
+
+    let status = if args.completed {
+        "completed"
+    } else {
+        "pending"
+    };
+    let id: i64 = client
+        .query_one(
+            include_str!("sql/insert/redemption.sql"),
+            &[
+                &args.item.as_str(),
+                &args.platform.to_string().to_lowercase(),
+                &args.id.as_str(),
+                &args.name.as_str(),
+                &args.cost,
+                &status,
+            ],
+        )
+        .await?
+        .get(0);
+
+    client.commit().await?;
+    Ok(id)
+}
+
+pub(crate) async fn pending(db: DbPool) -> error::Result<Vec<Redemption>> {
+    let client = db.get().await?;
+    let rows = client
+        .query(include_str!("sql/select/redemptions_pending.sql"), &[])
+        .await?;
+
+    rows.iter()
+        .map(|row| {
+            Ok(Redemption {
+                id: row.try_get(0)?,
+                item: row.try_get(1)?,
+                platform: Platform::from_str(row.try_get(2)?)?,
+                user_id: row.try_get(3)?,
+                user_name: row.try_get(4)?,
+                cost: row.try_get(5)?,
+                status: row.try_get(6)?,
+                created: row.try_get(7)?,
+            })
+        })
+        .collect()
+}
+
+/// Mark a pending redemption completed, or refunded with its cost given back
+pub(crate) async fn resolve(db: DbPool, id: i64, refund: bool) -> error::Result<()> {
+    let mut client = db.get().await?;
+    let client = client.build_transaction().start().await?;
+
+    let status = if refund { "refunded" } else { "completed" };
+    let row = client
+        .query_opt(
+            include_str!("sql/update/resolve_redemption.sql"),
+            &[&id, &status],
+        )
+        .await?
+        .ok_or(ShopError::NotPending)?;
+
+    let client = if refund {
+        let platform = Platform::from_str(row.try_get(0)?)?;
+        let user_id: &str = row.try_get(1)?;
+        let cost: i32 = row.try_get(2)?;
+        handle_deposit_id(client, platform, user_id, cost, "refund").await?
+    } else {
+        client
+    };
+
+    client.commit().await?;
+    Ok(())
+}
@@ -0,0 +1 @@
+DELETE FRom link_tw WHERE id = $1;
\ No newline at end of file
@@ -0,0 +1 @@
+DELETE FRom link_yt WHERE id = $1;
\ No newline at end of file
@@ -0,0 +1,4 @@
+DELETE FROM mod_notes
+  WHERE id = $1
+  RETURNING id, platform, platform_id, disp_name, note, author,
+    EXTRACT(EPOCH FROM created)::bigint AS created;
\ No newline at end of file
@@ -0,0 +1,2 @@
+INSERT INTO command_usage (platform, platform_id, disp_name, command, used)
+SELECT * FROM unnest($1::varchar[], $2::varchar[], $3::varchar[], $4::varchar[], $5::timestamptz[]);
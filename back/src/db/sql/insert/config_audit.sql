@@ -0,0 +1,2 @@
+INSERT INTO config_audit (author, action, changes)
+  VALUES ($1, $2, $3);
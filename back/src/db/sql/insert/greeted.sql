@@ -0,0 +1,4 @@
+INSERT INTO greeted (platform, platform_id)
+  VALUES ($1, $2)
+  ON CONFLICT (platform, platform_id) DO NOTHING
+  RETURNING platform_id;
\ No newline at end of file
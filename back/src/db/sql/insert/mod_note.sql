@@ -0,0 +1,4 @@
+INSERT INTO mod_notes (platform, platform_id, disp_name, note, author)
+  VALUES ($1, $2, $3, $4, $5)
+  RETURNING id, platform, platform_id, disp_name, note, author,
+    EXTRACT(EPOCH FROM created)::bigint AS created;
@@ -0,0 +1,2 @@
+INSERT INTO point_ledger (platform, platform_id, delta, reason)
+  VALUES ($1, $2, $3, $4);
\ No newline at end of file
@@ -0,0 +1,3 @@
+INSERT INTO point_ledger (platform, platform_id, delta, reason)
+SELECT $1::varchar, platform_id, delta, 'upsert' FROM unnest($2::varchar[], $3::int[]) AS t (platform_id, delta)
+WHERE delta <> 0;
\ No newline at end of file
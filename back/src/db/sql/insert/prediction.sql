@@ -0,0 +1,2 @@
+INSERT INTO predictions (id, question, closes)
+  VALUES ($1, $2, $3);
\ No newline at end of file
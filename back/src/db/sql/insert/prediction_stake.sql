@@ -0,0 +1,2 @@
+INSERT INTO prediction_stakes (prediction, platform, platform_id, disp_name, outcome, amount)
+  VALUES ($1, $2, $3, $4, $5, $6);
\ No newline at end of file
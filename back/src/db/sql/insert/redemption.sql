@@ -0,0 +1,3 @@
+INSERT INTO redemptions (item, platform, platform_id, disp_name, cost, status)
+  VALUES ($1, $2, $3, $4, $5, $6)
+  RETURNING id;
\ No newline at end of file
@@ -0,0 +1,5 @@
+INSERT INTO modaction_twitch (platform_id, action, reason, at)
+  SELECT $1::varchar, $2::varchar, $3::varchar, $4::timestamptz
+  WHERE NOT EXISTS (
+    SELECT 1 FROM modaction_twitch WHERE platform_id = $1 AND action = $2 AND at = $4
+  );
\ No newline at end of file
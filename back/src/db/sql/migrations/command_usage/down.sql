@@ -0,0 +1 @@
+DROP TABLE command_usage;
\ No newline at end of file
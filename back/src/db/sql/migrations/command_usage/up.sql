@@ -0,0 +1,18 @@
+CREATE TABLE public.command_usage
+(
+    id bigserial NOT NULL,
+    platform character varying NOT NULL,
+    platform_id character varying NOT NULL,
+    disp_name character varying,
+    command character varying NOT NULL,
+    used timestamp with time zone NOT NULL DEFAULT now(),
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX command_usage_used ON public.command_usage (used);
+
+ALTER TABLE IF EXISTS public.command_usage
+    OWNER to aussiebot;
+
+GRANT ALL ON TABLE public.command_usage TO aussiebot;
+GRANT ALL ON SEQUENCE public.command_usage_id_seq TO aussiebot;
@@ -0,0 +1 @@
+DROP TABLE config_audit;
\ No newline at end of file
@@ -0,0 +1,15 @@
+CREATE TABLE public.config_audit
+(
+    id bigserial NOT NULL,
+    author character varying NOT NULL,
+    action character varying NOT NULL,
+    changes character varying NOT NULL,
+    created timestamp with time zone DEFAULT now(),
+    PRIMARY KEY (id)
+);
+
+ALTER TABLE IF EXISTS public.config_audit
+    OWNER to aussiebot;
+
+GRANT ALL ON TABLE public.config_audit TO aussiebot;
+GRANT ALL ON SEQUENCE public.config_audit_id_seq TO aussiebot;
@@ -0,0 +1 @@
+DROP TABLE counter;
\ No newline at end of file
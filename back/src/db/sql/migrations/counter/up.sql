@@ -0,0 +1,12 @@
+CREATE TABLE public.counter
+(
+    name character varying NOT NULL,
+    count bigint NOT NULL DEFAULT 0,
+    updated timestamp with time zone DEFAULT now(),
+    PRIMARY KEY (name)
+);
+
+ALTER TABLE IF EXISTS public.counter
+    OWNER to aussiebot;
+
+GRANT ALL ON TABLE public.counter TO aussiebot;
\ No newline at end of file
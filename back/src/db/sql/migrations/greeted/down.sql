@@ -0,0 +1 @@
+DROP TABLE greeted;
\ No newline at end of file
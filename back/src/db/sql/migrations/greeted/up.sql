@@ -0,0 +1,20 @@
+CREATE TABLE public.greeted
+(
+    platform character varying NOT NULL,
+    platform_id character varying NOT NULL,
+    first_seen timestamp with time zone DEFAULT now(),
+    PRIMARY KEY (platform, platform_id)
+);
+
+ALTER TABLE IF EXISTS public.greeted
+    OWNER to aussiebot;
+
+GRANT ALL ON TABLE public.greeted TO aussiebot;
+
+-- existing users have been seen already
+INSERT INTO public.greeted (platform, platform_id)
+    SELECT 'youtube', platform_id FROM public.youtube
+    UNION ALL
+    SELECT 'discord', platform_id FROM public.discord
+    UNION ALL
+    SELECT 'twitch', platform_id FROM public.twitch;
\ No newline at end of file
@@ -0,0 +1 @@
+DROP TABLE mod_notes;
\ No newline at end of file
@@ -0,0 +1,19 @@
+CREATE TABLE public.mod_notes
+(
+    id bigserial NOT NULL,
+    platform character varying NOT NULL,
+    platform_id character varying NOT NULL,
+    disp_name character varying,
+    note character varying NOT NULL,
+    author character varying NOT NULL,
+    created timestamp with time zone DEFAULT now(),
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX mod_notes_user ON public.mod_notes (platform, platform_id);
+
+ALTER TABLE IF EXISTS public.mod_notes
+    OWNER to aussiebot;
+
+GRANT ALL ON TABLE public.mod_notes TO aussiebot;
+GRANT ALL ON SEQUENCE public.mod_notes_id_seq TO aussiebot;
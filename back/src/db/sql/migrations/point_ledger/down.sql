@@ -0,0 +1 @@
+DROP TABLE point_ledger;
\ No newline at end of file
@@ -0,0 +1,29 @@
+CREATE TABLE public.point_ledger
+(
+    id bigserial NOT NULL,
+    platform character varying NOT NULL,
+    platform_id character varying NOT NULL,
+    delta integer NOT NULL,
+    reason character varying NOT NULL,
+    at timestamp with time zone DEFAULT now(),
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX point_ledger_user ON public.point_ledger (platform, platform_id);
+
+ALTER TABLE IF EXISTS public.point_ledger
+    OWNER to aussiebot;
+
+GRANT ALL ON TABLE public.point_ledger TO aussiebot;
+GRANT ALL ON SEQUENCE public.point_ledger_id_seq TO aussiebot;
+
+-- existing balances are where the ledger starts from
+INSERT INTO public.point_ledger (platform, platform_id, delta, reason)
+    SELECT 'youtube', platform_id, youtube_points, 'opening' FROM public.youtube
+        WHERE COALESCE(youtube_points, 0) <> 0
+    UNION ALL
+    SELECT 'discord', platform_id, discord_points, 'opening' FROM public.discord
+        WHERE COALESCE(discord_points, 0) <> 0
+    UNION ALL
+    SELECT 'twitch', platform_id, twitch_points, 'opening' FROM public.twitch
+        WHERE COALESCE(twitch_points, 0) <> 0;
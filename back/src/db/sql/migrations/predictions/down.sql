@@ -0,0 +1,2 @@
+DROP TABLE prediction_stakes;
+DROP TABLE predictions;
\ No newline at end of file
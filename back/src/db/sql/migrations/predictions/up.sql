@@ -0,0 +1,35 @@
+CREATE TABLE public.predictions
+(
+    id character varying NOT NULL,
+    question character varying NOT NULL,
+    status character varying NOT NULL DEFAULT 'open',
+    winner integer,
+    created timestamp with time zone DEFAULT now(),
+    closes timestamp with time zone NOT NULL,
+    resolved timestamp with time zone,
+    PRIMARY KEY (id)
+);
+
+CREATE TABLE public.prediction_stakes
+(
+    id bigserial NOT NULL,
+    prediction character varying NOT NULL REFERENCES public.predictions (id),
+    platform character varying NOT NULL,
+    platform_id character varying NOT NULL,
+    disp_name character varying,
+    outcome integer NOT NULL,
+    amount integer NOT NULL,
+    created timestamp with time zone DEFAULT now(),
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX prediction_stakes_prediction ON public.prediction_stakes (prediction);
+
+ALTER TABLE IF EXISTS public.predictions
+    OWNER to aussiebot;
+ALTER TABLE IF EXISTS public.prediction_stakes
+    OWNER to aussiebot;
+
+GRANT ALL ON TABLE public.predictions TO aussiebot;
+GRANT ALL ON TABLE public.prediction_stakes TO aussiebot;
+GRANT ALL ON SEQUENCE public.prediction_stakes_id_seq TO aussiebot;
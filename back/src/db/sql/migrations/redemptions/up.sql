@@ -0,0 +1,21 @@
+CREATE TABLE public.redemptions
+(
+    id bigserial NOT NULL,
+    item character varying NOT NULL,
+    platform character varying NOT NULL,
+    platform_id character varying NOT NULL,
+    disp_name character varying,
+    cost integer NOT NULL,
+    status character varying NOT NULL DEFAULT 'pending',
+    created timestamp with time zone DEFAULT now(),
+    resolved timestamp with time zone,
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX redemptions_item_status ON public.redemptions (item, status);
+
+ALTER TABLE IF EXISTS public.redemptions
+    OWNER to aussiebot;
+
+GRANT ALL ON TABLE public.redemptions TO aussiebot;
+GRANT ALL ON SEQUENCE public.redemptions_id_seq TO aussiebot;
\ No newline at end of file
@@ -0,0 +1 @@
+DROP TABLE voice_time;
\ No newline at end of file
@@ -0,0 +1,12 @@
+CREATE TABLE public.voice_time
+(
+    platform_id character varying NOT NULL,
+    seconds integer NOT NULL DEFAULT 0,
+    updated timestamp with time zone DEFAULT now(),
+    PRIMARY KEY (platform_id)
+);
+
+ALTER TABLE IF EXISTS public.voice_time
+    OWNER to aussiebot;
+
+GRANT ALL ON TABLE public.voice_time TO aussiebot;
\ No newline at end of file
@@ -0,0 +1,9 @@
+SELECT COALESCE(discord.platform_id, ledger.platform_id) AS platform_id,
+    discord.discord_points,
+    COALESCE(ledger.expected, 0)::bigint AS expected,
+    discord.platform_id IS NOT NULL AS present
+  FROM discord
+    FULL JOIN (SELECT platform_id, SUM(delta) AS expected FROM point_ledger
+                 WHERE platform = 'discord' GROUP BY platform_id) ledger
+      ON discord.platform_id = ledger.platform_id
+  WHERE COALESCE(discord.discord_points, 0) <> COALESCE(ledger.expected, 0);
\ No newline at end of file
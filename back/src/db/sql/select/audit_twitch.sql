@@ -0,0 +1,9 @@
+SELECT COALESCE(twitch.platform_id, ledger.platform_id) AS platform_id,
+    twitch.twitch_points,
+    COALESCE(ledger.expected, 0)::bigint AS expected,
+    twitch.platform_id IS NOT NULL AS present
+  FROM twitch
+    FULL JOIN (SELECT platform_id, SUM(delta) AS expected FROM point_ledger
+                 WHERE platform = 'twitch' GROUP BY platform_id) ledger
+      ON twitch.platform_id = ledger.platform_id
+  WHERE COALESCE(twitch.twitch_points, 0) <> COALESCE(ledger.expected, 0);
\ No newline at end of file
@@ -0,0 +1,9 @@
+SELECT COALESCE(youtube.platform_id, ledger.platform_id) AS platform_id,
+    youtube.youtube_points,
+    COALESCE(ledger.expected, 0)::bigint AS expected,
+    youtube.platform_id IS NOT NULL AS present
+  FROM youtube
+    FULL JOIN (SELECT platform_id, SUM(delta) AS expected FROM point_ledger
+                 WHERE platform = 'youtube' GROUP BY platform_id) ledger
+      ON youtube.platform_id = ledger.platform_id
+  WHERE COALESCE(youtube.youtube_points, 0) <> COALESCE(ledger.expected, 0);
\ No newline at end of file
@@ -0,0 +1,5 @@
+SELECT id, author, action, changes,
+    EXTRACT(EPOCH FROM created)::bigint AS created
+  FROM config_audit
+  ORDER BY id DESC
+  LIMIT $1;
@@ -0,0 +1 @@
+SELECT count FROM counter WHERE name = $1;
\ No newline at end of file
@@ -0,0 +1,7 @@
+ SELECT discord.platform_id
+   FROM discord
+     LEFT JOIN link_yt ON discord.platform_id = link_yt.discord_id
+     LEFT JOIN link_tw ON discord.platform_id = link_tw.discord_id
+     LEFT JOIN youtube ON youtube.platform_id = link_yt.id
+     LEFT JOIN twitch ON twitch.platform_id = link_tw.id
+  	WHERE COALESCE(discord.discord_points, 0) + COALESCE(youtube.youtube_points, 0) + COALESCE(twitch.twitch_points, 0) >= $1;
\ No newline at end of file
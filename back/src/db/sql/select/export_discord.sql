@@ -0,0 +1,4 @@
+SELECT platform_id, disp_name, discord_points FROM discord
+  WHERE platform_id > $1
+  ORDER BY platform_id
+  LIMIT $2;
\ No newline at end of file
@@ -0,0 +1,3 @@
+SELECT id, discord_id FROM link_tw
+  WHERE discord_id IS NOT NULL
+  ORDER BY id;
\ No newline at end of file
@@ -0,0 +1,2 @@
+SELECT platform_id, action, reason, at FROM modaction_youtube
+  ORDER BY id;
\ No newline at end of file
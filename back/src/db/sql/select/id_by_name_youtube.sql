@@ -0,0 +1,4 @@
+SELECT platform_id, disp_name FROM youtube
+  WHERE lower(disp_name) = lower($1)
+  ORDER BY last_seen DESC
+  LIMIT 1;
\ No newline at end of file
@@ -0,0 +1,2 @@
+SELECT COALESCE(SUM(delta), 0)::bigint FROM point_ledger
+  WHERE platform = $1 AND platform_id = $2;
\ No newline at end of file
@@ -0,0 +1,5 @@
+SELECT id, platform, platform_id, disp_name, note, author,
+    EXTRACT(EPOCH FROM created)::bigint AS created
+  FROM mod_notes
+  WHERE platform = $1 AND platform_id = $2
+  ORDER BY id;
\ No newline at end of file
@@ -0,0 +1,3 @@
+SELECT status = 'open', closes > now() FROM predictions
+  WHERE id = $1
+  FOR UPDATE;
\ No newline at end of file
@@ -0,0 +1,3 @@
+SELECT outcome FROM prediction_stakes
+  WHERE prediction = $1 AND platform = $2 AND platform_id = $3
+  LIMIT 1;
\ No newline at end of file
@@ -0,0 +1,4 @@
+SELECT platform, platform_id, outcome, SUM(amount)::bigint
+  FROM prediction_stakes
+  WHERE prediction = $1
+  GROUP BY platform, platform_id, outcome;
\ No newline at end of file
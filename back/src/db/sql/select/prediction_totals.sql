@@ -0,0 +1,4 @@
+SELECT outcome, SUM(amount)::bigint, COUNT(DISTINCT platform || ':' || platform_id)
+  FROM prediction_stakes
+  WHERE prediction = $1
+  GROUP BY outcome;
\ No newline at end of file
@@ -0,0 +1,5 @@
+SELECT id, item, platform, platform_id, disp_name, cost, status,
+    EXTRACT(EPOCH FROM created)::bigint AS created
+  FROM redemptions
+  WHERE status = 'pending'
+  ORDER BY id;
\ No newline at end of file
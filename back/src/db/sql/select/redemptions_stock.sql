@@ -0,0 +1,2 @@
+SELECT count(*) FROM redemptions
+  WHERE item = $1 AND status != 'refunded';
\ No newline at end of file
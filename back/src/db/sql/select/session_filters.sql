@@ -0,0 +1,11 @@
+SELECT reason, count(*) FROM (
+    SELECT reason FROM modaction_youtube WHERE at >= $1
+    UNION ALL
+    SELECT reason FROM modaction_discord WHERE at >= $1
+    UNION ALL
+    SELECT reason FROM modaction_twitch WHERE at >= $1
+) actions
+WHERE reason IS NOT NULL
+GROUP BY reason
+ORDER BY 2 DESC, 1
+LIMIT $2;
\ No newline at end of file
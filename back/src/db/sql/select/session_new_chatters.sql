@@ -0,0 +1,4 @@
+SELECT platform, count(*) FROM greeted
+WHERE first_seen >= $1
+GROUP BY platform
+ORDER BY 1;
\ No newline at end of file
@@ -0,0 +1,2 @@
+SELECT COALESCE(sum(delta), 0)::bigint FROM point_ledger
+WHERE at >= $1 AND delta > 0 AND reason IN ('upsert', 'award');
\ No newline at end of file
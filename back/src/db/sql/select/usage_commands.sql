@@ -0,0 +1,5 @@
+SELECT command, count(*) FROM command_usage
+WHERE used >= $1
+GROUP BY command
+ORDER BY 2 DESC, 1
+LIMIT $2;
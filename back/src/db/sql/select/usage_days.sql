@@ -0,0 +1,4 @@
+SELECT to_char(date_trunc('day', used), 'YYYY-MM-DD'), count(*) FROM command_usage
+WHERE used >= $1
+GROUP BY 1
+ORDER BY 1;
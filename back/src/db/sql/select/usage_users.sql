@@ -0,0 +1,5 @@
+SELECT platform, platform_id, (array_agg(disp_name ORDER BY used DESC))[1], count(*) FROM command_usage
+WHERE used >= $1
+GROUP BY platform, platform_id
+ORDER BY 4 DESC, 2
+LIMIT $2;
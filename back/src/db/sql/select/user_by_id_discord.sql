@@ -0,0 +1,2 @@
+SELECT platform_id, disp_name FROM discord
+  WHERE platform_id = $1;
\ No newline at end of file
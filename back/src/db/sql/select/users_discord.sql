@@ -0,0 +1,4 @@
+SELECT platform_id, disp_name FROM discord
+  WHERE disp_name ILIKE $1 || '%'
+  ORDER BY last_seen DESC
+  LIMIT $2;
\ No newline at end of file
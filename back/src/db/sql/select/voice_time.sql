@@ -0,0 +1 @@
+SELECT seconds FROM voice_time WHERE platform_id = $1;
\ No newline at end of file
@@ -0,0 +1 @@
+SELECT DISTINCT platform, platform_id FROM mod_notes;
\ No newline at end of file
@@ -0,0 +1,175 @@
+-- Everything the postgres migrations set up, timestamps are unix seconds
+
+CREATE TABLE IF NOT EXISTS youtube
+(
+    platform_id TEXT NOT NULL PRIMARY KEY,
+    disp_name TEXT,
+    youtube_points INTEGER DEFAULT 0,
+    time_watched INTEGER DEFAULT 0,
+    last_seen INTEGER DEFAULT (CAST(strftime('%s', 'now') AS INTEGER))
+);
+
+CREATE TABLE IF NOT EXISTS discord
+(
+    platform_id TEXT NOT NULL PRIMARY KEY,
+    disp_name TEXT,
+    discord_points INTEGER DEFAULT 0,
+    time_watched INTEGER DEFAULT 0,
+    last_seen INTEGER DEFAULT (CAST(strftime('%s', 'now') AS INTEGER))
+);
+
+CREATE TABLE IF NOT EXISTS twitch
+(
+    platform_id TEXT NOT NULL PRIMARY KEY,
+    disp_name TEXT,
+    twitch_points INTEGER DEFAULT 0,
+    time_watched INTEGER DEFAULT 0,
+    last_seen INTEGER DEFAULT (CAST(strftime('%s', 'now') AS INTEGER))
+);
+
+CREATE TABLE IF NOT EXISTS link_yt
+(
+    id TEXT NOT NULL PRIMARY KEY,
+    discord_id TEXT
+);
+
+CREATE TABLE IF NOT EXISTS link_tw
+(
+    id TEXT NOT NULL PRIMARY KEY,
+    discord_id TEXT
+);
+
+CREATE TABLE IF NOT EXISTS counter
+(
+    name TEXT NOT NULL PRIMARY KEY,
+    count INTEGER NOT NULL DEFAULT 0,
+    updated INTEGER DEFAULT (CAST(strftime('%s', 'now') AS INTEGER))
+);
+
+CREATE TABLE IF NOT EXISTS greeted
+(
+    platform TEXT NOT NULL,
+    platform_id TEXT NOT NULL,
+    first_seen INTEGER DEFAULT (CAST(strftime('%s', 'now') AS INTEGER)),
+    PRIMARY KEY (platform, platform_id)
+);
+
+CREATE TABLE IF NOT EXISTS modaction_youtube
+(
+    id INTEGER PRIMARY KEY,
+    platform_id TEXT NOT NULL,
+    action TEXT NOT NULL,
+    reason TEXT,
+    at INTEGER DEFAULT (CAST(strftime('%s', 'now') AS INTEGER))
+);
+
+CREATE TABLE IF NOT EXISTS modaction_discord
+(
+    id INTEGER PRIMARY KEY,
+    platform_id TEXT NOT NULL,
+    action TEXT NOT NULL,
+    reason TEXT,
+    at INTEGER DEFAULT (CAST(strftime('%s', 'now') AS INTEGER))
+);
+
+CREATE TABLE IF NOT EXISTS modaction_twitch
+(
+    id INTEGER PRIMARY KEY,
+    platform_id TEXT NOT NULL,
+    action TEXT NOT NULL,
+    reason TEXT,
+    at INTEGER DEFAULT (CAST(strftime('%s', 'now') AS INTEGER))
+);
+
+CREATE TABLE IF NOT EXISTS point_ledger
+(
+    id INTEGER PRIMARY KEY,
+    platform TEXT NOT NULL,
+    platform_id TEXT NOT NULL,
+    delta INTEGER NOT NULL,
+    reason TEXT NOT NULL,
+    at INTEGER DEFAULT (CAST(strftime('%s', 'now') AS INTEGER))
+);
+
+CREATE INDEX IF NOT EXISTS point_ledger_user ON point_ledger (platform, platform_id);
+
+CREATE TABLE IF NOT EXISTS redemptions
+(
+    id INTEGER PRIMARY KEY,
+    item TEXT NOT NULL,
+    platform TEXT NOT NULL,
+    platform_id TEXT NOT NULL,
+    disp_name TEXT,
+    cost INTEGER NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending',
+    created INTEGER DEFAULT (CAST(strftime('%s', 'now') AS INTEGER)),
+    resolved INTEGER
+);
+
+CREATE INDEX IF NOT EXISTS redemptions_item_status ON redemptions (item, status);
+
+CREATE TABLE IF NOT EXISTS mod_notes
+(
+    id INTEGER PRIMARY KEY,
+    platform TEXT NOT NULL,
+    platform_id TEXT NOT NULL,
+    disp_name TEXT,
+    note TEXT NOT NULL,
+    author TEXT NOT NULL,
+    created INTEGER DEFAULT (CAST(strftime('%s', 'now') AS INTEGER))
+);
+
+CREATE INDEX IF NOT EXISTS mod_notes_user ON mod_notes (platform, platform_id);
+
+CREATE TABLE IF NOT EXISTS command_usage
+(
+    id INTEGER PRIMARY KEY,
+    platform TEXT NOT NULL,
+    platform_id TEXT NOT NULL,
+    disp_name TEXT,
+    command TEXT NOT NULL,
+    used INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS command_usage_used ON command_usage (used);
+
+CREATE TABLE IF NOT EXISTS predictions
+(
+    id TEXT PRIMARY KEY,
+    question TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'open',
+    winner INTEGER,
+    created INTEGER DEFAULT (CAST(strftime('%s', 'now') AS INTEGER)),
+    closes INTEGER NOT NULL,
+    resolved INTEGER
+);
+
+CREATE TABLE IF NOT EXISTS prediction_stakes
+(
+    id INTEGER PRIMARY KEY,
+    prediction TEXT NOT NULL REFERENCES predictions (id),
+    platform TEXT NOT NULL,
+    platform_id TEXT NOT NULL,
+    disp_name TEXT,
+    outcome INTEGER NOT NULL,
+    amount INTEGER NOT NULL,
+    created INTEGER DEFAULT (CAST(strftime('%s', 'now') AS INTEGER))
+);
+
+CREATE INDEX IF NOT EXISTS prediction_stakes_prediction ON prediction_stakes (prediction);
+
+CREATE TABLE IF NOT EXISTS config_audit
+(
+    id INTEGER PRIMARY KEY,
+    author TEXT NOT NULL,
+    action TEXT NOT NULL,
+    changes TEXT NOT NULL,
+    created INTEGER DEFAULT (CAST(strftime('%s', 'now') AS INTEGER))
+);
+
+CREATE TABLE IF NOT EXISTS voice_time
+(
+    platform_id TEXT NOT NULL PRIMARY KEY,
+    seconds INTEGER NOT NULL DEFAULT 0,
+    updated INTEGER DEFAULT (CAST(strftime('%s', 'now') AS INTEGER))
+);
@@ -0,0 +1,2 @@
+UPDATE discord SET discord_points = $2
+  WHERE platform_id = $1;
\ No newline at end of file
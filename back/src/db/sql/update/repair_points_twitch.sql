@@ -0,0 +1,2 @@
+UPDATE twitch SET twitch_points = $2
+  WHERE platform_id = $1;
\ No newline at end of file
@@ -0,0 +1,2 @@
+UPDATE youtube SET youtube_points = $2
+  WHERE platform_id = $1;
\ No newline at end of file
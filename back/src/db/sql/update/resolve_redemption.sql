@@ -0,0 +1,3 @@
+UPDATE redemptions SET status = $2, resolved = now()
+  WHERE id = $1 AND status = 'pending'
+  RETURNING platform, platform_id, cost;
\ No newline at end of file
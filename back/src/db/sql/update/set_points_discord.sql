@@ -1,3 +1,5 @@
 UPDATE discord SET discord_points = $2
-  WHERE disp_name = $1 and discord_points < $2
-    RETURNING *;
\ No newline at end of file
+  FROM (SELECT platform_id, COALESCE(discord_points, 0) AS old_points FROM discord
+          WHERE disp_name = $1 and discord_points < $2 FOR UPDATE) old
+  WHERE discord.platform_id = old.platform_id
+    RETURNING discord.platform_id, $2 - old.old_points;
\ No newline at end of file
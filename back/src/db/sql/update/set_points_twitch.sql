@@ -1,3 +1,5 @@
 UPDATE twitch SET twitch_points = $2
-  WHERE disp_name = $1 and twitch_points < $2
-    RETURNING *;
\ No newline at end of file
+  FROM (SELECT platform_id, COALESCE(twitch_points, 0) AS old_points FROM twitch
+          WHERE disp_name = $1 and twitch_points < $2 FOR UPDATE) old
+  WHERE twitch.platform_id = old.platform_id
+    RETURNING twitch.platform_id, $2 - old.old_points;
\ No newline at end of file
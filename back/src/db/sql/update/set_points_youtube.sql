@@ -1,3 +1,5 @@
 UPDATE youtube SET youtube_points = $2
-  WHERE disp_name = $1 and youtube_points < $2
-    RETURNING *;
\ No newline at end of file
+  FROM (SELECT platform_id, COALESCE(youtube_points, 0) AS old_points FROM youtube
+          WHERE disp_name = $1 and youtube_points < $2 FOR UPDATE) old
+  WHERE youtube.platform_id = old.platform_id
+    RETURNING youtube.platform_id, $2 - old.old_points;
\ No newline at end of file
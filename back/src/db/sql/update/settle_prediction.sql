@@ -0,0 +1,2 @@
+UPDATE predictions SET status = $2, winner = $3, resolved = now()
+  WHERE id = $1;
\ No newline at end of file
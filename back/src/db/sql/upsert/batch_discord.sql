@@ -0,0 +1,4 @@
+INSERT INTO discord (platform_id, disp_name, discord_points)
+SELECT * FROM unnest($1::varchar[], $2::varchar[], $3::int[])
+ON CONFLICT (platform_id)
+DO UPDATE SET disp_name = excluded.disp_name, discord_points = discord.discord_points + excluded.discord_points;
\ No newline at end of file
@@ -0,0 +1,4 @@
+INSERT INTO twitch (platform_id, disp_name, twitch_points)
+SELECT * FROM unnest($1::varchar[], $2::varchar[], $3::int[])
+ON CONFLICT (platform_id)
+DO UPDATE SET disp_name = excluded.disp_name, twitch_points = twitch.twitch_points + excluded.twitch_points;
\ No newline at end of file
@@ -0,0 +1,4 @@
+INSERT INTO youtube (platform_id, disp_name, youtube_points)
+SELECT * FROM unnest($1::varchar[], $2::varchar[], $3::int[])
+ON CONFLICT (platform_id)
+DO UPDATE SET disp_name = excluded.disp_name, youtube_points = youtube.youtube_points + excluded.youtube_points;
\ No newline at end of file
@@ -0,0 +1,5 @@
+INSERT INTO counter (name, count) 
+  VALUES ($1, $2) 
+  ON CONFLICT (name) 
+  DO UPDATE SET count = $2, updated = now()
+  RETURNING *;
\ No newline at end of file
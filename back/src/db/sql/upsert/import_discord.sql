@@ -0,0 +1,6 @@
+INSERT INTO discord (platform_id, disp_name, discord_points)
+  VALUES ($1, $2, $3)
+  ON CONFLICT (platform_id)
+  DO UPDATE SET disp_name = COALESCE(excluded.disp_name, discord.disp_name),
+    discord_points = CASE WHEN $4 THEN discord.discord_points + excluded.discord_points ELSE excluded.discord_points END
+  RETURNING discord_points;
\ No newline at end of file
@@ -0,0 +1,6 @@
+INSERT INTO twitch (platform_id, disp_name, twitch_points)
+  VALUES ($1, $2, $3)
+  ON CONFLICT (platform_id)
+  DO UPDATE SET disp_name = COALESCE(excluded.disp_name, twitch.disp_name),
+    twitch_points = CASE WHEN $4 THEN twitch.twitch_points + excluded.twitch_points ELSE excluded.twitch_points END
+  RETURNING twitch_points;
\ No newline at end of file
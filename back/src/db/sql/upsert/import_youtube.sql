@@ -0,0 +1,6 @@
+INSERT INTO youtube (platform_id, disp_name, youtube_points)
+  VALUES ($1, $2, $3)
+  ON CONFLICT (platform_id)
+  DO UPDATE SET disp_name = COALESCE(excluded.disp_name, youtube.disp_name),
+    youtube_points = CASE WHEN $4 THEN youtube.youtube_points + excluded.youtube_points ELSE excluded.youtube_points END
+  RETURNING youtube_points;
\ No newline at end of file
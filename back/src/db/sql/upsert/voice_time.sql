@@ -0,0 +1,4 @@
+INSERT INTO voice_time (platform_id, seconds)
+SELECT * FROM unnest($1::varchar[], $2::int[])
+ON CONFLICT (platform_id)
+DO UPDATE SET seconds = voice_time.seconds + excluded.seconds, updated = now();
\ No newline at end of file
@@ -0,0 +1,1186 @@
+//! A single SQLite file instead of postgres, for small deployments.
+//! The postgres queries are reused where SQLite reads them the same way. SQLite numbers `$n`
+//! parameters in the order they first appear, so only ones using them in order are shared,
+//! the rest are written out here with `?n`
+
+use super::{
+    batch::{self, Increment, UpsertBatch},
+    config_audit::ConfigAudit,
+    give::{BetOp, GiveError, GiveOp, GiveSource, GiveTarget},
+    hours::HoursOp,
+    link::{LinkOp, UnlinkOp},
+    modaction::ModActionDump,
+    notes::{ModNote, NoteOp, NoteTarget},
+    prediction::{self, OutcomeTotal, PredictionError, Settlement, StakeOp, UserStake},
+    session::SessionTotals,
+    shop::{RedeemOp, Redemption, ShopError},
+    usage::{UsageBatch, UsageRange, UsageStats, UsageUser, USAGE_TOP},
+    voice::VoiceBatch,
+    Db, Resp, TaskChanPair,
+};
+use crate::{
+    error::{self, ChanSendError, Error},
+    msg::Platform,
+};
+use parking_lot::Mutex;
+use rusqlite::{params, Connection, OptionalExtension, Row, Transaction, TransactionBehavior};
+use std::{
+    path::Path,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::mpsc;
+
+/// How long a write waits on another connection (e.g a backup) before giving up
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+const NOW: &str = "CAST(strftime('%s', 'now') AS INTEGER)";
+
+fn unix(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// (table, points column)
+fn table(platform: Platform) -> error::Result<(&'static str, &'static str)> {
+    match platform {
+        Platform::YOUTUBE => Ok(("youtube", "youtube_points")),
+        Platform::DISCORD => Ok(("discord", "discord_points")),
+        Platform::TWITCH => Ok(("twitch", "twitch_points")),
+        _ => Err(GiveError::InvalidPlatform.into()),
+    }
+}
+
+fn platform_name(platform: Platform) -> String {
+    platform.to_string().to_lowercase()
+}
+
+/// Record a change to a user's points, in the same transaction as the change itself
+fn ledger(
+    tx: &Transaction<'_>,
+    platform: Platform,
+    id: &str,
+    delta: i32,
+    reason: &str,
+) -> error::Result<()> {
+    if delta == 0 {
+        return Ok(());
+    }
+    tx.execute(
+        include_str!("sql/insert/point_ledger.sql"),
+        params![platform_name(platform), id, delta, reason],
+    )?;
+    Ok(())
+}
+
+pub(super) struct Actor {
+    rx: mpsc::Receiver<TaskChanPair>,
+    conn: Arc<Mutex<Connection>>,
+}
+
+/// Database operations
+/// backed by sqlite
+impl Actor {
+    pub(super) fn open(path: &Path, rx: mpsc::Receiver<TaskChanPair>) -> error::Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.execute_batch(include_str!("sql/sqlite/schema.sql"))?;
+        tracing::info!(path = %path.display(), "opened sqlite database");
+
+        Ok(Self {
+            rx,
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    pub(super) async fn run(self) {
+        let conn = self.conn;
+        batch::run(self.rx, |(task, tx)| {
+            let conn = conn.clone();
+            tokio::spawn(async move {
+                // one connection, so queries run one at a time off the runtime
+                let resp = tokio::task::spawn_blocking(move || {
+                    let mut conn = conn.lock();
+                    Self::handle_task(&mut conn, task)
+                })
+                .await
+                .map_err(Error::Join)
+                .and_then(|resp| resp);
+
+                if let Err(e) = tx.send(resp) {
+                    tracing::error!(
+                        "{}",
+                        ChanSendError {
+                            msg: format!("{:?}", e),
+                        }
+                    );
+                }
+            });
+        })
+        .await;
+    }
+
+    fn handle_task(conn: &mut Connection, task: Db) -> error::Result<Resp> {
+        match task {
+            Db::GetPoints(platform, id) => {
+                let sql = match platform {
+                    Platform::YOUTUBE => include_str!("sql/select/points_youtube.sql"),
+                    Platform::DISCORD => include_str!("sql/select/points_discord.sql"),
+                    Platform::TWITCH => include_str!("sql/select/points_twitch.sql"),
+                    _ => unreachable!(),
+                };
+                let points = conn.query_row(sql, params![id.as_str()], |row| {
+                    Ok([
+                        (Platform::YOUTUBE, row.get(3)?),
+                        (Platform::DISCORD, row.get(4)?),
+                        (Platform::TWITCH, row.get(5)?),
+                    ])
+                })?;
+                Ok(Resp::GetPoints(points))
+            }
+            Db::SetPoints(platform, name, points) => {
+                let (table, column) = table(platform)?;
+                let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+                // only raises points
+                let raised = tx
+                    .prepare(&format!(
+                        "SELECT platform_id, COALESCE({column}, 0) FROM {table}
+                           WHERE disp_name = ?1 AND {column} < ?2"
+                    ))?
+                    .query_map(params![name.as_str(), points], |row| {
+                        Ok((row.get::<_, String>(0)?, row.get::<_, i32>(1)?))
+                    })?
+                    .collect::<Result<Vec<_>, _>>()?;
+                for (id, old) in raised {
+                    tx.execute(
+                        &format!("UPDATE {table} SET {column} = ?2 WHERE platform_id = ?1"),
+                        params![id, points],
+                    )?;
+                    ledger(&tx, platform, &id, points - old, "set")?;
+                }
+                tx.commit()?;
+
+                tracing::info!(to = points, "set points");
+                Ok(Resp::Ok)
+            }
+            Db::Upsert(platform, id, name, points) => {
+                let batch = UpsertBatch(vec![Increment {
+                    platform,
+                    id,
+                    name,
+                    points,
+                }]);
+                Self::upsert_batch(conn, batch).map(|_| Resp::Ok)
+            }
+            Db::UpsertBatch(batch) => Self::upsert_batch(conn, batch).map(|_| Resp::Ok),
+            // the batching in `run` answers it
+            Db::FlushPoints => Ok(Resp::Ok),
+            Db::Ping => {
+                conn.execute_batch("SELECT 1")?;
+                Ok(Resp::Ok)
+            }
+            Db::Give(args) => Self::give(conn, args).map(Resp::Give),
+            Db::GiveAll(ops) => Self::give_all(conn, ops).map(Resp::GiveAll),
+            Db::Bet(args) => {
+                Self::bet(conn, args).map(|(amount, winnings)| Resp::Bet(amount, winnings))
+            }
+            Db::ModAction(platform, id, action, reason) => {
+                let sql = match platform {
+                    Platform::YOUTUBE => include_str!("sql/insert/modaction_youtube.sql"),
+                    Platform::DISCORD => include_str!("sql/insert/modaction_discord.sql"),
+                    Platform::TWITCH => include_str!("sql/insert/modaction_twitch.sql"),
+                    _ => unreachable!(),
+                };
+                conn.query_row(
+                    sql,
+                    params![id.as_str(), action.to_string(), reason.as_str()],
+                    |_| Ok(()),
+                )?;
+                Ok(Resp::Ok)
+            }
+            Db::Link(args) => Self::link(conn, args).map(|_| Resp::Ok),
+            Db::Unlink(args) => Self::unlink(conn, args).map(Resp::Unlink),
+            Db::Hours(args) => Self::hours(conn, args).map(Resp::Hours),
+            Db::DumpModActions => Self::mod_actions(conn).map(Resp::ModActionDump),
+            Db::GetCounter(name) => {
+                let count = conn
+                    .query_row(
+                        include_str!("sql/select/counter.sql"),
+                        params![name.as_str()],
+                        |row| row.get(0),
+                    )
+                    .optional()?;
+                Ok(Resp::Counter(count))
+            }
+            Db::DiscordPointsAbove(points) => {
+                let ids = conn
+                    .prepare(include_str!("sql/select/discord_points_above.sql"))?
+                    .query_map(params![points], |row| row.get(0))?
+                    .collect::<Result<_, _>>()?;
+                Ok(Resp::Ids(ids))
+            }
+            Db::SetCounter(name, count) => {
+                conn.execute(
+                    &format!(
+                        "INSERT INTO counter (name, count) VALUES (?1, ?2)
+                           ON CONFLICT (name) DO UPDATE SET count = ?2, updated = {NOW}"
+                    ),
+                    params![name.as_str(), count],
+                )?;
+                Ok(Resp::Ok)
+            }
+            Db::FirstSeen(platform, id) => {
+                // only returns a row if this is the first insert
+                let row = conn
+                    .query_row(
+                        include_str!("sql/insert/greeted.sql"),
+                        params![platform_name(platform), id.as_str()],
+                        |_| Ok(()),
+                    )
+                    .optional()?;
+                Ok(Resp::FirstSeen(row.is_some()))
+            }
+            Db::Redeem(args) => Self::redeem(conn, args).map(Resp::Redeemed),
+            Db::PendingRedemptions => Self::pending(conn).map(Resp::Redemptions),
+            Db::ResolveRedemption(id, refund) => Self::resolve(conn, id, refund).map(|_| Resp::Ok),
+            Db::SearchUsers(platform, prefix, limit) => {
+                let table = match table(platform) {
+                    Ok((table, _)) => table,
+                    Err(_) => return Ok(Resp::Users(vec![])),
+                };
+                // match the prefix literally, LIKE ignores ASCII case like ILIKE
+                let prefix = prefix
+                    .replace('\\', "\\\\")
+                    .replace('%', "\\%")
+                    .replace('_', "\\_");
+                let users = conn
+                    .prepare(&format!(
+                        "SELECT platform_id, disp_name FROM {table}
+                           WHERE disp_name LIKE ?1 || '%' ESCAPE '\\'
+                           ORDER BY last_seen DESC
+                           LIMIT ?2"
+                    ))?
+                    .query_map(params![prefix, limit], |row| Ok((row.get(0)?, row.get(1)?)))?
+                    .collect::<Result<_, _>>()?;
+                Ok(Resp::Users(users))
+            }
+            Db::FindUser(platform, key) => {
+                let sql = match key.sql(platform) {
+                    Some(sql) => sql,
+                    None => return Ok(Resp::User(None)),
+                };
+                let user = conn
+                    .query_row(sql, params![key.value()], |row| {
+                        Ok((row.get(0)?, row.get(1)?))
+                    })
+                    .optional()?;
+                Ok(Resp::User(user))
+            }
+            Db::ModNotes(args) => Self::notes(conn, args).map(Resp::Notes),
+            Db::Watchlist => {
+                let rows = conn
+                    .prepare(include_str!("sql/select/watchlist.sql"))?
+                    .query_map([], |row| {
+                        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+                    })?
+                    .collect::<Result<Vec<_>, _>>()?;
+                rows.into_iter()
+                    .map(|(platform, id)| Ok((Platform::from_str(&platform)?, id)))
+                    .collect::<error::Result<_>>()
+                    .map(Resp::Watchlist)
+            }
+            Db::RecordUsage(batch) => Self::record_usage(conn, batch).map(|_| Resp::Ok),
+            Db::Usage(range) => Self::usage(conn, range).map(Resp::Usage),
+            Db::SessionTotals(since) => Self::session_totals(conn, since).map(Resp::SessionTotals),
+            Db::OpenPrediction(id, question, closes) => {
+                conn.execute(
+                    include_str!("sql/insert/prediction.sql"),
+                    params![id.as_str(), question.as_str(), unix(closes)],
+                )?;
+                Ok(Resp::Ok)
+            }
+            Db::Stake(args) => Self::stake(conn, args).map(Resp::PredictionTotals),
+            Db::PredictionTotals(id) => {
+                Self::prediction_totals(conn, &id).map(Resp::PredictionTotals)
+            }
+            Db::SettlePrediction(id, winner) => {
+                Self::settle_prediction(conn, &id, winner).map(Resp::Settled)
+            }
+            Db::RecordConfigChange(args) => {
+                conn.execute(
+                    include_str!("sql/insert/config_audit.sql"),
+                    params![
+                        args.author.as_str(),
+                        args.action,
+                        serde_json::to_string(&args.changes)?
+                    ],
+                )?;
+                Ok(Resp::Ok)
+            }
+            Db::ConfigAudit(limit) => {
+                let rows = conn
+                    .prepare(
+                        "SELECT id, author, action, changes, created FROM config_audit
+                           ORDER BY id DESC
+                           LIMIT ?1",
+                    )?
+                    .query_map(params![limit], |row| {
+                        Ok((
+                            row.get(0)?,
+                            row.get(1)?,
+                            row.get(2)?,
+                            row.get::<_, String>(3)?,
+                            row.get(4)?,
+                        ))
+                    })?
+                    .collect::<Result<Vec<_>, _>>()?;
+                rows.into_iter()
+                    .map(|(id, author, action, changes, created)| {
+                        Ok(ConfigAudit {
+                            id,
+                            author,
+                            action,
+                            changes: serde_json::from_str(&changes)?,
+                            created,
+                        })
+                    })
+                    .collect::<error::Result<_>>()
+                    .map(Resp::ConfigAudit)
+            }
+            Db::AddVoiceTime(batch) => Self::add_voice_time(conn, batch).map(|_| Resp::Ok),
+            Db::VoiceTime(id) => {
+                let secs = conn
+                    .query_row(
+                        include_str!("sql/select/voice_time.sql"),
+                        params![id.as_str()],
+                        |row| row.get(0),
+                    )
+                    .optional()?;
+                Ok(Resp::VoiceTime(secs.unwrap_or_default()))
+            }
+            Db::ImportUsers(_)
+            | Db::ExportUsers(..)
+            | Db::AuditPoints(_)
+            | Db::ExportLinks
+            | Db::ExportModActions
+            | Db::RestoreRecords(_) => Err(Error::Generic(
+                "not supported with sqlite storage, use postgres".into(),
+            )),
+        }
+    }
+
+    /// No arrays to unnest, but it's still one transaction instead of one per increment
+    fn upsert_batch(conn: &mut Connection, batch: UpsertBatch) -> error::Result<()> {
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        for (platform, (ids, names, points)) in batch.by_platform() {
+            let sql = match platform {
+                Platform::YOUTUBE => include_str!("sql/upsert/youtube_id.sql"),
+                Platform::DISCORD => include_str!("sql/upsert/discord_id.sql"),
+                Platform::TWITCH => include_str!("sql/upsert/twitch_id.sql"),
+                _ => unreachable!(),
+            };
+            let mut upsert = tx.prepare(sql)?;
+            for ((id, name), points) in ids.iter().zip(&names).zip(&points) {
+                upsert.query_row(params![id, name, points], |_| Ok(()))?;
+                ledger(&tx, platform, id, *points, "upsert")?;
+            }
+            tracing::info!(platform = %platform, users = ids.len(), "incremented points");
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn add_voice_time(conn: &mut Connection, batch: VoiceBatch) -> error::Result<()> {
+        let (ids, names, secs, points) = batch.columns();
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        {
+            let mut upsert = tx.prepare(include_str!("sql/upsert/discord_id.sql"))?;
+            let mut voice = tx.prepare(&format!(
+                "INSERT INTO voice_time (platform_id, seconds) VALUES (?1, ?2)
+                   ON CONFLICT (platform_id) DO UPDATE SET seconds = seconds + ?2, updated = {NOW}"
+            ))?;
+            for (((id, name), secs), points) in ids.iter().zip(&names).zip(&secs).zip(&points) {
+                upsert.query_row(params![id, name, points], |_| Ok(()))?;
+                ledger(&tx, Platform::DISCORD, id, *points, "upsert")?;
+                voice.execute(params![id, secs])?;
+            }
+        }
+        tx.commit()?;
+        tracing::info!(users = ids.len(), "added voice time");
+        Ok(())
+    }
+
+    fn give(conn: &mut Connection, args: GiveOp) -> error::Result<i32> {
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let amount = Self::apply_give(&tx, &args)?;
+        tx.commit()?;
+        Ok(amount)
+    }
+
+    fn give_all(conn: &mut Connection, ops: Vec<GiveOp>) -> error::Result<Vec<i32>> {
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let amounts = ops
+            .iter()
+            .map(|args| Self::apply_give(&tx, args))
+            .collect::<error::Result<Vec<_>>>()?;
+        tx.commit()?;
+        Ok(amounts)
+    }
+
+    fn bet(conn: &mut Connection, args: BetOp) -> error::Result<(i32, i32)> {
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let amount = Self::apply_give(&tx, &args.wager())?;
+        let winnings = match args.payout(amount) {
+            Some(payout) => Self::apply_give(&tx, &payout)?,
+            None => 0,
+        };
+        tx.commit()?;
+        Ok((amount, winnings))
+    }
+
+    fn apply_give(tx: &Transaction<'_>, args: &GiveOp) -> error::Result<i32> {
+        let reason = args.reason();
+
+        let amount = match (&args.from, &args.to) {
+            (GiveSource::Linked(platorig, platfrom, id), GiveTarget::Linked(platto)) => {
+                if platfrom == platto {
+                    return Err(GiveError::SamePlatform.into());
+                }
+
+                let link_sql = match *platorig {
+                    Platform::YOUTUBE => include_str!("sql/select/points_youtube.sql"),
+                    Platform::DISCORD => include_str!("sql/select/points_discord.sql"),
+                    Platform::TWITCH => include_str!("sql/select/points_twitch.sql"),
+                    _ => unreachable!(),
+                };
+                let ids = tx.query_row(link_sql, params![id.as_str()], |row| {
+                    Ok([row.get::<_, Option<String>>(0)?, row.get(1)?, row.get(2)?])
+                })?;
+                let get_id = |p: Platform| {
+                    let id = match p {
+                        Platform::YOUTUBE => &ids[0],
+                        Platform::DISCORD => &ids[1],
+                        Platform::TWITCH => &ids[2],
+                        _ => return Err(GiveError::InvalidPlatform.into()),
+                    };
+                    id.clone()
+                        .ok_or_else(|| Error::Generic(format!("not linked on {:?}", p)))
+                };
+
+                let (from_id, to_id) = (get_id(*platfrom)?, get_id(*platto)?);
+                let amount = Self::amount(tx, *platfrom, &from_id, args)?;
+                Self::deduct(tx, *platfrom, &from_id, amount, reason)?;
+                Self::deposit(tx, *platto, &to_id, false, amount, reason)?;
+                amount
+            }
+            (GiveSource::Id(platfrom, from_id), to) => {
+                let amount = Self::amount(tx, *platfrom, from_id, args)?;
+                Self::deduct(tx, *platfrom, from_id, amount, reason)?;
+                match to {
+                    GiveTarget::Name(platto, to_name) => {
+                        Self::deposit(tx, *platto, to_name, true, amount, reason)?
+                    }
+                    GiveTarget::User(platto, to_id, _to_name) => {
+                        Self::deposit(tx, *platto, to_id, false, amount, reason)?
+                    }
+                    GiveTarget::Spend => {}
+                    GiveTarget::Linked(_) => {
+                        panic!("Both or neither of GiveSource and GiveTarget must be Linked")
+                    }
+                }
+                amount
+            }
+            (GiveSource::None, GiveTarget::Name(platto, to_name)) => {
+                Self::deposit(tx, *platto, to_name, true, args.amount, reason)?;
+                args.amount
+            }
+            (GiveSource::None, GiveTarget::User(platto, to_id, _to_name)) => {
+                Self::deposit(tx, *platto, to_id, false, args.amount, reason)?;
+                args.amount
+            }
+            (GiveSource::None, GiveTarget::Spend) => panic!("Invalid combination"),
+            (_, GiveTarget::Linked(_)) | (GiveSource::Linked(_, _, _), _) => {
+                panic!("Both or neither of GiveSource and GiveTarget must be Linked")
+            }
+        };
+
+        Ok(amount)
+    }
+
+    /// The amount to give, all of their points if it's -1
+    fn amount(
+        tx: &Transaction<'_>,
+        platform: Platform,
+        source: &str,
+        args: &GiveOp,
+    ) -> error::Result<i32> {
+        let (min, max) = (args.min as i32, args.max as i32);
+
+        let amount = if args.amount == -1 {
+            let (table, column) = table(platform)?;
+            tx.query_row(
+                &format!("SELECT {column} FROM {table} WHERE platform_id = ?1"),
+                params![source],
+                |row| row.get(0),
+            )?
+        } else {
+            args.amount
+        };
+
+        if amount < min {
+            return Err(GiveError::AmountBelowMin { amount, min }.into());
+        }
+        Ok(amount.min(max))
+    }
+
+    fn deduct(
+        tx: &Transaction<'_>,
+        platform: Platform,
+        source: &str,
+        amount: i32,
+        reason: &str,
+    ) -> error::Result<()> {
+        let (table, column) = table(platform)?;
+        let deducted = tx.execute(
+            &format!(
+                "UPDATE {table} SET {column} = {column} - ?2
+                   WHERE platform_id = ?1 AND {column} >= ?2"
+            ),
+            params![source, amount],
+        )?;
+
+        // rolled back when tx drops
+        if deducted == 0 {
+            tracing::debug!(
+                "\x1b[91mFailed to deduct {} point{} from {}\x1b[0m",
+                amount,
+                if amount != 1 { "s" } else { "" },
+                source,
+            );
+            return Err(GiveError::Deduct.into());
+        }
+
+        ledger(tx, platform, source, -amount, reason)
+    }
+
+    /// Credit the user with the id `target`, or everyone with the name if `by_name`
+    fn deposit(
+        tx: &Transaction<'_>,
+        platform: Platform,
+        target: &str,
+        by_name: bool,
+        amount: i32,
+        reason: &str,
+    ) -> error::Result<()> {
+        let (table, column) = match (platform, by_name) {
+            (Platform::TWITCH, true) => return Err(GiveError::InvalidPlatform.into()),
+            (platform, _) => table(platform)?,
+        };
+        let by = if by_name { "disp_name" } else { "platform_id" };
+        let credited = tx
+            .prepare(&format!(
+                "UPDATE {table} SET {column} = {column} + ?2
+                   WHERE {by} = ?1
+                   RETURNING platform_id"
+            ))?
+            .query_map(params![target, amount], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if credited.is_empty() {
+            tracing::debug!(
+                "\x1b[91mFailed to deposit {} point{} into {}\x1b[0m",
+                amount,
+                if amount != 1 { "s" } else { "" },
+                target
+            );
+            return Err(GiveError::Deposit.into());
+        }
+
+        // names aren't unique, so go by the ids of whoever was actually credited
+        for id in credited {
+            ledger(tx, platform, &id, amount, reason)?;
+        }
+        Ok(())
+    }
+
+    fn link(conn: &mut Connection, args: LinkOp) -> error::Result<()> {
+        let upsert_sql = match args.platform {
+            Platform::YOUTUBE => include_str!("sql/upsert/link_yt.sql"),
+            Platform::TWITCH => include_str!("sql/upsert/link_tw.sql"),
+            _ => unreachable!(),
+        };
+
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        // delete existing links
+        for sql in [
+            include_str!("sql/delete/link_yt.sql"),
+            include_str!("sql/delete/link_tw.sql"),
+        ] {
+            tx.execute(sql, params![args.discord_id.as_str()])?;
+        }
+        tx.query_row(
+            upsert_sql,
+            params![args.platform_id.as_str(), args.discord_id.as_str()],
+            |_| Ok(()),
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn unlink(conn: &mut Connection, args: UnlinkOp) -> error::Result<u64> {
+        let delete_sql: &[&str] = match args.platform {
+            // a discord user may be linked to both
+            Platform::DISCORD => &[
+                include_str!("sql/delete/link_yt.sql"),
+                include_str!("sql/delete/link_tw.sql"),
+            ],
+            Platform::YOUTUBE => &[include_str!("sql/delete/link_yt_id.sql")],
+            Platform::TWITCH => &[include_str!("sql/delete/link_tw_id.sql")],
+            _ => unreachable!(),
+        };
+
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let mut removed = 0;
+        for sql in delete_sql {
+            removed += tx.execute(sql, params![args.id.as_str()])? as u64;
+        }
+        tx.commit()?;
+        Ok(removed)
+    }
+
+    fn hours(conn: &mut Connection, args: HoursOp) -> error::Result<i32> {
+        let HoursOp {
+            platform,
+            id,
+            max_diff,
+        } = args;
+        let (table, _) = table(platform)?;
+        let now = unix(SystemTime::now());
+
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let seen = tx
+            .query_row(
+                &format!("SELECT last_seen, time_watched FROM {table} WHERE platform_id = ?1"),
+                params![id.as_str()],
+                |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i32>(1)?)),
+            )
+            .optional()?;
+
+        let watchtime = match seen {
+            Some((last_seen, watchtime)) => {
+                // ignore if non-monotonic (e.g if Chat just inserted (last_seen=now()) before this runs)
+                let diff = (now - last_seen).clamp(0, i32::MAX as i64) as i32;
+                if max_diff > 0 && diff >= max_diff.min(i32::MAX as i64) as i32 {
+                    // too long since last message
+                    tracing::debug!("diff {} > max_diff {}", diff, max_diff);
+                    watchtime
+                } else {
+                    watchtime + diff
+                }
+            }
+            None => 0,
+        };
+
+        tx.execute(
+            &format!(
+                "INSERT INTO {table} (platform_id, time_watched, last_seen) VALUES (?1, ?2, ?3)
+                   ON CONFLICT (platform_id) DO UPDATE SET time_watched = ?2, last_seen = ?3"
+            ),
+            params![id.as_str(), watchtime, now],
+        )?;
+        tx.commit()?;
+        Ok(watchtime)
+    }
+
+    fn mod_actions(conn: &mut Connection) -> error::Result<ModActionDump> {
+        [
+            (
+                Platform::YOUTUBE,
+                include_str!("sql/select/modaction_youtube.sql"),
+            ),
+            (
+                Platform::DISCORD,
+                include_str!("sql/select/modaction_discord.sql"),
+            ),
+            (
+                Platform::TWITCH,
+                include_str!("sql/select/modaction_twitch.sql"),
+            ),
+        ]
+        .into_iter()
+        .map(|(platform, sql)| {
+            let rows = conn
+                .prepare(sql)?
+                .query_map([], |row| {
+                    Ok((
+                        row.get(0)?,
+                        row.get(1)?,
+                        row.get(2)?,
+                        row.get(3)?,
+                        row.get::<_, i64>(4)?.max(0) as u64,
+                    ))
+                })?
+                .filter_map(|row| match row {
+                    Ok(row) => Some(row),
+                    Err(e) => {
+                        tracing::error!("{}", e);
+                        None
+                    }
+                })
+                .collect();
+            Ok((platform, rows))
+        })
+        .collect()
+    }
+
+    fn redeem(conn: &mut Connection, args: RedeemOp) -> error::Result<i64> {
+        // immediate, so redemptions of the same item are serialized until commit
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+        if let Some(stock) = args.stock {
+            let redeemed: i64 = tx.query_row(
+                include_str!("sql/select/redemptions_stock.sql"),
+                params![args.item.as_str()],
+                |row| row.get(0),
+            )?;
+            if redeemed >= stock {
+                return Err(ShopError::OutOfStock.into());
+            }
+        }
+
+        Self::deduct(&tx, args.platform, &args.id, args.cost, "redeem")?;
+
+        let status = if args.completed {
+            "completed"
+        } else {
+            "pending"
+        };
+        let id = tx.query_row(
+            include_str!("sql/insert/redemption.sql"),
+            params![
+                args.item.as_str(),
+                platform_name(args.platform),
+                args.id.as_str(),
+                args.name.as_str(),
+                args.cost,
+                status,
+            ],
+            |row| row.get(0),
+        )?;
+
+        tx.commit()?;
+        Ok(id)
+    }
+
+    fn pending(conn: &mut Connection) -> error::Result<Vec<Redemption>> {
+        let rows = conn
+            .prepare(
+                "SELECT id, item, platform, platform_id, disp_name, cost, status, created
+                   FROM redemptions
+                   WHERE status = 'pending'
+                   ORDER BY id",
+            )?
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(2)?,
+                    Redemption {
+                        id: row.get(0)?,
+                        item: row.get(1)?,
+                        platform: Platform::empty(),
+                        user_id: row.get(3)?,
+                        user_name: row.get(4)?,
+                        cost: row.get(5)?,
+                        status: row.get(6)?,
+                        created: row.get(7)?,
+                    },
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        rows.into_iter()
+            .map(|(platform, redemption)| {
+                Ok(Redemption {
+                    platform: Platform::from_str(&platform)?,
+                    ..redemption
+                })
+            })
+            .collect()
+    }
+
+    /// Mark a pending redemption completed, or refunded with its cost given back
+    fn resolve(conn: &mut Connection, id: i64, refund: bool) -> error::Result<()> {
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+        let status = if refund { "refunded" } else { "completed" };
+        let (platform, user_id, cost) = tx
+            .query_row(
+                &format!(
+                    "UPDATE redemptions SET status = ?2, resolved = {NOW}
+                       WHERE id = ?1 AND status = 'pending'
+                       RETURNING platform, platform_id, cost"
+                ),
+                params![id, status],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, i32>(2)?,
+                    ))
+                },
+            )
+            .optional()?
+            .ok_or(ShopError::NotPending)?;
+
+        if refund {
+            let platform = Platform::from_str(&platform)?;
+            Self::deposit(&tx, platform, &user_id, false, cost, "refund")?;
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn notes(conn: &mut Connection, args: NoteOp) -> error::Result<Vec<ModNote>> {
+        const COLUMNS: &str = "id, platform, platform_id, disp_name, note, author, created";
+
+        fn note(row: &Row<'_>) -> rusqlite::Result<(String, ModNote)> {
+            Ok((
+                row.get(1)?,
+                ModNote {
+                    id: row.get(0)?,
+                    platform: Platform::empty(),
+                    user_id: row.get(2)?,
+                    user_name: row.get(3)?,
+                    note: row.get(4)?,
+                    author: row.get(5)?,
+                    created: row.get(6)?,
+                },
+            ))
+        }
+
+        let rows = match args {
+            NoteOp::Add {
+                platform,
+                target,
+                note: text,
+                author,
+            } => {
+                let (id, name) = match Self::resolve_user(conn, platform, target)? {
+                    Some(user) => user,
+                    None => return Ok(vec![]),
+                };
+                vec![conn.query_row(
+                    &format!(
+                        "INSERT INTO mod_notes (platform, platform_id, disp_name, note, author)
+                           VALUES (?1, ?2, ?3, ?4, ?5)
+                           RETURNING {COLUMNS}"
+                    ),
+                    params![platform_name(platform), id, name, text, author.as_str()],
+                    note,
+                )?]
+            }
+            NoteOp::List(platform, target) => {
+                let (id, _) = match Self::resolve_user(conn, platform, target)? {
+                    Some(user) => user,
+                    None => return Ok(vec![]),
+                };
+                conn.prepare(&format!(
+                    "SELECT {COLUMNS} FROM mod_notes
+                       WHERE platform = ?1 AND platform_id = ?2
+                       ORDER BY id"
+                ))?
+                .query_map(params![platform_name(platform), id], note)?
+                .collect::<Result<_, _>>()?
+            }
+            NoteOp::Remove(id) => conn
+                .prepare(&format!(
+                    "DELETE FROM mod_notes WHERE id = ?1 RETURNING {COLUMNS}"
+                ))?
+                .query_map(params![id], note)?
+                .collect::<Result<_, _>>()?,
+        };
+
+        rows.into_iter()
+            .map(|(platform, note)| {
+                Ok(ModNote {
+                    platform: Platform::from_str(&platform)?,
+                    ..note
+                })
+            })
+            .collect()
+    }
+
+    /// (id, name) of the user, None if they haven't been seen
+    fn resolve_user(
+        conn: &Connection,
+        platform: Platform,
+        target: NoteTarget,
+    ) -> error::Result<Option<(String, String)>> {
+        let name = match target {
+            NoteTarget::User(id, name) => return Ok(Some((id.to_string(), name.to_string()))),
+            NoteTarget::Name(name) => name,
+        };
+        let sql = match platform {
+            Platform::YOUTUBE => include_str!("sql/select/id_by_name_youtube.sql"),
+            Platform::DISCORD => include_str!("sql/select/id_by_name_discord.sql"),
+            Platform::TWITCH => include_str!("sql/select/id_by_name_twitch.sql"),
+            _ => return Ok(None),
+        };
+        Ok(conn
+            .query_row(sql, params![name.as_str()], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .optional()?)
+    }
+
+    fn record_usage(conn: &mut Connection, UsageBatch(records): UsageBatch) -> error::Result<()> {
+        let tx = conn.transaction()?;
+        {
+            let mut insert = tx.prepare(
+                "INSERT INTO command_usage (platform, platform_id, disp_name, command, used)
+                   VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?;
+            for r in records {
+                insert.execute(params![
+                    platform_name(r.platform),
+                    r.user_id.as_str(),
+                    r.user_name.as_str(),
+                    r.command,
+                    unix(r.used),
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn usage(conn: &mut Connection, range: UsageRange) -> error::Result<UsageStats> {
+        let since = unix(range.since());
+
+        let commands = conn
+            .prepare(
+                "SELECT command, count(*) FROM command_usage
+                   WHERE used >= ?1
+                   GROUP BY command
+                   ORDER BY 2 DESC, 1
+                   LIMIT ?2",
+            )?
+            .query_map(params![since, USAGE_TOP], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?
+            .collect::<Result<_, _>>()?;
+
+        // with max(), SQLite takes the bare disp_name from the same (latest) row
+        let users = conn
+            .prepare(
+                "SELECT platform, platform_id, disp_name, max(used), count(*) FROM command_usage
+                   WHERE used >= ?1
+                   GROUP BY platform, platform_id
+                   ORDER BY 5 DESC, 2
+                   LIMIT ?2",
+            )?
+            .query_map(params![since, USAGE_TOP], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    UsageUser {
+                        platform: Platform::empty(),
+                        id: row.get(1)?,
+                        name: row.get(2)?,
+                        count: row.get(4)?,
+                    },
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .map(|(platform, user)| {
+                Ok(UsageUser {
+                    platform: Platform::from_str(&platform)?,
+                    ..user
+                })
+            })
+            .collect::<error::Result<_>>()?;
+
+        let days = conn
+            .prepare(
+                "SELECT date(used, 'unixepoch'), count(*) FROM command_usage
+                   WHERE used >= ?1
+                   GROUP BY 1
+                   ORDER BY 1",
+            )?
+            .query_map(params![since], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_, _>>()?;
+
+        Ok(UsageStats {
+            range,
+            commands,
+            users,
+            days,
+        })
+    }
+
+    /// Whether the prediction's still open, and still taking bets
+    fn prediction_open(tx: &Transaction<'_>, id: &str) -> error::Result<(bool, bool)> {
+        let open = tx
+            .query_row(
+                &format!("SELECT status = 'open', closes > {NOW} FROM predictions WHERE id = ?1"),
+                params![id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        Ok(open.unwrap_or_default())
+    }
+
+    fn prediction_totals(conn: &Connection, id: &str) -> error::Result<Vec<OutcomeTotal>> {
+        let totals = conn
+            .prepare(
+                "SELECT outcome, SUM(amount), COUNT(DISTINCT platform || ':' || platform_id)
+                   FROM prediction_stakes
+                   WHERE prediction = ?1
+                   GROUP BY outcome",
+            )?
+            .query_map(params![id], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })?
+            .collect::<Result<_, _>>()?;
+        Ok(totals)
+    }
+
+    /// Deduct the stake and record it in one go, returning the totals with it
+    fn stake(conn: &mut Connection, args: StakeOp) -> error::Result<Vec<OutcomeTotal>> {
+        // immediate, so stakes can't land after the prediction's settled
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+        if Self::prediction_open(&tx, &args.prediction)? != (true, true) {
+            return Err(PredictionError::Closed.into());
+        }
+
+        let platform = platform_name(args.platform);
+        let outcome: Option<i32> = tx
+            .query_row(
+                include_str!("sql/select/prediction_outcome.sql"),
+                params![args.prediction.as_str(), platform, args.id.as_str()],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if let Some(outcome) = outcome.filter(|o| *o != args.outcome) {
+            return Err(PredictionError::OtherOutcome(outcome).into());
+        }
+
+        Self::deduct(&tx, args.platform, &args.id, args.amount, "prediction")?;
+        tx.execute(
+            include_str!("sql/insert/prediction_stake.sql"),
+            params![
+                args.prediction.as_str(),
+                platform,
+                args.id.as_str(),
+                args.name.as_str(),
+                args.outcome,
+                args.amount,
+            ],
+        )?;
+        let totals = Self::prediction_totals(&tx, &args.prediction)?;
+
+        tx.commit()?;
+        Ok(totals)
+    }
+
+    /// Pay out the winners, or refund everyone if there's no winner
+    fn settle_prediction(
+        conn: &mut Connection,
+        id: &str,
+        winner: Option<i32>,
+    ) -> error::Result<Settlement> {
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+        if !Self::prediction_open(&tx, id)?.0 {
+            return Err(PredictionError::Closed.into());
+        }
+
+        let stakes = tx
+            .prepare(
+                "SELECT platform, platform_id, outcome, SUM(amount)
+                   FROM prediction_stakes
+                   WHERE prediction = ?1
+                   GROUP BY platform, platform_id, outcome",
+            )?
+            .query_map(params![id], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .map(|(platform, user, outcome, staked)| {
+                Ok((Platform::from_str(&platform)?, user, outcome, staked))
+            })
+            .collect::<error::Result<Vec<UserStake>>>()?;
+
+        let (settlement, payouts) = prediction::payouts(stakes, winner);
+        let (status, reason) = if settlement.refunded {
+            ("refunded", "refund")
+        } else {
+            ("resolved", "prediction")
+        };
+        tx.execute(
+            &format!(
+                "UPDATE predictions SET status = ?2, winner = ?3, resolved = {NOW}
+                   WHERE id = ?1"
+            ),
+            params![id, status, winner],
+        )?;
+        for (platform, user, amount) in payouts {
+            Self::deposit(&tx, platform, &user, false, amount, reason)?;
+        }
+
+        tx.commit()?;
+        Ok(settlement)
+    }
+
+    fn session_totals(conn: &mut Connection, since: SystemTime) -> error::Result<SessionTotals> {
+        let since = unix(since);
+
+        let new_chatters = conn
+            .prepare(include_str!("sql/select/session_new_chatters.sql"))?
+            .query_map(params![since], |row| {
+                Ok((row.get::<_, String>(0)?, row.get(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .map(|(platform, count)| Ok((Platform::from_str(&platform)?, count)))
+            .collect::<error::Result<_>>()?;
+
+        let points_awarded = conn.query_row(
+            "SELECT COALESCE(sum(delta), 0) FROM point_ledger
+               WHERE at >= ?1 AND delta > 0 AND reason IN ('upsert', 'award')",
+            params![since],
+            |row| row.get(0),
+        )?;
+
+        let filters = conn
+            .prepare(include_str!("sql/select/session_filters.sql"))?
+            .query_map(params![since, USAGE_TOP], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?
+            .collect::<Result<_, _>>()?;
+
+        let commands = conn
+            .prepare(include_str!("sql/select/usage_commands.sql"))?
+            .query_map(params![since, USAGE_TOP], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?
+            .collect::<Result<_, _>>()?;
+
+        Ok(SessionTotals {
+            new_chatters,
+            points_awarded,
+            filters,
+            commands,
+        })
+    }
+}
@@ -0,0 +1,201 @@
+use super::{Db, Handle};
+pub use crate::proto::db::{UsageRange, UsageStats, UsageUser};
+use crate::{
+    error,
+    msg::{Platform, User},
+    DbPool,
+};
+use std::{
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tokio::sync::mpsc;
+use tracing::Instrument;
+
+/// Runs written per insert
+const USAGE_BATCH: usize = 200;
+/// Runs are written at least this often
+const USAGE_FLUSH_INTERVAL: Duration = Duration::from_secs(10);
+/// Most entries in each top list
+pub(super) const USAGE_TOP: i64 = 10;
+
+/// A successful command run
+#[derive(Clone)]
+pub(crate) struct UsageRecord {
+    pub(crate) platform: Platform,
+    pub(crate) user_id: Arc<String>,
+    pub(crate) user_name: Arc<String>,
+    pub(crate) command: String,
+    pub(crate) used: SystemTime,
+}
+
+#[derive(Clone)]
+pub(crate) struct UsageBatch(pub(crate) Vec<UsageRecord>);
+
+// hide the whole batch from tracing
+impl std::fmt::Debug for UsageBatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("UsageBatch").field(&self.0.len()).finish()
+    }
+}
+
+impl UsageRange {
+    pub(super) fn since(self) -> SystemTime {
+        let days = match self {
+            Self::Day => 1,
+            Self::Week => 7,
+            Self::Month => 30,
+            Self::All => return SystemTime::UNIX_EPOCH,
+        };
+        SystemTime::now() - Duration::from_secs(days * 24 * 60 * 60)
+    }
+}
+
+impl FromStr for UsageRange {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "day" | "today" => Ok(Self::Day),
+            "week" => Ok(Self::Week),
+            "month" => Ok(Self::Month),
+            "all" => Ok(Self::All),
+            _ => Err(()),
+        }
+    }
+}
+
+pub(crate) async fn record(db: DbPool, UsageBatch(records): UsageBatch) -> error::Result<()> {
+    let client = db.get().await?;
+
+    let mut platforms = Vec::with_capacity(records.len());
+    let mut ids = Vec::with_capacity(records.len());
+    let mut names = Vec::with_capacity(records.len());
+    let mut commands = Vec::with_capacity(records.len());
+    let mut used = Vec::with_capacity(records.len());
+    for r in records {
+        platforms.push(r.platform.to_string().to_lowercase());
+        ids.push(r.user_id.to_string());
+        names.push(r.user_name.to_string());
+        commands.push(r.command);
+        used.push(r.used);
+    }
+
+    client
+        .execute(
+            include_str!("sql/insert/command_usage.sql"),
+            &[&platforms, &ids, &names, &commands, &used],
+        )
+        .await?;
+    Ok(())
+}
+
+pub(crate) async fn stats(db: DbPool, range: UsageRange) -> error::Result<UsageStats> {
+    let client = db.get().await?;
+    let since = range.since();
+
+    let commands = client
+        .query(
+            include_str!("sql/select/usage_commands.sql"),
+            &[&since, &USAGE_TOP],
+        )
+        .await?
+        .iter()
+        .map(|row| Ok((row.try_get(0)?, row.try_get(1)?)))
+        .collect::<error::Result<_>>()?;
+
+    let users = client
+        .query(
+            include_str!("sql/select/usage_users.sql"),
+            &[&since, &USAGE_TOP],
+        )
+        .await?
+        .iter()
+        .map(|row| {
+            Ok(UsageUser {
+                platform: Platform::from_str(row.try_get(0)?)?,
+                id: row.try_get(1)?,
+                name: row.try_get(2)?,
+                count: row.try_get(3)?,
+            })
+        })
+        .collect::<error::Result<_>>()?;
+
+    let days = client
+        .query(include_str!("sql/select/usage_days.sql"), &[&since])
+        .await?
+        .iter()
+        .map(|row| Ok((row.try_get(0)?, row.try_get(1)?)))
+        .collect::<error::Result<_>>()?;
+
+    Ok(UsageStats {
+        range,
+        commands,
+        users,
+        days,
+    })
+}
+
+/// Collects command runs and writes them in batches, so busy chats don't
+/// cost a write per command
+#[derive(Debug, Clone)]
+pub struct UsageWriter {
+    tx: mpsc::Sender<UsageRecord>,
+}
+
+impl UsageWriter {
+    pub fn new(db: Handle) -> Self {
+        let (tx, rx) = mpsc::channel(USAGE_BATCH * 4);
+        tokio::spawn(Self::run(db, rx).instrument(tracing::info_span!("UsageWriter")));
+        Self { tx }
+    }
+
+    /// Dropped rather than waited on if the writer's behind
+    pub(crate) fn record(&self, platform: Platform, user: &User, command: &str) {
+        let record = UsageRecord {
+            platform,
+            user_id: user.id.clone(),
+            user_name: user.name.clone(),
+            command: command.to_owned(),
+            used: SystemTime::now(),
+        };
+        if self.tx.try_send(record).is_err() {
+            tracing::warn!(command, "usage writer behind, dropping run");
+        }
+    }
+
+    async fn run(db: Handle, mut rx: mpsc::Receiver<UsageRecord>) {
+        let mut batch = Vec::with_capacity(USAGE_BATCH);
+        let mut interval = tokio::time::interval(USAGE_FLUSH_INTERVAL);
+
+        loop {
+            let closed = tokio::select! {
+                record = rx.recv() => match record {
+                    Some(record) => {
+                        batch.push(record);
+                        if batch.len() < USAGE_BATCH {
+                            continue;
+                        }
+                        false
+                    }
+                    None => true,
+                },
+                _ = interval.tick() => false,
+            };
+
+            if !batch.is_empty() {
+                let records = std::mem::replace(&mut batch, Vec::with_capacity(USAGE_BATCH));
+                let count = records.len();
+                match Db::RecordUsage(UsageBatch(records)).exec(&db).await {
+                    Ok(_) => tracing::debug!(count, "usage written"),
+                    Err(e) => tracing::error!(count, "{}", e),
+                }
+            }
+
+            if closed {
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use super::{give::GiveError, ledger};
+pub use crate::proto::db::UserRecord;
+use crate::{error, msg::Platform, DbPool};
+use std::sync::Arc;
+
+#[derive(Clone)]
+pub(crate) struct ImportOp {
+    /// All on the same platform
+    pub(crate) users: Vec<UserRecord>,
+    /// Add to existing points instead of replacing them
+    pub(crate) add: bool,
+}
+
+// hide potentially massive batches from tracing
+impl std::fmt::Debug for ImportOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ImportOp")
+            .field("users", &self.users.len())
+            .field("add", &self.add)
+            .finish()
+    }
+}
+
+/// How to look a user up on a platform
+#[derive(Debug, Clone)]
+pub(crate) enum UserKey {
+    Id(Arc<String>),
+    /// Display name, the most recently seen user with it is picked
+    Name(Arc<String>),
+}
+
+impl UserKey {
+    pub(super) fn sql(&self, platform: Platform) -> Option<&'static str> {
+        Some(match (self, platform) {
+            (UserKey::Id(_), Platform::YOUTUBE) => {
+                include_str!("sql/select/user_by_id_youtube.sql")
+            }
+            (UserKey::Id(_), Platform::DISCORD) => {
+                include_str!("sql/select/user_by_id_discord.sql")
+            }
+            (UserKey::Id(_), Platform::TWITCH) => include_str!("sql/select/user_by_id_twitch.sql"),
+            (UserKey::Name(_), Platform::YOUTUBE) => {
+                include_str!("sql/select/id_by_name_youtube.sql")
+            }
+            (UserKey::Name(_), Platform::DISCORD) => {
+                include_str!("sql/select/id_by_name_discord.sql")
+            }
+            (UserKey::Name(_), Platform::TWITCH) => {
+                include_str!("sql/select/id_by_name_twitch.sql")
+            }
+            _ => return None,
+        })
+    }
+
+    pub(crate) fn value(&self) -> &str {
+        match self {
+            UserKey::Id(id) => id,
+            UserKey::Name(name) => name,
+        }
+    }
+}
+
+/// (id, name) of the user, None if they haven't been seen
+pub(crate) async fn find(
+    db: DbPool,
+    platform: Platform,
+    key: UserKey,
+) -> error::Result<Option<(String, String)>> {
+    let sql = match key.sql(platform) {
+        Some(sql) => sql,
+        None => return Ok(None),
+    };
+    let client = db.get().await?;
+    let row = client.query_opt(sql, &[&key.value()]).await?;
+    row.map(|row| Ok((row.try_get(0)?, row.try_get(1)?)))
+        .transpose()
+}
+
+/// Upsert a batch of users in one transaction, returning how many were written
+pub(crate) async fn import(db: DbPool, args: ImportOp) -> error::Result<u64> {
+    let platform = match args.users.first() {
+        Some(user) => user.platform,
+        None => return Ok(0),
+    };
+    let (lock_sql, sql) = match platform {
+        Platform::YOUTUBE => (
+            include_str!("sql/select/youtube_id_lock.sql"),
+            include_str!("sql/upsert/import_youtube.sql"),
+        ),
+        Platform::DISCORD => (
+            include_str!("sql/select/discord_id_lock.sql"),
+            include_str!("sql/upsert/import_discord.sql"),
+        ),
+        Platform::TWITCH => (
+            include_str!("sql/select/twitch_id_lock.sql"),
+            include_str!("sql/upsert/import_twitch.sql"),
+        ),
+        _ => return Err(GiveError::InvalidPlatform.into()),
+    };
+
+    let mut client = db.get().await?;
+    let client = client.build_transaction().start().await?;
+    let lock_stmt = client.prepare(lock_sql).await?;
+    let stmt = client.prepare(sql).await?;
+
+    let mut written = 0;
+    for user in &args.users {
+        if user.platform != platform {
+            return Err(GiveError::InvalidPlatform.into());
+        }
+        // the ledger needs the change, not the new total
+        let before = match client.query_opt(&lock_stmt, &[&user.id.as_str()]).await? {
+            Some(row) => row.try_get::<_, Option<i32>>(2)?.unwrap_or_default(),
+            None => 0,
+        };
+        let after = client
+            .query_one(
+                &stmt,
+                &[&user.id.as_str(), &user.name, &user.points, &args.add],
+            )
+            .await?
+            .try_get::<_, Option<i32>>(0)?
+            .unwrap_or_default();
+        ledger::record(&client, platform, &user.id, after - before, "import").await?;
+        written += 1;
+    }
+
+    client.commit().await?;
+    Ok(written)
+}
+
+/// Up to `limit` users ordered by id, starting after `after`
+pub(crate) async fn export(
+    db: DbPool,
+    platform: Platform,
+    after: Arc<String>,
+    limit: i64,
+) -> error::Result<Vec<UserRecord>> {
+    let sql = match platform {
+        Platform::YOUTUBE => include_str!("sql/select/export_youtube.sql"),
+        Platform::DISCORD => include_str!("sql/select/export_discord.sql"),
+        Platform::TWITCH => include_str!("sql/select/export_twitch.sql"),
+        _ => return Err(GiveError::InvalidPlatform.into()),
+    };
+
+    let client = db.get().await?;
+    let rows = client.query(sql, &[&after.as_str(), &limit]).await?;
+
+    rows.iter()
+        .map(|row| {
+            Ok(UserRecord {
+                platform,
+                id: row.try_get(0)?,
+                name: row.try_get(1)?,
+                points: row.try_get::<_, Option<i32>>(2)?.unwrap_or_default(),
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,73 @@
+//! Time Discord members spend in voice channels, written along with the points it earned them
+use crate::{error, msg::Platform, DbPool};
+use std::sync::Arc;
+
+/// A member's voice time since it was last written
+#[derive(Debug, Clone)]
+pub(crate) struct VoiceTime {
+    pub(crate) id: Arc<String>,
+    pub(crate) name: Arc<String>,
+    pub(crate) secs: i32,
+    pub(crate) points: i32,
+}
+
+#[derive(Clone)]
+pub(crate) struct VoiceBatch(pub(crate) Vec<VoiceTime>);
+
+// hide the whole batch from tracing
+impl std::fmt::Debug for VoiceBatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("VoiceBatch").field(&self.0.len()).finish()
+    }
+}
+
+/// (ids, names, secs, points)
+type Columns = (Vec<String>, Vec<String>, Vec<i32>, Vec<i32>);
+
+impl VoiceBatch {
+    pub(super) fn columns(self) -> Columns {
+        let mut columns = Columns::default();
+        for time in self.0 {
+            columns.0.push(time.id.to_string());
+            columns.1.push(time.name.to_string());
+            columns.2.push(time.secs);
+            columns.3.push(time.points);
+        }
+        columns
+    }
+}
+
+pub(super) async fn add(db: DbPool, batch: VoiceBatch) -> error::Result<()> {
+    let (ids, names, secs, points) = batch.columns();
+
+    let mut client = db.get().await?;
+    let client = client.build_transaction().start().await?;
+    client
+        .execute(
+            include_str!("sql/upsert/batch_discord.sql"),
+            &[&ids, &names, &points],
+        )
+        .await?;
+    client
+        .execute(
+            include_str!("sql/insert/point_ledger_batch.sql"),
+            &[&Platform::DISCORD.to_string().to_lowercase(), &ids, &points],
+        )
+        .await?;
+    client
+        .execute(include_str!("sql/upsert/voice_time.sql"), &[&ids, &secs])
+        .await?;
+    client.commit().await?;
+
+    tracing::info!(users = ids.len(), "added voice time");
+    Ok(())
+}
+
+/// Seconds spent in voice, 0 if they never have been
+pub(super) async fn get(db: DbPool, id: Arc<String>) -> error::Result<i32> {
+    let client = db.get().await?;
+    let row = client
+        .query_opt(include_str!("sql/select/voice_time.sql"), &[&id.as_str()])
+        .await?;
+    Ok(row.map_or(0, |row| row.get(0)))
+}
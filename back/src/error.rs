@@ -1,8 +1,9 @@
 use crate::pubsub::EOF as PubSubEOf;
 use crate::{
+    cache::CacheUnavailable,
     cmds::link::LinkError,
-    cmds::OwnedValueError,
-    db::give::GiveError,
+    cmds::{InvokeDepthError, OwnedValueError},
+    db::{give::GiveError, prediction::PredictionError, shop::ShopError},
     msg::{ArgMapError, PlatformError},
     ws::WsError,
 };
@@ -63,13 +64,13 @@ pub(crate) type Result<T> = std::result::Result<T, Error>;
 // }
 
 macro_rules! def_err {
-  ( $( $err_name:ident ( $err_ty:ty )   ),+ $(,)? ) => {
+  ( $( $(#[$attr:meta])* $err_name:ident ( $err_ty:ty )   ),+ $(,)? ) => {
 
     #[derive(Debug)]
     #[non_exhaustive]
     pub enum Error {
       Generic(String),
-      $( $err_name( $err_ty ) ),+
+      $( $(#[$attr])* $err_name( $err_ty ) ),+
     }
 
     impl std::error::Error for Error {}
@@ -79,6 +80,7 @@ macro_rules! def_err {
             match self {
               Error::Generic(s) => f.write_str(s),
               $(
+                $(#[$attr])*
                 Error::$err_name(e) => e.fmt(f)
               ),+
             }
@@ -86,6 +88,7 @@ macro_rules! def_err {
     }
 
   $(
+    $(#[$attr])*
     impl From<$err_ty> for Error {
         fn from(err: $err_ty) -> Self {
             Error::$err_name(err)
@@ -159,6 +162,8 @@ def_err![
     ParseInt(ParseIntError),
     SerdeJson(serde_json::Error),
     Postgres(tokio_postgres::Error),
+    #[cfg(feature = "sqlite")]
+    Sqlite(rusqlite::Error),
     SystemTime(SystemTimeError),
     Join(JoinError),
     HeaderToStr(HeaderToStrError),
@@ -170,9 +175,16 @@ def_err![
     ChanSend(ChanSendError),
     OneShotRecv(OneShotRecvError),
     GiveOp(GiveError),
+    Shop(ShopError),
+    Prediction(PredictionError),
     PubSubEOF(PubSubEOf),
     Link(LinkError),
-    TryFromInt(TryFromIntError)
+    TryFromInt(TryFromIntError),
+    CacheUnavailable(CacheUnavailable),
+    Reqwest(reqwest::Error),
+    InvokeDepth(InvokeDepthError),
+    MsgPackEncode(rmp_serde::encode::Error),
+    MsgPackDecode(rmp_serde::decode::Error)
 ];
 
 impl<T> From<SendError<T>> for Error {
@@ -0,0 +1,110 @@
+//! Named feature flags kept in redis, so risky behaviour can be tried on some of the invocations
+//! that'd use it before all of them, and turned off again, without touching config or redeploying.
+//! Every instance reads the same flags, each rereading them at most every [`REFRESH_INTERVAL`]
+
+pub use crate::proto::admin::Flag;
+use crate::{
+    cache::{self, Cache, RespType},
+    error,
+};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use rand::Rng;
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
+impl Flag {
+    fn roll(self) -> bool {
+        match self {
+            Flag::Bool(on) => on,
+            Flag::Percent(pct) => rand::thread_rng().gen_range(0..100) < pct,
+        }
+    }
+}
+
+type Flags = Arc<HashMap<String, Flag>>;
+
+/// When the flags were last read, and what they were
+static FLAGS: Lazy<RwLock<Option<(Instant, Flags)>>> = Lazy::new(Default::default);
+
+static FLAGS_KEY: Lazy<Arc<String>> =
+    Lazy::new(|| Arc::new(format!("aussiebot!{}!flags", crate::channel_name())));
+
+async fn load(cache: &cache::Handle) -> error::Result<Flags> {
+    let fields = match Cache::HashGetAll(FLAGS_KEY.clone()).exec(cache).await? {
+        RespType::VecStringString(fields) => fields,
+        _ => unreachable!(),
+    };
+    let flags = fields
+        .into_iter()
+        .filter_map(|(name, flag)| match serde_json::from_str(&flag) {
+            Ok(flag) => Some((name, flag)),
+            Err(e) => {
+                tracing::warn!(name = name.as_str(), "skipping unreadable flag: {}", e);
+                None
+            }
+        })
+        .collect();
+    Ok(Arc::new(flags))
+}
+
+/// Every flag, read from redis if the copy here is too old
+pub(crate) async fn all(cache: &cache::Handle) -> error::Result<Flags> {
+    if let Some((read, flags)) = &*FLAGS.read() {
+        if read.elapsed() < REFRESH_INTERVAL {
+            return Ok(flags.clone());
+        }
+    }
+    let flags = load(cache).await?;
+    *FLAGS.write() = Some((Instant::now(), flags.clone()));
+    Ok(flags)
+}
+
+/// Whether the flag's on this time. Unset flags are off, and so is everything if redis can't be
+/// reached, since whatever's behind a flag is the riskier path
+pub(crate) async fn enabled(cache: &cache::Handle, name: &str) -> bool {
+    match all(cache).await {
+        Ok(flags) => flags.get(name).is_some_and(|flag| flag.roll()),
+        Err(e) => {
+            tracing::error!(name, "couldn't read flags: {}", e);
+            false
+        }
+    }
+}
+
+/// Set the flag, or remove it if None
+pub(crate) async fn set(
+    cache: &cache::Handle,
+    name: &str,
+    flag: Option<Flag>,
+) -> error::Result<()> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("flag names can't be empty".into());
+    }
+    match flag {
+        Some(Flag::Percent(pct)) if pct > 100 => {
+            return Err(format!("{} is over 100%", pct).into());
+        }
+        Some(flag) => {
+            let value = serde_json::to_string(&flag)?;
+            Cache::HashSet(FLAGS_KEY.clone(), Arc::new(name.to_owned()), value, false)
+                .exec(cache)
+                .await?;
+        }
+        None => {
+            Cache::HashDelete(FLAGS_KEY.clone(), Arc::new(name.to_owned()))
+                .exec(cache)
+                .await?;
+        }
+    }
+    tracing::info!(name, ?flag, "set flag");
+    // this instance sees it straight away, the others within REFRESH_INTERVAL
+    *FLAGS.write() = None;
+    Ok(())
+}
@@ -0,0 +1,342 @@
+//! Liveness for the back server and the connectors, served as `/healthz` and `/metrics` when
+//! HEALTH_BIND is set. Each service tracks the links it can't work without (its pubsub connection,
+//! a connector's gateway), whether they're up and when they last carried a message, so whatever
+//! runs it can restart one that's stuck.
+
+use crate::config::HealthConfig;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde_derive::Serialize;
+use std::{
+    fmt::Write,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+
+pub(crate) const JSON: &str = "application/json";
+const PROMETHEUS: &str = "text/plain; version=0.0.4";
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Link {
+    /// UPSTREAM_CHAN or DOWNSTREAM_CHAN, whichever the service reads
+    Pubsub,
+    /// The chat platform's connection, e.g. the discord gateway
+    Gateway,
+}
+
+impl Link {
+    fn label(self) -> &'static str {
+        match self {
+            Link::Pubsub => "pubsub",
+            Link::Gateway => "gateway",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct LinkReport {
+    pub link: Link,
+    pub up: bool,
+    /// When it last went up or down (unix secs)
+    pub changed: u64,
+    /// Messages carried since startup
+    pub messages: u64,
+    /// When the last one was (unix secs)
+    pub last_message: Option<u64>,
+    /// Up, but hasn't carried anything in longer than HEALTH_STALE_AFTER
+    pub stale: bool,
+}
+
+/// Answers `/healthz`
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub healthy: bool,
+    pub uptime: u64,
+    pub links: Vec<LinkReport>,
+}
+
+/// Counted since startup, by name and help
+static COUNTERS: [(&str, &str, &AtomicU64); 4] = [
+    (
+        "duplicates_suppressed_total",
+        "Messages dropped for repeating a recently seen dedupe id",
+        &crate::msg::DUPLICATES_SUPPRESSED,
+    ),
+    (
+        "unsigned_dropped_total",
+        "Messages dropped for coming over pubsub without a valid signature",
+        &crate::msg::UNSIGNED_DROPPED,
+    ),
+    (
+        "commands_timed_out_total",
+        "Command runs cut off for going over their timeout",
+        &crate::cmds::COMMANDS_TIMED_OUT,
+    ),
+    (
+        "bonus_points_granted_total",
+        "Bonus points multipliers have granted",
+        &crate::cmds::multiplier::BONUS_POINTS_GRANTED,
+    ),
+];
+
+static STARTED: Lazy<Instant> = Lazy::new(Instant::now);
+static LINKS: Lazy<RwLock<Vec<LinkReport>>> = Lazy::new(Default::default);
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Run `f` on the link's entry, adding it as down if it's new
+fn update(link: Link, f: impl FnOnce(&mut LinkReport)) {
+    Lazy::force(&STARTED);
+    let mut links = LINKS.write();
+    let idx = match links.iter().position(|l| l.link == link) {
+        Some(idx) => idx,
+        None => {
+            links.push(LinkReport {
+                link,
+                up: false,
+                changed: now_secs(),
+                messages: 0,
+                last_message: None,
+                stale: false,
+            });
+            links.len() - 1
+        }
+    };
+    f(&mut links[idx]);
+}
+
+/// Count the link as unhealthy until it first comes up
+pub fn track(link: Link) {
+    update(link, |_| {});
+}
+
+pub fn connected(link: Link, up: bool) {
+    update(link, |l| {
+        if l.up != up {
+            l.up = up;
+            l.changed = now_secs();
+            match up {
+                true => tracing::info!(link = link.label(), "\x1b[92mlink up\x1b[0m"),
+                false => tracing::warn!(link = link.label(), "link down"),
+            }
+        }
+    });
+}
+
+/// The link carried a message
+pub fn message(link: Link) {
+    update(link, |l| {
+        l.messages += 1;
+        l.last_message = Some(now_secs());
+    });
+}
+
+pub fn report(config: &HealthConfig) -> Report {
+    let now = now_secs();
+    let mut links = LINKS.read().clone();
+    for link in &mut links {
+        // going quiet right after coming up counts too
+        let since = link.last_message.unwrap_or(link.changed).max(link.changed);
+        link.stale = link.up
+            && config
+                .stale_after
+                .is_some_and(|after| now.saturating_sub(since) > after.as_secs());
+    }
+    Report {
+        healthy: links.iter().all(|l| l.up && !l.stale),
+        uptime: STARTED.elapsed().as_secs(),
+        links,
+    }
+}
+
+/// Prometheus text format
+fn metrics(report: &Report, service: &str) -> String {
+    let mut out = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, values: Vec<(Option<Link>, u64)>| {
+        let _ = writeln!(out, "# HELP aussiebot_{} {}", name, help);
+        let _ = writeln!(out, "# TYPE aussiebot_{} {}", name, kind);
+        for (link, value) in values {
+            let _ = match link {
+                Some(link) => writeln!(
+                    out,
+                    "aussiebot_{}{{service=\"{}\",link=\"{}\"}} {}",
+                    name,
+                    service,
+                    link.label(),
+                    value
+                ),
+                None => writeln!(
+                    out,
+                    "aussiebot_{}{{service=\"{}\"}} {}",
+                    name, service, value
+                ),
+            };
+        }
+    };
+    let per_link = |f: fn(&LinkReport) -> Option<u64>| -> Vec<_> {
+        report
+            .links
+            .iter()
+            .filter_map(|l| Some((Some(l.link), f(l)?)))
+            .collect()
+    };
+
+    metric(
+        "healthy",
+        "gauge",
+        "Whether every link is up and none has gone stale",
+        vec![(None, report.healthy as u64)],
+    );
+    metric(
+        "uptime_seconds",
+        "gauge",
+        "Seconds since the service started",
+        vec![(None, report.uptime)],
+    );
+    metric(
+        "link_up",
+        "gauge",
+        "Whether the link is connected",
+        per_link(|l| Some(l.up as u64)),
+    );
+    metric(
+        "link_stale",
+        "gauge",
+        "Whether the link is up but hasn't carried anything in a while",
+        per_link(|l| Some(l.stale as u64)),
+    );
+    metric(
+        "link_changed_timestamp_seconds",
+        "gauge",
+        "When the link last went up or down",
+        per_link(|l| Some(l.changed)),
+    );
+    metric(
+        "link_messages_total",
+        "counter",
+        "Messages the link has carried",
+        per_link(|l| Some(l.messages)),
+    );
+    metric(
+        "link_last_message_timestamp_seconds",
+        "gauge",
+        "When the link last carried a message",
+        per_link(|l| l.last_message),
+    );
+    for (name, help, counter) in COUNTERS {
+        metric(
+            name,
+            "counter",
+            help,
+            vec![(None, counter.load(Ordering::Relaxed))],
+        );
+    }
+    out
+}
+
+/// Answer `GET /healthz` with the Report, 200 if healthy and 503 otherwise, and `GET /metrics`
+pub async fn serve(config: HealthConfig, service: &'static str) {
+    serve_http(config.bind, "/healthz and /metrics", move |path| {
+        let report = report(&config);
+        match path {
+            "/healthz" => Some((
+                match report.healthy {
+                    true => "200 OK",
+                    false => "503 Service Unavailable",
+                },
+                JSON,
+                serde_json::to_string(&report).unwrap_or_default(),
+            )),
+            "/metrics" => Some(("200 OK", PROMETHEUS, metrics(&report, service))),
+            _ => None,
+        }
+    })
+    .await
+}
+
+/// Just enough HTTP for probes. `route` answers a GET for a path with (status, content type, body),
+/// anything else is a 404
+pub(crate) async fn serve_http<F>(bind: SocketAddr, what: &str, route: F)
+where
+    F: Fn(&str) -> Option<(&'static str, &'static str, String)> + Send + Sync + 'static,
+{
+    let listener = match TcpListener::bind(bind).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            tracing::error!(%bind, "couldn't serve {}: {}", what, e);
+            return;
+        }
+    };
+    tracing::info!(%bind, "serving {}", what);
+
+    let route = Arc::new(route);
+    while let Ok((mut stream, _)) = listener.accept().await {
+        let route = route.clone();
+        tokio::spawn(async move {
+            // only the request line matters
+            let mut buf = [0u8; 1024];
+            let n = match stream.read(&mut buf).await {
+                Ok(n) => n,
+                Err(_) => return,
+            };
+            let request = String::from_utf8_lossy(&buf[..n]);
+            let answer = match request.split_whitespace().take(2).collect::<Vec<_>>()[..] {
+                ["GET", path] => route(path),
+                _ => None,
+            };
+            let (status, content_type, body) =
+                answer.unwrap_or(("404 Not Found", JSON, String::new()));
+            let response = format!(
+                "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                content_type,
+                body.len(),
+                body
+            );
+            let _ = stream.write_all(response.as_bytes()).await;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exports_the_counters() {
+        crate::msg::UNSIGNED_DROPPED.fetch_add(3, Ordering::Relaxed);
+        let report = Report {
+            healthy: true,
+            uptime: 5,
+            links: vec![],
+        };
+        let out = metrics(&report, "backrs");
+        assert!(out.contains("# TYPE aussiebot_unsigned_dropped_total counter\n"));
+        let line = out
+            .lines()
+            .find(|l| l.starts_with("aussiebot_unsigned_dropped_total{service=\"backrs\"} "))
+            .unwrap();
+        assert!(line.rsplit(' ').next().unwrap().parse::<u64>().unwrap() >= 3);
+        for name in [
+            "duplicates_suppressed_total",
+            "commands_timed_out_total",
+            "bonus_points_granted_total",
+        ] {
+            assert!(out.contains(&format!("# TYPE aussiebot_{} counter\n", name)));
+        }
+    }
+}
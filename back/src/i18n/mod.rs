@@ -0,0 +1,466 @@
+use crate::error;
+use once_cell::sync::{Lazy, OnceCell};
+use std::{collections::HashMap, fmt::Display, fmt::Write, io::ErrorKind};
+use tokio::fs;
+
+/// Built-in English responses, used for any key missing from the locale file
+static EN: &[(&str, &str)] = &[
+    ("bulkmod.and_more", " and {count} more"),
+    (
+        "bulkmod.confirm_banwave",
+        "{count} user{s} matched: {users}. {prefix} confirm within {secs}s to ban them",
+    ),
+    (
+        "bulkmod.confirm_purge",
+        "{prefix} confirm within {secs}s to delete {user}'s recent messages",
+    ),
+    (
+        "bulkmod.confirm_untimeout",
+        "{prefix} confirm within {secs}s to lift {user}'s timeout",
+    ),
+    ("bulkmod.too_many", "⚠ {count} matches, over the limit of {max}"),
+    ("bulkmod.no_matches", "No recent chatters match {pattern}"),
+    ("bulkmod.bad_pattern", "⚠ Invalid pattern: {error}"),
+    (
+        "bulkmod.log_unavailable",
+        "⚠ Couldn't read the chat log, try again in a bit",
+    ),
+    (
+        "bulkmod.nothing_pending",
+        "⚠ Nothing to confirm, it's only held for {secs}s",
+    ),
+    ("bulkmod.unknown_user", "⚠ Haven't seen {user} in chat"),
+    ("bulkmod.purged", "Purged {user}'s recent messages"),
+    ("bulkmod.untimed_out", "Lifted {user}'s timeout"),
+    ("bulkmod.banned", "Banned {count} user{s}"),
+    ("channel.routed", "{hint} posts now go to <#{id}>"),
+    ("channel.reset", "Reset where {hint} posts go"),
+    (
+        "channel.unknown",
+        "⚠ There's no {hint} channel, try announce, mod-log or bot-spam",
+    ),
+    ("clip.created", "clipped it! {url}"),
+    ("clip.offline", "⚠ The twitch stream is offline"),
+    ("clip.failed", "⚠ Twitch didn't finish the clip, try again in a bit"),
+    ("clip.in_progress", "⚠ A clip's already being made"),
+    ("clip.unavailable", "⚠ Clipping isn't set up"),
+    ("customcmd.added", "Added {prefix}"),
+    ("customcmd.edited", "Updated {prefix}"),
+    ("customcmd.removed", "Removed {prefix}"),
+    (
+        "customcmd.bad_value",
+        "⚠ {value} isn't a valid value for --{flag}",
+    ),
+    (
+        "customcmd.bad_flag",
+        "⚠ Unknown option --{flag}, try --perms, --cooldown, --user-cooldown or --platforms",
+    ),
+    ("customcmd.too_long", "⚠ The reply can be at most {max} characters"),
+    (
+        "customcmd.bad_prefix",
+        "⚠ The prefix has to be 1 to {max} characters",
+    ),
+    ("customcmd.no_text", "⚠ What should {prefix} reply with?"),
+    ("customcmd.taken", "⚠ {prefix} is already used by {name}"),
+    ("customcmd.not_found", "⚠ There's no text command called {prefix} that was added from chat"),
+    ("customcmd.nothing", "⚠ Nothing to change for {prefix}"),
+    (
+        "customcmd.conflict",
+        "⚠ Couldn't save, that prefix clashes with another command",
+    ),
+    ("customcmd.gone", "⚠ Couldn't save, the command was changed in the meantime"),
+    ("customcmd.busy", "⚠ The config is being changed, try again in a bit"),
+    (
+        "duel.challenge",
+        "{challenger} challenged {name} to a duel for {amount}! {name}, type {accept} or {decline} within {secs}s",
+    ),
+    (
+        "duel.called_off",
+        "The duel's off, {name} doesn't have enough to cover the wager",
+    ),
+    ("duel.declined", "{name} declined {challenger}'s duel"),
+    ("duel.insufficient", "⚠ You don't have enough {currency}"),
+    ("duel.none", "No one's challenged you, or it's expired"),
+    ("duel.pending", "{name} already has a duel waiting"),
+    ("duel.unknown", "Couldn't find {name}"),
+    ("duel.won", "{winner} beat {loser} and took {amount}!"),
+    ("emotes.top", "Top emotes: {emotes}"),
+    ("emotes.entry", "{name} ({count})"),
+    ("emotes.empty", "No emotes have been used yet"),
+    ("errors.dm_not_allowed", "{cmd} only works in chat"),
+    ("errors.invalid_args", "Invalid arguments"),
+    ("errors.invalid_args_usage", "Invalid arguments, usage: {usage}"),
+    ("errors.not_ready", "⚠ Still starting up, try again in a bit"),
+    (
+        "errors.insufficient_perms",
+        "You don't have permission to use {cmd}",
+    ),
+    (
+        "errors.out_of_range",
+        "That has to be between {min} and {max}",
+    ),
+    ("gamble.won", "rolled {roll} and won {amount}!"),
+    ("gamble.even", "rolled {roll} and broke even"),
+    ("gamble.lost", "rolled {roll} and lost {amount}"),
+    (
+        "gamble.lost_jackpot",
+        "rolled {roll} and lost {amount}, the jackpot is now {jackpot}",
+    ),
+    ("gamble.jackpot", "rolled {roll} and hit the jackpot of {jackpot}!"),
+    ("gamble.insufficient", "⚠ You don't have enough {currency}"),
+    ("give.success", "gave {name} {amount}"),
+    (
+        "heist.started",
+        "started a heist with {amount} point{s}! Type {prefix} <amount> in the next {duration}s to join the crew",
+    ),
+    ("heist.joined", "joined the heist with {amount} point{s}!"),
+    ("heist.already_joined", "You're already in this heist"),
+    ("heist.member", "{name} ({amount})"),
+    ("heist.success", "The heist was a success! Payouts: {crew}"),
+    (
+        "heist.failure",
+        "The heist failed, the crew of {count} lost everything monkaW",
+    ),
+    ("hours.watchtime", "{hours} hour{hours_s} {minutes} minute{minutes_s}"),
+    ("hours.with_voice", "{watchtime}, plus {voice} in voice"),
+    (
+        "link.dm_prompt",
+        "DM Aussiebot with or type \"!link\" in the discord server",
+    ),
+    (
+        "link.code",
+        "Type `!link {code}` within {expiry} sec(s) in the stream's live chat to link that account with your discord",
+    ),
+    ("link.invalid_code", "Invalid or expired code"),
+    ("link.too_many_attempts", "Too many attempts, try again later"),
+    ("link.linked", "Successfully linked!"),
+    (
+        "link.wrong_platform",
+        "Type that code in the stream's live chat instead",
+    ),
+    ("link.unlinked", "Successfully unlinked!"),
+    ("link.nothing_to_unlink", "Nothing to unlink"),
+    ("list.separator", ", "),
+    ("list.and", " and "),
+    ("memebank.not_found", "⚠ Not found"),
+    ("memebank.renamed", "Renamed `{old}` to `{new}`"),
+    ("memebank.removed", "Removed `{name}`: {link}"),
+    ("memebank.added", "Added `{name}`: {link}"),
+    ("memebank.invalid_link", "⚠ Invalid link"),
+    ("memebank.list_item", ":small_orange_diamond: {name}"),
+    ("memebank.empty", "⚠ No items saved"),
+    ("memebank.total", "(_{count} item{s} in total_)"),
+    ("memebank.cleared", "Items cleared"),
+    ("memebank.submitted", "Sent `{name}` to the mods, it's #{id} in the queue"),
+    ("memebank.mods_only", "⚠ Only mods can look at the queue"),
+    ("memebank.queue_empty", "No memes waiting"),
+    ("memebank.queue_item", "#{id} `{name}` by {user} ({rejected} rejected before): {link}"),
+    ("memebank.approved", "Approved #{id} `{name}` from {user}"),
+    ("memebank.rejected", "Rejected #{id} `{name}` from {user}"),
+    ("memebank.not_queued", "⚠ #{id} isn't in the queue"),
+    ("memebank.rejected_note", "{count} memes rejected"),
+    (
+        "multiplier.started",
+        "{factor} points on {platforms} for the next {remaining}!",
+    ),
+    ("multiplier.status", "{factor} points for another {remaining}"),
+    ("multiplier.stopped", "Multiplier's over, it gave out {bonus} extra"),
+    ("multiplier.none", "No multiplier running"),
+    ("multiplier.announce", "{factor} points for another {remaining}"),
+    (
+        "multiplier.usage",
+        "Usage: {prefix} [<2x-{max}x> <1h|30m> [platform]|off [platform]]",
+    ),
+    ("notes.added", "Noted #{id} on {user}"),
+    ("notes.entry", "#{id} {note} (by {author})"),
+    ("notes.list", "Notes on {user}: {notes}"),
+    ("notes.sent", "{count} note{s} on {user}, see the web UI"),
+    ("notes.none", "No notes on {user}"),
+    ("notes.removed", "Removed note #{id} on {user}"),
+    ("notes.not_found", "⚠ No note #{id}"),
+    ("notes.unknown_user", "⚠ Haven't seen {user} in chat"),
+    ("perms.level", "{user} is {level} ({source})"),
+    ("perms.from_platform", "from {platform}"),
+    ("perms.from_rule", "from the {rule} role"),
+    ("perms.unknown", "⚠ Haven't seen {user} in chat lately"),
+    ("points.entry", "{points} ({platform})"),
+    ("ping.delivered", "Your ping went out on {platform}"),
+    ("ping.failed", "⚠ Your ping couldn't be delivered on {platform}"),
+    (
+        "ping.queued",
+        "⚠ {platform} isn't answering, your ping will go out once it's back",
+    ),
+    ("poll.option", "{n}. {option}"),
+    ("poll.started", "Poll: {question} Vote with {options}"),
+    ("poll.running", "Poll: {question} Vote with {options} ({total} vote{s} so far)"),
+    ("poll.result", "{n}. {option}: {votes} ({pct}%)"),
+    ("poll.ended", "Poll closed: {question} {results} ({total} vote{s})"),
+    ("poll.already_running", "⚠ There's already a poll running, end it first"),
+    ("poll.not_running", "⚠ There's no poll running"),
+    ("poll.options", "⚠ Polls need {min} to {max} options"),
+    ("prediction.option", "{n}. {outcome}"),
+    ("prediction.total", "{n}. {outcome}: {amount} from {bettors}"),
+    (
+        "prediction.opened",
+        "Prediction: {question} Bet with {bet} <amount> <outcome> in the next {window}s: {outcomes}",
+    ),
+    ("prediction.running", "Prediction: {question} {totals}"),
+    (
+        "prediction.locked",
+        "Bets are closed on {question} with {bettors} bettor{s} waiting on the result",
+    ),
+    ("prediction.bet", "bet {amount} on {outcome}"),
+    (
+        "prediction.resolved",
+        "Prediction over, {outcome} it is! {users} winner{s} split {pool}",
+    ),
+    (
+        "prediction.refunded",
+        "Prediction over, {outcome} it is! Nobody picked it, so {pool} was refunded",
+    ),
+    ("prediction.cancelled", "Prediction cancelled, {pool} was refunded"),
+    ("prediction.already_running", "⚠ There's already a prediction running, resolve it first"),
+    ("prediction.not_running", "⚠ There's no prediction running"),
+    ("prediction.outcomes", "⚠ Predictions need {min} to {max} outcomes"),
+    ("prediction.unknown_outcome", "⚠ That's not one of the outcomes"),
+    ("prediction.closed", "⚠ Bets are closed"),
+    ("prediction.insufficient", "⚠ You don't have enough {currency}"),
+    ("prediction.other_outcome", "⚠ You already bet on {outcome}"),
+    ("queue.entrant", "{n}. {name}"),
+    ("queue.list", "Up next: {next} ({size}/{max} waiting)"),
+    ("queue.empty", "Nobody's in the queue"),
+    ("queue.joined", "joined the queue at #{position}"),
+    ("queue.already_joined", "⚠ You're already in the queue at #{position}"),
+    ("queue.full", "⚠ The queue's full ({max} waiting), try again later"),
+    ("queue.left", "left the queue"),
+    ("queue.not_joined", "⚠ You're not in the queue, join with {join}"),
+    ("queue.position", "You're #{position} in the queue"),
+    ("queue.next", "{name}, you're up! ({waiting} other{s} waiting)"),
+    ("queue.cleared", "Queue cleared"),
+    ("role_reward.awarded", "Enjoy {label}!"),
+    ("role_reward.short", "You need {points} more points for {label}"),
+    ("russian_roulette.immune", "(immune) "),
+    (
+        "russian_roulette.started",
+        "{immune}started a game of russian roulette with the '{penalty}' penalty for {amount} point{s}!",
+    ),
+    (
+        "russian_roulette.joined",
+        "{immune}joined the russian roulette game with {amount} point{s}!",
+    ),
+    (
+        "russian_roulette.lobby.started",
+        "started a russian roulette lobby with a {amount} point{s} buy-in! Join with {prefix} join in the next {duration}s",
+    ),
+    (
+        "russian_roulette.lobby.joined",
+        "joined the russian roulette lobby for {amount} point{s}!",
+    ),
+    (
+        "russian_roulette.lobby.already_joined",
+        "You're already in this lobby",
+    ),
+    (
+        "russian_roulette.lobby.running",
+        "⚠ A game's already running, join the next one with {prefix} join",
+    ),
+    (
+        "russian_roulette.lobby.none",
+        "⚠ No lobby's open, start one with {prefix} start <buy-in>",
+    ),
+    (
+        "russian_roulette.lobby.buy_in",
+        "⚠ The buy-in has to be between {min} and {max} points",
+    ),
+    (
+        "russian_roulette.lobby.too_few",
+        "Not enough players for russian roulette ({count}/{min}), everyone's been refunded",
+    ),
+    (
+        "russian_roulette.lobby.closed",
+        "The lobby's closed with {count} players and {pot} points in the pot. Let's begin...",
+    ),
+    (
+        "russian_roulette.lobby.round",
+        "Round {round}: the cylinder spins... {left} players left",
+    ),
+    ("russian_roulette.lobby.eliminated", "💥 BANG! {name} is out"),
+    (
+        "russian_roulette.lobby.winner",
+        "{name} is the last one standing and takes the {pot} point pot!",
+    ),
+    (
+        "russian_roulette.no_survivors",
+        "The game is over, there were no survivors monkaW",
+    ),
+    ("russian_roulette.survivors", "The game is over! Survivors: {survivors}"),
+    ("russian_roulette.survivor", "{name} ({amount})"),
+    ("shop.item", "{item} ({cost})"),
+    ("shop.list", "In the shop: {items}. Use {prefix} <item> to redeem"),
+    ("shop.empty", "The shop is empty"),
+    ("shop.not_found", "⚠ No such item in the shop"),
+    ("shop.insufficient", "⚠ You need {cost} points for that"),
+    ("shop.out_of_stock", "⚠ That's out of stock"),
+    ("shop.unavailable", "⚠ That item can't be redeemed"),
+    ("shop.redeemed", "redeemed {item}!"),
+    ("shop.pending", "redeemed {item}, a mod will sort it out soon"),
+    (
+        "raidguard.on",
+        "🛡 Raid mode is on for {mins} minute{s} ({why}), chat's locked down a bit",
+    ),
+    ("raidguard.over", "Raid mode is over, thanks for your patience"),
+    ("raidguard.off", "Raid mode is off"),
+    ("raidguard.not_on", "Raid mode isn't on"),
+    ("raidguard.status", "Raid mode is on for another {mins} minute{s}"),
+    ("raidguard.usage", "Usage: {prefix} [on [minutes]|off]"),
+    ("raidguard.why_new", "{count} new chatters"),
+    ("raidguard.why_messages", "{count} messages"),
+    ("raidguard.why_manual", "turned on by {user}"),
+    ("slowmode.set", "Slow mode: {secs}s between messages"),
+    (
+        "slowmode.set_for",
+        "Slow mode: {secs}s between messages for {mins} minute{s}",
+    ),
+    ("slowmode.off", "Slow mode is off"),
+    ("slowmode.reset", "Slow mode back to {secs}s between messages"),
+    ("slowmode.status", "Slow mode is {secs}s between messages"),
+    ("slowmode.usage", "Usage: {prefix} [<seconds> [minutes]|off|reset]"),
+    ("stats.summary", "Most used {range}: {commands}. Most active: {users}"),
+    ("stats.entry", "{name} ({count})"),
+    ("stats.empty", "No commands have been used {range}"),
+    ("stats.day", "today"),
+    ("stats.week", "this week"),
+    ("stats.month", "this month"),
+    ("stats.all", "ever"),
+    ("shoutout.not_found", "⚠ There's no channel called {login}"),
+    ("shoutout.unknown", "something"),
+    ("transfer.success", "transferred {amount} from {from} to {to}"),
+    ("uptime.offline", "The stream is offline"),
+    (
+        "uptime.elapsed",
+        "{hours} hour{hours_s} {minutes} minute{minutes_s}",
+    ),
+    (
+        "uptime.live",
+        "{platform}: live for {elapsed} with {viewers} viewer{s}",
+    ),
+    ("uptime.title", "{platform}: {title}"),
+    (
+        "uptime.all",
+        "{platform}: {title} (live for {elapsed} with {viewers} viewer{s})",
+    ),
+    ("trivia.question", "Trivia: {question} ({duration}s to answer)"),
+    ("trivia.running", "Trivia: {question}"),
+    ("trivia.won", "{name} got it! The answer was {answer}"),
+    (
+        "trivia.won_points",
+        "{name} got it and won {amount}! The answer was {answer}",
+    ),
+    ("trivia.timeout", "Time's up! The answer was {answer}"),
+    ("trivia.skipped", "Skipped, the answer was {answer}"),
+    ("trivia.not_running", "⚠ There's no trivia question running"),
+    ("trivia.unavailable", "⚠ Couldn't load the trivia questions"),
+    ("trivia.scores", "Trivia scores: {scores}"),
+    ("trivia.score", "{name} ({count})"),
+    ("trivia.no_scores", "Nobody's answered a trivia question yet this stream"),
+    ("trivia.reset", "Trivia scores cleared"),
+    ("stream_meta.title", "{streams} title set to {value}"),
+    ("stream_meta.game", "{streams} game set to {value}"),
+    (
+        "wordlist.added",
+        "Added a tier {tier} entry ({count} in total)",
+    ),
+    ("wordlist.removed", "Removed ({count} left)"),
+    ("wordlist.not_found", "⚠ Not found"),
+    ("wordlist.count", "{count} in the list"),
+    (
+        "wordlist.usage",
+        "Usage: {prefix} [add [tier] <phrase>|remove <phrase>]",
+    ),
+];
+
+static FALLBACK: Lazy<HashMap<&'static str, &'static str>> =
+    Lazy::new(|| EN.iter().copied().collect());
+
+static LOCALE: OnceCell<HashMap<String, String>> = OnceCell::new();
+
+/// Load the locale file for the configured language, if there is one
+#[tracing::instrument]
+pub async fn load() -> error::Result<()> {
+    let path = crate::config_dir()
+        .join("locales")
+        .join(format!("{}.json", crate::language()));
+
+    let contents = match fs::read_to_string(&path).await {
+        Ok(c) => c,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            if crate::language() != "en" {
+                tracing::warn!("no locale file at {:?}, falling back to English", path);
+            }
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    // deserialise
+    let strings: HashMap<String, String> = serde_json::from_str(&contents)?;
+
+    for (key, _) in EN.iter().filter(|(k, _)| !strings.contains_key(*k)) {
+        tracing::debug!("locale {} is missing {}", crate::language(), key);
+    }
+
+    let _ = LOCALE.set(strings);
+
+    Ok(())
+}
+
+/// Look up a response by key and fill in its {var} placeholders
+pub(crate) fn tr(key: &str, vars: &[(&str, &dyn Display)]) -> String {
+    let template = LOCALE
+        .get()
+        .and_then(|l| l.get(key))
+        .map(String::as_str)
+        .or_else(|| FALLBACK.get(key).copied())
+        .unwrap_or(key);
+
+    fill(template, vars)
+}
+
+/// Plural suffix for English-style templates, e.g. `point{s}`
+pub(crate) fn plural<T: PartialEq + From<u8>>(n: T) -> &'static str {
+    if n != T::from(1) {
+        "s"
+    } else {
+        ""
+    }
+}
+
+/// Unknown placeholders are left as is
+pub(crate) fn fill(template: &str, vars: &[(&str, &dyn Display)]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+
+        let var = after.find('}').and_then(|end| {
+            let name = &after[..end];
+            vars.iter().find(|(k, _)| *k == name).map(|(_, v)| (end, v))
+        });
+
+        match var {
+            Some((end, value)) => {
+                write!(out, "{}", value).unwrap();
+                rest = &after[end + 1..];
+            }
+            None => {
+                out.push('{');
+                rest = after;
+            }
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
@@ -0,0 +1,107 @@
+//! Which instance runs timers, logs and the other background tasks when there's more than one.
+//!
+//! Every instance tries to lease [`LEADER_KEY`] under its INSTANCE_ID every [`RENEW_EVERY`].
+//! The holder keeps extending it, so it only changes hands once the leader stops renewing,
+//! when it shuts down or dies and [`LEASE_TTL`] secs pass. A leader that can't renew steps down
+//! before then, while the lease it last got still holds, so two never lead at once
+
+use crate::{error::Error, lock};
+use once_cell::sync::Lazy;
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+use tokio::sync::watch;
+use tracing::{info_span, Instrument};
+
+const RENEW_EVERY: Duration = Duration::from_secs(10);
+const LEASE_TTL: u64 = 30;
+
+static LEADER_KEY: Lazy<String> =
+    Lazy::new(|| format!("aussiebot!{}!leader", crate::channel_name()));
+
+static IS_LEADER: AtomicBool = AtomicBool::new(false);
+
+/// Whether the lease could lapse before the next try at renewing it, by when someone else may
+/// have taken it
+fn may_lapse(since_renewed: Duration) -> bool {
+    since_renewed + RENEW_EVERY >= Duration::from_secs(LEASE_TTL)
+}
+
+/// Whether this instance should be running the background tasks
+pub fn is_leader() -> bool {
+    IS_LEADER.load(Ordering::Relaxed)
+}
+
+/// Contend for the lease, the first try being straight away. Whether this instance has it
+/// is sent on the returned channel whenever that changes
+pub fn start(lock: lock::Handle) -> watch::Receiver<bool> {
+    let (tx, rx) = watch::channel(false);
+    let instance = &crate::config::server().instance;
+
+    tokio::spawn(
+        async move {
+            let mut interval = tokio::time::interval(RENEW_EVERY);
+            let mut last_renewed = Instant::now();
+            loop {
+                interval.tick().await;
+                // the lease runs from when it was asked for, not from when the answer came back
+                let attempted = Instant::now();
+                let leading = match tokio::time::timeout(
+                    RENEW_EVERY,
+                    lock.lease(&*LEADER_KEY, instance.as_str(), LEASE_TTL),
+                )
+                .await
+                .unwrap_or_else(|_| Err(Error::Generic("timed out".into())))
+                {
+                    Ok(leading) => {
+                        last_renewed = attempted;
+                        leading
+                    }
+                    Err(e) if is_leader() && !may_lapse(last_renewed.elapsed()) => {
+                        tracing::warn!("couldn't renew the lease: {}", e);
+                        continue;
+                    }
+                    Err(e) => {
+                        tracing::error!("couldn't renew the lease: {}", e);
+                        false
+                    }
+                };
+
+                if IS_LEADER.swap(leading, Ordering::Relaxed) != leading {
+                    tracing::info!(leading, "\x1b[93mleadership changed\x1b[0m");
+                    if tx.send(leading).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+        .instrument(info_span!("leader", instance = instance.as_str())),
+    );
+    rx
+}
+
+/// Hand the lease over now rather than after it lapses, when shutting down
+pub async fn resign(lock: &lock::Handle) {
+    if !IS_LEADER.swap(false, Ordering::Relaxed) {
+        return;
+    }
+    let instance = crate::config::server().instance.as_str();
+    if let Err(e) = lock.release(&*LEADER_KEY, instance).await {
+        tracing::error!("couldn't give up the lease: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_down_before_the_lease_lapses() {
+        assert!(!may_lapse(Duration::ZERO));
+        assert!(!may_lapse(RENEW_EVERY));
+        // one more missed renewal and it'd be gone
+        assert!(may_lapse(Duration::from_secs(LEASE_TTL) - RENEW_EVERY));
+        assert!(may_lapse(Duration::from_secs(LEASE_TTL)));
+    }
+}
@@ -1,57 +1,143 @@
+#[cfg(feature = "server")]
 use bb8::Pool;
+#[cfg(feature = "server")]
 use bb8_postgres::PostgresConnectionManager;
+#[cfg(feature = "server")]
 use bb8_redis::RedisConnectionManager;
+#[cfg(feature = "server")]
+use config::{DatabaseConfig, RedisConfig};
+#[cfg(feature = "server")]
 use error::Error;
-use once_cell::sync::Lazy;
-use tokio_postgres::NoTls;
+#[cfg(feature = "server")]
+use std::path::Path;
+#[cfg(feature = "server")]
+use tokio_postgres::config::SslMode;
 
+#[cfg(feature = "server")]
 pub mod auth;
+#[cfg(feature = "server")]
 pub mod cache;
+#[cfg(feature = "server")]
 pub mod cmds;
+#[cfg(feature = "server")]
+pub mod config;
+#[cfg(feature = "server")]
 pub mod db;
+#[cfg(feature = "server")]
 pub mod error;
+#[cfg(feature = "server")]
+pub mod flags;
+#[cfg(feature = "server")]
+pub mod health;
+#[cfg(feature = "server")]
+pub mod i18n;
+#[cfg(feature = "server")]
+pub mod leader;
+#[cfg(feature = "server")]
 pub mod lock;
+#[cfg(feature = "server")]
+pub mod log_level;
+#[cfg(feature = "server")]
 pub mod msg;
+#[cfg(feature = "server")]
 pub mod pubsub;
+#[cfg(feature = "server")]
+pub mod shard;
+#[cfg(feature = "server")]
+pub mod telemetry;
+#[cfg(feature = "server")]
+pub mod twitch;
+#[cfg(feature = "server")]
+pub mod urls;
+#[cfg(feature = "server")]
 pub mod ws;
 
+/// Message types shared with clients, built without the `server` feature too
+pub mod proto;
+
+#[cfg(feature = "server")]
 pub type RedisPool = Pool<RedisConnectionManager>;
-pub type DbPool = Pool<PostgresConnectionManager<NoTls>>;
+#[cfg(feature = "server")]
+pub type DbPool = Pool<PostgresConnectionManager<db::tls::MakeConnect>>;
 
 pub fn assert_sync<T: ?Sized + Sync>() {}
 pub fn assert_send<T: ?Sized + Send>() {}
 pub fn assert_send_val<T: ?Sized + Send>(_t: &T) {}
 pub fn assert_send_sync_val<T: ?Sized + Sync + Send>(_t: &T) {}
 
-pub static CHANNEL_NAME: Lazy<String> =
-    Lazy::new(|| dotenv::var("CHANNEL_NAME").unwrap().to_lowercase());
-pub static UPSTREAM_CHAN: Lazy<String> =
-    Lazy::new(|| dotenv::var("UPSTREAM_CHAN").unwrap().to_lowercase());
-pub static DOWNSTREAM_CHAN: Lazy<String> =
-    Lazy::new(|| dotenv::var("DOWNSTREAM_CHAN").unwrap().to_lowercase());
-pub static WS_BIND: Lazy<String> = Lazy::new(|| dotenv::var("WS_BIND").unwrap());
-pub static CONFIG_DIR: Lazy<String> = Lazy::new(|| dotenv::var("CONFIG_DIR").unwrap());
+/// Lowercased, the same as [`config::Config::channel_name`]. Only valid after Config::init
+#[cfg(feature = "server")]
+pub fn channel_name() -> &'static str {
+    &config::get().channel_name
+}
+
+/// Only valid after ServerConfig::init
+#[cfg(feature = "server")]
+pub fn config_dir() -> &'static Path {
+    &config::server().config_dir
+}
+
+/// Response language, loaded from `CONFIG_DIR/locales/<lang>.json`
+#[cfg(feature = "server")]
+pub fn language() -> &'static str {
+    &config::get().language
+}
+
+#[cfg(feature = "server")]
+#[tracing::instrument]
+pub async fn init_db(config: &DatabaseConfig) -> error::Result<DbPool> {
+    connect_db(config.config.clone(), config).await
+}
 
+/// Reads go to the primary if there's no replica or it can't be reached
+#[cfg(feature = "server")]
 #[tracing::instrument]
-pub async fn init_db() -> error::Result<DbPool> {
-    let manager = bb8_postgres::PostgresConnectionManager::new_from_stringlike(
-        dotenv::var("DATABASE_CONFIG").expect("DATABASE_CONFIG env var"),
-        tokio_postgres::NoTls,
-    )?;
+pub async fn init_read_db(config: &DatabaseConfig) -> Option<DbPool> {
+    let read_config = config.read_config.clone()?;
+    match connect_db(read_config, config).await {
+        Ok(pool) => Some(pool),
+        Err(e) => {
+            tracing::error!("read replica unavailable, reading from the primary: {}", e);
+            None
+        }
+    }
+}
+
+#[cfg(feature = "server")]
+async fn connect_db(
+    mut pg_config: tokio_postgres::Config,
+    config: &DatabaseConfig,
+) -> error::Result<DbPool> {
+    // prefer (the default) would now trip over self-signed certs that went unnoticed before
+    if pg_config.get_ssl_mode() != SslMode::Require {
+        pg_config.ssl_mode(SslMode::Disable);
+    }
+    if let Some(timeout) = config.statement_timeout {
+        let options = format!(
+            "{} -c statement_timeout={}",
+            pg_config.get_options().unwrap_or_default(),
+            timeout.as_millis()
+        );
+        pg_config.options(options.trim());
+    }
+    pg_config.connect_timeout(config.connect_timeout);
+
+    let tls = db::tls::MakeConnect::new(config.tls_ca.as_deref())?;
+    let manager = PostgresConnectionManager::new(pg_config, tls);
     Pool::builder()
-        .max_size(10)
+        .max_size(config.pool_size)
+        .connection_timeout(config.connect_timeout)
         .build(manager)
         .await
         .map_err(Error::Postgres)
 }
 
+#[cfg(feature = "server")]
 #[tracing::instrument]
-pub async fn init_redis() -> error::Result<RedisPool> {
-    let manager = bb8_redis::RedisConnectionManager::new(
-        dotenv::var("REDIS_URL").expect("REDIS_URL env var"),
-    )?;
+pub async fn init_redis(config: &RedisConfig) -> error::Result<RedisPool> {
+    let manager = bb8_redis::RedisConnectionManager::new(config.url.as_str())?;
     Pool::builder()
-        .max_size(10)
+        .max_size(config.pool_size)
         .build(manager)
         .await
         .map_err(Error::Redis)
@@ -0,0 +1,93 @@
+use super::{Answer, HeldLock, Op, TaskChanPair};
+use crate::error::ChanSendError;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use tokio::sync::mpsc;
+
+/// Handles locking
+/// backed by a map in memory, so only locks within this process
+pub(super) struct Actor {
+    rx: mpsc::Receiver<TaskChanPair>,
+    /// key, holder, when it expires
+    locks: HashMap<String, (String, Instant)>,
+}
+
+impl Actor {
+    pub(super) fn new(rx: mpsc::Receiver<TaskChanPair>) -> Self {
+        Self {
+            rx,
+            locks: HashMap::new(),
+        }
+    }
+
+    fn handle_task(&mut self, task: Op) -> Answer {
+        let now = Instant::now();
+        // expired ones are as good as unlocked
+        self.locks.retain(|_, (_, expires)| *expires > now);
+        let done = match task {
+            Op::Lock(key, owner, time) => {
+                if let Some((held, _)) = self.locks.get(&key) {
+                    return Answer::Held(Some(held.clone()));
+                }
+                self.locks
+                    .insert(key, (owner, now + Duration::from_secs(time)));
+                true
+            }
+            Op::Cooldown(key, time) => {
+                if self.locks.contains_key(&key) {
+                    return Answer::Done(false);
+                }
+                self.locks.insert(
+                    key,
+                    ("cooldown".to_owned(), now + Duration::from_secs(time)),
+                );
+                true
+            }
+            Op::Unlock(key) => self.locks.remove(&key).is_some(),
+            Op::Lease(key, holder, time) => match self.locks.get_mut(&key) {
+                Some((current, _)) if *current != holder => false,
+                Some((_, expires)) => {
+                    *expires = now + Duration::from_secs(time);
+                    true
+                }
+                None => {
+                    self.locks
+                        .insert(key, (holder, now + Duration::from_secs(time)));
+                    true
+                }
+            },
+            Op::Release(key, holder) => match self.locks.get(&key) {
+                Some((current, _)) if *current == holder => self.locks.remove(&key).is_some(),
+                _ => false,
+            },
+            Op::List => {
+                let locks = self
+                    .locks
+                    .iter()
+                    .map(|(key, (value, expires))| {
+                        let ttl = expires.saturating_duration_since(now).as_secs() as i64;
+                        HeldLock::new(key.clone(), value, ttl)
+                    })
+                    .collect();
+                return Answer::Locks(locks);
+            }
+        };
+        Answer::Done(done)
+    }
+
+    pub(super) async fn run(mut self) {
+        while let Some((task, tx)) = self.rx.recv().await {
+            let answer = self.handle_task(task);
+            if let Err(e) = tx.send(Ok(answer)) {
+                tracing::error!(
+                    "{}",
+                    ChanSendError {
+                        msg: format!("{:?}", e)
+                    }
+                );
+            }
+        }
+    }
+}
@@ -1,19 +1,133 @@
+mod memory;
+
+pub use crate::proto::admin::HeldLock;
 use crate::{
     error::{self, ChanSendError, Error},
     RedisPool,
 };
 use bb8_redis::redis;
+use once_cell::sync::Lazy;
+use std::{
+    cell::RefCell,
+    future::Future,
+    panic::Location,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 use tokio::sync::{mpsc, oneshot};
 
+tokio::task_local! {
+    /// Locks taken by the future being run by [`Handle::timed`]
+    static TAKEN: RefCell<Vec<String>>;
+}
+
+/// Every key that's locked, scored by when it expires, so they can be listed without a SCAN
+static INDEX_KEY: Lazy<String> = Lazy::new(|| format!("aussiebot!{}!locks", crate::channel_name()));
+
 #[allow(dead_code)]
 #[derive(Debug)]
-enum Lock {
-    Lock(String, u64),
+enum Op {
+    /// key, owner tag, expiry
+    Lock(String, String, u64),
+    /// key, expiry. Not indexed, there's too many of them to list
+    Cooldown(String, u64),
     Unlock(String),
+    /// key, holder, expiry
+    Lease(String, String, u64),
+    /// key, holder
+    Release(String, String),
+    List,
+}
+
+/// Take the key if it's free, or say who has it
+const LOCK_SCRIPT: &str = r#"
+if redis.call('SET', KEYS[1], ARGV[1], 'NX', 'EX', ARGV[2]) then
+    redis.call('ZREMRANGEBYSCORE', KEYS[2], '-inf', ARGV[4])
+    redis.call('ZADD', KEYS[2], ARGV[3], KEYS[1])
+    return 1
+end
+return redis.call('GET', KEYS[1])
+"#;
+const UNLOCK_SCRIPT: &str = r#"
+redis.call('ZREM', KEYS[2], KEYS[1])
+return redis.call('DEL', KEYS[1])
+"#;
+/// Take the key if it's free, or push back its expiry if the holder already has it
+const LEASE_SCRIPT: &str = r#"
+if redis.call('SET', KEYS[1], ARGV[1], 'NX', 'EX', ARGV[2]) then
+    redis.call('ZADD', KEYS[2], ARGV[3], KEYS[1])
+    return 1
+end
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    redis.call('ZADD', KEYS[2], ARGV[3], KEYS[1])
+    return redis.call('EXPIRE', KEYS[1], ARGV[2])
+end
+return 0
+"#;
+/// Delete the key, only if it's still the holder's
+const RELEASE_SCRIPT: &str = r#"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    redis.call('ZREM', KEYS[2], KEYS[1])
+    return redis.call('DEL', KEYS[1])
+end
+return 0
+"#;
+/// key, owner, ttl for every lock still held, dropping the rest from the index
+const LIST_SCRIPT: &str = r#"
+redis.call('ZREMRANGEBYSCORE', KEYS[1], '-inf', ARGV[1])
+local out = {}
+for _, key in ipairs(redis.call('ZRANGE', KEYS[1], 0, -1)) do
+    local owner = redis.pcall('GET', key)
+    if type(owner) == 'string' then
+        table.insert(out, key)
+        table.insert(out, owner)
+        table.insert(out, tostring(redis.call('TTL', key)))
+    else
+        redis.call('ZREM', KEYS[1], key)
+    end
+end
+return out
+"#;
+
+impl HeldLock {
+    fn new(key: String, value: &str, ttl: i64) -> Self {
+        let (owner, since) = parse_owner(value);
+        Self {
+            key,
+            owner: owner.to_owned(),
+            since,
+            ttl,
+        }
+    }
 }
 
-type Resp = error::Result<bool>;
-type TaskChanPair = (Lock, oneshot::Sender<Resp>);
+/// Locks are stored as `owner|since`, leases as just the holder
+fn parse_owner(value: &str) -> (&str, u64) {
+    match value.rsplit_once('|') {
+        Some((owner, since)) => match since.parse() {
+            Ok(since) => (owner, since),
+            Err(_) => (value, 0),
+        },
+        None => (value, 0),
+    }
+}
+
+#[derive(Debug)]
+enum Answer {
+    Done(bool),
+    /// Didn't get the lock, and the value of whoever has it
+    Held(Option<String>),
+    Locks(Vec<HeldLock>),
+}
+
+type Resp = error::Result<Answer>;
+type TaskChanPair = (Op, oneshot::Sender<Resp>);
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
 
 struct Actor {
     rx: mpsc::Receiver<TaskChanPair>,
@@ -27,44 +141,93 @@ impl Actor {
         Self { rx, pool }
     }
 
-    async fn handle_task(pool: RedisPool, (task, tx): TaskChanPair) -> error::Result<()> {
+    async fn query(pool: RedisPool, task: Op) -> redis::RedisResult<Answer> {
         let mut conn = pool.get().await.unwrap();
+        let now = now_secs();
         match task {
-            Lock::Lock(key, time) => {
+            Op::Lock(key, owner, time) => {
                 // try to acquire lock
-                let locked = redis::cmd("SET")
-                    .arg(&[&key, "1", "NX", "EX", &time.to_string()])
-                    .query_async::<redis::aio::Connection, bool>(&mut conn)
-                    .await
-                    .map_err(Error::Redis);
-                // send result
-                tx.send(locked).map_err(|e| {
-                    ChanSendError {
-                        msg: format!("{:?}", e),
+                let expiry = (now + time).to_string();
+                let resp = redis::cmd("EVAL")
+                    .arg(&[
+                        LOCK_SCRIPT,
+                        "2",
+                        &key,
+                        &INDEX_KEY,
+                        &owner,
+                        &time.to_string(),
+                        &expiry,
+                        &now.to_string(),
+                    ])
+                    .query_async::<redis::aio::Connection, redis::Value>(&mut conn)
+                    .await?;
+                Ok(match resp {
+                    redis::Value::Int(_) => Answer::Done(true),
+                    redis::Value::Data(ref held) => {
+                        Answer::Held(Some(String::from_utf8_lossy(held).into_owned()))
                     }
-                    .into()
+                    _ => Answer::Held(None),
                 })
-                //println!("acquired lock: {:?} ({})", locked, &key);
             }
-            Lock::Unlock(key) => {
+            Op::Cooldown(key, time) => redis::cmd("SET")
+                .arg(&[&key, "1", "NX", "EX", &time.to_string()])
+                .query_async::<redis::aio::Connection, Option<String>>(&mut conn)
+                .await
+                .map(|set| Answer::Done(set.is_some())),
+            Op::Unlock(key) => {
                 // try to release lock
-                let unlocked = redis::cmd("DEL")
-                    .arg(&key)
+                redis::cmd("EVAL")
+                    .arg(&[UNLOCK_SCRIPT, "2", &key, &INDEX_KEY])
                     .query_async::<redis::aio::Connection, bool>(&mut conn)
                     .await
-                    .map_err(Error::Redis);
-                // send result
-                tx.send(unlocked).map_err(|e| {
-                    ChanSendError {
-                        msg: format!("{:?}", e),
-                    }
-                    .into()
-                })
-                //println!("released lock: {:?} ({})", unlocked, &key);
+                    .map(Answer::Done)
+            }
+            Op::Lease(key, holder, time) => {
+                let expiry = (now + time).to_string();
+                redis::cmd("EVAL")
+                    .arg(&[
+                        LEASE_SCRIPT,
+                        "2",
+                        &key,
+                        &INDEX_KEY,
+                        &holder,
+                        &time.to_string(),
+                        &expiry,
+                    ])
+                    .query_async::<redis::aio::Connection, bool>(&mut conn)
+                    .await
+                    .map(Answer::Done)
+            }
+            Op::Release(key, holder) => redis::cmd("EVAL")
+                .arg(&[RELEASE_SCRIPT, "2", &key, &INDEX_KEY, &holder])
+                .query_async::<redis::aio::Connection, bool>(&mut conn)
+                .await
+                .map(Answer::Done),
+            Op::List => {
+                let flat = redis::cmd("EVAL")
+                    .arg(&[LIST_SCRIPT, "1", &INDEX_KEY, &now.to_string()])
+                    .query_async::<redis::aio::Connection, Vec<String>>(&mut conn)
+                    .await?;
+                let locks = flat
+                    .chunks_exact(3)
+                    .map(|l| HeldLock::new(l[0].clone(), &l[1], l[2].parse().unwrap_or(-1)))
+                    .collect();
+                Ok(Answer::Locks(locks))
             }
         }
     }
 
+    async fn handle_task(pool: RedisPool, (task, tx): TaskChanPair) -> error::Result<()> {
+        let resp = Self::query(pool, task).await;
+        // send result
+        tx.send(resp.map_err(Error::Redis)).map_err(|e| {
+            ChanSendError {
+                msg: format!("{:?}", e),
+            }
+            .into()
+        })
+    }
+
     async fn run(mut self) {
         while let Some(msg) = self.rx.recv().await {
             let pool = self.pool.clone();
@@ -91,23 +254,166 @@ impl Handle {
         Self { tx }
     }
 
-    //#[tracing::instrument(skip(self, key), fields(key), ret)]
-    pub async fn lock(&self, key: impl Into<String>, time: u64) -> error::Result<bool> {
-        let key = key.into();
-        tracing::Span::current().record("key", &key.as_str());
+    /// Kept in this process instead of redis, for running without it
+    pub fn memory() -> Self {
+        let (tx, rx) = mpsc::channel(32);
+        tokio::spawn(memory::Actor::new(rx).run());
+        Self { tx }
+    }
+
+    async fn send(&self, op: Op) -> Resp {
         let (resp_tx, resp_rx) = oneshot::channel::<Resp>();
-        self.tx.send((Lock::Lock(key, time), resp_tx)).await?;
+        self.tx.send((op, resp_tx)).await?;
         // TODO: implement a timeout here
         resp_rx.await?
     }
 
+    async fn send_bool(&self, op: Op) -> error::Result<bool> {
+        match self.send(op).await? {
+            Answer::Done(done) => Ok(done),
+            Answer::Held(_) => Ok(false),
+            Answer::Locks(_) => unreachable!(),
+        }
+    }
+
+    /// Take the lock for `time` secs if it's free. It's tagged with where it was taken from,
+    /// which is what [`Handle::list`] shows as the owner
+    #[track_caller]
+    pub fn lock(
+        &self,
+        key: impl Into<String>,
+        time: u64,
+    ) -> impl Future<Output = error::Result<bool>> + Send + '_ {
+        let caller = Location::caller();
+        let key = key.into();
+        async move {
+            let owner = format!(
+                "{} {}:{}",
+                crate::config::server().instance,
+                caller.file(),
+                caller.line()
+            );
+            // nothing else frees it while this task waits on it, so it's only taken once it expires
+            if TAKEN
+                .try_with(|taken| taken.borrow().contains(&key))
+                .unwrap_or_default()
+            {
+                tracing::warn!(
+                    key = key.as_str(),
+                    owner = owner.as_str(),
+                    "taking a lock this task already holds"
+                );
+            }
+
+            let value = format!("{}|{}", owner, now_secs());
+            match self.send(Op::Lock(key.clone(), value, time)).await? {
+                Answer::Done(true) => {
+                    tracing::debug!(key = key.as_str(), owner = owner.as_str(), time, "locked");
+                    let _ = TAKEN.try_with(|taken| taken.borrow_mut().push(key));
+                    Ok(true)
+                }
+                Answer::Held(held) => {
+                    if let Some(held) = held {
+                        let (holder, since) = parse_owner(&held);
+                        tracing::debug!(
+                            key = key.as_str(),
+                            holder,
+                            held_for = now_secs().saturating_sub(since),
+                            "lock is taken"
+                        );
+                    }
+                    Ok(false)
+                }
+                Answer::Done(false) => Ok(false),
+                Answer::Locks(_) => unreachable!(),
+            }
+        }
+    }
+
+    /// Take a ratelimit for `time` secs if it's free. Unlike [`Handle::lock`] it's left out of
+    /// [`Handle::list`], there's one per user and command and no one needs to free them
+    pub async fn cooldown(&self, key: impl Into<String>, time: u64) -> error::Result<bool> {
+        let key = key.into();
+        let taken = self.send_bool(Op::Cooldown(key.clone(), time)).await?;
+        if taken {
+            let _ = TAKEN.try_with(|taken| taken.borrow_mut().push(key));
+        }
+        Ok(taken)
+    }
+
     //#[tracing::instrument(skip_all, fields(key), ret)]
     pub async fn unlock(&self, key: impl Into<String>) -> error::Result<bool> {
         let key = key.into();
         tracing::Span::current().record("key", &key.as_str());
-        let (resp_tx, resp_rx) = oneshot::channel::<Resp>();
-        self.tx.send((Lock::Unlock(key), resp_tx)).await?;
-        // TODO: implement a timeout here
-        resp_rx.await?
+        let _ = TAKEN.try_with(|taken| taken.borrow_mut().retain(|k| *k != key));
+        let unlocked = self.send_bool(Op::Unlock(key.clone())).await?;
+        tracing::debug!(key = key.as_str(), unlocked, "unlock");
+        Ok(unlocked)
+    }
+
+    /// Run `fut` for up to `timeout`, None if it's cut off. The locks it took and didn't
+    /// give back are released then, e.g. ratelimits, so nothing's left held for nothing
+    pub(crate) async fn timed<T>(
+        &self,
+        timeout: Duration,
+        fut: impl Future<Output = T>,
+    ) -> Option<T> {
+        let (res, taken) = TAKEN
+            .scope(RefCell::default(), async {
+                let res = tokio::time::timeout(timeout, fut).await;
+                (res, TAKEN.with(RefCell::take))
+            })
+            .await;
+        if res.is_err() {
+            for key in taken {
+                tracing::debug!(key = key.as_str(), "releasing lock of a timed out run");
+                if let Err(e) = self.unlock(key).await {
+                    tracing::error!("couldn't release lock: {}", e);
+                }
+            }
+        }
+        res.ok()
+    }
+
+    /// Take the lock for `holder`, or extend it if they have it already
+    pub async fn lease(
+        &self,
+        key: impl Into<String>,
+        holder: impl Into<String>,
+        time: u64,
+    ) -> error::Result<bool> {
+        self.send_bool(Op::Lease(key.into(), holder.into(), time))
+            .await
+    }
+
+    /// Give up a lease, unless it's lapsed and someone else has it now
+    pub async fn release(
+        &self,
+        key: impl Into<String>,
+        holder: impl Into<String>,
+    ) -> error::Result<bool> {
+        self.send_bool(Op::Release(key.into(), holder.into())).await
+    }
+
+    /// Every lock and lease held right now, by key
+    pub async fn list(&self) -> error::Result<Vec<HeldLock>> {
+        match self.send(Op::List).await? {
+            Answer::Locks(mut locks) => {
+                locks.sort_unstable_by(|a, b| a.key.cmp(&b.key));
+                Ok(locks)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Free a lock no matter who has it, for ones left behind. None if it wasn't held
+    pub async fn force_unlock(&self, key: &str) -> error::Result<Option<HeldLock>> {
+        let held = self.list().await?.into_iter().find(|l| l.key == key);
+        if !self.unlock(key).await? {
+            return Ok(None);
+        }
+        Ok(Some(
+            held.unwrap_or_else(|| HeldLock::new(key.to_owned(), "", -1)),
+        ))
     }
 }
@@ -0,0 +1,100 @@
+//! Log verbosity that can be changed while running, per target, e.g. turning on TRACE for
+//! just `back::cmds::filter` while debugging it in production
+
+pub use crate::proto::admin::LogLevels;
+use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
+use std::{collections::BTreeMap, str::FromStr, time::Duration};
+use tracing_subscriber::{
+    filter::{LevelFilter, Targets},
+    reload, Registry,
+};
+
+static HANDLE: OnceCell<reload::Handle<Targets, Registry>> = OnceCell::new();
+static STATE: Mutex<State> = Mutex::new(State {
+    default: LevelFilter::DEBUG,
+    targets: BTreeMap::new(),
+    generation: 0,
+});
+
+struct State {
+    default: LevelFilter,
+    targets: BTreeMap<String, LevelFilter>,
+    /// Bumped on every change, so reverting a temporary one doesn't undo anything newer
+    generation: u64,
+}
+
+impl State {
+    fn filter(&self) -> Targets {
+        Targets::new()
+            .with_default(self.default)
+            .with_targets(self.targets.iter().map(|(t, l)| (t.clone(), *l)))
+    }
+
+    fn levels(&self) -> LogLevels {
+        LogLevels {
+            default: self.default.to_string(),
+            targets: self
+                .targets
+                .iter()
+                .map(|(t, l)| (t.clone(), l.to_string()))
+                .collect(),
+        }
+    }
+}
+
+/// The filter to put right on top of the registry, starting out at `default` for everything
+pub fn layer(default: LevelFilter) -> reload::Layer<Targets, Registry> {
+    let mut state = STATE.lock();
+    state.default = default;
+    let (layer, handle) = reload::Layer::new(state.filter());
+    let _ = HANDLE.set(handle);
+    layer
+}
+
+pub fn get() -> LogLevels {
+    STATE.lock().levels()
+}
+
+/// Set the level for a target, or the default if None. A None level drops the target's own
+/// level, so it goes back to the default. Changes made `for_secs` are undone after that long,
+/// unless something else changed since
+pub fn set(
+    target: Option<&str>,
+    level: Option<&str>,
+    for_secs: Option<u64>,
+) -> Result<LogLevels, String> {
+    let level = level
+        .map(|l| LevelFilter::from_str(l).map_err(|_| format!("invalid level {:?}", l)))
+        .transpose()?;
+    let handle = HANDLE.get().ok_or("log levels can't be changed")?;
+
+    let mut state = STATE.lock();
+    let previous = match (target, level) {
+        (None, None) => return Err("the default needs a level".into()),
+        (None, Some(level)) => Some(std::mem::replace(&mut state.default, level)),
+        (Some(target), Some(level)) => state.targets.insert(target.to_owned(), level),
+        (Some(target), None) => state.targets.remove(target),
+    };
+    handle
+        .reload(state.filter())
+        .map_err(|e| format!("couldn't reload the filter: {}", e))?;
+    state.generation += 1;
+    tracing::warn!(?target, ?level, ?for_secs, "log level changed");
+
+    if let Some(secs) = for_secs {
+        let (target, generation) = (target.map(str::to_owned), state.generation);
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_secs(secs)).await;
+            if STATE.lock().generation != generation {
+                return;
+            }
+            let previous = previous.map(|l| l.to_string());
+            if let Err(e) = set(target.as_deref(), previous.as_deref(), None) {
+                tracing::error!("couldn't revert log level: {}", e);
+            }
+        });
+    }
+
+    Ok(state.levels())
+}
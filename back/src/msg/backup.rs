@@ -0,0 +1,421 @@
+//! Backups of everything that isn't just cached: the config files (quotes are commands, so they
+//! come with cmds.json), the web UI's users, and points, links and mod actions from the database.
+//! An archive is a gzipped tar with a manifest, written to BACKUP_DIR or streamed over the
+//! websocket. Restoring checks the manifest and every entry before anything is applied.
+//! Both are admin only, since the archive carries the users file that logins are checked against.
+
+use super::{corr_id, Location, Payload, Platform, Response, CHAT_PLATFORMS};
+pub use crate::proto::admin::{Manifest, RestoreReport, RestoreSource};
+use crate::{
+    cmds::{config_path, CmdDump, ConfigFile},
+    db::{
+        self,
+        backup::{LinkRecord, ModActionRecord, RestoreOp},
+        users::{ImportOp, UserRecord},
+        Db,
+    },
+    error::{self, Error},
+    lock,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use std::{
+    collections::HashMap,
+    io::{ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::{fs, sync::mpsc};
+
+/// Archives in any other format are rejected
+pub const FORMAT: u32 = 1;
+
+const CONFIG_FILES: [ConfigFile; 8] = [
+    ConfigFile::Commands,
+    ConfigFile::Filters,
+    ConfigFile::Timers,
+    ConfigFile::Users,
+    ConfigFile::ServiceAccounts,
+    ConfigFile::Currency,
+    ConfigFile::Profiles,
+    ConfigFile::PermMap,
+];
+
+const MANIFEST: &str = "manifest.json";
+const POINTS: &str = "db/points.jsonl";
+const LINKS: &str = "db/links.json";
+const MOD_ACTIONS: &str = "db/mod_actions.json";
+
+/// Users per page read, and per transaction restored
+const USER_BATCH: usize = 1000;
+/// Bytes of archive per BackupChunk, before base64
+const CHUNK_SIZE: usize = 256 * 1024;
+/// An archive unpacking to more than this is rejected
+const MAX_UNPACKED: u64 = 512 * 1024 * 1024;
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Take a backup, written to `dir` if set and streamed back as BackupChunks otherwise, then
+/// answer with BackupDone
+pub(super) async fn backup(
+    db: &db::Handle,
+    dir: Option<PathBuf>,
+    platform: Platform,
+    location: Location,
+    resp: &mpsc::Sender<(Location, Response)>,
+) {
+    let created = now_secs();
+    let res = async {
+        let archive = create(db, created).await?;
+        let file = match dir {
+            Some(dir) => {
+                let name = format!("backup-{}-{}.tar.gz", crate::channel_name(), created);
+                write_atomic(&dir.join(&name), &archive).await?;
+                Some(name)
+            }
+            None => {
+                let mut chunks = archive.chunks(CHUNK_SIZE).peekable();
+                while let Some(chunk) = chunks.next() {
+                    Response {
+                        platform,
+                        channel: crate::channel_name(),
+                        corr_id: corr_id(),
+                        payload: Payload::BackupChunk {
+                            chunk: Arc::new(STANDARD.encode(chunk)),
+                            done: chunks.peek().is_none(),
+                        },
+                    }
+                    .send(location.clone(), resp)
+                    .await;
+                }
+                None
+            }
+        };
+        Ok::<_, Error>((file, archive.len()))
+    }
+    .await;
+
+    let (file, bytes, error) = match res {
+        Ok((file, bytes)) => {
+            tracing::info!(?file, bytes, "backup taken");
+            (file, bytes, None)
+        }
+        Err(e) => {
+            tracing::error!("backup failed: {}", e);
+            (None, 0, Some(Arc::new(e.to_string())))
+        }
+    };
+    Response {
+        platform,
+        channel: crate::channel_name(),
+        corr_id: corr_id(),
+        payload: Payload::BackupDone { file, bytes, error },
+    }
+    .send(location, resp)
+    .await;
+}
+
+/// Restore a backup from `dir` or the archive sent, then answer with Restored
+pub(super) async fn restore(
+    db: &db::Handle,
+    lock: &lock::Handle,
+    source: RestoreSource,
+    dir: Option<PathBuf>,
+    platform: Platform,
+    location: Location,
+    resp: &mpsc::Sender<(Location, Response)>,
+) {
+    let res = async {
+        let archive = match source {
+            RestoreSource::File(name) => {
+                let dir = dir.ok_or("BACKUP_DIR isn't set")?;
+                if name.starts_with('.') || name.contains(['/', '\\']) {
+                    return Err(Error::Generic(format!("invalid backup name {}", name)));
+                }
+                fs::read(dir.join(name)).await?
+            }
+            RestoreSource::Archive(archive) => STANDARD
+                .decode(archive)
+                .map_err(|e| Error::Generic(format!("invalid archive: {}", e)))?,
+        };
+        apply(db, lock, archive).await
+    }
+    .await;
+
+    let (report, error) = match res {
+        Ok(report) => {
+            tracing::info!(?report, "backup restored");
+            (Some(report), None)
+        }
+        Err(e) => {
+            tracing::error!("restore failed: {}", e);
+            (None, Some(Arc::new(e.to_string())))
+        }
+    };
+    Response {
+        platform,
+        channel: crate::channel_name(),
+        corr_id: corr_id(),
+        payload: Payload::Restored { report, error },
+    }
+    .send(location, resp)
+    .await;
+}
+
+async fn create(db: &db::Handle, created: u64) -> error::Result<Vec<u8>> {
+    let mut tar = Tar::default();
+    let manifest = Manifest {
+        format: FORMAT,
+        version: env!("CARGO_PKG_VERSION").into(),
+        channel: crate::channel_name().to_owned(),
+        created,
+    };
+    tar.append(MANIFEST, &serde_json::to_vec_pretty(&manifest)?, created)?;
+
+    for file in CONFIG_FILES {
+        let name = config_path(file);
+        match fs::read(crate::config_dir().join(name)).await {
+            Ok(contents) => tar.append(&format!("config/{}", name), &contents, created)?,
+            // e.g. no service accounts saved yet
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(Error::Io(e)),
+        }
+    }
+
+    let mut points = vec![];
+    for platform in CHAT_PLATFORMS {
+        let mut after = Arc::new(String::new());
+        loop {
+            let users = match Db::ExportUsers(platform, after.clone(), USER_BATCH as i64)
+                .exec(db)
+                .await?
+            {
+                db::Resp::UserRecords(users) => users,
+                _ => unreachable!(),
+            };
+            for user in &users {
+                serde_json::to_writer(&mut points, user)?;
+                points.push(b'\n');
+            }
+            match users.last() {
+                Some(last) if users.len() == USER_BATCH => after = Arc::new(last.id.clone()),
+                _ => break,
+            }
+        }
+    }
+    tar.append(POINTS, &points, created)?;
+
+    let links = match Db::ExportLinks.exec(db).await? {
+        db::Resp::Links(links) => links,
+        _ => unreachable!(),
+    };
+    tar.append(LINKS, &serde_json::to_vec(&links)?, created)?;
+    let mod_actions = match Db::ExportModActions.exec(db).await? {
+        db::Resp::ModActionRecords(mod_actions) => mod_actions,
+        _ => unreachable!(),
+    };
+    tar.append(MOD_ACTIONS, &serde_json::to_vec(&mod_actions)?, created)?;
+
+    Ok(tokio::task::spawn_blocking(move || tar.finish()).await??)
+}
+
+async fn apply(
+    db: &db::Handle,
+    lock: &lock::Handle,
+    archive: Vec<u8>,
+) -> error::Result<RestoreReport> {
+    let mut entries = tokio::task::spawn_blocking(move || unpack(&archive)).await??;
+
+    // check everything before touching anything
+    let manifest: Manifest =
+        serde_json::from_slice(&entries.remove(MANIFEST).ok_or("archive has no manifest")?)?;
+    if manifest.format != FORMAT {
+        return Err(Error::Generic(format!(
+            "archive is format {} (taken by version {}), only format {} can be restored",
+            manifest.format, manifest.version, FORMAT
+        )));
+    }
+    if manifest.channel != crate::channel_name() {
+        return Err(Error::Generic(format!(
+            "archive is for channel {}, not {}",
+            manifest.channel,
+            crate::channel_name()
+        )));
+    }
+
+    let mut config_files = vec![];
+    for file in CONFIG_FILES {
+        let name = config_path(file);
+        let contents = match entries.remove(&format!("config/{}", name)) {
+            Some(contents) => contents,
+            None => continue,
+        };
+        let valid = match file {
+            ConfigFile::Commands | ConfigFile::Filters | ConfigFile::Timers => {
+                serde_json::from_slice::<Vec<CmdDump>>(&contents).map(|_| ())
+            }
+            _ => serde_json::from_slice::<serde_json::Value>(&contents).map(|_| ()),
+        };
+        if let Err(e) = valid {
+            return Err(Error::Generic(format!("invalid {}: {}", name, e)));
+        }
+        config_files.push((name, contents));
+    }
+
+    let mut users = match entries.remove(POINTS) {
+        Some(points) => points
+            .split(|b| *b == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(serde_json::from_slice::<UserRecord>)
+            .collect::<Result<Vec<_>, _>>()?,
+        None => vec![],
+    };
+    let links: Vec<LinkRecord> = match entries.remove(LINKS) {
+        Some(links) => serde_json::from_slice(&links)?,
+        None => vec![],
+    };
+    let mod_actions: Vec<ModActionRecord> = match entries.remove(MOD_ACTIONS) {
+        Some(mod_actions) => serde_json::from_slice(&mod_actions)?,
+        None => vec![],
+    };
+    if !entries.is_empty() {
+        let unknown: Vec<_> = entries.into_keys().collect();
+        return Err(Error::Generic(format!("unknown entries {:?}", unknown)));
+    }
+
+    tracing::info!(
+        ?manifest,
+        config_files = config_files.len(),
+        users = users.len(),
+        links = links.len(),
+        mod_actions = mod_actions.len(),
+        "restoring backup"
+    );
+
+    // the database first, the config files are easier to put back by hand if it fails
+    users.retain(|u| CHAT_PLATFORMS.contains(&u.platform) && !u.id.is_empty());
+    // batches have to be on one platform
+    users.sort_by_key(|u| u.platform.bits());
+    let mut restored_users = 0;
+    let mut users = users.into_iter().peekable();
+    while let Some(first) = users.next() {
+        let batch_platform = first.platform;
+        let mut batch = vec![first];
+        while batch.len() < USER_BATCH {
+            match users.next_if(|u| u.platform == batch_platform) {
+                Some(user) => batch.push(user),
+                None => break,
+            }
+        }
+        match Db::ImportUsers(ImportOp {
+            users: batch,
+            add: false,
+        })
+        .exec(db)
+        .await?
+        {
+            db::Resp::Imported(n) => restored_users += n,
+            _ => unreachable!(),
+        }
+    }
+
+    let records = match Db::RestoreRecords(RestoreOp { links, mod_actions })
+        .exec(db)
+        .await?
+    {
+        db::Resp::Imported(n) => n,
+        _ => unreachable!(),
+    };
+
+    // shares the lock with everything else that writes config files
+    if !lock.lock(&*super::CONFIG_FILE_LOCK, 5).await? {
+        return Err("config files are being changed, try again".into());
+    }
+    let mut written = vec![];
+    for (name, contents) in config_files {
+        let res = write_atomic(&crate::config_dir().join(name), &contents).await;
+        if let Err(e) = res {
+            let _ = lock.unlock(&*super::CONFIG_FILE_LOCK).await;
+            return Err(e);
+        }
+        written.push(name.to_owned());
+    }
+    let _ = lock.unlock(&*super::CONFIG_FILE_LOCK).await;
+
+    Ok(RestoreReport {
+        manifest,
+        config_files: written,
+        users: restored_users,
+        records,
+    })
+}
+
+/// Through a temporary file, so a crash part way doesn't leave half of one
+async fn write_atomic(path: &Path, contents: &[u8]) -> error::Result<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, contents).await?;
+    fs::rename(&tmp, path).await?;
+    Ok(())
+}
+
+/// Gzipped once it's finished, off the runtime
+struct Tar(tar::Builder<Vec<u8>>);
+
+impl Default for Tar {
+    fn default() -> Self {
+        Self(tar::Builder::new(vec![]))
+    }
+}
+
+impl Tar {
+    fn append(&mut self, name: &str, data: &[u8], mtime: u64) -> std::io::Result<()> {
+        let mut header = tar::Header::new_ustar();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(mtime);
+        self.0.append_data(&mut header, name, data)
+    }
+
+    /// End the archive and gzip it
+    fn finish(self) -> std::io::Result<Vec<u8>> {
+        let tar = self.0.into_inner()?;
+        let mut gz = GzEncoder::new(vec![], Compression::default());
+        gz.write_all(&tar)?;
+        gz.finish()
+    }
+}
+
+/// Regular files in the gzipped tar, by name
+fn unpack(archive: &[u8]) -> std::io::Result<HashMap<String, Vec<u8>>> {
+    let invalid = |msg: &str| std::io::Error::new(ErrorKind::InvalidData, msg);
+    let mut tar = vec![];
+    GzDecoder::new(archive)
+        .take(MAX_UNPACKED + 1)
+        .read_to_end(&mut tar)?;
+    if tar.len() as u64 > MAX_UNPACKED {
+        return Err(invalid("archive is too large"));
+    }
+
+    let mut entries = HashMap::new();
+    for entry in tar::Archive::new(&tar[..]).entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name = entry
+            .path()?
+            .to_str()
+            .ok_or_else(|| invalid("archive entry name isn't UTF-8"))?
+            .to_owned();
+        let mut contents = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut contents)?;
+        entries.insert(name, contents);
+    }
+    Ok(entries)
+}
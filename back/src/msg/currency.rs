@@ -0,0 +1,99 @@
+pub use crate::proto::admin::{Currency, CurrencyPosition};
+use crate::{
+    cmds::{config_path, ConfigFile},
+    error::{self, Error},
+};
+use parking_lot::RwLock;
+use std::{io::ErrorKind, sync::Arc};
+use tokio::fs;
+
+impl Currency {
+    /// The name to use for an amount, singular only for exactly 1
+    pub(crate) fn name(&self, amount: impl Into<i128>) -> &str {
+        if amount.into() == 1 {
+            &self.singular
+        } else {
+            &self.plural
+        }
+    }
+
+    /// An amount with its digits grouped and the currency on the configured side
+    pub(crate) fn format(&self, amount: impl Into<i128>) -> String {
+        let amount = amount.into();
+        let digits = amount.unsigned_abs().to_string();
+
+        let mut number = String::with_capacity(digits.len() * 2);
+        if amount < 0 {
+            number.push('-');
+        }
+        for (i, digit) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i) % 3 == 0 {
+                number.push_str(&self.separator);
+            }
+            number.push(digit);
+        }
+
+        let label = [self.emoji.as_str(), self.name(amount)]
+            .into_iter()
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+        if label.is_empty() {
+            return number;
+        }
+
+        match self.position {
+            CurrencyPosition::Before => format!("{} {}", label, number),
+            CurrencyPosition::After => format!("{} {}", number, label),
+        }
+    }
+
+    fn normalize(mut self) -> Self {
+        for s in [
+            &mut self.singular,
+            &mut self.plural,
+            &mut self.emoji,
+            &mut self.separator,
+        ] {
+            *s = s.trim().to_owned();
+        }
+        if self.plural.is_empty() {
+            self.plural = self.singular.clone();
+        }
+        self
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct CurrencyConfig(RwLock<Arc<Currency>>);
+
+impl CurrencyConfig {
+    /// Points are called points until something's been saved
+    #[tracing::instrument]
+    pub async fn load() -> error::Result<Self> {
+        let path = crate::config_dir().join(config_path(ConfigFile::Currency));
+        let currency = match fs::read_to_string(path).await {
+            Ok(contents) => serde_json::from_str::<Currency>(&contents)?.normalize(),
+            Err(e) if e.kind() == ErrorKind::NotFound => Currency::default(),
+            Err(e) => return Err(Error::Io(e)),
+        };
+        Ok(Self(RwLock::new(Arc::new(currency))))
+    }
+
+    pub(crate) fn get(&self) -> Arc<Currency> {
+        self.0.read().clone()
+    }
+
+    /// Replace the setting and write it to disk
+    pub(crate) async fn set(&self, currency: Currency) -> error::Result<()> {
+        let currency = currency.normalize();
+        let dump = serde_json::to_string_pretty(&currency)?;
+        *self.0.write() = Arc::new(currency);
+        fs::write(
+            crate::config_dir().join(config_path(ConfigFile::Currency)),
+            dump,
+        )
+        .await
+        .map_err(Error::Io)
+    }
+}
@@ -0,0 +1,237 @@
+//! Responses that couldn't be delivered, retried a few times and then kept in redis
+//! until an admin replays them
+
+use super::{Location, Platform};
+pub use crate::proto::admin::DeadLetter;
+use crate::{
+    cache::{self, Cache, RespType},
+    error::{self, Error},
+    pubsub, ws,
+};
+use once_cell::sync::Lazy;
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::{mpsc, oneshot};
+
+/// Tries after the first one fails
+const RETRIES: u32 = 3;
+/// Wait before the first retry, doubled for each one after
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+/// Oldest ones are dropped past this
+const MAX_DEAD_LETTERS: isize = 1000;
+
+static DEAD_LETTER_KEY: Lazy<Arc<String>> =
+    Lazy::new(|| Arc::new(format!("aussiebot!{}!dead_letters", crate::channel_name())));
+
+/// Sends serialized responses to pubsub and the websocket server
+#[derive(Clone)]
+pub(crate) struct Outbox {
+    pub_in_tx: mpsc::Sender<pubsub::Msg>,
+    ws_in_tx: mpsc::Sender<ws::Msg>,
+    cache: cache::Handle,
+}
+
+impl Outbox {
+    pub(crate) fn new(
+        pub_in_tx: mpsc::Sender<pubsub::Msg>,
+        ws_in_tx: mpsc::Sender<ws::Msg>,
+        cache: cache::Handle,
+    ) -> Self {
+        Self {
+            pub_in_tx,
+            ws_in_tx,
+            cache,
+        }
+    }
+
+    /// Hand the message off, retrying in the background if it fails.
+    /// Websocket sends go out in order, publishes are confirmed off to the side
+    pub(crate) async fn send(
+        &self,
+        loc: Location,
+        platform: Platform,
+        kind: Option<Arc<str>>,
+        msg: Arc<str>,
+    ) {
+        let (publish, ws_dest) = match loc {
+            Location::Pubsub => (true, None),
+            Location::Websocket(username, addr, _) => (false, Some(Some(vec![(username, addr)]))),
+            Location::Websockets(addrs) => (false, Some(addrs)),
+            Location::Broadcast => (true, Some(None)),
+        };
+
+        if let Some(dest) = ws_dest {
+            let tag = ws::Tag {
+                kind: kind.clone(),
+                platform,
+            };
+            if self
+                .ws_in_tx
+                .send((dest.clone(), tag, msg.clone()))
+                .await
+                .is_err()
+            {
+                self.retry(
+                    Location::Websockets(dest),
+                    platform,
+                    kind.clone(),
+                    msg.clone(),
+                    "websocket server closed".into(),
+                );
+            }
+        }
+
+        if publish {
+            let (ack_tx, ack_rx) = oneshot::channel();
+            if self
+                .pub_in_tx
+                .send((msg.clone(), Some(ack_tx)))
+                .await
+                .is_err()
+            {
+                self.retry(
+                    Location::Pubsub,
+                    platform,
+                    kind,
+                    msg,
+                    "pubsub closed".into(),
+                );
+                return;
+            }
+            let this = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = Self::acked(ack_rx).await {
+                    this.retry(Location::Pubsub, platform, kind, msg, e.to_string());
+                }
+            });
+        }
+    }
+
+    async fn acked(ack_rx: oneshot::Receiver<error::Result<()>>) -> error::Result<()> {
+        ack_rx
+            .await
+            .map_err(|_| Error::Generic("publish dropped".into()))?
+    }
+
+    /// One more try, waiting for the publish if there is one
+    async fn attempt(
+        &self,
+        loc: &Location,
+        platform: Platform,
+        kind: &Option<Arc<str>>,
+        msg: &Arc<str>,
+    ) -> error::Result<()> {
+        match loc {
+            Location::Pubsub => {
+                let (ack_tx, ack_rx) = oneshot::channel();
+                self.pub_in_tx.send((msg.clone(), Some(ack_tx))).await?;
+                Self::acked(ack_rx).await
+            }
+            Location::Websockets(dest) => {
+                let tag = ws::Tag {
+                    kind: kind.clone(),
+                    platform,
+                };
+                self.ws_in_tx.send((dest.clone(), tag, msg.clone())).await?;
+                Ok(())
+            }
+            // split up by send
+            Location::Websocket(..) | Location::Broadcast => unreachable!(),
+        }
+    }
+
+    fn retry(
+        &self,
+        loc: Location,
+        platform: Platform,
+        kind: Option<Arc<str>>,
+        msg: Arc<str>,
+        mut error: String,
+    ) {
+        let this = self.clone();
+        tokio::spawn(async move {
+            let mut backoff = RETRY_BACKOFF;
+            for tries in 1..=RETRIES {
+                tracing::warn!(location = ?loc, tries, "send failed, retrying: {}", error);
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+                match this.attempt(&loc, platform, &kind, &msg).await {
+                    Ok(()) => return,
+                    Err(e) => error = e.to_string(),
+                }
+            }
+
+            tracing::error!(location = ?loc, "send failed, dead-lettering: {}", error);
+            let letter = DeadLetter {
+                location: loc,
+                platform,
+                kind,
+                msg: msg.to_string(),
+                error,
+                failed_at: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or_default(),
+            };
+            if let Err(e) = this.park(&letter).await {
+                tracing::error!(letter = ?letter, "couldn't keep dead letter: {}", e);
+            }
+        });
+    }
+
+    async fn park(&self, letter: &DeadLetter) -> error::Result<()> {
+        let letter = Arc::new(serde_json::to_string(letter)?);
+        Cache::ListPush(DEAD_LETTER_KEY.clone(), letter, MAX_DEAD_LETTERS)
+            .exec(&self.cache)
+            .await?;
+        Ok(())
+    }
+
+    /// Oldest first
+    pub(crate) async fn dead_letters(cache: &cache::Handle) -> error::Result<Vec<DeadLetter>> {
+        match Cache::ListRange(DEAD_LETTER_KEY.clone())
+            .exec(cache)
+            .await?
+        {
+            RespType::VecString(list) => Ok(Self::parse(list)),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Send every dead letter again, oldest first. Any that fail again go back in the list
+    pub(crate) async fn replay(&self) -> error::Result<usize> {
+        let letters = match Cache::ListDrain(DEAD_LETTER_KEY.clone())
+            .exec(&self.cache)
+            .await?
+        {
+            RespType::VecString(list) => Self::parse(list),
+            _ => unreachable!(),
+        };
+
+        let count = letters.len();
+        for letter in letters {
+            self.send(
+                letter.location,
+                letter.platform,
+                letter.kind,
+                letter.msg.into(),
+            )
+            .await;
+        }
+        Ok(count)
+    }
+
+    fn parse(list: Vec<String>) -> Vec<DeadLetter> {
+        list.iter()
+            .filter_map(|s| match serde_json::from_str(s) {
+                Ok(letter) => Some(letter),
+                Err(e) => {
+                    tracing::warn!("skipping unreadable dead letter: {}", e);
+                    None
+                }
+            })
+            .collect()
+    }
+}
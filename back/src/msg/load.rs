@@ -0,0 +1,85 @@
+use super::Platform;
+use crate::config::ChatLoadConfig;
+use parking_lot::Mutex;
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+/// Repeats of a message within this are merged while overloaded
+const DEDUPE_WINDOW: Duration = Duration::from_secs(5);
+
+/// Keeps raid-level chat bursts from over-scheduling command runs.
+/// Filters aren't subject to any of this, only command/timer evaluation is
+pub struct ChatLoad {
+    config: ChatLoadConfig,
+    permits: Semaphore,
+    waiting: AtomicUsize,
+    /// (start of the current 1s window, messages seen in it)
+    window: Mutex<(Instant, u32)>,
+    /// hash of (platform, message) -> last seen
+    recent: Mutex<HashMap<u64, Instant>>,
+}
+
+impl ChatLoad {
+    pub fn new(config: ChatLoadConfig) -> Self {
+        Self {
+            config,
+            permits: Semaphore::new(config.concurrency),
+            waiting: AtomicUsize::new(0),
+            window: Mutex::new((Instant::now(), 0)),
+            recent: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Count a message towards throughput, true if over the threshold
+    pub(crate) fn record(&self) -> bool {
+        let mut window = self.window.lock();
+        let now = Instant::now();
+
+        if now.duration_since(window.0) >= Duration::from_secs(1) {
+            *window = (now, 0);
+            // only ever filled while overloaded, so this stays small
+            self.recent
+                .lock()
+                .retain(|_, seen| now.duration_since(*seen) < DEDUPE_WINDOW);
+        }
+        window.1 += 1;
+
+        self.config.shed_threshold > 0 && window.1 > self.config.shed_threshold
+    }
+
+    /// True if the same message was seen on the platform recently
+    pub(crate) fn is_duplicate(&self, platform: Platform, msg: &str) -> bool {
+        let mut hasher = DefaultHasher::new();
+        platform.bits().hash(&mut hasher);
+        msg.trim().to_lowercase().hash(&mut hasher);
+
+        let now = Instant::now();
+        match self.recent.lock().insert(hasher.finish(), now) {
+            Some(seen) => now.duration_since(seen) < DEDUPE_WINDOW,
+            None => false,
+        }
+    }
+
+    /// Wait for a turn to run commands. None if too much chat is already waiting,
+    /// unless `must_run` is set
+    pub(crate) async fn acquire(&self, must_run: bool) -> Option<SemaphorePermit<'_>> {
+        if let Ok(permit) = self.permits.try_acquire() {
+            return Some(permit);
+        }
+
+        let waiting = self.waiting.fetch_add(1, Ordering::Relaxed);
+        let permit = if must_run || waiting < self.config.queue_limit {
+            self.permits.acquire().await.ok()
+        } else {
+            None
+        };
+        self.waiting.fetch_sub(1, Ordering::Relaxed);
+
+        permit
+    }
+}
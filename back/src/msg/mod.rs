@@ -1,300 +1,317 @@
-pub mod discord;
-pub(crate) mod util;
+pub mod backup;
+pub mod currency;
+pub mod dead_letter;
+pub mod load;
+pub mod perm_map;
+pub mod profile;
+pub mod ready;
+pub mod service;
+pub mod session;
+mod watchdog;
+
+pub use crate::proto::{
+    connector, discord, new_corr_id, Alert, ArgMap, ArgMapError, Autocomplete, Chat, ChatMeta,
+    Emote, ExportFormat, HealthStatus, Invocation, InvocationKind, InvokeAs, Location, Message,
+    MessageText, Monetization, Payload, Permissions, Ping, Platform, PlatformError, Service,
+    StreamEvent, StreamSignal, User, PLATFORMS,
+};
 
 use crate::{
+    auth::{self, Role},
     cache::{self, Cache, RespType},
-    cmds::{self, ArgValue, ArgsDump, Command, CommandConfig, ModAction, RunRes, SchemaDump},
-    db::{self, modaction::ModActionDump},
+    cmds::{
+        self,
+        autocomplete::{self, PartialArg},
+        hook::{self, HookEvent},
+        reaction_role::ReactionRole,
+        uptime, ArgsDump, Command, ModAction, RunRes,
+    },
+    db::{self},
     error::{self, Error},
-    lock, pubsub, ws,
+    i18n::tr,
+    lock, log_level,
+    pubsub::{self, sign::Signature},
+    shard, ws,
 };
 use bb8_redis::redis;
-use bitflags::bitflags;
+use futures_util::future::{BoxFuture, FutureExt};
 use once_cell::sync::Lazy;
 use parking_lot::RwLock;
 use serde_derive::{Deserialize, Serialize};
 use std::{
-    collections::HashMap, fmt::Display, net::SocketAddr, ops::ControlFlow, str::FromStr, sync::Arc,
+    collections::HashMap,
+    fmt::{Display, Write as _},
+    net::SocketAddr,
+    ops::ControlFlow,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
 };
 use tokio::{
-    sync::{mpsc, watch},
+    sync::{mpsc, oneshot, watch, SemaphorePermit},
     task::JoinHandle,
 };
+use tracing::Instrument;
 
-bitflags! {
-  pub struct Permissions: u32 {
-    const NONE = 1 << 0;
-    const MEMBER = 1 << 1;
-    const MOD = 1 << 2;
-    const ADMIN = 1 << 3;
-    const OWNER = 1 << 4;
-  }
-
-  pub struct Platform: u32 {
-    //const ALL = 0;
-    const YOUTUBE = 1 << 0;
-    const TWITCH = 1 << 1;
-    const DISCORD = 1 << 2;
-    const WEB = 1 << 3;
-    const STREAM = Self::YOUTUBE.bits | Self::TWITCH.bits;
-    const CHAT = Self::STREAM.bits | Self::DISCORD.bits;
-    // const UI = Self::WEB.bits;
-    const ANNOUNCE = Self::DISCORD.bits | Self::WEB.bits;
-  }
-}
+pub(crate) const CHAT_PLATFORMS: [Platform; 3] =
+    [Platform::YOUTUBE, Platform::DISCORD, Platform::TWITCH];
+
+impl Chat {
+    /// Byte ranges of the emotes in the message, in order. Ones that don't fit the message are left out
+    fn emote_ranges(&self) -> Vec<std::ops::Range<usize>> {
+        let emotes = match self.meta {
+            Some(ChatMeta::Emotes(ref emotes)) if !emotes.is_empty() => emotes,
+            _ => return vec![],
+        };
+
+        // char offset -> byte offset, with one past the end
+        let bytes: Vec<usize> = self
+            .msg
+            .char_indices()
+            .map(|(b, _)| b)
+            .chain(std::iter::once(self.msg.len()))
+            .collect();
+
+        let mut ranges: Vec<_> = emotes
+            .iter()
+            .filter(|e| e.start < e.end && e.end < bytes.len())
+            .map(|e| bytes[e.start]..bytes[e.end])
+            .collect();
+        ranges.sort_by_key(|r| r.start);
+        ranges.dedup_by(|next, prev| next.start < prev.end);
+        ranges
+    }
 
-impl Default for Permissions {
-    fn default() -> Self {
-        Self::NONE
+    /// The text of each emote in the message, e.g. "Kappa"
+    pub(crate) fn emote_names(&self) -> Vec<&str> {
+        self.emote_ranges()
+            .into_iter()
+            .map(|r| &self.msg[r])
+            .collect()
     }
-}
 
-macro_rules! impl_platform_display {
-  ($($name:ident $disp:literal),+) => {
-    impl Display for Platform {
-        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-          match self {
-            $(&Platform::$name => {
-              write!(f, $disp)
-            }),+,
-            _ => write!(f, "{:?}", self)
-          }
+    /// The message with its emotes cut out, so filters only see what was typed
+    pub fn text_without_emotes(&self) -> std::borrow::Cow<'_, str> {
+        let ranges = self.emote_ranges();
+        if ranges.is_empty() {
+            return self.msg.as_str().into();
         }
+
+        let mut text = String::with_capacity(self.msg.len());
+        let mut last = 0;
+        for r in ranges {
+            text.push_str(&self.msg[last..r.start]);
+            text.push(' ');
+            last = r.end;
+        }
+        text.push_str(&self.msg[last..]);
+
+        text.split_whitespace().collect::<Vec<_>>().join(" ").into()
     }
-  }
 }
 
-#[derive(Debug)]
-pub struct PlatformError {
-    got: String,
+/// What set off an invocation nobody typed, so commands can tell it apart from a chatter's
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SystemActor {
+    /// Commands setting themselves up, see [`InvocationKind::Init`]
+    Init,
+    /// A stream starting or being raided, see [`InvocationKind::StreamEvent`]
+    StreamEvent,
 }
 
-impl std::fmt::Display for PlatformError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_fmt(format_args!("invalid platform {:?}", self.got))
+impl SystemActor {
+    /// Stands in for the user, with Owner perms so permission checks don't get in the way
+    pub fn user(self) -> Arc<User> {
+        Arc::new(User {
+            id: Arc::new(format!("@{}", self)),
+            name: Arc::new(self.to_string()),
+            perms: Permissions::OWNER,
+            roles: vec![],
+        })
     }
 }
 
-impl std::error::Error for PlatformError {}
-
-macro_rules! impl_platform_fromstr {
-  ($($name:ident $({ $($alt:ident),+ })?),+ $(,)?) => {
-    impl FromStr for Platform {
-      type Err = PlatformError;
+impl Display for SystemActor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Init => "init",
+            Self::StreamEvent => "stream_event",
+        })
+    }
+}
 
-      fn from_str(s: &str) -> Result<Self, Self::Err> {
-          match s.as_ref() {
-            $(stringify!($name) $($(| stringify!($alt))+)? => Ok(Platform::$name)),+,
-            _ => Err(PlatformError { got: s.to_owned() })
-          }
-      }
+impl Invocation {
+    /// Set for invocations the backend makes itself rather than ones from chat.
+    /// A raid's user is the raider, everything else runs as [`SystemActor::user`]
+    pub fn actor(&self) -> Option<SystemActor> {
+        match self.kind {
+            Some(InvocationKind::Init) => Some(SystemActor::Init),
+            Some(InvocationKind::StreamEvent(_)) => Some(SystemActor::StreamEvent),
+            _ => None,
+        }
     }
-  }
 }
 
-pub const PLATFORMS: [&str; 3] = ["Youtube", "Discord", "Twitch"];
-impl_platform_display!(YOUTUBE "Youtube", DISCORD "Discord", TWITCH "Twitch");
-impl_platform_fromstr!(
-    YOUTUBE {
-        y,
-        yt,
-        youtube,
-        Youtube
-    },
-    TWITCH {
-        t,
-        tw,
-        twitch,
-        Twitch
-    },
-    DISCORD {
-        d,
-        disc,
-        discord,
-        Discord
-    },
-    WEB
-);
+impl Payload {
+    /// Whether it has to come signed over pubsub (when a secret's set), anything
+    /// that acts as a user, changes state or reads more than the schema does
+    fn needs_signature(&self) -> bool {
+        !matches!(
+            self,
+            Payload::Ping(_)
+                | Payload::NotifyStart
+                | Payload::DumpHealth
+                | Payload::DumpSchema
+                | Payload::DumpSchemaIf(_)
+                | Payload::DumpJsonSchema
+                | Payload::DumpArgs(_)
+        )
+    }
 
-pub(crate) const CHAT_PLATFORMS: [Platform; 3] =
-    [Platform::YOUTUBE, Platform::DISCORD, Platform::TWITCH];
+    /// Who may send it, None for anyone. The rest only come from logged in web UI peers
+    fn required_role(&self) -> Option<Role> {
+        match self {
+            Payload::ExportLog(_)
+            | Payload::EditWordlist { .. }
+            | Payload::ResolveRedemption { .. }
+            | Payload::StartMultiplier { .. }
+            | Payload::StopMultiplier(_)
+            | Payload::DumpMultipliers
+            | Payload::DumpProfiles
+            | Payload::SaveProfile(_)
+            | Payload::DeleteProfile(_)
+            | Payload::SetTagEnabled { .. }
+            | Payload::SetProfile(_)
+            | Payload::DumpChatStats { .. }
+            | Payload::ModerateMeme { .. } => Some(Role::Mod),
+            Payload::InvokeAs(_)
+            | Payload::ListLocks
+            | Payload::ForceUnlock(_)
+            | Payload::ImportUsers { .. }
+            | Payload::ExportUsers { .. }
+            | Payload::Backup { .. }
+            | Payload::Restore(_)
+            | Payload::AuditPoints { .. }
+            | Payload::SetServiceAccounts(_)
+            | Payload::SetFlag { .. }
+            | Payload::SetPermMap(_)
+            | Payload::SetCurrency(_)
+            | Payload::ConfigDump(_)
+            | Payload::PatchConfig(_)
+            | Payload::DumpDeadLetters
+            | Payload::ReplayDeadLetters
+            | Payload::DumpConfigAudit(_)
+            | Payload::ListSessions
+            | Payload::ListConnections
+            | Payload::GetLogLevels
+            | Payload::SetLogLevel { .. }
+            | Payload::RevokeSession(_) => Some(Role::Admin),
+            _ => None,
+        }
+    }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
-pub struct User {
-    // https://serde.rs/feature-flags.html#-features-rc
-    pub id: Arc<String>,
-    pub name: Arc<String>,
-    pub perms: Permissions,
-}
+    /// Only ever sent by connectors, things that happened on a platform. Web UI peers could
+    /// otherwise make them up to award points, fire alerts or hand out roles
+    fn connector_only(&self) -> bool {
+        match self {
+            Payload::StreamEvent(_)
+            | Payload::Monetization(..)
+            | Payload::Alert(..)
+            | Payload::RoleMenuPosted { .. }
+            | Payload::VoicePresence(..)
+            | Payload::VoiceSync(_) => true,
+            Payload::InvokeCommand(invocation) => matches!(
+                invocation.kind,
+                Some(
+                    InvocationKind::Reaction { .. }
+                        | InvocationKind::StreamEvent(_)
+                        | InvocationKind::Monetization(_)
+                        | InvocationKind::Alert(_)
+                        | InvocationKind::Init
+                )
+            ),
+            _ => false,
+        }
+    }
 
-/// Optional platform-specific metadata
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub enum ChatMeta {
-    /// donations
-    Youtube(Arc<String>),
-    /// chan id, chan name
-    Discord1(u64, Arc<String>),
-    /// chan id, chan name, attachments (filename,url), stickers
-    Discord2(
-        u64,
-        Arc<String>,
-        Arc<Vec<(String, String)>>,
-        Arc<Vec<String>>,
-    ),
-    /// attachments (filename,url), stickers
-    Discord3(Arc<Vec<(String, String)>>, Arc<Vec<String>>),
-    /// guild id,
-    Discord4(Arc<String>),
-    /// interaction token, interaction id, ephemeral, is_dm
-    DiscordInteraction(Arc<String>, u64, bool, bool),
-    // DiscordDM(Arc<Vec<(String, String)>>, Arc<Vec<String>>), // attachments (filename,url), stickers
+    /// Chats and invocations from a web UI peer run as the peer's session, whoever they say
+    /// they're from. Acting as someone else is what [`Payload::InvokeAs`] is for
+    fn into_session(self, username: &Arc<String>, role: Role) -> Self {
+        match self {
+            Payload::Chat(chat) => Payload::Chat(Chat {
+                user: session_user(username, role),
+                ..chat
+            }),
+            Payload::InvokeCommand(invocation) => Payload::InvokeCommand(Invocation {
+                user: session_user(username, role),
+                ..invocation
+            }),
+            other => other,
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Chat {
-    pub user: Arc<User>,
-    pub msg: Arc<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub meta: Option<ChatMeta>,
+/// Who a web UI peer is when they chat or invoke a command, with no roles for the PermMap to raise
+fn session_user(username: &Arc<String>, role: Role) -> Arc<User> {
+    Arc::new(User {
+        id: Arc::new(format!("@web:{}", username)),
+        name: username.clone(),
+        perms: match role {
+            Role::Mod => Permissions::MOD,
+            Role::Admin => Permissions::ADMIN,
+        },
+        roles: vec![],
+    })
 }
-
-pub type ArgMap = HashMap<String, ArgValue>;
-
+/// Something for the msg task to handle
 #[derive(Debug)]
-pub struct ArgMapError;
-
-impl std::fmt::Display for ArgMapError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str("invalid argmap")
-    }
+pub enum Incoming {
+    /// As it was sent, checked against its signature if it came over pubsub
+    Raw(String),
+    /// Parsed already by a websocket peer's read task, to rate limit it by payload
+    Parsed(Message),
 }
 
-impl std::error::Error for ArgMapError {}
+/// How long a dedupe id is remembered for (in seconds)
+const DEDUPE_TTL: usize = 120;
 
-#[derive(Debug, Serialize, Deserialize)]
-pub enum InvocationKind {
-    Invoke,
-    Autocomplete,
-    Reaction { message_id: String, emoji: String },
-    StreamEvent(StreamEvent),
-    Init,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Invocation {
-    pub user: Arc<User>,
-    pub cmd: Arc<String>,
-    pub args: ArgMap,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub meta: Option<ChatMeta>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    /// None implies InvocationKind::Invoke
-    pub kind: Option<InvocationKind>,
-}
+/// Messages dropped for repeating a recently seen dedupe id
+pub static DUPLICATES_SUPPRESSED: AtomicU64 = AtomicU64::new(0);
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Ping {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub pinger: Option<(Platform, Arc<User>)>,
-    pub pingee: Arc<User>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub msg: Option<Arc<String>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub meta: Option<ChatMeta>,
-}
+/// Messages dropped for coming over pubsub without a valid signature
+pub static UNSIGNED_DROPPED: AtomicU64 = AtomicU64::new(0);
 
-#[derive(Debug, Serialize, Deserialize)]
-pub enum StreamSignal {
-    Start(Arc<String>),
-    Stop(Arc<String>),
+tokio::task_local! {
+    /// Correlation id of the message being handled
+    static CORR_ID: Arc<String>;
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub enum StreamEvent {
-    /// A platform has detected a stream start
-    DetectStart(Arc<String>),
-    /// A chat platform has started following a stream
-    Started(Arc<String>, Arc<String>),
-    /// A platform has detected a stream stop
-    DetectStop(Arc<String>),
-    /// A chat platform has stopped following a stream
-    Stopped(Arc<String>),
+/// Who made a config change, for the audit log
+fn author(location: &Location) -> Arc<String> {
+    match location {
+        Location::Websocket(username, ..) => username.clone(),
+        Location::Pubsub => Arc::new("pubsub".to_owned()),
+        _ => Arc::new("internal".to_owned()),
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Autocomplete {
-    /// key-value autocomplete choices
-    pub choices: Vec<(String, String)>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub meta: Option<ChatMeta>,
+/// Swap config edits made from chat for Ok, so they count as a successful run
+fn take_config_edits<'a>(res: impl Iterator<Item = &'a mut RunRes>) -> Vec<cmds::ConfigEdit> {
+    res.filter_map(|r| match std::mem::replace(r, RunRes::Ok) {
+        RunRes::EditConfig(edit) => Some(*edit),
+        other => {
+            *r = other;
+            None
+        }
+    })
+    .collect()
 }
 
-// TODO: split into recv and resp
-#[derive(Debug, Serialize, Deserialize)]
-#[non_exhaustive]
-pub enum Payload {
-    // recv
-    //#[serde(skip_serializing)]
-    Chat(Chat),
-    InvokeCommand(Invocation),
-    // #[serde(skip_serializing)]
-    StreamEvent(StreamEvent),
-    // TODO: not right
-    Ping(Ping),
-    // #[serde(skip_serializing)]
-    // SetConfig(Vec<cmds::OwnedCmdDump>),
-    // #[serde(skip_serializing)]
-    DumpConfig,
-    // #[serde(skip_serializing)]
-    DumpSchema,
-    // #[serde(skip_serializing)]
-    DumpLog(Platform), // TODO: add an optional arg for max num of latest items
-    DumpModActions,
-    DumpArgs(Platform),
-    //------------------------------
-    // send
-    // #[serde(skip_deserializing)]
-    ConfigSaved,
-    // #[serde(skip_deserializing)]
-    ConfigChanged,
-    // #[serde(skip_deserializing)]
-    /// user, action, reason
-    ModAction(Arc<User>, ModAction, Arc<String>),
-    // #[serde(skip_deserializing)]
-    StreamSignal(StreamSignal),
-    StreamAnnouncement(Arc<String>, Arc<String>),
-    // #[serde(skip_deserializing)]
-    /// Aussiebot's replies to users
-    Message {
-        #[serde(skip_serializing_if = "Option::is_none")]
-        user: Option<(Platform, Arc<User>)>,
-        msg: Arc<String>, // TODO: arc breaks json string newlines
-        #[serde(skip_serializing_if = "Option::is_none")]
-        meta: Option<ChatMeta>,
-    },
-    // #[serde(skip_deserializing)]
-    Autocorrect(Arc<User>, Vec<String>),
-    #[serde(skip_deserializing)] // SchemaDump has Value refs
-    SchemaDump(Arc<SchemaDump>),
-    // #[serde(skip_deserializing)]
-    LogDump(Vec<(Platform, Vec<String>)>),
-    //------------------------------
-    // both
-    // #[serde(skip_deserializing)]
-    ConfigDump(CommandConfig),
-    ModActionsDump(ModActionDump),
-    ArgsDump(ArgsDump),
-    Autocomplete(Autocomplete),
-    /// Discord-specific functionality
-    Discord(discord::DiscordAction),
-    /// Sent when a platform has started and is ready
-    NotifyStart,
-}
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Message {
-    pub platform: Platform,
-    pub channel: String,
-    pub payload: Payload,
+/// Correlation id of the message being handled by the current task, if any
+pub fn corr_id() -> Option<Arc<String>> {
+    CORR_ID.try_with(Clone::clone).ok()
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -305,6 +322,9 @@ pub struct Response {
     pub platform: Platform,
     pub channel: &'static str,
     pub payload: Payload,
+    /// Correlation id of the message that triggered this, echoed by connectors
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub corr_id: Option<Arc<String>>,
 }
 
 impl Response {
@@ -320,19 +340,13 @@ impl Response {
     }
 }
 
-#[derive(Debug, Clone)]
-pub enum Location {
-    Pubsub,
-    /// Addr, username
-    Websocket(Arc<String>, SocketAddr),
-    Websockets(Option<Vec<(Arc<String>, SocketAddr)>>),
-    Broadcast,
-}
-
 #[derive(Clone)]
 pub struct Server {
     pub pub_in_tx: mpsc::Sender<pubsub::Msg>, // redis <- msg resp
     pub ws_in_tx: mpsc::Sender<ws::Msg>,      // ws <- msg resp
+    pub ws_revoke_tx: mpsc::Sender<Arc<String>>, // ws <- revoked session ids
+    pub ws_subscribe_tx: mpsc::Sender<(SocketAddr, ws::Subscription)>, // ws <- peer subscriptions
+    pub ws_list_tx: mpsc::Sender<oneshot::Sender<Vec<ws::Connection>>>, // ws <- connection listings
     pub msg_out_tx: mpsc::Sender<(Location, Response)>,
     pub commands: Arc<RwLock<Arc<Vec<Command>>>>,
     pub filters: Arc<RwLock<Arc<Vec<Command>>>>,
@@ -341,11 +355,130 @@ pub struct Server {
     pub cache: cache::Handle,
     pub lock: lock::Handle,
     pub cancel_tasks: Arc<RwLock<Option<watch::Sender<()>>>>,
+    pub chat_load: Arc<load::ChatLoad>,
+    pub service_accounts: Arc<service::ServiceAccounts>,
+    pub perm_map: Arc<perm_map::PermMap>,
+    pub currency: Arc<currency::CurrencyConfig>,
+    pub profiles: Arc<profile::Profiles>,
+    pub usage: db::usage::UsageWriter,
+}
+
+/// Users written per transaction when importing
+const IMPORT_BATCH: usize = 500;
+/// Users per UserExport chunk
+const EXPORT_BATCH: i64 = 1000;
+
+/// Quote a CSV field if it needs it
+fn csv_field(s: &str) -> std::borrow::Cow<'_, str> {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\"")).into()
+    } else {
+        s.into()
+    }
+}
+
+/// Text in a chat reply that may have links to shorten
+fn reply_text(resp: &Response) -> Option<&str> {
+    match &resp.payload {
+        Payload::Message { msg, .. } => Some(msg),
+        Payload::Ping(Ping { msg: Some(msg), .. }) => Some(msg),
+        _ => None,
+    }
+}
+
+/// Shorten long links in chat replies so they fit in a message, if URL_SHORTENER is set
+async fn shorten_links(resp: &mut Response) {
+    match &mut resp.payload {
+        Payload::Message { msg, .. } => {
+            if let Some(shortened) = crate::urls::shorten_all(msg).await {
+                *msg = shortened.into();
+            }
+        }
+        Payload::Ping(Ping { msg: Some(msg), .. }) => {
+            if let Some(shortened) = crate::urls::shorten_all(msg).await {
+                *msg = shortened.into();
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Stream chats can't show newlines, so multi-line replies going only there are put on one line
+fn flatten_for_stream(resp: &mut Response) {
+    if resp.platform.is_empty() || !Platform::STREAM.contains(resp.platform) {
+        return;
+    }
+    if let Payload::Message { msg, .. } = &mut resp.payload {
+        if msg.is_multiline() {
+            *msg = msg.single_line(" | ").into();
+        }
+    }
+}
+
+/// Who's replaying as someone else, only ever a web UI admin.
+/// Acting as anyone is as good as being them, so mods can't
+fn run_as_admin(location: &Location) -> Option<Arc<String>> {
+    match location {
+        Location::Websocket(username, _, Role::Admin) => Some(username.clone()),
+        _ => None,
+    }
 }
 
+/// Whether it's a chat or invocation from a user in a bucket another instance holds. Every
+/// instance reads the whole stream when sharded, and only the owner should act on it
+fn owned_elsewhere(
+    platform: Platform,
+    payload: &Payload,
+    location: &Location,
+    owns: impl Fn(Platform, &str) -> bool,
+) -> bool {
+    if !matches!(location, Location::Pubsub) {
+        return false;
+    }
+    match payload {
+        Payload::Chat(chat) => !owns(platform, &chat.user.id),
+        Payload::InvokeCommand(invocation) => !owns(platform, &invocation.user.id),
+        _ => false,
+    }
+}
+
+/// Whether the payload's sender has the role it needs
+fn permitted(payload: &Payload, location: &Location) -> bool {
+    if matches!(location, Location::Websocket(..)) && payload.connector_only() {
+        tracing::warn!(location = ?location, kind = payload.kind(), "\x1b[91mnot permitted from web peers\x1b[0m");
+        return false;
+    }
+    let required = match payload.required_role() {
+        Some(required) => required,
+        None => return true,
+    };
+    match location {
+        Location::Websocket(_, _, role) if *role >= required => true,
+        _ => {
+            tracing::warn!(location = ?location, kind = payload.kind(), required = ?required, "\x1b[91mnot permitted\x1b[0m");
+            false
+        }
+    }
+}
+
+/// Whether secret command fields are shown decrypted, only ever to web UI users in CONFIG_OWNERS
+fn is_config_owner(location: &Location) -> bool {
+    match location {
+        Location::Websocket(username, ..) => crate::config::server()
+            .config_owners
+            .iter()
+            .any(|owner| owner.eq_ignore_ascii_case(username)),
+        _ => false,
+    }
+}
+
+/// How long a config change waits for another to finish before giving up
+const CONFIG_LOCK_WAIT: Duration = Duration::from_secs(5);
+const CONFIG_LOCK_RETRY: Duration = Duration::from_millis(250);
+
 // '!' to avoid conflicting with lock variables
 pub static CONFIG_FILE_LOCK: Lazy<String> =
-    Lazy::new(|| format!("aussiebot!config_{}", &*super::CHANNEL_NAME));
+    Lazy::new(|| format!("aussiebot!config_{}", super::channel_name()));
 
 impl Server {
     async fn msg(&self, msg: Message, location: Location) {
@@ -353,14 +486,42 @@ impl Server {
             platform,
             channel,
             payload,
+            dedupe_id,
+            ..
         } = msg;
 
         tracing::info!(platform=%platform, location=?location,"\x1b[93mMessage received\x1b[0m");
 
         #[allow(clippy::op_ref)]
-        if channel.as_str() != &*crate::CHANNEL_NAME {
+        if channel.as_str() != crate::channel_name() {
+            return;
+        }
+
+        // before the dedupe id is claimed, or the owner would see it as a duplicate
+        if owned_elsewhere(platform, &payload, &location, shard::owns) {
+            tracing::trace!("another instance's user");
+            return;
+        }
+
+        if let Some(dedupe_id) = dedupe_id {
+            if self.is_duplicate(platform, &dedupe_id).await {
+                let suppressed = DUPLICATES_SUPPRESSED.fetch_add(1, Ordering::Relaxed) + 1;
+                tracing::info!(
+                    dedupe_id = dedupe_id.as_str(),
+                    suppressed,
+                    "dropping duplicate message"
+                );
+                return;
+            }
+        }
+
+        if !permitted(&payload, &location) {
             return;
         }
+        let payload = match location {
+            Location::Websocket(ref username, _, role) => payload.into_session(username, role),
+            _ => payload,
+        };
 
         match payload {
             Payload::NotifyStart => self.started(platform, location).await,
@@ -371,130 +532,626 @@ impl Server {
             Payload::StreamEvent(event) => {
                 self.stream_event(platform, event, location).await;
             }
+            Payload::Monetization(user, event) => {
+                self.monetization(platform, user, event, location).await;
+            }
+            Payload::Alert(user, event) => {
+                self.alert(platform, user, event, location).await;
+            }
+            Payload::RoleMenuPosted { name, message_id } if platform == Platform::DISCORD => {
+                if let Err(e) = ReactionRole::posted(&self.cache, &name, message_id).await {
+                    tracing::error!(name = name.as_str(), "couldn't keep role menu: {}", e);
+                }
+            }
+            Payload::VoicePresence(user, presence) if platform == Platform::DISCORD => {
+                let commands = self.commands.read().clone();
+                for cmd in commands.iter() {
+                    if let Command::VoicePoints(voice) = cmd {
+                        if let Err(e) = voice.presence(&self.cache, &user, &presence).await {
+                            tracing::error!(name = voice.name.as_str(), "{}", e);
+                        }
+                    }
+                }
+            }
+            Payload::VoiceSync(present) if platform == Platform::DISCORD => {
+                let commands = self.commands.read().clone();
+                for cmd in commands.iter() {
+                    if let Command::VoicePoints(voice) = cmd {
+                        if let Err(e) = voice.sync(&self.cache, &present).await {
+                            tracing::error!(name = voice.name.as_str(), "{}", e);
+                        }
+                    }
+                }
+            }
             Payload::DumpConfig => {
-                let dump = self.dump_config();
+                let dump = self.dump_config().dump_secrets(is_config_owner(&location));
                 //if let Ok(Ok(dump)) = dump {
                 // send resp
                 Response {
                     platform,
-                    channel: &*crate::CHANNEL_NAME,
+                    channel: crate::channel_name(),
+                    corr_id: corr_id(),
                     payload: Payload::ConfigDump(dump),
                 }
                 .send(location, &self.msg_out_tx)
                 .await;
                 //}
             }
-            Payload::DumpSchema => {
-                let dump = Arc::new(cmds::schema(platform));
-                // send resp
+            Payload::DumpHealth => {
                 Response {
                     platform,
-                    channel: &*crate::CHANNEL_NAME,
-                    payload: Payload::SchemaDump(dump),
+                    channel: crate::channel_name(),
+                    corr_id: corr_id(),
+                    payload: Payload::HealthDump(ready::report()),
                 }
                 .send(location, &self.msg_out_tx)
                 .await;
             }
-            Payload::ConfigDump(config) => {
-                tracing::debug!("ConfigDump: {:#?}", config);
-
+            Payload::DumpSchema => self.dump_schema(platform, location, None).await,
+            Payload::DumpSchemaIf(version) => {
+                self.dump_schema(platform, location, Some(version)).await
+            }
+            Payload::DumpJsonSchema => {
+                Response {
+                    platform,
+                    channel: crate::channel_name(),
+                    corr_id: corr_id(),
+                    payload: Payload::JsonSchemaDump(cmds::json_schema::json_schema(platform)),
+                }
+                .send(location, &self.msg_out_tx)
+                .await;
+            }
+            Payload::ConfigDump(mut dump) => {
                 // acquire lock on disk config (max 5 seconds)
-                let locked = self.lock.lock(&*CONFIG_FILE_LOCK, 5).await.unwrap();
-
-                if locked {
-                    // set config
-                    // TODO: filter out invalid commands from active config
-                    self.handle_cmds_with_tasks(&config.commands, &config.timers);
-                    *self.commands.write() = config.commands.clone();
-                    *self.filters.write() = config.filters.clone();
-                    *self.timers.write() = config.timers.clone();
-
-                    // dump to disk
-                    let _ = futures_util::future::join3(
-                        cmds::save_cmds(&config.commands),
-                        cmds::save_filters(&config.filters),
-                        cmds::save_timers(&config.timers),
-                    )
+                if !self.lock_config(platform, &location).await {
+                    return;
+                }
+                dump.keep_redacted(&self.dump_config());
+                let config = cmds::CommandConfig::from(dump);
+                tracing::debug!("ConfigDump: {:#?}", config);
+                let saved = self
+                    .set_config(config, "ConfigDump", platform, location)
                     .await;
+                let _ = self.lock.unlock(&*CONFIG_FILE_LOCK).await;
 
-                    // send ok to dumper
-                    Response {
-                        platform,
-                        channel: &*crate::CHANNEL_NAME,
-                        payload: Payload::ConfigSaved,
-                    }
-                    .send(location, &self.msg_out_tx)
-                    .await;
+                if saved {
+                    // e.g. role menus that were added or edited
+                    self.init_commands(Platform::DISCORD).await;
+                }
+            }
+            Payload::PatchConfig(mut patch) => {
+                tracing::debug!("PatchConfig: {:#?}", patch);
 
-                    // broadcast config change notif
-                    Response {
-                        platform,
-                        channel: &*crate::CHANNEL_NAME,
-                        payload: Payload::ConfigChanged,
+                // merged into the config as it is once no one else is changing it
+                if !self.lock_config(platform, &location).await {
+                    return;
+                }
+                let config = self.dump_config();
+                patch.keep_redacted(&config);
+                let saved = match patch.apply(&config) {
+                    Ok(config) => {
+                        self.set_config(config, "PatchConfig", platform, location)
+                            .await
                     }
-                    .send(Location::Broadcast, &self.msg_out_tx)
-                    .await;
+                    Err(unknown) => {
+                        tracing::warn!(?unknown, "rejecting config patch with unknown commands");
+                        Response {
+                            platform,
+                            channel: crate::channel_name(),
+                            corr_id: corr_id(),
+                            payload: Payload::UnknownCommands(unknown),
+                        }
+                        .send(location, &self.msg_out_tx)
+                        .await;
+                        false
+                    }
+                };
+                let _ = self.lock.unlock(&*CONFIG_FILE_LOCK).await;
 
-                    let _ = self.lock.unlock(&*CONFIG_FILE_LOCK).await;
+                if saved {
+                    self.init_commands(Platform::DISCORD).await;
                 }
             }
-            Payload::DumpLog(platform) => {
-                let list = cmds::log::Log::list(&self.cache, &platform).await;
+            Payload::DumpLog {
+                platform,
+                offset,
+                limit,
+            } => {
+                let list = cmds::log::Log::list(&self.cache, &platform, offset, limit).await;
                 if let Some(list) = list {
-                    // TODO: list may be huge, impl partial fetching or smth
                     Response {
                         platform,
-                        channel: &*crate::CHANNEL_NAME,
+                        channel: crate::channel_name(),
+                        corr_id: corr_id(),
                         payload: Payload::LogDump(list),
                     }
                     .send(location, &self.msg_out_tx)
                     .await;
                 }
             }
-            Payload::Ping(ping) => {
-                tracing::info!("\x1b[93mPing received\x1b[0m");
-                if ping.pingee.id.is_empty() {
-                    // TODO: fill pingee ID from db if missing
+            Payload::ExportLog(log_platform) => {
+                let (cache, resp) = (self.cache.clone(), self.msg_out_tx.clone());
+                // may take a while, don't hold up other payloads
+                tokio::spawn(async move {
+                    if let Err(e) =
+                        cmds::log::Log::export(&cache, &log_platform, location, &resp).await
+                    {
+                        tracing::error!("{}", e);
+                    }
+                });
+            }
+            Payload::DumpWordlist(name) => {
+                self.dump_wordlist(platform, name, location).await;
+            }
+            Payload::EditWordlist { name, add, remove } => {
+                if let Err(e) =
+                    cmds::wordlist_filter::WordlistFilter::edit(&self.cache, &name, add, remove)
+                        .await
+                {
+                    tracing::error!("{}", e);
+                    return;
                 }
-                // forward ping
-                Response {
-                    platform,
-                    channel: &*crate::CHANNEL_NAME,
-                    payload: Payload::Ping(ping),
+                self.dump_wordlist(platform, name, location).await;
+            }
+            Payload::DumpRedemptions => {
+                self.dump_redemptions(platform, location).await;
+            }
+            Payload::ResolveRedemption { id, refund } => {
+                if let Err(e) = db::Db::ResolveRedemption(id, refund).exec(&self.db).await {
+                    tracing::error!("{}", e);
+                    return;
                 }
-                .send(Location::Broadcast, &self.msg_out_tx)
-                .await;
+                tracing::info!(id, refund, "redemption resolved");
+                self.dump_redemptions(platform, location).await;
             }
-            Payload::DumpModActions => {
-                let list = cmds::log::Log::list_mod_actions(&self.db).await;
-                match list {
-                    Ok(list) => {
+            Payload::ImportUsers { users, add } => {
+                let (db, resp) = (self.db.clone(), self.msg_out_tx.clone());
+                // may take a while, don't hold up other payloads
+                tokio::spawn(async move {
+                    Self::import_users(&db, users, add, platform, location, &resp).await;
+                });
+            }
+            Payload::ExportUsers {
+                platform: export_platform,
+                format,
+            } => {
+                let (db, resp) = (self.db.clone(), self.msg_out_tx.clone());
+                tokio::spawn(async move {
+                    if let Err(e) =
+                        Self::export_users(&db, export_platform, format, location, &resp).await
+                    {
+                        tracing::error!("{}", e);
+                    }
+                });
+            }
+            Payload::Backup { stream } => {
+                let dir = crate::config::server()
+                    .backup_dir
+                    .clone()
+                    .filter(|_| !stream);
+                let (db, resp) = (self.db.clone(), self.msg_out_tx.clone());
+                // goes through every user, don't hold up other payloads
+                tokio::spawn(async move {
+                    backup::backup(&db, dir, platform, location, &resp).await;
+                });
+            }
+            Payload::Restore(source) => {
+                let dir = crate::config::server().backup_dir.clone();
+                let (db, lock, resp) =
+                    (self.db.clone(), self.lock.clone(), self.msg_out_tx.clone());
+                tokio::spawn(async move {
+                    backup::restore(&db, &lock, source, dir, platform, location, &resp).await;
+                });
+            }
+            Payload::AuditPoints { repair } => {
+                let (db, resp) = (self.db.clone(), self.msg_out_tx.clone());
+                // goes through every user, don't hold up other payloads
+                tokio::spawn(async move {
+                    Self::audit_points(&db, repair, platform, location, &resp).await;
+                });
+            }
+            Payload::StartMultiplier {
+                platforms,
+                factor,
+                secs,
+            } => {
+                self.start_multiplier(platforms, factor, secs, platform, location)
+                    .await;
+            }
+            Payload::StopMultiplier(platforms) => {
+                self.stop_multiplier(platforms, platform, location).await;
+            }
+            Payload::DumpMultipliers => {
+                self.dump_multipliers(platform, location).await;
+            }
+            Payload::DumpProfiles => {
+                self.dump_profiles(platform, location).await;
+            }
+            Payload::SaveProfile(profile::Profile { name, tags }) => {
+                self.save_profile(name, Some(tags), platform, location)
+                    .await;
+            }
+            Payload::DeleteProfile(name) => {
+                self.save_profile(name, None, platform, location).await;
+            }
+            Payload::SetTagEnabled { tag, enabled } => {
+                let states = [(tag, enabled)].into_iter().collect();
+                self.set_tags(&states, "SetTagEnabled", platform, location)
+                    .await;
+            }
+            Payload::SetProfile(name) => {
+                let profile = match self.profiles.get(&name) {
+                    Some(profile) => profile,
+                    None => {
+                        tracing::warn!(name = name.as_str(), "no such profile");
+                        return;
+                    }
+                };
+                tracing::info!(name = name.as_str(), tags = ?profile.tags, "applying profile");
+                self.set_tags(&profile.tags, "SetProfile", platform, location)
+                    .await;
+            }
+            Payload::DumpServiceAccounts => {
+                self.dump_service_accounts(platform, location).await;
+            }
+            Payload::SetServiceAccounts(accounts) => {
+                // shares the lock with the rest of the config on disk
+                if !self.lock_config(platform, &location).await {
+                    return;
+                }
+                let res = self.service_accounts.set(accounts).await;
+                let _ = self.lock.unlock(&*CONFIG_FILE_LOCK).await;
+                if let Err(e) = res {
+                    tracing::error!("{}", e);
+                    return;
+                }
+                self.dump_service_accounts(platform, location).await;
+            }
+            Payload::DumpPermMap => {
+                self.dump_perm_map(platform, location).await;
+            }
+            Payload::DumpFlags => {
+                self.dump_flags(platform, location).await;
+            }
+            Payload::SetFlag { name, flag } => {
+                if let Err(e) = crate::flags::set(&self.cache, &name, flag).await {
+                    tracing::error!("{}", e);
+                    return;
+                }
+                self.dump_flags(platform, location).await;
+            }
+            Payload::ListLocks => {
+                self.list_locks(platform, location).await;
+            }
+            Payload::ForceUnlock(key) => {
+                self.force_unlock(&key, &location).await;
+                self.list_locks(platform, location).await;
+            }
+            Payload::SetPermMap(rules) => {
+                // shares the lock with the rest of the config on disk
+                if !self.lock_config(platform, &location).await {
+                    return;
+                }
+                let res = self.perm_map.set(rules).await;
+                let _ = self.lock.unlock(&*CONFIG_FILE_LOCK).await;
+                if let Err(e) = res {
+                    tracing::error!("{}", e);
+                    return;
+                }
+                self.dump_perm_map(platform, location).await;
+            }
+            Payload::DumpCurrency => {
+                self.dump_currency(platform, location).await;
+            }
+            Payload::SetCurrency(currency) => {
+                if !self.lock_config(platform, &location).await {
+                    return;
+                }
+                let res = self.currency.set(currency).await;
+                let _ = self.lock.unlock(&*CONFIG_FILE_LOCK).await;
+                if let Err(e) = res {
+                    tracing::error!("{}", e);
+                    return;
+                }
+                self.dump_currency(platform, location).await;
+            }
+            Payload::Subscribe(sub) => {
+                let addr = match location {
+                    Location::Websocket(_, addr, _) => addr,
+                    _ => {
+                        tracing::warn!(location=?location, "Subscribe is only accepted over websockets");
+                        return;
+                    }
+                };
+                let _ = self.ws_subscribe_tx.send((addr, sub)).await;
+            }
+            Payload::DumpDeadLetters => {
+                self.dump_dead_letters(platform, location).await;
+            }
+            Payload::ReplayDeadLetters => {
+                match self.outbox().replay().await {
+                    Ok(count) => tracing::info!(count, "replayed dead letters"),
+                    Err(e) => tracing::error!("{}", e),
+                }
+                self.dump_dead_letters(platform, location).await;
+            }
+            Payload::DumpMemeQueue => {
+                self.dump_meme_queue(platform, location).await;
+            }
+            Payload::DumpConfigAudit(limit) => {
+                self.dump_config_audit(limit, platform, location).await;
+            }
+            Payload::DumpChatStats { from, to } => {
+                let timers = self.timers.read().clone();
+                let stats = timers.iter().find_map(|cmd| match cmd {
+                    Command::ChatStats(stats) if stats.enabled => Some(stats),
+                    _ => None,
+                });
+                let stats = match stats {
+                    Some(stats) => stats,
+                    None => {
+                        tracing::warn!("no ChatStats to query");
+                        return;
+                    }
+                };
+                match stats.history(&self.cache, from, to).await {
+                    Ok(snapshot) => {
                         Response {
                             platform,
-                            channel: &*crate::CHANNEL_NAME,
-                            payload: Payload::ModActionsDump(list),
+                            channel: crate::channel_name(),
+                            corr_id: corr_id(),
+                            payload: Payload::ChatStats(snapshot),
                         }
                         .send(location, &self.msg_out_tx)
                         .await;
                     }
+                    Err(e) => tracing::error!("{}", e),
+                }
+            }
+            Payload::ModerateMeme { id, approve } => {
+                let commands = self.commands.read().clone();
+                let bank = commands.iter().find_map(|cmd| match cmd {
+                    Command::MemeBank(bank) if bank.enabled => Some(bank),
+                    _ => None,
+                });
+                let bank = match bank {
+                    Some(bank) => bank,
+                    None => {
+                        tracing::warn!("no MemeBank to moderate with");
+                        return;
+                    }
+                };
+                let moderator = match location {
+                    Location::Websocket(ref username, _, role) => session_user(username, role),
+                    _ => return,
+                };
+                // anything the moderation sets off is only for the moderator
+                let server = self.replying_only_to(location.clone());
+                let ctx = cmds::Context {
+                    user: &moderator,
+                    actor: None,
+                    meta: &None,
+                    platform: Platform::DISCORD,
+                    location: location.clone(),
+                    resp: &server.msg_out_tx,
+                    db: &self.db,
+                    cache: &self.cache,
+                    lock: &self.lock,
+                    filter_cache: RwLock::new(None),
+                    commands: commands.clone(),
+                    currency: self.currency.get(),
+                    depth: 0,
+                    corr_id: corr_id(),
+                };
+                match bank.moderate(&ctx, id, approve).await {
+                    Ok(Some(_)) => {}
+                    Ok(None) => tracing::info!(id, "meme already moderated"),
                     Err(e) => {
                         tracing::error!("{}", e);
+                        return;
                     }
                 }
+                self.dump_meme_queue(platform, location).await;
             }
-            Payload::DumpArgs(args_platform) => {
-                self.dump_args(platform, location, args_platform).await
+            Payload::ListSessions => {
+                self.dump_sessions(platform, location).await;
             }
-            _ => unreachable!(),
-        }
-    }
-
-    #[tracing::instrument(skip_all, fields(name = invocation.user.name.as_str(), cmd = invocation.cmd.as_str()))]
-    async fn invoke(&self, platform: Platform, invocation: &Invocation, location: Location) {
-        tracing::info!(args=?invocation.args, kind=?invocation.kind, user=?invocation.user, "\x1b[93mInvocation received\x1b[0m");
+            Payload::ListConnections => {
+                self.dump_connections(platform, location).await;
+            }
+            Payload::GetLogLevels => {
+                self.dump_log_levels(platform, location, log_level::get())
+                    .await;
+            }
+            Payload::SetLogLevel {
+                target,
+                level,
+                for_secs,
+            } => {
+                let levels = match log_level::set(target.as_deref(), level.as_deref(), for_secs) {
+                    Ok(levels) => levels,
+                    Err(e) => {
+                        tracing::error!("{}", e);
+                        return;
+                    }
+                };
+                self.dump_log_levels(platform, location, levels).await;
+            }
+            Payload::RevokeSession(id) => {
+                match auth::revoke_session(&self.cache, id.clone()).await {
+                    Ok(true) => tracing::info!(id = id.as_str(), "session revoked"),
+                    Ok(false) => tracing::info!(id = id.as_str(), "session already expired"),
+                    Err(e) => {
+                        tracing::error!("{}", e);
+                        return;
+                    }
+                }
+                // the peer asking may well be the one getting kicked, so answer first
+                self.dump_sessions(platform, location).await;
+                let _ = self.ws_revoke_tx.send(id).await;
+            }
+            Payload::Ping(ping) => {
+                tracing::info!("\x1b[93mPing received\x1b[0m");
+                if ping.pingee.id.is_empty() {
+                    // TODO: fill pingee ID from db if missing
+                }
+                // forward ping
+                Response {
+                    platform,
+                    channel: crate::channel_name(),
+                    corr_id: corr_id(),
+                    payload: Payload::Ping(ping),
+                }
+                .send(Location::Broadcast, &self.msg_out_tx)
+                .await;
+            }
+            Payload::PingDelivered(id) => {
+                if let Err(e) =
+                    cmds::ping::Ping::receipt(&self.cache, &self.msg_out_tx, id, None).await
+                {
+                    tracing::error!("{}", e);
+                }
+            }
+            Payload::PingFailed { id, reason } => {
+                if let Err(e) =
+                    cmds::ping::Ping::receipt(&self.cache, &self.msg_out_tx, id, Some(reason)).await
+                {
+                    tracing::error!("{}", e);
+                }
+            }
+            Payload::DumpModActions => {
+                let list = cmds::log::Log::list_mod_actions(&self.db).await;
+                match list {
+                    Ok(list) => {
+                        Response {
+                            platform,
+                            channel: crate::channel_name(),
+                            corr_id: corr_id(),
+                            payload: Payload::ModActionsDump(list),
+                        }
+                        .send(location, &self.msg_out_tx)
+                        .await;
+                    }
+                    Err(e) => {
+                        tracing::error!("{}", e);
+                    }
+                }
+            }
+            Payload::DumpUsage { range } => match db::Db::Usage(range).exec(&self.db).await {
+                Ok(db::Resp::Usage(stats)) => {
+                    Response {
+                        platform,
+                        channel: crate::channel_name(),
+                        corr_id: corr_id(),
+                        payload: Payload::UsageDump(stats),
+                    }
+                    .send(location, &self.msg_out_tx)
+                    .await;
+                }
+                Ok(_) => unreachable!(),
+                Err(e) => tracing::error!("{}", e),
+            },
+            Payload::DumpArgs(args_platform) => {
+                self.dump_args(platform, location, args_platform).await
+            }
+            Payload::InvokeAs(invoke_as) => self.invoke_as(invoke_as, location).await,
+            _ => unreachable!(),
+        }
+    }
+
+    /// A copy whose responses all go to `location`, wherever they were meant for
+    fn replying_only_to(&self, location: Location) -> Self {
+        let (resp_tx, mut resp_rx) = mpsc::channel::<(Location, Response)>(32);
+
+        let msg_out_tx = self.msg_out_tx.clone();
+        tokio::spawn(async move {
+            while let Some((_, resp)) = resp_rx.recv().await {
+                resp.send(location.clone(), &msg_out_tx).await;
+            }
+        });
+
+        // closes resp_rx when dropped
+        Self {
+            msg_out_tx: resp_tx,
+            ..self.clone()
+        }
+    }
+
+    /// Replay a chat or invocation as a synthesized user.
+    /// Responses, including mod actions, are only sent back to the admin
+    async fn invoke_as(&self, invoke_as: InvokeAs, location: Location) {
+        let admin = match run_as_admin(&location) {
+            Some(admin) => admin,
+            None => return,
+        };
+
+        let server = self.replying_only_to(location.clone());
+
+        match invoke_as {
+            InvokeAs::Chat(platform, chat) => {
+                let span = tracing::info_span!("InvokeAs", admin = admin.as_str(), as_user = ?chat.user, platform = %platform);
+                async {
+                    tracing::info!(msg=%chat.msg, "\x1b[95mReplaying chat\x1b[0m");
+                    server.chat(platform, &chat, location).await;
+                }
+                .instrument(span)
+                .await
+            }
+            InvokeAs::Invoke(platform, invocation) => {
+                let span = tracing::info_span!("InvokeAs", admin = admin.as_str(), as_user = ?invocation.user, platform = %platform);
+                async {
+                    tracing::info!(cmd=%invocation.cmd, "\x1b[95mReplaying invocation\x1b[0m");
+                    server.invoke(platform, &invocation, location).await;
+                }
+                .instrument(span)
+                .await
+            }
+        }
+    }
+
+    #[tracing::instrument(skip_all, fields(name = invocation.user.name.as_str(), cmd = invocation.cmd.as_str()))]
+    async fn invoke(&self, platform: Platform, invocation: &Invocation, location: Location) {
+        tracing::info!(args=?invocation.args, kind=?invocation.kind, user=?invocation.user, "\x1b[93mInvocation received\x1b[0m");
+
+        if matches!(location, Location::Pubsub) && !shard::owns(platform, &invocation.user.id) {
+            tracing::trace!("another instance's user");
+            return;
+        }
+
+        let actor = invocation.actor();
+        if !ready::is_ready() {
+            tracing::info!("not ready, turning the invocation away");
+            if actor.is_some() {
+                return;
+            }
+            Response {
+                platform,
+                channel: crate::channel_name(),
+                corr_id: corr_id(),
+                payload: Payload::Message {
+                    user: Some((platform, invocation.user.clone())),
+                    msg: tr("errors.not_ready", &[]).into(),
+                    meta: invocation.meta.clone(),
+                    hint: None,
+                    thread: None,
+                },
+            }
+            .send(location, &self.msg_out_tx)
+            .await;
+            return;
+        }
+
+        // ignore filters and timers
+        let commands = self.commands.read().clone();
+        let user = match actor {
+            // already has the perms it needs, or is a raider who isn't in chat
+            Some(_) => invocation.user.clone(),
+            None => self.sync_perms(platform, &invocation.user),
+        };
 
         let ctx = cmds::Context {
-            user: &invocation.user,
+            user: &user,
+            actor,
             meta: &invocation.meta,
             platform,
             location,
@@ -503,13 +1160,136 @@ impl Server {
             cache: &self.cache,
             lock: &self.lock,
             filter_cache: RwLock::new(None),
+            commands: commands.clone(),
+            currency: self.currency.get(),
+            depth: 0,
+            corr_id: corr_id(),
         };
 
-        // ignore filters and timers
-        let commands = self.commands.read().clone();
-        let _ =
+        if let Some(InvocationKind::Autocomplete { focused }) = &invocation.kind {
+            self.autocomplete(&ctx, invocation, focused).await;
+            return;
+        }
+
+        if matches!(invocation.kind, None | Some(InvocationKind::Invoke))
+            && self.invalid_invocation(&ctx, invocation).await
+        {
+            return;
+        }
+
+        let dm = invocation.meta.as_ref().is_some_and(ChatMeta::is_dm);
+        let commands: Vec<_> = commands
+            .iter()
+            .filter(|cmd| !dm || cmd.allow_dm(platform))
+            .collect();
+        let invoked = |cmd: &&Command| {
+            cmd.prefix()
+                .is_some_and(|prefix| cmds::unbang_prefix(prefix) == *invocation.cmd)
+        };
+        if dm
+            && matches!(invocation.kind, None | Some(InvocationKind::Invoke))
+            && !commands.iter().any(invoked)
+        {
+            self.dm_not_allowed(&ctx, invocation).await;
+            return;
+        }
+
+        let mut res =
             futures_util::future::join_all(commands.iter().map(|cmd| cmd.invoke(&ctx, invocation)))
                 .await;
+        for edit in take_config_edits(res.iter_mut().flatten()) {
+            self.chat_config_edit(&ctx, edit).await;
+        }
+
+        self.command_hooks(
+            &ctx,
+            commands.iter().copied().zip(res.iter().map(Option::as_ref)),
+        );
+        // nobody to explain them to
+        if actor.is_none() {
+            self.explain_errors(
+                &ctx,
+                commands.iter().copied().zip(res.iter().map(Option::as_ref)),
+                |prefix| cmds::unbang_prefix(prefix) == *invocation.cmd,
+            )
+            .await;
+        }
+    }
+
+    /// Tell someone who invoked a command from their DMs that it has to be run in chat
+    async fn dm_not_allowed(&self, ctx: &cmds::Context<'_>, invocation: &Invocation) {
+        tracing::info!(cmd = invocation.cmd.as_str(), "not allowed in DMs");
+        let msg = tr("errors.dm_not_allowed", &[("cmd", &invocation.cmd)]);
+        Response {
+            platform: ctx.platform,
+            channel: crate::channel_name(),
+            corr_id: ctx.corr_id.clone(),
+            payload: Payload::Message {
+                user: None,
+                msg: msg.into(),
+                meta: invocation.meta.clone(),
+                hint: None,
+                thread: None,
+            },
+        }
+        .send(ctx.location.clone(), ctx.resp)
+        .await;
+    }
+
+    /// Check the args against the invoked command's args schema before anything runs,
+    /// telling the invoker what's wrong if they don't fit
+    async fn invalid_invocation(&self, ctx: &cmds::Context<'_>, invocation: &Invocation) -> bool {
+        let schema = ctx.commands.iter().find_map(|cmd| {
+            cmd.args_schema(ctx.platform)
+                .filter(|(prefix, ..)| *prefix == *invocation.cmd)
+        });
+        let (_, _, _, _, schema) = match schema {
+            Some(schema) => schema,
+            None => return false,
+        };
+
+        let invalid = match cmds::validate::check(&schema, &invocation.args) {
+            Some(invalid) => invalid,
+            None => return false,
+        };
+        tracing::info!(missing = ?invalid.missing, type_errors = ?invalid.type_errors, "invalid invocation");
+
+        Response {
+            platform: ctx.platform,
+            channel: crate::channel_name(),
+            corr_id: ctx.corr_id.clone(),
+            payload: Payload::InvalidInvocation {
+                missing: invalid.missing,
+                type_errors: invalid.type_errors,
+                meta: invocation.meta.clone(),
+            },
+        }
+        .send(ctx.location.clone(), ctx.resp)
+        .await;
+        true
+    }
+
+    /// The user with the level their roles give them, kept for `!perms`
+    fn sync_perms(&self, platform: Platform, user: &Arc<User>) -> Arc<User> {
+        let synced = self.perm_map.apply(platform, user);
+
+        let (cache, id, remembered) = (self.cache.clone(), user.id.clone(), synced.clone());
+        tokio::spawn(async move {
+            if let Err(e) = perm_map::remember(&cache, platform, &id, &remembered).await {
+                tracing::warn!("couldn't keep synced perms: {}", e);
+            }
+        });
+
+        if synced.perms == user.perms {
+            return user.clone();
+        }
+        tracing::debug!(from = ?user.perms, to = ?synced.perms, source = ?synced.source, "perms synced");
+        Arc::new(User {
+            id: user.id.clone(),
+            name: user.name.clone(),
+            perms: synced.perms,
+            roles: user.roles.clone(),
+        })
     }
 
     /// Process a chat message
@@ -517,9 +1297,30 @@ impl Server {
     async fn chat(&self, platform: Platform, chat: &Chat, location: Location) {
         tracing::info!(user=?chat.user, meta=?chat.meta, msg=%chat.msg,"\x1b[93mChat received\x1b[0m");
 
+        if matches!(location, Location::Pubsub) && !shard::owns(platform, &chat.user.id) {
+            tracing::trace!("another instance's user");
+            return;
+        }
+
+        if !ready::is_ready() {
+            tracing::debug!("not ready, dropping chat");
+            return;
+        }
+
+        // before anything checks them
+        let chat = &Chat {
+            user: self.sync_perms(platform, &chat.user),
+            ..chat.clone()
+        };
+
+        let overloaded = self.chat_load.record();
+        let commands = self.commands.read().clone();
+        let is_service = self.service_accounts.is_service(platform, &chat.user.id);
+
         // it's ok to take refs because each chat msg gets its own task with its own `self` instance
         let ctx = cmds::Context {
             user: &chat.user,
+            actor: None,
             meta: &chat.meta,
             platform,
             location,
@@ -528,74 +1329,880 @@ impl Server {
             cache: &self.cache,
             lock: &self.lock,
             filter_cache: RwLock::new(None),
+            commands: commands.clone(),
+            currency: self.currency.get(),
+            depth: 0,
+            corr_id: corr_id(),
         };
 
-        if let Some((mod_action, filter_name)) = self.filter_chat(&ctx, chat).await {
+        if chat.meta.as_ref().is_some_and(ChatMeta::is_dm) {
+            if is_service {
+                tracing::debug!("service account, skipping dm commands");
+            } else {
+                self.dm_chat(&ctx, chat, &commands).await;
+            }
+            return;
+        }
+
+        if is_service {
+            tracing::debug!("service account, skipping filters and commands");
+        } else if let Some((mod_action, filter_name)) = self.filter_chat(&ctx, chat).await {
             tracing::info!(
                 "Filter tripped, name: {}, action: {:?}",
                 filter_name,
                 mod_action
             );
             if ctx.user.perms < Permissions::MOD {
+                hook::fire(
+                    &ctx,
+                    HookEvent::ModAction {
+                        action: mod_action,
+                        filter: filter_name.clone(),
+                    },
+                );
+
                 // send resp
                 Response {
                     platform: ctx.platform,
-                    channel: &*crate::CHANNEL_NAME,
+                    channel: crate::channel_name(),
+                    corr_id: corr_id(),
                     payload: Payload::ModAction(ctx.user.clone(), mod_action, filter_name),
                 }
                 .send(Location::Broadcast, ctx.resp)
                 .await;
             }
-        } else {
+        } else if let Some(_permit) = self
+            .chat_permit(platform, chat, &commands, overloaded)
+            .await
+        {
             // await Timer.runs' as well, to count messages
             let timers = self.timers.read().clone();
-            let commands = self.commands.read().clone();
             let iter = commands.iter().chain(timers.iter()); //timers.iter().chain(commands.iter());
 
-            let res = futures_util::future::join_all(iter.map(|cmd| cmd.chat(&ctx, chat))).await;
+            let mut res =
+                futures_util::future::join_all(iter.map(|cmd| cmd.chat(&ctx, chat))).await;
             tracing::debug!(res=?res);
 
+            for edit in take_config_edits(res.iter_mut().filter_map(|r| r.as_mut().ok())) {
+                self.chat_config_edit(&ctx, edit).await;
+            }
             self.autocorrect(&ctx, &res).await;
+            // timers come after commands, so they're left out here
+            self.command_hooks(
+                &ctx,
+                commands.iter().zip(res.iter().map(|r| r.as_ref().ok())),
+            );
+            self.explain_errors(
+                &ctx,
+                commands.iter().zip(res.iter().map(|r| r.as_ref().ok())),
+                |prefix| cmds::util::starts_with_prefix(prefix, &chat.msg),
+            )
+            .await;
         }
 
-        // send chat to any and all web clients
-        Response {
-            platform,
-            channel: &*crate::CHANNEL_NAME,
-            payload: Payload::Chat(chat.clone()),
+        session::record_chat(&self.cache, platform).await;
+
+        // send chat to any and all web clients
+        Response {
+            platform,
+            channel: crate::channel_name(),
+            corr_id: corr_id(),
+            payload: Payload::Chat(chat.clone()),
+        }
+        .send(Location::Websockets(None), &self.msg_out_tx)
+        .await;
+    }
+
+    /// DMs and whispers only run commands that allow them. They aren't filtered, don't
+    /// count towards timers or stats, and aren't shown to web clients
+    async fn dm_chat(&self, ctx: &cmds::Context<'_>, chat: &Chat, commands: &[Command]) {
+        let commands: Vec<_> = commands
+            .iter()
+            .filter(|cmd| cmd.allow_dm(ctx.platform))
+            .collect();
+
+        let res =
+            futures_util::future::join_all(commands.iter().map(|cmd| cmd.chat(ctx, chat))).await;
+        tracing::debug!(res=?res, "dm");
+
+        self.command_hooks(
+            ctx,
+            commands
+                .iter()
+                .copied()
+                .zip(res.iter().map(|r| r.as_ref().ok())),
+        );
+        self.explain_errors(
+            ctx,
+            commands
+                .iter()
+                .copied()
+                .zip(res.iter().map(|r| r.as_ref().ok())),
+            |prefix| cmds::util::starts_with_prefix(prefix, &chat.msg),
+        )
+        .await;
+    }
+
+    /// Turn to run commands on a chat message, None if it's shed.
+    /// Under load, repeats (e.g. raid spam) are merged into the first one, but command
+    /// invocations always get through eventually
+    async fn chat_permit<'a>(
+        &'a self,
+        platform: Platform,
+        chat: &Chat,
+        commands: &[Command],
+        overloaded: bool,
+    ) -> Option<SemaphorePermit<'a>> {
+        let is_invocation = || {
+            commands.iter().any(|cmd| {
+                cmd.prefix()
+                    .is_some_and(|prefix| cmds::util::starts_with_prefix(prefix, &chat.msg))
+            })
+        };
+
+        let must_run = !overloaded || is_invocation();
+        if !must_run && self.chat_load.is_duplicate(platform, &chat.msg) {
+            tracing::debug!("overloaded, merging repeated message");
+            return None;
+        }
+
+        let permit = self.chat_load.acquire(must_run).await;
+        if permit.is_none() {
+            tracing::warn!("chat queue full, shedding message");
+        }
+        permit
+    }
+
+    /// Send autocorrect suggestions if any, if no command was successfully run
+    async fn autocorrect(&self, ctx: &cmds::Context<'_>, res: &[error::Result<RunRes>]) {
+        // accumulate suggestions, unless at least one successful command call
+        let res = res.iter().try_fold(vec![], |mut sugg, curr| match curr {
+            Ok(RunRes::Ok) => {
+                tracing::debug!("at least one RunRes::Ok found, stopping autocorrect");
+                ControlFlow::Break(())
+            }
+            Ok(RunRes::Autocorrect(prefix)) => {
+                sugg.push(prefix.to_owned());
+                ControlFlow::Continue(sugg)
+            }
+            _ => ControlFlow::Continue(sugg),
+        });
+
+        let autocorrect_list = match res {
+            ControlFlow::Continue(res) => res,
+            _ => return,
+        };
+
+        // send suggestions if any
+        if !autocorrect_list.is_empty() {
+            tracing::info!(suggestions=?autocorrect_list, "autocorrect");
+            // send resp
+            Response {
+                platform: ctx.platform,
+                channel: crate::channel_name(),
+                corr_id: corr_id(),
+                payload: Payload::Autocorrect(ctx.user.clone(), autocorrect_list),
+            }
+            .send(ctx.location.clone(), ctx.resp)
+            .await;
+        }
+    }
+
+    async fn dump_schema(
+        &self,
+        platform: Platform,
+        location: Location,
+        known_version: Option<Arc<String>>,
+    ) {
+        let (version, schema) = match cmds::versioned_schema(platform) {
+            Ok(s) => s,
+            Err(e) => {
+                tracing::error!("{}", e);
+                return;
+            }
+        };
+
+        let payload = if known_version.as_deref() == Some(&*version) {
+            Payload::NotModified
+        } else {
+            Payload::SchemaDump { version, schema }
+        };
+
+        // send resp
+        Response {
+            platform,
+            channel: crate::channel_name(),
+            corr_id: corr_id(),
+            payload,
+        }
+        .send(location, &self.msg_out_tx)
+        .await;
+    }
+
+    async fn dump_wordlist(&self, platform: Platform, name: Arc<String>, location: Location) {
+        let words = match cmds::wordlist_filter::WordlistFilter::dump(&self.cache, &name).await {
+            Ok(words) => words,
+            Err(e) => {
+                tracing::error!("{}", e);
+                return;
+            }
+        };
+
+        Response {
+            platform,
+            channel: crate::channel_name(),
+            corr_id: corr_id(),
+            payload: Payload::WordlistDump { name, words },
+        }
+        .send(location, &self.msg_out_tx)
+        .await;
+    }
+
+    /// Remembers the id, true if it was already seen. Lets messages through if redis is down
+    async fn is_duplicate(&self, platform: Platform, dedupe_id: &str) -> bool {
+        let key = format!(
+            "aussiebot!{}!dedupe!{}!{}",
+            crate::channel_name(),
+            platform,
+            dedupe_id
+        );
+        match Cache::Set(key.into(), Arc::new("1".into()), DEDUPE_TTL, true)
+            .exec(&self.cache)
+            .await
+        {
+            Ok(RespType::Bool(set)) => !set,
+            Ok(_) => unreachable!(),
+            Err(e) => {
+                tracing::error!("{}", e);
+                false
+            }
+        }
+    }
+
+    /// Choices from the command being typed into, sorted and capped centrally
+    async fn autocomplete(&self, ctx: &cmds::Context<'_>, invocation: &Invocation, focused: &str) {
+        // not sent if nothing's been typed in yet
+        let arg = PartialArg::find(&invocation.args, focused).unwrap_or(PartialArg {
+            name: focused,
+            value: "",
+        });
+
+        let res = futures_util::future::join_all(
+            ctx.commands
+                .iter()
+                .map(|cmd| cmd.autocomplete(ctx, &invocation.cmd, arg)),
+        )
+        .await;
+
+        let choices = match res.into_iter().flatten().next() {
+            Some(Ok(choices)) => autocomplete::finish(choices, arg.value),
+            Some(Err(e)) => {
+                tracing::error!("{}", e);
+                return;
+            }
+            None => return,
+        };
+        tracing::debug!(arg = ?arg, choices = choices.len(), "autocompleted");
+
+        Response {
+            platform: ctx.platform,
+            channel: crate::channel_name(),
+            corr_id: ctx.corr_id.clone(),
+            payload: Payload::Autocomplete(Autocomplete {
+                choices,
+                meta: invocation.meta.clone(),
+            }),
+        }
+        .send(ctx.location.clone(), &self.msg_out_tx)
+        .await;
+    }
+
+    async fn dump_redemptions(&self, platform: Platform, location: Location) {
+        let queue = match db::Db::PendingRedemptions.exec(&self.db).await {
+            Ok(db::Resp::Redemptions(queue)) => queue,
+            Ok(_) => unreachable!(),
+            Err(e) => {
+                tracing::error!("{}", e);
+                return;
+            }
+        };
+
+        Response {
+            platform,
+            channel: crate::channel_name(),
+            corr_id: corr_id(),
+            payload: Payload::RedemptionQueue(queue),
+        }
+        .send(location, &self.msg_out_tx)
+        .await;
+    }
+
+    /// Import in batches, one transaction each, reporting progress after every one
+    async fn import_users(
+        db: &db::Handle,
+        mut users: Vec<db::users::UserRecord>,
+        add: bool,
+        platform: Platform,
+        location: Location,
+        resp: &mpsc::Sender<(Location, Response)>,
+    ) {
+        let total = users.len();
+        users.retain(|u| CHAT_PLATFORMS.contains(&u.platform) && !u.id.is_empty());
+        let skipped = total - users.len();
+        // batches have to be on one platform
+        users.sort_by_key(|u| u.platform.bits());
+
+        tracing::info!(total, skipped, add, "importing users");
+
+        let mut imported = 0;
+        let mut error = None;
+        let mut users = users.into_iter().peekable();
+        while let Some(first) = users.next() {
+            let batch_platform = first.platform;
+            let mut batch = vec![first];
+            while batch.len() < IMPORT_BATCH {
+                match users.next_if(|u| u.platform == batch_platform) {
+                    Some(user) => batch.push(user),
+                    None => break,
+                }
+            }
+
+            match db::Db::ImportUsers(db::users::ImportOp { users: batch, add })
+                .exec(db)
+                .await
+            {
+                Ok(db::Resp::Imported(n)) => imported += n,
+                Ok(_) => unreachable!(),
+                Err(e) => {
+                    tracing::error!("{}", e);
+                    error = Some(Arc::new(e.to_string()));
+                    break;
+                }
+            }
+
+            if users.peek().is_none() {
+                break;
+            }
+
+            Response {
+                platform,
+                channel: crate::channel_name(),
+                corr_id: corr_id(),
+                payload: Payload::ImportProgress {
+                    imported,
+                    total,
+                    skipped,
+                    done: false,
+                    error: None,
+                },
+            }
+            .send(location.clone(), resp)
+            .await;
+        }
+
+        tracing::info!(imported, failed = error.is_some(), "imported users");
+
+        Response {
+            platform,
+            channel: crate::channel_name(),
+            corr_id: corr_id(),
+            payload: Payload::ImportProgress {
+                imported,
+                total,
+                skipped,
+                done: true,
+                error,
+            },
+        }
+        .send(location, resp)
+        .await;
+    }
+
+    /// Stream every user on the platforms to `location`
+    async fn export_users(
+        db: &db::Handle,
+        platforms: Platform,
+        format: ExportFormat,
+        location: Location,
+        resp: &mpsc::Sender<(Location, Response)>,
+    ) -> error::Result<()> {
+        for platform in CHAT_PLATFORMS
+            .into_iter()
+            .filter(|p| platforms.contains(*p))
+        {
+            let mut after = Arc::new(String::new());
+            let mut chunk = match format {
+                ExportFormat::Csv => "platform,id,name,points\n".to_owned(),
+                ExportFormat::Json => String::new(),
+            };
+            loop {
+                let users = match db::Db::ExportUsers(platform, after.clone(), EXPORT_BATCH)
+                    .exec(db)
+                    .await?
+                {
+                    db::Resp::UserRecords(users) => users,
+                    _ => unreachable!(),
+                };
+
+                let done = (users.len() as i64) < EXPORT_BATCH;
+                if let Some(last) = users.last() {
+                    after = Arc::new(last.id.clone());
+                }
+                for user in &users {
+                    match format {
+                        ExportFormat::Json => chunk.push_str(&serde_json::to_string(user)?),
+                        ExportFormat::Csv => write!(
+                            chunk,
+                            "{},{},{},{}",
+                            platform.to_string().to_lowercase(),
+                            csv_field(&user.id),
+                            csv_field(user.name.as_deref().unwrap_or_default()),
+                            user.points
+                        )
+                        .unwrap(),
+                    }
+                    chunk.push('\n');
+                }
+
+                Response {
+                    platform,
+                    channel: crate::channel_name(),
+                    corr_id: corr_id(),
+                    payload: Payload::UserExport {
+                        platform,
+                        chunk: std::mem::take(&mut chunk).into(),
+                        done,
+                    },
+                }
+                .send(location.clone(), resp)
+                .await;
+
+                if done {
+                    break;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn dump_sessions(&self, platform: Platform, location: Location) {
+        let sessions = match auth::list_sessions(&self.cache).await {
+            Ok(sessions) => sessions,
+            Err(e) => {
+                tracing::error!("{}", e);
+                return;
+            }
+        };
+
+        Response {
+            platform,
+            channel: crate::channel_name(),
+            corr_id: corr_id(),
+            payload: Payload::Sessions(sessions),
+        }
+        .send(location, &self.msg_out_tx)
+        .await;
+    }
+
+    async fn dump_log_levels(
+        &self,
+        platform: Platform,
+        location: Location,
+        levels: log_level::LogLevels,
+    ) {
+        Response {
+            platform,
+            channel: crate::channel_name(),
+            corr_id: corr_id(),
+            payload: Payload::LogLevels(levels),
+        }
+        .send(location, &self.msg_out_tx)
+        .await;
+    }
+
+    async fn dump_connections(&self, platform: Platform, location: Location) {
+        let (tx, rx) = oneshot::channel();
+        if self.ws_list_tx.send(tx).await.is_err() {
+            tracing::error!("ws server is gone");
+            return;
+        }
+        let conns = match rx.await {
+            Ok(conns) => conns,
+            Err(e) => {
+                tracing::error!("{}", e);
+                return;
+            }
+        };
+
+        Response {
+            platform,
+            channel: crate::channel_name(),
+            corr_id: corr_id(),
+            payload: Payload::Connections(conns),
+        }
+        .send(location, &self.msg_out_tx)
+        .await;
+    }
+
+    fn outbox(&self) -> dead_letter::Outbox {
+        dead_letter::Outbox::new(
+            self.pub_in_tx.clone(),
+            self.ws_in_tx.clone(),
+            self.cache.clone(),
+        )
+    }
+
+    async fn dump_dead_letters(&self, platform: Platform, location: Location) {
+        let letters = match dead_letter::Outbox::dead_letters(&self.cache).await {
+            Ok(letters) => letters,
+            Err(e) => {
+                tracing::error!("{}", e);
+                return;
+            }
+        };
+
+        Response {
+            platform,
+            channel: crate::channel_name(),
+            corr_id: corr_id(),
+            payload: Payload::DeadLetters(letters),
+        }
+        .send(location, &self.msg_out_tx)
+        .await;
+    }
+
+    async fn dump_meme_queue(&self, platform: Platform, location: Location) {
+        let queue = match cmds::memebank::MemeBank::queue(&self.cache).await {
+            Ok(queue) => queue,
+            Err(e) => {
+                tracing::error!("{}", e);
+                return;
+            }
+        };
+
+        Response {
+            platform,
+            channel: crate::channel_name(),
+            corr_id: corr_id(),
+            payload: Payload::MemeQueue(queue),
+        }
+        .send(location, &self.msg_out_tx)
+        .await;
+    }
+
+    async fn dump_service_accounts(&self, platform: Platform, location: Location) {
+        Response {
+            platform,
+            channel: crate::channel_name(),
+            corr_id: corr_id(),
+            payload: Payload::ServiceAccounts(self.service_accounts.list().to_vec()),
+        }
+        .send(location, &self.msg_out_tx)
+        .await;
+    }
+
+    async fn dump_perm_map(&self, platform: Platform, location: Location) {
+        Response {
+            platform,
+            channel: crate::channel_name(),
+            corr_id: corr_id(),
+            payload: Payload::PermMap(self.perm_map.list().to_vec()),
+        }
+        .send(location, &self.msg_out_tx)
+        .await;
+    }
+
+    async fn dump_flags(&self, platform: Platform, location: Location) {
+        let flags = match crate::flags::all(&self.cache).await {
+            Ok(flags) => flags,
+            Err(e) => {
+                tracing::error!("{}", e);
+                return;
+            }
+        };
+        let mut flags: Vec<_> = flags.iter().map(|(n, f)| (n.clone(), *f)).collect();
+        flags.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        Response {
+            platform,
+            channel: crate::channel_name(),
+            corr_id: corr_id(),
+            payload: Payload::Flags(flags),
+        }
+        .send(location, &self.msg_out_tx)
+        .await;
+    }
+
+    async fn list_locks(&self, platform: Platform, location: Location) {
+        let locks = match self.lock.list().await {
+            Ok(locks) => locks,
+            Err(e) => {
+                tracing::error!("{}", e);
+                return;
+            }
+        };
+        Response {
+            platform,
+            channel: crate::channel_name(),
+            corr_id: corr_id(),
+            payload: Payload::Locks(locks),
+        }
+        .send(location, &self.msg_out_tx)
+        .await;
+    }
+
+    /// Free a lock that's been left behind, recorded in the config audit log
+    async fn force_unlock(&self, key: &str, location: &Location) {
+        let held = match self.lock.force_unlock(key).await {
+            Ok(Some(held)) => held,
+            Ok(None) => {
+                tracing::info!(key, "force unlock of a lock that isn't held");
+                return;
+            }
+            Err(e) => {
+                tracing::error!(key, "couldn't force unlock: {}", e);
+                return;
+            }
+        };
+        tracing::warn!(
+            key,
+            owner = held.owner.as_str(),
+            since = held.since,
+            ttl = held.ttl,
+            "force unlocked"
+        );
+        let change = cmds::ConfigChange {
+            list: "locks".into(),
+            kind: "lock".into(),
+            name: key.to_owned(),
+            change: cmds::ChangeKind::Removed,
+        };
+        self.audit_config(vec![change], "ForceUnlock", author(location))
+            .await;
+    }
+
+    async fn dump_profiles(&self, platform: Platform, location: Location) {
+        Response {
+            platform,
+            channel: crate::channel_name(),
+            corr_id: corr_id(),
+            payload: Payload::Profiles(self.profiles.list().to_vec()),
+        }
+        .send(location, &self.msg_out_tx)
+        .await;
+    }
+
+    /// Wait for the lock on the config on disk, answering ConfigBusy if it can't be had
+    async fn lock_config(&self, platform: Platform, location: &Location) -> bool {
+        let deadline = tokio::time::Instant::now() + CONFIG_LOCK_WAIT;
+        loop {
+            match self.lock.lock(&*CONFIG_FILE_LOCK, 5).await {
+                Ok(true) => return true,
+                Ok(false) if tokio::time::Instant::now() < deadline => {
+                    tokio::time::sleep(CONFIG_LOCK_RETRY).await;
+                }
+                Ok(false) => {
+                    tracing::warn!("config is busy, giving up");
+                    break;
+                }
+                Err(e) => {
+                    tracing::error!("{}", e);
+                    break;
+                }
+            }
+        }
+
+        Response {
+            platform,
+            channel: crate::channel_name(),
+            corr_id: corr_id(),
+            payload: Payload::ConfigBusy,
+        }
+        .send(location.clone(), &self.msg_out_tx)
+        .await;
+        false
+    }
+
+    /// Save the profile, or delete it if `tags` is None
+    async fn save_profile(
+        &self,
+        name: String,
+        tags: Option<profile::TagStates>,
+        platform: Platform,
+        location: Location,
+    ) {
+        if !self.lock_config(platform, &location).await {
+            return;
+        }
+        let res = self.profiles.set(name, tags).await;
+        let _ = self.lock.unlock(&*CONFIG_FILE_LOCK).await;
+        if let Err(e) = res {
+            tracing::error!("{}", e);
+            return;
+        }
+        self.dump_profiles(platform, location).await;
+    }
+
+    /// Switch commands on or off by tag, saved like any other config change
+    async fn set_tags(
+        &self,
+        states: &profile::TagStates,
+        action: &'static str,
+        platform: Platform,
+        location: Location,
+    ) {
+        if !self.lock_config(platform, &location).await {
+            return;
+        }
+        let config = self.dump_config().with_tags(states);
+        let saved = self.set_config(config, action, platform, location).await;
+        let _ = self.lock.unlock(&*CONFIG_FILE_LOCK).await;
+
+        if saved {
+            self.init_commands(Platform::DISCORD).await;
+        }
+    }
+
+    async fn dump_currency(&self, platform: Platform, location: Location) {
+        Response {
+            platform,
+            channel: crate::channel_name(),
+            corr_id: corr_id(),
+            payload: Payload::Currency((*self.currency.get()).clone()),
+        }
+        .send(location, &self.msg_out_tx)
+        .await;
+    }
+
+    /// Check everyone's points against the ledger and report any that don't match
+    async fn audit_points(
+        db: &db::Handle,
+        repair: bool,
+        platform: Platform,
+        location: Location,
+        resp: &mpsc::Sender<(Location, Response)>,
+    ) {
+        let discrepancies = match db::Db::AuditPoints(repair).exec(db).await {
+            Ok(db::Resp::Discrepancies(d)) => d,
+            Ok(_) => unreachable!(),
+            Err(e) => {
+                tracing::error!("{}", e);
+                return;
+            }
+        };
+        tracing::info!(found = discrepancies.len(), repair, "points audited");
+
+        Response {
+            platform,
+            channel: crate::channel_name(),
+            corr_id: corr_id(),
+            payload: Payload::PointsAudit(discrepancies),
+        }
+        .send(location, resp)
+        .await;
+    }
+
+    /// Audit points every so often, only telling web clients if something's off
+    async fn audit_loop(
+        db: db::Handle,
+        every: Duration,
+        repair: bool,
+        resp: mpsc::Sender<(Location, Response)>,
+    ) {
+        let mut interval = tokio::time::interval(every);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        // skip the immediate first tick, startup is busy enough
+        interval.tick().await;
+
+        loop {
+            interval.tick().await;
+            let discrepancies = match db::Db::AuditPoints(repair).exec(&db).await {
+                Ok(db::Resp::Discrepancies(d)) => d,
+                Ok(_) => unreachable!(),
+                Err(e) => {
+                    tracing::error!("scheduled points audit failed: {}", e);
+                    continue;
+                }
+            };
+            if discrepancies.is_empty() {
+                continue;
+            }
+
+            Response {
+                platform: Platform::WEB,
+                channel: crate::channel_name(),
+                corr_id: corr_id(),
+                payload: Payload::PointsAudit(discrepancies),
+            }
+            .send(Location::Websockets(None), &resp)
+            .await;
         }
-        .send(Location::Websockets(None), &self.msg_out_tx)
-        .await;
     }
 
-    /// Send autocorrect suggestions if any, if no command was successfully run
-    async fn autocorrect(&self, ctx: &cmds::Context<'_>, res: &[error::Result<RunRes>]) {
-        // accumulate suggestions, unless at least one successful command call
-        let res = res.iter().try_fold(vec![], |mut sugg, curr| match curr {
-            Ok(RunRes::Ok) => {
-                tracing::debug!("at least one RunRes::Ok found, stopping autocorrect");
-                ControlFlow::Break(())
+    /// Let hooks know which commands ran successfully, and record their usage
+    fn command_hooks<'a>(
+        &self,
+        ctx: &cmds::Context<'_>,
+        res: impl Iterator<Item = (&'a Command, Option<&'a RunRes>)>,
+    ) {
+        for (cmd, res) in res {
+            if let Some(RunRes::Ok) = res {
+                if ctx.actor.is_none() {
+                    self.usage.record(ctx.platform, ctx.user, cmd.name());
+                }
+                let name = Arc::new(cmd.name().to_owned());
+                hook::fire(ctx, HookEvent::Command { name });
             }
-            Ok(RunRes::Autocorrect(prefix)) => {
-                sugg.push(prefix.to_owned());
-                ControlFlow::Continue(sugg)
+        }
+    }
+
+    /// Reply to users with why their command didn't run, for commands with verbose_errors set.
+    /// `tried` says whether a prefix is what the user ran
+    async fn explain_errors<'a>(
+        &self,
+        ctx: &cmds::Context<'_>,
+        res: impl Iterator<Item = (&'a Command, Option<&'a RunRes>)>,
+        tried: impl Fn(&str) -> bool,
+    ) {
+        for (cmd, res) in res {
+            if !cmd.verbose_errors() {
+                continue;
             }
-            _ => ControlFlow::Continue(sugg),
-        });
 
-        let autocorrect_list = match res {
-            ControlFlow::Continue(res) => res,
-            _ => return,
-        };
+            // turned away like it was off, unless it's the perms that are missing
+            let insufficient_perms = match res {
+                Some(RunRes::InsufficientPerms) => true,
+                None | Some(RunRes::Disabled) => cmd.lacks_perms(ctx, &tried),
+                _ => false,
+            };
+            let msg = match res {
+                _ if insufficient_perms => tr(
+                    "errors.insufficient_perms",
+                    &[("cmd", &cmd.prefix().unwrap_or_default())],
+                ),
+                Some(RunRes::InvalidArgs) => match cmd.usage(ctx.platform) {
+                    Some(usage) => tr("errors.invalid_args_usage", &[("usage", &usage)]),
+                    None => tr("errors.invalid_args", &[]),
+                },
+                Some(RunRes::OutOfRange { min, max }) => {
+                    tr("errors.out_of_range", &[("min", min), ("max", max)])
+                }
+                _ => continue,
+            };
+
+            tracing::debug!(msg = msg.as_str(), "explaining error");
 
-        // send suggestions if any
-        if !autocorrect_list.is_empty() {
-            tracing::info!(suggestions=?autocorrect_list, "autocorrect");
-            // send resp
             Response {
                 platform: ctx.platform,
-                channel: &*crate::CHANNEL_NAME,
-                payload: Payload::Autocorrect(ctx.user.clone(), autocorrect_list),
+                channel: crate::channel_name(),
+                corr_id: corr_id(),
+                payload: Payload::Message {
+                    user: Some((ctx.platform, ctx.user.clone())),
+                    msg: msg.into(),
+                    meta: ctx.meta.clone(),
+                    hint: None,
+                    thread: None,
+                },
             }
             .send(ctx.location.clone(), ctx.resp)
             .await;
@@ -652,19 +2259,28 @@ impl Server {
 
     #[tracing::instrument(skip(self))]
     async fn started(&self, platform: Platform, location: Location) {
+        ready::started(platform);
+
+        // pings that went unanswered while it was down
+        if let Err(e) = cmds::ping::Ping::redeliver(&self.cache, &self.msg_out_tx, platform).await {
+            tracing::error!("{}", e);
+        }
+
         match platform {
             Platform::DISCORD => {
                 self.dump_args(platform, location, platform).await;
+                self.init_commands(platform).await;
             }
             platform if Platform::STREAM.contains(platform) => {
-                let url_key = format!("aussiebot!{}!streamurl!{}", &*super::CHANNEL_NAME, platform);
+                let url_key = format!("aussiebot!{}!streamurl!{}", super::channel_name(), platform);
                 if let Ok(RespType::String(url)) =
                     Cache::Get(url_key.into()).exec(&self.cache).await
                 {
                     tracing::info!(url=%url,"sending start");
                     Response {
                         platform,
-                        channel: &*crate::CHANNEL_NAME,
+                        channel: crate::channel_name(),
+                        corr_id: corr_id(),
                         payload: Payload::StreamSignal(StreamSignal::Start(url.into())),
                     }
                     .send(Location::Broadcast, &self.msg_out_tx)
@@ -675,6 +2291,19 @@ impl Server {
         }
     }
 
+    /// Let commands set themselves up on `platform`, when it connects or the config changes
+    async fn init_commands(&self, platform: Platform) {
+        let invocation = Invocation {
+            cmd: Arc::new("@init".into()),
+            args: HashMap::with_capacity(0),
+            kind: Some(InvocationKind::Init),
+            meta: None,
+            user: SystemActor::Init.user(),
+        };
+
+        self.invoke(platform, &invocation, Location::Pubsub).await;
+    }
+
     async fn dump_args(&self, platform: Platform, location: Location, args_platform: Platform) {
         let cmds = self.commands.read().clone();
         let args: ArgsDump = cmds
@@ -683,13 +2312,288 @@ impl Server {
             .collect();
         Response {
             platform,
-            channel: &*crate::CHANNEL_NAME,
+            channel: crate::channel_name(),
+            corr_id: corr_id(),
             payload: Payload::ArgsDump(args),
         }
         .send(location, &self.msg_out_tx)
         .await;
     }
 
+    /// Replace the config and save it to disk, unless commands in it share prefixes.
+    /// The config file lock has to be held
+    /// `action` is the payload the change came in, for the audit log
+    async fn set_config(
+        &self,
+        config: cmds::CommandConfig,
+        action: &'static str,
+        platform: Platform,
+        location: Location,
+    ) -> bool {
+        if let Err(conflicts) = self.save_config(config, action, author(&location)).await {
+            Response {
+                platform,
+                channel: crate::channel_name(),
+                corr_id: corr_id(),
+                payload: Payload::ConfigRejected(conflicts),
+            }
+            .send(location, &self.msg_out_tx)
+            .await;
+            return false;
+        }
+
+        // send ok to dumper
+        Response {
+            platform,
+            channel: crate::channel_name(),
+            corr_id: corr_id(),
+            payload: Payload::ConfigSaved,
+        }
+        .send(location, &self.msg_out_tx)
+        .await;
+
+        // broadcast config change notif
+        Response {
+            platform,
+            channel: crate::channel_name(),
+            corr_id: corr_id(),
+            payload: Payload::ConfigChanged,
+        }
+        .send(Location::Broadcast, &self.msg_out_tx)
+        .await;
+
+        true
+    }
+
+    /// What [`Self::set_config`] does before replying, for changes that don't come from an editor.
+    /// The config file lock has to be held
+    async fn save_config(
+        &self,
+        config: cmds::CommandConfig,
+        action: &'static str,
+        author: Arc<String>,
+    ) -> Result<(), Vec<cmds::PrefixConflict>> {
+        let conflicts = config.prefix_conflicts();
+        if !conflicts.is_empty() {
+            tracing::warn!(?conflicts, "rejecting config with conflicting prefixes");
+            return Err(conflicts);
+        }
+
+        let changes = self.dump_config().diff(&config);
+
+        // TODO: filter out invalid commands from active config
+        self.handle_cmds_with_tasks(&config.commands, &config.timers);
+        *self.commands.write() = config.commands.clone();
+        *self.filters.write() = config.filters.clone();
+        *self.timers.write() = config.timers.clone();
+
+        // dump to disk
+        let _ = futures_util::future::join3(
+            cmds::save_cmds(&config.commands),
+            cmds::save_filters(&config.filters),
+            cmds::save_timers(&config.timers),
+        )
+        .await;
+        self.audit_config(changes, action, author).await;
+        Ok(())
+    }
+
+    /// Record who made a config change and what it changed, if anything
+    async fn audit_config(
+        &self,
+        changes: Vec<cmds::ConfigChange>,
+        action: &'static str,
+        author: Arc<String>,
+    ) {
+        if changes.is_empty() {
+            return;
+        }
+        tracing::info!(
+            author = author.as_str(),
+            action,
+            changes = changes.len(),
+            "config changed"
+        );
+
+        let op = db::config_audit::AuditOp {
+            author,
+            action,
+            changes,
+        };
+        if let Err(e) = db::Db::RecordConfigChange(op).exec(&self.db).await {
+            tracing::error!("couldn't record config change: {}", e);
+        }
+    }
+
+    /// Save a command added, edited or removed from chat, and tell whoever did it how it went
+    /// Boxed, since it goes back through invoke to set commands up again
+    fn chat_config_edit<'a>(
+        &'a self,
+        ctx: &'a cmds::Context<'a>,
+        edit: cmds::ConfigEdit,
+    ) -> BoxFuture<'a, ()> {
+        async move {
+            let author = Arc::new(format!("{} ({})", ctx.user.name, ctx.platform));
+            let msg = match self.lock.lock(&*CONFIG_FILE_LOCK, 5).await {
+                Ok(true) => {
+                    let saved = match edit.patch.apply(&self.dump_config()) {
+                        Ok(config) => self
+                            .save_config(config, edit.action, author)
+                            .await
+                            .map(|_| true),
+                        // removed by someone else in the meantime
+                        Err(unknown) => {
+                            tracing::warn!(?unknown, "chat config edit of unknown commands");
+                            Ok(false)
+                        }
+                    };
+                    let _ = self.lock.unlock(&*CONFIG_FILE_LOCK).await;
+                    match saved {
+                        Ok(true) => {
+                            Response {
+                                platform: ctx.platform,
+                                channel: crate::channel_name(),
+                                corr_id: corr_id(),
+                                payload: Payload::ConfigChanged,
+                            }
+                            .send(Location::Broadcast, &self.msg_out_tx)
+                            .await;
+                            self.init_commands(Platform::DISCORD).await;
+                            edit.done
+                        }
+                        Ok(false) => tr("customcmd.gone", &[]),
+                        Err(_) => tr("customcmd.conflict", &[]),
+                    }
+                }
+                Ok(false) => tr("customcmd.busy", &[]),
+                Err(e) => {
+                    tracing::error!("{}", e);
+                    tr("customcmd.busy", &[])
+                }
+            };
+
+            Response {
+                platform: ctx.platform,
+                channel: crate::channel_name(),
+                corr_id: ctx.corr_id.clone(),
+                payload: Payload::Message {
+                    user: ctx.reply_to(),
+                    msg: msg.into(),
+                    meta: ctx.meta.clone(),
+                    hint: None,
+                    thread: None,
+                },
+            }
+            .send(Location::Pubsub, &self.msg_out_tx)
+            .await;
+        }
+        .boxed()
+    }
+
+    async fn start_multiplier(
+        &self,
+        on: Platform,
+        factor: f64,
+        secs: u64,
+        platform: Platform,
+        location: Location,
+    ) {
+        let on = on & Platform::CHAT;
+        if on.is_empty()
+            || !(factor > 1.0 && factor <= cmds::multiplier::MAX_FACTOR)
+            || secs == 0
+            || secs > cmds::multiplier::MAX_SECS
+        {
+            tracing::warn!(platforms=?on, factor, secs, "invalid multiplier");
+            return;
+        }
+        let by = match &location {
+            Location::Websocket(username, ..) => username.clone(),
+            _ => Arc::new("internal".to_owned()),
+        };
+        let active = match cmds::multiplier::start(&self.cache, on, factor, secs, by).await {
+            Ok(active) => active,
+            Err(e) => {
+                tracing::error!("{}", e);
+                return;
+            }
+        };
+        self.announce(on, cmds::multiplier::started_msg(&active, on))
+            .await;
+        self.dump_multipliers(platform, location).await;
+    }
+
+    async fn stop_multiplier(&self, on: Platform, platform: Platform, location: Location) {
+        let granted = match cmds::multiplier::stop(&self.cache, on).await {
+            Ok(granted) => granted,
+            Err(e) => {
+                tracing::error!("{}", e);
+                return;
+            }
+        };
+        if !granted.is_empty() {
+            let stopped = granted.iter().fold(Platform::empty(), |on, (p, _)| on | *p);
+            let msg = cmds::multiplier::stopped_msg(&granted, &self.currency.get());
+            self.announce(stopped, msg).await;
+        }
+        self.dump_multipliers(platform, location).await;
+    }
+
+    async fn dump_multipliers(&self, platform: Platform, location: Location) {
+        let multipliers = match cmds::multiplier::dump(&self.cache).await {
+            Ok(multipliers) => multipliers,
+            Err(e) => {
+                tracing::error!("{}", e);
+                return;
+            }
+        };
+        Response {
+            platform,
+            channel: crate::channel_name(),
+            corr_id: corr_id(),
+            payload: Payload::Multipliers(multipliers),
+        }
+        .send(location, &self.msg_out_tx)
+        .await;
+    }
+
+    /// Post to chat on the platforms, as if a timer sent it
+    async fn announce(&self, platforms: Platform, msg: String) {
+        Response {
+            platform: platforms,
+            channel: crate::channel_name(),
+            corr_id: corr_id(),
+            payload: Payload::Message {
+                user: None,
+                msg: msg.into(),
+                meta: None,
+                hint: None,
+                thread: None,
+            },
+        }
+        .send(Location::Pubsub, &self.msg_out_tx)
+        .await;
+    }
+
+    async fn dump_config_audit(&self, limit: u32, platform: Platform, location: Location) {
+        let audit = match db::Db::ConfigAudit(limit as i64).exec(&self.db).await {
+            Ok(db::Resp::ConfigAudit(audit)) => audit,
+            Ok(_) => unreachable!(),
+            Err(e) => {
+                tracing::error!("{}", e);
+                return;
+            }
+        };
+        Response {
+            platform,
+            channel: crate::channel_name(),
+            corr_id: corr_id(),
+            payload: Payload::ConfigAudit(audit),
+        }
+        .send(location, &self.msg_out_tx)
+        .await;
+    }
+
     fn dump_config(&self) -> cmds::CommandConfig {
         //Result<Result<String, serde_json::Error>, tokio::task::JoinError> {
         let commands = self.commands.read().clone();
@@ -703,7 +2607,7 @@ impl Server {
         }
     }
 
-    fn handle_cmds_with_tasks(&self, commands: &[Command], timers: &[Command]) {
+    fn handle_cmds_with_tasks(&self, commands: &Arc<Vec<Command>>, timers: &Arc<Vec<Command>>) {
         // cancel existing timer/log tasks if any
         if let Some(cancel_chan) = self.cancel_tasks.write().take() {
             let _ = cancel_chan.send(());
@@ -712,17 +2616,177 @@ impl Server {
 
         let (cancel_chan_tx, cancel_chan_rx) = watch::channel(()); //spmc
 
-        // start new timer tasks
-        for timer in timers {
+        // everything but the watchlist is left to the leader, this runs again when that changes
+        let leading = crate::leader::is_leader();
+
+        // start new timer tasks, restarted by the watchdog if they die
+        for (i, timer) in timers.iter().enumerate().filter(|_| leading) {
             if let Command::Timer(t) = timer {
-                t.init(cancel_chan_rx.clone(), &self.cache, &self.msg_out_tx);
+                let (timers, cancel_chan_rx, cache, resp, commands) = (
+                    timers.clone(),
+                    cancel_chan_rx.clone(),
+                    self.cache.clone(),
+                    self.msg_out_tx.clone(),
+                    commands.clone(),
+                );
+                watchdog::supervise(
+                    stringify!(Timer),
+                    t.name.clone(),
+                    cancel_chan_rx.clone(),
+                    self.msg_out_tx.clone(),
+                    move || match &timers[i] {
+                        Command::Timer(t) => {
+                            t.init(cancel_chan_rx.clone(), &cache, &resp, &commands)
+                        }
+                        _ => None,
+                    },
+                );
+            } else if let Command::ChatStats(stats) = timer {
+                let (timers, cancel_chan_rx, cache, resp) = (
+                    timers.clone(),
+                    cancel_chan_rx.clone(),
+                    self.cache.clone(),
+                    self.msg_out_tx.clone(),
+                );
+                watchdog::supervise(
+                    stringify!(ChatStats),
+                    stats.name.clone(),
+                    cancel_chan_rx.clone(),
+                    self.msg_out_tx.clone(),
+                    move || match &timers[i] {
+                        Command::ChatStats(stats) => {
+                            stats.init(cancel_chan_rx.clone(), &cache, &resp)
+                        }
+                        _ => None,
+                    },
+                );
+            } else if let Command::EmoteStats(stats) = timer {
+                let (timers, cancel_chan_rx, cache) =
+                    (timers.clone(), cancel_chan_rx.clone(), self.cache.clone());
+                watchdog::supervise(
+                    stringify!(EmoteStats),
+                    stats.name.clone(),
+                    cancel_chan_rx.clone(),
+                    self.msg_out_tx.clone(),
+                    move || match &timers[i] {
+                        Command::EmoteStats(stats) => stats.init(cancel_chan_rx.clone(), &cache),
+                        _ => None,
+                    },
+                );
             }
         }
 
-        // start new log tasks
-        for command in commands {
-            if let Command::Log(log) = command {
-                log.init(cancel_chan_rx.clone(), &self.cache);
+        // start new log, counter, voice points, alert and role sync tasks, clean up stale heists and roulette lobbies, resume polls and trivia, start trivia auto mode and load the watchlist
+        for (i, command) in commands.iter().enumerate() {
+            match command {
+                Command::ModNotes(notes) => {
+                    notes.init(&self.db);
+                }
+                _ if !leading => {}
+                Command::Log(log) => {
+                    let (commands, cancel_chan_rx, cache) =
+                        (commands.clone(), cancel_chan_rx.clone(), self.cache.clone());
+                    watchdog::supervise(
+                        stringify!(Log),
+                        log.name.clone(),
+                        cancel_chan_rx.clone(),
+                        self.msg_out_tx.clone(),
+                        move || match &commands[i] {
+                            Command::Log(log) => log.init(cancel_chan_rx.clone(), &cache),
+                            _ => None,
+                        },
+                    );
+                }
+                Command::Counter(counter) => {
+                    counter.init(cancel_chan_rx.clone(), &self.cache, &self.db);
+                }
+                Command::VoicePoints(voice) => {
+                    voice.init(
+                        cancel_chan_rx.clone(),
+                        &self.cache,
+                        &self.db,
+                        &self.msg_out_tx,
+                    );
+                }
+                Command::Alerts(alerts) => {
+                    let (commands, cancel_chan_rx, cache, resp) = (
+                        commands.clone(),
+                        cancel_chan_rx.clone(),
+                        self.cache.clone(),
+                        self.msg_out_tx.clone(),
+                    );
+                    watchdog::supervise(
+                        stringify!(Alerts),
+                        alerts.name.clone(),
+                        cancel_chan_rx.clone(),
+                        self.msg_out_tx.clone(),
+                        move || match &commands[i] {
+                            Command::Alerts(alerts) => {
+                                alerts.init(cancel_chan_rx.clone(), &cache, &resp)
+                            }
+                            _ => None,
+                        },
+                    );
+                }
+                Command::RoleReward(reward) => {
+                    reward.init(
+                        cancel_chan_rx.clone(),
+                        &self.cache,
+                        &self.db,
+                        &self.msg_out_tx,
+                    );
+                }
+                Command::RussianRoulette(rr) => {
+                    rr.init(
+                        cancel_chan_rx.clone(),
+                        &self.cache,
+                        &self.db,
+                        &self.lock,
+                        &self.msg_out_tx,
+                    );
+                }
+                Command::Heist(heist) => {
+                    heist.init(
+                        cancel_chan_rx.clone(),
+                        &self.cache,
+                        &self.db,
+                        &self.lock,
+                        &self.msg_out_tx,
+                    );
+                }
+                Command::Poll(poll) => {
+                    poll.init(cancel_chan_rx.clone(), &self.cache, &self.msg_out_tx);
+                }
+                Command::Prediction(prediction) => {
+                    prediction.init(
+                        cancel_chan_rx.clone(),
+                        &self.cache,
+                        &self.db,
+                        &self.msg_out_tx,
+                    );
+                }
+                Command::Trivia(trivia) => {
+                    trivia.init(cancel_chan_rx.clone(), &self.cache, &self.msg_out_tx);
+                    let (commands, cancel_chan_rx, cache, resp) = (
+                        commands.clone(),
+                        cancel_chan_rx.clone(),
+                        self.cache.clone(),
+                        self.msg_out_tx.clone(),
+                    );
+                    watchdog::supervise(
+                        stringify!(Trivia),
+                        trivia.name.clone(),
+                        cancel_chan_rx.clone(),
+                        self.msg_out_tx.clone(),
+                        move || match &commands[i] {
+                            Command::Trivia(trivia) => {
+                                trivia.auto(cancel_chan_rx.clone(), &cache, &resp)
+                            }
+                            _ => None,
+                        },
+                    );
+                }
+                _ => {}
             }
         }
 
@@ -730,6 +2794,28 @@ impl Server {
         *self.cancel_tasks.write() = Some(cancel_chan_tx);
     }
 
+    /// Report on the session that just ended to the Discord mod channel and the dashboard
+    async fn session_summary(&self, platform: Platform) {
+        let summary = match session::end(&self.cache, &self.db, platform).await {
+            Ok(Some(summary)) => summary,
+            Ok(None) => return,
+            Err(e) => {
+                tracing::error!("couldn't summarize session: {}", e);
+                return;
+            }
+        };
+        tracing::info!(summary = ?summary, "session ended");
+
+        Response {
+            platform: Platform::ANNOUNCE,
+            channel: crate::channel_name(),
+            corr_id: corr_id(),
+            payload: Payload::SessionSummary(summary),
+        }
+        .send(Location::Broadcast, &self.msg_out_tx)
+        .await;
+    }
+
     #[tracing::instrument(skip(self))]
     async fn stream_event(&self, platform: Platform, event: StreamEvent, location: Location) {
         match event {
@@ -739,7 +2825,8 @@ impl Server {
                 tracing::info!(url=%url,"sending start");
                 Response {
                     platform,
-                    channel: &*crate::CHANNEL_NAME,
+                    channel: crate::channel_name(),
+                    corr_id: corr_id(),
                     payload: Payload::StreamSignal(StreamSignal::Start(url.clone())),
                 }
                 .send(Location::Broadcast, &self.msg_out_tx)
@@ -749,18 +2836,25 @@ impl Server {
             StreamEvent::DetectStop(ref url) => {
                 // broadcast stream stop signal
                 tracing::info!(url=%url,"sending stop");
+                let _ = Cache::Delete(uptime::metadata_key(platform).into())
+                    .exec(&self.cache)
+                    .await;
                 Response {
                     platform,
-                    channel: &*crate::CHANNEL_NAME,
+                    channel: crate::channel_name(),
+                    corr_id: corr_id(),
                     payload: Payload::StreamSignal(StreamSignal::Stop(url.clone())),
                 }
                 .send(Location::Broadcast, &self.msg_out_tx)
                 .await;
             }
             StreamEvent::Started(ref url, ref id) => {
+                if let Err(e) = session::start(&self.cache).await {
+                    tracing::error!("couldn't start session: {}", e);
+                }
                 // fetch swap stream id, announce if different
-                let id_key = format!("aussiebot!{}!streamid!{}", &*super::CHANNEL_NAME, platform);
-                let url_key = format!("aussiebot!{}!streamurl!{}", &*super::CHANNEL_NAME, platform);
+                let id_key = format!("aussiebot!{}!streamid!{}", super::channel_name(), platform);
+                let url_key = format!("aussiebot!{}!streamurl!{}", super::channel_name(), platform);
                 let (_, prev_id) = tokio::join!(
                     Cache::Set(url_key.into(), url.clone(), 0, false).exec(&self.cache),
                     Cache::SetGet(id_key.into(), id.clone(), 0).exec(&self.cache)
@@ -778,36 +2872,148 @@ impl Server {
                         args: HashMap::with_capacity(0),
                         kind: Some(InvocationKind::StreamEvent(event)),
                         meta: None,
-                        user: Arc::new(User::default()),
+                        user: SystemActor::StreamEvent.user(),
                     };
 
                     self.invoke(platform, &invocation, location).await;
                 }
             }
+            StreamEvent::Raid { ref from, viewers } => {
+                tracing::info!(
+                    from = from.name.as_str(),
+                    viewers,
+                    "\x1b[93mIncoming raid\x1b[0m"
+                );
+                let invocation = Invocation {
+                    cmd: Arc::new("@stream_event".into()),
+                    args: HashMap::with_capacity(0),
+                    user: from.clone(),
+                    kind: Some(InvocationKind::StreamEvent(event)),
+                    meta: None,
+                };
+
+                self.invoke(platform, &invocation, location).await;
+            }
             StreamEvent::Stopped(vid) => {
                 tracing::info!(vid = %vid, "stop event");
+                let _ = Cache::Delete(uptime::metadata_key(platform).into())
+                    .exec(&self.cache)
+                    .await;
+                self.session_summary(platform).await;
+            }
+            StreamEvent::Metadata {
+                title,
+                started_at,
+                viewer_count,
+            } => {
+                let metadata = uptime::Metadata {
+                    title,
+                    started_at,
+                    viewer_count,
+                };
+                let res = match serde_json::to_string(&metadata) {
+                    Ok(value) => {
+                        Cache::Set(
+                            uptime::metadata_key(platform).into(),
+                            value.into(),
+                            uptime::METADATA_TTL,
+                            false,
+                        )
+                        .exec(&self.cache)
+                        .await
+                    }
+                    Err(e) => Err(e.into()),
+                };
+                if let Err(e) = res {
+                    tracing::error!("{}", e);
+                }
             }
         }
     }
 
-    async fn msg_rx_loop(self, mut msg_in_rx: mpsc::Receiver<(Location, String)>) {
+    #[tracing::instrument(skip(self))]
+    async fn monetization(
+        &self,
+        platform: Platform,
+        user: Arc<User>,
+        event: Monetization,
+        location: Location,
+    ) {
+        tracing::info!("\x1b[93mMonetization event received\x1b[0m");
+
+        let invocation = Invocation {
+            cmd: Arc::new("@monetization".into()),
+            args: HashMap::with_capacity(0),
+            kind: Some(InvocationKind::Monetization(event)),
+            meta: None,
+            user,
+        };
+
+        self.invoke(platform, &invocation, location).await;
+    }
+
+    async fn alert(&self, platform: Platform, user: Arc<User>, event: Alert, location: Location) {
+        tracing::info!("\x1b[93mAlert received\x1b[0m");
+
+        let invocation = Invocation {
+            cmd: Arc::new("@alert".into()),
+            args: HashMap::with_capacity(0),
+            kind: Some(InvocationKind::Alert(event)),
+            meta: None,
+            user,
+        };
+
+        self.invoke(platform, &invocation, location).await;
+    }
+
+    async fn msg_rx_loop(self, mut msg_in_rx: mpsc::Receiver<(Location, Incoming)>) {
         while let Some(msg) = msg_in_rx.recv().await {
             let (loc, msg) = msg;
             //println!("msg recv: {} from {:?}", msg, loc);
             let server = self.clone();
             //tokio::spawn(async move {
-            let msg = tokio::task::spawn_blocking(move || {
-                let de = serde_json::from_str::<Message>(&msg);
-                (msg, de)
-            })
-            .await;
+            let from_pubsub = matches!(loc, Location::Pubsub);
+            let msg = match msg {
+                // ws peers are authenticated when they connect
+                Incoming::Parsed(msg) => Ok((Signature::NotRequired, Ok(msg))),
+                Incoming::Raw(msg) => {
+                    tokio::task::spawn_blocking(move || {
+                        let signature = if from_pubsub {
+                            pubsub::sign::verify(&crate::config::get().upstream_chan, &msg)
+                        } else {
+                            Signature::NotRequired
+                        };
+                        let de = serde_json::from_str::<Message>(&msg).map_err(|e| (msg, e));
+                        (signature, de)
+                    })
+                    .await
+                }
+            };
             match msg {
-                Ok((_, Ok(msg))) => {
-                    tokio::spawn(async move {
-                        server.msg(msg, loc).await;
-                    });
+                Ok((signature, Ok(msg)))
+                    if !signature.trusted() && msg.payload.needs_signature() =>
+                {
+                    let dropped = UNSIGNED_DROPPED.fetch_add(1, Ordering::Relaxed) + 1;
+                    tracing::warn!(
+                        platform = %msg.platform,
+                        kind = msg.payload.kind(),
+                        signature = ?signature,
+                        dropped,
+                        "dropping unsigned message"
+                    );
+                }
+                Ok((_, Ok(mut msg))) => {
+                    let corr_id = msg.corr_id.get_or_insert_with(new_corr_id).clone();
+                    let span = tracing::info_span!("msg", corr_id = corr_id.as_str(), platform = %msg.platform);
+                    tokio::spawn(
+                        CORR_ID
+                            .scope(corr_id, async move {
+                                server.msg(msg, loc).await;
+                            })
+                            .instrument(span),
+                    );
                 }
-                Ok((orig_msg, Err(e))) => {
+                Ok((_, Err((orig_msg, e)))) => {
                     tracing::error!(orig_msg = ?orig_msg, loc = ?loc, "INVALID MSG: {}", e);
                 }
                 Err(e) => {
@@ -819,35 +3025,92 @@ impl Server {
     }
 
     async fn msg_tx_loop(self, mut msg_out_rx: mpsc::Receiver<(Location, Response)>) {
+        let outbox = self.outbox();
         while let Some(msg) = msg_out_rx.recv().await {
-            let (loc, msg) = msg;
-            // serialise msg
-            let msg = tokio::task::spawn_blocking(move || serde_json::to_string(&msg)).await;
-            if let Ok(Ok(msg)) = msg {
-                // TODO: by making an arc we just defer cloning to the edges, i.e before writing out to each ws' stream. pubsub can take a &str, but not ws
-                let msg = Arc::new(msg);
-                // route accordingly
-                match loc {
-                    Location::Pubsub => {
-                        let _ = self.pub_in_tx.send(msg).await;
-                    }
-                    Location::Websocket(username, addr) => {
-                        let _ = self
-                            .ws_in_tx
-                            .send((Some(vec![(username, addr)]), msg))
-                            .await;
-                    }
-                    Location::Websockets(addrs) => {
-                        let _ = self.ws_in_tx.send((addrs, msg)).await;
-                    }
-                    Location::Broadcast => {
-                        let _ = tokio::join!(
-                            self.pub_in_tx.send(msg.clone()),
-                            self.ws_in_tx.send((None, msg))
-                        );
+            let (loc, mut msg) = msg;
+            let platform = msg.platform;
+            let span = tracing::debug_span!(
+                "reply",
+                corr_id = msg.corr_id.as_ref().map(|id| id.as_str()),
+                platform = %platform
+            );
+            let shorten = Platform::CHAT.intersects(platform)
+                && reply_text(&msg).is_some_and(crate::urls::shortenable);
+            if shorten {
+                // the shortener's called off the loop so a slow one only holds up this reply,
+                // which can then go out after replies sent later
+                let outbox = outbox.clone();
+                tokio::spawn(
+                    async move {
+                        shorten_links(&mut msg).await;
+                        Self::deliver(&outbox, loc, msg).await;
                     }
-                }
+                    .instrument(span),
+                );
+            } else {
+                Self::deliver(&outbox, loc, msg).instrument(span).await;
+            }
+        }
+    }
+
+    /// Serialise a response and send it where it's going
+    async fn deliver(outbox: &dead_letter::Outbox, loc: Location, mut msg: Response) {
+        flatten_for_stream(&mut msg);
+        let platform = msg.platform;
+        let kind = msg.payload.kind();
+        // serialise msg
+        let msg = tokio::task::spawn_blocking(move || serde_json::to_string(&msg)).await;
+        if let Ok(Ok(msg)) = msg {
+            // shared as-is by every destination, ws peers only copy it when writing out to their stream
+            // failed sends are retried, then kept as dead letters
+            outbox
+                .send(loc, platform, Some(kind.into()), msg.into())
+                .await;
+        }
+    }
+
+    async fn health_loop(
+        service: Service,
+        mut health_rx: watch::Receiver<HealthStatus>,
+        msg_out_tx: mpsc::Sender<(Location, Response)>,
+    ) {
+        while health_rx.changed().await.is_ok() {
+            let status = *health_rx.borrow();
+            tracing::info!(service = ?service, status = ?status, "\x1b[93mhealth changed\x1b[0m");
+            Response {
+                platform: Platform::WEB,
+                channel: crate::channel_name(),
+                corr_id: corr_id(),
+                payload: Payload::Health(service.clone(), status),
+            }
+            .send(Location::Broadcast, &msg_out_tx)
+            .await;
+        }
+    }
+
+    /// Start or stop the background tasks as this instance gains or loses the leader lease
+    async fn leader_loop(self, mut leadership: watch::Receiver<bool>) {
+        let instance = &crate::config::server().instance;
+        while leadership.changed().await.is_ok() {
+            let leading = *leadership.borrow();
+            let commands = self.commands.read().clone();
+            let timers = self.timers.read().clone();
+            self.handle_cmds_with_tasks(&commands, &timers);
+
+            Response {
+                platform: Platform::WEB,
+                channel: crate::channel_name(),
+                corr_id: corr_id(),
+                payload: Payload::Health(
+                    Service::Leader(instance.clone()),
+                    match leading {
+                        true => HealthStatus::Up,
+                        false => HealthStatus::Down,
+                    },
+                ),
             }
+            .send(Location::Broadcast, &self.msg_out_tx)
+            .await;
         }
     }
 
@@ -855,7 +3118,7 @@ impl Server {
     #[tracing::instrument(skip_all)]
     pub fn start(
         self,
-        msg_in_rx: mpsc::Receiver<(Location, String)>,
+        msg_in_rx: mpsc::Receiver<(Location, Incoming)>,
         msg_out_rx: mpsc::Receiver<(Location, Response)>,
     ) -> JoinHandle<()> {
         tracing::info!("\x1b[92m-------------Starting message loop-------------\x1b[0m");
@@ -865,6 +3128,36 @@ impl Server {
         let timers = self.timers.read().clone();
         self.handle_cmds_with_tasks(&commands, &timers);
 
+        // take over the timers if no other instance has them
+        let leadership = crate::leader::start(self.lock.clone());
+        tokio::spawn(self.clone().leader_loop(leadership));
+
+        // hold off chat until everything it needs is up
+        tokio::spawn(Self::health_loop(
+            Service::Ready,
+            ready::start(self.cache.clone(), self.db.clone()),
+            self.msg_out_tx.clone(),
+        ));
+        if let Some(bind) = crate::config::server().readyz_bind {
+            tokio::spawn(ready::serve(bind));
+        }
+
+        // report cache status transitions
+        tokio::spawn(Self::health_loop(
+            Service::Cache,
+            self.cache.health(),
+            self.msg_out_tx.clone(),
+        ));
+
+        if let Some(every) = crate::config::server().points_audit_interval {
+            tokio::spawn(Self::audit_loop(
+                self.db.clone(),
+                every,
+                crate::config::server().points_audit_repair,
+                self.msg_out_tx.clone(),
+            ));
+        }
+
         // handle response messages
         let server = self.clone();
         tokio::spawn(server.msg_tx_loop(msg_out_rx));
@@ -873,3 +3166,162 @@ impl Server {
         tokio::spawn(self.msg_rx_loop(msg_in_rx))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn web(role: Role) -> Location {
+        Location::Websocket(
+            Arc::new("discord:someone".to_owned()),
+            "127.0.0.1:9000".parse().unwrap(),
+            role,
+        )
+    }
+
+    fn invocation(kind: Option<InvocationKind>) -> Invocation {
+        Invocation {
+            user: Arc::new(User {
+                id: Arc::new("1234".to_owned()),
+                name: Arc::new("streamer".to_owned()),
+                perms: Permissions::OWNER,
+                roles: vec!["owner-role".to_owned()],
+            }),
+            cmd: Arc::new("give".to_owned()),
+            args: ArgMap::new(),
+            meta: None,
+            kind,
+        }
+    }
+
+    #[test]
+    fn mod_session_cant_raise_its_perms() {
+        let username = Arc::new("discord:someone".to_owned());
+        let payload = Payload::InvokeCommand(invocation(None)).into_session(&username, Role::Mod);
+        let user = match payload {
+            Payload::InvokeCommand(invocation) => invocation.user,
+            _ => unreachable!(),
+        };
+        assert_eq!(user.perms, Permissions::MOD);
+        assert_eq!(user.id.as_str(), "@web:discord:someone");
+        assert_eq!(user.name, username);
+        // nothing for the PermMap to match on
+        assert!(user.roles.is_empty());
+    }
+
+    #[test]
+    fn web_chats_run_as_the_session() {
+        let username = Arc::new("admin".to_owned());
+        let chat = Chat {
+            user: invocation(None).user,
+            msg: Arc::new("!give @someone 1000".to_owned()),
+            meta: None,
+        };
+        let user = match Payload::Chat(chat).into_session(&username, Role::Admin) {
+            Payload::Chat(chat) => chat.user,
+            _ => unreachable!(),
+        };
+        assert_eq!(user.perms, Permissions::ADMIN);
+        assert_eq!(user.name, username);
+    }
+
+    #[test]
+    fn mods_cant_rewrite_the_config() {
+        let patch = Payload::PatchConfig(Default::default());
+        assert!(!permitted(&patch, &web(Role::Mod)));
+        assert!(permitted(&patch, &web(Role::Admin)));
+        assert!(!permitted(
+            &Payload::InvokeAs(InvokeAs::Invoke(Platform::TWITCH, invocation(None))),
+            &web(Role::Mod)
+        ));
+    }
+
+    #[test]
+    fn only_admins_run_as_others() {
+        let invoke_as = Payload::InvokeAs(InvokeAs::Invoke(Platform::TWITCH, invocation(None)));
+        assert!(permitted(&invoke_as, &web(Role::Admin)));
+        assert!(!permitted(&invoke_as, &web(Role::Mod)));
+        assert!(!permitted(&invoke_as, &Location::Pubsub));
+        assert!(!permitted(&invoke_as, &Location::Broadcast));
+
+        assert_eq!(
+            run_as_admin(&web(Role::Admin))
+                .as_deref()
+                .map(String::as_str),
+            Some("discord:someone")
+        );
+        assert!(run_as_admin(&web(Role::Mod)).is_none());
+        assert!(run_as_admin(&Location::Pubsub).is_none());
+    }
+
+    #[test]
+    fn run_as_keeps_the_synthesized_user() {
+        let username = Arc::new("admin".to_owned());
+        let payload = Payload::InvokeAs(InvokeAs::Invoke(Platform::TWITCH, invocation(None)))
+            .into_session(&username, Role::Admin);
+        let user = match payload {
+            Payload::InvokeAs(InvokeAs::Invoke(_, invocation)) => invocation.user,
+            _ => unreachable!(),
+        };
+        assert_eq!(user.id.as_str(), "1234");
+        assert_eq!(user.perms, Permissions::OWNER);
+    }
+
+    #[test]
+    fn only_the_shard_owner_claims_chats() {
+        let chat = Payload::Chat(Chat {
+            user: invocation(None).user,
+            msg: Arc::new("hi".to_owned()),
+            meta: None,
+        });
+        let invoke = Payload::InvokeCommand(invocation(None));
+        let owner = |_: Platform, _: &str| true;
+        let other = |_: Platform, _: &str| false;
+
+        for payload in [&chat, &invoke] {
+            assert!(!owned_elsewhere(
+                Platform::TWITCH,
+                payload,
+                &Location::Pubsub,
+                owner
+            ));
+            assert!(owned_elsewhere(
+                Platform::TWITCH,
+                payload,
+                &Location::Pubsub,
+                other
+            ));
+            // web UI peers are answered by whichever instance they're connected to
+            assert!(!owned_elsewhere(
+                Platform::TWITCH,
+                payload,
+                &web(Role::Admin),
+                other
+            ));
+        }
+        // everything else is handled by all of them
+        assert!(!owned_elsewhere(
+            Platform::TWITCH,
+            &Payload::VoiceSync(vec![]),
+            &Location::Pubsub,
+            other
+        ));
+    }
+
+    #[test]
+    fn web_peers_cant_send_platform_events() {
+        for role in [Role::Mod, Role::Admin] {
+            assert!(!permitted(&Payload::VoiceSync(vec![]), &web(role)));
+            assert!(!permitted(
+                &Payload::InvokeCommand(invocation(Some(InvocationKind::Init))),
+                &web(role)
+            ));
+            assert!(permitted(
+                &Payload::InvokeCommand(invocation(Some(InvocationKind::Invoke))),
+                &web(role)
+            ));
+        }
+        // connectors still can
+        assert!(permitted(&Payload::VoiceSync(vec![]), &Location::Pubsub));
+    }
+}
@@ -0,0 +1,128 @@
+//! Permission levels granted by platform roles, on top of what the connectors work out
+//! themselves (e.g. Discord's moderator permissions). Applied to everyone who chats, and
+//! kept for a while so `!perms` can say where someone's level came from
+
+use super::{Permissions, Platform, User};
+pub use crate::proto::admin::PermRule;
+use crate::{
+    cache::{self, Cache, RespType},
+    cmds::{config_path, ConfigFile},
+    error::{self, Error},
+};
+use bb8_redis::redis;
+use parking_lot::RwLock;
+use serde_derive::{Deserialize, Serialize};
+use std::{collections::HashSet, io::ErrorKind, sync::Arc};
+use tokio::fs;
+
+/// How long a user's computed level is kept for (in seconds)
+const SYNCED_TTL: usize = 86400;
+
+/// Where a user's level came from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum PermSource {
+    /// Set by the connector
+    Platform,
+    /// The rule's name, or its role if it has none
+    Rule(String),
+}
+
+/// A user's level as of their last message
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncedPerms {
+    pub perms: Permissions,
+    pub source: PermSource,
+}
+
+#[derive(Debug, Default)]
+pub struct PermMap(RwLock<Arc<Vec<PermRule>>>);
+
+fn synced_key(platform: Platform, id: &str) -> Arc<String> {
+    Arc::new(format!(
+        "aussiebot!{}!perms!{}!{}",
+        crate::channel_name(),
+        platform,
+        id
+    ))
+}
+
+impl PermMap {
+    /// Starts out empty if nothing's been saved yet
+    #[tracing::instrument]
+    pub async fn load() -> error::Result<Self> {
+        let path = crate::config_dir().join(config_path(ConfigFile::PermMap));
+        let rules = match fs::read_to_string(path).await {
+            Ok(contents) => serde_json::from_str(&contents)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => vec![],
+            Err(e) => return Err(Error::Io(e)),
+        };
+        Ok(Self(RwLock::new(Arc::new(rules))))
+    }
+
+    pub(crate) fn list(&self) -> Arc<Vec<PermRule>> {
+        self.0.read().clone()
+    }
+
+    /// Replace the rules and write them to disk
+    pub(crate) async fn set(&self, mut rules: Vec<PermRule>) -> error::Result<()> {
+        let mut seen = HashSet::new();
+        rules.retain(|r| !r.role.trim().is_empty() && seen.insert((r.platform, r.role.clone())));
+
+        let dump = serde_json::to_string_pretty(&rules)?;
+        *self.0.write() = Arc::new(rules);
+        fs::write(
+            crate::config_dir().join(config_path(ConfigFile::PermMap)),
+            dump,
+        )
+        .await
+        .map_err(Error::Io)
+    }
+
+    /// The highest level out of the connector's and every rule matching the user's roles,
+    /// the connector's winning ties
+    pub(crate) fn apply(&self, platform: Platform, user: &User) -> SyncedPerms {
+        let rules = self.0.read();
+        let best = rules
+            .iter()
+            .filter(|r| r.platform == platform && user.roles.contains(&r.role))
+            .max_by_key(|r| r.perms);
+        match best {
+            Some(rule) if rule.perms > user.perms => SyncedPerms {
+                perms: rule.perms,
+                source: PermSource::Rule(rule.name.clone().unwrap_or_else(|| rule.role.clone())),
+            },
+            _ => SyncedPerms {
+                perms: user.perms,
+                source: PermSource::Platform,
+            },
+        }
+    }
+}
+
+/// Keep a user's level for `!perms`
+pub(crate) async fn remember(
+    cache: &cache::Handle,
+    platform: Platform,
+    id: &str,
+    synced: &SyncedPerms,
+) -> error::Result<()> {
+    let json = serde_json::to_string(synced)?;
+    Cache::Set(synced_key(platform, id), json.into(), SYNCED_TTL, false)
+        .exec(cache)
+        .await?;
+    Ok(())
+}
+
+/// A user's level as of their last message, None if they haven't chatted in a while
+pub(crate) async fn recall(
+    cache: &cache::Handle,
+    platform: Platform,
+    id: &str,
+) -> error::Result<Option<SyncedPerms>> {
+    match Cache::Get(synced_key(platform, id)).exec(cache).await {
+        Ok(RespType::String(json)) => Ok(Some(serde_json::from_str(&json)?)),
+        Ok(_) => unreachable!(),
+        Err(Error::Redis(e)) if e.kind() == redis::ErrorKind::TypeError => Ok(None),
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,52 @@
+pub use crate::proto::admin::{Profile, TagStates};
+use crate::{
+    cmds::{config_path, ConfigFile},
+    error::{self, Error},
+};
+use parking_lot::RwLock;
+use std::{io::ErrorKind, sync::Arc};
+use tokio::fs;
+
+#[derive(Debug, Default)]
+pub struct Profiles(RwLock<Arc<Vec<Profile>>>);
+
+impl Profiles {
+    /// Starts out empty if nothing's been saved yet
+    #[tracing::instrument]
+    pub async fn load() -> error::Result<Self> {
+        let path = crate::config_dir().join(config_path(ConfigFile::Profiles));
+        let profiles = match fs::read_to_string(path).await {
+            Ok(contents) => serde_json::from_str(&contents)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => vec![],
+            Err(e) => return Err(Error::Io(e)),
+        };
+        Ok(Self(RwLock::new(Arc::new(profiles))))
+    }
+
+    pub(crate) fn list(&self) -> Arc<Vec<Profile>> {
+        self.0.read().clone()
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<Profile> {
+        self.0.read().iter().find(|p| p.name == name).cloned()
+    }
+
+    /// Add or replace a profile, or remove it if `tags` is None, and write them all to disk
+    pub(crate) async fn set(&self, name: String, tags: Option<TagStates>) -> error::Result<()> {
+        let mut profiles = (*self.list()).clone();
+        profiles.retain(|p| p.name != name);
+        if let Some(tags) = tags {
+            profiles.push(Profile { name, tags });
+            profiles.sort_by(|a, b| a.name.cmp(&b.name));
+        }
+
+        let dump = serde_json::to_string_pretty(&profiles)?;
+        *self.0.write() = Arc::new(profiles);
+        fs::write(
+            crate::config_dir().join(config_path(ConfigFile::Profiles)),
+            dump,
+        )
+        .await
+        .map_err(Error::Io)
+    }
+}
@@ -0,0 +1,151 @@
+//! Whether everything chat depends on is up: the cache, the database, the config files and the
+//! connectors in READY_PLATFORMS. Until it all is, chat is dropped and invocations are told to
+//! wait, the rest (web UI requests, stream events) is handled as usual
+
+use super::{HealthStatus, Platform};
+pub use crate::proto::admin::{Check, Report};
+use crate::{
+    cache::{self, Cache},
+    db::{self, Db},
+    health,
+};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use std::{
+    net::SocketAddr,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+use tokio::sync::watch;
+use tracing::{info_span, Instrument};
+
+const RETRY_EVERY: Duration = Duration::from_secs(2);
+
+struct Readiness {
+    checks: RwLock<Vec<(Check, bool)>>,
+    ready: AtomicBool,
+    tx: watch::Sender<HealthStatus>,
+}
+
+static STATE: Lazy<Readiness> = Lazy::new(|| {
+    let connectors = crate::config::server().ready_platforms;
+    let checks = [Check::Cache, Check::Database, Check::Config]
+        .into_iter()
+        .chain(
+            [Platform::YOUTUBE, Platform::TWITCH, Platform::DISCORD]
+                .into_iter()
+                .filter(|p| connectors.contains(*p))
+                .map(Check::Connector),
+        )
+        .map(|check| (check, false))
+        .collect();
+    Readiness {
+        checks: RwLock::new(checks),
+        ready: AtomicBool::new(false),
+        tx: watch::channel(HealthStatus::Down).0,
+    }
+});
+
+pub(crate) fn is_ready() -> bool {
+    STATE.ready.load(Ordering::Relaxed)
+}
+
+pub(crate) fn report() -> Report {
+    Report {
+        ready: is_ready(),
+        checks: STATE.checks.read().clone(),
+    }
+}
+
+/// Mark a check as passed, untracked ones (e.g. connectors no one waits on) are ignored
+fn pass(check: Check) {
+    let mut checks = STATE.checks.write();
+    match checks.iter_mut().find(|(c, _)| *c == check) {
+        Some((_, passed)) if !*passed => *passed = true,
+        _ => return,
+    }
+    tracing::info!(?check, "\x1b[92mcheck passed\x1b[0m");
+    if checks.iter().all(|(_, passed)| *passed) && !STATE.ready.swap(true, Ordering::Relaxed) {
+        tracing::info!("\x1b[92mready\x1b[0m");
+        STATE.tx.send_replace(HealthStatus::Up);
+    }
+}
+
+/// A connector sent NotifyStart
+pub(crate) fn started(platform: Platform) {
+    pass(Check::Connector(platform));
+}
+
+/// Run the self-test, with the result sent on the returned channel
+pub(super) fn start(cache: cache::Handle, db: db::Handle) -> watch::Receiver<HealthStatus> {
+    // only called once everything's been loaded
+    pass(Check::Config);
+
+    let timeout = crate::config::server().ready_timeout;
+    tokio::spawn(
+        async move {
+            let since = Instant::now();
+            let mut warned = false;
+            while !is_ready() {
+                if !passed(Check::Cache) {
+                    match Cache::Ping.exec(&cache).await {
+                        Ok(_) => pass(Check::Cache),
+                        Err(e) => tracing::debug!("cache not ready: {}", e),
+                    }
+                }
+                if !passed(Check::Database) {
+                    match Db::Ping.exec(&db).await {
+                        Ok(_) => pass(Check::Database),
+                        Err(e) => tracing::debug!("database not ready: {}", e),
+                    }
+                }
+                if !warned && since.elapsed() >= timeout && !is_ready() {
+                    warned = true;
+                    let waiting: Vec<_> = STATE
+                        .checks
+                        .read()
+                        .iter()
+                        .filter(|(_, passed)| !passed)
+                        .map(|(check, _)| *check)
+                        .collect();
+                    tracing::error!(
+                        ?waiting,
+                        "not ready after {}s, chat is held off until it is",
+                        timeout.as_secs()
+                    );
+                }
+                tokio::time::sleep(RETRY_EVERY).await;
+            }
+        }
+        .instrument(info_span!("self_test")),
+    );
+    STATE.tx.subscribe()
+}
+
+fn passed(check: Check) -> bool {
+    STATE
+        .checks
+        .read()
+        .iter()
+        .any(|(c, passed)| *c == check && *passed)
+}
+
+/// Answer `GET /readyz` with the Report, 200 if ready and 503 otherwise
+pub(super) async fn serve(bind: SocketAddr) {
+    health::serve_http(bind, "/readyz", |path| match path {
+        "/readyz" => {
+            let report = report();
+            let status = match report.ready {
+                true => "200 OK",
+                false => "503 Service Unavailable",
+            };
+            Some((
+                status,
+                health::JSON,
+                serde_json::to_string(&report).unwrap_or_default(),
+            ))
+        }
+        _ => None,
+    })
+    .await
+}
@@ -0,0 +1,52 @@
+use super::Platform;
+pub use crate::proto::admin::ServiceAccount;
+use crate::{
+    cmds::{config_path, ConfigFile},
+    error::{self, Error},
+};
+use parking_lot::RwLock;
+use std::{collections::HashSet, io::ErrorKind, sync::Arc};
+use tokio::fs;
+
+#[derive(Debug, Default)]
+pub struct ServiceAccounts(RwLock<Arc<Vec<ServiceAccount>>>);
+
+impl ServiceAccounts {
+    /// Starts out empty if nothing's been saved yet
+    #[tracing::instrument]
+    pub async fn load() -> error::Result<Self> {
+        let path = crate::config_dir().join(config_path(ConfigFile::ServiceAccounts));
+        let accounts = match fs::read_to_string(path).await {
+            Ok(contents) => serde_json::from_str(&contents)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => vec![],
+            Err(e) => return Err(Error::Io(e)),
+        };
+        Ok(Self(RwLock::new(Arc::new(accounts))))
+    }
+
+    pub(crate) fn is_service(&self, platform: Platform, id: &str) -> bool {
+        self.0
+            .read()
+            .iter()
+            .any(|a| a.platform == platform && a.id == id)
+    }
+
+    pub(crate) fn list(&self) -> Arc<Vec<ServiceAccount>> {
+        self.0.read().clone()
+    }
+
+    /// Replace the list and write it to disk
+    pub(crate) async fn set(&self, mut accounts: Vec<ServiceAccount>) -> error::Result<()> {
+        let mut seen = HashSet::new();
+        accounts.retain(|a| !a.id.trim().is_empty() && seen.insert((a.platform, a.id.clone())));
+
+        let dump = serde_json::to_string_pretty(&accounts)?;
+        *self.0.write() = Arc::new(accounts);
+        fs::write(
+            crate::config_dir().join(config_path(ConfigFile::ServiceAccounts)),
+            dump,
+        )
+        .await
+        .map_err(Error::Io)
+    }
+}
@@ -0,0 +1,113 @@
+//! Totals for a stream, from when it starts until it stops, reported to the mods and the dashboard.
+//! Chat messages are counted in redis, everything else is already in the db.
+//! Streams overlapping on several platforms share a session, which ends with the first one to stop
+
+use super::Platform;
+pub use crate::proto::admin::SessionSummary;
+use crate::{
+    cache::{self, Cache, RespType},
+    db::{self, Db, Resp},
+    error::{self, Error},
+};
+use bb8_redis::redis;
+use once_cell::sync::Lazy;
+use std::{
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+static START_KEY: Lazy<Arc<String>> =
+    Lazy::new(|| Arc::new(format!("aussiebot!{}!session!start", crate::channel_name())));
+static MESSAGES_KEY: Lazy<Arc<String>> = Lazy::new(|| {
+    Arc::new(format!(
+        "aussiebot!{}!session!messages",
+        crate::channel_name()
+    ))
+});
+
+impl SessionSummary {
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs(self.ended_at.saturating_sub(self.started_at))
+    }
+}
+
+fn now() -> error::Result<u64> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs())
+}
+
+/// Count a chat message towards the session
+pub(crate) async fn record_chat(cache: &cache::Handle, platform: Platform) {
+    let res = Cache::Zincrby(MESSAGES_KEY.clone(), platform.to_string().into(), 1)
+        .exec(cache)
+        .await;
+    if let Err(e) = res {
+        tracing::warn!(platform = %platform, "couldn't count chat message: {}", e);
+    }
+}
+
+/// Start a session, unless a stream on another platform already did
+pub(crate) async fn start(cache: &cache::Handle) -> error::Result<()> {
+    let started = Cache::Set(START_KEY.clone(), now()?.to_string().into(), 0, true)
+        .exec(cache)
+        .await?;
+    if let RespType::Bool(true) = started {
+        tracing::info!("session started");
+        // messages from between streams don't count
+        Cache::Delete(MESSAGES_KEY.clone()).exec(cache).await?;
+    }
+    Ok(())
+}
+
+/// When the running session started (Unix secs), None between streams
+pub(crate) async fn started_at(cache: &cache::Handle) -> error::Result<Option<u64>> {
+    match Cache::Get(START_KEY.clone()).exec(cache).await {
+        Ok(RespType::String(started_at)) => Ok(Some(started_at.parse()?)),
+        Ok(_) => unreachable!(),
+        Err(Error::Redis(e)) if e.kind() == redis::ErrorKind::TypeError => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// End the session, None if there wasn't one running
+pub(crate) async fn end(
+    cache: &cache::Handle,
+    db: &db::Handle,
+    platform: Platform,
+) -> error::Result<Option<SessionSummary>> {
+    let started_at = match Cache::GetDel(START_KEY.clone()).exec(cache).await {
+        Ok(RespType::String(started_at)) => started_at.parse::<u64>()?,
+        Ok(_) => unreachable!(),
+        Err(Error::Redis(e)) if e.kind() == redis::ErrorKind::TypeError => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let ended_at = now()?;
+
+    let messages = match Cache::Zrangewithscores(MESSAGES_KEY.clone(), 0, -1)
+        .exec(cache)
+        .await?
+    {
+        RespType::VecStringScore(counts) => counts
+            .into_iter()
+            .filter_map(|(platform, count)| {
+                Some((Platform::from_str(&platform).ok()?, count.max(0) as u64))
+            })
+            .collect(),
+        _ => unreachable!(),
+    };
+    Cache::Delete(MESSAGES_KEY.clone()).exec(cache).await?;
+
+    let since = UNIX_EPOCH + Duration::from_secs(started_at);
+    let totals = match Db::SessionTotals(since).exec(db).await? {
+        Resp::SessionTotals(totals) => totals,
+        _ => unreachable!(),
+    };
+
+    Ok(Some(SessionSummary {
+        platform,
+        started_at,
+        ended_at,
+        messages,
+        totals,
+    }))
+}
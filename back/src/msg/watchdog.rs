@@ -0,0 +1,102 @@
+//! Keeps timer and log tasks running, a panic inside one would otherwise leave it
+//! stopped until the next config change
+
+use super::{corr_id, HealthStatus, Location, Payload, Platform, Response, Service};
+use std::time::{Duration, Instant};
+use tokio::{
+    sync::{mpsc, watch},
+    task::JoinHandle,
+};
+use tracing::Instrument;
+
+/// Wait before the first restart, doubled for each one after
+const BACKOFF_MIN: Duration = Duration::from_secs(1);
+const BACKOFF_MAX: Duration = Duration::from_secs(5 * 60);
+/// A task that stays up this long starts over from the shortest wait
+const HEALTHY_AFTER: Duration = Duration::from_secs(10 * 60);
+
+/// Run the task from `spawn`, restarting it with backoff whenever it stops before
+/// `cancel_chan` fires. Nothing is supervised if `spawn` doesn't start one
+pub(crate) fn supervise<F>(
+    kind: &'static str,
+    name: String,
+    mut cancel_chan: watch::Receiver<()>,
+    resp: mpsc::Sender<(Location, Response)>,
+    spawn: F,
+) where
+    F: Fn() -> Option<JoinHandle<()>> + Send + 'static,
+{
+    let mut handle = match spawn() {
+        Some(handle) => handle,
+        None => return,
+    };
+
+    let service = Service::Task(kind.to_owned(), name.clone());
+    let health = move |status| {
+        let resp = resp.clone();
+        let service = service.clone();
+        async move {
+            Response {
+                platform: Platform::WEB,
+                channel: crate::channel_name(),
+                corr_id: corr_id(),
+                payload: Payload::Health(service, status),
+            }
+            .send(Location::Broadcast, &resp)
+            .await;
+        }
+    };
+
+    tokio::spawn(
+        async move {
+            let mut restarts = 0u32;
+            let mut backoff = BACKOFF_MIN;
+
+            loop {
+                let started = Instant::now();
+                let res = tokio::select! {
+                    res = &mut handle => res,
+                    _ = cancel_chan.changed() => return,
+                };
+
+                // the task saw the cancel first
+                if !matches!(cancel_chan.has_changed(), Ok(false)) {
+                    return;
+                }
+
+                let reason = match res {
+                    Ok(()) => "exited",
+                    Err(e) if e.is_panic() => "panicked",
+                    Err(_) => "was aborted",
+                };
+                if started.elapsed() >= HEALTHY_AFTER {
+                    backoff = BACKOFF_MIN;
+                }
+                restarts += 1;
+                tracing::error!(
+                    kind,
+                    name = name.as_str(),
+                    restarts,
+                    backoff = ?backoff,
+                    "\x1b[91mtask {}, restarting\x1b[0m",
+                    reason
+                );
+                health(HealthStatus::Down).await;
+
+                tokio::select! {
+                    _ = tokio::time::sleep(backoff) => {}
+                    _ = cancel_chan.changed() => return,
+                }
+                backoff = (backoff * 2).min(BACKOFF_MAX);
+
+                handle = match spawn() {
+                    Some(handle) => handle,
+                    None => return,
+                };
+                tracing::info!(kind, name = name.as_str(), restarts, "task restarted");
+                health(HealthStatus::Restarted(restarts)).await;
+            }
+        }
+        .instrument(tracing::info_span!("Watchdog")),
+    );
+}
@@ -0,0 +1,177 @@
+//! Settings and state the dashboard reads and changes
+
+use super::{db::SessionTotals, Location, Permissions, Platform};
+use serde_derive::{Deserialize, Serialize};
+use std::{collections::BTreeMap, sync::Arc};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Flag {
+    /// On or off for everyone
+    Bool(bool),
+    /// On for this % of checks, rolled each time
+    Percent(u8),
+}
+
+/// A lock that's held right now
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeldLock {
+    pub key: String,
+    /// Instance and where in the code it was taken, or a lease's holder
+    pub owner: String,
+    /// unix timestamp (in seconds) it was taken, 0 if unknown
+    pub since: u64,
+    /// Seconds until it expires
+    pub ttl: i64,
+}
+
+/// The levels in effect
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogLevels {
+    /// For targets without one of their own
+    pub default: String,
+    /// (target, level)
+    pub targets: Vec<(String, String)>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Check {
+    Cache,
+    Database,
+    /// Commands, filters and timers loaded
+    Config,
+    /// Sent a NotifyStart
+    Connector(Platform),
+}
+
+/// Answers a DumpHealth
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Report {
+    pub ready: bool,
+    /// Every required check, and whether it's passed
+    pub checks: Vec<(Check, bool)>,
+}
+
+/// (tag, enabled), tags left out are left as they are when the profile's applied
+pub type TagStates = BTreeMap<String, bool>;
+
+/// A named set of tag states, e.g. a "chill stream" with gambling off
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    pub tags: TagStates,
+}
+
+/// Another bot's account (e.g. Nightbot). Its chat skips filters and commands,
+/// so it earns no points and isn't greeted
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServiceAccount {
+    pub platform: Platform,
+    pub id: String,
+    /// Just for telling them apart in the UI
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+/// Which side of the amount the currency goes
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CurrencyPosition {
+    /// e.g. $ 1,000
+    Before,
+    /// e.g. 1,000 points
+    #[default]
+    After,
+}
+
+/// What points are called in replies, shared by every command
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Currency {
+    pub singular: String,
+    pub plural: String,
+    /// Shown before the name, empty for none
+    pub emoji: String,
+    /// Between every three digits, empty for none
+    pub separator: String,
+    pub position: CurrencyPosition,
+}
+
+impl Default for Currency {
+    fn default() -> Self {
+        Self {
+            singular: "point".into(),
+            plural: "points".into(),
+            emoji: String::new(),
+            separator: String::new(),
+            position: CurrencyPosition::default(),
+        }
+    }
+}
+
+/// Grants a level to anyone with the role: a Discord role id, a Twitch badge
+/// (e.g. "vip", "subscriber") or a Youtube flag ("moderator", "member", "owner")
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PermRule {
+    pub platform: Platform,
+    pub role: String,
+    pub perms: Permissions,
+    /// Just for telling them apart in the UI and in `!perms`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionSummary {
+    /// Platform whose stream stopped
+    pub platform: Platform,
+    /// Unix timestamps (in seconds)
+    pub started_at: u64,
+    pub ended_at: u64,
+    /// (platform, chat messages)
+    pub messages: Vec<(Platform, u64)>,
+    #[serde(flatten)]
+    pub totals: SessionTotals,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub format: u32,
+    /// The back server's, when it was taken
+    pub version: String,
+    pub channel: String,
+    /// unix secs
+    pub created: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum RestoreSource {
+    /// The name of an archive in BACKUP_DIR
+    File(String),
+    /// The archive itself, base64
+    Archive(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RestoreReport {
+    pub manifest: Manifest,
+    /// Written to CONFIG_DIR, they're picked up on restart
+    pub config_files: Vec<String>,
+    pub users: u64,
+    /// Links and mod actions written
+    pub records: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub location: Location,
+    pub platform: Platform,
+    /// Payload variant name, for matching against subscriptions on replay
+    #[serde(default)]
+    pub kind: Option<Arc<str>>,
+    /// The serialized Response
+    pub msg: String,
+    /// Why the last try failed
+    pub error: String,
+    /// Unix timestamp (in seconds)
+    pub failed_at: u64,
+}
@@ -0,0 +1,71 @@
+//! The login handshake and web UI sessions
+
+use serde_derive::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub enum AuthMsg {
+    ListUsers,
+    RequestCode(Arc<String>),
+    Login(Arc<String>, Arc<String>),
+    /// Answered with Providers
+    ListProviders,
+    /// Answered with OAuthRedirect, where to send the user to log in
+    OAuthStart(OAuthProvider),
+    /// The code and state the provider sent the user back with
+    OAuthLogin {
+        provider: OAuthProvider,
+        code: Arc<String>,
+        state: Arc<String>,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub enum AuthError {
+    Ratelimited,
+    ServerError,
+    /// The provider isn't set up for logins
+    Unavailable,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub enum AuthResp {
+    Users(Arc<Vec<String>>),
+    InvalidUser,
+    CodeReady,
+    CodeExpired,
+    AuthSuccess(Arc<String>),
+    AuthFail,
+    AuthError(AuthError),
+    /// OAuth providers that can be logged in with
+    Providers(Vec<OAuthProvider>),
+    OAuthRedirect(String),
+}
+
+/// What a logged in web UI peer is allowed to do
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Role {
+    /// Logged in with OAuth, can moderate but not touch bot-wide settings
+    #[default]
+    Mod,
+    /// In the users file
+    Admin,
+}
+
+/// A logged in web UI peer
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Session {
+    pub id: String,
+    pub user: String,
+    pub ip: String,
+    /// unix timestamp (in seconds)
+    pub created: i64,
+    #[serde(default)]
+    pub role: Role,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OAuthProvider {
+    Discord,
+    Twitch,
+}
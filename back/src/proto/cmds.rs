@@ -0,0 +1,242 @@
+//! Command config, args and what commands report, as they go over the wire
+
+use super::{Permissions, Platform, User};
+use serde_derive::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt::Display, sync::Arc};
+
+#[derive(Deserialize, Serialize)]
+pub enum Value {
+    None,
+    String(String),
+    Number(i64),
+    Bool(bool),
+    Permissions(u32),
+    Platforms(u32),
+    Regex(String),
+    ModAction(ModAction),
+    List(Vec<String>),
+    /// Sealed at rest, see [`secret`]
+    Secret(String),
+}
+
+impl std::fmt::Debug for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::None => f.write_str("None"),
+            Value::String(x) => f.debug_tuple("String").field(x).finish(),
+            Value::Number(x) => f.debug_tuple("Number").field(x).finish(),
+            Value::Bool(x) => f.debug_tuple("Bool").field(x).finish(),
+            Value::Permissions(x) => f.debug_tuple("Permissions").field(x).finish(),
+            Value::Platforms(x) => f.debug_tuple("Platforms").field(x).finish(),
+            Value::Regex(x) => f.debug_tuple("Regex").field(x).finish(),
+            Value::ModAction(x) => f.debug_tuple("ModAction").field(x).finish(),
+            Value::List(x) => f.debug_tuple("List").field(x).finish(),
+            // may be plaintext on its way in from the web UI
+            Value::Secret(_) => f.write_str("Secret(..)"),
+        }
+    }
+}
+
+impl Default for Value {
+    fn default() -> Self {
+        Value::None
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Clone, Copy, Serialize, Deserialize)]
+pub enum ModAction {
+    None,
+    Warn,
+    Remove,
+    Timeout(u32),
+    Kick,
+    Ban,
+    /// Delete their recent messages
+    Purge,
+    /// Lift a timeout
+    Untimeout,
+}
+
+impl Display for ModAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModAction::None => write!(f, "None"),
+            ModAction::Warn => write!(f, "Warn"),
+            ModAction::Remove => write!(f, "Remove"),
+            ModAction::Timeout(t) => write!(f, "Timeout ({}s)", t),
+            ModAction::Kick => write!(f, "Kick"),
+            ModAction::Ban => write!(f, "Ban"),
+            ModAction::Purge => write!(f, "Purge"),
+            ModAction::Untimeout => write!(f, "Untimeout"),
+        }
+    }
+}
+
+/// (cmd type, cmd name, (config key-value pairs))
+pub type CmdDump = (String, String, Vec<(String, Value)>);
+
+/// prefix, desc, hidden (ephemeral), perms, arg
+pub(crate) type ArgDump = (String, String, bool, Permissions, Vec<Arg>);
+
+pub type ArgsDump = Vec<ArgDump>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Arg {
+    pub kind: ArgKind,
+    pub optional: bool,
+    pub name: String,
+    pub desc: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ArgKind {
+    String,
+    Integer {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        min: Option<i64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        max: Option<i64>,
+    },
+    Bool,
+    User,
+    Platform,
+    SubCommandGroup(Vec<Arg>), // Arg should only be of ArgKind::SubCommand
+    SubCommand(Vec<Arg>),
+    Autocomplete,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ArgValue {
+    String(String),
+    Integer(i64),
+    Bool(bool),
+    User(User),
+    Platform(Platform),
+    SubCommand(HashMap<String, ArgValue>),
+}
+
+/// A CommandConfig as it's sent and saved, with every command dumped
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConfigDump {
+    pub filters: Vec<CmdDump>,
+    pub commands: Vec<CmdDump>,
+    pub timers: Vec<CmdDump>,
+}
+
+/// Changes to one of the lists, matched to the current commands by type and name
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ListPatch {
+    /// Replaces the command with the same type and name, or is added to the end of the list
+    #[serde(default)]
+    pub set: Vec<CmdDump>,
+    /// (cmd type, cmd name), ones that don't exist are ignored
+    #[serde(default)]
+    pub remove: Vec<(String, String)>,
+}
+
+/// Only the commands that changed. Merged into the config as it is when the patch is applied,
+/// so editors working on different commands don't undo each other's changes
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ConfigPatch {
+    #[serde(default)]
+    pub filters: ListPatch,
+    #[serde(default)]
+    pub commands: ListPatch,
+    #[serde(default)]
+    pub timers: ListPatch,
+}
+
+/// Enabled commands answering to the same prefix on the same platforms
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrefixConflict {
+    /// Lowercased, without the bang
+    pub prefix: String,
+    pub commands: Vec<String>,
+    /// Platforms more than one of them runs on
+    pub platforms: Platform,
+}
+
+/// A command added, removed or edited by a config change, for the audit log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigChange {
+    /// filters, commands or timers
+    pub list: String,
+    /// cmd type
+    pub kind: String,
+    pub name: String,
+    pub change: ChangeKind,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    /// Keys whose values changed, values are left out since some are secret
+    Edited(Vec<String>),
+}
+
+/// Why an argument's value was rejected. Nested arguments are named `subcommand.arg`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ArgTypeError {
+    /// arg, what it takes
+    WrongType(String, String),
+    /// arg, min
+    TooSmall(String, i64),
+    /// arg, max
+    TooLarge(String, i64),
+    /// Not an argument of the command
+    Unknown(String),
+}
+
+impl Display for ArgTypeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::WrongType(arg, expected) => write!(f, "{} should be {}", arg, expected),
+            Self::TooSmall(arg, min) => write!(f, "{} should be at least {}", arg, min),
+            Self::TooLarge(arg, max) => write!(f, "{} should be at most {}", arg, max),
+            Self::Unknown(arg) => write!(f, "{} isn't an argument", arg),
+        }
+    }
+}
+
+/// A running multiplier on one platform
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Active {
+    pub factor: f64,
+    /// Unix secs
+    pub until: u64,
+    /// Who started it
+    pub by: Arc<String>,
+    /// Bonus points granted so far, only filled in for the dashboard
+    #[serde(default)]
+    pub bonus: u64,
+}
+
+/// Chat over a window, for overlays and the dashboard
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatStatsSnapshot {
+    /// Unix secs, end exclusive
+    pub from: u64,
+    pub to: u64,
+    pub messages: u64,
+    /// Messages in each minute of the window, oldest first
+    pub per_minute: Vec<u64>,
+    pub chatters: u64,
+    /// (name, uses), most used first
+    pub top_emotes: Vec<(String, u64)>,
+}
+
+/// A submission waiting on a mod, it's only saved to the submitter's memes once approved
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingMeme {
+    pub id: u64,
+    pub user_id: Arc<String>,
+    pub user_name: Arc<String>,
+    pub link: String,
+    pub name: String,
+    /// unix timestamp (in seconds)
+    pub submitted: u64,
+    /// How many of the submitter's memes have been rejected so far
+    #[serde(default)]
+    pub rejected: u64,
+}
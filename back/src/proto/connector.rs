@@ -0,0 +1,20 @@
+//! Requests for the stream chat connectors to act on their platform, like `discord::DiscordAction`
+//! is for the Discord bot
+
+use serde_derive::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum TwitchAction {
+    SetTitle(Arc<String>),
+    /// By category name, the connector looks up its id
+    SetGame(Arc<String>),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum YoutubeAction {
+    /// Of the live broadcast
+    SetTitle(Arc<String>),
+    /// The game shown on the broadcast, by name
+    SetGame(Arc<String>),
+}
@@ -0,0 +1,110 @@
+//! Rows and reports read from the database, as they go over the wire
+
+use super::{cmds::ConfigChange, Platform};
+use serde_derive::{Deserialize, Serialize};
+
+/// A saved config change, for the dashboard's history
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigAudit {
+    pub id: i64,
+    /// Web UI user, or where the change came from if it wasn't over a websocket
+    pub author: String,
+    /// Payload the change came in, e.g. PatchConfig
+    pub action: String,
+    pub changes: Vec<ConfigChange>,
+    /// unix timestamp (in seconds)
+    pub created: i64,
+}
+
+/// A user whose points don't add up to what the ledger says they should be
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Discrepancy {
+    pub platform: Platform,
+    pub id: String,
+    /// None if the user only exists in the ledger
+    pub actual: Option<i32>,
+    pub expected: i64,
+    /// Whether their points were set back to the expected amount
+    pub repaired: bool,
+}
+
+pub(crate) type ModActionRow = (Option<String>, String, String, String, u64);
+
+pub(crate) type ModActionDump = Vec<(Platform, Vec<ModActionRow>)>;
+
+/// A moderator's note on a user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModNote {
+    pub id: i64,
+    pub platform: Platform,
+    pub user_id: String,
+    pub user_name: Option<String>,
+    pub note: String,
+    pub author: String,
+    /// unix timestamp (in seconds)
+    pub created: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Redemption {
+    pub id: i64,
+    pub item: String,
+    pub platform: Platform,
+    pub user_id: String,
+    pub user_name: Option<String>,
+    pub cost: i32,
+    pub status: String,
+    /// unix timestamp (in seconds)
+    pub created: i64,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UsageRange {
+    Day,
+    #[default]
+    Week,
+    Month,
+    All,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageUser {
+    pub platform: Platform,
+    pub id: String,
+    pub name: Option<String>,
+    pub count: i64,
+}
+
+/// Command runs over a range, busiest first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageStats {
+    pub range: UsageRange,
+    /// (command name, runs)
+    pub commands: Vec<(String, i64)>,
+    pub users: Vec<UsageUser>,
+    /// (YYYY-MM-DD, runs), oldest first
+    pub days: Vec<(String, i64)>,
+}
+
+/// A user's points on one platform, as imported from or exported to other bots
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserRecord {
+    pub platform: Platform,
+    pub id: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    pub points: i32,
+}
+
+/// What the db recorded over a stream session
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SessionTotals {
+    /// (platform, users seen for the first time)
+    pub new_chatters: Vec<(Platform, i64)>,
+    /// Points given out for chatting and by commands, transfers and gives between users don't count
+    pub points_awarded: i64,
+    /// (filter name, mod actions), most first
+    pub filters: Vec<(String, i64)>,
+    /// (command name, runs), most first
+    pub commands: Vec<(String, i64)>,
+}
@@ -0,0 +1,167 @@
+use serde_derive::{Deserialize, Serialize};
+use std::{fmt, str::FromStr, sync::Arc};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Role {
+    pub user_id: Arc<String>,
+    pub role_id: Arc<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub guild_id: Option<Arc<String>>,
+    pub reason: Option<Arc<String>>,
+}
+
+/// A message of emoji → role choices the discord bot keeps posted
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RoleMenu {
+    /// ReactionRole name, the posted message is reported back under it
+    pub name: Arc<String>,
+    pub channel_id: Arc<String>,
+    /// The message to sync, a new one is posted if None or it's gone
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message_id: Option<Arc<String>>,
+    /// Only set on messages the bot posted itself
+    pub content: Arc<String>,
+    /// (emoji or custom emoji id, role id)
+    pub roles: Vec<(Arc<String>, Arc<String>)>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Reaction {
+    pub channel_id: Arc<String>,
+    pub message_id: Arc<String>,
+    pub user_id: Arc<String>,
+    /// Emoji or custom emoji id
+    pub emoji: Arc<String>,
+}
+
+/// Where a member is in voice, from their voice state
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VoicePresence {
+    /// Voice channel they're in, None once they've left
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel_id: Option<Arc<String>>,
+    /// Whether that's the server's AFK channel
+    #[serde(default)]
+    pub afk: bool,
+}
+
+/// Logical Discord channels, mapped to real channel ids by the discord bot
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChannelHint {
+    Announce,
+    /// Only for mods, so nothing's posted to it until it's routed
+    ModLog,
+    BotSpam,
+}
+
+impl ChannelHint {
+    pub const ALL: [ChannelHint; 3] = [Self::Announce, Self::ModLog, Self::BotSpam];
+
+    /// For commands with an optional channel name in their config
+    #[cfg(feature = "server")]
+    pub(crate) fn from_config(name: &Option<String>) -> Option<Self> {
+        let name = name.as_ref()?;
+        match name.parse() {
+            Ok(hint) => Some(hint),
+            Err(()) => {
+                tracing::warn!(name = name.as_str(), "unknown discord channel");
+                None
+            }
+        }
+    }
+}
+
+impl fmt::Display for ChannelHint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Announce => write!(f, "announce"),
+            Self::ModLog => write!(f, "mod-log"),
+            Self::BotSpam => write!(f, "bot-spam"),
+        }
+    }
+}
+
+impl FromStr for ChannelHint {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|hint| hint.to_string().eq_ignore_ascii_case(s.trim()))
+            .ok_or(())
+    }
+}
+
+/// Where a reply goes relative to the channel the chat came from, replies go in the same
+/// channel (or thread) without one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ThreadReply {
+    /// Out of the thread into its parent channel, unless that's a forum
+    Parent,
+    /// In a thread started for it, for replies long enough to flood the channel
+    NewThread,
+}
+
+impl ThreadReply {
+    pub const ALL: [ThreadReply; 2] = [Self::Parent, Self::NewThread];
+
+    /// For commands with an optional thread setting in their config
+    #[cfg(feature = "server")]
+    pub(crate) fn from_config(name: &Option<String>) -> Option<Self> {
+        let name = name.as_ref()?;
+        match name.parse() {
+            Ok(reply) => Some(reply),
+            Err(()) => {
+                tracing::warn!(name = name.as_str(), "unknown discord thread setting");
+                None
+            }
+        }
+    }
+}
+
+impl fmt::Display for ThreadReply {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Parent => write!(f, "parent"),
+            Self::NewThread => write!(f, "new-thread"),
+        }
+    }
+}
+
+impl FromStr for ThreadReply {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|reply| reply.to_string().eq_ignore_ascii_case(s.trim()))
+            .ok_or(())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum DiscordAction {
+    AddRole(Role),
+    RemoveRole(Role),
+    StreamerId(Arc<String>),
+    /// Point a logical channel at a Discord channel id, or back to the default if None
+    SetChannel(ChannelHint, Option<Arc<String>>),
+    /// Post or update the menu, add its reactions, and give/take roles to match who's reacted.
+    /// A newly posted menu is reported back with RoleMenuPosted
+    SyncRoleMenu(RoleMenu),
+    /// Take a user's reaction off a message
+    RemoveReaction(Reaction),
+    /// Answered with a VoiceSync of everyone in voice
+    SyncVoice,
+}
+
+struct DiscordConfig {
+    enabled: bool,
+    owner_id: String,
+    streamer_id: String,
+    detect_stream: bool,
+    bot_cmds_channel: String,
+    stream_announce_channel: String,
+}
@@ -0,0 +1,1026 @@
+//! Messages between the back server, its connectors and websocket clients, with everything
+//! they carry. Built without the `server` feature too, so clients only need serde for them.
+//! The server's modules re-export these where they're used
+
+pub mod admin;
+pub mod auth;
+pub mod cmds;
+pub mod connector;
+pub mod db;
+pub mod discord;
+mod util;
+pub mod ws;
+
+use auth::Role;
+use bitflags::bitflags;
+use cmds::{ArgValue, ArgsDump, ConfigDump, ModAction};
+use db::ModActionDump;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use std::{collections::HashMap, fmt::Display, net::SocketAddr, str::FromStr, sync::Arc};
+
+bitflags! {
+  pub struct Permissions: u32 {
+    const NONE = 1 << 0;
+    const MEMBER = 1 << 1;
+    const MOD = 1 << 2;
+    const ADMIN = 1 << 3;
+    const OWNER = 1 << 4;
+  }
+
+  pub struct Platform: u32 {
+    //const ALL = 0;
+    const YOUTUBE = 1 << 0;
+    const TWITCH = 1 << 1;
+    const DISCORD = 1 << 2;
+    const WEB = 1 << 3;
+    const STREAM = Self::YOUTUBE.bits | Self::TWITCH.bits;
+    const CHAT = Self::STREAM.bits | Self::DISCORD.bits;
+    // const UI = Self::WEB.bits;
+    const ANNOUNCE = Self::DISCORD.bits | Self::WEB.bits;
+  }
+}
+
+impl Default for Permissions {
+    fn default() -> Self {
+        Self::NONE
+    }
+}
+
+macro_rules! impl_platform_display {
+  ($($name:ident $disp:literal),+) => {
+    impl Display for Platform {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+          match self {
+            $(&Platform::$name => {
+              write!(f, $disp)
+            }),+,
+            _ => write!(f, "{:?}", self)
+          }
+        }
+    }
+  }
+}
+
+#[derive(Debug)]
+pub struct PlatformError {
+    got: String,
+}
+
+impl std::fmt::Display for PlatformError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("invalid platform {:?}", self.got))
+    }
+}
+
+impl std::error::Error for PlatformError {}
+
+macro_rules! impl_platform_fromstr {
+  ($($name:ident $({ $($alt:ident),+ })?),+ $(,)?) => {
+    impl FromStr for Platform {
+      type Err = PlatformError;
+
+      fn from_str(s: &str) -> Result<Self, Self::Err> {
+          match s.as_ref() {
+            $(stringify!($name) $($(| stringify!($alt))+)? => Ok(Platform::$name)),+,
+            _ => Err(PlatformError { got: s.to_owned() })
+          }
+      }
+    }
+  }
+}
+
+pub const PLATFORMS: [&str; 3] = ["Youtube", "Discord", "Twitch"];
+impl_platform_display!(YOUTUBE "Youtube", DISCORD "Discord", TWITCH "Twitch");
+impl_platform_fromstr!(
+    YOUTUBE {
+        y,
+        yt,
+        youtube,
+        Youtube
+    },
+    TWITCH {
+        t,
+        tw,
+        twitch,
+        Twitch
+    },
+    DISCORD {
+        d,
+        disc,
+        discord,
+        Discord
+    },
+    WEB
+);
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct User {
+    // https://serde.rs/feature-flags.html#-features-rc
+    pub id: Arc<String>,
+    pub name: Arc<String>,
+    pub perms: Permissions,
+    /// Matched against the PermMap: Discord role ids, Twitch badges or Youtube flags
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<String>,
+}
+
+/// Optional platform-specific metadata
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum ChatMeta {
+    /// donations
+    Youtube(Arc<String>),
+    /// chan id, chan name
+    Discord1(u64, Arc<String>),
+    /// chan id, chan name, attachments (filename,url), stickers
+    Discord2(
+        u64,
+        Arc<String>,
+        Arc<Vec<(String, String)>>,
+        Arc<Vec<String>>,
+    ),
+    /// attachments (filename,url), stickers
+    Discord3(Arc<Vec<(String, String)>>, Arc<Vec<String>>),
+    /// thread id, thread name, parent chan id, parent is a forum, attachments (filename,url), stickers.
+    /// For chat in threads and forum posts
+    DiscordThread(
+        u64,
+        Arc<String>,
+        u64,
+        bool,
+        Arc<Vec<(String, String)>>,
+        Arc<Vec<String>>,
+    ),
+    /// guild id,
+    Discord4(Arc<String>),
+    /// interaction token, interaction id, ephemeral, is_dm
+    DiscordInteraction(Arc<String>, u64, bool, bool),
+    /// emotes in the message, from twitch/youtube
+    Emotes(Arc<Vec<Emote>>),
+    /// sent privately: the Discord DM channel id, 0 for Twitch whispers (answered by whisper)
+    DirectMessage(u64),
+    // DiscordDM(Arc<Vec<(String, String)>>, Arc<Vec<String>>), // attachments (filename,url), stickers
+}
+
+impl ChatMeta {
+    /// Sent as a DM or whisper, so only commands that allow it run and replies stay private
+    pub fn is_dm(&self) -> bool {
+        matches!(
+            self,
+            ChatMeta::DirectMessage(_) | ChatMeta::DiscordInteraction(_, _, _, true)
+        )
+    }
+}
+
+/// Text of a reply. Always holds real newlines, so multi-line replies serialize as one JSON string
+/// with `\n` escapes instead of a literal backslash-n, whether the text came from a config
+/// template, a websocket peer or pubsub
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MessageText(Arc<String>);
+
+impl MessageText {
+    pub fn new(text: impl Into<String>) -> Self {
+        let text: String = text.into();
+        if text.contains('\r') {
+            Self(Arc::new(text.replace("\r\n", "\n").replace('\r', "\n")))
+        } else {
+            Self(Arc::new(text))
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+
+    pub fn is_multiline(&self) -> bool {
+        self.0.contains('\n')
+    }
+
+    /// For platforms that can't show newlines, blank lines are dropped
+    pub fn single_line(&self, sep: &str) -> String {
+        self.0
+            .lines()
+            .map(str::trim_end)
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>()
+            .join(sep)
+    }
+}
+
+impl std::ops::Deref for MessageText {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl AsRef<str> for MessageText {
+    fn as_ref(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl Display for MessageText {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<String> for MessageText {
+    fn from(text: String) -> Self {
+        Self::new(text)
+    }
+}
+
+impl From<&str> for MessageText {
+    fn from(text: &str) -> Self {
+        Self::new(text)
+    }
+}
+
+impl From<&String> for MessageText {
+    fn from(text: &String) -> Self {
+        Self::new(text.as_str())
+    }
+}
+
+impl From<Arc<String>> for MessageText {
+    fn from(text: Arc<String>) -> Self {
+        if text.contains('\r') {
+            Self::new(text.as_str())
+        } else {
+            Self(text)
+        }
+    }
+}
+
+impl From<MessageText> for Arc<String> {
+    fn from(text: MessageText) -> Self {
+        text.0
+    }
+}
+
+/// Where an emote is in a chat message, in chars (not bytes), end exclusive
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Emote {
+    /// The platform's id for it
+    pub id: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Chat {
+    pub user: Arc<User>,
+    pub msg: Arc<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub meta: Option<ChatMeta>,
+}
+
+pub type ArgMap = HashMap<String, ArgValue>;
+
+#[derive(Debug)]
+pub struct ArgMapError;
+
+impl std::fmt::Display for ArgMapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("invalid argmap")
+    }
+}
+
+impl std::error::Error for ArgMapError {}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum InvocationKind {
+    Invoke,
+    /// Name of the argument being typed in
+    Autocomplete {
+        focused: String,
+    },
+    Reaction {
+        message_id: String,
+        emoji: String,
+    },
+    StreamEvent(StreamEvent),
+    Monetization(Monetization),
+    Alert(Alert),
+    Init,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Invocation {
+    pub user: Arc<User>,
+    pub cmd: Arc<String>,
+    pub args: ArgMap,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub meta: Option<ChatMeta>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    /// None implies InvocationKind::Invoke
+    pub kind: Option<InvocationKind>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Ping {
+    /// Set when the sender wants a PingDelivered or PingFailed back
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<Arc<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pinger: Option<(Platform, Arc<User>)>,
+    pub pingee: Arc<User>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub msg: Option<Arc<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub meta: Option<ChatMeta>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum StreamSignal {
+    Start(Arc<String>),
+    Stop(Arc<String>),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum StreamEvent {
+    /// A platform has detected a stream start
+    DetectStart(Arc<String>),
+    /// A chat platform has started following a stream
+    Started(Arc<String>, Arc<String>),
+    /// A platform has detected a stream stop
+    DetectStop(Arc<String>),
+    /// A chat platform has stopped following a stream
+    Stopped(Arc<String>),
+    /// Periodic snapshot of the live stream, started_at is a unix timestamp (in seconds)
+    Metadata {
+        title: Arc<String>,
+        started_at: u64,
+        viewer_count: u64,
+    },
+    /// Another channel raided/hosted this one
+    Raid { from: Arc<User>, viewers: u64 },
+}
+
+/// Paid chat events, sent by the platform they happened on
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum Monetization {
+    /// Twitch sub. tier (0 for Prime), cumulative months, gifted
+    Subscription { tier: u8, months: u32, gifted: bool },
+    /// Twitch bits cheered
+    Bits(u32),
+    /// Youtube membership. level name, cumulative months
+    Membership { level: Arc<String>, months: u32 },
+    /// Youtube superchat. amount in micros, currency code, display string
+    Superchat {
+        amount_micros: u64,
+        currency: Arc<String>,
+        display: Arc<String>,
+    },
+}
+
+/// Events for the alert overlay, sent by the platform they happened on
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum Alert {
+    Follow,
+    /// Tier (0 for Prime), cumulative months, gifted
+    Subscription {
+        tier: u8,
+        months: u32,
+        gifted: bool,
+    },
+    /// Youtube membership. level name, cumulative months
+    Membership {
+        level: Arc<String>,
+        months: u32,
+    },
+    /// Viewers brought along
+    Raid {
+        viewers: u64,
+    },
+    /// Amount in micros, currency code, display string
+    Donation {
+        amount_micros: u64,
+        currency: Arc<String>,
+        display: Arc<String>,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub enum Service {
+    Cache,
+    /// A command's background task, by command type and name
+    Task(String, String),
+    /// Up while this instance, by INSTANCE_ID, runs the background tasks
+    Leader(String),
+    /// Up once everything chat needs is, see admin::Check
+    Ready,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum HealthStatus {
+    Up,
+    Down,
+    /// Back up after stopping, with the number of restarts so far
+    Restarted(u32),
+}
+
+/// A chat or invocation replayed as another user, for debugging
+#[derive(Debug, Serialize, Deserialize)]
+pub enum InvokeAs {
+    Chat(Platform, Chat),
+    Invoke(Platform, Invocation),
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub enum ExportFormat {
+    /// Newline-delimited JSON
+    #[default]
+    Json,
+    /// With a header row at the start of each platform
+    Csv,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Autocomplete {
+    /// key-value autocomplete choices
+    pub choices: Vec<(String, String)>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub meta: Option<ChatMeta>,
+}
+
+// TODO: split into recv and resp
+#[derive(Debug, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum Payload {
+    // recv
+    //#[serde(skip_serializing)]
+    Chat(Chat),
+    InvokeCommand(Invocation),
+    // #[serde(skip_serializing)]
+    StreamEvent(StreamEvent),
+    /// user, event
+    Monetization(Arc<User>, Monetization),
+    /// user, event. Queued for the overlay by Alerts commands
+    Alert(Arc<User>, Alert),
+    // TODO: not right
+    Ping(Ping),
+    /// From a connector, the Ping with this id went out
+    PingDelivered(Arc<String>),
+    /// From a connector, the Ping with this id couldn't be delivered and won't be retried
+    PingFailed {
+        id: Arc<String>,
+        reason: Arc<String>,
+    },
+    // #[serde(skip_serializing)]
+    // SetConfig(Vec<cmds::OwnedCmdDump>),
+    // #[serde(skip_serializing)]
+    DumpConfig,
+    /// Only the commands that changed, answered like a ConfigDump
+    PatchConfig(cmds::ConfigPatch),
+    // #[serde(skip_serializing)]
+    DumpSchema,
+    /// Schema version the client already has. Answered with NotModified if it's current
+    DumpSchemaIf(Arc<String>),
+    /// Answered with the schema as standard JSON Schema
+    DumpJsonSchema,
+    // #[serde(skip_serializing)]
+    DumpLog {
+        platform: Platform,
+        /// Number of the latest messages to skip
+        #[serde(default)]
+        offset: usize,
+        /// Max number of messages per platform, everything if unset
+        #[serde(default)]
+        limit: Option<usize>,
+    },
+    /// Websocket only, streamed back as LogExport chunks
+    ExportLog(Platform),
+    DumpModActions,
+    /// Answered with UsageDump
+    DumpUsage {
+        #[serde(default)]
+        range: db::UsageRange,
+    },
+    DumpArgs(Platform),
+    /// Websocket only, responses are sent back to the invoker
+    InvokeAs(InvokeAs),
+    /// WordlistFilter name
+    DumpWordlist(Arc<String>),
+    /// Websocket only, answered with the updated WordlistDump
+    EditWordlist {
+        name: Arc<String>,
+        /// (phrase, tier)
+        #[serde(default)]
+        add: Vec<(String, u8)>,
+        #[serde(default)]
+        remove: Vec<String>,
+    },
+    DumpRedemptions,
+    /// Websocket only, answered with the updated RedemptionQueue
+    ResolveRedemption {
+        id: i64,
+        /// Give the points back instead of marking it completed
+        #[serde(default)]
+        refund: bool,
+    },
+    /// Websocket only, answered with ImportProgress after every batch
+    ImportUsers {
+        users: Vec<db::UserRecord>,
+        /// Add to existing points instead of replacing them
+        #[serde(default)]
+        add: bool,
+    },
+    /// Websocket only, streamed back as UserExport chunks
+    ExportUsers {
+        platform: Platform,
+        #[serde(default)]
+        format: ExportFormat,
+    },
+    /// Websocket only, written to BACKUP_DIR if it's set and `stream` isn't, streamed back as
+    /// BackupChunks otherwise. Answered with BackupDone either way
+    Backup {
+        #[serde(default)]
+        stream: bool,
+    },
+    /// Websocket only, answered with Restored
+    Restore(admin::RestoreSource),
+    /// Websocket only, answered with Sessions
+    ListSessions,
+    /// Websocket only, kicks any peers logged in with the session and answers with the updated Sessions
+    RevokeSession(Arc<String>),
+    /// Websocket only, answered with Connections
+    ListConnections,
+    /// Websocket only, answered with LogLevels
+    GetLogLevels,
+    /// Websocket only, changes how verbose a target's logs are and answers with LogLevels
+    SetLogLevel {
+        /// e.g. back::cmds::filter, the default for every target if None
+        #[serde(default)]
+        target: Option<String>,
+        /// off, error, warn, info, debug or trace. None puts the target back on the default
+        #[serde(default)]
+        level: Option<String>,
+        /// Change it back after this long (in seconds), for good if None
+        #[serde(default)]
+        for_secs: Option<u64>,
+    },
+    /// Websocket only, answered with Profiles
+    DumpProfiles,
+    /// Websocket only, adds or replaces a profile and answers with Profiles
+    SaveProfile(admin::Profile),
+    /// Websocket only, answered with Profiles
+    DeleteProfile(String),
+    /// Websocket only, switches on or off every command with the tag, answered like a ConfigDump
+    SetTagEnabled {
+        tag: String,
+        enabled: bool,
+    },
+    /// Websocket only, sets the tags as the named profile has them, answered like a ConfigDump
+    SetProfile(String),
+    DumpServiceAccounts,
+    /// Websocket only, replaces the list and answers with the saved ServiceAccounts
+    SetServiceAccounts(Vec<admin::ServiceAccount>),
+    DumpPermMap,
+    /// Websocket only, replaces the rules and answers with the saved PermMap
+    SetPermMap(Vec<admin::PermRule>),
+    /// Answered with Flags
+    DumpFlags,
+    /// Websocket only, sets the feature flag or removes it if None, answered with Flags
+    SetFlag {
+        name: String,
+        flag: Option<admin::Flag>,
+    },
+    /// Websocket only, answered with Locks
+    ListLocks,
+    /// Websocket only, frees the lock whoever has it and answers with Locks
+    ForceUnlock(String),
+    DumpCurrency,
+    /// Answered with HealthDump
+    DumpHealth,
+    /// Websocket only, replaces the setting and answers with the saved Currency
+    SetCurrency(admin::Currency),
+    /// Websocket only, answered with DeadLetters
+    DumpDeadLetters,
+    /// Websocket only, sends every dead letter again and answers with what's left
+    ReplayDeadLetters,
+    DumpMemeQueue,
+    /// Websocket only, answered with ConfigAudit, up to this many of the latest changes
+    DumpConfigAudit(u32),
+    /// Websocket only, answered with ChatStats for messages sent between the two (unix secs)
+    DumpChatStats {
+        from: u64,
+        to: u64,
+    },
+    /// Websocket only, limits what the peer is sent to broadcasts matching it
+    Subscribe(ws::Subscription),
+    /// Websocket only, approves or rejects a queued meme and answers with the updated MemeQueue
+    ModerateMeme {
+        id: u64,
+        approve: bool,
+    },
+    /// Websocket only, answered with PointsAudit
+    AuditPoints {
+        /// Set points back to what the ledger says they should be
+        #[serde(default)]
+        repair: bool,
+    },
+    /// Websocket only, multiplies the points chatters earn on the platforms for `secs`,
+    /// answered with Multipliers
+    StartMultiplier {
+        platforms: Platform,
+        factor: f64,
+        secs: u64,
+    },
+    /// Websocket only, ends the multipliers on the platforms and answers with Multipliers
+    StopMultiplier(Platform),
+    /// Websocket only, answered with Multipliers
+    DumpMultipliers,
+    //------------------------------
+    // send
+    // #[serde(skip_deserializing)]
+    ConfigSaved,
+    /// Running points multipliers, with the bonus points each has granted so far
+    Multipliers(Vec<(Platform, cmds::Active)>),
+    /// Who changed the config and what they changed, latest first
+    ConfigAudit(Vec<db::ConfigAudit>),
+    // #[serde(skip_deserializing)]
+    ConfigChanged,
+    /// Answers a ConfigDump or PatchConfig that wasn't saved because commands share prefixes
+    ConfigRejected(Vec<cmds::PrefixConflict>),
+    /// Answers a config change that wasn't made because another one held the config for too long,
+    /// or the lock couldn't be reached
+    ConfigBusy,
+    /// Answers a PatchConfig that wasn't applied because it sets commands of types that don't exist,
+    /// (cmd type, cmd name)
+    UnknownCommands(Vec<(String, String)>),
+    // #[serde(skip_deserializing)]
+    /// user, action, reason
+    ModAction(Arc<User>, ModAction, Arc<String>),
+    // #[serde(skip_deserializing)]
+    StreamSignal(StreamSignal),
+    /// url, message, where to announce it on Discord
+    StreamAnnouncement(Arc<String>, Arc<String>, discord::ChannelHint),
+    // #[serde(skip_deserializing)]
+    /// Aussiebot's replies to users
+    Message {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        user: Option<(Platform, Arc<User>)>,
+        msg: MessageText,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        meta: Option<ChatMeta>,
+        /// Discord channel to send to instead of replying where the chat came from
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        hint: Option<discord::ChannelHint>,
+        /// Where to reply on Discord relative to the chat's channel, if not in it
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        thread: Option<discord::ThreadReply>,
+    },
+    /// Answer to an Invocation whose args don't fit the command's args schema, nothing is run
+    InvalidInvocation {
+        /// Required args that weren't given
+        missing: Vec<String>,
+        type_errors: Vec<cmds::ArgTypeError>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        meta: Option<ChatMeta>,
+    },
+    // #[serde(skip_deserializing)]
+    Autocorrect(Arc<User>, Vec<String>),
+    SchemaDump {
+        version: Arc<String>,
+        schema: Arc<RawValue>,
+    },
+    NotModified,
+    JsonSchemaDump(serde_json::Value),
+    // #[serde(skip_deserializing)]
+    LogDump(Vec<(Platform, Vec<String>)>),
+    /// Newline-delimited JSON, oldest first. `done` is set on the last chunk of each platform
+    LogExport {
+        platform: Platform,
+        chunk: Arc<String>,
+        done: bool,
+    },
+    /// (normalized phrase, tier)
+    WordlistDump {
+        name: Arc<String>,
+        words: Vec<(String, u8)>,
+    },
+    /// Pending shop redemptions, oldest first
+    RedemptionQueue(Vec<db::Redemption>),
+    ImportProgress {
+        imported: u64,
+        total: usize,
+        /// Users on platforms that can't hold points
+        skipped: usize,
+        done: bool,
+        /// Set if a batch failed, nothing after it is imported
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<Arc<String>>,
+    },
+    /// Ordered by id. `done` is set on the last chunk of each platform
+    UserExport {
+        platform: Platform,
+        chunk: Arc<String>,
+        done: bool,
+    },
+    /// A piece of a streamed backup archive, base64. `done` is set on the last
+    BackupChunk {
+        chunk: Arc<String>,
+        done: bool,
+    },
+    BackupDone {
+        /// The archive's name in BACKUP_DIR, None if it was streamed
+        #[serde(skip_serializing_if = "Option::is_none")]
+        file: Option<String>,
+        /// Size of the archive
+        bytes: usize,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<Arc<String>>,
+    },
+    /// Nothing is applied if the archive's rejected, otherwise `error` is where restoring stopped
+    Restored {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        report: Option<admin::RestoreReport>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<Arc<String>>,
+    },
+    /// Web UI logins, oldest first
+    Sessions(Vec<auth::Session>),
+    /// Websocket peers connected right now, oldest first
+    Connections(Vec<ws::Connection>),
+    /// Which startup checks have passed
+    HealthDump(admin::Report),
+    /// To a websocket peer that sent too much, everything it sends is dropped for a while
+    RateLimited {
+        /// Payload variant that went over the limit
+        kind: Arc<str>,
+        secs: u64,
+    },
+    LogLevels(admin::LogLevels),
+    /// Top commands and users, and commands per day
+    UsageDump(db::UsageStats),
+    /// Users whose points don't match the ledger
+    PointsAudit(Vec<db::Discrepancy>),
+    /// A user with mod notes on them chatted
+    WatchlistAlert {
+        platform: Platform,
+        user: Arc<User>,
+        msg: Arc<String>,
+        notes: Vec<db::ModNote>,
+    },
+    /// Who a banwave would ban, before it's confirmed
+    BanwavePreview {
+        platform: Platform,
+        pattern: String,
+        users: Vec<Arc<User>>,
+        /// Who asked for it
+        by: Arc<String>,
+    },
+    /// Memes waiting on a mod, oldest first
+    MemeQueue(Vec<cmds::PendingMeme>),
+    /// Saved tag states, by name
+    Profiles(Vec<admin::Profile>),
+    /// Accounts of other bots, whose chat is left alone
+    ServiceAccounts(Vec<admin::ServiceAccount>),
+    /// Levels granted by platform roles
+    PermMap(Vec<admin::PermRule>),
+    /// Feature flags by name
+    Flags(Vec<(String, admin::Flag)>),
+    /// Locks and leases held right now, by key
+    Locks(Vec<admin::HeldLock>),
+    /// What points are called and how amounts are written
+    Currency(admin::Currency),
+    /// Responses that couldn't be delivered, oldest first
+    DeadLetters(Vec<admin::DeadLetter>),
+    /// Notes listed by a mod
+    ModNotes {
+        platform: Platform,
+        notes: Vec<db::ModNote>,
+    },
+    /// Sent when a stream stops, for the mod channel and the dashboard to keep
+    SessionSummary(admin::SessionSummary),
+    /// Votes so far on a poll, sent on every vote for overlays
+    PollUpdate {
+        name: String,
+        question: String,
+        options: Vec<String>,
+        /// Same order as the options
+        votes: Vec<u64>,
+        closed: bool,
+    },
+    /// The alert to show on the overlay now, the next one comes once it's been up for duration
+    AlertDisplay {
+        name: String,
+        user: String,
+        alert: Alert,
+        text: String,
+        /// In seconds
+        duration: u64,
+    },
+    /// Chat velocity, chatters and top emotes over a window, sent every so often for overlays
+    ChatStats(cmds::ChatStatsSnapshot),
+    /// Everyone waiting in a Queue in the order they're up, sent on every change for overlays
+    ViewerQueue {
+        name: String,
+        /// (platform, name)
+        entrants: Vec<(Platform, String)>,
+        max_size: u64,
+    },
+    /// Stakes so far on a prediction, sent on every bet for overlays
+    PredictionUpdate {
+        name: String,
+        question: String,
+        outcomes: Vec<String>,
+        /// Points staked, same order as the outcomes
+        points: Vec<i64>,
+        /// Same order as the outcomes
+        bettors: Vec<u64>,
+        /// Still taking bets
+        betting: bool,
+        /// Resolved or cancelled
+        ended: bool,
+        /// Index of the outcome that won, if it was resolved
+        winner: Option<usize>,
+    },
+    //------------------------------
+    // both
+    // #[serde(skip_deserializing)]
+    /// Secret fields are redacted, unless the web UI user is one of CONFIG_OWNERS
+    ConfigDump(ConfigDump),
+    ModActionsDump(ModActionDump),
+    ArgsDump(ArgsDump),
+    Autocomplete(Autocomplete),
+    /// Discord-specific functionality
+    Discord(discord::DiscordAction),
+    /// For the Twitch connector
+    Twitch(connector::TwitchAction),
+    /// For the YouTube connector
+    Youtube(connector::YoutubeAction),
+    /// Discord only, ReactionRole name, id of the role menu message the bot posted
+    RoleMenuPosted {
+        name: Arc<String>,
+        message_id: Arc<String>,
+    },
+    /// Discord only, a member joined, moved between or left voice channels
+    VoicePresence(Arc<User>, discord::VoicePresence),
+    /// Discord only, everyone in a voice channel when the bot (re)connects.
+    /// Anyone tracked as being in voice who isn't listed left while it was away
+    VoiceSync(Vec<(Arc<User>, discord::VoicePresence)>),
+    /// Sent when a platform has started and is ready
+    NotifyStart,
+    /// Sent when a backing service goes up or down
+    Health(Service, HealthStatus),
+}
+
+impl Payload {
+    /// The variant name, as it's serialized
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Payload::Chat(..) => "Chat",
+            Payload::InvokeCommand(..) => "InvokeCommand",
+            Payload::StreamEvent(..) => "StreamEvent",
+            Payload::Monetization(..) => "Monetization",
+            Payload::Alert(..) => "Alert",
+            Payload::Ping(..) => "Ping",
+            Payload::PingDelivered(..) => "PingDelivered",
+            Payload::PingFailed { .. } => "PingFailed",
+            Payload::DumpConfig => "DumpConfig",
+            Payload::PatchConfig(..) => "PatchConfig",
+            Payload::DumpSchema => "DumpSchema",
+            Payload::DumpSchemaIf(..) => "DumpSchemaIf",
+            Payload::DumpJsonSchema => "DumpJsonSchema",
+            Payload::DumpLog { .. } => "DumpLog",
+            Payload::ExportLog(..) => "ExportLog",
+            Payload::DumpModActions => "DumpModActions",
+            Payload::DumpUsage { .. } => "DumpUsage",
+            Payload::DumpArgs(..) => "DumpArgs",
+            Payload::InvokeAs(..) => "InvokeAs",
+            Payload::DumpWordlist(..) => "DumpWordlist",
+            Payload::EditWordlist { .. } => "EditWordlist",
+            Payload::DumpRedemptions => "DumpRedemptions",
+            Payload::ResolveRedemption { .. } => "ResolveRedemption",
+            Payload::ImportUsers { .. } => "ImportUsers",
+            Payload::ExportUsers { .. } => "ExportUsers",
+            Payload::Backup { .. } => "Backup",
+            Payload::Restore(..) => "Restore",
+            Payload::ListSessions => "ListSessions",
+            Payload::RevokeSession(..) => "RevokeSession",
+            Payload::ListConnections => "ListConnections",
+            Payload::GetLogLevels => "GetLogLevels",
+            Payload::SetLogLevel { .. } => "SetLogLevel",
+            Payload::DumpProfiles => "DumpProfiles",
+            Payload::SaveProfile(..) => "SaveProfile",
+            Payload::DeleteProfile(..) => "DeleteProfile",
+            Payload::SetTagEnabled { .. } => "SetTagEnabled",
+            Payload::SetProfile(..) => "SetProfile",
+            Payload::DumpServiceAccounts => "DumpServiceAccounts",
+            Payload::SetServiceAccounts(..) => "SetServiceAccounts",
+            Payload::DumpPermMap => "DumpPermMap",
+            Payload::SetPermMap(..) => "SetPermMap",
+            Payload::DumpFlags => "DumpFlags",
+            Payload::SetFlag { .. } => "SetFlag",
+            Payload::ListLocks => "ListLocks",
+            Payload::ForceUnlock(..) => "ForceUnlock",
+            Payload::DumpCurrency => "DumpCurrency",
+            Payload::DumpHealth => "DumpHealth",
+            Payload::SetCurrency(..) => "SetCurrency",
+            Payload::DumpDeadLetters => "DumpDeadLetters",
+            Payload::ReplayDeadLetters => "ReplayDeadLetters",
+            Payload::DumpMemeQueue => "DumpMemeQueue",
+            Payload::DumpConfigAudit(..) => "DumpConfigAudit",
+            Payload::DumpChatStats { .. } => "DumpChatStats",
+            Payload::Subscribe(..) => "Subscribe",
+            Payload::ModerateMeme { .. } => "ModerateMeme",
+            Payload::AuditPoints { .. } => "AuditPoints",
+            Payload::StartMultiplier { .. } => "StartMultiplier",
+            Payload::StopMultiplier(..) => "StopMultiplier",
+            Payload::DumpMultipliers => "DumpMultipliers",
+            Payload::ConfigSaved => "ConfigSaved",
+            Payload::Multipliers(..) => "Multipliers",
+            Payload::ConfigAudit(..) => "ConfigAudit",
+            Payload::ConfigChanged => "ConfigChanged",
+            Payload::ConfigRejected(..) => "ConfigRejected",
+            Payload::ConfigBusy => "ConfigBusy",
+            Payload::UnknownCommands(..) => "UnknownCommands",
+            Payload::ModAction(..) => "ModAction",
+            Payload::StreamSignal(..) => "StreamSignal",
+            Payload::StreamAnnouncement(..) => "StreamAnnouncement",
+            Payload::Message { .. } => "Message",
+            Payload::InvalidInvocation { .. } => "InvalidInvocation",
+            Payload::Autocorrect(..) => "Autocorrect",
+            Payload::SchemaDump { .. } => "SchemaDump",
+            Payload::NotModified => "NotModified",
+            Payload::JsonSchemaDump(..) => "JsonSchemaDump",
+            Payload::LogDump(..) => "LogDump",
+            Payload::LogExport { .. } => "LogExport",
+            Payload::WordlistDump { .. } => "WordlistDump",
+            Payload::RedemptionQueue(..) => "RedemptionQueue",
+            Payload::ImportProgress { .. } => "ImportProgress",
+            Payload::UserExport { .. } => "UserExport",
+            Payload::BackupChunk { .. } => "BackupChunk",
+            Payload::BackupDone { .. } => "BackupDone",
+            Payload::Restored { .. } => "Restored",
+            Payload::Sessions(..) => "Sessions",
+            Payload::Connections(..) => "Connections",
+            Payload::HealthDump(..) => "HealthDump",
+            Payload::RateLimited { .. } => "RateLimited",
+            Payload::LogLevels(..) => "LogLevels",
+            Payload::UsageDump(..) => "UsageDump",
+            Payload::PointsAudit(..) => "PointsAudit",
+            Payload::WatchlistAlert { .. } => "WatchlistAlert",
+            Payload::BanwavePreview { .. } => "BanwavePreview",
+            Payload::MemeQueue(..) => "MemeQueue",
+            Payload::Profiles(..) => "Profiles",
+            Payload::ServiceAccounts(..) => "ServiceAccounts",
+            Payload::PermMap(..) => "PermMap",
+            Payload::Flags(..) => "Flags",
+            Payload::Locks(..) => "Locks",
+            Payload::Currency(..) => "Currency",
+            Payload::DeadLetters(..) => "DeadLetters",
+            Payload::ModNotes { .. } => "ModNotes",
+            Payload::SessionSummary(..) => "SessionSummary",
+            Payload::PollUpdate { .. } => "PollUpdate",
+            Payload::AlertDisplay { .. } => "AlertDisplay",
+            Payload::ChatStats(..) => "ChatStats",
+            Payload::ViewerQueue { .. } => "ViewerQueue",
+            Payload::PredictionUpdate { .. } => "PredictionUpdate",
+            Payload::ConfigDump(..) => "ConfigDump",
+            Payload::ModActionsDump(..) => "ModActionsDump",
+            Payload::ArgsDump(..) => "ArgsDump",
+            Payload::Autocomplete(..) => "Autocomplete",
+            Payload::Discord(..) => "Discord",
+            Payload::Twitch(..) => "Twitch",
+            Payload::Youtube(..) => "Youtube",
+            Payload::RoleMenuPosted { .. } => "RoleMenuPosted",
+            Payload::VoicePresence(..) => "VoicePresence",
+            Payload::VoiceSync(..) => "VoiceSync",
+            Payload::NotifyStart => "NotifyStart",
+            Payload::Health(..) => "Health",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Message {
+    pub platform: Platform,
+    pub channel: String,
+    pub payload: Payload,
+    /// Ties together the logs of everything a message sets off, across services.
+    /// Assigned on receipt if the sender didn't set one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub corr_id: Option<Arc<String>>,
+    /// Set by senders that may resend on reconnect, repeats within DEDUPE_TTL are dropped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dedupe_id: Option<Arc<String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Location {
+    Pubsub,
+    /// Username, addr, and the role of the session it's logged in with
+    Websocket(Arc<String>, SocketAddr, Role),
+    Websockets(Option<Vec<(Arc<String>, SocketAddr)>>),
+    Broadcast,
+}
+
+pub fn new_corr_id() -> Arc<String> {
+    Arc::new(format!("{:016x}", rand::random::<u64>()))
+}
@@ -0,0 +1,108 @@
+use serde::{ser::Serialize, Deserialize, Deserializer, Serializer};
+
+macro_rules! impl_serde_bitflags {
+    ($($name:ident),+$(,)?) => {
+      $(
+        impl Serialize for $name {
+          fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+          where
+              S: Serializer,
+          {
+              serializer.serialize_u32(self.bits())
+          }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: Deserializer<'de>,
+            {
+                let bits = u32::deserialize(deserializer)?;
+                $name::from_bits(bits)
+                    .ok_or(format!(concat!("Unable to deserialise ",stringify!($name),": invalid bit flags {:?}"), bits))
+                    .map_err(serde::de::Error::custom)
+            }
+        }
+      )+
+    };
+}
+
+use super::MessageText;
+use super::Permissions;
+use super::Platform;
+
+impl_serde_bitflags!(Platform, Permissions);
+
+impl Serialize for MessageText {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+/// Takes a string, or a list of lines to be joined
+impl<'de> Deserialize<'de> for MessageText {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(serde_derive::Deserialize)]
+        #[serde(untagged)]
+        enum Text {
+            Str(String),
+            Lines(Vec<String>),
+        }
+
+        Ok(match Text::deserialize(deserializer)? {
+            Text::Str(text) => MessageText::new(text),
+            Text::Lines(lines) => MessageText::new(lines.join("\n")),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MessageText;
+
+    fn round_trip(text: &MessageText) -> MessageText {
+        let json = serde_json::to_string(text).unwrap();
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn multi_line_round_trips() {
+        let text = MessageText::new("Leaderboard:\n1. a\n2. b");
+        assert_eq!(
+            serde_json::to_string(&text).unwrap(),
+            r#""Leaderboard:\n1. a\n2. b""#
+        );
+        assert_eq!(round_trip(&text), text);
+    }
+
+    #[test]
+    fn backslash_n_is_kept() {
+        let text = MessageText::new(r"C:\new\notes");
+        assert_eq!(round_trip(&text).as_str(), r"C:\new\notes");
+    }
+
+    #[test]
+    fn carriage_returns_become_newlines() {
+        let text: MessageText = serde_json::from_str(r#""a\r\nb\rc""#).unwrap();
+        assert_eq!(text.as_str(), "a\nb\nc");
+        assert_eq!(round_trip(&text), text);
+    }
+
+    #[test]
+    fn lines_are_joined() {
+        let text: MessageText = serde_json::from_str(r#"["a", "b"]"#).unwrap();
+        assert_eq!(text.as_str(), "a\nb");
+    }
+
+    #[test]
+    fn single_line_drops_blank_lines() {
+        let text = MessageText::new("a  \n\nb\n");
+        assert_eq!(text.single_line(" | "), "a | b");
+    }
+}
@@ -0,0 +1,34 @@
+//! Websocket peers and the framing around messages
+
+use super::{auth::Role, Platform};
+use serde_derive::{Deserialize, Serialize};
+use std::{net::SocketAddr, sync::Arc};
+
+/// A connected peer, as listed to admins
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Connection {
+    pub peer: SocketAddr,
+    pub username: Arc<String>,
+    pub role: Role,
+    /// Unix timestamp
+    pub connected_at: u64,
+    /// Seconds since the peer last sent anything, heartbeat or otherwise
+    pub idle: u64,
+}
+
+/// Broadcasts a peer wants, e.g. an overlay that only needs chat and poll updates.
+/// Replies to the peer's own requests are always sent
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Subscription {
+    /// Payload variant names, everything if empty
+    #[serde(default)]
+    pub kinds: Vec<String>,
+    /// Everything if None
+    #[serde(default)]
+    pub platforms: Option<Platform>,
+}
+
+/// Sent by peers to keep the connection alive, answered with HEARTBEAT_PONG
+pub const HEARTBEAT_PING: &str = "💓";
+
+pub const HEARTBEAT_PONG: &str = "👀";
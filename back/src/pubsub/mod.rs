@@ -1,22 +1,31 @@
 use crate::{
     error::{self, Error},
-    msg::Location,
+    health::{self, Link},
+    msg::{Incoming, Location},
     RedisPool,
 };
 use bb8_redis::redis::AsyncCommands;
 use futures_util::StreamExt;
 use std::sync::Arc;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, oneshot};
 
-pub type Msg = Arc<String>;
+pub mod sign;
+pub mod stream;
+
+/// The message, and where to report whether it was published
+pub type Msg = (Arc<str>, Option<oneshot::Sender<error::Result<()>>>);
 
 // TODO: generalise (Location, String)
 pub struct Server {
-    msg_in_tx: mpsc::Sender<(Location, String)>, // <- subbo
-    msg_out_rx: mpsc::Receiver<Msg>,             // -> pubbo
+    msg_in_tx: mpsc::Sender<(Location, Incoming)>, // <- subbo
+    msg_out_rx: mpsc::Receiver<Msg>,               // -> pubbo
     pool: RedisPool,
     pub_chan: &'static str,
     sub_chan: &'static str,
+    /// Read sub_chan as a stream with this group, instead of subscribing to it
+    read_group: Option<stream::Group>,
+    /// Add to pub_chan as a stream capped at this length, instead of publishing to it
+    write_max_len: Option<usize>,
 }
 
 #[derive(Debug)]
@@ -31,7 +40,7 @@ impl std::fmt::Display for EOF {
 impl Server {
     pub fn new(
         pool: RedisPool,
-        msg_in_tx: mpsc::Sender<(Location, String)>,
+        msg_in_tx: mpsc::Sender<(Location, Incoming)>,
         msg_out_rx: mpsc::Receiver<Msg>,
         pub_chan: &'static str,
         sub_chan: &'static str,
@@ -42,23 +51,39 @@ impl Server {
             msg_out_rx,
             pub_chan,
             sub_chan,
+            read_group: None,
+            write_max_len: None,
         }
     }
 
+    /// Read sub_chan as a redis stream, see [`stream`]
+    pub fn read_stream(mut self, group: stream::Group) -> Self {
+        self.read_group = Some(group);
+        self
+    }
+
+    /// Add to pub_chan as a redis stream, see [`stream`]
+    pub fn write_stream(mut self, max_len: usize) -> Self {
+        self.write_max_len = Some(max_len);
+        self
+    }
+
     async fn sub_task(
         pool: RedisPool,
-        msg_in_tx: mpsc::Sender<(Location, String)>,
+        msg_in_tx: mpsc::Sender<(Location, Incoming)>,
         sub_chan: &str,
     ) -> error::Result<()> {
         let client = pool.dedicated_connection().await?;
         let mut sub = client.into_pubsub();
         sub.subscribe(sub_chan).await?;
+        health::connected(Link::Pubsub, true);
         let mut sub = sub.into_on_message();
         loop {
             // get pubsub message
             let msg = sub.next().await.ok_or(EOF)?.get_payload::<String>()?;
+            health::message(Link::Pubsub);
             // wrap with location
-            let msg = (Location::Pubsub, msg);
+            let msg = (Location::Pubsub, Incoming::Raw(msg));
             // forward to msg task
             msg_in_tx.send(msg).await?;
         }
@@ -68,18 +93,37 @@ impl Server {
         pool: RedisPool,
         mut msg_out_rx: mpsc::Receiver<Msg>,
         pub_chan: &'static str,
+        write_max_len: Option<usize>,
     ) {
-        while let Some(msg) = msg_out_rx.recv().await {
+        while let Some((msg, ack)) = msg_out_rx.recv().await {
+            let msg = sign::sign(pub_chan, &msg).map(Arc::from).unwrap_or(msg);
             let redis = pool.clone();
             // spawn a task to publish
             tokio::spawn(async move {
-                redis
-                    .get()
-                    .await
-                    .unwrap()
-                    .publish::<&str, &str, bool>(pub_chan, &msg)
-                    .await
-                    .unwrap()
+                let res = match write_max_len {
+                    Some(max_len) => stream::add(&redis, pub_chan, max_len, &msg).await,
+                    None => {
+                        async {
+                            redis
+                                .get()
+                                .await?
+                                .publish::<&str, &str, bool>(pub_chan, &msg)
+                                .await?;
+                            Ok(())
+                        }
+                        .await
+                    }
+                };
+                match ack {
+                    Some(ack) => {
+                        let _ = ack.send(res);
+                    }
+                    None => {
+                        if let Err(e) = res {
+                            tracing::error!("publish failed: {}", e);
+                        }
+                    }
+                }
             });
         }
     }
@@ -97,18 +141,29 @@ impl Server {
             pool,
             pub_chan,
             sub_chan,
+            read_group,
+            write_max_len,
         } = self;
 
         // Spawn sub task in a loop (conn closes during inactivity)
+        health::track(Link::Pubsub);
         let _pool = pool.clone();
         tokio::spawn(async move {
             //for _ in 0.. {
             loop {
-                match Self::sub_task(_pool.clone(), msg_in_tx.clone(), sub_chan).await {
+                let res = match read_group {
+                    Some(ref group) => {
+                        stream::read_task(_pool.clone(), msg_in_tx.clone(), sub_chan, group).await
+                    }
+                    None => Self::sub_task(_pool.clone(), msg_in_tx.clone(), sub_chan).await,
+                };
+                match res {
+                    // resubscribed right away
                     Err(Error::PubSubEOF(e)) => {
                         tracing::trace!("{}", e);
                     }
                     Err(e) => {
+                        health::connected(Link::Pubsub, false);
                         tracing::error!("{}", e);
                     }
                     Ok(_) => {}
@@ -117,8 +172,19 @@ impl Server {
         });
 
         // Spawn pub task
-        tokio::spawn(Self::pub_task(pool, msg_out_rx, pub_chan));
+        tokio::spawn(Self::pub_task(pool, msg_out_rx, pub_chan, write_max_len));
 
         tracing::info!(chan = sub_chan, "listening");
     }
 }
+
+/// Without redis there's nobody to publish to, so messages are dropped (as if sent)
+pub fn discard(mut msg_out_rx: mpsc::Receiver<Msg>) {
+    tokio::spawn(async move {
+        while let Some((_, ack)) = msg_out_rx.recv().await {
+            if let Some(ack) = ack {
+                let _ = ack.send(Ok(()));
+            }
+        }
+    });
+}
@@ -0,0 +1,330 @@
+//! Message signing, so only services holding PUBSUB_SECRET can drive the bot over redis.
+//!
+//! A signed message is the serialized Message with `,"sig":"<ts>.<nonce>.<hex>"` added before its
+//! closing brace. `<ts>` is when it was signed in unix secs, `<nonce>` is random hex, and `<hex>` is
+//! the lowercase HMAC-SHA256 of the channel it's published on, `<ts>`, `<nonce>` and the message as
+//! it was before the field was added, each followed by a NUL byte.
+//! Services that don't read `sig` just see an unknown field.
+//!
+//! Binding the channel means a message can't be sent back the other way, and messages older than
+//! [`MAX_AGE`] or with a nonce seen in that time are rejected, so they can't be replayed either.
+//! Stream messages that are redelivered after that long are rejected with them
+
+use hmac::{Hmac, KeyInit, Mac};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use rand::Rng;
+use sha2::Sha256;
+use std::{
+    collections::{HashSet, VecDeque},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const SIG_FIELD: &str = ",\"sig\":\"";
+/// Secs a signed message is accepted for, either way to allow for clock drift
+const MAX_AGE: u64 = 60;
+
+static KEY: Lazy<Option<Hmac<Sha256>>> = Lazy::new(|| {
+    let secret = crate::config::get().pubsub_secret.as_ref()?;
+    Some(Hmac::new_from_slice(secret.0.as_bytes()).expect("HMAC takes keys of any size"))
+});
+
+/// Nonces of messages accepted lately, oldest first
+static SEEN: Lazy<Mutex<Seen>> = Lazy::new(Default::default);
+
+#[derive(Default)]
+struct Seen {
+    order: VecDeque<(u64, String)>,
+    nonces: HashSet<String>,
+}
+
+impl Seen {
+    /// False if the nonce has been seen already. Ones seen over twice [`MAX_AGE`] ago are
+    /// forgotten, by then anything signed with them is stale
+    fn insert(&mut self, now: u64, nonce: &str) -> bool {
+        while let Some((seen_at, _)) = self.order.front() {
+            if now.saturating_sub(*seen_at) <= 2 * MAX_AGE {
+                break;
+            }
+            if let Some((_, old)) = self.order.pop_front() {
+                self.nonces.remove(&old);
+            }
+        }
+        if !self.nonces.insert(nonce.to_owned()) {
+            return false;
+        }
+        self.order.push_back((now, nonce.to_owned()));
+        true
+    }
+}
+
+/// What checking a message's signature found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signature {
+    /// No secret's set, so nothing is signed
+    NotRequired,
+    Valid,
+    Missing,
+    Invalid,
+    /// Signed too long ago, or too far in the future
+    Stale,
+    /// Signed properly, but seen before
+    Replayed,
+}
+
+impl Signature {
+    pub fn trusted(self) -> bool {
+        matches!(self, Signature::NotRequired | Signature::Valid)
+    }
+}
+
+fn unix_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn mac(
+    mut mac: Hmac<Sha256>,
+    channel: &str,
+    ts: &str,
+    nonce: &str,
+    body: &[&[u8]],
+) -> Hmac<Sha256> {
+    for part in [channel.as_bytes(), ts.as_bytes(), nonce.as_bytes()] {
+        mac.update(part);
+        mac.update(&[0]);
+    }
+    for part in body {
+        mac.update(part);
+    }
+    mac.update(&[0]);
+    mac
+}
+
+/// Sign a serialized Message for publishing on `channel`, None if no secret's set
+pub fn sign(channel: &str, json: &str) -> Option<String> {
+    let key = KEY.clone()?;
+    let ts = unix_secs();
+    let nonce = format!("{:032x}", rand::thread_rng().gen::<u128>());
+    sign_with(key, channel, json, ts, &nonce)
+}
+
+fn sign_with(key: Hmac<Sha256>, channel: &str, json: &str, ts: u64, nonce: &str) -> Option<String> {
+    let body = json.strip_suffix('}')?;
+    let ts = ts.to_string();
+    let mac = mac(key, channel, &ts, nonce, &[json.as_bytes()]);
+
+    let mut signed =
+        String::with_capacity(json.len() + SIG_FIELD.len() + ts.len() + nonce.len() + 68);
+    signed.push_str(body);
+    signed.push_str(SIG_FIELD);
+    signed.push_str(&ts);
+    signed.push('.');
+    signed.push_str(nonce);
+    signed.push('.');
+    for b in mac.finalize().into_bytes() {
+        signed.push_str(&format!("{:02x}", b));
+    }
+    signed.push_str("\"}");
+    Some(signed)
+}
+
+/// Check a message read from `channel`
+pub fn verify(channel: &str, json: &str) -> Signature {
+    match KEY.clone() {
+        Some(key) => verify_with(key, &mut SEEN.lock(), unix_secs(), channel, json),
+        None => Signature::NotRequired,
+    }
+}
+
+fn verify_with(
+    key: Hmac<Sha256>,
+    seen: &mut Seen,
+    now: u64,
+    channel: &str,
+    json: &str,
+) -> Signature {
+    let (body, sig) = match json
+        .trim_end()
+        .strip_suffix("\"}")
+        .and_then(|rest| rest.rsplit_once(SIG_FIELD))
+    {
+        Some(split) => split,
+        None => return Signature::Missing,
+    };
+    let mut parts = sig.splitn(3, '.');
+    let (ts, nonce, sig) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(ts), Some(nonce), Some(sig)) => (ts, nonce, sig),
+        _ => return Signature::Invalid,
+    };
+    let sig = match decode_hex(sig) {
+        Some(sig) => sig,
+        None => return Signature::Invalid,
+    };
+
+    let mac = mac(key, channel, ts, nonce, &[body.as_bytes(), b"}"]);
+    if mac.verify_slice(&sig).is_err() {
+        return Signature::Invalid;
+    }
+
+    let signed_at = match ts.parse::<u64>() {
+        Ok(signed_at) => signed_at,
+        Err(_) => return Signature::Invalid,
+    };
+    if now.abs_diff(signed_at) > MAX_AGE {
+        return Signature::Stale;
+    }
+    if !seen.insert(now, nonce) {
+        return Signature::Replayed;
+    }
+    Signature::Valid
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| match pair {
+            [_, _] => u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok(),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+    const MSG: &str = r#"{"payload":{"Chat":{}}}"#;
+
+    fn key() -> Hmac<Sha256> {
+        Hmac::new_from_slice(b"secret").unwrap()
+    }
+
+    fn signed(channel: &str, ts: u64, nonce: &str) -> String {
+        sign_with(key(), channel, MSG, ts, nonce).unwrap()
+    }
+
+    fn verify(seen: &mut Seen, now: u64, channel: &str, json: &str) -> Signature {
+        verify_with(key(), seen, now, channel, json)
+    }
+
+    #[test]
+    fn accepts_what_it_signed() {
+        let json = signed("to_bot", NOW, "ab");
+        assert!(json.starts_with(r#"{"payload":{"Chat":{}},"sig":"1700000000.ab."#));
+        assert_eq!(
+            verify(&mut Seen::default(), NOW, "to_bot", &json),
+            Signature::Valid
+        );
+        // within the allowed drift either way
+        for now in [NOW - MAX_AGE, NOW + MAX_AGE] {
+            assert_eq!(
+                verify(&mut Seen::default(), now, "to_bot", &json),
+                Signature::Valid
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_unsigned_and_tampered() {
+        let mut seen = Seen::default();
+        assert_eq!(verify(&mut seen, NOW, "to_bot", MSG), Signature::Missing);
+
+        let json = signed("to_bot", NOW, "ab");
+        let tampered = json.replace("Chat", "Chit");
+        assert_eq!(
+            verify(&mut seen, NOW, "to_bot", &tampered),
+            Signature::Invalid
+        );
+
+        let other_key = sign_with(
+            Hmac::new_from_slice(b"other").unwrap(),
+            "to_bot",
+            MSG,
+            NOW,
+            "ab",
+        )
+        .unwrap();
+        assert_eq!(
+            verify(&mut seen, NOW, "to_bot", &other_key),
+            Signature::Invalid
+        );
+
+        // the timestamp and nonce are covered too
+        let retimed = json.replace("1700000000.ab.", "1700000001.ab.");
+        assert_eq!(
+            verify(&mut seen, NOW, "to_bot", &retimed),
+            Signature::Invalid
+        );
+        let renonced = json.replace("1700000000.ab.", "1700000000.cd.");
+        assert_eq!(
+            verify(&mut seen, NOW, "to_bot", &renonced),
+            Signature::Invalid
+        );
+
+        let truncated = &json[..json.len() - 4];
+        assert_eq!(
+            verify(&mut seen, NOW, "to_bot", truncated),
+            Signature::Missing
+        );
+        let bad_hex = json.replace(".ab.", ".ab.zz");
+        assert_eq!(
+            verify(&mut seen, NOW, "to_bot", &bad_hex),
+            Signature::Invalid
+        );
+    }
+
+    #[test]
+    fn bound_to_the_channel() {
+        let json = signed("from_bot", NOW, "ab");
+        assert_eq!(
+            verify(&mut Seen::default(), NOW, "to_bot", &json),
+            Signature::Invalid
+        );
+    }
+
+    #[test]
+    fn rejects_stale() {
+        let json = signed("to_bot", NOW, "ab");
+        let mut seen = Seen::default();
+        assert_eq!(
+            verify(&mut seen, NOW + MAX_AGE + 1, "to_bot", &json),
+            Signature::Stale
+        );
+        assert_eq!(
+            verify(&mut seen, NOW - MAX_AGE - 1, "to_bot", &json),
+            Signature::Stale
+        );
+    }
+
+    #[test]
+    fn rejects_replays() {
+        let mut seen = Seen::default();
+        let json = signed("to_bot", NOW, "ab");
+        assert_eq!(verify(&mut seen, NOW, "to_bot", &json), Signature::Valid);
+        assert_eq!(
+            verify(&mut seen, NOW + 1, "to_bot", &json),
+            Signature::Replayed
+        );
+
+        // another nonce is another message
+        let other = signed("to_bot", NOW, "cd");
+        assert_eq!(
+            verify(&mut seen, NOW + 1, "to_bot", &other),
+            Signature::Valid
+        );
+    }
+
+    #[test]
+    fn forgets_nonces_once_stale() {
+        let mut seen = Seen::default();
+        assert!(seen.insert(NOW, "ab"));
+        assert!(!seen.insert(NOW + 2 * MAX_AGE, "ab"));
+        assert!(seen.insert(NOW + 4 * MAX_AGE + 1, "cd"));
+        assert!(seen.insert(NOW + 4 * MAX_AGE + 1, "ab"));
+        assert_eq!(seen.order.len(), 2);
+    }
+}
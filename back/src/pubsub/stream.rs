@@ -0,0 +1,173 @@
+//! Chat on UPSTREAM_CHAN as a redis stream instead of pubsub. Entries stay in the stream until a
+//! consumer in the group acknowledges them, so chat sent while the back server is restarting is
+//! handled once it's back up instead of being lost.
+use crate::{
+    error::{self, Error},
+    health::{self, Link},
+    msg::{Incoming, Location},
+    RedisPool,
+};
+use bb8_redis::redis::{self, Value};
+use tokio::sync::mpsc;
+
+/// Field each entry's message is stored under
+const FIELD: &str = "msg";
+/// Entries read at a time
+const BATCH: usize = 32;
+/// Milliseconds to wait for new entries before asking again
+const BLOCK_MS: usize = 5_000;
+/// Milliseconds an entry has to go unacknowledged before it's taken from a consumer that's gone
+const CLAIM_IDLE_MS: usize = 60_000;
+
+/// Who reads the stream. Each entry goes to one consumer in the group
+#[derive(Debug, Clone)]
+pub struct Group {
+    pub name: String,
+    pub consumer: String,
+}
+
+/// stream -> [(id, [field, value, ..])]
+type ReadReply = Option<Vec<(String, Vec<(String, Vec<String>)>)>>;
+
+pub(super) async fn add(
+    pool: &RedisPool,
+    stream: &str,
+    max_len: usize,
+    msg: &str,
+) -> error::Result<()> {
+    let mut conn = pool.get().await?;
+    redis::cmd("XADD")
+        .arg(stream)
+        .arg("MAXLEN")
+        .arg("~")
+        .arg(max_len)
+        .arg("*")
+        .arg(FIELD)
+        .arg(msg)
+        .query_async::<_, String>(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+/// Hands entries to the msg task, acknowledging each once it's been handed over
+pub(super) async fn read_task(
+    pool: RedisPool,
+    msg_in_tx: mpsc::Sender<(Location, Incoming)>,
+    stream: &str,
+    group: &Group,
+) -> error::Result<()> {
+    let mut conn = pool.dedicated_connection().await?;
+    create_group(&mut conn, stream, &group.name).await?;
+    if let Err(e) = claim_stale(&mut conn, stream, group).await {
+        // XAUTOCLAIM is redis 6.2+, without it entries left by a consumer that's gone wait for it
+        tracing::warn!(stream, "couldn't claim stale entries: {}", e);
+    }
+    health::connected(Link::Pubsub, true);
+
+    // what this consumer was given but didn't acknowledge, then new entries
+    let mut backlog = true;
+    loop {
+        let reply: ReadReply = redis::cmd("XREADGROUP")
+            .arg("GROUP")
+            .arg(&group.name)
+            .arg(&group.consumer)
+            .arg("COUNT")
+            .arg(BATCH)
+            .arg("BLOCK")
+            .arg(BLOCK_MS)
+            .arg("STREAMS")
+            .arg(stream)
+            .arg(if backlog { "0" } else { ">" })
+            .query_async(&mut conn)
+            .await?;
+        let entries: Vec<_> = reply
+            .into_iter()
+            .flatten()
+            .flat_map(|(_, entries)| entries)
+            .collect();
+        if backlog && entries.is_empty() {
+            tracing::debug!(stream, "caught up on unacknowledged entries");
+            backlog = false;
+            continue;
+        }
+
+        for (id, fields) in entries {
+            health::message(Link::Pubsub);
+            // trimmed before it was acknowledged, so there's nothing left of it
+            match message(fields) {
+                Some(msg) => {
+                    msg_in_tx
+                        .send((Location::Pubsub, Incoming::Raw(msg)))
+                        .await?
+                }
+                None => tracing::warn!(stream, id = id.as_str(), "entry without a message"),
+            }
+            redis::cmd("XACK")
+                .arg(stream)
+                .arg(&group.name)
+                .arg(&id)
+                .query_async::<_, u64>(&mut conn)
+                .await?;
+        }
+    }
+}
+
+/// Start reading at the end if the group's new, entries already in the stream were never meant for it
+async fn create_group(
+    conn: &mut redis::aio::Connection,
+    stream: &str,
+    group: &str,
+) -> error::Result<()> {
+    let res = redis::cmd("XGROUP")
+        .arg("CREATE")
+        .arg(stream)
+        .arg(group)
+        .arg("$")
+        .arg("MKSTREAM")
+        .query_async::<_, ()>(conn)
+        .await;
+    match res {
+        Err(e) if e.code() == Some("BUSYGROUP") => Ok(()),
+        res => res.map_err(Error::from),
+    }
+}
+
+/// Take over entries consumers that aren't around anymore were given, e.g. under an old INSTANCE_ID
+async fn claim_stale(
+    conn: &mut redis::aio::Connection,
+    stream: &str,
+    group: &Group,
+) -> error::Result<()> {
+    let mut cursor = "0-0".to_owned();
+    loop {
+        // [next cursor, entries, deleted ids (redis 7+)]
+        let reply: Vec<Value> = redis::cmd("XAUTOCLAIM")
+            .arg(stream)
+            .arg(&group.name)
+            .arg(&group.consumer)
+            .arg(CLAIM_IDLE_MS)
+            .arg(&cursor)
+            .arg("COUNT")
+            .arg(BATCH)
+            .arg("JUSTID")
+            .query_async(conn)
+            .await?;
+        cursor = match reply.first() {
+            Some(next) => redis::from_redis_value(next)?,
+            None => return Ok(()),
+        };
+        if cursor == "0-0" {
+            return Ok(());
+        }
+    }
+}
+
+fn message(fields: Vec<String>) -> Option<String> {
+    let mut fields = fields.into_iter();
+    while let (Some(field), Some(value)) = (fields.next(), fields.next()) {
+        if field == FIELD {
+            return Some(value);
+        }
+    }
+    None
+}
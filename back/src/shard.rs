@@ -0,0 +1,193 @@
+//! Splitting chat between backend instances subscribed to the same UPSTREAM_CHAN.
+//!
+//! Users are hashed by platform and id into SHARD_BUCKETS buckets, and each instance leases
+//! an even share of them in redis, renewing every [`RENEW_EVERY`]. Chat and invocations from
+//! users in buckets an instance doesn't hold are left to whoever does, everything else (config
+//! changes, stream events, web UI requests) is still handled by all of them. Timers are left
+//! to the leader, see [`crate::leader`].
+//!
+//! A lease that isn't renewed lapses after [`LEASE_TTL`] secs, so a dead instance's users go
+//! unanswered for at most that long before the rest pick them up
+
+use crate::{
+    cache::{self, Cache, RespType},
+    config::ShardConfig,
+    error::{self, Error},
+    lock,
+    msg::Platform,
+};
+use once_cell::sync::{Lazy, OnceCell};
+use parking_lot::RwLock;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tracing::{info_span, Instrument};
+
+const RENEW_EVERY: Duration = Duration::from_secs(10);
+/// Secs, also how long an instance can go quiet before it's no longer counted
+const LEASE_TTL: u64 = 30;
+
+/// instance -> unix ms it last checked in
+static INSTANCES_KEY: Lazy<Arc<String>> =
+    Lazy::new(|| Arc::new(format!("aussiebot!{}!instances", crate::channel_name())));
+
+static STATE: OnceCell<Shards> = OnceCell::new();
+
+struct Shards {
+    buckets: u32,
+    instance: Arc<String>,
+    /// Indexed by bucket
+    owned: RwLock<Vec<bool>>,
+}
+
+/// FNV-1a, every instance has to agree on where a user goes, whatever it was built with
+fn fnv1a(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    bytes.into_iter().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Which of `buckets` a user belongs in
+pub fn bucket(buckets: u32, platform: Platform, id: &str) -> u32 {
+    let bytes = platform.bits().to_le_bytes().into_iter().chain(id.bytes());
+    (fnv1a(bytes) % buckets as u64) as u32
+}
+
+/// Whether this instance handles the user, always true without sharding
+pub fn owns(platform: Platform, id: &str) -> bool {
+    match STATE.get() {
+        Some(shards) => shards.owned.read()[bucket(shards.buckets, platform, id) as usize],
+        None => true,
+    }
+}
+
+fn lease_key(bucket: u32) -> String {
+    format!("aussiebot!{}!shard!{}", crate::channel_name(), bucket)
+}
+
+fn unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Start claiming buckets. Until the first round is done, nothing is owned
+pub fn start(config: &ShardConfig, cache: cache::Handle, lock: lock::Handle) {
+    let shards = STATE.get_or_init(|| Shards {
+        buckets: config.buckets,
+        instance: Arc::new(crate::config::server().instance.clone()),
+        owned: RwLock::new(vec![false; config.buckets as usize]),
+    });
+    tracing::info!(
+        instance = shards.instance.as_str(),
+        buckets = shards.buckets,
+        "sharding"
+    );
+
+    tokio::spawn(
+        async move {
+            let mut interval = tokio::time::interval(RENEW_EVERY);
+            let mut last_renewed = Instant::now();
+            loop {
+                interval.tick().await;
+                // leases run from when they were asked for, not from when the answer came back
+                let attempted = Instant::now();
+                match tokio::time::timeout(RENEW_EVERY, shards.rebalance(&cache, &lock))
+                    .await
+                    .unwrap_or_else(|_| Err(Error::Generic("timed out".into())))
+                {
+                    Ok(()) => last_renewed = attempted,
+                    // they could lapse before the next try, and someone else take them
+                    Err(e)
+                        if last_renewed.elapsed() + RENEW_EVERY
+                            >= Duration::from_secs(LEASE_TTL) =>
+                    {
+                        tracing::error!("couldn't renew leases, dropping them: {}", e);
+                        shards.owned.write().fill(false);
+                    }
+                    Err(e) => tracing::warn!("couldn't renew leases: {}", e),
+                }
+            }
+        }
+        .instrument(info_span!("shard")),
+    );
+}
+
+impl Shards {
+    /// Check in, keep up to an even share of the buckets and claim free ones to make it up
+    async fn rebalance(&self, cache: &cache::Handle, lock: &lock::Handle) -> error::Result<()> {
+        let now = unix_ms();
+        Cache::HashSet(
+            INSTANCES_KEY.clone(),
+            self.instance.clone(),
+            now.to_string(),
+            false,
+        )
+        .exec(cache)
+        .await?;
+        let instances = match Cache::HashGetAll(INSTANCES_KEY.clone()).exec(cache).await? {
+            RespType::VecStringString(instances) => instances,
+            _ => unreachable!(),
+        };
+
+        let mut alive = 0;
+        for (instance, seen) in instances {
+            let seen: u64 = seen.parse().unwrap_or_default();
+            if now.saturating_sub(seen) > LEASE_TTL * 1000 {
+                Cache::HashDelete(INSTANCES_KEY.clone(), instance.into())
+                    .exec(cache)
+                    .await?;
+            } else {
+                alive += 1;
+            }
+        }
+        let share = self.buckets.div_ceil(alive.max(1));
+
+        let mut owned = self.owned.read().clone();
+        let mut held = 0;
+        for bucket in 0..self.buckets {
+            if !owned[bucket as usize] {
+                continue;
+            }
+            let key = lease_key(bucket);
+            if held < share {
+                // renewed only if it's still ours, it may have lapsed and gone to someone else
+                if lock.lease(key, self.instance.as_str(), LEASE_TTL).await? {
+                    held += 1;
+                    continue;
+                }
+            } else {
+                lock.release(key, self.instance.as_str()).await?;
+            }
+            owned[bucket as usize] = false;
+        }
+
+        // start somewhere different to the others, so they don't all go for the same ones
+        let offset = (fnv1a(self.instance.bytes()) % self.buckets as u64) as u32;
+        for i in 0..self.buckets {
+            if held >= share {
+                break;
+            }
+            let bucket = (offset + i) % self.buckets;
+            if owned[bucket as usize] {
+                continue;
+            }
+            if lock
+                .lease(lease_key(bucket), self.instance.as_str(), LEASE_TTL)
+                .await?
+            {
+                owned[bucket as usize] = true;
+                held += 1;
+            }
+        }
+
+        let mut current = self.owned.write();
+        if *current != owned {
+            tracing::info!(held, share, alive, "\x1b[93mbuckets changed\x1b[0m");
+            *current = owned;
+        }
+        Ok(())
+    }
+}
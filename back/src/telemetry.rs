@@ -0,0 +1,78 @@
+//! Optional OpenTelemetry export of tracing spans, for following a message from redis
+//! through the commands to the reply in Jaeger/Tempo
+
+use crate::config::TelemetryConfig;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{trace::SdkTracerProvider, Resource};
+use tracing::Subscriber;
+use tracing_subscriber::{
+    filter::{LevelFilter, Targets},
+    registry::LookupSpan,
+    Layer,
+};
+
+/// Flushes spans that haven't been exported yet when dropped, keep it alive in main
+pub struct Guard(SdkTracerProvider);
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        if let Err(e) = self.0.shutdown() {
+            eprintln!("couldn't flush spans: {}", e);
+        }
+    }
+}
+
+/// A layer exporting spans to the collector in `config`, nothing if it's unset or the
+/// exporter can't be built. Has to be called inside the tokio runtime
+pub fn layer<S>(
+    config: Option<&TelemetryConfig>,
+    service_name: &'static str,
+) -> (Option<impl Layer<S>>, Option<Guard>)
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    let config = match config {
+        Some(config) => config,
+        None => return (None, None),
+    };
+
+    let exporter = match SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(&config.endpoint)
+        .build()
+    {
+        Ok(exporter) => exporter,
+        Err(e) => {
+            // tracing isn't set up yet
+            eprintln!("couldn't build OTLP exporter, not exporting spans: {}", e);
+            return (None, None);
+        }
+    };
+
+    let service_name = config
+        .service_name
+        .clone()
+        .unwrap_or_else(|| service_name.to_owned());
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(
+            Resource::builder()
+                .with_service_name(service_name.clone())
+                .build(),
+        )
+        .build();
+
+    // the exporter's own spans would otherwise be exported too, forever
+    let exporter_crates = Targets::new()
+        .with_default(LevelFilter::TRACE)
+        .with_target("h2", LevelFilter::OFF)
+        .with_target("hyper", LevelFilter::OFF)
+        .with_target("tonic", LevelFilter::OFF)
+        .with_target("tower", LevelFilter::OFF)
+        .with_target("opentelemetry", LevelFilter::OFF);
+    let layer = tracing_opentelemetry::layer()
+        .with_tracer(provider.tracer(service_name))
+        .with_filter(exporter_crates);
+    (Some(layer), Some(Guard(provider)))
+}
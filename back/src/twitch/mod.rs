@@ -0,0 +1,408 @@
+use crate::{
+    config::{self, Secret, TwitchConfig},
+    error::{self, Error},
+    msg::{new_corr_id, Incoming, Location, Message, Payload, Platform, StreamEvent},
+    pubsub,
+};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde_derive::Deserialize;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tokio::sync::mpsc;
+use tracing::{info_span, Instrument};
+
+const TOKEN_URL: &str = "https://id.twitch.tv/oauth2/token";
+const STREAMS_URL: &str = "https://api.twitch.tv/helix/streams";
+const USERS_URL: &str = "https://api.twitch.tv/helix/users";
+const CHANNELS_URL: &str = "https://api.twitch.tv/helix/channels";
+const CLIPS_URL: &str = "https://api.twitch.tv/helix/clips";
+
+/// Consecutive offline polls before a stop is sent, so brief drops don't end the stream
+const OFFLINE_DEBOUNCE: u8 = 3;
+
+#[derive(Debug, Deserialize)]
+struct Token {
+    access_token: String,
+    expires_in: u64,
+    /// Only given back for user tokens, twitch may hand out a new one on each refresh
+    #[serde(default)]
+    refresh_token: Option<String>,
+}
+
+impl Token {
+    /// When to get a new one, a minute early
+    fn refresh_at(&self) -> Instant {
+        Instant::now() + Duration::from_secs(self.expires_in.saturating_sub(60))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Stream {
+    title: String,
+    started_at: String,
+    viewer_count: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct Data<T> {
+    data: Vec<T>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HelixUser {
+    id: String,
+}
+
+/// A channel's last known stream info
+#[derive(Debug, Deserialize)]
+pub struct ChannelInfo {
+    #[serde(rename = "broadcaster_login")]
+    pub login: String,
+    #[serde(rename = "broadcaster_name")]
+    pub display_name: String,
+    #[serde(rename = "game_name")]
+    pub game: String,
+    pub title: String,
+}
+
+/// A clip that's been asked for, it takes a few seconds before it can be watched
+#[derive(Debug, Deserialize)]
+pub struct CreatedClip {
+    pub id: String,
+    pub edit_url: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Clip {
+    pub id: String,
+    pub url: String,
+}
+
+/// Shared Helix API client, None if twitch credentials aren't configured
+pub static HELIX: Lazy<Option<Helix>> = Lazy::new(|| Helix::new(&config::server().twitch));
+
+/// The channel's twitch login
+pub fn login() -> &'static str {
+    &config::server().twitch.login
+}
+
+pub struct Helix {
+    client: reqwest::Client,
+    client_id: String,
+    client_secret: Secret,
+    token: Mutex<Option<(String, Instant)>>,
+    /// User access token with the clips:edit scope, for the same client id. When it expires is
+    /// only known once it's been refreshed
+    user_token: Mutex<Option<(String, Option<Instant>)>>,
+    refresh_token: Mutex<Option<String>>,
+}
+
+impl Helix {
+    fn new(config: &TwitchConfig) -> Option<Self> {
+        let app = config.app.clone()?;
+        Some(Self {
+            client: reqwest::Client::new(),
+            client_id: app.client_id,
+            client_secret: app.client_secret,
+            token: Mutex::new(None),
+            user_token: Mutex::new(config.user_token.clone().map(|token| (token.0, None))),
+            refresh_token: Mutex::new(config.refresh_token.clone().map(|token| token.0)),
+        })
+    }
+
+    /// App access token, refreshed shortly before it expires
+    async fn token(&self) -> error::Result<String> {
+        if let Some((ref token, expires_at)) = *self.token.lock() {
+            if Instant::now() < expires_at {
+                return Ok(token.clone());
+            }
+        }
+
+        let token = self
+            .fetch_token(&[("grant_type", "client_credentials")])
+            .await?;
+        *self.token.lock() = Some((token.access_token.clone(), token.refresh_at()));
+
+        Ok(token.access_token)
+    }
+
+    async fn fetch_token(&self, grant: &[(&str, &str)]) -> error::Result<Token> {
+        Ok(self
+            .client
+            .post(TOKEN_URL)
+            .query(&[
+                ("client_id", self.client_id.as_str()),
+                ("client_secret", self.client_secret.0.as_str()),
+            ])
+            .query(grant)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+
+    /// User access token, refreshed shortly before it expires if there's a refresh token
+    async fn user_token(&self) -> error::Result<String> {
+        let can_refresh = self.refresh_token.lock().is_some();
+        if let Some((ref token, expires_at)) = *self.user_token.lock() {
+            match expires_at {
+                Some(expires_at) if Instant::now() >= expires_at && can_refresh => {}
+                _ => return Ok(token.clone()),
+            }
+        }
+        self.refresh_user_token().await
+    }
+
+    async fn refresh_user_token(&self) -> error::Result<String> {
+        let refresh_token = match self.refresh_token.lock().clone() {
+            Some(token) => token,
+            None => return Err("TWITCH_USER_TOKEN isn't set".into()),
+        };
+
+        let token = self
+            .fetch_token(&[
+                ("grant_type", "refresh_token"),
+                ("refresh_token", refresh_token.as_str()),
+            ])
+            .await?;
+        *self.user_token.lock() = Some((token.access_token.clone(), Some(token.refresh_at())));
+        if let Some(refresh_token) = token.refresh_token {
+            *self.refresh_token.lock() = Some(refresh_token);
+        }
+        tracing::info!("refreshed the twitch user token");
+
+        Ok(token.access_token)
+    }
+
+    async fn get<T: serde::de::DeserializeOwned>(
+        &self,
+        url: &str,
+        query: &[(&str, &str)],
+    ) -> error::Result<Vec<T>> {
+        let token = self.token().await?;
+
+        let resp = self
+            .client
+            .get(url)
+            .query(query)
+            .header("Client-Id", &self.client_id)
+            .bearer_auth(token)
+            .send()
+            .await?;
+
+        if resp.status() == reqwest::StatusCode::UNAUTHORIZED {
+            // token was revoked, get a new one next time
+            *self.token.lock() = None;
+        }
+
+        let data: Data<T> = resp.error_for_status()?.json().await?;
+        Ok(data.data)
+    }
+
+    /// None if the channel doesn't exist
+    async fn user_id(&self, login: &str) -> error::Result<Option<String>> {
+        let user: Option<HelixUser> = self
+            .get(USERS_URL, &[("login", login)])
+            .await?
+            .into_iter()
+            .next();
+        Ok(user.map(|u| u.id))
+    }
+
+    /// None if the channel doesn't exist
+    pub async fn channel_info(&self, login: &str) -> error::Result<Option<ChannelInfo>> {
+        let id = match self.user_id(login).await? {
+            Some(id) => id,
+            None => return Ok(None),
+        };
+
+        Ok(self
+            .get(CHANNELS_URL, &[("broadcaster_id", id.as_str())])
+            .await?
+            .into_iter()
+            .next())
+    }
+
+    /// Whether there's a user token to create clips with
+    pub fn can_clip(&self) -> bool {
+        self.user_token.lock().is_some() || self.refresh_token.lock().is_some()
+    }
+
+    /// None if the channel isn't live
+    pub async fn create_clip(&self, login: &str) -> error::Result<Option<CreatedClip>> {
+        let id = match self.user_id(login).await? {
+            Some(id) => id,
+            None => return Ok(None),
+        };
+
+        let mut user_token = self.user_token().await?;
+        let mut refreshed = false;
+        let resp = loop {
+            let resp = self
+                .client
+                .post(CLIPS_URL)
+                .query(&[("broadcaster_id", id.as_str())])
+                .header("Client-Id", &self.client_id)
+                .bearer_auth(&user_token)
+                .send()
+                .await?;
+
+            // expired early or was revoked, refresh it and try again once
+            if resp.status() == reqwest::StatusCode::UNAUTHORIZED
+                && !refreshed
+                && self.refresh_token.lock().is_some()
+            {
+                user_token = self.refresh_user_token().await?;
+                refreshed = true;
+                continue;
+            }
+            break resp;
+        };
+
+        // not live
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let data: Data<CreatedClip> = resp.error_for_status()?.json().await?;
+        Ok(data.data.into_iter().next())
+    }
+
+    /// None until the clip's done processing
+    pub async fn clip(&self, id: &str) -> error::Result<Option<Clip>> {
+        Ok(self.get(CLIPS_URL, &[("id", id)]).await?.into_iter().next())
+    }
+}
+
+/// Polls the Helix API for the channel's live status, for when discord presence can't be relied on
+pub struct Poller {
+    msg_in_tx: mpsc::Sender<(Location, Incoming)>,
+    helix: &'static Helix,
+    login: String,
+    interval: Duration,
+}
+
+impl Poller {
+    /// None if twitch credentials aren't configured
+    pub fn new(
+        config: &TwitchConfig,
+        msg_in_tx: mpsc::Sender<(Location, Incoming)>,
+    ) -> Option<Self> {
+        let helix = HELIX.as_ref()?;
+
+        Some(Self {
+            msg_in_tx,
+            helix,
+            login: config.login.clone(),
+            interval: config.poll_interval,
+        })
+    }
+
+    /// None if not live
+    async fn live_stream(&self) -> error::Result<Option<Stream>> {
+        // only live streams are listed
+        let streams = self
+            .helix
+            .get(STREAMS_URL, &[("user_login", self.login.as_str())])
+            .await?;
+        Ok(streams.into_iter().next())
+    }
+
+    fn metadata(stream: Stream) -> StreamEvent {
+        let started_at = OffsetDateTime::parse(&stream.started_at, &Rfc3339)
+            .map(|t| t.unix_timestamp().max(0) as u64)
+            .unwrap_or_else(|e| {
+                tracing::warn!(started_at = stream.started_at.as_str(), "{}", e);
+                0
+            });
+
+        StreamEvent::Metadata {
+            title: Arc::new(stream.title),
+            started_at,
+            viewer_count: stream.viewer_count,
+        }
+    }
+
+    async fn send(&self, event: StreamEvent) -> error::Result<()> {
+        let corr_id = new_corr_id();
+        tracing::debug!(corr_id = corr_id.as_str(), event = ?event, "sending stream event");
+        let msg = Message {
+            platform: Platform::TWITCH,
+            channel: crate::channel_name().to_owned(),
+            payload: Payload::StreamEvent(event),
+            corr_id: Some(corr_id),
+            dedupe_id: None,
+        };
+        let msg = serde_json::to_string(&msg)?;
+        let msg = pubsub::sign::sign(&crate::config::get().upstream_chan, &msg).unwrap_or(msg);
+        // handled like the connectors' stream events
+        self.msg_in_tx
+            .send((Location::Pubsub, Incoming::Raw(msg)))
+            .await
+            .map_err(Error::from)
+    }
+
+    async fn poll_task(self) {
+        let url = Arc::new(format!("https://www.twitch.tv/{}", self.login));
+        let mut interval = tokio::time::interval(self.interval);
+        let mut was_live = false;
+        let mut offline_polls = 0;
+
+        loop {
+            interval.tick().await;
+
+            let stream = match self.live_stream().await {
+                Ok(s) => s,
+                Err(e) => {
+                    tracing::error!("{}", e);
+                    continue;
+                }
+            };
+            let is_live = stream.is_some();
+
+            let event = match (was_live, is_live) {
+                (false, true) => {
+                    tracing::info!(url = url.as_str(), "not streaming -> streaming");
+                    was_live = true;
+                    Some(StreamEvent::DetectStart(url.clone()))
+                }
+                (true, false) => {
+                    offline_polls += 1;
+                    if offline_polls < OFFLINE_DEBOUNCE {
+                        continue;
+                    }
+                    tracing::info!(url = url.as_str(), "streaming -> not streaming");
+                    was_live = false;
+                    Some(StreamEvent::DetectStop(url.clone()))
+                }
+                _ => None,
+            };
+
+            if is_live {
+                offline_polls = 0;
+            }
+
+            // metadata follows the start, so it's there by the time chat asks
+            let metadata = stream.map(Self::metadata);
+            for event in event.into_iter().chain(metadata) {
+                if let Err(e) = self.send(event).await {
+                    tracing::error!("{}", e);
+                }
+            }
+        }
+    }
+
+    pub fn start(self) {
+        tracing::info!(
+            "\x1b[93mSpawning Twitch poller for {} with interval: {}s\x1b[0m",
+            self.login,
+            self.interval.as_secs()
+        );
+        tokio::spawn(self.poll_task().instrument(info_span!("Twitch poller")));
+    }
+}
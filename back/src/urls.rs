@@ -0,0 +1,172 @@
+//! Links in chat. Shortened ones are expanded so filters see where they really go, and long
+//! ones in responses can be shortened so they fit in a Twitch message.
+//!
+//! Only hosts in URL_EXPAND_HOSTS are followed, one redirect at a time, so a link can't send
+//! the bot anywhere it wasn't told to go. Results are remembered, since the same links tend
+//! to come up over and over (e.g. in timers)
+
+use crate::{config::UrlConfig, error};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use regex::Regex;
+use std::{borrow::Cow, collections::HashMap, time::Duration};
+use tokio::time::{timeout_at, Instant};
+use url::Url;
+
+static URL_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"https?://[^\s<>]+").unwrap());
+
+static CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+    reqwest::Client::builder()
+        .timeout(config().timeout)
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+});
+
+/// Redirects followed per link
+const MAX_HOPS: usize = 5;
+/// Links remembered each way, forgotten all at once past this
+const MEMO_SIZE: usize = 1024;
+/// Time given to all the links in one message, those not done by then are left alone
+const BUDGET: Duration = Duration::from_secs(2);
+
+static EXPANDED: Lazy<Mutex<HashMap<String, String>>> = Lazy::new(Default::default);
+static SHORTENED: Lazy<Mutex<HashMap<String, String>>> = Lazy::new(Default::default);
+
+fn config() -> &'static UrlConfig {
+    &crate::config::server().urls
+}
+
+fn remember(memo: &Mutex<HashMap<String, String>>, from: &str, to: &str) {
+    let mut memo = memo.lock();
+    if memo.len() >= MEMO_SIZE {
+        memo.clear();
+    }
+    memo.insert(from.to_owned(), to.to_owned());
+}
+
+/// Links in the text, in order
+pub fn find(text: &str) -> impl Iterator<Item = &str> {
+    URL_REGEX.find_iter(text).map(|m| m.as_str())
+}
+
+fn expandable(url: &Url) -> bool {
+    url.host_str().is_some_and(|host| {
+        let host = host.to_lowercase();
+        config().expand_hosts.iter().any(|allowed| {
+            host == *allowed
+                || host
+                    .strip_suffix(allowed.as_str())
+                    .is_some_and(|sub| sub.ends_with('.'))
+        })
+    })
+}
+
+/// Where a link ends up, following redirects while they stay on URL_EXPAND_HOSTS.
+/// Links that aren't on them come back as-is
+pub async fn expand(link: &str) -> error::Result<String> {
+    if let Some(expanded) = EXPANDED.lock().get(link) {
+        return Ok(expanded.clone());
+    }
+
+    let mut url = Url::parse(link)?;
+    for _ in 0..MAX_HOPS {
+        if !expandable(&url) {
+            break;
+        }
+        let resp = CLIENT.head(url.clone()).send().await?;
+        let next = match resp.headers().get(reqwest::header::LOCATION) {
+            Some(location) if resp.status().is_redirection() => location,
+            _ => break,
+        };
+        // may be relative
+        url = url.join(next.to_str().unwrap_or_default())?;
+    }
+
+    let expanded = url.to_string();
+    tracing::debug!(link, expanded = expanded.as_str(), "expanded");
+    remember(&EXPANDED, link, &expanded);
+    Ok(expanded)
+}
+
+/// The text with every shortened link expanded, those that couldn't be are left alone
+pub async fn expand_all(text: &str) -> Cow<'_, str> {
+    let links: Vec<_> = find(text)
+        .filter(|link| Url::parse(link).is_ok_and(|url| expandable(&url)))
+        .collect();
+    if links.is_empty() {
+        return text.into();
+    }
+
+    let deadline = Instant::now() + BUDGET;
+    let mut expanded = text.to_owned();
+    for link in links {
+        match timeout_at(deadline, expand(link)).await {
+            Ok(Ok(to)) => expanded = expanded.replacen(link, &to, 1),
+            Ok(Err(e)) => tracing::warn!(link, "couldn't expand: {}", e),
+            Err(_) => {
+                tracing::warn!(link, "ran out of time expanding links");
+                break;
+            }
+        }
+    }
+    expanded.into()
+}
+
+/// A shorter link from URL_SHORTENER
+pub async fn shorten(link: &str, shortener: &str) -> error::Result<String> {
+    if let Some(short) = SHORTENED.lock().get(link) {
+        return Ok(short.clone());
+    }
+
+    let encoded: String = url::form_urlencoded::byte_serialize(link.as_bytes()).collect();
+    let short = CLIENT
+        .get(shortener.replace("{url}", &encoded))
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let short = short.trim();
+    // anything else is an error page, or not worth it
+    let short = match Url::parse(short) {
+        Ok(_) if short.len() < link.len() => short,
+        _ => link,
+    };
+
+    remember(&SHORTENED, link, short);
+    Ok(short.to_owned())
+}
+
+/// Whether the text has links shorten_all would try to shorten
+pub fn shortenable(text: &str) -> bool {
+    let config = config();
+    config.shortener.is_some() && find(text).any(|link| link.len() > config.shorten_over)
+}
+
+/// The text with links over URL_SHORTEN_OVER shortened, if URL_SHORTENER is set.
+/// None if there was nothing to shorten
+pub async fn shorten_all(text: &str) -> Option<String> {
+    let config = config();
+    let shortener = config.shortener.as_deref()?;
+    let links: Vec<_> = find(text)
+        .filter(|link| link.len() > config.shorten_over)
+        .collect();
+    if links.is_empty() {
+        return None;
+    }
+
+    let deadline = Instant::now() + BUDGET;
+    let mut shortened = text.to_owned();
+    for link in links {
+        match timeout_at(deadline, shorten(link, shortener)).await {
+            Ok(Ok(to)) => shortened = shortened.replacen(link, &to, 1),
+            Ok(Err(e)) => tracing::warn!(link, "couldn't shorten: {}", e),
+            Err(_) => {
+                tracing::warn!(link, "ran out of time shortening links");
+                break;
+            }
+        }
+    }
+    Some(shortened)
+}
@@ -0,0 +1,127 @@
+use crate::error;
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use std::{
+    io::{Read, Write},
+    sync::Arc,
+};
+use tokio_tungstenite::tungstenite::{http::HeaderMap, Message};
+
+/// Inflated frames past this are rejected, so a tiny frame can't balloon in memory
+const MAX_INFLATED_LEN: u64 = 1 << 20;
+
+/// Subprotocols in no particular order, the client's order of preference wins
+const PROTOCOLS: &[(&str, Codec)] = &[
+    ("aussiebot.json", Codec::Json),
+    ("aussiebot.json+deflate", Codec::JsonDeflate),
+    ("aussiebot.msgpack", Codec::MsgPack),
+    ("aussiebot.msgpack+deflate", Codec::MsgPackDeflate),
+];
+
+/// Wire format of a peer's data frames, negotiated through Sec-WebSocket-Protocol.
+/// Peers that don't ask for one get JSON text frames like before.
+/// Heartbeats are always plain text frames
+///
+/// tungstenite doesn't do permessage-deflate, so deflate codecs compress each
+/// frame on their own (zlib) and send it as binary
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(super) enum Codec {
+    #[default]
+    Json,
+    JsonDeflate,
+    MsgPack,
+    MsgPackDeflate,
+}
+
+impl Codec {
+    /// Pick the first subprotocol offered that we support, along with the name to echo back
+    pub(super) fn negotiate(headers: &HeaderMap) -> Option<(Self, &'static str)> {
+        headers
+            .get_all("sec-websocket-protocol")
+            .iter()
+            .filter_map(|hv| hv.to_str().ok())
+            .flat_map(|hv| hv.split(','))
+            .find_map(|offered| {
+                PROTOCOLS
+                    .iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case(offered.trim()))
+                    .map(|(name, codec)| (*codec, *name))
+            })
+    }
+
+    fn is_deflate(self) -> bool {
+        matches!(self, Codec::JsonDeflate | Codec::MsgPackDeflate)
+    }
+
+    fn is_msgpack(self) -> bool {
+        matches!(self, Codec::MsgPack | Codec::MsgPackDeflate)
+    }
+
+    /// Cheap enough to do inline
+    pub(super) fn is_plain(self) -> bool {
+        self == Codec::Json
+    }
+
+    /// Encode a serialized JSON message for the wire
+    pub(super) fn encode(self, msg: &str) -> error::Result<Message> {
+        if self.is_plain() {
+            return Ok(Message::Text(msg.to_owned()));
+        }
+
+        let bytes = if self.is_msgpack() {
+            let value: serde_json::Value = serde_json::from_str(msg)?;
+            rmp_serde::to_vec_named(&value)?
+        } else {
+            msg.as_bytes().to_vec()
+        };
+
+        let bytes = if self.is_deflate() {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(&bytes)?;
+            encoder.finish()?
+        } else {
+            bytes
+        };
+
+        Ok(Message::Binary(bytes))
+    }
+
+    /// Same as encode, off the runtime unless it's plain JSON, compressing big dumps takes a while
+    pub(super) async fn encode_blocking(self, msg: Arc<str>) -> error::Result<Message> {
+        if self.is_plain() {
+            return self.encode(&msg);
+        }
+        tokio::task::spawn_blocking(move || self.encode(&msg)).await?
+    }
+
+    /// Decode a frame back into a JSON string, None for control frames.
+    /// Text frames are taken as JSON whatever the codec
+    pub(super) fn decode(self, msg: Message) -> error::Result<Option<String>> {
+        let bytes = match msg {
+            Message::Text(s) => return Ok(Some(s)),
+            Message::Binary(b) => b,
+            _ => return Ok(None),
+        };
+
+        let bytes = if self.is_deflate() {
+            let mut inflated = vec![];
+            ZlibDecoder::new(&*bytes)
+                .take(MAX_INFLATED_LEN + 1)
+                .read_to_end(&mut inflated)?;
+            if inflated.len() as u64 > MAX_INFLATED_LEN {
+                return Err(super::WsError::Parse("inflated frame too large").into());
+            }
+            inflated
+        } else {
+            bytes
+        };
+
+        let msg = if self.is_msgpack() {
+            let value: serde_json::Value = rmp_serde::from_slice(&bytes)?;
+            serde_json::to_string(&value)?
+        } else {
+            String::from_utf8(bytes).map_err(|_| super::WsError::Parse("utf-8 frame"))?
+        };
+
+        Ok(Some(msg))
+    }
+}
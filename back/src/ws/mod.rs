@@ -1,35 +1,127 @@
 use crate::{
-    auth::{self, AuthMsg, AuthResp},
+    auth::{self, AuthError, AuthMsg, AuthResp, Role},
+    config::ServerConfig,
     error,
-    msg::Location,
+    msg::{self, Incoming, Location, Platform},
 };
 use futures_util::{pin_mut, stream::SplitStream, SinkExt, StreamExt, TryStreamExt};
 use parking_lot::RwLock;
 use std::{
-    collections::HashMap,
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
     net::{IpAddr, SocketAddr},
-    sync::Arc,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 use tokio::{
-    net::{TcpListener, TcpStream},
-    sync::mpsc,
+    net::TcpListener,
+    sync::{mpsc, oneshot},
+    task::AbortHandle,
 };
 use tokio_tungstenite::{
     accept_hdr_async,
     tungstenite::{
         handshake::server::{Request, Response},
         http::{HeaderMap, HeaderValue, StatusCode},
+        protocol::{frame::coding::CloseCode, CloseFrame},
         Message,
     },
     WebSocketStream,
 };
 use url::Url;
 
-pub type Msg = (Option<Vec<(Arc<String>, SocketAddr)>>, Arc<String>);
-type PeerMap = HashMap<SocketAddr, mpsc::Sender<Arc<String>>>;
+mod codec;
+mod ratelimit;
+mod tls;
+pub use crate::proto::ws::{Connection, Subscription, HEARTBEAT_PING, HEARTBEAT_PONG};
+use codec::Codec;
+use ratelimit::Limiter;
+use tls::Stream;
+
+/// (destinations, tag checked against subscriptions, serialized message)
+pub type Msg = (Option<Vec<(Arc<String>, SocketAddr)>>, Tag, Arc<str>);
+/// An encoded data frame, shared by every peer on the same codec
+type Frame = Arc<Message>;
+/// Peer => (send channel, what it subscribed to, its wire format)
+type PeerMap = HashMap<SocketAddr, (mpsc::Sender<Frame>, Option<Arc<Subscription>>, Codec)>;
+/// Peer => its session and how long since it was last heard from
+type SessionMap = HashMap<SocketAddr, Conn>;
+/// (username, session id, role, stream)
+type Authed = (Arc<String>, Arc<String>, Role, WebSocketStream<Stream>);
+/// None broadcasts to every peer in the shard
+type ShardMsg = (Option<Vec<SocketAddr>>, Tag, Arc<str>);
+
+/// An authed peer
+struct Conn {
+    username: Arc<String>,
+    session: Arc<String>,
+    role: Role,
+    /// Closes the connection with the frame sent
+    kick_tx: mpsc::Sender<CloseFrame<'static>>,
+    heartbeat: Arc<Heartbeat>,
+    /// Unix timestamp
+    connected_at: u64,
+    /// The read task, which won't stop by itself until TCP gives up on a dead peer
+    reader: Option<AbortHandle>,
+}
+
+/// When a peer was last heard from
+struct Heartbeat {
+    since: Instant,
+    /// Millis after `since`
+    last: AtomicU64,
+}
+
+impl Heartbeat {
+    fn new() -> Self {
+        Self {
+            since: Instant::now(),
+            last: AtomicU64::new(0),
+        }
+    }
 
-const HEARTBEAT_PING: &str = "💓";
-const HEARTBEAT_PONG: &str = "👀";
+    fn beat(&self) {
+        self.last
+            .store(self.since.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+
+    fn idle(&self) -> Duration {
+        self.since
+            .elapsed()
+            .saturating_sub(Duration::from_millis(self.last.load(Ordering::Relaxed)))
+    }
+}
+
+/// What a broadcast is, for matching against subscriptions
+#[derive(Debug, Clone)]
+pub struct Tag {
+    /// Payload variant name
+    pub kind: Option<Arc<str>>,
+    pub platform: Platform,
+}
+
+impl Subscription {
+    fn matches(&self, tag: &Tag) -> bool {
+        let kind = self.kinds.is_empty()
+            || tag
+                .kind
+                .as_deref()
+                .is_some_and(|kind| self.kinds.iter().any(|k| k == kind));
+        let platform = self
+            .platforms
+            .is_none_or(|platforms| platforms.intersects(tag.platform));
+        kind && platform
+    }
+}
+
+/// Number of fanout workers, peers are spread across them by address
+const FANOUT_SHARDS: usize = 8;
+/// Messages waiting on a peer's write task. A peer this far behind is dropped, rather than
+/// holding up everyone else in its shard
+const PEER_QUEUE: usize = 32;
 
 /// WS server handles demuxing. It has to keep track of which peer SocketAddr corresponds to which ws_out_tx channel
 /// msg_in_tx is just cloned and shared across all peers as a fan-in channel
@@ -39,18 +131,87 @@ const HEARTBEAT_PONG: &str = "👀";
 ///  ws peer 2 rx  |---------> msg_in_tx -> msg task -> ws_in_rx -------->|  peer 2 tx
 ///  ws peer 3 rx /                                                        \ peer 3 tx
 ///
+/// The demux is split into FANOUT_SHARDS workers, each owning the peers that hash to it,
+/// so a slow peer only holds up its own shard
 #[derive(Clone)]
 pub struct Server {
-    msg_in_tx: mpsc::Sender<(Location, String)>, // <- ws
-    clients: Arc<RwLock<PeerMap>>,               // map sockets to channels
-    disconnect_tx: mpsc::Sender<SocketAddr>,     // receive disconnect events
+    msg_in_tx: mpsc::Sender<(Location, Incoming)>, // <- ws
+    shards: Arc<[Shard]>,                          // map sockets to channels
+    disconnect_tx: mpsc::Sender<SocketAddr>,       // receive disconnect events
+    sessions: Arc<RwLock<SessionMap>>,             // which session each peer logged in with
     auth: auth::Handle,
+    config: &'static ServerConfig,
+}
+
+struct Shard {
+    clients: Arc<RwLock<PeerMap>>,
+    tx: mpsc::Sender<ShardMsg>,
+}
+
+impl Shard {
+    fn new(lag_tx: mpsc::Sender<SocketAddr>) -> Self {
+        let clients = Arc::new(RwLock::new(HashMap::new()));
+        let (tx, rx) = mpsc::channel::<ShardMsg>(32);
+        tokio::spawn(Self::run(rx, clients.clone(), lag_tx));
+        Self { clients, tx }
+    }
+
+    async fn run(
+        mut rx: mpsc::Receiver<ShardMsg>,
+        clients: Arc<RwLock<PeerMap>>,
+        lag_tx: mpsc::Sender<SocketAddr>,
+    ) {
+        while let Some((dest_addrs, tag, msg)) = rx.recv().await {
+            // snapshot the senders, don't hold the lock across awaits
+            let txs: Vec<(SocketAddr, mpsc::Sender<Frame>, Codec)> = {
+                let clients = clients.read();
+                match dest_addrs {
+                    Some(addrs) => addrs
+                        .iter()
+                        .filter_map(|addr| {
+                            clients
+                                .get(addr)
+                                .map(|(tx, _, codec)| (*addr, tx.clone(), *codec))
+                        })
+                        .collect(),
+                    // only broadcasts are filtered
+                    None => clients
+                        .iter()
+                        .filter(|(_, (_, sub, _))| sub.as_ref().is_none_or(|sub| sub.matches(&tag)))
+                        .map(|(addr, (tx, _, codec))| (*addr, tx.clone(), *codec))
+                        .collect(),
+                }
+            };
+
+            // encoded once for each codec in use, not for every peer
+            let mut frames: Vec<(Codec, Frame)> = vec![];
+            for (_, _, codec) in &txs {
+                if frames.iter().any(|(c, _)| c == codec) {
+                    continue;
+                }
+                match codec.encode_blocking(msg.clone()).await {
+                    Ok(frame) => frames.push((*codec, Arc::new(frame))),
+                    Err(e) => tracing::error!(codec = ?codec, "{}", e),
+                }
+            }
+
+            let lagging = Server::send_mult(&frames, &txs);
+            if lagging.is_empty() {
+                continue;
+            }
+            // nothing more is queued for them while they're kicked
+            let mut clients = clients.write();
+            for addr in lagging {
+                clients.remove(&addr);
+                let _ = lag_tx.try_send(addr);
+            }
+        }
+    }
 }
 
 #[derive(Debug)]
 pub enum WsError {
     Parse(&'static str),
-    MissingHeader(&'static str),
     CorsMissingOrigin,
     CorsInvalidOrigin { origin: String },
 }
@@ -63,103 +224,313 @@ impl std::fmt::Display for WsError {
 
 // TODO: state machine for handling auth
 impl Server {
+    fn shard_idx(addr: &SocketAddr) -> usize {
+        let mut hasher = DefaultHasher::new();
+        addr.hash(&mut hasher);
+        (hasher.finish() as usize) % FANOUT_SHARDS
+    }
+
+    /// Route messages to the shards owning their destination peers
     #[tracing::instrument(skip_all)]
-    async fn fanout(mut ws_in_rx: mpsc::Receiver<Msg>, clients: Arc<RwLock<PeerMap>>) {
-        while let Some((dest_addrs, msg)) = ws_in_rx.recv().await {
+    async fn fanout(mut ws_in_rx: mpsc::Receiver<Msg>, shards: Arc<[Shard]>) {
+        while let Some((dest_addrs, tag, msg)) = ws_in_rx.recv().await {
             if let Some(addrs) = dest_addrs {
-                match addrs[..] {
-                    // slice pattern for a single elem
-                    [ref addr] => {
-                        let (_username, addr) = addr;
-                        let client = clients.read().get(addr).cloned();
-                        if let Some(tx) = client {
-                            let _ = tx.send(msg).await;
-                        }
-                    }
-                    _ => {
-                        // filter and send
-                        let clients: PeerMap = clients.read().clone();
-                        Self::send_mult(
-                            msg,
-                            addrs
-                                .iter()
-                                .filter_map(|(_username, addr)| clients.get(addr)),
-                        )
-                        .await
+                // group destinations by shard
+                let mut by_shard: Vec<Vec<SocketAddr>> = vec![vec![]; FANOUT_SHARDS];
+                for (_username, addr) in addrs {
+                    by_shard[Self::shard_idx(&addr)].push(addr);
+                }
+                for (shard, addrs) in shards.iter().zip(by_shard) {
+                    if !addrs.is_empty() {
+                        let _ = shard.tx.send((Some(addrs), tag.clone(), msg.clone())).await;
                     }
-                };
+                }
             } else {
-                let clients: PeerMap = clients.read().clone();
-                Self::send_mult(msg, clients.iter().map(|(_, tx)| tx)).await;
+                for shard in shards.iter() {
+                    let _ = shard.tx.send((None, tag.clone(), msg.clone())).await;
+                }
             }
         }
     }
 
     pub fn new(
-        msg_in_tx: mpsc::Sender<(Location, String)>, /* <- ws */
-        ws_in_rx: mpsc::Receiver<Msg>,               /* -> ws */
+        msg_in_tx: mpsc::Sender<(Location, Incoming)>, /* <- ws */
+        ws_in_rx: mpsc::Receiver<Msg>,                 /* -> ws */
+        revoke_rx: mpsc::Receiver<Arc<String>>,        /* revoked session ids */
+        subscribe_rx: mpsc::Receiver<(SocketAddr, Subscription)>, /* peer subscriptions */
+        list_rx: mpsc::Receiver<oneshot::Sender<Vec<Connection>>>, /* connection listings */
         auth: auth::Handle,
+        config: &'static ServerConfig,
     ) -> Self {
-        let clients = Arc::new(RwLock::new(HashMap::new()));
+        let (lag_tx, lag_rx) = mpsc::channel::<SocketAddr>(32);
+        let shards: Arc<[Shard]> = (0..FANOUT_SHARDS)
+            .map(|_| Shard::new(lag_tx.clone()))
+            .collect();
+        let sessions = Arc::new(RwLock::new(HashMap::new()));
         let (disconnect_tx, disconnect_rx) = mpsc::channel::<SocketAddr>(32);
 
         // spawn task to handle disconnects
-        tokio::spawn(Self::disconnect(clients.clone(), disconnect_rx));
+        tokio::spawn(Self::disconnect(
+            shards.clone(),
+            sessions.clone(),
+            disconnect_rx,
+        ));
+
+        // spawn task to kick peers whose session was revoked
+        tokio::spawn(Self::revoke(
+            sessions.clone(),
+            disconnect_tx.clone(),
+            revoke_rx,
+        ));
+
+        // spawn task to set what peers get broadcast
+        tokio::spawn(Self::subscribe(shards.clone(), subscribe_rx));
+
+        // spawn task to kick peers that fell too far behind
+        tokio::spawn(Self::kick_lagging(
+            sessions.clone(),
+            disconnect_tx.clone(),
+            lag_rx,
+        ));
+
+        // spawn task to list connections
+        tokio::spawn(Self::list(sessions.clone(), list_rx));
+
+        // spawn task to drop peers that stopped pinging
+        if let Some(timeout) = config.ws_idle_timeout {
+            tokio::spawn(Self::reap(sessions.clone(), disconnect_tx.clone(), timeout));
+        }
 
         // fan out ws_in_rx to all clients
-        tokio::spawn(Self::fanout(ws_in_rx, clients.clone()));
+        tokio::spawn(Self::fanout(ws_in_rx, shards.clone()));
 
         Self {
-            clients,
+            shards,
             disconnect_tx,
+            sessions,
             msg_in_tx,
             auth,
+            config,
         }
     }
 
     async fn disconnect(
-        clients: Arc<RwLock<PeerMap>>,
+        shards: Arc<[Shard]>,
+        sessions: Arc<RwLock<SessionMap>>,
         mut disconnect_rx: mpsc::Receiver<SocketAddr>,
     ) {
         while let Some(addr) = disconnect_rx.recv().await {
-            clients.write().remove(&addr);
+            shards[Self::shard_idx(&addr)].clients.write().remove(&addr);
+            sessions.write().remove(&addr);
             tracing::debug!("removed {} from clients", addr);
         }
     }
 
-    async fn send_mult<'a, M, I>(msg: M, clients: I)
-    where
-        M: 'a + Clone,
-        I: Iterator<Item = &'a mpsc::Sender<M>>,
-    {
-        tracing::debug!(
-            "\x1b[33mSending to approx {:?} ws peers\x1b[0m",
-            clients.size_hint()
-        );
-        for tx in clients {
-            let _ = tx.send(msg.clone()).await;
+    /// Close and forget the revoked session's peers. Like reaping, their read tasks are stopped
+    /// here, so nothing more they send gets through while waiting on the close to be acked
+    async fn revoke(
+        sessions: Arc<RwLock<SessionMap>>,
+        disconnect_tx: mpsc::Sender<SocketAddr>,
+        mut revoke_rx: mpsc::Receiver<Arc<String>>,
+    ) {
+        while let Some(id) = revoke_rx.recv().await {
+            let kicked: Vec<(SocketAddr, Conn)> = {
+                let mut sessions = sessions.write();
+                let addrs: Vec<SocketAddr> = sessions
+                    .iter()
+                    .filter(|(_, conn)| conn.session == id)
+                    .map(|(addr, _)| *addr)
+                    .collect();
+                addrs
+                    .into_iter()
+                    .filter_map(|addr| Some((addr, sessions.remove(&addr)?)))
+                    .collect()
+            };
+
+            for (addr, conn) in kicked {
+                tracing::info!(session = id.as_str(), "\x1b[91mkicking {}\x1b[0m", addr);
+                Self::kick(
+                    addr,
+                    conn,
+                    CloseCode::Policy,
+                    "session revoked",
+                    &disconnect_tx,
+                )
+                .await;
+            }
+        }
+    }
+
+    async fn kick_lagging(
+        sessions: Arc<RwLock<SessionMap>>,
+        disconnect_tx: mpsc::Sender<SocketAddr>,
+        mut lag_rx: mpsc::Receiver<SocketAddr>,
+    ) {
+        while let Some(addr) = lag_rx.recv().await {
+            let conn = sessions.write().remove(&addr);
+            if let Some(conn) = conn {
+                tracing::info!(
+                    peer = %addr,
+                    username = conn.username.as_str(),
+                    "\x1b[91mkicking peer that fell behind\x1b[0m"
+                );
+                Self::kick(
+                    addr,
+                    conn,
+                    CloseCode::Again,
+                    "too far behind",
+                    &disconnect_tx,
+                )
+                .await;
+            }
         }
     }
 
+    /// Close a peer already taken out of the session map. Its read task is stopped here,
+    /// a peer that's gone quiet or is being kicked may never ack the close
+    async fn kick(
+        addr: SocketAddr,
+        conn: Conn,
+        code: CloseCode,
+        reason: &'static str,
+        disconnect_tx: &mpsc::Sender<SocketAddr>,
+    ) {
+        if let Some(reader) = conn.reader {
+            reader.abort();
+        }
+        let _ = conn.kick_tx.try_send(CloseFrame {
+            code,
+            reason: reason.into(),
+        });
+        // dropping its channel from the shard stops the write task, if the close didn't
+        let _ = disconnect_tx.send(addr).await;
+    }
+
+    async fn list(
+        sessions: Arc<RwLock<SessionMap>>,
+        mut list_rx: mpsc::Receiver<oneshot::Sender<Vec<Connection>>>,
+    ) {
+        while let Some(tx) = list_rx.recv().await {
+            let mut conns: Vec<Connection> = sessions
+                .read()
+                .iter()
+                .map(|(addr, conn)| Connection {
+                    peer: *addr,
+                    username: conn.username.clone(),
+                    role: conn.role,
+                    connected_at: conn.connected_at,
+                    idle: conn.heartbeat.idle().as_secs(),
+                })
+                .collect();
+            conns.sort_by_key(|c| c.connected_at);
+            let _ = tx.send(conns);
+        }
+    }
+
+    /// Close and forget peers not heard from in `timeout`.
+    /// A dead peer never acks the close, so its read task is stopped here instead of cleaning up after itself
+    async fn reap(
+        sessions: Arc<RwLock<SessionMap>>,
+        disconnect_tx: mpsc::Sender<SocketAddr>,
+        timeout: Duration,
+    ) {
+        let mut interval = tokio::time::interval((timeout / 3).max(Duration::from_secs(1)));
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+
+            let idle: Vec<(SocketAddr, Duration, Conn)> = {
+                let mut sessions = sessions.write();
+                let addrs: Vec<SocketAddr> = sessions
+                    .iter()
+                    .filter(|(_, conn)| conn.heartbeat.idle() > timeout)
+                    .map(|(addr, _)| *addr)
+                    .collect();
+                addrs
+                    .into_iter()
+                    .filter_map(|addr| {
+                        let conn = sessions.remove(&addr)?;
+                        Some((addr, conn.heartbeat.idle(), conn))
+                    })
+                    .collect()
+            };
+
+            for (addr, idle, conn) in idle {
+                tracing::info!(
+                    peer = %addr,
+                    username = conn.username.as_str(),
+                    idle = idle.as_secs(),
+                    "\x1b[91mreaping idle connection\x1b[0m"
+                );
+                Self::kick(
+                    addr,
+                    conn,
+                    CloseCode::Away,
+                    "heartbeat timeout",
+                    &disconnect_tx,
+                )
+                .await;
+            }
+        }
+    }
+
+    async fn subscribe(
+        shards: Arc<[Shard]>,
+        mut subscribe_rx: mpsc::Receiver<(SocketAddr, Subscription)>,
+    ) {
+        while let Some((addr, sub)) = subscribe_rx.recv().await {
+            let mut clients = shards[Self::shard_idx(&addr)].clients.write();
+            if let Some((_, peer_sub, _)) = clients.get_mut(&addr) {
+                tracing::info!(peer = %addr, sub = ?sub, "subscribed");
+                // the default lets everything through, same as never subscribing
+                *peer_sub = Some(sub)
+                    .filter(|s| *s != Subscription::default())
+                    .map(Arc::new);
+            }
+        }
+    }
+
+    /// Queue each peer its codec's frame without waiting on any of them, returning those whose
+    /// queues are full
+    fn send_mult(
+        frames: &[(Codec, Frame)],
+        clients: &[(SocketAddr, mpsc::Sender<Frame>, Codec)],
+    ) -> Vec<SocketAddr> {
+        tracing::debug!("\x1b[33mSending to {} ws peers\x1b[0m", clients.len());
+        clients
+            .iter()
+            .filter_map(|(addr, tx, codec)| {
+                let (_, frame) = frames.iter().find(|(c, _)| c == codec)?;
+                match tx.try_send(frame.clone()) {
+                    Err(mpsc::error::TrySendError::Full(_)) => Some(*addr),
+                    // closed ones are already being cleaned up
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
     #[tracing::instrument(skip_all)]
     async fn auth(
-        ws_stream: WebSocketStream<TcpStream>,
+        ws_stream: WebSocketStream<Stream>,
         auth: &auth::Handle,
         peer_ip: String,
-    ) -> error::Result<Option<(Arc<String>, WebSocketStream<TcpStream>)>> {
+        codec: Codec,
+    ) -> error::Result<Option<Authed>> {
         let (mut ws_sink, mut ws_source) = ws_stream.split();
 
         while let Some(Ok(msg)) = ws_source.next().await {
-            let msg = if msg.is_text() || msg.is_binary() {
-                if let Ok(msg) = msg.into_text() {
-                    msg
-                } else {
+            if msg.is_close() {
+                return Ok(None);
+            }
+
+            let msg = match codec.decode(msg) {
+                Ok(Some(msg)) => msg,
+                Ok(None) => continue,
+                Err(e) => {
+                    tracing::error!("{}", e);
                     continue;
                 }
-            } else if msg.is_close() {
-                return Ok(None);
-            } else {
-                continue;
             };
 
             if msg == HEARTBEAT_PING {
@@ -199,15 +570,34 @@ impl Server {
                 Ok(r) => r,
             };
 
+            // only tell the peer it's in once there's a session to hold it
+            let mut session = None;
+            let resp = match resp {
+                AuthResp::AuthSuccess(user) => match auth.new_session(&user, &peer_ip).await {
+                    Ok(id_role) => {
+                        session = Some(id_role);
+                        AuthResp::AuthSuccess(user)
+                    }
+                    Err(e) => {
+                        tracing::error!("{}", e);
+                        AuthResp::AuthError(AuthError::ServerError)
+                    }
+                },
+                r => r,
+            };
+
             //let auth_success = resp == AuthResp::AuthSuccess;
 
             let res = tokio::task::spawn_blocking(move || {
-                (serde_json::to_string::<AuthResp>(&resp), resp)
+                let encoded = serde_json::to_string::<AuthResp>(&resp)
+                    .map_err(error::Error::from)
+                    .and_then(|resp_str| codec.encode(&resp_str));
+                (encoded, resp)
             })
             .await;
 
-            let (resp_str, resp) = match res {
-                Ok((Ok(resp_str), resp)) => (resp_str, resp),
+            let (resp_msg, resp) = match res {
+                Ok((Ok(resp_msg), resp)) => (resp_msg, resp),
                 Ok((Err(e), resp)) => {
                     tracing::error!("{:?}, orig resp: {:?}", e, resp);
                     continue;
@@ -218,49 +608,84 @@ impl Server {
                 }
             };
 
-            let _ = ws_sink.send(Message::Text(resp_str)).await;
+            let _ = ws_sink.send(resp_msg).await;
 
-            if let AuthResp::AuthSuccess(user) = resp {
+            if let (AuthResp::AuthSuccess(user), Some((session, role))) = (resp, session) {
                 // from this point on, conn is authenticated
                 let ws_stream = ws_sink.reunite(ws_source)?;
-                return Ok(Some((user, ws_stream)));
+                return Ok(Some((user, session, role, ws_stream)));
             }
         }
 
         Ok(None)
     }
 
-    #[tracing::instrument(skip(ws_receiver, msg_in_tx, disconnect_tx, hb_tx))]
+    #[tracing::instrument(skip(ws_receiver, msg_in_tx, disconnect_tx, hb, limiter))]
     async fn ws_read(
-        peer: SocketAddr,
-        ws_receiver: SplitStream<WebSocketStream<TcpStream>>,
-        msg_in_tx: mpsc::Sender<(Location, String)>,
+        (peer, username, role): (SocketAddr, Arc<String>, Role),
+        ws_receiver: SplitStream<WebSocketStream<Stream>>,
+        msg_in_tx: mpsc::Sender<(Location, Incoming)>,
         disconnect_tx: mpsc::Sender<SocketAddr>,
-        hb_tx: mpsc::Sender<()>,
-        username: Arc<String>,
+        hb: (mpsc::Sender<()>, Arc<Heartbeat>), // pongs, and when the peer was last heard from
+        mut limiter: Limiter,
+        codec: Codec,
     ) {
         tracing::debug!("starting read task");
-        // filter non-text or binary messages
+        // filter non-text or binary messages, and any that don't decode
         let filtered = ws_receiver.try_filter_map(|msg| async move {
-            if msg.is_text() || msg.is_binary() {
-                Ok(msg.into_text().ok())
-            } else {
-                Ok(None)
+            match codec.decode(msg) {
+                Ok(msg) => Ok(msg),
+                Err(e) => {
+                    tracing::error!("{}", e);
+                    Ok(None)
+                }
             }
         });
 
+        let (hb_tx, heartbeat) = hb;
+
         // pin stream future
         pin_mut!(filtered);
 
         // ws -> msg task
         while let Some(Ok(msg)) = filtered.next().await {
+            heartbeat.beat();
             if msg == HEARTBEAT_PING {
                 let _ = hb_tx.send(()).await;
-            } else {
-                // wrap with location
-                let msg = (Location::Websocket(username.clone(), peer), msg);
-                if msg_in_tx.send(msg).await.is_err() {
-                    break;
+                continue;
+            }
+
+            // parsed here and not again, so it's limited by what it really is
+            let parsed = tokio::task::spawn_blocking(move || {
+                serde_json::from_str::<msg::Message>(&msg).map_err(|e| (msg, e))
+            })
+            .await;
+            let parsed = match parsed {
+                Ok(parsed) => parsed,
+                Err(e) => {
+                    tracing::error!("{}", e);
+                    continue;
+                }
+            };
+            if !limiter
+                .allow(parsed.as_ref().ok().map(|msg| &msg.payload))
+                .await
+            {
+                continue;
+            }
+            match parsed {
+                Ok(msg) => {
+                    // wrap with location
+                    let msg = (
+                        Location::Websocket(username.clone(), peer, role),
+                        Incoming::Parsed(msg),
+                    );
+                    if msg_in_tx.send(msg).await.is_err() {
+                        break;
+                    }
+                }
+                Err((orig_msg, e)) => {
+                    tracing::error!(orig_msg = ?orig_msg, "INVALID MSG: {}", e);
                 }
             }
         }
@@ -271,17 +696,25 @@ impl Server {
         let _ = disconnect_tx.send(peer).await;
     }
 
-    fn real_ip(real_ip: &mut Option<IpAddr>, headers: &HeaderMap) -> error::Result<()> {
-        let hv = if let Some(hv) = headers.get("x-real-ip") {
-            hv
-        } else {
-            headers
+    /// The address a trusted proxy says the peer has. Anyone can set the headers, so they're
+    /// only believed from WS_TRUSTED_PROXIES. Each proxy appends to x-forwarded-for, so the last
+    /// address in it is the one ours saw
+    fn real_ip(trusted: &[IpAddr], peer: IpAddr, headers: &HeaderMap) -> Option<IpAddr> {
+        if !trusted.contains(&peer) {
+            return None;
+        }
+        let ip = match headers.get("x-real-ip") {
+            Some(hv) => hv.to_str().ok(),
+            None => headers
                 .get("x-forwarded-for")
-                .ok_or(WsError::MissingHeader("x-forwarded-for"))? // fallback
+                .and_then(|hv| hv.to_str().ok())
+                .and_then(|hv| hv.rsplit(',').next()),
         };
-        let ip = hv.to_str()?.parse::<IpAddr>()?;
-        real_ip.replace(ip);
-        Ok(())
+        let ip = ip.and_then(|ip| ip.trim().parse().ok());
+        if ip.is_none() {
+            tracing::warn!(proxy = %peer, "trusted proxy didn't say who the peer is");
+        }
+        ip
     }
 
     #[tracing::instrument(skip_all)]
@@ -332,11 +765,17 @@ impl Server {
 
     // TODO: should not be infallible
     #[tracing::instrument(skip_all, fields(peer))]
-    async fn new_conn(&self, peer: SocketAddr, stream: TcpStream) {
+    async fn new_conn(&self, peer: SocketAddr, stream: Stream) {
         let mut real_ip: Option<IpAddr> = None;
+        let mut codec = Codec::default();
 
         let ws_stream = accept_hdr_async(stream, |req: &Request, mut res: Response| {
             let headers = req.headers();
+            if let Some((negotiated, protocol)) = Codec::negotiate(headers) {
+                codec = negotiated;
+                res.headers_mut()
+                    .insert("sec-websocket-protocol", HeaderValue::from_static(protocol));
+            }
             if let Err(e) = Self::cors(headers, &mut res) {
                 tracing::error!("{}", e);
                 return Err(Response::builder()
@@ -344,19 +783,13 @@ impl Server {
                     .body(None)
                     .unwrap());
             }
-            if let Err(e) = Self::real_ip(&mut real_ip, headers) {
-                tracing::error!("{}", e);
-                return Err(Response::builder()
-                    .status(StatusCode::INTERNAL_SERVER_ERROR)
-                    .body(None)
-                    .unwrap());
-            }
+            real_ip = Self::real_ip(&self.config.ws_trusted_proxies, peer.ip(), headers);
             Ok(res)
         })
         .await
         .expect("Failed to accept");
 
-        // replace peer with real peer if available
+        // replace peer with the proxied peer if there is one
         let peer = if let Some(ip) = real_ip {
             SocketAddr::from((ip, peer.port()))
         } else {
@@ -364,19 +797,19 @@ impl Server {
         };
 
         tracing::Span::current().record("peer", &&*peer.to_string());
-        tracing::debug!("\x1b[93mnew ws connection, waiting for auth\x1b[0m");
+        tracing::debug!(codec = ?codec, "\x1b[93mnew ws connection, waiting for auth\x1b[0m");
 
         // wait till auth completes
-        let auth_resp = Self::auth(ws_stream, &self.auth, peer.ip().to_string()).await;
+        let auth_resp = Self::auth(ws_stream, &self.auth, peer.ip().to_string(), codec).await;
 
-        let (username, ws_stream) = match auth_resp {
+        let (username, session, role, ws_stream) = match auth_resp {
             Err(e) => {
                 tracing::error!("{}", e);
                 return;
             }
-            Ok(Some((user, ws))) => {
-                tracing::info!("\x1b[92mAuth success\x1b[0m");
-                (user, ws)
+            Ok(Some((user, session, role, ws))) => {
+                tracing::info!(role = ?role, "\x1b[92mAuth success\x1b[0m");
+                (user, session, role, ws)
             }
             Ok(_) => {
                 tracing::info!("\x1b[91mAuth failed\x1b[0m");
@@ -386,7 +819,7 @@ impl Server {
 
         let (mut ws_sender, ws_receiver) = ws_stream.split();
 
-        let (ws_in_tx, mut ws_chan) = mpsc::channel::<Arc<String>>(32);
+        let (ws_in_tx, mut ws_chan) = mpsc::channel::<Frame>(PEER_QUEUE);
 
         let disconnect_tx = self.disconnect_tx.clone();
         let msg_in_tx = self.msg_in_tx.clone();
@@ -394,11 +827,32 @@ impl Server {
         // heartbeat channel
         let (hb_tx, mut hb_rx) = mpsc::channel::<()>(32);
 
+        // closes the conn if its session is revoked or it goes quiet
+        let (kick_tx, mut kick_rx) = mpsc::channel::<CloseFrame<'static>>(1);
+        let heartbeat = Arc::new(Heartbeat::new());
+        let connected_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        self.sessions.write().insert(
+            peer,
+            Conn {
+                username: username.clone(),
+                session,
+                role,
+                kick_tx,
+                heartbeat: heartbeat.clone(),
+                connected_at,
+                reader: None,
+            },
+        );
+
         //add (peer, ws_in_tx) to self.clients
         // add first before starting
-        let clients = self.clients.clone();
+        let clients = self.shards[Self::shard_idx(&peer)].clients.clone();
+        let limiter = Limiter::new(self.config, ws_in_tx.clone(), codec);
         tokio::task::spawn_blocking(move || {
-            clients.write().insert(peer, ws_in_tx);
+            clients.write().insert(peer, (ws_in_tx, None, codec));
             tracing::debug!("added {} to clients", peer);
         })
         .await
@@ -406,14 +860,18 @@ impl Server {
 
         // spawn task to read from ws
         // aborts when peer's incoming stream closes
-        let _ = tokio::spawn(Self::ws_read(
-            peer,
+        let reader = tokio::spawn(Self::ws_read(
+            (peer, username, role),
             ws_receiver,
             msg_in_tx,
             disconnect_tx,
-            hb_tx,
-            username,
+            (hb_tx, heartbeat),
+            limiter,
+            codec,
         ));
+        if let Some(conn) = self.sessions.write().get_mut(&peer) {
+            conn.reader = Some(reader.abort_handle());
+        }
 
         // spawn task to write to ws
         // aborts when ws_in_tx is dropped from the peer map
@@ -423,10 +881,17 @@ impl Server {
                     _ = hb_rx.recv() => {
                       let _ = ws_sender.send(HEARTBEAT_PONG.into()).await;
                     }
-                    msg = ws_chan.recv() => {
-                        match msg {
-                          Some(msg) => {
-                            if (ws_sender.send((&*msg).to_owned().into()).await).is_err() {
+                    Some(frame) = kick_rx.recv() => {
+                        // the read task cleans up once the peer acks the close
+                        let _ = ws_sender.send(Message::Close(Some(frame))).await;
+                        break;
+                    }
+                    frame = ws_chan.recv() => {
+                        match frame {
+                          Some(frame) => {
+                            // only copied if other peers still have it
+                            let frame = Arc::try_unwrap(frame).unwrap_or_else(|f| (*f).clone());
+                            if (ws_sender.send(frame).await).is_err() {
                                 break;
                             }
                         },
@@ -444,24 +909,46 @@ impl Server {
     pub async fn start(self) {
         tracing::info!("\x1b[92m------------Starting websocket loop------------\x1b[0m");
 
-        let listener = TcpListener::bind(&*crate::WS_BIND)
-            .await
-            .expect("Can't listen");
+        let addr = self.config.ws_bind;
+        let listener = TcpListener::bind(addr).await.expect("Can't listen");
+
+        let tls = self
+            .config
+            .tls
+            .as_ref()
+            .map(|tls| tls::acceptor(tls).expect("Can't load TLS cert/key"));
 
         // spawn task to accept new ws conns
         // aborts when listener closes
         // in which case it'll drop self
+        let secure = tls.is_some();
         tokio::spawn(async move {
             loop {
                 if let Ok((stream, peer)) = listener.accept().await {
                     let server = self.clone();
+                    let tls = tls.clone();
                     tokio::spawn(async move {
+                        let stream = match tls {
+                            Some(acceptor) => {
+                                let hosts = server.config.tls.as_ref().map(|t| &t.hosts[..]);
+                                match tls::accept(&acceptor, stream, hosts.unwrap_or_default())
+                                    .await
+                                {
+                                    Ok(stream) => stream,
+                                    Err(e) => {
+                                        tracing::warn!(peer = %peer, "TLS handshake failed: {}", e);
+                                        return;
+                                    }
+                                }
+                            }
+                            None => Stream::Plain(stream),
+                        };
                         server.new_conn(peer, stream).await;
                     });
                 }
             }
         });
 
-        tracing::info!(addr = %&*crate::WS_BIND, "listening");
+        tracing::info!(addr = %addr, tls = secure, "listening");
     }
 }
@@ -0,0 +1,152 @@
+//! Per-peer limits on what's sent over websockets, so one client flooding DumpLog
+//! can't starve the msg task for everyone else
+
+use super::{Codec, Frame};
+use crate::{
+    config::{RateLimit, ServerConfig},
+    msg::{Payload, Platform, Response},
+};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::mpsc;
+
+/// Requests that take a lot of work to answer, e.g. reading back the whole log
+fn is_expensive(payload: &Payload) -> bool {
+    matches!(
+        payload,
+        Payload::DumpConfig
+            | Payload::DumpSchema
+            | Payload::DumpSchemaIf(_)
+            | Payload::DumpJsonSchema
+            | Payload::DumpLog { .. }
+            | Payload::ExportLog(_)
+            | Payload::DumpModActions
+            | Payload::DumpUsage { .. }
+            | Payload::DumpArgs(_)
+            | Payload::DumpWordlist(_)
+            | Payload::DumpRedemptions
+            | Payload::ImportUsers { .. }
+            | Payload::ExportUsers { .. }
+            | Payload::Backup { .. }
+            | Payload::Restore(_)
+            | Payload::DumpProfiles
+            | Payload::DumpServiceAccounts
+            | Payload::DumpPermMap
+            | Payload::DumpFlags
+            | Payload::ListLocks
+            | Payload::DumpCurrency
+            | Payload::DumpHealth
+            | Payload::DumpDeadLetters
+            | Payload::ReplayDeadLetters
+            | Payload::DumpMemeQueue
+            | Payload::DumpConfigAudit(_)
+            | Payload::DumpChatStats { .. }
+            | Payload::AuditPoints { .. }
+            | Payload::DumpMultipliers
+    )
+}
+
+/// Holds up to `count` tokens, refilled at `count` per `per`
+struct Bucket {
+    tokens: f64,
+    capacity: f64,
+    /// Tokens per second
+    rate: f64,
+    last: Instant,
+}
+
+impl Bucket {
+    fn new(limit: RateLimit) -> Self {
+        let capacity = limit.count as f64;
+        Self {
+            tokens: capacity,
+            capacity,
+            rate: capacity / limit.per.as_secs_f64(),
+            last: Instant::now(),
+        }
+    }
+
+    fn take(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.last = now;
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+/// One peer's buckets. Going over either mutes the peer, with a RateLimited frame to say so
+pub(super) struct Limiter {
+    cheap: Bucket,
+    expensive: Bucket,
+    mute: Duration,
+    muted_until: Option<Instant>,
+    /// The peer's write task
+    reply_tx: mpsc::Sender<Frame>,
+    codec: Codec,
+}
+
+impl Limiter {
+    pub(super) fn new(config: &ServerConfig, reply_tx: mpsc::Sender<Frame>, codec: Codec) -> Self {
+        Self {
+            cheap: Bucket::new(config.ws_rate_limit),
+            expensive: Bucket::new(config.ws_dump_rate_limit),
+            mute: config.ws_mute,
+            muted_until: None,
+            reply_tx,
+            codec,
+        }
+    }
+
+    /// Whether the message should be passed on. Frames that don't parse are None, and
+    /// count as expensive so they can't be sent any faster than dumps
+    pub(super) async fn allow(&mut self, payload: Option<&Payload>) -> bool {
+        let now = Instant::now();
+        match self.muted_until {
+            Some(until) if now < until => return false,
+            Some(_) => self.muted_until = None,
+            None => {}
+        }
+
+        let kind = payload.map(Payload::kind).unwrap_or("invalid");
+        let bucket = match payload.is_none_or(is_expensive) {
+            true => &mut self.expensive,
+            false => &mut self.cheap,
+        };
+        if bucket.take(now) {
+            return true;
+        }
+
+        tracing::warn!(
+            kind,
+            secs = self.mute.as_secs(),
+            "\x1b[91mrate limited, muting\x1b[0m"
+        );
+        self.muted_until = Some(now + self.mute);
+        let warning = Response {
+            platform: Platform::WEB,
+            channel: crate::channel_name(),
+            payload: Payload::RateLimited {
+                kind: kind.into(),
+                secs: self.mute.as_secs(),
+            },
+            corr_id: None,
+        };
+        let warning = match serde_json::to_string(&warning) {
+            Ok(warning) => self.codec.encode(&warning),
+            Err(e) => Err(e.into()),
+        };
+        match warning {
+            Ok(warning) => {
+                let _ = self.reply_tx.send(Arc::new(warning)).await;
+            }
+            Err(e) => tracing::error!("{}", e),
+        }
+        false
+    }
+}
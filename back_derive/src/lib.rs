@@ -3,7 +3,7 @@ use quote::{format_ident, quote, quote_spanned};
 use syn::{
     parse::Parser, parse_macro_input, punctuated::Punctuated, spanned::Spanned, token::Comma,
     visit_mut::VisitMut, Attribute, DeriveInput, Expr, ExprRange, Field, Fields, Ident, ItemStruct,
-    Lit, LitStr, Meta, NestedMeta, RangeLimits, Token,
+    Lit, LitInt, LitStr, Meta, NestedMeta, RangeLimits, Token, Type,
 };
 
 #[derive(Debug, Clone)]
@@ -87,6 +87,12 @@ struct CmdFieldAttr {
     def_value: Option<Lit>,
     def_expr: Option<LitStr>,
     constr: Option<Constraint>,
+    /// Heading the UI shows the key under
+    group: Option<LitStr>,
+    /// Hidden by the UI unless asked for
+    advanced: bool,
+    /// Overrides the key's place in the UI, which is otherwise declaration order
+    order: Option<LitInt>,
 }
 
 fn err(err_cond: bool, spanned: &dyn Spanned, msg: impl Into<String>) -> syn::Result<()> {
@@ -113,6 +119,9 @@ fn parse_cmd_field(f: &Field) -> syn::Result<Option<CmdFieldAttr>> {
     let mut def_value: Option<Lit> = None;
     let mut def_expr: Option<LitStr> = None;
     let mut constr: Option<Constraint> = None;
+    let mut group: Option<LitStr> = None;
+    let mut advanced = false;
+    let mut order: Option<LitInt> = None;
 
     for sub_attr in meta_list.iter() {
         let sub_meta = match sub_attr {
@@ -127,6 +136,8 @@ fn parse_cmd_field(f: &Field) -> syn::Result<Option<CmdFieldAttr>> {
                         skip: true,
                         ..Default::default()
                     }));
+                } else if path.is_ident("advanced") {
+                    advanced = true;
                 } else {
                     return Err(syn::Error::new(path.span(), "invalid attribute"));
                 }
@@ -183,6 +194,30 @@ fn parse_cmd_field(f: &Field) -> syn::Result<Option<CmdFieldAttr>> {
                         }
                     };
                     def_expr = Some(ls.clone());
+                } else if list.path.is_ident("group") {
+                    err(group.is_some(), list, "expected only one `group` attribute")?;
+
+                    match value {
+                        NestedMeta::Lit(Lit::Str(ls)) => group = Some(ls.clone()),
+                        _ => {
+                            return Err(syn::Error::new(
+                                value.span(),
+                                "expected string for `group` attr",
+                            ))
+                        }
+                    }
+                } else if list.path.is_ident("order") {
+                    err(order.is_some(), list, "expected only one `order` attribute")?;
+
+                    match value {
+                        NestedMeta::Lit(Lit::Int(li)) => order = Some(li.clone()),
+                        _ => {
+                            return Err(syn::Error::new(
+                                value.span(),
+                                "expected integer for `order` attr",
+                            ))
+                        }
+                    }
                 } else if list.path.is_ident("constr") {
                     err(
                         list.nested.len() != 1,
@@ -269,6 +304,9 @@ fn parse_cmd_field(f: &Field) -> syn::Result<Option<CmdFieldAttr>> {
         def_value,
         def_expr,
         constr,
+        group,
+        advanced,
+        order,
     }))
 }
 
@@ -339,6 +377,26 @@ fn parse_cmd_struct(meta_list: &Punctuated<NestedMeta, Comma>) -> syn::Result<Op
     }))
 }
 
+/// `T` for an `Option<T>` field, these can be left unset in the config
+fn option_inner(ty: &Type) -> Option<&Type> {
+    let seg = match ty {
+        Type::Path(p) if p.qself.is_none() => p.path.segments.last()?,
+        _ => return None,
+    };
+    if seg.ident != "Option" {
+        return None;
+    }
+    match &seg.arguments {
+        syn::PathArguments::AngleBracketed(args) if args.args.len() == 1 => {
+            match args.args.first()? {
+                syn::GenericArgument::Type(t) => Some(t),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
 // TODO: only yse first doc string as description
 fn doc<'a>(attrs: impl Iterator<Item = &'a Attribute>) -> String {
     let docstrings: Vec<String> = attrs
@@ -374,6 +432,31 @@ fn emit_fn_new<'a>(
         let constr = cmd.constr.clone().unwrap_or_default();
         let constr: proc_macro2::TokenStream = constr.into();
 
+        if let Some(inner) = option_inner(fty) {
+            // Value::None unsets it, constraints only apply to a set value
+            return quote! {
+              if let Some(value) = kv.remove(stringify!(#fname)) {
+                if let crate::cmds::Value::None = value {
+                  cmd.#fname = None;
+                } else {
+                  if !value.verify(#constr) {
+                    println!(concat!("failed verification: ", stringify!(#fname)));
+                    return None;
+                  }
+
+                  match <#inner>::try_from(value) {
+                    Ok(value) => {
+                      cmd.#fname = Some(value);
+                    },
+                    Err(e) => {
+                      ::tracing::warn!(key=stringify!(#fname), cmd=stringify!(#name), name=cmd.name.as_str(), "{}", e)
+                    }
+                  }
+                }
+              }
+            };
+        }
+
         quote! {
           if let Some(value) = kv.remove(stringify!(#fname)) {
             if !value.verify(#constr) {
@@ -396,10 +479,8 @@ fn emit_fn_new<'a>(
 
     let autocorrect = if autocorrect {
         quote! {
-          if !cmd.prefix.is_empty() {
-            // build DFA
-            cmd.levenshtein = Some(crate::cmds::DFAWrapper(crate::cmds::DFA_BUILDER.build_dfa(&cmd.prefix)));
-          }
+          // rebuilt with every config change, which all come through here
+          cmd.levenshtein = crate::cmds::DFAWrapper::build(&cmd.prefix, cmd.autocorrect_distance);
         }
     } else {
         quote! {}
@@ -431,6 +512,10 @@ fn emit_fn_def<'a>(
         };
         let fty = &field.ty;
 
+        if option_inner(fty).is_some() && (cmd.def_value.is_some() || cmd.def_expr.is_some()) {
+            return (syn::Error::new(field.span(), "optional fields default to unset, `def` and `defl` aren't allowed").to_compile_error(), quote! {});
+        }
+
         let field_ts = if let Some(ref def) = cmd.def_value {
             quote! {
               #fname: #def.into()
@@ -487,25 +572,50 @@ fn emit_fns_schema_dump<'a>(
 ) -> proc_macro2::TokenStream {
     let cmd_doc = syn::Lit::new(proc_macro2::Literal::string(cmd_doc));
 
-    let (field_schemas, field_dumps): (Vec<proc_macro2::TokenStream>, Vec<proc_macro2::TokenStream>) = fields.zip(cmd_attrs).flat_map(|(f, cmd)| {
-        if cmd.skip {
-          return None;
-        }
-        let fname = f.ident.as_ref().unwrap();
-        //let fty = &f.ty;
-        let doc_str = doc(f.attrs.iter());
-        let mut fdesc = syn::Lit::new(proc_macro2::Literal::string(&*doc_str));
-        fdesc.set_span(f.span());
-        let constr: proc_macro2::TokenStream = cmd.constr.clone().unwrap_or_default().into();
-        Some((
-            quote! {
-                (stringify!(#fname).to_owned(), #fdesc.to_owned(), crate::cmds::Value::from(cmd.#fname), #constr)
-            },
-            quote! {
-              (stringify!(#fname).to_owned(), crate::cmds::Value::from(self.#fname.clone()))
-            },
-        ))
-    }).unzip();
+    let (field_schemas, field_dumps): (
+        Vec<proc_macro2::TokenStream>,
+        Vec<proc_macro2::TokenStream>,
+    ) = fields
+        .zip(cmd_attrs)
+        .filter(|(_, cmd)| !cmd.skip)
+        .enumerate()
+        .map(|(i, (f, cmd))| {
+            let fname = f.ident.as_ref().unwrap();
+            //let fty = &f.ty;
+            let doc_str = doc(f.attrs.iter());
+            let mut fdesc = syn::Lit::new(proc_macro2::Literal::string(&*doc_str));
+            fdesc.set_span(f.span());
+            let constr: proc_macro2::TokenStream = cmd.constr.clone().unwrap_or_default().into();
+            // an unset default has no type, so optional fields carry the inner type's default instead
+            let (default, optional) = match option_inner(&f.ty) {
+                Some(inner) => (
+                    quote! { crate::cmds::Value::from(<#inner>::default()) },
+                    true,
+                ),
+                None => (quote! { crate::cmds::Value::from(cmd.#fname) }, false),
+            };
+            let group = match cmd.group {
+                Some(ref group) => quote! { Some(#group.to_owned()) },
+                None => quote! { None },
+            };
+            let advanced = cmd.advanced;
+            let order = match cmd.order {
+                Some(ref order) => quote! { #order },
+                None => quote! { #i as u32 },
+            };
+            let hint = quote! {
+                crate::cmds::KeyHint { group: #group, advanced: #advanced, order: #order }
+            };
+            (
+                quote! {
+                    (stringify!(#fname).to_owned(), #fdesc.to_owned(), #default, #constr, #optional, #hint)
+                },
+                quote! {
+                  (stringify!(#fname).to_owned(), crate::cmds::Value::from(self.#fname.clone()))
+                },
+            )
+        })
+        .unzip();
 
     let cmd_type: proc_macro2::TokenStream = cmd_type.into();
 
@@ -553,23 +663,40 @@ fn emit_locks(name: &'_ Ident, locks: Vec<Ident>) -> proc_macro2::TokenStream {
 
     quote! {
       #(
-        pub(crate) static #lock_keys: ::once_cell::sync::Lazy<String> = ::once_cell::sync::Lazy::new(|| format!(concat!("aussiebot_{}_", stringify!(#lock_values)), &*crate::CHANNEL_NAME));
+        pub(crate) static #lock_keys: ::once_cell::sync::Lazy<String> = ::once_cell::sync::Lazy::new(|| format!(concat!("aussiebot_{}_", stringify!(#lock_values)), crate::channel_name()));
       )*
     }
 }
 
 fn emit_fn_args_schema<'a>(
-    mut fields: impl Iterator<Item = &'a Field>,
+    fields: impl Iterator<Item = &'a Field>,
     cmd_doc: &'a str,
 ) -> proc_macro2::TokenStream {
     let cmd_doc = syn::Lit::new(proc_macro2::Literal::string(cmd_doc));
 
-    let has_prefix = fields.any(|field| field.ident.as_ref().unwrap() == "prefix");
-    if !has_prefix {
+    // `prefix`, then any others it answers to, e.g. `redeem_prefix`
+    let mut prefixes: Vec<_> = fields
+        .filter_map(|field| field.ident.as_ref())
+        .filter(|ident| *ident == "prefix" || ident.to_string().ends_with("_prefix"))
+        .collect();
+    if !prefixes.iter().any(|ident| *ident == "prefix") {
         return quote! {};
     }
+    prefixes.sort_by_key(|ident| *ident != "prefix");
 
     quote! {
+      fn prefix(&self) -> Option<&str> {
+        Some(&self.prefix)
+      }
+
+      fn prefixes(&self) -> Vec<&str> {
+        vec![#(self.#prefixes.as_str()),*]
+      }
+
+      fn verbose_errors(&self) -> bool {
+        self.verbose_errors
+      }
+
       fn args_schema(&self, platform: Platform) -> Option<crate::cmds::ArgDump> {
         use crate::cmds::Invokable;
         use crate::cmds::CmdDesc;
@@ -675,7 +802,31 @@ impl VisitMut for AddFields {
             quote! {}
         };
 
-        let old_f = &fields.named;
+        let verbose_errors = if self.prefix {
+            quote! {
+              /// Reply with usage on invalid arguments or insufficient permissions
+              #[cmd(advanced)]
+              verbose_errors: bool,
+            }
+        } else {
+            quote! {}
+        };
+
+        let mut old_f = fields.named.clone();
+        if let Some(i) = old_f
+            .iter()
+            .position(|f| f.ident.as_ref().unwrap() == "autocorrect")
+        {
+            // right after the on/off switch
+            let distance: syn::FieldsNamed = syn::parse_quote! {
+              {
+                /// Max typos to autocorrect (prefixes under 3 characters are never autocorrected)
+                #[cmd(def(2u64), constr(range = "1..=3"), advanced)]
+                autocorrect_distance: u64
+              }
+            };
+            old_f.insert(i + 1, distance.named.into_iter().next().unwrap());
+        }
         let new_f: syn::FieldsNamed = syn::parse_quote! {
           {
             /// Command name
@@ -684,6 +835,12 @@ impl VisitMut for AddFields {
             #levenshtein
             /// Command enabled
             enabled: bool,
+            /// Groups to switch on and off together, e.g. gambling
+            tags: Vec<String>,
+            /// Seconds it gets to handle a message before it's cut off, COMMAND_TIMEOUT if 0
+            #[cmd(def(0u64), constr(range = "0..=600"), advanced)]
+            timeout: u64,
+            #verbose_errors
             #old_f
           }
         };
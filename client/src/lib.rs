@@ -0,0 +1,522 @@
+//! Async client for aussiebot's websocket server, for bots and overlays that want to talk to it
+//! without redoing the login handshake, heartbeats and message framing by hand.
+//!
+//! ```no_run
+//! # async fn run() -> client::Result<()> {
+//! let client = client::AussiebotClient::connect("wss://aussiebot.siid.sh/ws", "https://aussiebot.siid.sh", "channel").await?;
+//! client.request_code("llama").await?;
+//! client.login("llama", "CODE FROM DISCORD").await?;
+//!
+//! let mut chat = client.subscribe_chat();
+//! while let Some(msg) = chat.next().await {
+//!     println!("{:?}", msg.payload);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use back::proto::{
+    auth::{AuthMsg, AuthResp, OAuthProvider},
+    new_corr_id,
+    ws::{HEARTBEAT_PING, HEARTBEAT_PONG},
+};
+use futures_util::{SinkExt, StreamExt};
+use parking_lot::Mutex;
+use serde_json::value::RawValue;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{self, client::IntoClientRequest, http::HeaderValue, Message as WsMessage},
+};
+
+pub use back::proto::{
+    admin::DeadLetter,
+    cmds::{ConfigDump, ConfigPatch, PrefixConflict},
+    db::{UsageRange, UsageStats},
+    Message, Payload, Platform,
+};
+
+/// How often to ping the server. A connection that misses a pong by the next ping is dropped
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+/// How long to wait for the answer to a request, config dumps can be big
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// Server messages buffered per subscriber before the oldest are skipped
+const EVENT_BUFFER: usize = 256;
+
+#[derive(Debug)]
+pub enum Error {
+    Ws(tungstenite::Error),
+    Json(serde_json::Error),
+    /// Login didn't go through
+    Auth(AuthResp),
+    /// Config wasn't saved because commands share prefixes
+    Rejected(Vec<PrefixConflict>),
+    /// Config patch wasn't applied because it sets commands of types that don't exist, (type, name)
+    UnknownCommands(Vec<(String, String)>),
+    /// Config wasn't changed because someone else was changing it
+    Busy,
+    Timeout,
+    /// The connection was closed, or dropped after missing a heartbeat
+    Closed,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Ws(e) => write!(f, "websocket error: {}", e),
+            Error::Json(e) => write!(f, "json error: {}", e),
+            Error::Auth(resp) => write!(f, "login failed: {:?}", resp),
+            Error::Rejected(conflicts) => write!(f, "config rejected: {:?}", conflicts),
+            Error::UnknownCommands(unknown) => write!(f, "unknown commands: {:?}", unknown),
+            Error::Busy => f.write_str("config is busy, try again"),
+            Error::Timeout => f.write_str("timed out waiting for the server"),
+            Error::Closed => f.write_str("connection closed"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<tungstenite::Error> for Error {
+    fn from(e: tungstenite::Error) -> Self {
+        Error::Ws(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Which payloads answer a request
+type Answers = fn(&Payload) -> bool;
+
+/// Requests waiting on the server
+#[derive(Default)]
+struct Pending {
+    /// Auth replies don't carry a correlation id, so only one login step runs at a time
+    auth: Option<oneshot::Sender<AuthResp>>,
+    /// By correlation id
+    requests: HashMap<Arc<String>, (Answers, oneshot::Sender<Message>)>,
+}
+
+/// A connection to the websocket server. Requests can be made concurrently from clones
+#[derive(Clone)]
+pub struct AussiebotClient {
+    channel: Arc<String>,
+    out_tx: mpsc::Sender<String>,
+    pending: Arc<Mutex<Pending>>,
+    /// Kept to hand out new subscriptions, closes when the connection does
+    events: Arc<broadcast::Receiver<Arc<Message>>>,
+    /// Serializes login steps, see [`Pending::auth`]
+    auth_lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl AussiebotClient {
+    /// Connect to the server at `url`. `origin` has to be one the server allows,
+    /// and `channel` the channel it's running for
+    pub async fn connect(url: &str, origin: &str, channel: impl Into<String>) -> Result<Self> {
+        let mut request = url.into_client_request()?;
+        let origin = HeaderValue::from_str(origin)
+            .map_err(|e| Error::Ws(tungstenite::Error::HttpFormat(e.into())))?;
+        request.headers_mut().insert("origin", origin);
+        Self::connect_with(request, channel).await
+    }
+
+    /// Connect with a prepared request, e.g. to set the x-forwarded-for header the server
+    /// expects from its proxy when connecting to it directly
+    pub async fn connect_with(
+        request: impl IntoClientRequest + Unpin,
+        channel: impl Into<String>,
+    ) -> Result<Self> {
+        let (ws_stream, _) = connect_async(request).await?;
+
+        let (out_tx, out_rx) = mpsc::channel(32);
+        let (events_tx, events) = broadcast::channel(EVENT_BUFFER);
+        let pending = Arc::new(Mutex::new(Pending::default()));
+
+        tokio::spawn(run(ws_stream, out_rx, events_tx, pending.clone()));
+
+        Ok(Self {
+            channel: Arc::new(channel.into()),
+            out_tx,
+            pending,
+            events: Arc::new(events),
+            auth_lock: Default::default(),
+        })
+    }
+
+    async fn auth(&self, msg: AuthMsg) -> Result<AuthResp> {
+        let _guard = self.auth_lock.lock().await;
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().auth = Some(tx);
+
+        let res = async {
+            self.send_raw(serde_json::to_string(&msg)?).await?;
+            match tokio::time::timeout(REQUEST_TIMEOUT, rx).await {
+                Ok(Ok(resp)) => Ok(resp),
+                Ok(Err(_)) => Err(Error::Closed),
+                Err(_) => Err(Error::Timeout),
+            }
+        }
+        .await;
+
+        self.pending.lock().auth = None;
+        res
+    }
+
+    /// Users allowed to log in
+    pub async fn list_users(&self) -> Result<Arc<Vec<String>>> {
+        match self.auth(AuthMsg::ListUsers).await? {
+            AuthResp::Users(users) => Ok(users),
+            resp => Err(Error::Auth(resp)),
+        }
+    }
+
+    /// Have a login code sent to the user on Discord
+    pub async fn request_code(&self, user: &str) -> Result<()> {
+        match self
+            .auth(AuthMsg::RequestCode(Arc::new(user.to_owned())))
+            .await?
+        {
+            AuthResp::CodeReady => Ok(()),
+            resp => Err(Error::Auth(resp)),
+        }
+    }
+
+    /// Log in with the code from [`request_code`](Self::request_code).
+    /// Everything else needs this done first
+    pub async fn login(&self, user: &str, code: &str) -> Result<Arc<String>> {
+        let msg = AuthMsg::Login(Arc::new(user.to_owned()), Arc::new(code.to_owned()));
+        match self.auth(msg).await? {
+            AuthResp::AuthSuccess(user) => Ok(user),
+            resp => Err(Error::Auth(resp)),
+        }
+    }
+
+    /// OAuth providers the back server lets users log in with
+    pub async fn oauth_providers(&self) -> Result<Vec<OAuthProvider>> {
+        match self.auth(AuthMsg::ListProviders).await? {
+            AuthResp::Providers(providers) => Ok(providers),
+            resp => Err(Error::Auth(resp)),
+        }
+    }
+
+    /// Where to send the user to log in with the provider
+    pub async fn oauth_start(&self, provider: OAuthProvider) -> Result<String> {
+        match self.auth(AuthMsg::OAuthStart(provider)).await? {
+            AuthResp::OAuthRedirect(url) => Ok(url),
+            resp => Err(Error::Auth(resp)),
+        }
+    }
+
+    /// Log in with the code and state the provider redirected back with, in place of
+    /// [`login`](Self::login)
+    pub async fn oauth_login(
+        &self,
+        provider: OAuthProvider,
+        code: &str,
+        state: &str,
+    ) -> Result<Arc<String>> {
+        let msg = AuthMsg::OAuthLogin {
+            provider,
+            code: Arc::new(code.to_owned()),
+            state: Arc::new(state.to_owned()),
+        };
+        match self.auth(msg).await? {
+            AuthResp::AuthSuccess(user) => Ok(user),
+            resp => Err(Error::Auth(resp)),
+        }
+    }
+
+    async fn send_raw(&self, msg: String) -> Result<()> {
+        self.out_tx.send(msg).await.map_err(|_| Error::Closed)
+    }
+
+    fn message(&self, payload: Payload, corr_id: Arc<String>) -> Message {
+        Message {
+            platform: Platform::WEB,
+            channel: self.channel.to_string(),
+            payload,
+            corr_id: Some(corr_id),
+            dedupe_id: None,
+        }
+    }
+
+    /// Send a payload without waiting for anything back
+    pub async fn send(&self, payload: Payload) -> Result<()> {
+        let msg = serde_json::to_string(&self.message(payload, new_corr_id()))?;
+        self.send_raw(msg).await
+    }
+
+    /// Have the server only send broadcasts of these payload kinds and platforms,
+    /// empty kinds and None get everything again. Replies to requests always come through
+    pub async fn filter_broadcasts(
+        &self,
+        kinds: Vec<String>,
+        platforms: Option<Platform>,
+    ) -> Result<()> {
+        let sub = back::proto::ws::Subscription { kinds, platforms };
+        self.send(Payload::Subscribe(sub)).await
+    }
+
+    /// Send a payload and wait for the first reply to it that `answers` accepts
+    pub async fn request(&self, payload: Payload, answers: Answers) -> Result<Payload> {
+        let corr_id = new_corr_id();
+        let msg = serde_json::to_string(&self.message(payload, corr_id.clone()))?;
+
+        let (tx, rx) = oneshot::channel();
+        self.pending
+            .lock()
+            .requests
+            .insert(corr_id.clone(), (answers, tx));
+
+        let res = async {
+            self.send_raw(msg).await?;
+            match tokio::time::timeout(REQUEST_TIMEOUT, rx).await {
+                Ok(Ok(msg)) => Ok(msg.payload),
+                Ok(Err(_)) => Err(Error::Closed),
+                Err(_) => Err(Error::Timeout),
+            }
+        }
+        .await;
+
+        self.pending.lock().requests.remove(&corr_id);
+        res
+    }
+
+    /// The config schema along with its version
+    pub async fn dump_schema(&self) -> Result<(Arc<String>, Arc<RawValue>)> {
+        let answers: Answers = |p| matches!(p, Payload::SchemaDump { .. });
+        match self.request(Payload::DumpSchema, answers).await? {
+            Payload::SchemaDump { version, schema } => Ok((version, schema)),
+            _ => unreachable!(),
+        }
+    }
+
+    /// The config schema as standard JSON Schema
+    pub async fn dump_json_schema(&self) -> Result<serde_json::Value> {
+        let answers: Answers = |p| matches!(p, Payload::JsonSchemaDump(_));
+        match self.request(Payload::DumpJsonSchema, answers).await? {
+            Payload::JsonSchemaDump(schema) => Ok(schema),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Command usage aggregates over a range
+    pub async fn dump_usage(&self, range: UsageRange) -> Result<UsageStats> {
+        let answers: Answers = |p| matches!(p, Payload::UsageDump(_));
+        match self.request(Payload::DumpUsage { range }, answers).await? {
+            Payload::UsageDump(stats) => Ok(stats),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Responses the server couldn't deliver, oldest first
+    pub async fn dead_letters(&self) -> Result<Vec<DeadLetter>> {
+        let answers: Answers = |p| matches!(p, Payload::DeadLetters(_));
+        match self.request(Payload::DumpDeadLetters, answers).await? {
+            Payload::DeadLetters(letters) => Ok(letters),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Send the dead letters again, answered with any that failed again
+    pub async fn replay_dead_letters(&self) -> Result<Vec<DeadLetter>> {
+        let answers: Answers = |p| matches!(p, Payload::DeadLetters(_));
+        match self.request(Payload::ReplayDeadLetters, answers).await? {
+            Payload::DeadLetters(letters) => Ok(letters),
+            _ => unreachable!(),
+        }
+    }
+
+    /// The commands, filters and timers currently running, with secret fields redacted
+    pub async fn dump_config(&self) -> Result<ConfigDump> {
+        let answers: Answers = |p| matches!(p, Payload::ConfigDump(_));
+        match self.request(Payload::DumpConfig, answers).await? {
+            Payload::ConfigDump(config) => Ok(config),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Replace the running config and save it. Secret fields left redacted are kept as they were
+    pub async fn set_config(&self, config: ConfigDump) -> Result<()> {
+        let answers: Answers = |p| {
+            matches!(
+                p,
+                Payload::ConfigSaved | Payload::ConfigRejected(_) | Payload::ConfigBusy
+            )
+        };
+        match self.request(Payload::ConfigDump(config), answers).await? {
+            Payload::ConfigSaved => Ok(()),
+            Payload::ConfigRejected(conflicts) => Err(Error::Rejected(conflicts)),
+            Payload::ConfigBusy => Err(Error::Busy),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Change only some commands, leaving any others' edits in place
+    pub async fn patch_config(&self, patch: ConfigPatch) -> Result<()> {
+        let answers: Answers = |p| {
+            matches!(
+                p,
+                Payload::ConfigSaved
+                    | Payload::ConfigRejected(_)
+                    | Payload::UnknownCommands(_)
+                    | Payload::ConfigBusy
+            )
+        };
+        match self.request(Payload::PatchConfig(patch), answers).await? {
+            Payload::ConfigSaved => Ok(()),
+            Payload::ConfigRejected(conflicts) => Err(Error::Rejected(conflicts)),
+            Payload::ConfigBusy => Err(Error::Busy),
+            Payload::UnknownCommands(unknown) => Err(Error::UnknownCommands(unknown)),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Every message the server sends that isn't the answer to a request
+    pub fn subscribe(&self) -> Subscription {
+        Subscription {
+            rx: self.events.resubscribe(),
+            filter: |_| true,
+        }
+    }
+
+    /// The bot's chat replies and moderation, as seen by the web UI
+    pub fn subscribe_chat(&self) -> Subscription {
+        Subscription {
+            rx: self.events.resubscribe(),
+            filter: |p| {
+                matches!(
+                    p,
+                    Payload::Message { .. } | Payload::ModAction(..) | Payload::Autocorrect(..)
+                )
+            },
+        }
+    }
+}
+
+/// Messages from the server, see [`AussiebotClient::subscribe`]
+pub struct Subscription {
+    rx: broadcast::Receiver<Arc<Message>>,
+    filter: fn(&Payload) -> bool,
+}
+
+impl Subscription {
+    /// The next message, None once the connection is closed.
+    /// Messages missed by falling too far behind are skipped
+    pub async fn next(&mut self) -> Option<Arc<Message>> {
+        loop {
+            match self.rx.recv().await {
+                Ok(msg) if (self.filter)(&msg.payload) => return Some(msg),
+                Ok(_) => {}
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    tracing::warn!(skipped, "subscriber fell behind");
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+/// Route a text frame to the request waiting on it, or to subscribers
+fn dispatch(text: &str, events: &broadcast::Sender<Arc<Message>>, pending: &Mutex<Pending>) {
+    if let Ok(msg) = serde_json::from_str::<Message>(text) {
+        let waiting = {
+            let mut pending = pending.lock();
+            let answered = msg
+                .corr_id
+                .as_ref()
+                .and_then(|id| pending.requests.get(id))
+                .is_some_and(|(answers, _)| answers(&msg.payload));
+            if answered {
+                pending.requests.remove(msg.corr_id.as_ref().unwrap())
+            } else {
+                None
+            }
+        };
+        match waiting {
+            Some((_, tx)) => {
+                let _ = tx.send(msg);
+            }
+            None => {
+                // nobody listening is fine
+                let _ = events.send(Arc::new(msg));
+            }
+        }
+        return;
+    }
+
+    match serde_json::from_str::<AuthResp>(text) {
+        Ok(resp) => {
+            if let Some(tx) = pending.lock().auth.take() {
+                let _ = tx.send(resp);
+            }
+        }
+        Err(e) => tracing::warn!(msg = text, "unrecognised message: {}", e),
+    }
+}
+
+/// Pump the connection until either end closes it or a heartbeat goes unanswered
+async fn run<S>(
+    ws_stream: tokio_tungstenite::WebSocketStream<S>,
+    mut out_rx: mpsc::Receiver<String>,
+    events: broadcast::Sender<Arc<Message>>,
+    pending: Arc<Mutex<Pending>>,
+) where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    let (mut ws_sink, mut ws_source) = ws_stream.split();
+    let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+    let mut ponged = true;
+
+    loop {
+        tokio::select! {
+            msg = ws_source.next() => match msg {
+                Some(Ok(WsMessage::Text(text))) if text == HEARTBEAT_PONG => ponged = true,
+                Some(Ok(WsMessage::Text(text))) => dispatch(&text, &events, &pending),
+                Some(Ok(WsMessage::Close(frame))) => {
+                    tracing::info!(?frame, "server closed the connection");
+                    break;
+                }
+                Some(Ok(_)) => {}
+                Some(Err(e)) => {
+                    tracing::error!("{}", e);
+                    break;
+                }
+                None => break,
+            },
+            msg = out_rx.recv() => match msg {
+                Some(msg) => {
+                    if let Err(e) = ws_sink.send(WsMessage::Text(msg)).await {
+                        tracing::error!("{}", e);
+                        break;
+                    }
+                }
+                // every handle was dropped
+                None => {
+                    let _ = ws_sink.close().await;
+                    break;
+                }
+            },
+            _ = heartbeat.tick() => {
+                if !ponged {
+                    tracing::warn!("missed heartbeat, dropping connection");
+                    break;
+                }
+                ponged = false;
+                if ws_sink.send(HEARTBEAT_PING.into()).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+
+    // fail anything still waiting
+    *pending.lock() = Pending::default();
+}
@@ -1,22 +1,26 @@
 use crate::msg::CommandCache;
 use back::{
+    channel_name,
     cmds::ArgValue,
+    config,
+    health::{self, Link},
     msg::{
-        self, Chat, ChatMeta, Invocation, InvocationKind, Location, Payload, Permissions, Ping,
-        Platform, Response, StreamEvent, User,
+        self, discord::VoicePresence, Chat, ChatMeta, Invocation, InvocationKind, Location,
+        Payload, Permissions, Ping, Platform, Response, StreamEvent, User,
     },
-    CHANNEL_NAME,
 };
 use once_cell::sync::Lazy;
 use parking_lot::{Mutex, RwLock};
 use regex::Regex;
 use serenity::{
     async_trait,
-    client::{Context, EventHandler},
+    cache::Cache,
+    client::{bridge::gateway::event::ShardStageUpdateEvent, Context, EventHandler},
+    gateway::ConnectionStage,
     http::Http,
     model::{
         self,
-        channel::{Channel, Message},
+        channel::{Channel, ChannelType, Message},
         gateway::{ActivityType, Presence, Ready},
         interactions::{
             application_command::{
@@ -40,32 +44,18 @@ use tokio::sync::{mpsc, oneshot};
 use tracing::info_span;
 use tracing::Instrument;
 
-pub(crate) static OWNER_ID: Lazy<UserId> = Lazy::new(|| {
-    dotenv::var("STREAMER_ID")
-        .unwrap()
-        .parse::<UserId>()
-        .unwrap_or_default()
-});
-static AUSSIEBOT_ID: Lazy<UserId> = Lazy::new(|| {
-    dotenv::var("AUSSIEBOT_ID")
-        .unwrap()
-        .parse::<UserId>()
-        .unwrap_or_default()
-});
-pub(crate) static GUILD_ID: Lazy<GuildId> = Lazy::new(|| {
-    GuildId(
-        dotenv::var("GUILD_ID")
-            .unwrap()
-            .parse::<u64>()
-            .unwrap_or_default(),
-    )
-});
-static MEMBER_ROLE_ID: Lazy<RoleId> = Lazy::new(|| {
-    dotenv::var("MEMBER_ROLE_ID")
-        .unwrap()
-        .parse::<RoleId>()
-        .unwrap_or_default()
-});
+pub(crate) fn owner_id() -> UserId {
+    UserId(config::discord().streamer_id)
+}
+fn bot_id() -> UserId {
+    UserId(config::discord().bot_id)
+}
+pub(crate) fn guild_id() -> GuildId {
+    GuildId(config::discord().guild_id)
+}
+fn member_role_id() -> RoleId {
+    RoleId(config::discord().member_role_id)
+}
 
 const MEE6_ID: UserId = UserId(159985870458322944);
 const EINLLAMA_ID: UserId = UserId(624224573176545288);
@@ -103,6 +93,7 @@ impl Handler {
 impl EventHandler for Handler {
     #[tracing::instrument(skip_all, fields(author, guild))]
     async fn message(&self, ctx: Context, msg: Message) {
+        health::message(Link::Gateway);
         match msg.author.id {
             MEE6_ID => {
                 //println!("{}", Local::now());
@@ -113,7 +104,7 @@ impl EventHandler for Handler {
             EINLLAMA_ID => {
                 self.handle_mee6(&ctx, &msg).await;
             }
-            x if x == *AUSSIEBOT_ID => {
+            x if x == bot_id() => {
                 self.handle_aussiebot(&ctx, &msg);
                 return;
             }
@@ -125,7 +116,7 @@ impl EventHandler for Handler {
 
         if let Some(ref referenced_msg) = msg.referenced_message {
             // check if aussiebot sent the orig msg
-            if referenced_msg.author.id == *AUSSIEBOT_ID {
+            if referenced_msg.author.id == bot_id() {
                 self.handle_reply(&ctx, &msg).await;
             }
         }
@@ -133,7 +124,7 @@ impl EventHandler for Handler {
         #[allow(clippy::match_single_binding)]
         match msg.guild_id {
             _ => {
-                //Some(id) if id == *GUILD_ID => {
+                //Some(id) if id == guild_id() => {
                 // convert Message to Chat
                 let chat = from_message(msg, &ctx).await;
 
@@ -142,7 +133,8 @@ impl EventHandler for Handler {
                 // send ok to dumper
                 Response {
                     platform: Platform::DISCORD,
-                    channel: &*CHANNEL_NAME,
+                    channel: channel_name(),
+                    corr_id: None,
                     payload: Payload::Chat(chat),
                 }
                 .send(Location::Pubsub, &self.msg_out_tx)
@@ -210,7 +202,8 @@ impl EventHandler for Handler {
             // send stream detection event
             let resp_fut = Response {
                 platform: Platform::DISCORD,
-                channel: &*CHANNEL_NAME,
+                channel: channel_name(),
+                corr_id: None,
                 payload: Payload::StreamEvent(StreamEvent::DetectStart(new_url)),
             }
             .send(Location::Pubsub, &self.msg_out_tx);
@@ -255,7 +248,8 @@ impl EventHandler for Handler {
                     // send stream stop event
                     let resp_fut = Response {
                         platform: Platform::DISCORD,
-                        channel: &*CHANNEL_NAME,
+                        channel: channel_name(),
+                        corr_id: None,
                         payload: Payload::StreamEvent(StreamEvent::DetectStop(prev_url)),
                     }
                     .send(Location::Pubsub, &msg_out_tx);
@@ -290,7 +284,8 @@ impl EventHandler for Handler {
                     // send stream detection event
                     let resp_fut = Response {
                         platform: Platform::DISCORD,
-                        channel: &*CHANNEL_NAME,
+                        channel: channel_name(),
+                        corr_id: None,
                         payload: Payload::StreamEvent(StreamEvent::DetectStart(new_url)),
                     }
                     .send(Location::Pubsub, &self.msg_out_tx);
@@ -305,10 +300,12 @@ impl EventHandler for Handler {
     #[tracing::instrument(skip_all)]
     async fn ready(&self, ctx: Context, ready: Ready) {
         tracing::info!("{} is connected!", ready.user.name);
+        health::connected(Link::Gateway, true);
         // announce startup here
         let resp_fut = Response {
             platform: Platform::DISCORD,
-            channel: &*CHANNEL_NAME,
+            channel: channel_name(),
+            corr_id: None,
             payload: Payload::NotifyStart,
         }
         .send(Location::Pubsub, &self.msg_out_tx);
@@ -354,6 +351,20 @@ impl EventHandler for Handler {
         //self.handle_reaction(removed_reaction, false).await;
         //tracing::debug!(_channel_id=%_channel_id, "{:?}", _removed_from_message_id);
     }
+
+    async fn shard_stage_update(&self, _ctx: Context, update: ShardStageUpdateEvent) {
+        tracing::debug!(old = ?update.old, new = ?update.new, "shard stage");
+        health::connected(Link::Gateway, update.new == ConnectionStage::Connected);
+    }
+
+    async fn voice_state_update(&self, ctx: Context, _old: Option<VoiceState>, new: VoiceState) {
+        self.handle_voice_state(&ctx.cache, new).await;
+    }
+
+    async fn cache_ready(&self, ctx: Context, _guilds: Vec<GuildId>) {
+        // anyone already in voice when the bot connected
+        self.sync_voice(&ctx.cache).await;
+    }
 }
 
 fn _parse_opt(opt: &ApplicationCommandInteractionDataOption) -> Option<(String, ArgValue)> {
@@ -384,6 +395,7 @@ fn _parse_opt(opt: &ApplicationCommandInteractionDataOption) -> Option<(String,
                     id: user.id.to_string().into(),
                     name: user.name.clone().into(),
                     perms,
+                    roles: role_ids(maybe_member.as_ref().map(|m| &m.roles)),
                 })
             }
             _ => unimplemented!(),
@@ -393,6 +405,17 @@ fn _parse_opt(opt: &ApplicationCommandInteractionDataOption) -> Option<(String,
     })
 }
 
+/// Name of the option being typed in, which may be nested in a subcommand
+fn _focused_opt(opts: &[ApplicationCommandInteractionDataOption]) -> Option<&str> {
+    opts.iter().find_map(|opt| {
+        if opt.focused {
+            Some(opt.name.as_str())
+        } else {
+            _focused_opt(&opt.options)
+        }
+    })
+}
+
 impl Handler {
     #[tracing::instrument(skip_all, fields(author=command.user.name.as_str()))]
     async fn application_command(&self, command: ApplicationCommandInteraction, http: &Arc<Http>) {
@@ -422,6 +445,7 @@ impl Handler {
             id: command.user.id.to_string().into(),
             name: name.into(),
             perms,
+            roles: role_ids(command.member.as_ref().map(|m| &m.roles)),
         };
 
         let is_dm = command
@@ -443,7 +467,8 @@ impl Handler {
 
         let resp_fut = Response {
             platform: Platform::DISCORD,
-            channel: &*CHANNEL_NAME,
+            channel: channel_name(),
+            corr_id: None,
             payload: Payload::InvokeCommand(Invocation {
                 user: user.into(),
                 cmd: prefix.into(),
@@ -486,6 +511,11 @@ impl Handler {
         let args: HashMap<String, ArgValue> =
             HashMap::from_iter(command.data.options.iter().filter_map(_parse_opt));
 
+        let focused = match _focused_opt(&command.data.options) {
+            Some(name) => name.to_owned(),
+            None => return,
+        };
+
         // send back token as meta
         let perms = perms_from_maybe_member(command.member.as_ref());
 
@@ -495,6 +525,7 @@ impl Handler {
             id: command.user.id.to_string().into(),
             name: name.into(),
             perms,
+            roles: role_ids(command.member.as_ref().map(|m| &m.roles)),
         };
 
         let is_dm = command
@@ -511,7 +542,8 @@ impl Handler {
 
         Response {
             platform: Platform::DISCORD,
-            channel: &*CHANNEL_NAME,
+            channel: channel_name(),
+            corr_id: None,
             payload: Payload::InvokeCommand(Invocation {
                 user: user.into(),
                 cmd: prefix.into(),
@@ -522,7 +554,7 @@ impl Handler {
                     ephemeral,
                     is_dm,
                 )),
-                kind: Some(InvocationKind::Autocomplete),
+                kind: Some(InvocationKind::Autocomplete { focused }),
             }),
         }
         .send(Location::Pubsub, &self.msg_out_tx)
@@ -539,7 +571,8 @@ impl Handler {
 
         Response {
             platform: Platform::DISCORD,
-            channel: &*CHANNEL_NAME,
+            channel: channel_name(),
+            corr_id: None,
             payload: Payload::StreamEvent(StreamEvent::DetectStart(url)),
         }
         .send(Location::Pubsub, &self.msg_out_tx)
@@ -588,20 +621,24 @@ impl Handler {
 
         Response {
             platform,
-            channel: &*CHANNEL_NAME,
+            channel: channel_name(),
+            corr_id: None,
             payload: Payload::Ping(Ping {
+                id: None,
                 pinger: Some((
                     Platform::DISCORD,
                     Arc::new(User {
                         id: Arc::new(pinger_id),
                         name: Arc::new(pinger_nick),
                         perms: Permissions::NONE,
+                        roles: vec![],
                     }),
                 )),
                 pingee: Arc::new(User {
                     id: pingee_id,
                     name: pingee_name,
                     perms: Permissions::NONE,
+                    roles: vec![],
                 }),
                 msg: Some(msg.content_safe(&ctx.cache).into()),
                 meta: None,
@@ -624,6 +661,7 @@ impl Handler {
             id: user_id.into(),
             name: "".to_owned().into(),
             perms: Permissions::NONE,
+            roles: vec![],
         };
 
         let emoji = match reaction.emoji {
@@ -644,7 +682,8 @@ impl Handler {
 
         Response {
             platform: Platform::DISCORD,
-            channel: &*CHANNEL_NAME,
+            channel: channel_name(),
+            corr_id: None,
             payload: Payload::InvokeCommand(Invocation {
                 user: user.into(),
                 cmd,
@@ -660,6 +699,94 @@ impl Handler {
     }
 }
 
+impl Handler {
+    #[tracing::instrument(skip_all, fields(user = %state.user_id))]
+    async fn handle_voice_state(&self, cache: &Cache, state: VoiceState) {
+        if state.guild_id != Some(guild_id()) {
+            return;
+        }
+        let member = match state
+            .member
+            .clone()
+            .or_else(|| cache.member(guild_id(), state.user_id))
+        {
+            Some(member) if !member.user.bot => member,
+            _ => return,
+        };
+        let afk_channel_id = cache
+            .guild_field(guild_id(), |guild| guild.afk_channel_id)
+            .flatten();
+
+        Response {
+            platform: Platform::DISCORD,
+            channel: channel_name(),
+            corr_id: None,
+            payload: Payload::VoicePresence(
+                voice_user(&member),
+                voice_presence(&state, afk_channel_id),
+            ),
+        }
+        .send(Location::Pubsub, &self.msg_out_tx)
+        .await;
+    }
+
+    /// Send everyone in voice, so the backend can catch up on joins and leaves it missed
+    #[tracing::instrument(skip_all)]
+    pub(crate) async fn sync_voice(&self, cache: &Cache) {
+        let present = cache.guild_field(guild_id(), |guild| {
+            guild
+                .voice_states
+                .values()
+                .filter_map(|state| {
+                    let member = guild
+                        .members
+                        .get(&state.user_id)
+                        .or(state.member.as_ref())?;
+                    (!member.user.bot).then(|| {
+                        (
+                            voice_user(member),
+                            voice_presence(state, guild.afk_channel_id),
+                        )
+                    })
+                })
+                .collect::<Vec<_>>()
+        });
+        let present = match present {
+            Some(present) => present,
+            None => {
+                tracing::warn!("guild isn't cached, can't sync voice");
+                return;
+            }
+        };
+
+        tracing::info!(in_voice = present.len(), "syncing voice presence");
+        Response {
+            platform: Platform::DISCORD,
+            channel: channel_name(),
+            corr_id: None,
+            payload: Payload::VoiceSync(present),
+        }
+        .send(Location::Pubsub, &self.msg_out_tx)
+        .await;
+    }
+}
+
+fn voice_user(member: &Member) -> Arc<User> {
+    Arc::new(User {
+        id: member.user.id.to_string().into(),
+        name: member.user.tag().into(),
+        perms: perms_from_maybe_member(Some(member)),
+        roles: role_ids(Some(&member.roles)),
+    })
+}
+
+fn voice_presence(state: &VoiceState, afk_channel_id: Option<ChannelId>) -> VoicePresence {
+    VoicePresence {
+        channel_id: state.channel_id.map(|id| id.to_string().into()),
+        afk: state.channel_id.is_some() && state.channel_id == afk_channel_id,
+    }
+}
+
 static EMOJI_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"<a?:([^<>:]+):(?:\d+)>").unwrap());
 
 #[tracing::instrument(skip_all, ret)]
@@ -669,7 +796,7 @@ async fn from_message(msg: Message, ctx: &Context) -> Chat {
 
     //let _guild_name = msg.guild_field(&ctx.cache, |g| g.name.to_owned());
 
-    let perms = perms_from_msg(&msg, ctx).await;
+    let (perms, roles) = perms_from_msg(&msg, ctx).await;
 
     // let nick = msg
     //     .author_nick(&ctx.http)
@@ -694,24 +821,42 @@ async fn from_message(msg: Message, ctx: &Context) -> Chat {
         .to_channel(&ctx.http)
         .await
         .map(|chan| match chan {
-            Channel::Guild(c) => (c.id, c.name, false),
-            Channel::Private(c) => (c.id, "DMs".into(), /*true*/ false),
-            Channel::Category(c) => (c.id, c.name, false),
+            // threads and forum posts, with their parent and whether it's a forum
+            Channel::Guild(c) if c.thread_metadata.is_some() => {
+                let parent = c.parent_id.map(|parent| {
+                    let is_forum = ctx
+                        .cache
+                        .guild_channel(parent)
+                        .is_some_and(|p| p.kind == ChannelType::Forum);
+                    (parent, is_forum)
+                });
+                (c.id, c.name, false, parent)
+            }
+            Channel::Guild(c) => (c.id, c.name, false, None),
+            Channel::Private(c) => (c.id, "DMs".into(), true, None),
+            Channel::Category(c) => (c.id, c.name, false, None),
             _ => unimplemented!(),
         });
 
-    let channel = channel.map(|(cid, cname, dms)| (cid, Arc::new(cname), dms));
+    let channel = channel.map(|(cid, cname, dms, parent)| (cid, Arc::new(cname), dms, parent));
 
     tracing::info!(channel=?channel, content=%content);
 
     let meta = match (channel, att_data.is_empty(), stk_names.is_empty()) {
-        // (Ok((_cid, _cname, true)), true, true) => {
-        //     Some(ChatMeta::DiscordDM(Arc::new(att_data), Arc::new(stk_names)))
-        // }
-        (Ok((cid, cname, _)), true, true) => Some(ChatMeta::Discord1(cid.into(), cname)),
-        (Ok((cid, cname, _)), false, false)
-        | (Ok((cid, cname, _)), true, false)
-        | (Ok((cid, cname, _)), false, true) => Some(ChatMeta::Discord2(
+        // only commands that allow DMs look at these, so attachments are dropped
+        (Ok((cid, _, true, _)), _, _) => Some(ChatMeta::DirectMessage(cid.into())),
+        (Ok((tid, tname, _, Some((parent, is_forum)))), _, _) => Some(ChatMeta::DiscordThread(
+            tid.into(),
+            tname,
+            parent.into(),
+            is_forum,
+            Arc::new(att_data),
+            Arc::new(stk_names),
+        )),
+        (Ok((cid, cname, _, _)), true, true) => Some(ChatMeta::Discord1(cid.into(), cname)),
+        (Ok((cid, cname, _, _)), false, false)
+        | (Ok((cid, cname, _, _)), true, false)
+        | (Ok((cid, cname, _, _)), false, true) => Some(ChatMeta::Discord2(
             cid.into(),
             cname,
             Arc::new(att_data),
@@ -728,47 +873,57 @@ async fn from_message(msg: Message, ctx: &Context) -> Chat {
             id: Arc::new(msg.author.id.to_string()),
             name: Arc::new(author_tag),
             perms,
+            roles,
         }),
         msg: Arc::new(content.to_string()),
         meta,
     }
 }
 
-async fn perms_from_msg(msg: &Message, ctx: &Context) -> Permissions {
-    if msg.author.id == *OWNER_ID {
-        return Permissions::OWNER;
+/// With the member's role ids, for the back's PermMap
+async fn perms_from_msg(msg: &Message, ctx: &Context) -> (Permissions, Vec<String>) {
+    if msg.author.id == owner_id() {
+        return (Permissions::OWNER, vec![]);
     }
 
-    let member = if let Some(guild) = GUILD_ID.to_guild_cached(&ctx.cache) {
+    let member = if let Some(guild) = guild_id().to_guild_cached(&ctx.cache) {
         if msg.author.id == guild.owner_id {
-            return Permissions::OWNER;
+            return (Permissions::OWNER, vec![]);
         }
         guild.member(&ctx.http, msg.author.id).await
     } else {
         msg.member(&ctx.http).await
     };
 
-    if let Ok(member) = member {
-        if let Ok(perms) = member.permissions(&ctx.cache) {
-            if perms.contains(model::Permissions::ADMINISTRATOR) {
-                Permissions::ADMIN
-            } else if perms
-                .intersects(model::Permissions::MODERATE_MEMBERS | model::Permissions::KICK_MEMBERS)
-            {
-                Permissions::MOD
-            } else if member.roles.contains(&*MEMBER_ROLE_ID) {
-                Permissions::MEMBER
-            } else {
-                Permissions::NONE
-            }
-        } else if member.roles.contains(&*MEMBER_ROLE_ID) {
+    let member = match member {
+        Ok(member) => member,
+        Err(_) => return (Permissions::NONE, vec![]),
+    };
+    let roles = role_ids(Some(&member.roles));
+    let perms = if let Ok(perms) = member.permissions(&ctx.cache) {
+        if perms.contains(model::Permissions::ADMINISTRATOR) {
+            Permissions::ADMIN
+        } else if perms
+            .intersects(model::Permissions::MODERATE_MEMBERS | model::Permissions::KICK_MEMBERS)
+        {
+            Permissions::MOD
+        } else if member.roles.contains(&member_role_id()) {
             Permissions::MEMBER
         } else {
             Permissions::NONE
         }
+    } else if member.roles.contains(&member_role_id()) {
+        Permissions::MEMBER
     } else {
         Permissions::NONE
-    }
+    };
+    (perms, roles)
+}
+
+fn role_ids(roles: Option<&Vec<RoleId>>) -> Vec<String> {
+    roles
+        .map(|roles| roles.iter().map(|r| r.to_string()).collect())
+        .unwrap_or_default()
 }
 
 pub(crate) trait FromPerms {
@@ -796,12 +951,12 @@ fn perms_from_maybe_member(maybe_member: Option<&Member>) -> msg::Permissions {
                 .intersects(model::Permissions::MODERATE_MEMBERS | model::Permissions::KICK_MEMBERS)
             {
                 Permissions::MOD
-            } else if member.roles.contains(&*MEMBER_ROLE_ID) {
+            } else if member.roles.contains(&member_role_id()) {
                 Permissions::MEMBER
             } else {
                 Permissions::NONE
             }
-        } else if member.roles.contains(&*MEMBER_ROLE_ID) {
+        } else if member.roles.contains(&member_role_id()) {
             Permissions::MEMBER
         } else {
             Permissions::NONE
@@ -819,12 +974,12 @@ fn perms_from_partial_member(member: &PartialMember) -> msg::Permissions {
             .intersects(model::Permissions::MODERATE_MEMBERS | model::Permissions::KICK_MEMBERS)
         {
             Permissions::MOD
-        } else if member.roles.contains(&*MEMBER_ROLE_ID) {
+        } else if member.roles.contains(&member_role_id()) {
             Permissions::MEMBER
         } else {
             Permissions::NONE
         }
-    } else if member.roles.contains(&*MEMBER_ROLE_ID) {
+    } else if member.roles.contains(&member_role_id()) {
         Permissions::MEMBER
     } else {
         Permissions::NONE
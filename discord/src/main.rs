@@ -1,9 +1,13 @@
 mod discord;
 mod msg;
+mod routes;
 
 use crate::discord::Handler;
-use back::msg::{Location, Response};
-use back::{init_redis, pubsub};
+use back::msg::{Incoming, Location, Response};
+use back::{
+    config::{Config, Transport},
+    health, init_redis, pubsub, telemetry,
+};
 use bb8_redis::bb8::Pool;
 use bb8_redis::RedisConnectionManager;
 use parking_lot::{Mutex, RwLock};
@@ -24,18 +28,24 @@ pub type RedisPool = Pool<RedisConnectionManager>;
 async fn main() {
     dotenv::dotenv().unwrap();
 
+    let (config, discord_config) = match Config::load_discord() {
+        Ok((config, discord_config)) => (config.init(), discord_config.init()),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
     let filter = Targets::new()
         .with_target("discord", LevelFilter::DEBUG)
         .with_target("back", LevelFilter::DEBUG)
         .with_target("serenity", LevelFilter::WARN)
         .with_target("h2", LevelFilter::WARN);
 
-    let file_appender = tracing_appender::rolling::never(
-        dotenv::var("LOG_DIR").expect("Log dir in env"),
-        "disc.log",
-    );
+    let file_appender = tracing_appender::rolling::never(&config.log_dir, "disc.log");
     let (non_blocking, _guard) = tracing_appender::non_blocking(file_appender);
 
+    let (otel, _telemetry) = telemetry::layer(config.telemetry.as_ref(), "discordrs");
     tracing_subscriber::registry()
         .with(filter)
         .with(
@@ -43,14 +53,14 @@ async fn main() {
                 .with_span_events(FmtSpan::CLOSE)
                 .with_writer(non_blocking),
         )
+        .with(otel)
         .init();
 
-    let was_streaming = std::env::var("STARTED").is_ok();
+    let was_streaming = discord_config.was_streaming;
     //println!("was_streaming: {}", was_streaming);
     tracing::info!(was_streaming = was_streaming);
 
-    // Configure the client with your Discord bot token in the environment.
-    let token = dotenv::var("DISCORD_TOKEN").expect("Expected a token in the environment");
+    let token = &discord_config.token.0;
 
     // validate_token is currently broken for new tokens
     //utils::validate_token(&token).expect("Expected a valid discord token");
@@ -63,7 +73,8 @@ async fn main() {
         | GatewayIntents::MESSAGE_CONTENT
         | GatewayIntents::DIRECT_MESSAGES
         | GatewayIntents::DIRECT_MESSAGE_REACTIONS
-        | GatewayIntents::GUILD_PRESENCES;
+        | GatewayIntents::GUILD_PRESENCES
+        | GatewayIntents::GUILD_VOICE_STATES;
 
     let cmd_cache = Arc::new(RwLock::new(None));
 
@@ -72,7 +83,7 @@ async fn main() {
     //let (discord_out_tx, discord_out_rx) = mpsc::channel::<discord::DiscordEvent>(32);
 
     // start msg loop
-    let (msg_in_tx, msg_in_rx) = mpsc::channel::<(Location, String)>(32);
+    let (msg_in_tx, msg_in_rx) = mpsc::channel::<(Location, Incoming)>(32);
     let (msg_out_tx, msg_out_rx) = mpsc::channel::<(Location, Response)>(32);
 
     // init handler state
@@ -84,7 +95,7 @@ async fn main() {
         stream_announced: Arc::new(AtomicBool::new(false)),
         mee6_last_url: Arc::new(Mutex::new(Arc::new("".into()))),
         cmd_cache: cmd_cache.clone(),
-        streamer_id: Arc::new(RwLock::new(*discord::OWNER_ID)),
+        streamer_id: Arc::new(RwLock::new(discord::owner_id())),
     };
 
     // Build our client.
@@ -101,31 +112,45 @@ async fn main() {
         handler,
         cache,
         cmd_cache,
+        routes: Arc::new(routes::ChannelRoutes::load(discord_config).await),
+        seen_pings: Default::default(),
     };
 
     msg.start(msg_in_rx, msg_out_rx);
 
+    // down until the gateway's ready
+    health::track(health::Link::Gateway);
+    if let Some(health) = config.health.clone() {
+        tokio::spawn(health::serve(health, "discordrs"));
+    }
+
     // start pubsub
-    start_pubsub(msg_in_tx, pub_in_rx).await;
+    start_pubsub(config, msg_in_tx, pub_in_rx).await;
 
     //let _ = tokio::join!(client.start(), hmsg);
     client.start().await.unwrap();
 }
 
 async fn start_pubsub(
-    msg_in_tx: mpsc::Sender<(Location, String)>,
+    config: &'static Config,
+    msg_in_tx: mpsc::Sender<(Location, Incoming)>,
     pub_in_rx: mpsc::Receiver<pubsub::Msg>,
 ) {
-    // init redis pool
-    let pool = init_redis().await.unwrap();
+    // init redis pool, Config::load makes sure it's set
+    let redis = config.redis.as_ref().unwrap();
+    let pool = init_redis(redis).await.unwrap();
 
     // start pubsub
-    pubsub::Server::new(
+    let server = pubsub::Server::new(
         pool,
         msg_in_tx,
         pub_in_rx,
-        &*back::UPSTREAM_CHAN,
-        &*back::DOWNSTREAM_CHAN,
-    )
+        &config.upstream_chan,
+        &config.downstream_chan,
+    );
+    match config.upstream_transport {
+        Transport::Pubsub => server,
+        Transport::Stream { max_len } => server.write_stream(max_len),
+    }
     .start();
 }
@@ -1,14 +1,21 @@
-use crate::discord::{Handler, GUILD_ID};
+use crate::{
+    discord::{guild_id, Handler},
+    routes::ChannelRoutes,
+};
 use back::{
+    channel_name,
     cmds::{Arg, ArgKind, ArgsDump, ModAction},
     msg::{
-        self, discord::DiscordAction, ChatMeta, Location, Message, Payload, Permissions, Ping,
-        Platform, Response, User, PLATFORMS,
+        self,
+        discord::{ChannelHint, DiscordAction, RoleMenu, ThreadReply},
+        session::SessionSummary,
+        ChatMeta, Incoming, Location, Message, MessageText, Payload, Permissions, Ping, Platform,
+        Response, User, PLATFORMS,
     },
-    pubsub, CHANNEL_NAME,
+    pubsub,
 };
 use once_cell::sync::Lazy;
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 use serenity::{
     builder::{
         CreateApplicationCommandOption, CreateAutocompleteResponse, EditInteractionResponse,
@@ -16,7 +23,8 @@ use serenity::{
     json::{self, Value},
     model::{
         self,
-        id::{ChannelId, RoleId, UserId},
+        channel::{ChannelType, ReactionType},
+        id::{ChannelId, EmojiId, MessageId, RoleId, UserId},
         interactions::application_command::{
             ApplicationCommand, ApplicationCommandOptionType, ApplicationCommandType,
         },
@@ -28,7 +36,7 @@ use serenity::{
 use std::{
     collections::HashMap,
     sync::Arc,
-    time::{SystemTime, UNIX_EPOCH},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 use tokio::{sync::mpsc, task::JoinHandle};
 
@@ -42,6 +50,56 @@ pub(crate) struct Server {
     pub(crate) handler: Handler,
     pub(crate) cache: Arc<CacheAndHttp>,
     pub(crate) cmd_cache: Arc<RwLock<Option<CommandCache>>>,
+    pub(crate) routes: Arc<ChannelRoutes>,
+    pub(crate) seen_pings: Arc<Mutex<SeenPings>>,
+}
+
+/// How long a ping's id is remembered after it's handled, well past the backend's last resend
+const SEEN_PING_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Pings handled lately, so ones the backend sends again are acked without DMing twice
+#[derive(Default)]
+pub(crate) struct SeenPings(HashMap<Arc<String>, (Instant, Option<bool>)>);
+
+impl SeenPings {
+    /// None if `id` is new, and it's now marked as being sent. Otherwise whether it was delivered,
+    /// or None inside if it's still being sent
+    fn check(&mut self, id: &Arc<String>) -> Option<Option<bool>> {
+        let now = Instant::now();
+        self.0
+            .retain(|_, (seen_at, _)| now.duration_since(*seen_at) < SEEN_PING_TTL);
+        match self.0.get(id) {
+            Some((_, delivered)) => Some(*delivered),
+            None => {
+                self.0.insert(id.clone(), (now, None));
+                None
+            }
+        }
+    }
+
+    fn done(&mut self, id: Arc<String>, delivered: bool) {
+        self.0.insert(id, (Instant::now(), Some(delivered)));
+    }
+}
+
+/// Threads made for long replies are archived after an hour without messages
+const THREAD_ARCHIVE_MINS: u16 = 60;
+/// Discord's limit on channel names
+const THREAD_NAME_LEN: usize = 100;
+
+/// A reply's first line, as the name of a thread made for it
+fn thread_name(msg: &str) -> String {
+    let name: String = msg
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .take(THREAD_NAME_LEN)
+        .collect();
+    match name.trim() {
+        "" => "Reply".into(),
+        _ => name,
+    }
 }
 
 static LLAMA_PING: Lazy<Arc<User>> = Lazy::new(|| {
@@ -49,25 +107,13 @@ static LLAMA_PING: Lazy<Arc<User>> = Lazy::new(|| {
         id: "624224573176545288".to_owned().into(),
         name: "".to_owned().into(),
         perms: Permissions::ADMIN,
+        roles: vec![],
     })
 });
 
-static STREAM_ANNOUNCE_CHAN_ID: Lazy<ChannelId> = Lazy::new(|| {
-    dotenv::var("STREAM_ANNOUNCE_CHAN_ID")
-        .unwrap()
-        .parse::<ChannelId>()
-        .unwrap_or_default()
-});
-static BOT_CHAN_ID: Lazy<ChannelId> = Lazy::new(|| {
-    dotenv::var("BOT_CHAN_ID")
-        .unwrap()
-        .parse::<ChannelId>()
-        .unwrap_or_default()
-});
-
 impl Server {
     // TODO: generalise chans
-    #[tracing::instrument(skip_all)]
+    #[tracing::instrument(skip_all, fields(corr_id = msg.corr_id.as_ref().map(|id| id.as_str()), platform = %msg.platform))]
     async fn msg(&self, msg: Message, _: Location) {
         tracing::info!("\x1b[93mMessage received\x1b[0m");
 
@@ -75,10 +121,12 @@ impl Server {
             platform,
             channel,
             payload,
+            corr_id,
+            ..
         } = msg;
 
         // Discord is a UI platform, it receives all and checks platform applicability for each payload type
-        if channel.as_str() != CHANNEL_NAME.as_str() {
+        if channel.as_str() != channel_name() {
             return;
         }
 
@@ -115,16 +163,26 @@ impl Server {
                 }
             }
             // a Message should be visible
-            Payload::Message { user, msg, meta } if platform.contains(Platform::DISCORD) => {
-                tracing::info!(user = ?user, msg = msg.as_str(), meta = ?meta, "Payload::Message");
-                let msg = match user {
+            Payload::Message {
+                user,
+                msg,
+                meta,
+                hint,
+                thread,
+            } if platform.contains(Platform::DISCORD) => {
+                tracing::info!(user = ?user, msg = msg.as_str(), meta = ?meta, hint = ?hint, thread = ?thread, "Payload::Message");
+                let new_thread_name = thread_name(&msg);
+                let is_dm = meta.as_ref().is_some_and(ChatMeta::is_dm);
+                let msg: MessageText = match user {
+                    // no one else is there to tell apart
+                    Some(_) if is_dm => msg,
                     Some((Platform::DISCORD, user)) => {
                         let new_msg = format!("<@{}> {}", user.id, msg);
-                        Arc::new(new_msg)
+                        new_msg.into()
                     }
                     Some((platform, user)) => {
                         let new_msg = format!("{} ({}) {}", user.name, platform, msg);
-                        Arc::new(new_msg)
+                        new_msg.into()
                     }
                     _ => msg,
                 };
@@ -162,13 +220,34 @@ impl Server {
 
                 if !was_interaction || !was_shown {
                     // send to relevant channel
-                    let channel = match meta {
-                        Some(ChatMeta::Discord1(cid, _))
-                        | Some(ChatMeta::Discord2(cid, _, _, _)) => {
-                            // reply on channel with id `cid`
-                            ChannelId(cid)
+                    let channel = match (hint, meta) {
+                        // replies to DMs stay in them
+                        (_, Some(ChatMeta::DirectMessage(cid))) => ChannelId(cid),
+                        // the backend asked for a specific channel
+                        (Some(hint), _) => match self.routes.resolve(hint) {
+                            Some(channel) => channel,
+                            None => {
+                                tracing::warn!(hint = %hint, "channel isn't routed, not sending");
+                                return;
+                            }
+                        },
+                        // threads can't have threads, and forums only take posts
+                        (_, Some(ChatMeta::DiscordThread(tid, _, parent, is_forum, _, _))) => {
+                            match thread {
+                                Some(ThreadReply::Parent) if !is_forum => ChannelId(parent),
+                                _ => ChannelId(tid),
+                            }
                         }
-                        _ => *BOT_CHAN_ID, // default to preset bot chan
+                        (_, Some(ChatMeta::Discord1(cid, _)))
+                        | (_, Some(ChatMeta::Discord2(cid, _, _, _))) => match thread {
+                            Some(ThreadReply::NewThread) => self
+                                .new_thread(ChannelId(cid), new_thread_name)
+                                .await
+                                .unwrap_or(ChannelId(cid)),
+                            // reply on channel with id `cid`
+                            _ => ChannelId(cid),
+                        },
+                        _ => self.routes.bot_spam(), // default to preset bot chan
                     };
                     tracing::info!(channel = %channel, "sending message");
                     if let Err(why) = channel.say(&self.cache.http, &msg).await {
@@ -176,7 +255,7 @@ impl Server {
                     }
                 }
             }
-            Payload::StreamAnnouncement(url, msg) => {
+            Payload::StreamAnnouncement(url, msg, hint) => {
                 // backend decides if we announce, but do one last check in case mee6 pings just before backend tells us to announce
                 let last_url = self.handler.mee6_last_url.lock().clone();
 
@@ -196,22 +275,79 @@ impl Server {
                 //     .is_ok()
                 {
                     tracing::debug!("annoncing");
-                    let chan = &*STREAM_ANNOUNCE_CHAN_ID;
-                    if let Err(why) = chan.say(&self.cache.http, &msg).await {
-                        tracing::error!("Error sending message: {:?}", why);
+                    match self.routes.resolve(hint) {
+                        Some(chan) => {
+                            if let Err(why) = chan.say(&self.cache.http, &msg).await {
+                                tracing::error!("Error sending message: {:?}", why);
+                            }
+                        }
+                        None => {
+                            tracing::warn!(hint = %hint, "channel isn't routed, not announcing")
+                        }
                     }
                 } else {
                     tracing::info!("MEE6 already pinged stream, not announcing");
                 }
             }
+            Payload::InvalidInvocation {
+                missing,
+                type_errors,
+                meta: Some(ChatMeta::DiscordInteraction(token, ..)),
+            } if platform == Platform::DISCORD => {
+                let mut msg = MessageBuilder::new();
+                if !missing.is_empty() {
+                    msg.push_line(format!("Missing: {}", missing.join(", ")));
+                }
+                for error in type_errors {
+                    msg.push_line(error.to_string());
+                }
+                self.interaction_error(&token, msg.build()).await;
+            }
             Payload::Ping(ping) if platform == Platform::DISCORD => {
-                self.ping(ping).await;
+                let id = ping.id.clone();
+                let seen = id.as_ref().and_then(|id| self.seen_pings.lock().check(id));
+                let delivered = match seen {
+                    // the first's still being sent, and will be acked when it's done
+                    Some(None) => return,
+                    // sent again as the ack was late or lost, just ack it again
+                    Some(Some(delivered)) => {
+                        tracing::info!("ping already handled, acking again");
+                        delivered
+                    }
+                    None => {
+                        let delivered = self.ping(ping).await.is_some();
+                        if let Some(id) = &id {
+                            self.seen_pings.lock().done(id.clone(), delivered);
+                        }
+                        delivered
+                    }
+                };
+                // the backend keeps the ping queued until it hears back
+                if let Some(id) = id {
+                    let payload = if delivered {
+                        Payload::PingDelivered(id)
+                    } else {
+                        Payload::PingFailed {
+                            id,
+                            reason: Arc::new("couldn't DM the user".into()),
+                        }
+                    };
+                    Response {
+                        platform: Platform::DISCORD,
+                        channel: channel_name(),
+                        corr_id: corr_id.clone(),
+                        payload,
+                    }
+                    .send(Location::Pubsub, &self.msg_out_tx)
+                    .await;
+                }
             }
             Payload::ConfigChanged => {
                 // get new arg schema
                 Response {
                     platform: Platform::DISCORD,
-                    channel: &*CHANNEL_NAME,
+                    channel: channel_name(),
+                    corr_id: corr_id.clone(),
                     payload: Payload::DumpArgs(Platform::DISCORD),
                 }
                 .send(Location::Pubsub, &self.msg_out_tx)
@@ -227,6 +363,7 @@ impl Server {
             Payload::ModAction(user, action, reason) => {
                 // send a debug dm
                 self.ping(Ping {
+                    id: None,
                     pinger: None,
                     pingee: LLAMA_PING.clone(),
                     msg: Some(
@@ -240,6 +377,9 @@ impl Server {
                 })
                 .await;
             }
+            Payload::SessionSummary(summary) if platform.contains(Platform::DISCORD) => {
+                self.session_summary(summary).await;
+            }
             Payload::Discord(action) => match action {
                 DiscordAction::AddRole(inner) => {
                     self.role(inner, true).await;
@@ -254,11 +394,215 @@ impl Server {
                         *self.handler.streamer_id.write() = id;
                     }
                 }
+                DiscordAction::SetChannel(hint, id) => {
+                    let id = match id.map(|id| id.parse::<ChannelId>()).transpose() {
+                        Ok(id) => id,
+                        Err(_) => {
+                            tracing::warn!(hint = %hint, "invalid channel id");
+                            return;
+                        }
+                    };
+                    tracing::info!(hint = %hint, id = ?id, "Setting channel route");
+                    self.routes.set(hint, id).await;
+                }
+                DiscordAction::SyncRoleMenu(menu) => {
+                    self.role_menu(menu, corr_id).await;
+                }
+                DiscordAction::RemoveReaction(reaction) => {
+                    self.remove_reaction(reaction).await;
+                }
+                DiscordAction::SyncVoice => {
+                    self.handler.sync_voice(&self.cache.cache).await;
+                }
             },
             _ => {}
         }
     }
 
+    /// Swap the deferred response for one only the invoker can see
+    /// A public thread off `channel` to reply in, None if it couldn't be made
+    async fn new_thread(&self, channel: ChannelId, name: String) -> Option<ChannelId> {
+        let thread = channel
+            .create_private_thread(&self.cache.http, |t| {
+                t.name(name)
+                    .kind(ChannelType::PublicThread)
+                    .auto_archive_duration(THREAD_ARCHIVE_MINS)
+            })
+            .await;
+        match thread {
+            Ok(thread) => Some(thread.id),
+            Err(why) => {
+                tracing::error!(why=?why, channel = %channel, "Error creating thread");
+                None
+            }
+        }
+    }
+
+    #[tracing::instrument(skip(self, token))]
+    async fn interaction_error(&self, token: &str, msg: String) {
+        if let Err(why) = self
+            .cache
+            .http
+            .delete_original_interaction_response(token)
+            .await
+        {
+            tracing::error!(why=?why,"Error deleting orig. interaction resp.");
+        }
+
+        // ephemeral flag is 64
+        let map = serde_json::json!({
+            "content": msg,
+            "flags": 64,
+        });
+        if let Err(why) = self.cache.http.create_followup_message(token, &map).await {
+            tracing::error!(why=?why,"Error sending interaction error");
+        }
+    }
+
+    fn reaction_type(emoji: &str) -> ReactionType {
+        match emoji.parse::<u64>() {
+            Ok(id) => ReactionType::Custom {
+                animated: false,
+                id: EmojiId(id),
+                name: None,
+            },
+            Err(_) => ReactionType::Unicode(emoji.to_owned()),
+        }
+    }
+
+    /// Post or update a role menu, react with its choices, then give and take roles to
+    /// match the reactions, catching up on any made while the bot was offline
+    #[tracing::instrument(skip(self, corr_id), fields(name = menu.name.as_str()))]
+    async fn role_menu(&self, menu: RoleMenu, corr_id: Option<Arc<String>>) -> Option<()> {
+        let http = &self.cache.http;
+        let channel = menu.channel_id.parse::<ChannelId>().ok()?;
+        let bot_id = self.cache.cache.current_user_id();
+
+        let existing = match menu.message_id {
+            Some(ref id) => match id.parse::<u64>() {
+                Ok(id) => channel.message(http, MessageId(id)).await.ok(),
+                Err(_) => None,
+            },
+            None => None,
+        };
+
+        let message = match existing {
+            Some(mut message) => {
+                if message.author.id == bot_id && message.content != *menu.content {
+                    if let Err(why) = message
+                        .edit(&self.cache, |m| m.content(&menu.content))
+                        .await
+                    {
+                        tracing::error!(why=?why,"Error editing role menu");
+                    }
+                }
+                message
+            }
+            None => {
+                let message = match channel.say(http, &menu.content).await {
+                    Ok(message) => message,
+                    Err(why) => {
+                        tracing::error!(why=?why,"Error posting role menu");
+                        return None;
+                    }
+                };
+                Response {
+                    platform: Platform::DISCORD,
+                    channel: channel_name(),
+                    corr_id,
+                    payload: Payload::RoleMenuPosted {
+                        name: menu.name.clone(),
+                        message_id: message.id.to_string().into(),
+                    },
+                }
+                .send(Location::Pubsub, &self.msg_out_tx)
+                .await;
+                message
+            }
+        };
+
+        let guild = self.cache.cache.guild(guild_id())?;
+        let reason = Some(format!("ReactionRole ({}) sync", menu.name));
+        for (emoji, role_id) in &menu.roles {
+            let reaction = Self::reaction_type(emoji);
+            let role_id = match role_id.parse::<RoleId>() {
+                Ok(id) => id,
+                Err(_) => {
+                    tracing::warn!(role_id = role_id.as_str(), "invalid role id");
+                    continue;
+                }
+            };
+            if let Err(why) = message.react(&self.cache, reaction.clone()).await {
+                tracing::error!(why=?why, emoji = emoji.as_str(), "Error reacting to role menu");
+                continue;
+            }
+
+            // 100 at a time
+            let mut reacted = vec![];
+            let mut after = None;
+            loop {
+                let users = match message
+                    .reaction_users(http, reaction.clone(), Some(100), after)
+                    .await
+                {
+                    Ok(users) => users,
+                    Err(why) => {
+                        tracing::error!(why=?why,"Error listing reactions");
+                        break;
+                    }
+                };
+                after = users.last().map(|u| u.id);
+                let done = users.len() < 100;
+                reacted.extend(users.into_iter().map(|u| u.id).filter(|id| *id != bot_id));
+                if done {
+                    break;
+                }
+            }
+
+            for (user_id, member) in &guild.members {
+                let has_role = member.roles.contains(&role_id);
+                let res = match (has_role, reacted.contains(user_id)) {
+                    (false, true) => {
+                        http.add_member_role(guild.id.0, user_id.0, role_id.0, reason.as_deref())
+                            .await
+                    }
+                    (true, false) => {
+                        http.remove_member_role(guild.id.0, user_id.0, role_id.0, reason.as_deref())
+                            .await
+                    }
+                    _ => continue,
+                };
+                tracing::info!(user = %user_id, role = %role_id, added = !has_role, "synced role");
+                if let Err(why) = res {
+                    tracing::error!("{}", why);
+                }
+            }
+        }
+
+        Some(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn remove_reaction(&self, reaction: msg::discord::Reaction) -> Option<()> {
+        let channel_id = reaction.channel_id.parse::<u64>().ok()?;
+        let message_id = reaction.message_id.parse::<u64>().ok()?;
+        let user_id = reaction.user_id.parse::<u64>().ok()?;
+        if let Err(why) = self
+            .cache
+            .http
+            .delete_reaction(
+                channel_id,
+                message_id,
+                Some(user_id),
+                &Self::reaction_type(&reaction.emoji),
+            )
+            .await
+        {
+            tracing::error!("{}", why);
+        }
+        Some(())
+    }
+
     #[tracing::instrument(skip(self))]
     async fn mod_action(
         &self,
@@ -267,7 +611,7 @@ impl Server {
         reason: Arc<String>,
     ) -> Option<()> {
         let user_id = user.id.parse::<UserId>().ok()?;
-        //let mut member = self.cache.cache.member(*GUILD_ID, user_id)?;
+        //let mut member = self.cache.cache.member(guild_id(), user_id)?;
 
         match action {
             ModAction::None => {}
@@ -291,11 +635,62 @@ impl Server {
             }
             ModAction::Kick => {}
             ModAction::Ban => {}
+            ModAction::Purge => {}
+            ModAction::Untimeout => {}
         }
 
         Some(())
     }
 
+    /// Post the stream's totals to the mod channel
+    #[tracing::instrument(skip(self))]
+    async fn session_summary(&self, summary: SessionSummary) -> Option<()> {
+        fn list<T: std::fmt::Display>(items: &[(T, impl std::fmt::Display)]) -> String {
+            if items.is_empty() {
+                return "none".into();
+            }
+            items
+                .iter()
+                .map(|(name, count)| format!("{}: {}", name, count))
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+
+        let duration = summary.duration().as_secs();
+        let ended_at = Timestamp::from_unix_timestamp(summary.ended_at.try_into().ok()?).ok()?;
+        let totals = &summary.totals;
+
+        let channel = match self.routes.resolve(ChannelHint::ModLog) {
+            Some(channel) => channel,
+            None => {
+                tracing::warn!("mod log isn't routed, not sending the session summary");
+                return None;
+            }
+        };
+        let res = channel
+            .send_message(&self.cache.http, |m| {
+                m.embed(|e| {
+                    e.title(format!("{} stream summary", summary.platform))
+                        .timestamp(ended_at)
+                        .field(
+                            "Duration",
+                            format!("{}h {}m", duration / 3600, duration / 60 % 60),
+                            true,
+                        )
+                        .field("Points awarded", totals.points_awarded.to_string(), true)
+                        .field("Messages", list(&summary.messages), false)
+                        .field("New chatters", list(&totals.new_chatters), false)
+                        .field("Filters triggered", list(&totals.filters), false)
+                        .field("Top commands", list(&totals.commands), false)
+                })
+            })
+            .await;
+        if let Err(why) = res {
+            tracing::error!(why=?why,"Error sending session summary");
+        }
+        Some(())
+    }
+
     #[tracing::instrument(skip(self))]
     async fn role(
         &self,
@@ -311,7 +706,7 @@ impl Server {
         let role_id = *role_id.parse::<RoleId>().ok()?.as_u64();
         let guild_id = guild_id
             .and_then(|id| (&*id).parse::<u64>().ok())
-            .unwrap_or(*GUILD_ID.as_u64());
+            .unwrap_or(*crate::discord::guild_id().as_u64());
         let member = self.cache.cache.member(guild_id, user_id)?;
         let _reason = reason.as_ref().map(|r| r.as_str());
         if is_add {
@@ -415,7 +810,7 @@ impl Server {
 
     async fn args_dump(&self, dump: ArgsDump) {
         use crate::discord::FromPerms;
-        // let guild_id = &*GUILD_ID;
+        // let guild_id = &guild_id();
         // let _ = guild_id
         //     .set_application_commands(&self.cache.http, |commands| commands)
         //     .await;
@@ -482,6 +877,7 @@ impl Server {
     #[tracing::instrument(skip(self))]
     async fn ping(&self, ping: Ping) -> Option<()> {
         let Ping {
+            id: _,
             pinger,
             pingee: _pingee,
             msg,
@@ -497,7 +893,9 @@ impl Server {
             (Some((platform, pinger)), Some(msg)) => MessageBuilder::new()
                 .push_line(format!(
                     "{} pinged you from {}'s {}:",
-                    pinger.name, &*CHANNEL_NAME, platform
+                    pinger.name,
+                    channel_name(),
+                    platform
                 ))
                 .push_quote_line_safe(msg)
                 .push_line("(_reply to respond_)")
@@ -511,7 +909,9 @@ impl Server {
             (Some((platform, pinger)), _) => {
                 format!(
                     "{} pinged you from {}'s {}!\n(_reply to respond_)",
-                    pinger.name, &*CHANNEL_NAME, platform
+                    pinger.name,
+                    channel_name(),
+                    platform
                 )
             }
             (_, Some(msg)) => (&*msg).to_owned(),
@@ -589,24 +989,28 @@ impl Server {
     }
 
     // TODO: this is copied from aussiebot_back::msg::Server
-    async fn msg_rx_loop(self, mut msg_in_rx: mpsc::Receiver<(Location, String)>) {
+    async fn msg_rx_loop(self, mut msg_in_rx: mpsc::Receiver<(Location, Incoming)>) {
         while let Some(msg) = msg_in_rx.recv().await {
             let (loc, msg) = msg;
             //println!("msg recv: {} from {:?}", msg, loc);
             let server = self.clone();
             //tokio::spawn(async move {
-            let msg = tokio::task::spawn_blocking(move || {
-                let de = serde_json::from_str::<Message>(&msg);
-                (msg, de)
-            })
-            .await;
+            let msg = match msg {
+                Incoming::Parsed(msg) => Ok(Ok(msg)),
+                Incoming::Raw(msg) => {
+                    tokio::task::spawn_blocking(move || {
+                        serde_json::from_str::<Message>(&msg).map_err(|e| (msg, e))
+                    })
+                    .await
+                }
+            };
             match msg {
-                Ok((_, Ok(msg))) => {
+                Ok(Ok(msg)) => {
                     tokio::spawn(async move {
                         server.msg(msg, loc).await;
                     });
                 }
-                Ok((orig_msg, Err(e))) => {
+                Ok(Err((orig_msg, e))) => {
                     tracing::error!(orig_msg = ?orig_msg, loc = ?loc, "INVALID MSG: {}", e);
                 }
                 Err(e) => {
@@ -619,16 +1023,19 @@ impl Server {
 
     async fn msg_tx_loop(self, mut msg_out_rx: mpsc::Receiver<(Location, Response)>) {
         while let Some(msg) = msg_out_rx.recv().await {
-            let (loc, msg) = msg;
+            let (loc, mut msg) = msg;
+            // events originating here start their own trail
+            let corr_id = msg.corr_id.get_or_insert_with(msg::new_corr_id);
+            tracing::debug!(corr_id = corr_id.as_str(), "sending response");
             // serialise msg
             let msg = tokio::task::spawn_blocking(move || serde_json::to_string(&msg)).await;
             if let Ok(Ok(msg)) = msg {
-                // TODO: by making an arc we just defer cloning to the edges, i.e before writing out to each ws' stream. pubsub can take a &str, but not ws
-                let msg = Arc::new(msg);
+                // shared as-is by every destination, ws peers only copy it when writing out to their stream
+                let msg: Arc<str> = msg.into();
                 // route accordingly
                 match loc {
                     Location::Pubsub | Location::Broadcast => {
-                        let _ = self.pub_in_tx.send(msg).await;
+                        let _ = self.pub_in_tx.send((msg, None)).await;
                     }
                     _ => unimplemented!(),
                 }
@@ -639,7 +1046,7 @@ impl Server {
     /// Start the server, consuming it
     pub fn start(
         self,
-        msg_in_rx: mpsc::Receiver<(Location, String)>,
+        msg_in_rx: mpsc::Receiver<(Location, Incoming)>,
         msg_out_rx: mpsc::Receiver<(Location, Response)>,
     ) -> JoinHandle<()> {
         tracing::info!("\x1b[92m-------------Starting message loop-------------\x1b[0m");
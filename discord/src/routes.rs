@@ -0,0 +1,106 @@
+use back::{config::DiscordConfig, msg::discord::ChannelHint};
+use parking_lot::RwLock;
+use serenity::model::id::ChannelId;
+use std::{collections::HashMap, io::ErrorKind, path::PathBuf};
+
+const ROUTES_FILE: &str = "discord_channels.json";
+
+/// Where each logical channel goes. Unset ones use the channels from the config, except the mod
+/// log which is private and so only goes somewhere once it's routed
+#[derive(Debug)]
+pub(crate) struct ChannelRoutes {
+    routes: RwLock<HashMap<ChannelHint, ChannelId>>,
+    path: PathBuf,
+    announce_chan: ChannelId,
+    bot_chan: ChannelId,
+}
+
+impl ChannelRoutes {
+    #[tracing::instrument(skip_all)]
+    pub(crate) async fn load(config: &DiscordConfig) -> Self {
+        let routes = Self {
+            routes: Default::default(),
+            path: config.config_dir.join(ROUTES_FILE),
+            announce_chan: ChannelId(config.announce_chan),
+            bot_chan: ChannelId(config.bot_chan),
+        };
+
+        let contents = match tokio::fs::read_to_string(&routes.path).await {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => return routes,
+            Err(e) => {
+                tracing::error!("couldn't read channel routes: {}", e);
+                return routes;
+            }
+        };
+
+        let saved = match serde_json::from_str::<HashMap<ChannelHint, String>>(&contents) {
+            Ok(saved) => saved,
+            Err(e) => {
+                tracing::error!("couldn't parse channel routes: {}", e);
+                return routes;
+            }
+        };
+
+        let saved = saved
+            .into_iter()
+            .filter_map(|(hint, id)| match id.parse::<ChannelId>() {
+                Ok(id) => Some((hint, id)),
+                Err(_) => {
+                    tracing::warn!(hint = %hint, id = id.as_str(), "invalid channel id");
+                    None
+                }
+            })
+            .collect();
+        *routes.routes.write() = saved;
+        routes
+    }
+
+    /// None if it's the mod log and that hasn't been routed
+    pub(crate) fn resolve(&self, hint: ChannelHint) -> Option<ChannelId> {
+        if let Some(id) = self.routes.read().get(&hint) {
+            return Some(*id);
+        }
+        match hint {
+            ChannelHint::Announce => Some(self.announce_chan),
+            ChannelHint::BotSpam => Some(self.bot_chan),
+            ChannelHint::ModLog => None,
+        }
+    }
+
+    /// The bot spam channel, which always has somewhere to go
+    pub(crate) fn bot_spam(&self) -> ChannelId {
+        self.routes
+            .read()
+            .get(&ChannelHint::BotSpam)
+            .copied()
+            .unwrap_or(self.bot_chan)
+    }
+
+    /// Route `hint` to `id`, or back to its default if None, and save the routes
+    #[tracing::instrument(skip(self))]
+    pub(crate) async fn set(&self, hint: ChannelHint, id: Option<ChannelId>) {
+        let dump = {
+            let mut routes = self.routes.write();
+            match id {
+                Some(id) => routes.insert(hint, id),
+                None => routes.remove(&hint),
+            };
+            routes
+                .iter()
+                .map(|(hint, id)| (*hint, id.to_string()))
+                .collect::<HashMap<_, _>>()
+        };
+
+        let dump = match serde_json::to_string_pretty(&dump) {
+            Ok(dump) => dump,
+            Err(e) => {
+                tracing::error!("couldn't serialize channel routes: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = tokio::fs::write(&self.path, dump).await {
+            tracing::error!("couldn't save channel routes: {}", e);
+        }
+    }
+}
@@ -31,6 +31,8 @@ import {
   TPlatformValue,
   TPermsValue,
   TModActionValue,
+  TListValue,
+  TSecretValue,
   TValue,
   TConfig,
   TFns,
@@ -46,6 +48,7 @@ import {
   verify_number,
   verify_value,
   verify_modaction,
+  verify_list,
 } from "./util";
 
 // delay before committing if no further changes made (in ms)
@@ -113,6 +116,11 @@ const fromPermV = (v: TPermsValue): TPerms => v.Permissions;
 const toPermV = (Permissions: TPerms): TPermsValue => ({ Permissions });
 const fromMV = (v: TModActionValue): TModAction => v.ModAction;
 const toMV = (ModAction: TModAction): TModActionValue => ({ ModAction });
+// one item per line
+const fromLV = (v: TListValue): string => v.List.join("\n");
+const toLV = (s: string): TListValue => ({ List: s.split("\n") });
+const fromSecV = (v: TSecretValue): string => v.Secret;
+const toSecV = (Secret: string): TSecretValue => ({ Secret });
 
 interface ConfigProps {
   schema: TSchema;
@@ -281,6 +289,8 @@ const Field = (props: FieldProps<TValue>) => {
       platform: (value) => PlatformField({ ...props, value }),
       perms: (value) => PermissionsField({ ...props, value }),
       modaction: (value) => ModActionField({ ...props, value }),
+      list: (value) => ListField({ ...props, value }),
+      secret: (value) => SecretField({ ...props, value }),
       default: () => <div>Unreachable: unknown value</div>,
     }),
     [props]
@@ -376,6 +386,67 @@ const StringField = (props: FieldProps<TStringValue>) => {
   );
 };
 
+const SecretField = (props: FieldProps<TSecretValue>) => {
+  const [value, setValue] = useState(props.value);
+  const onUpdate = props.onUpdate;
+
+  useEffect(() => {
+    setValue(props.value);
+  }, [props.value]);
+
+  const valid = verify_string({ String: value.Secret }, props.constraint);
+
+  const timer = useRef(null as NodeJS.Timeout | null);
+  // eslint-disable-next-line react-hooks/exhaustive-deps
+  const onChange = useCallback(
+    commitCallback(toSecV, timer, setValue, onUpdate),
+    [setValue, onUpdate]
+  );
+
+  return (
+    <FieldBox label={props.label}>
+      <TextField
+        type="password"
+        value={fromSecV(value)}
+        error={!valid}
+        onChange={onChange}
+        helperText={valid ? "" : props.helperText}
+      />
+    </FieldBox>
+  );
+};
+
+const ListField = (props: FieldProps<TListValue>) => {
+  const [value, setValue] = useState(props.value);
+  const onUpdate = props.onUpdate;
+
+  useEffect(() => {
+    setValue(props.value);
+  }, [props.value]);
+
+  const valid = verify_list(value, props.constraint);
+
+  const timer = useRef(null as NodeJS.Timeout | null);
+  // eslint-disable-next-line react-hooks/exhaustive-deps
+  const onChange = useCallback(
+    commitCallback(toLV, timer, setValue, onUpdate),
+    [setValue, onUpdate]
+  );
+
+  return (
+    <FieldBox label={props.label}>
+      <TextField
+        multiline
+        minRows={2}
+        value={fromLV(value)}
+        error={!valid}
+        onChange={onChange}
+        helperText={valid ? "One per line" : props.helperText}
+      />
+    </FieldBox>
+  );
+};
+
 const RegexField = (props: FieldProps<TRegexValue>) => {
   const [value, setValue] = useState(props.value);
   const { onUpdate } = props;
@@ -366,7 +366,7 @@ const parseMessage = (msg: TMessage, send: Sender<TEvent>) => {
   }
 
   if (isSchemaDump(payload)) {
-    send({ type: "SCHEMA", schema: payload.SchemaDump });
+    send({ type: "SCHEMA", schema: payload.SchemaDump.schema });
   }
 
   if (isLogDumpPayload(payload)) {
@@ -916,7 +916,7 @@ const mainMachine = createMachine(
       requestLog: send(
         {
           type: "WS_TX",
-          msg: toMessage({ DumpLog: ChatPlatforms }),
+          msg: toMessage({ DumpLog: { platform: ChatPlatforms } }),
         },
         { to: (ctx) => ctx.socketRef }
       ),
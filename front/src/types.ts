@@ -29,7 +29,9 @@ export type TModAction =
   | "Remove"
   | { Timeout: number }
   | "Kick"
-  | "Ban";
+  | "Ban"
+  | "Purge"
+  | "Untimeout";
 
 export const ModActions = ["None", "Warn", "Remove", "Timeout", "Kick", "Ban"];
 
@@ -76,6 +78,9 @@ export type TRegexValue = { Regex: string };
 export type TPlatformValue = { Platforms: TPlatform };
 export type TPermsValue = { Permissions: TPerms };
 export type TModActionValue = { ModAction: TModAction };
+export type TListValue = { List: string[] };
+/** "********" when redacted, sent back as-is to keep the saved value */
+export type TSecretValue = { Secret: string };
 export type TValue =
   | TBoolValue
   | TNumberValue
@@ -83,7 +88,9 @@ export type TValue =
   | TRegexValue
   | TPlatformValue
   | TPermsValue
-  | TModActionValue;
+  | TModActionValue
+  | TListValue
+  | TSecretValue;
 
 export type TMaybeValidValue = TValue & { valid: boolean };
 
@@ -95,10 +102,13 @@ export type TFns<U> = {
   platform: (v: TPlatformValue) => U;
   perms: (v: TPermsValue) => U;
   modaction: (v: TModActionValue) => U;
+  list: (v: TListValue) => U;
+  secret: (v: TSecretValue) => U;
   default: (v: TValue) => U; //default value
 };
 
-export type TKeyCmd = [string, TValue];
+/** unset optional fields are dumped as "None" */
+export type TKeyCmd = [string, TValue | "None"];
 export type TCmdConfig = [string, string, TKeyCmd[]];
 
 export type TConfig = {
@@ -124,7 +134,20 @@ export type TConfigSet = {
   [k in TConfigType]: TConfig[];
 };
 
-export type TKeySchema = [string, string, TValue, TConstraint];
+export type TKeyHint = {
+  group: string | null;
+  advanced: boolean;
+  order: number;
+};
+
+export type TKeySchema = [
+  string,
+  string,
+  TValue,
+  TConstraint,
+  boolean,
+  TKeyHint
+];
 export type TCmdSchema = [string, string, TConfigTypeKey, TKeySchema[]];
 
 export type TSchema = {
@@ -136,6 +159,8 @@ export type TSchema = {
         desc: string;
         def_value: TValue;
         constraint: TConstraint;
+        optional: boolean;
+        hint: TKeyHint;
       };
     };
   };
@@ -179,11 +204,17 @@ export type TChatMetaDiscord3 = {
   Discord3: [[string, string][], string[]];
 };
 
+/** char offsets into the message, end exclusive */
+export type TChatMetaEmotes = {
+  Emotes: { id: string; start: number; end: number }[];
+};
+
 export type TChatMeta =
   | TChatMetaYt
   | TChatMetaDiscord1
   | TChatMetaDiscord2
-  | TChatMetaDiscord3;
+  | TChatMetaDiscord3
+  | TChatMetaEmotes;
 
 export type TChat = {
   user: TChatUser;
@@ -196,7 +227,7 @@ export type TChatPayload = {
 };
 
 export type TDumpLogPayload = {
-  DumpLog: TPlatform;
+  DumpLog: { platform: TPlatform; offset?: number; limit?: number | null };
 };
 
 export type TPlatformLogDump = string[];
@@ -236,7 +267,9 @@ export type TArgsDumpPayload = {
   ArgsDump: TArgsDumpItem[];
 };
 
-export type TSchemaDump = { SchemaDump: TCmdSchema[] };
+export type TSchemaDump = {
+  SchemaDump: { version: string; schema: TCmdSchema[] };
+};
 
 export type TPayload =
   | "DumpConfig"
@@ -286,12 +319,27 @@ export type TModActionsLog = {
     ListUsers,
     RequestCode(Arc<String>),
     Login(Arc<String>, Arc<String>),
+    ListProviders,
+    OAuthStart(OAuthProvider),
+    OAuthLogin { provider, code, state },
 */
+export type TOAuthProvider = "Discord" | "Twitch";
 export type TAuthListUsers = "ListUsers";
 export type TAuthRequestCode = { RequestCode: string };
 export type TAuthLogin = { Login: [string, string] };
+export type TAuthListProviders = "ListProviders";
+export type TAuthOAuthStart = { OAuthStart: TOAuthProvider };
+export type TAuthOAuthLogin = {
+  OAuthLogin: { provider: TOAuthProvider; code: string; state: string };
+};
 
-export type TAuthMessage = TAuthListUsers | TAuthRequestCode | TAuthLogin;
+export type TAuthMessage =
+  | TAuthListUsers
+  | TAuthRequestCode
+  | TAuthLogin
+  | TAuthListProviders
+  | TAuthOAuthStart
+  | TAuthOAuthLogin;
 
 /*
     Users(Vec<String>),
@@ -300,6 +348,8 @@ export type TAuthMessage = TAuthListUsers | TAuthRequestCode | TAuthLogin;
     AuthSuccess(Arc<String>),
     AuthFail,
     AuthError,
+    Providers(Vec<OAuthProvider>),
+    OAuthRedirect(String),
 */
 
 export type TAuthUsers = { Users: string[] };
@@ -310,9 +360,12 @@ export type TAuthCodeExpired = "CodeExpired";
 export type TAuthSuccess = { AuthSuccess: string };
 export type TAuthFail = "AuthFail";
 
-export type TAuthErrorType = "Ratelimited" | "ServerError";
+export type TAuthErrorType = "Ratelimited" | "ServerError" | "Unavailable";
 export type TAuthError = { AuthError: TAuthErrorType };
 
+export type TAuthProviders = { Providers: TOAuthProvider[] };
+export type TAuthOAuthRedirect = { OAuthRedirect: string };
+
 export type TAuthResp =
   | TAuthUsers
   | TAuthInvalidUser
@@ -320,4 +373,6 @@ export type TAuthResp =
   | TAuthCodeExpired
   | TAuthSuccess
   | TAuthFail
-  | TAuthError;
+  | TAuthError
+  | TAuthProviders
+  | TAuthOAuthRedirect;
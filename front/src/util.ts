@@ -13,6 +13,7 @@ import {
   TConstraint,
   TEnum,
   TFns,
+  TListValue,
   TMaybeValidValue,
   TModActionValue,
   TNumberValue,
@@ -20,6 +21,7 @@ import {
   TPlatformValue,
   TRegexValue,
   TSchema,
+  TSecretValue,
   TStringValue,
   TValue,
 } from "./types";
@@ -38,6 +40,8 @@ const isPermissionsValue = (arg: object): arg is TPermsValue =>
   "Permissions" in arg;
 const isModActionValue = (arg: object): arg is TModActionValue =>
   "ModAction" in arg;
+const isListValue = (arg: object): arg is TListValue => "List" in arg;
+const isSecretValue = (arg: object): arg is TSecretValue => "Secret" in arg;
 
 export const strip_maybe_value = ({
   valid,
@@ -52,12 +56,14 @@ export function parse_schema(dump: Readonly<TCmdSchema[]>): TSchema {
         desc,
         configType,
         fields: keys.reduce(
-          (acc, [field, desc, def_value, constraint]) => ({
+          (acc, [field, desc, def_value, constraint, optional, hint]) => ({
             ...acc,
             [field]: {
               desc,
               def_value,
               constraint,
+              optional,
+              hint,
             },
           }),
           {}
@@ -75,11 +81,21 @@ export function parse_config(dump: TCmdConfig[], schema: TSchema): TConfig[] {
   return dump.map(([type, name, keys]) => ({
     type,
     name,
+    // unset optional fields are left out, the backend defaults missing ones to unset
     fields: Object.fromEntries(
-      keys.map(([field, value]) => [
-        field,
-        into_maybe_valid_value(value, schema[type].fields[field].constraint),
-      ])
+      keys.flatMap(([field, value]) =>
+        value === "None"
+          ? []
+          : [
+              [
+                field,
+                into_maybe_valid_value(
+                  value,
+                  schema[type].fields[field].constraint
+                ),
+              ],
+            ]
+      )
     ),
   }));
 }
@@ -124,6 +140,8 @@ function same_type_values(v: TValue, def: TValue) {
     platform: () => isPlatformValue(def),
     perms: () => isPermissionsValue(def),
     modaction: () => isModActionValue(def),
+    list: () => isListValue(def),
+    secret: () => isSecretValue(def),
     default: () => false,
   };
   return map_value(v, fns);
@@ -133,7 +151,12 @@ function check_config_types(dump: TCmdConfig[], schema: TSchema) {
   // eslint-disable-next-line @typescript-eslint/no-unused-vars
   for (const [type, name, keys] of dump) {
     for (const [field, value] of keys) {
-      const defValue = schema[type].fields[field].def_value;
+      const { def_value: defValue, optional } = schema[type].fields[field];
+      if (value === "None") {
+        if (optional) continue;
+        console.error("unset value for required field", field);
+        return false;
+      }
       if (!same_type_values(value, defValue)) {
         console.error(
           "invalid Value type, got: typeof",
@@ -180,6 +203,8 @@ export function verify_value(value: TValue, constraint: TConstraint): boolean {
     platform: def,
     perms: def,
     modaction: (value) => verify_modaction(value, constraint),
+    list: (value) => verify_list(value, constraint),
+    secret: (value) => verify_string({ String: value.Secret }, constraint),
     default: () => false,
   };
 
@@ -266,6 +291,11 @@ export function verify_string(value: TStringValue, constraint: TConstraint) {
   return false;
 }
 
+export function verify_list(value: TListValue, constraint: TConstraint) {
+  if (constraint === "NonEmpty") return value.List.length > 0;
+  return value.List.every((s) => verify_string({ String: s }, constraint));
+}
+
 export function verify_regex(value: TRegexValue, constraint: TConstraint) {
   // check if regex is valid
   const pat = value.Regex;
@@ -358,6 +388,12 @@ export function map_value<T>(value: TValue, fns: TFns<T>) {
   if (isModActionValue(value)) {
     return fns.modaction(value);
   }
+  if (isListValue(value)) {
+    return fns.list(value);
+  }
+  if (isSecretValue(value)) {
+    return fns.secret(value);
+  }
   // return default otherwise (unreachable unless a case was missing)
   return fns.default(value);
 }